@@ -0,0 +1,291 @@
+//! Badges summarizing a formula, for project READMEs.
+//!
+//! `zb info <formula> --badge <FILE>` writes the formula's version, the
+//! platforms it has bottles for and how many dependencies it has, as an SVG
+//! badge or a JSON document depending on the file's extension. Everything
+//! comes from the API when the badge is written, so rendering the file later
+//! needs no network.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use console::style;
+use serde_json::{Value, json};
+
+use zb_core::Formula;
+use zb_io::install::Installer;
+
+use crate::render::{Render, emit};
+
+/// Height of the SVG badge, in pixels
+const BADGE_HEIGHT: usize = 20;
+
+/// Approximate width of a character at the badge's 11px font size
+const CHAR_WIDTH: usize = 7;
+
+/// Padding on each side of a badge segment's text
+const SEGMENT_PADDING: usize = 6;
+
+/// File format of a badge, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BadgeFormat {
+    Svg,
+    Json,
+}
+
+/// What a badge shows about a formula
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Badge {
+    pub name: String,
+    pub version: String,
+    /// Bottle tags the formula is published for, sorted
+    pub platforms: Vec<String>,
+    /// Number of runtime dependencies
+    pub dependencies: usize,
+}
+
+/// Pick the badge format from the file extension.
+/// Extracted for testability.
+pub(crate) fn badge_format(path: &Path) -> Result<BadgeFormat, zb_core::Error> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("svg") => Ok(BadgeFormat::Svg),
+        Some(ext) if ext.eq_ignore_ascii_case("json") => Ok(BadgeFormat::Json),
+        _ => Err(zb_core::Error::StoreCorruption {
+            message: format!(
+                "can't tell the badge format of {}: use a .svg or .json file",
+                path.display()
+            ),
+        }),
+    }
+}
+
+fn plural(count: usize, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("1 {}", singular)
+    } else {
+        format!("{} {}", count, plural)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Badge {
+    pub(crate) fn from_formula(formula: &Formula) -> Self {
+        Badge {
+            name: formula.name.clone(),
+            version: formula.effective_version(),
+            platforms: formula.bottle.stable.files.keys().cloned().collect(),
+            dependencies: formula.effective_dependencies().len(),
+        }
+    }
+
+    /// Text and background color of each badge segment, left to right
+    fn segments(&self) -> Vec<(String, &'static str)> {
+        let platforms = if self.platforms.iter().any(|tag| tag == "all") {
+            ("all platforms".to_string(), "#4c1")
+        } else if self.platforms.is_empty() {
+            ("no bottles".to_string(), "#9f9f9f")
+        } else {
+            (
+                plural(self.platforms.len(), "platform", "platforms"),
+                "#4c1",
+            )
+        };
+        vec![
+            (self.name.clone(), "#555"),
+            (self.version.clone(), "#007ec6"),
+            platforms,
+            (
+                plural(self.dependencies, "dependency", "dependencies"),
+                "#6c757d",
+            ),
+        ]
+    }
+
+    /// The badge as a flat SVG image
+    pub(crate) fn to_svg(&self) -> String {
+        let segments = self.segments();
+        let label = segments
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        let mut rects = String::new();
+        let mut texts = String::new();
+        let mut x = 0;
+        for (text, color) in &segments {
+            let width = text.chars().count() * CHAR_WIDTH + 2 * SEGMENT_PADDING;
+            rects.push_str(&format!(
+                "    <rect x=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                x, width, BADGE_HEIGHT, color
+            ));
+            texts.push_str(&format!(
+                "    <text x=\"{}\" y=\"14\">{}</text>\n",
+                x + width / 2,
+                escape_xml(text)
+            ));
+            x += width;
+        }
+
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" role=\"img\" aria-label=\"{label}\">\n  \
+             <title>{label}</title>\n  \
+             <g shape-rendering=\"crispEdges\">\n{rects}  </g>\n  \
+             <g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\">\n{texts}  </g>\n\
+             </svg>\n",
+            width = x,
+            height = BADGE_HEIGHT,
+            label = escape_xml(&label),
+        )
+    }
+
+    /// The badge as a JSON summary
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "version": self.version,
+            "platforms": self.platforms,
+            "dependencies": self.dependencies,
+        })
+    }
+}
+
+/// A badge written to a file
+struct BadgeOutput {
+    path: PathBuf,
+    badge: Badge,
+}
+
+impl Render for BadgeOutput {
+    fn render_human(&self) {
+        println!(
+            "{} Wrote badge for {} {} to {}",
+            style("==>").cyan().bold(),
+            style(&self.badge.name).bold(),
+            self.badge.version,
+            self.path.display()
+        );
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "path": self.path,
+            "badge": self.badge.to_json(),
+        })
+    }
+}
+
+/// Write a badge for `formula` to `path`.
+pub async fn run(
+    installer: &Installer,
+    formula: &str,
+    path: PathBuf,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let format = badge_format(&path)?;
+    let badge = Badge::from_formula(&installer.get_formula(formula).await?);
+
+    let contents = match format {
+        BadgeFormat::Svg => badge.to_svg(),
+        BadgeFormat::Json => {
+            let mut doc = serde_json::to_string_pretty(&badge.to_json()).map_err(|e| {
+                zb_core::Error::StoreCorruption {
+                    message: format!("failed to serialize badge: {}", e),
+                }
+            })?;
+            doc.push('\n');
+            doc
+        }
+    };
+    fs::write(&path, contents).map_err(|e| zb_core::Error::StoreCorruption {
+        message: format!("failed to write {}: {}", path.display(), e),
+    })?;
+
+    emit(&BadgeOutput { path, badge }, json);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn badge(platforms: &[&str], dependencies: usize) -> Badge {
+        Badge {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            platforms: platforms.iter().map(|p| p.to_string()).collect(),
+            dependencies,
+        }
+    }
+
+    #[test]
+    fn test_badge_format() {
+        assert_eq!(
+            badge_format(Path::new("docs/jq.svg")).unwrap(),
+            BadgeFormat::Svg
+        );
+        assert_eq!(
+            badge_format(Path::new("jq.JSON")).unwrap(),
+            BadgeFormat::Json
+        );
+        assert!(badge_format(Path::new("jq.png")).is_err());
+        assert!(badge_format(Path::new("badge")).is_err());
+    }
+
+    #[test]
+    fn test_badge_from_formula() {
+        let formula: Formula = serde_json::from_str(
+            r#"{"name":"jq","versions":{"stable":"1.7.1"},"dependencies":["oniguruma"],
+                "bottle":{"stable":{"rebuild":1,"files":{
+                    "x86_64_linux":{"url":"https://x/a","sha256":"a"},
+                    "arm64_sonoma":{"url":"https://x/b","sha256":"b"}}}}}"#,
+        )
+        .unwrap();
+
+        let badge = Badge::from_formula(&formula);
+        assert_eq!(badge.version, "1.7.1_1");
+        assert_eq!(badge.platforms, vec!["arm64_sonoma", "x86_64_linux"]);
+        assert_eq!(badge.dependencies, 1);
+        assert_eq!(
+            badge.to_json(),
+            json!({
+                "name": "jq",
+                "version": "1.7.1_1",
+                "platforms": ["arm64_sonoma", "x86_64_linux"],
+                "dependencies": 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_badge_svg() {
+        let svg = badge(&["arm64_sonoma", "x86_64_linux"], 1).to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.contains("<title>jq | 1.7.1 | 2 platforms | 1 dependency</title>"));
+        assert!(svg.contains(">1.7.1</text>"));
+        assert_eq!(svg.matches("<rect ").count(), 4);
+        assert!(svg.ends_with("</svg>\n"));
+
+        let svg = badge(&["all"], 0).to_svg();
+        assert!(svg.contains(">all platforms</text>"));
+        assert!(svg.contains(">0 dependencies</text>"));
+
+        let svg = badge(&[], 3).to_svg();
+        assert!(svg.contains(">no bottles</text>"));
+    }
+
+    #[test]
+    fn test_badge_svg_escapes_text() {
+        let mut badge = badge(&[], 0);
+        badge.name = "a<b>&\"c\"".to_string();
+        let svg = badge.to_svg();
+        assert!(svg.contains(">a&lt;b&gt;&amp;&quot;c&quot;</text>"));
+        assert!(!svg.contains("a<b>"));
+    }
+}
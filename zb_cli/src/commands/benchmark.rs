@@ -0,0 +1,279 @@
+//! Benchmark command implementation.
+
+use console::style;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use zb_io::install::{Installer, create_installer};
+use zb_io::{BenchmarkRecord, InstallTimings, Phase};
+
+use crate::commands::stats::format_ms;
+
+/// Run the benchmark command.
+///
+/// Each iteration installs the formula into a fresh root and prefix under the
+/// system temp directory, so every run downloads, extracts, and links from scratch.
+pub async fn run(
+    installer: &mut Installer,
+    formula: &str,
+    iterations: u32,
+    concurrency: usize,
+) -> Result<(), zb_core::Error> {
+    if iterations == 0 {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "--iterations must be at least 1".to_string(),
+        });
+    }
+
+    let previous = installer.benchmark_history(formula, 1)?.into_iter().next();
+
+    println!(
+        "{} Benchmarking cold install of {} ({} iterations)",
+        style("==>").cyan().bold(),
+        style(formula).green(),
+        iterations
+    );
+
+    let mut runs = Vec::with_capacity(iterations as usize);
+    for i in 1..=iterations {
+        let timings = cold_install(formula, i, concurrency).await?;
+        println!("    {}", format_iteration_line(i, &timings));
+        runs.push(timings);
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let record = summarize_runs(formula, &runs, now);
+    installer.record_benchmark(&record)?;
+
+    println!();
+    for line in format_benchmark_lines(&record) {
+        println!("{}", line);
+    }
+    println!();
+    match previous {
+        Some(previous) => println!("    {}", format_comparison(&record, &previous)),
+        None => println!("    {}", style("No previous runs to compare against").dim()),
+    }
+
+    Ok(())
+}
+
+/// Install `formula` into a throwaway root and prefix and return the timings.
+async fn cold_install(
+    formula: &str,
+    iteration: u32,
+    concurrency: usize,
+) -> Result<InstallTimings, zb_core::Error> {
+    let dir = benchmark_dir(std::process::id(), iteration);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let result = async {
+        let mut cold = create_installer(&dir.join("root"), &dir.join("prefix"), concurrency)?;
+        let plan = cold.plan(formula).await?;
+        cold.execute(plan, true).await
+    }
+    .await;
+
+    let _ = std::fs::remove_dir_all(&dir);
+    result.map(|r| r.timings)
+}
+
+/// Temp directory used for one benchmark iteration.
+/// Extracted for testability.
+pub(crate) fn benchmark_dir(pid: u32, iteration: u32) -> PathBuf {
+    std::env::temp_dir().join(format!("zb-benchmark-{}-{}", pid, iteration))
+}
+
+fn to_ms(duration: Duration) -> i64 {
+    duration.as_millis().min(i64::MAX as u128) as i64
+}
+
+/// Time until the last bottle was available.
+fn download_time(timings: &InstallTimings) -> Duration {
+    timings
+        .packages
+        .iter()
+        .map(|p| p.download)
+        .max()
+        .unwrap_or_default()
+}
+
+/// Average a set of cold install runs into a benchmark record.
+/// Extracted for testability.
+pub(crate) fn summarize_runs(
+    formula: &str,
+    runs: &[InstallTimings],
+    ran_at: i64,
+) -> BenchmarkRecord {
+    let n = runs.len().max(1) as i64;
+    let avg = |f: &dyn Fn(&InstallTimings) -> Duration| -> i64 {
+        runs.iter().map(|t| to_ms(f(t))).sum::<i64>() / n
+    };
+
+    BenchmarkRecord {
+        formula: formula.to_string(),
+        ran_at,
+        iterations: runs.len() as u32,
+        packages: runs.first().map(|t| t.packages.len() as u64).unwrap_or(0),
+        total_ms: avg(&|t| t.total()),
+        resolve_ms: avg(&|t| t.resolve),
+        download_ms: avg(&download_time),
+        extract_ms: avg(&|t| t.phase_total(Phase::Extract)),
+        link_ms: avg(&|t| t.phase_total(Phase::Link)),
+    }
+}
+
+/// Format the result line for a single iteration.
+/// Extracted for testability.
+pub(crate) fn format_iteration_line(iteration: u32, timings: &InstallTimings) -> String {
+    let ms = |d: Duration| format_ms(d.as_secs_f64() * 1000.0);
+    format!(
+        "run {}: {} (resolve {}, download {}, extract {}, link {})",
+        iteration,
+        ms(timings.total()),
+        ms(timings.resolve),
+        ms(download_time(timings)),
+        ms(timings.phase_total(Phase::Extract)),
+        ms(timings.phase_total(Phase::Link))
+    )
+}
+
+/// Format the averaged benchmark results.
+/// Extracted for testability.
+pub(crate) fn format_benchmark_lines(record: &BenchmarkRecord) -> Vec<String> {
+    let ms = |v: i64| format_ms(v as f64);
+    vec![
+        format!(
+            "{} {} packages, average of {} runs",
+            style("==>").cyan().bold(),
+            record.packages,
+            record.iterations
+        ),
+        format!("    Total:     {}", ms(record.total_ms)),
+        format!("    Resolve:   {}", ms(record.resolve_ms)),
+        format!("    Download:  {}", ms(record.download_ms)),
+        format!("    Extract:   {}", ms(record.extract_ms)),
+        format!("    Link:      {}", ms(record.link_ms)),
+    ]
+}
+
+/// Compare a benchmark result against the previous run of the same formula.
+/// Extracted for testability.
+pub(crate) fn format_comparison(current: &BenchmarkRecord, previous: &BenchmarkRecord) -> String {
+    let previous_total = format_ms(previous.total_ms as f64);
+    if previous.total_ms <= 0 {
+        return format!("Previous run: {}", previous_total);
+    }
+
+    let change = (current.total_ms - previous.total_ms) as f64 / previous.total_ms as f64 * 100.0;
+    if change.abs() < 1.0 {
+        format!("Same as previous run ({})", previous_total)
+    } else if change < 0.0 {
+        format!(
+            "{:.0}% faster than previous run ({})",
+            -change, previous_total
+        )
+    } else {
+        format!(
+            "{:.0}% slower than previous run ({})",
+            change, previous_total
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zb_io::{PackageTiming, Span};
+
+    fn timings(total_ms: u64, downloads: &[u64]) -> InstallTimings {
+        InstallTimings {
+            resolve: Duration::from_millis(100),
+            execute: Duration::from_millis(total_ms - 100),
+            packages: downloads
+                .iter()
+                .map(|&d| PackageTiming {
+                    download: Duration::from_millis(d),
+                    ..Default::default()
+                })
+                .collect(),
+            spans: vec![Span {
+                phase: Phase::Extract,
+                package: "jq".to_string(),
+                start: Duration::ZERO,
+                end: Duration::from_millis(40),
+            }],
+        }
+    }
+
+    fn record(total_ms: i64) -> BenchmarkRecord {
+        BenchmarkRecord {
+            formula: "jq".to_string(),
+            total_ms,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_benchmark_dir_is_unique_per_iteration() {
+        assert_ne!(benchmark_dir(1, 1), benchmark_dir(1, 2));
+        assert!(benchmark_dir(1, 1).starts_with(std::env::temp_dir()));
+    }
+
+    #[test]
+    fn test_summarize_runs_averages() {
+        let runs = vec![timings(1000, &[300, 500]), timings(2000, &[700])];
+        let record = summarize_runs("jq", &runs, 42);
+
+        assert_eq!(record.iterations, 2);
+        assert_eq!(record.packages, 2);
+        assert_eq!(record.ran_at, 42);
+        assert_eq!(record.total_ms, 1500);
+        assert_eq!(record.resolve_ms, 100);
+        assert_eq!(record.download_ms, 600);
+        assert_eq!(record.extract_ms, 40);
+        assert_eq!(record.link_ms, 0);
+    }
+
+    #[test]
+    fn test_format_iteration_line() {
+        let line = format_iteration_line(2, &timings(1500, &[300]));
+        assert_eq!(
+            line,
+            "run 2: 1.50s (resolve 100ms, download 300ms, extract 40ms, link 0ms)"
+        );
+    }
+
+    #[test]
+    fn test_format_benchmark_lines() {
+        let lines = format_benchmark_lines(&record(1200));
+        assert!(lines.iter().any(|l| l.contains("Total:     1.20s")));
+    }
+
+    #[test]
+    fn test_format_comparison_faster() {
+        assert_eq!(
+            format_comparison(&record(800), &record(1000)),
+            "20% faster than previous run (1.00s)"
+        );
+    }
+
+    #[test]
+    fn test_format_comparison_slower() {
+        assert_eq!(
+            format_comparison(&record(1500), &record(1000)),
+            "50% slower than previous run (1.00s)"
+        );
+    }
+
+    #[test]
+    fn test_format_comparison_unchanged() {
+        assert_eq!(
+            format_comparison(&record(1002), &record(1000)),
+            "Same as previous run (1.00s)"
+        );
+    }
+}
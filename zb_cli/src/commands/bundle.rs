@@ -1,36 +1,49 @@
 //! Bundle command implementations.
 
 use console::style;
+use serde_json::{Value, json};
 use std::path::PathBuf;
 
+use zb_io::bundle_lock::lockfile_path;
 use zb_io::install::Installer;
-use zb_io::{BrewfileEntry, BundleCheckResult, BundleInstallResult};
+use zb_io::{BrewfileEntry, BundleCheckResult, BundleInstallResult, BundleLock};
 
 use crate::BundleAction;
+use crate::render::{Render, emit, print_json};
 
 /// Run the bundle command.
 pub async fn run(
     installer: &mut Installer,
     action: Option<BundleAction>,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let cwd = std::env::current_dir().map_err(|e| zb_core::Error::StoreCorruption {
         message: format!("failed to get current directory: {}", e),
     })?;
 
     match action {
-        None | Some(BundleAction::Install { file: None }) => {
-            run_install(installer, &cwd, None).await
+        None => run_install(installer, &cwd, None, false, json).await,
+        Some(BundleAction::Install { file, locked }) => {
+            run_install(installer, &cwd, file, locked, json).await
         }
-        Some(BundleAction::Install { file: Some(path) }) => {
-            run_install(installer, &cwd, Some(path)).await
+        Some(BundleAction::Lock { file }) => run_lock(installer, &cwd, file, json).await,
+        Some(BundleAction::Dump { .. } | BundleAction::Env { .. }) if json => {
+            Err(zb_core::Error::StoreCorruption {
+                message: "--json is not supported by zb bundle dump or zb bundle env".to_string(),
+            })
         }
         Some(BundleAction::Dump {
             file,
             describe,
             force,
         }) => run_dump(installer, file, describe, force),
-        Some(BundleAction::Check { file, strict }) => run_check(installer, &cwd, file, strict),
-        Some(BundleAction::List { file }) => run_list(installer, &cwd, file),
+        Some(BundleAction::Check { file, strict }) => {
+            run_check(installer, &cwd, file, strict, json)
+        }
+        Some(BundleAction::List { file }) => run_list(installer, &cwd, file, json),
+        Some(BundleAction::Env { file, shell }) => {
+            run_env(installer, &cwd, file, shell.as_deref().unwrap_or("bash"))
+        }
     }
 }
 
@@ -38,6 +51,8 @@ async fn run_install(
     installer: &mut Installer,
     cwd: &std::path::Path,
     file: Option<PathBuf>,
+    locked: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let brewfile_path = match file {
         Some(path) => {
@@ -52,15 +67,33 @@ async fn run_install(
             })?,
     };
 
-    println!(
-        "{} Installing from {}",
-        style("==>").cyan().bold(),
-        brewfile_path.display()
-    );
+    if !json {
+        println!(
+            "{} Installing from {}",
+            style("==>").cyan().bold(),
+            brewfile_path.display()
+        );
+    }
 
-    let result = installer.bundle_install(&brewfile_path).await?;
+    let result = if locked {
+        let lock_path = lockfile_path(&brewfile_path);
+        if !lock_path.exists() {
+            return Err(zb_core::Error::StoreCorruption {
+                message: format!(
+                    "no lockfile at {} (run `zb bundle lock` first)",
+                    lock_path.display()
+                ),
+            });
+        }
+        let lock = BundleLock::read(&lock_path)?;
+        installer
+            .bundle_install_locked(&brewfile_path, &lock)
+            .await?
+    } else {
+        installer.bundle_install(&brewfile_path).await?
+    };
 
-    print!("{}", format_install_result(&result));
+    emit(&result, json);
 
     if !result.failed.is_empty() {
         std::process::exit(1);
@@ -69,6 +102,57 @@ async fn run_install(
     Ok(())
 }
 
+async fn run_lock(
+    installer: &mut Installer,
+    cwd: &std::path::Path,
+    file: Option<PathBuf>,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let brewfile_path = match file {
+        Some(path) => validate_brewfile_path(Some(path), cwd)
+            .map_err(|e| zb_core::Error::StoreCorruption { message: e })?,
+        None => installer
+            .find_brewfile(cwd)
+            .ok_or_else(|| zb_core::Error::StoreCorruption {
+                message: format_no_brewfile_error(),
+            })?,
+    };
+    let lock_path = lockfile_path(&brewfile_path);
+
+    if !json {
+        println!(
+            "{} Locking {}",
+            style("==>").cyan().bold(),
+            brewfile_path.display()
+        );
+    }
+
+    // Keep bottles locked on other platforms
+    let previous = if lock_path.exists() {
+        Some(BundleLock::read(&lock_path)?)
+    } else {
+        None
+    };
+    let lock = installer.bundle_lock(&brewfile_path, previous).await?;
+    lock.write(&lock_path)?;
+
+    if json {
+        print_json(&json!({
+            "lockfile": lock_path,
+            "formulas": lock.entries.brew.keys().collect::<Vec<_>>(),
+        }));
+    } else {
+        println!(
+            "{} Locked {} formulas in {}",
+            style("==>").cyan().bold(),
+            lock.entries.brew.len(),
+            lock_path.display()
+        );
+    }
+
+    Ok(())
+}
+
 fn run_dump(
     installer: &mut Installer,
     file: Option<PathBuf>,
@@ -104,6 +188,7 @@ fn run_check(
     cwd: &std::path::Path,
     file: Option<PathBuf>,
     strict: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let brewfile_path = match file {
         Some(path) => {
@@ -118,15 +203,16 @@ fn run_check(
             })?,
     };
 
-    println!(
-        "{} Checking {}",
-        style("==>").cyan().bold(),
-        brewfile_path.display()
-    );
+    if !json {
+        println!(
+            "{} Checking {}",
+            style("==>").cyan().bold(),
+            brewfile_path.display()
+        );
+    }
 
     let result = installer.bundle_check(&brewfile_path)?;
-
-    print!("{}", format_check_result(&result));
+    emit(&result, json);
 
     if !result.satisfied && strict {
         std::process::exit(1);
@@ -139,6 +225,7 @@ fn run_list(
     installer: &mut Installer,
     cwd: &std::path::Path,
     file: Option<PathBuf>,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let brewfile_path = match file {
         Some(path) => {
@@ -155,20 +242,115 @@ fn run_list(
 
     let entries = installer.parse_brewfile(&brewfile_path)?;
 
-    println!(
-        "{} Entries in {}:",
-        style("==>").cyan().bold(),
-        brewfile_path.display()
-    );
-    print!("{}", format_list_output(&entries));
+    if json {
+        print_json(&brewfile_entries_json(&entries));
+    } else {
+        println!(
+            "{} Entries in {}:",
+            style("==>").cyan().bold(),
+            brewfile_path.display()
+        );
+        print!("{}", format_list_output(&entries));
+    }
 
     Ok(())
 }
 
+/// Print shell exports that put the Brewfile's kegs on the search paths.
+/// Missing formulas are reported on stderr so `eval` only sees exports.
+fn run_env(
+    installer: &Installer,
+    cwd: &std::path::Path,
+    file: Option<PathBuf>,
+    shell: &str,
+) -> Result<(), zb_core::Error> {
+    let brewfile_path = match file {
+        Some(path) => validate_brewfile_path(Some(path), cwd)
+            .map_err(|e| zb_core::Error::StoreCorruption { message: e })?,
+        None => installer
+            .find_brewfile(cwd)
+            .ok_or_else(|| zb_core::Error::StoreCorruption {
+                message: format_no_brewfile_error(),
+            })?,
+    };
+
+    let entries = installer.parse_brewfile(&brewfile_path)?;
+    let env = installer.bundle_env(&entries);
+
+    if !env.missing.is_empty() {
+        eprintln!(
+            "{} not installed: {} (run 'zb bundle' to install)",
+            style("zb:").yellow().bold(),
+            env.missing.join(", ")
+        );
+    }
+
+    let exports = format_env_exports(&env.path_vars(), shell);
+    if !exports.is_empty() {
+        println!("{}", exports);
+    }
+
+    Ok(())
+}
+
+impl Render for BundleInstallResult {
+    fn render_human(&self) {
+        print!("{}", format_install_result(self));
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "run_id": self.run_id,
+            "taps_added": self.taps_added,
+            "formulas_installed": self.formulas_installed,
+            "formulas_skipped": self.formulas_skipped,
+            "failed": self.failed.iter().map(|(name, error)| {
+                json!({ "name": name, "error": error })
+            }).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl Render for BundleCheckResult {
+    fn render_human(&self) {
+        print!("{}", format_check_result(self));
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "satisfied": self.satisfied,
+            "missing_taps": self.missing_taps,
+            "missing_formulas": self.missing_formulas,
+            "mismatched_formulas": self.mismatched_formulas.iter().map(|(name, args)| {
+                json!({ "name": name, "args": args })
+            }).collect::<Vec<_>>(),
+        })
+    }
+}
+
 // ============================================================================
 // Pure functions extracted for testability
 // ============================================================================
 
+/// Taps and formulas of a Brewfile as JSON, without its comments
+pub(crate) fn brewfile_entries_json(entries: &[BrewfileEntry]) -> Value {
+    let taps: Vec<&str> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            BrewfileEntry::Tap { name } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let brews: Vec<Value> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            BrewfileEntry::Brew { name, args } => Some(json!({ "name": name, "args": args })),
+            _ => None,
+        })
+        .collect();
+    json!({ "taps": taps, "brews": brews })
+}
+
 /// Count taps and formulas in Brewfile entries.
 pub(crate) fn count_brewfile_entries(entries: &[BrewfileEntry]) -> (usize, usize) {
     let mut tap_count = 0;
@@ -541,10 +723,106 @@ pub(crate) fn compute_check_summary(result: &BundleCheckResult) -> (usize, usize
     (missing_taps, missing_formulas, all_satisfied)
 }
 
+/// Format search path exports for a shell, prepending to current values.
+/// Extracted for testability.
+pub(crate) fn format_env_exports(vars: &[(&str, Vec<PathBuf>)], shell: &str) -> String {
+    vars.iter()
+        .map(|(var, dirs)| {
+            let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+            match shell {
+                "fish" => format!(
+                    "set -gx {} {} ${};",
+                    var,
+                    dirs.iter()
+                        .map(|d| format!("\"{}\"", d))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    var
+                ),
+                "csh" | "tcsh" => {
+                    format!("setenv {} \"{}:${{{}}}\";", var, dirs.join(":"), var)
+                }
+                // An empty MANPATH entry keeps the system man pages searchable
+                _ if *var == "MANPATH" => {
+                    format!("export MANPATH=\"{}:${{MANPATH:-}}\";", dirs.join(":"))
+                }
+                _ if *var == "PATH" => format!("export PATH=\"{}:$PATH\";", dirs.join(":")),
+                _ => format!(
+                    "export {}=\"{}${{{}:+:${}}}\";",
+                    var,
+                    dirs.join(":"),
+                    var,
+                    var
+                ),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // ========================================================================
+    // format_env_exports tests
+    // ========================================================================
+
+    fn env_vars() -> Vec<(&'static str, Vec<PathBuf>)> {
+        vec![
+            (
+                "PATH",
+                vec![
+                    PathBuf::from("/zb/Cellar/node/22.1.0/bin"),
+                    PathBuf::from("/zb/Cellar/openssl@3/3.3.0/bin"),
+                ],
+            ),
+            (
+                "MANPATH",
+                vec![PathBuf::from("/zb/Cellar/node/22.1.0/share/man")],
+            ),
+            (
+                "PKG_CONFIG_PATH",
+                vec![PathBuf::from("/zb/Cellar/openssl@3/3.3.0/lib/pkgconfig")],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_format_env_exports_posix() {
+        assert_eq!(
+            format_env_exports(&env_vars(), "bash"),
+            "export PATH=\"/zb/Cellar/node/22.1.0/bin:/zb/Cellar/openssl@3/3.3.0/bin:$PATH\";\n\
+             export MANPATH=\"/zb/Cellar/node/22.1.0/share/man:${MANPATH:-}\";\n\
+             export PKG_CONFIG_PATH=\"/zb/Cellar/openssl@3/3.3.0/lib/pkgconfig${PKG_CONFIG_PATH:+:$PKG_CONFIG_PATH}\";"
+        );
+        assert_eq!(
+            format_env_exports(&env_vars(), "zsh"),
+            format_env_exports(&env_vars(), "bash")
+        );
+    }
+
+    #[test]
+    fn test_format_env_exports_fish() {
+        let exports = format_env_exports(&env_vars(), "fish");
+        assert!(exports.starts_with(
+            "set -gx PATH \"/zb/Cellar/node/22.1.0/bin\" \"/zb/Cellar/openssl@3/3.3.0/bin\" $PATH;"
+        ));
+    }
+
+    #[test]
+    fn test_format_env_exports_csh() {
+        let exports = format_env_exports(&env_vars(), "csh");
+        assert!(
+            exports.contains("setenv MANPATH \"/zb/Cellar/node/22.1.0/share/man:${MANPATH}\";")
+        );
+    }
+
+    #[test]
+    fn test_format_env_exports_empty() {
+        assert_eq!(format_env_exports(&[], "bash"), "");
+    }
+
     // ========================================================================
     // count_brewfile_entries tests
     // ========================================================================
@@ -831,6 +1109,7 @@ mod tests {
             formulas_installed: vec!["git".to_string(), "ripgrep".to_string()],
             formulas_skipped: vec![],
             failed: vec![],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -850,6 +1129,7 @@ mod tests {
             formulas_installed: vec!["git".to_string()],
             formulas_skipped: vec!["ripgrep".to_string(), "fd".to_string()],
             failed: vec![],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -866,6 +1146,7 @@ mod tests {
             formulas_installed: vec!["git".to_string()],
             formulas_skipped: vec![],
             failed: vec![("badpkg".to_string(), "not found".to_string())],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -892,6 +1173,7 @@ mod tests {
             formulas_installed: vec![],
             formulas_skipped: vec!["git".to_string(), "ripgrep".to_string()],
             failed: vec![],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -910,6 +1192,7 @@ mod tests {
                 ("pkg2".to_string(), "checksum mismatch".to_string()),
                 ("pkg3".to_string(), "build failed".to_string()),
             ],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -1163,6 +1446,7 @@ mod tests {
             formulas_installed: vec!["git".to_string(), "ripgrep".to_string()],
             formulas_skipped: vec!["fd".to_string()],
             failed: vec![],
+            ..Default::default()
         };
 
         let (installed, skipped, failed, has_errors) = compute_install_summary(&result);
@@ -1182,6 +1466,7 @@ mod tests {
                 ("bad1".to_string(), "error".to_string()),
                 ("bad2".to_string(), "error".to_string()),
             ],
+            ..Default::default()
         };
 
         let (installed, skipped, failed, has_errors) = compute_install_summary(&result);
@@ -1257,6 +1542,7 @@ mod tests {
             formulas_installed: vec!["aaa".to_string(), "zzz".to_string(), "mmm".to_string()],
             formulas_skipped: vec![],
             failed: vec![],
+            ..Default::default()
         };
 
         let output = format_install_result_plain(&result);
@@ -1328,4 +1614,46 @@ mod tests {
         assert!(output.contains("very-long-username/very-long-repo-name"));
         assert!(output.contains("some-package-with-a-very-long-name@1.2.3"));
     }
+
+    #[test]
+    fn test_brewfile_entries_json_skips_comments() {
+        let entries = vec![
+            BrewfileEntry::Comment("# tools".to_string()),
+            BrewfileEntry::Tap {
+                name: "user/tools".to_string(),
+            },
+            BrewfileEntry::Brew {
+                name: "jq".to_string(),
+                args: vec!["--HEAD".to_string()],
+            },
+        ];
+
+        assert_eq!(
+            brewfile_entries_json(&entries),
+            json!({
+                "taps": ["user/tools"],
+                "brews": [{ "name": "jq", "args": ["--HEAD"] }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_result_render_json() {
+        let result = BundleCheckResult {
+            missing_taps: vec![],
+            missing_formulas: vec!["wget".to_string()],
+            mismatched_formulas: vec![("jq".to_string(), vec!["--HEAD".to_string()])],
+            satisfied: false,
+        };
+
+        assert_eq!(
+            result.render_json(),
+            json!({
+                "satisfied": false,
+                "missing_taps": [],
+                "missing_formulas": ["wget"],
+                "mismatched_formulas": [{ "name": "jq", "args": ["--HEAD"] }],
+            })
+        );
+    }
 }
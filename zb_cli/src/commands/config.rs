@@ -0,0 +1,34 @@
+//! Config-diff command implementation.
+
+use console::style;
+use std::process::Command;
+
+use zb_io::install::Installer;
+
+/// Show how the configs of `formula` in the prefix's `etc/` differ from the
+/// defaults its installed keg ships.
+pub fn run_diff(installer: &Installer, formula: &str) -> Result<(), zb_core::Error> {
+    let diffs = installer.config_diffs(formula)?;
+    if diffs.is_empty() {
+        println!("No modified configs for {}", style(formula).bold());
+        return Ok(());
+    }
+
+    for diff in &diffs {
+        let status = Command::new("diff")
+            .arg("-u")
+            .arg(&diff.default)
+            .arg(&diff.current)
+            .status();
+        if let Err(e) = status {
+            eprintln!("{} failed to run diff: {}", style("error:").red().bold(), e);
+            println!(
+                "{} differs from {}",
+                diff.current.display(),
+                diff.default.display()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,15 @@
-//! Deps, uses, and leaves command implementations.
+//! Deps, uses, leaves, and why command implementations.
+
+use std::str::FromStr;
 
 use console::style;
+use serde_json::{Value, json};
 
-use zb_io::install::Installer;
+use zb_io::WhyResult;
+use zb_io::install::{DepsGraph, DepsTree, Installer};
 
 use crate::display::print_deps_tree;
+use crate::render::{Render, emit};
 
 // ============================================================================
 // Formatting helpers (pure functions for testability)
@@ -57,33 +62,244 @@ pub fn format_deps_plain(deps: &[String]) -> Vec<String> {
 // Command implementations
 // ============================================================================
 
+/// Dependencies of a formula, with whether each is installed
+struct DepsOutput {
+    formula: String,
+    installed_only: bool,
+    all: bool,
+    deps: Vec<(String, bool)>,
+}
+
+impl Render for DepsOutput {
+    fn render_human(&self) {
+        if self.deps.is_empty() {
+            println!(
+                "{}",
+                format_no_deps_message(&self.formula, self.installed_only)
+            );
+            return;
+        }
+        println!("{}", format_deps_header(&self.formula, false, self.all));
+        println!();
+        for (dep, installed) in &self.deps {
+            println!("{}", format_dep_line(dep, *installed));
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "formula": self.formula,
+            "dependencies": self.deps.iter().map(|(name, installed)| {
+                json!({ "name": name, "installed": installed })
+            }).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Dependency tree of a formula
+struct DepsTreeOutput {
+    formula: String,
+    tree: DepsTree,
+    /// Show versions and keg state on each node
+    annotate: bool,
+}
+
+impl Render for DepsTreeOutput {
+    fn render_human(&self) {
+        println!("{}", format_deps_header(&self.formula, true, false));
+        println!();
+        print_deps_tree(&self.tree, "", true, self.annotate);
+    }
+
+    fn render_json(&self) -> Value {
+        deps_tree_json(&self.tree, self.annotate)
+    }
+}
+
+/// A dependency tree as nested JSON objects, with each node's versions and
+/// keg state when `annotate` is set.
+/// Extracted for testability.
+pub(crate) fn deps_tree_json(tree: &DepsTree, annotate: bool) -> Value {
+    let mut node = json!({
+        "name": tree.name,
+        "installed": tree.installed,
+        "dependencies": tree
+            .children
+            .iter()
+            .map(|child| deps_tree_json(child, annotate))
+            .collect::<Vec<_>>(),
+    });
+    if annotate {
+        let annotation = &tree.annotation;
+        node["installed_version"] = json!(annotation.installed_version);
+        node["available_version"] = json!(annotation.available_version);
+        node["pinned"] = json!(annotation.pinned);
+        node["keg_only"] = json!(annotation.keg_only);
+    }
+    node
+}
+
+/// Format for exporting a dependency graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// JSON object mapping each formula to its direct dependencies
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!(
+                "unknown graph format '{}': expected dot, mermaid or json",
+                s
+            )),
+        }
+    }
+}
+
+fn dot_quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A dependency graph in Graphviz DOT, with uninstalled formulas dashed.
+/// Extracted for testability.
+pub(crate) fn graph_dot(graph: &DepsGraph) -> String {
+    let mut out = String::from("digraph dependencies {\n    rankdir=LR;\n    node [shape=box];\n");
+    for (name, deps) in &graph.edges {
+        if graph.installed.contains(name) {
+            out.push_str(&format!("    {};\n", dot_quote(name)));
+        } else {
+            out.push_str(&format!("    {} [style=dashed];\n", dot_quote(name)));
+        }
+        for dep in deps {
+            out.push_str(&format!("    {} -> {};\n", dot_quote(name), dot_quote(dep)));
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// A dependency graph as a Mermaid flowchart, with uninstalled formulas
+/// dashed. Nodes get generated ids since formula names like `openssl@3`
+/// aren't valid Mermaid ids.
+/// Extracted for testability.
+pub(crate) fn graph_mermaid(graph: &DepsGraph) -> String {
+    let ids: std::collections::BTreeMap<&str, String> = graph
+        .edges
+        .keys()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), format!("n{}", i)))
+        .collect();
+
+    let mut out = String::from("flowchart LR\n");
+    for (name, id) in &ids {
+        out.push_str(&format!(
+            "    {}[\"{}\"]\n",
+            id,
+            name.replace('"', "#quot;")
+        ));
+    }
+    for (name, deps) in &graph.edges {
+        for dep in deps {
+            if let Some(dep_id) = ids.get(dep.as_str()) {
+                out.push_str(&format!("    {} --> {}\n", ids[name.as_str()], dep_id));
+            }
+        }
+    }
+    let missing: Vec<&str> = ids
+        .iter()
+        .filter(|(name, _)| !graph.installed.contains(**name))
+        .map(|(_, id)| id.as_str())
+        .collect();
+    if !missing.is_empty() {
+        out.push_str("    classDef missing stroke-dasharray: 5 5\n");
+        out.push_str(&format!("    class {} missing\n", missing.join(",")));
+    }
+    out
+}
+
+/// A dependency graph as a JSON adjacency list.
+/// Extracted for testability.
+pub(crate) fn graph_adjacency_json(graph: &DepsGraph) -> Value {
+    json!(graph.edges)
+}
+
+/// Print the dependency graph of `formula`'s closure, or of every installed
+/// package when no formula is given
+async fn print_deps_graph(
+    installer: &Installer,
+    formula: Option<&str>,
+    installed: bool,
+    format: GraphFormat,
+) -> Result<(), zb_core::Error> {
+    let graph = installer.get_deps_graph(formula, installed).await?;
+    match format {
+        GraphFormat::Dot => print!("{}", graph_dot(&graph)),
+        GraphFormat::Mermaid => print!("{}", graph_mermaid(&graph)),
+        GraphFormat::Json => crate::render::print_json(&graph_adjacency_json(&graph)),
+    }
+    Ok(())
+}
+
 /// Run the deps command.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_deps(
     installer: &mut Installer,
-    formula: String,
+    formula: Option<String>,
     tree: bool,
     installed: bool,
     all: bool,
+    graph: Option<GraphFormat>,
+    annotate: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
-    if tree {
-        println!("{}", format_deps_header(&formula, true, false));
-        println!();
+    if let Some(format) = graph {
+        return print_deps_graph(installer, formula.as_deref(), installed, format).await;
+    }
+    let Some(formula) = formula else {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "a formula is required unless exporting a graph with --graph".to_string(),
+        });
+    };
 
-        let tree_data = installer.get_deps_tree(&formula, installed).await?;
-        print_deps_tree(&tree_data, "", true);
+    // Annotations come with the tree, built from formulas it fetches anyway
+    if tree || annotate {
+        let tree = installer.get_deps_tree(&formula, installed).await?;
+        emit(
+            &DepsTreeOutput {
+                formula,
+                tree,
+                annotate,
+            },
+            json,
+        );
     } else {
         let deps = installer.get_deps(&formula, installed, all).await?;
-
-        if deps.is_empty() {
-            println!("{}", format_no_deps_message(&formula, installed));
-        } else {
-            println!("{}", format_deps_header(&formula, false, all));
-            println!();
-
-            for dep in &deps {
-                println!("{}", format_dep_line(dep, installer.is_installed(dep)));
-            }
-        }
+        let deps = deps
+            .into_iter()
+            .map(|dep| {
+                let installed = installer.is_installed(&dep);
+                (dep, installed)
+            })
+            .collect();
+        emit(
+            &DepsOutput {
+                formula,
+                installed_only: installed,
+                all,
+                deps,
+            },
+            json,
+        );
     }
 
     Ok(())
@@ -98,26 +314,41 @@ pub fn format_uses_header(formula: &str) -> String {
     )
 }
 
+fn uses_scope(eval_all: bool) -> &'static str {
+    if eval_all {
+        "formulas"
+    } else {
+        "installed formulas"
+    }
+}
+
 /// Format the "no uses" message.
-pub fn format_no_uses_message(formula: &str) -> String {
+pub fn format_no_uses_message(formula: &str, eval_all: bool) -> String {
     format!(
-        "{} No installed formulas use {}.",
+        "{} No {} use {}.",
         style("==>").cyan().bold(),
+        uses_scope(eval_all),
         style(formula).bold()
     )
 }
 
 /// Format the uses result header.
-pub fn format_uses_result_header(formula: &str, count: usize, recursive: bool) -> String {
+pub fn format_uses_result_header(
+    formula: &str,
+    count: usize,
+    recursive: bool,
+    eval_all: bool,
+) -> String {
     let suffix = if recursive {
         " (directly or indirectly)"
     } else {
         ""
     };
     format!(
-        "{} {} installed formulas use {}{}:",
+        "{} {} {} use {}{}:",
         style("==>").cyan().bold(),
         style(count).green().bold(),
+        uses_scope(eval_all),
         style(formula).bold(),
         suffix
     )
@@ -137,60 +368,177 @@ pub fn format_leaves_result_header(count: usize) -> String {
     )
 }
 
+/// Formulas that use a formula
+struct UsesOutput {
+    formula: String,
+    recursive: bool,
+    eval_all: bool,
+    uses: Vec<String>,
+}
+
+impl Render for UsesOutput {
+    fn render_human(&self) {
+        if self.uses.is_empty() {
+            println!("{}", format_no_uses_message(&self.formula, self.eval_all));
+            return;
+        }
+        println!(
+            "{}",
+            format_uses_result_header(
+                &self.formula,
+                self.uses.len(),
+                self.recursive,
+                self.eval_all
+            )
+        );
+        println!();
+        for line in format_deps_plain(&self.uses) {
+            println!("{}", line);
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "formula": self.formula,
+            "recursive": self.recursive,
+            "uses": self.uses,
+        })
+    }
+}
+
 /// Run the uses command.
 pub async fn run_uses(
     installer: &mut Installer,
     formula: String,
     recursive: bool,
+    eval_all: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
-    println!("{}", format_uses_header(&formula));
+    if !json {
+        println!("{}", format_uses_header(&formula));
+    }
 
     // Check if the formula exists (either installed or in API)
-    let formula_exists =
-        installer.is_installed(&formula) || installer.get_formula(&formula).await.is_ok();
-
-    if !formula_exists {
-        println!("Formula '{}' not found.", formula);
-        std::process::exit(1);
+    let resolved = installer.get_formula(&formula).await.ok();
+    if resolved.is_none() && !installer.is_installed(&formula) {
+        return Err(zb_core::Error::MissingFormula { name: formula });
     }
 
-    // uses command defaults to installed-only (installed flag is ignored, always true)
-    let uses = installer.get_uses(&formula, true, recursive).await?;
+    // The catalog index is keyed by canonical names, not aliases
+    let name = match resolved {
+        Some(f) if eval_all => f.name,
+        _ => formula.clone(),
+    };
+    let uses = installer.get_uses(&name, !eval_all, recursive).await?;
+
+    emit(
+        &UsesOutput {
+            formula,
+            recursive,
+            eval_all,
+            uses,
+        },
+        json,
+    );
+    Ok(())
+}
 
-    if uses.is_empty() {
-        println!("{}", format_no_uses_message(&formula));
-    } else {
-        println!(
-            "{}",
-            format_uses_result_header(&formula, uses.len(), recursive)
-        );
-        println!();
+/// Leaf packages
+struct LeavesOutput(Vec<String>);
 
-        for line in format_deps_plain(&uses) {
+impl Render for LeavesOutput {
+    fn render_human(&self) {
+        if self.0.is_empty() {
+            println!("No installed packages, or all packages are dependencies.");
+            return;
+        }
+        println!("{}", format_leaves_result_header(self.0.len()));
+        println!();
+        for line in format_deps_plain(&self.0) {
             println!("{}", line);
         }
     }
 
-    Ok(())
+    fn render_json(&self) -> Value {
+        json!(self.0)
+    }
 }
 
 /// Run the leaves command.
-pub async fn run_leaves(installer: &mut Installer) -> Result<(), zb_core::Error> {
-    println!("{}", format_leaves_header());
+pub async fn run_leaves(installer: &mut Installer, json: bool) -> Result<(), zb_core::Error> {
+    if !json {
+        println!("{}", format_leaves_header());
+    }
 
     let leaves = installer.get_leaves().await?;
+    emit(&LeavesOutput(leaves), json);
+    Ok(())
+}
 
-    if leaves.is_empty() {
-        println!("No installed packages, or all packages are dependencies.");
+/// Format a dependency chain, e.g. `node → icu4c`.
+pub fn format_why_chain(chain: &[String]) -> String {
+    chain.join(" → ")
+}
+
+/// Format the summary line for the why command.
+pub fn format_why_header(formula: &str, result: &WhyResult) -> String {
+    let arrow = style("==>").cyan().bold();
+    let formula = style(formula).bold();
+    if result.explicit && result.chains.is_empty() {
+        format!("{} {} was installed explicitly.", arrow, formula)
+    } else if result.explicit {
+        format!(
+            "{} {} was installed explicitly and is also needed by:",
+            arrow, formula
+        )
+    } else if result.chains.is_empty() {
+        format!(
+            "{} {} is not needed by any explicitly installed formula (orphaned; `zb autoremove` will remove it).",
+            arrow, formula
+        )
     } else {
-        println!("{}", format_leaves_result_header(leaves.len()));
-        println!();
+        format!("{} {} is installed because:", arrow, formula)
+    }
+}
 
-        for line in format_deps_plain(&leaves) {
-            println!("{}", line);
+/// Why a formula is installed
+struct WhyOutput {
+    formula: String,
+    result: WhyResult,
+}
+
+impl Render for WhyOutput {
+    fn render_human(&self) {
+        println!("{}", format_why_header(&self.formula, &self.result));
+        for chain in &self.result.chains {
+            println!("    {}", format_why_chain(chain));
         }
     }
 
+    fn render_json(&self) -> Value {
+        json!({
+            "formula": self.formula,
+            "explicit": self.result.explicit,
+            "orphan": self.result.is_orphan(),
+            "chains": self.result.chains,
+        })
+    }
+}
+
+/// Run the why command.
+pub async fn run_why(
+    installer: &Installer,
+    formula: &str,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let result = installer.why(formula).await?;
+    emit(
+        &WhyOutput {
+            formula: formula.to_string(),
+            result,
+        },
+        json,
+    );
     Ok(())
 }
 
@@ -346,14 +694,23 @@ mod tests {
 
     #[test]
     fn test_format_no_uses_message() {
-        let result = format_no_uses_message("zlib");
+        let result = format_no_uses_message("zlib", false);
         assert!(result.contains("No installed formulas use"));
         assert!(result.contains("zlib"));
     }
 
+    #[test]
+    fn test_format_uses_messages_eval_all() {
+        let result = format_no_uses_message("zlib", true);
+        assert!(result.contains("No formulas use"));
+        let result = format_uses_result_header("zlib", 3, false, true);
+        assert!(result.contains("formulas use"));
+        assert!(!result.contains("installed"));
+    }
+
     #[test]
     fn test_format_uses_result_header_non_recursive() {
-        let result = format_uses_result_header("readline", 5, false);
+        let result = format_uses_result_header("readline", 5, false, false);
         assert!(result.contains("5"));
         assert!(result.contains("installed formulas use"));
         assert!(result.contains("readline"));
@@ -362,7 +719,7 @@ mod tests {
 
     #[test]
     fn test_format_uses_result_header_recursive() {
-        let result = format_uses_result_header("ncurses", 12, true);
+        let result = format_uses_result_header("ncurses", 12, true, false);
         assert!(result.contains("12"));
         assert!(result.contains("ncurses"));
         assert!(result.contains("(directly or indirectly)"));
@@ -370,7 +727,7 @@ mod tests {
 
     #[test]
     fn test_format_uses_result_header_single() {
-        let result = format_uses_result_header("libffi", 1, false);
+        let result = format_uses_result_header("libffi", 1, false, false);
         assert!(result.contains("1"));
         // Count is still shown as-is (no plural handling)
     }
@@ -404,4 +761,187 @@ mod tests {
         let result = format_leaves_result_header(100);
         assert!(result.contains("100"));
     }
+
+    // ========================================================================
+    // Why Formatting Tests
+    // ========================================================================
+
+    #[test]
+    fn test_format_why_chain() {
+        let chain = vec!["node".to_string(), "icu4c".to_string()];
+        assert_eq!(format_why_chain(&chain), "node → icu4c");
+    }
+
+    #[test]
+    fn test_format_why_header_dependency() {
+        let result = WhyResult {
+            explicit: false,
+            chains: vec![vec!["node".to_string(), "icu4c".to_string()]],
+        };
+        let header = format_why_header("icu4c", &result);
+        assert!(header.contains("icu4c"));
+        assert!(header.contains("is installed because:"));
+    }
+
+    #[test]
+    fn test_format_why_header_explicit() {
+        let result = WhyResult {
+            explicit: true,
+            chains: vec![],
+        };
+        assert!(format_why_header("jq", &result).contains("was installed explicitly."));
+
+        let result = WhyResult {
+            explicit: true,
+            chains: vec![vec!["wget".to_string(), "openssl@3".to_string()]],
+        };
+        assert!(format_why_header("openssl@3", &result).contains("is also needed by:"));
+    }
+
+    #[test]
+    fn test_format_why_header_orphan() {
+        let result = WhyResult::default();
+        let header = format_why_header("oniguruma", &result);
+        assert!(header.contains("orphaned"));
+        assert!(header.contains("zb autoremove"));
+    }
+
+    #[test]
+    fn test_deps_tree_json() {
+        let tree = DepsTree {
+            name: "wget".to_string(),
+            installed: true,
+            annotation: Default::default(),
+            children: vec![DepsTree {
+                name: "openssl@3".to_string(),
+                installed: false,
+                annotation: Default::default(),
+                children: vec![],
+            }],
+        };
+
+        assert_eq!(
+            deps_tree_json(&tree, false),
+            json!({
+                "name": "wget",
+                "installed": true,
+                "dependencies": [
+                    { "name": "openssl@3", "installed": false, "dependencies": [] }
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_deps_tree_json_annotated() {
+        let tree = DepsTree {
+            name: "openssl@3".to_string(),
+            installed: true,
+            annotation: zb_io::DepAnnotation {
+                installed_version: Some("3.3.1".to_string()),
+                available_version: Some("3.3.2".to_string()),
+                pinned: true,
+                keg_only: false,
+            },
+            children: vec![],
+        };
+
+        assert_eq!(
+            deps_tree_json(&tree, true),
+            json!({
+                "name": "openssl@3",
+                "installed": true,
+                "installed_version": "3.3.1",
+                "available_version": "3.3.2",
+                "pinned": true,
+                "keg_only": false,
+                "dependencies": [],
+            })
+        );
+    }
+
+    fn diamond_graph() -> DepsGraph {
+        let tree = DepsTree {
+            name: "curl".to_string(),
+            installed: false,
+            annotation: Default::default(),
+            children: ["libssh2", "rtmpdump"]
+                .map(|name| DepsTree {
+                    name: name.to_string(),
+                    installed: true,
+                    annotation: Default::default(),
+                    children: vec![DepsTree {
+                        name: "openssl@3".to_string(),
+                        installed: true,
+                        annotation: Default::default(),
+                        children: vec![],
+                    }],
+                })
+                .to_vec(),
+        };
+        tree.to_graph()
+    }
+
+    #[test]
+    fn test_graph_format_from_str() {
+        assert_eq!("dot".parse(), Ok(GraphFormat::Dot));
+        assert_eq!("mermaid".parse(), Ok(GraphFormat::Mermaid));
+        assert_eq!("json".parse(), Ok(GraphFormat::Json));
+        assert!("svg".parse::<GraphFormat>().is_err());
+    }
+
+    #[test]
+    fn test_graph_dot() {
+        let dot = graph_dot(&diamond_graph());
+        assert!(dot.starts_with("digraph dependencies {"));
+        assert!(dot.contains("    \"curl\" [style=dashed];\n"));
+        assert!(dot.contains("    \"libssh2\";\n"));
+        assert!(dot.contains("    \"curl\" -> \"rtmpdump\";\n"));
+        assert_eq!(dot.matches("    \"openssl@3\";\n").count(), 1);
+        assert_eq!(dot.matches("-> \"openssl@3\"").count(), 2);
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_graph_mermaid() {
+        assert_eq!(
+            graph_mermaid(&diamond_graph()),
+            "flowchart LR\n    \
+             n0[\"curl\"]\n    \
+             n1[\"libssh2\"]\n    \
+             n2[\"openssl@3\"]\n    \
+             n3[\"rtmpdump\"]\n    \
+             n0 --> n1\n    \
+             n0 --> n3\n    \
+             n1 --> n2\n    \
+             n3 --> n2\n    \
+             classDef missing stroke-dasharray: 5 5\n    \
+             class n0 missing\n"
+        );
+    }
+
+    #[test]
+    fn test_graph_adjacency_json() {
+        assert_eq!(
+            graph_adjacency_json(&diamond_graph()),
+            json!({
+                "curl": ["libssh2", "rtmpdump"],
+                "libssh2": ["openssl@3"],
+                "openssl@3": [],
+                "rtmpdump": ["openssl@3"],
+            })
+        );
+    }
+
+    #[test]
+    fn test_why_render_json() {
+        let output = WhyOutput {
+            formula: "oniguruma".to_string(),
+            result: WhyResult::default(),
+        };
+        assert_eq!(
+            output.render_json(),
+            json!({ "formula": "oniguruma", "explicit": false, "orphan": true, "chains": [] })
+        );
+    }
 }
@@ -1,10 +1,13 @@
 //! Doctor command implementation.
 
 use console::style;
+use serde_json::{Value, json};
 
 use zb_io::install::Installer;
 use zb_io::{DoctorCheck, DoctorResult, DoctorStatus};
 
+use crate::render::{Render, emit};
+
 /// Format the marker symbol for a given doctor status.
 pub fn format_status_marker(status: &DoctorStatus) -> String {
     match status {
@@ -130,20 +133,57 @@ pub fn format_summary_styled(result: &DoctorResult) -> Vec<String> {
     }
 }
 
-/// Run the doctor command.
-pub async fn run(installer: &mut Installer) -> Result<(), zb_core::Error> {
-    println!("{} Running diagnostics...\n", style("==>").cyan().bold());
+/// Machine-readable name of a doctor status
+pub fn status_name(status: &DoctorStatus) -> &'static str {
+    match status {
+        DoctorStatus::Ok => "ok",
+        DoctorStatus::Warning => "warning",
+        DoctorStatus::Error => "error",
+    }
+}
 
-    let result = installer.doctor().await;
+impl Render for DoctorResult {
+    fn render_human(&self) {
+        for check in &self.checks {
+            println!("{}", format_check_line_styled(check));
+        }
 
-    for check in &result.checks {
-        println!("{}", format_check_line_styled(check));
+        println!();
+        for line in format_summary_styled(self) {
+            println!("{}", line);
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        json!({
+            "healthy": self.is_healthy(),
+            "errors": self.errors,
+            "warnings": self.warnings,
+            "checks": self.checks.iter().map(|check| json!({
+                "name": check.name,
+                "status": status_name(&check.status),
+                "message": check.message,
+                "fix": check.fix,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Run the doctor command.
+pub async fn run(
+    installer: &mut Installer,
+    network: bool,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    if !json {
+        println!("{} Running diagnostics...\n", style("==>").cyan().bold());
     }
 
-    println!();
-    for line in format_summary_styled(&result) {
-        println!("{}", line);
+    let mut result = installer.doctor().await;
+    if network {
+        result.extend(installer.network_doctor().await);
     }
+    emit(&result, json);
 
     Ok(())
 }
@@ -419,4 +459,32 @@ mod tests {
         assert!(output.contains("$HOME/bin"));
         assert!(output.contains("export PATH"));
     }
+
+    #[test]
+    fn test_doctor_render_json() {
+        let result = make_result(
+            vec![make_check(
+                "Prefix is not writable",
+                DoctorStatus::Error,
+                Some("chown it"),
+            )],
+            1,
+            0,
+        );
+
+        assert_eq!(
+            result.render_json(),
+            json!({
+                "healthy": false,
+                "errors": 1,
+                "warnings": 0,
+                "checks": [{
+                    "name": "test_check",
+                    "status": "error",
+                    "message": "Prefix is not writable",
+                    "fix": "chown it",
+                }],
+            })
+        );
+    }
 }
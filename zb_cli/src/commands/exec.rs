@@ -0,0 +1,122 @@
+//! Exec command implementation.
+
+use console::style;
+use std::path::PathBuf;
+use std::process::Command;
+
+use zb_io::install::Installer;
+
+/// Run the exec command: run `command` with the formula's keg (and its
+/// dependencies' kegs) first on the search paths, then exit with its status.
+pub async fn run(
+    installer: &Installer,
+    formula: &str,
+    command: &[String],
+) -> Result<(), zb_core::Error> {
+    let env = installer.exec_env(formula).await?;
+
+    for name in &env.missing {
+        eprintln!(
+            "{} dependency {} of {} is not installed",
+            style("Warning:").yellow().bold(),
+            name,
+            formula
+        );
+    }
+
+    let mut vars = env.path_vars();
+    vars.extend(env.library_path_var());
+    let exports = build_exec_env(&vars, |var| std::env::var(var).ok());
+
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+    match Command::new(program).args(args).envs(exports).status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!(
+                "{} failed to run {}: {}",
+                style("error:").red().bold(),
+                program,
+                e
+            );
+            std::process::exit(127);
+        }
+    }
+}
+
+/// Prepend keg directories to the current value of each variable.
+/// Extracted for testability.
+pub(crate) fn build_exec_env(
+    vars: &[(&str, Vec<PathBuf>)],
+    current: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(var, dirs)| {
+            let mut value = dirs
+                .iter()
+                .map(|d| d.display().to_string())
+                .collect::<Vec<_>>()
+                .join(":");
+            match current(var).filter(|v| !v.is_empty()) {
+                Some(existing) => {
+                    value.push(':');
+                    value.push_str(&existing);
+                }
+                // An empty MANPATH entry keeps the system man pages searchable
+                None if *var == "MANPATH" => value.push(':'),
+                None => {}
+            }
+            (var.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_exec_env_prepends_to_existing_values() {
+        let vars = vec![
+            (
+                "PATH",
+                vec![
+                    PathBuf::from("/cellar/node@18/18.20.0/bin"),
+                    PathBuf::from("/cellar/icu4c/74.2/bin"),
+                ],
+            ),
+            (
+                "PKG_CONFIG_PATH",
+                vec![PathBuf::from("/cellar/icu4c/74.2/lib/pkgconfig")],
+            ),
+            (
+                "MANPATH",
+                vec![PathBuf::from("/cellar/node@18/18.20.0/share/man")],
+            ),
+        ];
+        let env = build_exec_env(&vars, |var| match var {
+            "PATH" => Some("/usr/bin:/bin".to_string()),
+            "PKG_CONFIG_PATH" => Some(String::new()),
+            _ => None,
+        });
+
+        assert_eq!(
+            env,
+            vec![
+                (
+                    "PATH".to_string(),
+                    "/cellar/node@18/18.20.0/bin:/cellar/icu4c/74.2/bin:/usr/bin:/bin".to_string()
+                ),
+                (
+                    "PKG_CONFIG_PATH".to_string(),
+                    "/cellar/icu4c/74.2/lib/pkgconfig".to_string()
+                ),
+                (
+                    "MANPATH".to_string(),
+                    "/cellar/node@18/18.20.0/share/man:".to_string()
+                ),
+            ]
+        );
+    }
+}
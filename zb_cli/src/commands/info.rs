@@ -5,42 +5,189 @@ use std::path::Path;
 
 use zb_core::Formula;
 use zb_io::install::Installer;
-use zb_io::search::search_formulas;
-use zb_io::{ApiCache, ApiClient, InstalledKeg};
+use zb_io::search::{SearchOptions, SearchScope};
+use zb_io::{BottleAvailability, InstalledKeg, InventoryEntry, InventorySort, LinkStatus};
 
-use crate::display::chrono_lite_format;
+use crate::commands::query::installed_keg_json;
+use crate::display::{chrono_lite_format, format_age, format_bytes};
+use crate::render::print_json;
 
-/// Run the list command.
-pub fn run_list(installer: &Installer, pinned: bool) -> Result<(), zb_core::Error> {
-    let installed = if pinned {
-        installer.list_pinned()?
+/// Print every file a keg materialized, then the symlinks it owns in the
+/// prefix, one path per line.
+pub fn run_files(installer: &Installer, formula: &str, json: bool) -> Result<(), zb_core::Error> {
+    let keg_files = installer.list_keg_files(formula)?;
+    let linked_files = installer.get_linked_files(formula)?;
+
+    if json {
+        let doc = build_files_json(&keg_files, &linked_files);
+        match serde_json::to_string_pretty(&doc) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                return Err(zb_core::Error::StoreCorruption {
+                    message: format!("failed to serialize file list: {}", e),
+                });
+            }
+        }
     } else {
-        installer.list_installed()?
-    };
+        for line in format_file_listing(&keg_files, &linked_files) {
+            println!("{}", line);
+        }
+    }
 
-    match determine_list_output_kind(installed.len(), pinned) {
-        ListOutputKind::Empty { pinned: is_pinned } => {
-            println!("{}", empty_list_message(is_pinned));
+    Ok(())
+}
+
+/// Run the list command.
+pub fn run_list(
+    installer: &Installer,
+    pinned: bool,
+    installed_by: Option<&str>,
+    versions: bool,
+    full: bool,
+    sort: InventorySort,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let mut entries = installer.inventory(sort)?;
+    if pinned {
+        entries.retain(|entry| entry.keg.pinned);
+    }
+    if let Some(filter) = installed_by {
+        let matching = filter_by_provenance(
+            entries.iter().map(|entry| entry.keg.clone()).collect(),
+            filter,
+        );
+        entries.retain(|entry| matching.iter().any(|keg| keg.name == entry.keg.name));
+    }
+
+    if json {
+        let mut kegs = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let installed_versions: Vec<String> = installer
+                .list_versions(&entry.keg.name)?
+                .into_iter()
+                .map(|v| v.version)
+                .collect();
+            kegs.push(inventory_entry_json(entry, &installed_versions));
         }
-        ListOutputKind::HasItems { count: _ } => {
-            for keg in installed {
-                // format_list_entry provides the plain-text format (used for testing)
-                let _ = format_list_entry(&keg.name, &keg.version, keg.pinned);
-
-                // Styled output for terminal
-                let styled_pin = if keg.pinned {
-                    format!(" {}", style("(pinned)").yellow())
-                } else {
-                    String::new()
-                };
-                println!(
-                    "{} {}{}",
-                    style(&keg.name).bold(),
-                    style(&keg.version).dim(),
-                    styled_pin
-                );
-            }
+        print_json(&serde_json::Value::Array(kegs));
+        return Ok(());
+    }
+
+    if let Some(filter) = installed_by
+        && entries.is_empty()
+    {
+        println!("{}", empty_provenance_message(filter));
+        return Ok(());
+    }
+    if let ListOutputKind::Empty { pinned: is_pinned } =
+        determine_list_output_kind(entries.len(), pinned)
+    {
+        println!("{}", empty_list_message(is_pinned));
+        return Ok(());
+    }
+
+    if full {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let mut lines = format_inventory_lines(&entries, now).into_iter();
+        if let Some(header) = lines.next() {
+            println!("{}", style(header).bold());
+        }
+        for line in lines {
+            println!("{}", line);
         }
+        return Ok(());
+    }
+
+    for entry in entries {
+        let keg = entry.keg;
+        let installed_versions: Vec<String> = installer
+            .list_versions(&keg.name)?
+            .into_iter()
+            .map(|v| v.version)
+            .collect();
+        if versions {
+            println!(
+                "{}",
+                format_versions_line(&keg.name, &keg.version, &installed_versions)
+            );
+            continue;
+        }
+
+        // format_list_entry provides the plain-text format (used for testing)
+        let _ = format_list_entry(&keg.name, &keg.version, keg.pinned);
+
+        // Styled output for terminal
+        let styled_pin = if keg.pinned {
+            format!(" {}", style("(pinned)").yellow())
+        } else {
+            String::new()
+        };
+        let other_versions = format_other_versions(&keg.version, &installed_versions);
+        let styled_others = if other_versions.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", style(other_versions).dim())
+        };
+        let marker = format_link_marker(keg.link_status());
+        let styled_marker = match keg.link_status() {
+            LinkStatus::Linked => style(marker).green(),
+            LinkStatus::Unlinked => style(marker).red(),
+            LinkStatus::KegOnly => style(marker).dim(),
+        };
+        println!(
+            "{} {} {}{}{}",
+            styled_marker,
+            style(&keg.name).bold(),
+            style(&keg.version).dim(),
+            styled_pin,
+            styled_others
+        );
+    }
+
+    Ok(())
+}
+
+/// List installed formulas upstream has deprecated or disabled.
+pub async fn run_list_deprecated(installer: &Installer, json: bool) -> Result<(), zb_core::Error> {
+    let deprecated = installer.list_deprecated().await?;
+    if json {
+        print_json(&serde_json::json!(
+            deprecated
+                .iter()
+                .map(|(keg, message)| serde_json::json!({
+                    "name": keg.name,
+                    "version": keg.version,
+                    "message": message,
+                }))
+                .collect::<Vec<_>>()
+        ));
+        return Ok(());
+    }
+    if deprecated.is_empty() {
+        println!("No installed formulas are deprecated or disabled.");
+        return Ok(());
+    }
+
+    println!(
+        "{} {} installed {} deprecated or disabled:",
+        style("==>").cyan().bold(),
+        deprecated.len(),
+        if deprecated.len() == 1 {
+            "formula is"
+        } else {
+            "formulas are"
+        }
+    );
+    for (keg, message) in deprecated {
+        println!(
+            "    {} {} {}",
+            style(&keg.name).bold(),
+            style(&keg.version).dim(),
+            message
+        );
     }
 
     Ok(())
@@ -56,10 +203,16 @@ pub async fn run_info(
     let keg = installer.get_installed(&formula);
     let api_formula = installer.get_formula(&formula).await.ok();
 
+    // Before installing, show which platforms have bottles
+    let bottles = match (&keg, &api_formula) {
+        (None, Some(f)) => Some(installer.bottle_availability(f).await),
+        _ => None,
+    };
+
     if json {
-        print_info_json(installer, &formula, &keg, &api_formula).await?;
+        print_info_json(installer, &formula, &keg, &api_formula, &bottles).await?;
     } else {
-        print_info_human(installer, prefix, &formula, &keg, &api_formula).await?;
+        print_info_human(installer, prefix, &formula, &keg, &api_formula, &bottles).await?;
     }
 
     Ok(())
@@ -70,6 +223,7 @@ async fn print_info_json(
     formula: &str,
     keg: &Option<InstalledKeg>,
     api_formula: &Option<Formula>,
+    bottles: &Option<BottleAvailability>,
 ) -> Result<(), zb_core::Error> {
     let mut info = build_info_json_base(formula, keg.is_some());
 
@@ -82,6 +236,12 @@ async fn print_info_json(
             keg.explicit,
         );
         info.extend(installed_info);
+        info.insert(
+            "link_status".to_string(),
+            serde_json::json!(keg.link_status().as_str()),
+        );
+        info.insert("provenance".to_string(), serde_json::json!(keg.provenance));
+        info.insert("receipt".to_string(), serde_json::json!(keg.receipt));
 
         if let Ok(linked_files) = installer.get_linked_files(formula) {
             let files = build_linked_files_json(&linked_files);
@@ -105,6 +265,14 @@ async fn print_info_json(
             f.keg_only,
         );
         info.extend(api_info);
+        info.extend(build_deprecation_json(f));
+        if let Some(tap) = &f.tap {
+            info.insert("tap".to_string(), serde_json::json!(tap));
+            info.insert(
+                "tap_pinned".to_string(),
+                serde_json::json!(is_tap_pinned(installer, tap)),
+            );
+        }
 
         // Add outdated info if there's an update available
         if let Some(keg) = keg {
@@ -116,18 +284,12 @@ async fn print_info_json(
         }
     }
 
-    match serde_json::to_string_pretty(&info) {
-        Ok(json) => println!("{}", json),
-        Err(e) => {
-            eprintln!(
-                "{} Failed to serialize JSON: {}",
-                style("error:").red().bold(),
-                e
-            );
-            std::process::exit(1);
-        }
+    if let Some(bottles) = bottles {
+        info.extend(build_bottles_json(bottles));
     }
 
+    print_json(&serde_json::Value::Object(info));
+
     Ok(())
 }
 
@@ -137,6 +299,7 @@ async fn print_info_human(
     formula: &str,
     keg: &Option<InstalledKeg>,
     api_formula: &Option<Formula>,
+    bottles: &Option<BottleAvailability>,
 ) -> Result<(), zb_core::Error> {
     let output_kind = determine_info_output_kind(keg.is_some(), api_formula.is_some());
     if output_kind == InfoOutputKind::NotFound {
@@ -155,6 +318,14 @@ async fn print_info_human(
         if let Some(homepage) = &f.homepage {
             println!("{}", style(homepage).cyan().underlined());
         }
+        if let Some(message) = f.deprecation_message() {
+            let label = if f.disabled {
+                style("Disabled:").red().bold()
+            } else {
+                style("Deprecated:").yellow().bold()
+            };
+            println!("{} {} {}", label, f.name, message);
+        }
     }
 
     println!();
@@ -179,6 +350,20 @@ async fn print_info_human(
             print!(" {}", style("(installed as dependency)").dim());
         }
         println!();
+
+        let status = format_link_status(keg.link_status(), &keg.name);
+        let styled_status = match keg.link_status() {
+            LinkStatus::Unlinked => style(status).yellow(),
+            _ => style(status),
+        };
+        println!("{} {}", style("Link status:").dim(), styled_status);
+
+        if let Some(provenance) = &keg.provenance {
+            println!("{} {}", style("Installed by:").dim(), provenance);
+        }
+        if let Some(receipt) = &keg.receipt {
+            println!("{} {}", style("Built from:").dim(), receipt);
+        }
     } else {
         println!("{} Not installed", style("Installed:").dim());
     }
@@ -221,6 +406,17 @@ async fn print_info_human(
         println!("{} {}", style("License:").dim(), license);
     }
 
+    // Where the formula resolves from
+    if let Some(f) = api_formula
+        && let Some(tap) = &f.tap
+    {
+        println!(
+            "{} {}",
+            style("Tap:").dim(),
+            format_tap_source(tap, is_tap_pinned(installer, tap))
+        );
+    }
+
     // Keg-only status
     if let Some(f) = api_formula
         && f.keg_only
@@ -257,6 +453,27 @@ async fn print_info_human(
         }
     }
 
+    // Bottle availability, for formulas not installed yet
+    if let Some(bottles) = bottles {
+        println!();
+        println!("{}", style("Bottles:").dim());
+        if bottles.bottles.is_empty() {
+            println!("  none");
+        }
+        for bottle in &bottles.bottles {
+            let selected = bottles.selected.as_deref() == Some(bottle.tag.as_str());
+            let entry = format_bottle_entry(&bottle.tag, bottle.size, selected);
+            if selected {
+                println!("  {}", style(entry).green());
+            } else {
+                println!("  {}", entry);
+            }
+        }
+        if let Some(e) = &bottles.unsupported {
+            println!("{} {}", style("Not installable here:").red().bold(), e);
+        }
+    }
+
     // Dependents
     if keg.is_some()
         && let Ok(dependents) = installer.get_dependents(formula).await
@@ -314,6 +531,75 @@ async fn print_info_human(
     Ok(())
 }
 
+/// Build the deprecation status for JSON output, using the API's field names.
+/// Extracted for testability.
+pub(crate) fn build_deprecation_json(f: &Formula) -> serde_json::Map<String, serde_json::Value> {
+    let mut info = serde_json::Map::new();
+    info.insert("deprecated".to_string(), serde_json::json!(f.deprecated));
+    info.insert(
+        "deprecation_date".to_string(),
+        serde_json::json!(f.deprecation_date),
+    );
+    info.insert(
+        "deprecation_reason".to_string(),
+        serde_json::json!(f.deprecation_reason),
+    );
+    info.insert("disabled".to_string(), serde_json::json!(f.disabled));
+    info.insert(
+        "disable_date".to_string(),
+        serde_json::json!(f.disable_date),
+    );
+    info.insert(
+        "disable_reason".to_string(),
+        serde_json::json!(f.disable_reason),
+    );
+    info
+}
+
+/// Format one bottle of the availability list, e.g.
+/// `x86_64_linux  1.2 MB  (this platform)`.
+/// Extracted for testability.
+pub(crate) fn format_bottle_entry(tag: &str, size: Option<u64>, selected: bool) -> String {
+    let size = size.map_or_else(|| "size unknown".to_string(), format_bytes);
+    let mut entry = format!("{:<16} {}", tag, size);
+    if selected {
+        entry.push_str("  (this platform)");
+    }
+    entry
+}
+
+/// Build the bottle availability for JSON output.
+/// Extracted for testability.
+pub(crate) fn build_bottles_json(
+    availability: &BottleAvailability,
+) -> serde_json::Map<String, serde_json::Value> {
+    let bottles: Vec<_> = availability
+        .bottles
+        .iter()
+        .map(|bottle| {
+            serde_json::json!({
+                "tag": bottle.tag,
+                "size": bottle.size,
+                "selected": availability.selected.as_deref() == Some(bottle.tag.as_str()),
+            })
+        })
+        .collect();
+
+    let mut info = serde_json::Map::new();
+    info.insert("bottles".to_string(), serde_json::json!(bottles));
+    info.insert(
+        "supported".to_string(),
+        serde_json::json!(availability.unsupported.is_none()),
+    );
+    if let Some(e) = &availability.unsupported {
+        info.insert(
+            "unsupported_reason".to_string(),
+            serde_json::json!({"code": e.code(), "message": e.to_string()}),
+        );
+    }
+    info
+}
+
 /// Truncate a description to a maximum length with ellipsis.
 /// Extracted for testability.
 pub(crate) fn truncate_description(desc: &str, max_len: usize) -> String {
@@ -373,6 +659,31 @@ pub(crate) fn build_linked_files_json(linked_files: &[(String, String)]) -> Vec<
         .collect()
 }
 
+/// Build the `--files` JSON document.
+/// Extracted for testability.
+pub(crate) fn build_files_json(
+    keg_files: &[std::path::PathBuf],
+    linked_files: &[(String, String)],
+) -> serde_json::Value {
+    serde_json::json!({
+        "files": keg_files,
+        "linked_files": build_linked_files_json(linked_files),
+    })
+}
+
+/// Lines printed by `--files`: keg files first, then links in the prefix.
+/// Extracted for testability.
+pub(crate) fn format_file_listing(
+    keg_files: &[std::path::PathBuf],
+    linked_files: &[(String, String)],
+) -> Vec<String> {
+    keg_files
+        .iter()
+        .map(|f| f.display().to_string())
+        .chain(linked_files.iter().map(|(link, _)| link.clone()))
+        .collect()
+}
+
 /// Build search result JSON.
 /// Extracted for testability.
 pub(crate) fn build_search_result_json(
@@ -446,6 +757,20 @@ pub(crate) fn empty_list_message(pinned: bool) -> &'static str {
     }
 }
 
+/// Keep the kegs whose provenance matches a `kind` or `kind:value` filter.
+/// Extracted for testability.
+pub(crate) fn filter_by_provenance(kegs: Vec<InstalledKeg>, filter: &str) -> Vec<InstalledKeg> {
+    kegs.into_iter()
+        .filter(|keg| keg.provenance.as_ref().is_some_and(|p| p.matches(filter)))
+        .collect()
+}
+
+/// Generate the empty list message for `--installed-by`.
+/// Extracted for testability.
+pub(crate) fn empty_provenance_message(filter: &str) -> String {
+    format!("No formulas installed by '{}'.", filter)
+}
+
 /// Generate empty search results message based on filter.
 /// Extracted for testability.
 pub(crate) fn empty_search_message(query: &str, installed_only: bool) -> String {
@@ -501,6 +826,23 @@ pub(crate) enum VersionDisplay {
     NotInstalled(String),
 }
 
+/// Format the tap a formula resolves from, e.g. `user/tools (pinned)`.
+/// Extracted for testability.
+pub(crate) fn format_tap_source(tap: &str, pinned: bool) -> String {
+    if pinned {
+        format!("{} (pinned)", tap)
+    } else {
+        tap.to_string()
+    }
+}
+
+fn is_tap_pinned(installer: &Installer, tap: &str) -> bool {
+    installer
+        .list_taps()
+        .map(|taps| taps.iter().any(|t| t.name == tap && t.pinned))
+        .unwrap_or(false)
+}
+
 /// Format keg-only reason for display.
 /// Extracted for testability.
 pub(crate) fn format_keg_only_reason(explanation: Option<&str>) -> String {
@@ -565,6 +907,126 @@ pub(crate) fn format_list_entry(name: &str, version: &str, pinned: bool) -> Stri
     format!("{} {}{}", name, version, pin_marker)
 }
 
+/// Marker shown in the `zb list` link status column.
+/// Extracted for testability.
+pub(crate) fn format_link_marker(status: LinkStatus) -> &'static str {
+    match status {
+        LinkStatus::Linked => "✓",
+        LinkStatus::Unlinked => "✗",
+        LinkStatus::KegOnly => "○",
+    }
+}
+
+/// Describe a keg's link status for `zb info`.
+/// Extracted for testability.
+pub(crate) fn format_link_status(status: LinkStatus, name: &str) -> String {
+    match status {
+        LinkStatus::Linked => "linked".to_string(),
+        LinkStatus::Unlinked => format!("not linked (run `zb link {}`)", name),
+        LinkStatus::KegOnly => "keg-only, not linked".to_string(),
+    }
+}
+
+/// Format the inactive versions of a formula that are still in the Cellar.
+/// Extracted for testability.
+pub(crate) fn format_other_versions(active: &str, versions: &[String]) -> String {
+    let others: Vec<&str> = versions
+        .iter()
+        .map(String::as_str)
+        .filter(|v| *v != active)
+        .collect();
+    if others.is_empty() {
+        String::new()
+    } else {
+        format!("(also {})", others.join(", "))
+    }
+}
+
+/// A formula and every installed version, the active one last, as in
+/// `brew list --versions`.
+/// Extracted for testability.
+pub(crate) fn format_versions_line(name: &str, active: &str, versions: &[String]) -> String {
+    let mut line = name.to_string();
+    for version in versions.iter().filter(|v| *v != active) {
+        line.push(' ');
+        line.push_str(version);
+    }
+    line.push(' ');
+    line.push_str(active);
+    line
+}
+
+/// The `zb list --full` table: a header line, then one aligned row per keg.
+/// Extracted for testability.
+pub(crate) fn format_inventory_lines(entries: &[InventoryEntry], now: i64) -> Vec<String> {
+    let mut rows: Vec<[String; 8]> = vec![[
+        "Name".to_string(),
+        "Version".to_string(),
+        "Size".to_string(),
+        "Installed".to_string(),
+        "Reason".to_string(),
+        "Pinned".to_string(),
+        "Linked".to_string(),
+        "Source".to_string(),
+    ]];
+    for entry in entries {
+        let keg = &entry.keg;
+        rows.push([
+            keg.name.clone(),
+            keg.version.clone(),
+            entry
+                .size_bytes
+                .map(format_bytes)
+                .unwrap_or_else(|| "-".to_string()),
+            format_age(now - keg.installed_at),
+            if keg.explicit {
+                "explicit"
+            } else {
+                "dependency"
+            }
+            .to_string(),
+            if keg.pinned { "yes" } else { "no" }.to_string(),
+            match keg.link_status() {
+                LinkStatus::Linked => "linked",
+                LinkStatus::Unlinked => "unlinked",
+                LinkStatus::KegOnly => "keg-only",
+            }
+            .to_string(),
+            entry.source.clone(),
+        ]);
+    }
+
+    let mut widths = [0; 8];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Build the `zb list --json` object for one keg: the `zb query installed`
+/// entry plus its size and source.
+/// Extracted for testability.
+pub(crate) fn inventory_entry_json(
+    entry: &InventoryEntry,
+    versions: &[String],
+) -> serde_json::Value {
+    let mut json = installed_keg_json(&entry.keg, versions);
+    json["size_bytes"] = serde_json::json!(entry.size_bytes);
+    json["source"] = serde_json::json!(entry.source);
+    json
+}
+
 /// Determine what info output type to show based on available data.
 /// Extracted for testability.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -693,13 +1155,25 @@ pub(crate) fn determine_list_output_kind(item_count: usize, pinned_filter: bool)
     }
 }
 
+/// Map the search scoping flags to a search scope.
+/// Extracted for testability.
+pub(crate) fn search_scope(full_name: bool, alias: bool) -> SearchScope {
+    if full_name {
+        SearchScope::FullName
+    } else if alias {
+        SearchScope::Alias
+    } else {
+        SearchScope::All
+    }
+}
+
 /// Run the search command.
 pub async fn run_search(
     installer: &Installer,
-    root: &Path,
     query: String,
     json: bool,
     installed: bool,
+    scope: SearchScope,
 ) -> Result<(), zb_core::Error> {
     if !json {
         println!(
@@ -709,20 +1183,12 @@ pub async fn run_search(
         );
     }
 
-    let cache_dir = root.join("cache");
-    let cache = ApiCache::open(&cache_dir).ok();
-    let api_client = if let Some(c) = cache {
-        ApiClient::new().with_cache(c)
-    } else {
-        ApiClient::new()
+    let options = SearchOptions {
+        scope,
+        installed_only: installed,
+        ..SearchOptions::default()
     };
-
-    let formulas = api_client.get_all_formulas().await?;
-    let mut results = search_formulas(&formulas, &query);
-
-    if installed {
-        results.retain(|r| installer.is_installed(&r.name));
-    }
+    let results = installer.search(&query, &options).await?;
 
     let output_kind = determine_search_output_kind(json, results.len(), installed);
 
@@ -731,27 +1197,16 @@ pub async fn run_search(
             let json_results: Vec<serde_json::Value> = results
                 .iter()
                 .map(|r| {
-                    let is_installed = installer.is_installed(&r.name);
                     build_search_result_json(
                         &r.name,
                         &r.full_name,
                         &r.version,
                         &r.description,
-                        is_installed,
+                        r.installed,
                     )
                 })
                 .collect();
-            match serde_json::to_string_pretty(&json_results) {
-                Ok(json) => println!("{}", json),
-                Err(e) => {
-                    eprintln!(
-                        "{} Failed to serialize JSON: {}",
-                        style("error:").red().bold(),
-                        e
-                    );
-                    std::process::exit(1);
-                }
-            }
+            print_json(&serde_json::Value::Array(json_results));
         }
         SearchOutputKind::Empty { installed_only } => {
             println!("{}", empty_search_message(&query, installed_only));
@@ -772,7 +1227,7 @@ pub async fn run_search(
             let (display_count, remaining) = calculate_search_display(results.len(), 20);
 
             for result in results.iter().take(display_count) {
-                let is_installed = installer.is_installed(&result.name);
+                let is_installed = result.installed;
 
                 // Use format_search_result_entry for the base plain-text format
                 let plain_lines = format_search_result_entry(
@@ -819,6 +1274,7 @@ pub async fn run_search(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use zb_io::Provenance;
 
     // ========================================================================
     // Truncate Description Tests
@@ -1024,6 +1480,48 @@ mod tests {
         assert_eq!(json.len(), 3);
     }
 
+    // ========================================================================
+    // File Listing Tests
+    // ========================================================================
+
+    #[test]
+    fn test_format_file_listing_keg_files_then_links() {
+        let keg_files = vec![
+            std::path::PathBuf::from("/opt/zb/Cellar/jq/1.7.1/bin/jq"),
+            std::path::PathBuf::from("/opt/zb/Cellar/jq/1.7.1/share/man/man1/jq.1"),
+        ];
+        let linked = vec![(
+            "/opt/zb/bin/jq".to_string(),
+            "/opt/zb/Cellar/jq/1.7.1/bin/jq".to_string(),
+        )];
+
+        assert_eq!(
+            format_file_listing(&keg_files, &linked),
+            vec![
+                "/opt/zb/Cellar/jq/1.7.1/bin/jq",
+                "/opt/zb/Cellar/jq/1.7.1/share/man/man1/jq.1",
+                "/opt/zb/bin/jq",
+            ]
+        );
+        assert!(format_file_listing(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_build_files_json() {
+        let keg_files = vec![std::path::PathBuf::from("/opt/zb/Cellar/jq/1.7.1/bin/jq")];
+        let linked = vec![(
+            "/opt/zb/bin/jq".to_string(),
+            "/opt/zb/Cellar/jq/1.7.1/bin/jq".to_string(),
+        )];
+
+        let json = build_files_json(&keg_files, &linked);
+        assert_eq!(
+            json["files"],
+            serde_json::json!(["/opt/zb/Cellar/jq/1.7.1/bin/jq"])
+        );
+        assert_eq!(json["linked_files"][0]["link"], "/opt/zb/bin/jq");
+    }
+
     // ========================================================================
     // Search Result JSON Tests
     // ========================================================================
@@ -1227,6 +1725,69 @@ mod tests {
         assert_eq!(empty_list_message(false), "No formulas installed.");
     }
 
+    #[test]
+    fn test_empty_provenance_message() {
+        assert_eq!(
+            empty_provenance_message("brewfile"),
+            "No formulas installed by 'brewfile'."
+        );
+    }
+
+    // ========================================================================
+    // Provenance Filter Tests
+    // ========================================================================
+
+    fn keg_with_provenance(name: &str, provenance: Option<Provenance>) -> InstalledKeg {
+        InstalledKeg {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            store_key: "key".to_string(),
+            installed_at: 0,
+            pinned: false,
+            explicit: true,
+            linked: true,
+            keg_only: false,
+            provenance,
+            head_commit: None,
+            receipt: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_by_provenance() {
+        let kegs = vec![
+            keg_with_provenance("jq", Some(Provenance::Cli)),
+            keg_with_provenance(
+                "node",
+                Some(Provenance::Brewfile {
+                    path: "/work/Brewfile".to_string(),
+                    run_id: "20261016T120000Z".to_string(),
+                }),
+            ),
+            keg_with_provenance(
+                "icu4c",
+                Some(Provenance::DependencyOf {
+                    name: "node".to_string(),
+                }),
+            ),
+            keg_with_provenance("legacy", None),
+        ];
+
+        let names = |filter: &str| -> Vec<String> {
+            filter_by_provenance(kegs.clone(), filter)
+                .into_iter()
+                .map(|k| k.name)
+                .collect()
+        };
+
+        assert_eq!(names("cli"), vec!["jq"]);
+        assert_eq!(names("brewfile:/work/Brewfile"), vec!["node"]);
+        assert_eq!(names("brewfile:20261016T120000Z"), vec!["node"]);
+        assert_eq!(names("dependency_of:node"), vec!["icu4c"]);
+        assert!(names("dependency_of:jq").is_empty());
+        assert!(names("unknown").is_empty());
+    }
+
     // ========================================================================
     // Empty Search Message Tests
     // ========================================================================
@@ -1263,6 +1824,13 @@ mod tests {
         assert_eq!(search_results_label(true), "installed formulas");
     }
 
+    #[test]
+    fn test_search_scope_from_flags() {
+        assert_eq!(search_scope(false, false), SearchScope::All);
+        assert_eq!(search_scope(true, false), SearchScope::FullName);
+        assert_eq!(search_scope(false, true), SearchScope::Alias);
+    }
+
     // ========================================================================
     // Dependency Status Tests
     // ========================================================================
@@ -1353,6 +1921,12 @@ mod tests {
         assert_eq!(result, "Yes");
     }
 
+    #[test]
+    fn test_format_tap_source_marks_pinned_taps() {
+        assert_eq!(format_tap_source("homebrew/core", false), "homebrew/core");
+        assert_eq!(format_tap_source("user/tools", true), "user/tools (pinned)");
+    }
+
     #[test]
     fn test_format_keg_only_reason_none() {
         let result = format_keg_only_reason(None);
@@ -1400,6 +1974,78 @@ mod tests {
         assert_eq!(json.get("keg_only").unwrap(), false);
     }
 
+    #[test]
+    fn test_format_bottle_entry() {
+        assert_eq!(
+            format_bottle_entry("x86_64_linux", None, false),
+            "x86_64_linux     size unknown"
+        );
+        let entry = format_bottle_entry("arm64_sonoma", Some(2048), true);
+        assert!(entry.starts_with("arm64_sonoma     "), "{entry}");
+        assert!(entry.ends_with("  (this platform)"), "{entry}");
+    }
+
+    #[test]
+    fn test_build_bottles_json() {
+        use zb_io::AvailableBottle;
+
+        let availability = BottleAvailability {
+            bottles: vec![
+                AvailableBottle {
+                    tag: "arm64_sonoma".to_string(),
+                    size: Some(1024),
+                },
+                AvailableBottle {
+                    tag: "x86_64_linux".to_string(),
+                    size: None,
+                },
+            ],
+            selected: Some("x86_64_linux".to_string()),
+            unsupported: None,
+        };
+        let json = build_bottles_json(&availability);
+        assert_eq!(
+            json.get("bottles").unwrap(),
+            &serde_json::json!([
+                {"tag": "arm64_sonoma", "size": 1024, "selected": false},
+                {"tag": "x86_64_linux", "size": null, "selected": true},
+            ])
+        );
+        assert_eq!(json.get("supported").unwrap(), true);
+        assert!(!json.contains_key("unsupported_reason"));
+
+        let availability = BottleAvailability {
+            bottles: Vec::new(),
+            selected: None,
+            unsupported: Some(zb_core::Error::UnsupportedBottle {
+                name: "libheif".to_string(),
+                available_platforms: Vec::new(),
+            }),
+        };
+        let json = build_bottles_json(&availability);
+        assert_eq!(json.get("supported").unwrap(), false);
+        assert_eq!(
+            json["unsupported_reason"]["code"],
+            serde_json::json!("unsupported_bottle")
+        );
+    }
+
+    #[test]
+    fn test_build_deprecation_json() {
+        let f: Formula = serde_json::from_str(
+            r#"{"name":"python@3.8","versions":{"stable":"3.8.20"},"bottle":{"stable":{"files":{}}},
+                "deprecated":true,"deprecation_date":"2024-10-14","deprecation_reason":"unsupported"}"#,
+        )
+        .unwrap();
+        let json = build_deprecation_json(&f);
+
+        assert_eq!(json.get("deprecated").unwrap(), true);
+        assert_eq!(json.get("deprecation_date").unwrap(), "2024-10-14");
+        assert_eq!(json.get("deprecation_reason").unwrap(), "unsupported");
+        assert_eq!(json.get("disabled").unwrap(), false);
+        assert!(json.get("disable_date").unwrap().is_null());
+    }
+
     #[test]
     fn test_build_formula_api_json_minimal() {
         let json = build_formula_api_json("1.0.0", None, None, None, &[], &[], None, false);
@@ -1560,6 +2206,90 @@ mod tests {
         assert_eq!(result, "git 2.44.0");
     }
 
+    #[test]
+    fn test_format_link_marker_is_distinct_per_status() {
+        let markers = [
+            format_link_marker(LinkStatus::Linked),
+            format_link_marker(LinkStatus::Unlinked),
+            format_link_marker(LinkStatus::KegOnly),
+        ];
+        assert_ne!(markers[0], markers[1]);
+        assert_ne!(markers[1], markers[2]);
+        assert_ne!(markers[0], markers[2]);
+    }
+
+    #[test]
+    fn test_format_link_status() {
+        assert_eq!(format_link_status(LinkStatus::Linked, "jq"), "linked");
+        assert_eq!(
+            format_link_status(LinkStatus::Unlinked, "jq"),
+            "not linked (run `zb link jq`)"
+        );
+        assert_eq!(
+            format_link_status(LinkStatus::KegOnly, "openssl@3"),
+            "keg-only, not linked"
+        );
+    }
+
+    #[test]
+    fn test_format_other_versions() {
+        let versions = vec!["1.6".to_string(), "1.7.1".to_string()];
+        assert_eq!(format_other_versions("1.7.1", &versions), "(also 1.6)");
+        assert_eq!(format_other_versions("1.7.1", &versions[1..]), "");
+    }
+
+    #[test]
+    fn test_format_versions_line() {
+        let versions = vec!["1.7.1".to_string(), "1.6".to_string()];
+        assert_eq!(format_versions_line("jq", "1.6", &versions), "jq 1.7.1 1.6");
+        assert_eq!(format_versions_line("jq", "1.6", &versions[1..]), "jq 1.6");
+    }
+
+    fn inventory_entry(name: &str, size_bytes: Option<u64>) -> InventoryEntry {
+        let mut keg = keg_with_provenance(name, None);
+        keg.installed_at = 1_000;
+        InventoryEntry {
+            keg,
+            size_bytes,
+            source: "homebrew/core".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_format_inventory_lines() {
+        let mut oniguruma = inventory_entry("oniguruma", None);
+        oniguruma.keg.explicit = false;
+        oniguruma.keg.pinned = true;
+        oniguruma.keg.linked = false;
+        oniguruma.source = "user/tools".to_string();
+        let entries = vec![inventory_entry("jq", Some(2048)), oniguruma];
+
+        let lines = format_inventory_lines(&entries, 1_000 + 2 * 60 * 60);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Name       Version  Size"));
+        assert!(lines[0].ends_with("Source"));
+        assert!(lines[1].starts_with("jq         1.0      2.0 KB"));
+        for cell in ["2h ago", "explicit", "no", "linked", "homebrew/core"] {
+            assert!(lines[1].contains(cell), "{cell} missing from {}", lines[1]);
+        }
+        for cell in ["-", "dependency", "yes", "unlinked", "user/tools"] {
+            assert!(lines[2].contains(cell), "{cell} missing from {}", lines[2]);
+        }
+    }
+
+    #[test]
+    fn test_inventory_entry_json() {
+        let json = inventory_entry_json(&inventory_entry("jq", Some(2048)), &["1.0".to_string()]);
+        assert_eq!(json["name"], "jq");
+        assert_eq!(json["installed_at"], 1_000);
+        assert_eq!(json["explicit"], true);
+        assert_eq!(json["size_bytes"], 2048);
+        assert_eq!(json["source"], "homebrew/core");
+
+        let json = inventory_entry_json(&inventory_entry("jq", None), &[]);
+        assert!(json["size_bytes"].is_null());
+    }
+
     #[test]
     fn test_format_list_entry_pinned() {
         let result = format_list_entry("node", "22.0.0", true);
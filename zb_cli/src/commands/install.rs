@@ -2,64 +2,258 @@
 
 use console::style;
 use indicatif::MultiProgress;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use zb_core::formula::KegOnlyReason;
-use zb_io::install::Installer;
+use zb_core::{Formula, LinkConflictType};
+use zb_io::install::{InstallPlan, Installer};
+use zb_io::{
+    DownloadEstimate, InstallTimings, PackageEstimate, Phase, PlanFile, PlannedLinkConflict,
+    SourceBuildResult,
+};
 
+use crate::commands::stats::format_ms;
+use crate::commands::which::warn_shadowed;
 use crate::display::{
-    ProgressStyles, create_progress_callback, finish_progress_bars, suggest_homebrew,
+    ProgressStyles, create_progress_callback, finish_progress_bars, format_bytes, suggest_homebrew,
 };
+use crate::render::{Render, emit};
+
+/// Plans with more packages than this ask for confirmation
+const DEFAULT_CONFIRM_PACKAGES: usize = 50;
+
+/// Plans downloading more than this many megabytes ask for confirmation
+const DEFAULT_CONFIRM_MB: u64 = 1024;
+
+/// Overrides the package threshold; 0 disables it
+const CONFIRM_PACKAGES_ENV: &str = "ZB_CONFIRM_PACKAGES";
+
+/// Overrides the download threshold in megabytes; 0 disables it
+const CONFIRM_MB_ENV: &str = "ZB_CONFIRM_MB";
 
 /// Run the install command.
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     installer: &mut Installer,
     prefix: &Path,
-    formula: String,
+    formulas: Vec<String>,
     no_link: bool,
     build_from_source: bool,
     head: bool,
+    build_args: Vec<String>,
+    profile: bool,
+    yes: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
-    // Validate formula name
-    if let Err(msg) = validate_formula_name(&formula) {
-        return Err(zb_core::Error::MissingFormula { name: msg });
+    // Validate formula names
+    for formula in &formulas {
+        if let Err(msg) = validate_formula_name(formula) {
+            return Err(zb_core::Error::MissingFormula { name: msg });
+        }
     }
 
     let start = Instant::now();
 
-    // HEAD implies building from source
-    let build_from_source = should_build_from_source(build_from_source, head);
+    // HEAD and build arguments imply building from source
+    let build_from_source =
+        should_build_from_source(build_from_source || !build_args.is_empty(), head);
 
     if build_from_source {
-        run_source_install(installer, prefix, &formula, no_link, head, start).await
+        if profile {
+            eprintln!(
+                "{} --profile only applies to bottle installs",
+                style("Note:").yellow().bold()
+            );
+        }
+        // Builds run one at a time, each with its own summary
+        for formula in &formulas {
+            run_source_install(
+                installer,
+                prefix,
+                formula,
+                no_link,
+                head,
+                &build_args,
+                Instant::now(),
+                json,
+            )
+            .await?;
+        }
+        Ok(())
     } else {
-        run_bottle_install(installer, prefix, &formula, no_link, start).await
+        // Tap formulas with no bottle for this platform are planned as
+        // source builds alongside the bottles
+        run_bottle_install(
+            installer, prefix, &formulas, no_link, profile, yes, start, json,
+        )
+        .await
     }
 }
 
+/// A formula that was asked for, with what to tell the user about it
+struct Requested {
+    /// Canonical name
+    name: String,
+    version: String,
+    keg_only: bool,
+    keg_only_reason: Option<KegOnlyReason>,
+    caveats: Option<String>,
+}
+
+impl Requested {
+    fn from_formula(formula: &Formula) -> Self {
+        Requested {
+            name: formula.name.clone(),
+            version: formula.effective_version(),
+            keg_only: formula.keg_only,
+            keg_only_reason: formula.keg_only_reason.clone(),
+            caveats: formula.caveats.clone(),
+        }
+    }
+
+    fn to_json(&self, prefix: &Path) -> Value {
+        json!({
+            "name": self.name,
+            "version": self.version,
+            "keg_only": self.keg_only,
+            "caveats": self.caveats.as_ref().map(|c| substitute_prefix(c, prefix)),
+        })
+    }
+}
+
+/// What an install did
+struct InstallOutput<'a> {
+    prefix: &'a Path,
+    /// Formulas that were asked for, in order
+    requested: Vec<Requested>,
+    /// Every package installed or already present, with its version
+    packages: Vec<(String, String)>,
+    elapsed: Duration,
+    source: Option<SourceBuildResult>,
+    /// Timings to show, with `--profile`
+    timings: Option<InstallTimings>,
+}
+
+impl Render for InstallOutput<'_> {
+    fn render_human(&self) {
+        let elapsed = self.elapsed.as_secs_f64();
+        println!();
+        match &self.source {
+            Some(result) => {
+                println!(
+                    "{} {}",
+                    style("==>").cyan().bold(),
+                    format_install_complete_message(
+                        &result.name,
+                        &result.version,
+                        result.files_installed,
+                        elapsed
+                    )
+                );
+                if should_show_files_linked(result.files_linked) {
+                    println!(
+                        "    {} {}",
+                        style("✓").green(),
+                        format_files_linked_message(result.files_linked)
+                    );
+                }
+            }
+            None => println!(
+                "{} {}",
+                style("==>").cyan().bold(),
+                format_bottle_install_summary(self.packages.len(), elapsed)
+            ),
+        }
+
+        if let Some(timings) = &self.timings {
+            println!();
+            for line in format_profile_lines(timings, self.elapsed) {
+                println!("{}", line);
+            }
+        }
+
+        // Name the formula each caveat is for once there is more than one
+        let several = self.requested.len() > 1;
+        for requested in &self.requested {
+            print_keg_only_info(
+                requested.keg_only,
+                requested.keg_only_reason.as_ref(),
+                self.prefix,
+                &requested.name,
+            );
+            print_caveats(
+                requested.caveats.as_ref(),
+                self.prefix,
+                several.then_some(requested.name.as_str()),
+            );
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        // The first formula's fields stay at the top level for scripts that
+        // install one formula at a time
+        let first = self.requested.first();
+        json!({
+            "name": first.map(|r| &r.name),
+            "version": first.map(|r| &r.version),
+            "from_source": self.source.is_some(),
+            "head": self.source.as_ref().is_some_and(|s| s.head),
+            "packages": self.packages.iter().map(|(name, version)| {
+                json!({ "name": name, "version": version })
+            }).collect::<Vec<_>>(),
+            "files_linked": self.source.as_ref().map(|s| s.files_linked),
+            "elapsed_ms": self.elapsed.as_millis() as u64,
+            "keg_only": first.is_some_and(|r| r.keg_only),
+            "caveats": first
+                .and_then(|r| r.caveats.as_ref())
+                .map(|c| substitute_prefix(c, self.prefix)),
+            "requested": self
+                .requested
+                .iter()
+                .map(|r| r.to_json(self.prefix))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn run_source_install(
     installer: &mut Installer,
     prefix: &Path,
     formula: &str,
     no_link: bool,
     head: bool,
+    build_args: &[String],
     start: Instant,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
-    let build_type = get_build_type_label(head);
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_building_message(formula, build_type)
-    );
+    if !json {
+        let build_type = get_build_type_label(head);
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_building_message(formula, build_type)
+        );
+    }
+    if let Ok(f) = installer.get_formula(formula).await {
+        print_deprecation_warnings(std::slice::from_ref(&f));
+    }
 
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_downloading_message()
-    );
+    if !json {
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_downloading_message()
+        );
+    }
 
-    let result = match installer.install_from_source(formula, !no_link, head).await {
+    let result = match installer
+        .install_from_source(formula, !no_link, head, build_args)
+        .await
+    {
         Ok(r) => r,
         Err(e) => {
             eprintln!("{}", format_install_error_context(formula, true));
@@ -68,52 +262,210 @@ async fn run_source_install(
         }
     };
 
-    let elapsed = start.elapsed();
-    println!();
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_install_complete_message(
-            &result.name,
-            &result.version,
-            result.files_installed,
-            elapsed.as_secs_f64()
-        )
-    );
-    if should_show_files_linked(result.files_linked) {
-        println!(
-            "    {} {}",
-            style("✓").green(),
-            format_files_linked_message(result.files_linked)
-        );
-    }
+    let formula_info = installer.get_formula(formula).await.ok();
+    let requested = Requested {
+        name: result.name.clone(),
+        version: result.version.clone(),
+        keg_only: formula_info.as_ref().is_some_and(|f| f.keg_only),
+        keg_only_reason: formula_info
+            .as_ref()
+            .and_then(|f| f.keg_only_reason.clone()),
+        caveats: formula_info.and_then(|f| f.caveats),
+    };
+    let output = InstallOutput {
+        prefix,
+        requested: vec![requested],
+        packages: vec![(result.name.clone(), result.version.clone())],
+        elapsed: start.elapsed(),
+        timings: None,
+        source: Some(result),
+    };
+    emit(&output, json);
 
-    // Display keg-only and caveats info if present
-    if let Ok(formula_info) = installer.get_formula(formula).await {
-        print_keg_only_info(
-            formula_info.keg_only,
-            formula_info.keg_only_reason.as_ref(),
+    if !no_link {
+        warn_shadowed(
+            installer,
             prefix,
-            formula,
+            std::slice::from_ref(&output.requested[0].name),
         );
-        print_caveats(formula_info.caveats.as_ref(), prefix);
     }
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_bottle_install(
     installer: &mut Installer,
     prefix: &Path,
+    formulas: &[String],
+    no_link: bool,
+    profile: bool,
+    yes: bool,
+    start: Instant,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let label = formulas.join(" ");
+    if !json {
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_installing_message(&label)
+        );
+    }
+
+    // One plan covers every formula, so shared dependencies install once
+    let names: Vec<&str> = formulas.iter().map(String::as_str).collect();
+    let plan = match installer.plan_many(&names).await {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", format_plan_error_context(&label));
+            suggest_homebrew(&label, &e);
+            return Err(e);
+        }
+    };
+
+    install_plan(
+        installer, prefix, plan, &label, no_link, profile, yes, start, json,
+    )
+    .await
+}
+
+/// Show `plan`, ask for confirmation if it is large, and install it.
+/// `formula` is what was asked for, for error messages.
+#[allow(clippy::too_many_arguments)]
+async fn install_plan(
+    installer: &mut Installer,
+    prefix: &Path,
+    plan: InstallPlan,
     formula: &str,
     no_link: bool,
+    profile: bool,
+    yes: bool,
     start: Instant,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_installing_message(formula)
-    );
+    // Extract info from the requested formulas before executing the plan.
+    // They are looked up by canonical name, as `formula` may be an alias or
+    // a versioned request such as `node@22.11`.
+    let all_formulas = || plan.formulas.iter().chain(&plan.source_builds);
+    let requested: Vec<Requested> = plan
+        .roots
+        .iter()
+        .filter_map(|root| all_formulas().find(|f| &f.name == root))
+        .map(Requested::from_formula)
+        .collect();
+    let packages: Vec<(String, String)> = all_formulas()
+        .map(|f| (f.name.clone(), f.effective_version()))
+        .collect();
+
+    // Sizes are only shown in the listing, which JSON output skips
+    let estimate = if json {
+        None
+    } else {
+        Some(installer.estimate_download(&plan).await)
+    };
+    if let Some(estimate) = &estimate {
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_dependency_resolution(plan.formulas.len())
+        );
+        print_package_estimates(estimate);
+        for formula in &plan.source_builds {
+            println!(
+                "{} {}",
+                style("==>").cyan().bold(),
+                format_source_fallback_message(&formula.name)
+            );
+        }
+    }
+    print_deprecation_warnings(&plan.formulas);
+    print_deprecation_warnings(&plan.source_builds);
+
+    // Report every executable that can't be linked before downloading any
+    if !no_link {
+        let conflicts = installer.link_conflicts(&plan);
+        if let Some(first) = conflicts.first() {
+            eprintln!(
+                "{} {} can't be linked:",
+                style("==>").red().bold(),
+                style(format_executables(conflicts.len())).bold()
+            );
+            for conflict in &conflicts {
+                eprintln!("    {}", format_link_conflict(conflict, prefix));
+            }
+            return Err(first.to_error());
+        }
+    }
+
+    // Scripts can't answer the prompt, so JSON output never asks
+    if let Some(estimate) = &estimate
+        && !yes
+    {
+        confirm_large_install(estimate)?;
+    }
+
+    let result = if json {
+        installer.execute_with_progress(plan, !no_link, None).await
+    } else {
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_downloading_and_installing_message()
+        );
+
+        let multi = MultiProgress::new();
+        let styles = ProgressStyles::default();
+        let (progress_callback, bars) = create_progress_callback(multi, styles, "installed");
+        let result = installer
+            .execute_with_progress(plan, !no_link, Some(progress_callback))
+            .await;
+        finish_progress_bars(&bars);
+        result
+    };
+    let mut result = match result {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", format_install_error_context(formula, false));
+            suggest_homebrew(formula, &e);
+            return Err(e);
+        }
+    };
+
+    // A plan that was only a source build is summarized like one
+    let source = match result.built.len() {
+        1 if packages.len() == 1 => result.built.pop(),
+        _ => None,
+    };
+    let output = InstallOutput {
+        prefix,
+        requested,
+        packages,
+        elapsed: start.elapsed(),
+        source,
+        timings: profile.then_some(result.timings),
+    };
+    emit(&output, json);
+
+    if !no_link {
+        let planned: Vec<String> = output.packages.into_iter().map(|(name, _)| name).collect();
+        warn_shadowed(installer, prefix, &planned);
+    }
+
+    Ok(())
+}
+
+/// Resolve `formula` and write the plan to `path` for review instead of
+/// installing it.
+pub async fn emit_plan(
+    installer: &Installer,
+    formula: &str,
+    path: &Path,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    if let Err(msg) = validate_formula_name(formula) {
+        return Err(zb_core::Error::MissingFormula { name: msg });
+    }
 
     let plan = match installer.plan(formula).await {
         Ok(p) => p,
@@ -123,70 +475,419 @@ async fn run_bottle_install(
             return Err(e);
         }
     };
+    print_deprecation_warnings(&plan.formulas);
+
+    // A plan file only describes bottles
+    if let Some(unbottled) = plan.source_builds.first() {
+        return Err(zb_core::Error::UnsupportedBottle {
+            name: unbottled.name.clone(),
+            available_platforms: unbottled.bottle.stable.files.keys().cloned().collect(),
+        });
+    }
 
-    // Extract info from the root formula before executing the plan
-    let root_formula = plan.formulas.iter().find(|f| f.name == plan.root_name);
-    let root_caveats = root_formula.and_then(|f| f.caveats.clone());
-    let root_keg_only = root_formula.map(|f| f.keg_only).unwrap_or(false);
-    let root_keg_only_reason = root_formula.and_then(|f| f.keg_only_reason.clone());
+    let file = PlanFile::from_plan(&plan);
+    file.write(path)?;
+    emit(&EmitPlanOutput { path, file: &file }, json);
+    Ok(())
+}
 
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_dependency_resolution(plan.formulas.len())
-    );
-    for f in &plan.formulas {
-        // Use helper for consistent formatting (styled output uses same data)
-        let _ = format_dependency_entry(&f.name, &f.versions.stable);
+/// A plan written with `--emit-plan`
+struct EmitPlanOutput<'a> {
+    path: &'a Path,
+    file: &'a PlanFile,
+}
+
+impl Render for EmitPlanOutput<'_> {
+    fn render_human(&self) {
+        println!(
+            "{} Wrote plan for {} to {}",
+            style("==>").cyan().bold(),
+            style(&self.file.root).bold(),
+            self.path.display()
+        );
+        for package in &self.file.packages {
+            println!(
+                "    {} {} {}",
+                style(&package.name).green(),
+                style(&package.version).dim(),
+                style(&package.bottle.tag).dim()
+            );
+        }
         println!(
-            "    {} {}",
-            style(&f.name).green(),
-            style(&f.versions.stable).dim()
+            "    {} run it with: zb execute-plan {}",
+            style("→").dim(),
+            self.path.display()
         );
     }
 
-    println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_downloading_and_installing_message()
-    );
+    fn render_json(&self) -> Value {
+        json!({
+            "plan": self.path.display().to_string(),
+            "root": self.file.root,
+            "packages": plan_packages_json(self.file),
+        })
+    }
+}
 
-    let multi = MultiProgress::new();
-    let styles = ProgressStyles::default();
-    let (progress_callback, bars) = create_progress_callback(multi, styles, "installed");
+/// The packages of a plan file, with the bottles they pour.
+/// Extracted for testability.
+pub(crate) fn plan_packages_json(file: &PlanFile) -> Vec<Value> {
+    file.packages
+        .iter()
+        .map(|package| {
+            json!({
+                "name": package.name,
+                "version": package.version,
+                "bottle": {
+                    "tag": package.bottle.tag,
+                    "url": package.bottle.url,
+                    "sha256": package.bottle.sha256,
+                },
+            })
+        })
+        .collect()
+}
 
-    let result = match installer
-        .execute_with_progress(plan, !no_link, Some(progress_callback))
-        .await
-    {
-        Ok(r) => r,
+/// Install exactly the bottles of a plan written with `--emit-plan`.
+pub async fn execute_plan(
+    installer: &mut Installer,
+    prefix: &Path,
+    path: &Path,
+    no_link: bool,
+    yes: bool,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let start = Instant::now();
+    let file = PlanFile::read(path)?;
+    let root = file.root.clone();
+
+    if !json {
+        println!(
+            "{} Executing plan for {} from {}",
+            style("==>").cyan().bold(),
+            style(&root).bold(),
+            path.display()
+        );
+    }
+
+    let plan = installer.plan_from_file(file)?;
+    install_plan(
+        installer, prefix, plan, &root, no_link, false, yes, start, json,
+    )
+    .await
+}
+
+/// Resolve `formulas` and show what installing them would download and take
+/// up on disk, without installing anything.
+pub async fn dry_run(
+    installer: &Installer,
+    formulas: &[String],
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    for formula in formulas {
+        if let Err(msg) = validate_formula_name(formula) {
+            return Err(zb_core::Error::MissingFormula { name: msg });
+        }
+    }
+
+    let label = formulas.join(" ");
+    let names: Vec<&str> = formulas.iter().map(String::as_str).collect();
+    let plan = match installer.plan_many(&names).await {
+        Ok(p) => p,
         Err(e) => {
-            eprintln!("{}", format_install_error_context(formula, false));
-            suggest_homebrew(formula, &e);
+            eprintln!("{}", format_plan_error_context(&label));
+            suggest_homebrew(&label, &e);
             return Err(e);
         }
     };
+    print_deprecation_warnings(&plan.formulas);
+
+    let estimate = installer.estimate_download(&plan).await;
+    emit(
+        &DryRunOutput {
+            estimate: &estimate,
+        },
+        json,
+    );
+    Ok(())
+}
 
-    finish_progress_bars(&bars);
+/// What `zb install --dry-run` would install
+struct DryRunOutput<'a> {
+    estimate: &'a DownloadEstimate,
+}
 
-    let elapsed = start.elapsed();
-    println!();
+impl Render for DryRunOutput<'_> {
+    fn render_human(&self) {
+        println!(
+            "{} Would install {} packages",
+            style("==>").cyan().bold(),
+            self.estimate.packages.len()
+        );
+        print_package_estimates(self.estimate);
+    }
+
+    fn render_json(&self) -> Value {
+        plan_estimate_json(self.estimate)
+    }
+}
+
+/// List a plan's packages with their sizes, then the totals
+fn print_package_estimates(estimate: &DownloadEstimate) {
+    for package in &estimate.packages {
+        // Use helper for consistent formatting (styled output uses same data)
+        let _ = format_dependency_entry(&package.name, &package.version);
+        println!(
+            "    {} {} {}",
+            style(&package.name).green(),
+            style(&package.version).dim(),
+            style(format!("({})", format_package_sizes(package))).dim()
+        );
+    }
     println!(
-        "{} {}",
-        style("==>").cyan().bold(),
-        format_bottle_install_summary(result.installed, elapsed.as_secs_f64())
+        "    {} {}",
+        style("Total:").bold(),
+        format_plan_totals(estimate)
     );
+}
 
-    // Display keg-only and caveats info if present
-    print_keg_only_info(
-        root_keg_only,
-        root_keg_only_reason.as_ref(),
-        prefix,
-        formula,
+/// Format a package's download and installed size for the plan listing.
+/// Extracted for testability.
+pub(crate) fn format_package_sizes(package: &PackageEstimate) -> String {
+    let download = match package.download_bytes {
+        _ if package.cached => "cached".to_string(),
+        Some(bytes) => format!("{} download", format_bytes(bytes)),
+        None => "download size unknown".to_string(),
+    };
+    match package.installed_bytes {
+        Some(bytes) => format!("{}, ~{} installed", download, format_bytes(bytes)),
+        None => download,
+    }
+}
+
+/// Format a plan's total download and installed size.
+/// Extracted for testability.
+pub(crate) fn format_plan_totals(estimate: &DownloadEstimate) -> String {
+    let mut message = format!(
+        "{} to download, ~{} installed",
+        format_bytes(estimate.bytes),
+        format_bytes(estimate.installed_bytes)
     );
-    print_caveats(root_caveats.as_ref(), prefix);
+    if estimate.unknown > 0 {
+        message.push_str(&format!(
+            " (plus {} bottles of unknown size)",
+            estimate.unknown
+        ));
+    }
+    message
+}
 
-    Ok(())
+/// Build the JSON document for `zb install --dry-run`.
+/// Extracted for testability.
+pub(crate) fn plan_estimate_json(estimate: &DownloadEstimate) -> Value {
+    json!({
+        "packages": estimate.packages.iter().map(|package| {
+            json!({
+                "name": package.name,
+                "version": package.version,
+                "download_bytes": package.download_bytes,
+                "installed_bytes": package.installed_bytes,
+                "cached": package.cached,
+            })
+        }).collect::<Vec<_>>(),
+        "download_bytes": estimate.bytes,
+        "installed_bytes": estimate.installed_bytes,
+        "cached": estimate.cached,
+        "unknown": estimate.unknown,
+    })
+}
+
+/// Warn about plans above the configured size thresholds and ask whether to
+/// go on, on stderr so the prompt doesn't end up in piped output. Without a
+/// terminal to ask on, the install proceeds after the warning.
+fn confirm_large_install(estimate: &DownloadEstimate) -> Result<(), zb_core::Error> {
+    let stdin = io::stdin();
+    let answer = stdin.is_terminal().then(|| stdin.lock());
+    ask_large_install(estimate, LargeInstallThresholds::from_env(), answer)
+}
+
+/// Warn about `estimate` if it exceeds `thresholds` and read whether to go
+/// on from `answer`, or go on without asking when there is nothing to ask
+/// on. Fails with [`zb_core::Error::Aborted`] when the user declines.
+/// Extracted for testability.
+pub(crate) fn ask_large_install(
+    estimate: &DownloadEstimate,
+    thresholds: LargeInstallThresholds,
+    answer: Option<impl BufRead>,
+) -> Result<(), zb_core::Error> {
+    let packages = estimate.packages.len();
+
+    if !thresholds.exceeded(packages, Some(estimate)) {
+        return Ok(());
+    }
+
+    eprintln!(
+        "{} {}",
+        style("Warning:").yellow().bold(),
+        format_large_install_warning(packages, Some(estimate))
+    );
+
+    let Some(mut answer) = answer else {
+        return Ok(());
+    };
+
+    eprint!("Continue? [y/N] ");
+    if io::stderr().flush().is_err() {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "Failed to flush stderr".to_string(),
+        });
+    }
+
+    let mut input = String::new();
+    if answer.read_line(&mut input).is_err() {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "Failed to read user input".to_string(),
+        });
+    }
+    if is_confirmation(&input) {
+        Ok(())
+    } else {
+        Err(zb_core::Error::Aborted)
+    }
+}
+
+/// Plan size above which `zb install` asks before downloading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LargeInstallThresholds {
+    /// Package count; 0 disables the check
+    pub packages: usize,
+    /// Download size in bytes; 0 disables the check
+    pub bytes: u64,
+}
+
+impl LargeInstallThresholds {
+    fn from_env() -> Self {
+        Self::parse(
+            std::env::var(CONFIRM_PACKAGES_ENV).ok().as_deref(),
+            std::env::var(CONFIRM_MB_ENV).ok().as_deref(),
+        )
+    }
+
+    /// Build thresholds from the environment values, falling back to the
+    /// defaults for unset or invalid values.
+    /// Extracted for testability.
+    pub(crate) fn parse(packages: Option<&str>, megabytes: Option<&str>) -> Self {
+        let packages = packages
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_CONFIRM_PACKAGES);
+        let megabytes: u64 = megabytes
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_CONFIRM_MB);
+        Self {
+            packages,
+            bytes: megabytes * 1024 * 1024,
+        }
+    }
+
+    /// Whether a plan is large enough to ask for confirmation.
+    /// Extracted for testability.
+    pub(crate) fn exceeded(&self, packages: usize, estimate: Option<&DownloadEstimate>) -> bool {
+        let too_many = self.packages > 0 && packages > self.packages;
+        let too_big = self.bytes > 0 && estimate.is_some_and(|e| e.bytes > self.bytes);
+        too_many || too_big
+    }
+}
+
+/// Format the totals shown before a large install.
+/// Extracted for testability.
+pub(crate) fn format_large_install_warning(
+    packages: usize,
+    estimate: Option<&DownloadEstimate>,
+) -> String {
+    let mut message = format!("this will install {} packages", packages);
+    if let Some(estimate) = estimate {
+        message.push_str(&format!(", downloading {}", format_bytes(estimate.bytes)));
+        if estimate.unknown > 0 {
+            message.push_str(&format!(
+                " plus {} bottles of unknown size",
+                estimate.unknown
+            ));
+        }
+        if estimate.cached > 0 {
+            message.push_str(&format!(" ({} already cached)", estimate.cached));
+        }
+    }
+    message
+}
+
+/// Warn about formulas upstream has deprecated or disabled
+fn print_deprecation_warnings(formulas: &[Formula]) {
+    for f in formulas {
+        if let Some(message) = f.deprecation_message() {
+            eprintln!(
+                "{} {}",
+                style("Warning:").yellow().bold(),
+                format_deprecation_warning(&f.name, &message)
+            );
+        }
+    }
+}
+
+/// Format the warning for a deprecated or disabled formula.
+/// Extracted for testability.
+pub(crate) fn format_deprecation_warning(name: &str, message: &str) -> String {
+    format!("{} {}", name, message)
+}
+
+/// Whether a prompt answer means yes.
+/// Extracted for testability.
+pub(crate) fn is_confirmation(input: &str) -> bool {
+    let input = input.trim();
+    input.eq_ignore_ascii_case("y") || input.eq_ignore_ascii_case("yes")
+}
+
+/// Extracted for testability.
+pub(crate) fn format_profile_lines(timings: &InstallTimings, wall_clock: Duration) -> Vec<String> {
+    let ms = |d: Duration| format_ms(d.as_secs_f64() * 1000.0);
+
+    let mut lines = vec![
+        format!("{} Profile", style("==>").cyan().bold()),
+        format!("  API resolution:  {}", ms(timings.resolve)),
+        format!("  Execution:       {}", ms(timings.execute)),
+        format!(
+            "  Phase totals:    download {}, extract {}, link {}",
+            ms(timings.phase_total(Phase::Download)),
+            ms(timings.phase_total(Phase::Extract)),
+            ms(timings.phase_total(Phase::Link))
+        ),
+        format!("  Parallelism:     {:.1}x", timings.parallelism()),
+        format!("  Wall clock:      {}", ms(wall_clock)),
+    ];
+
+    if !timings.packages.is_empty() {
+        lines.push(String::new());
+        let width = timings
+            .packages
+            .iter()
+            .map(|p| p.name.len())
+            .max()
+            .unwrap_or(0);
+        for p in &timings.packages {
+            let mut line = format!(
+                "  {:<width$}  download {:>7}  extract {:>7}  link {:>7}",
+                p.name,
+                ms(p.download),
+                ms(p.extract),
+                ms(p.link),
+            );
+            if p.blob_cached {
+                line.push_str("  (cached)");
+            }
+            lines.push(line);
+        }
+    }
+
+    lines
 }
 
 /// Print keg-only information for a formula.
@@ -219,15 +920,15 @@ fn print_keg_only_info(
     );
 }
 
-/// Print caveats for a formula.
-fn print_caveats(caveats: Option<&String>, prefix: &Path) {
+/// Print caveats for a formula, naming it in the header if given.
+fn print_caveats(caveats: Option<&String>, prefix: &Path, formula: Option<&str>) {
     if !should_show_caveats(caveats) {
         return;
     }
     let caveats = caveats.unwrap();
 
     println!();
-    println!("{}", style("==> Caveats").yellow().bold());
+    println!("{}", style(format_caveats_header(formula)).yellow().bold());
     for line in process_caveats_lines(caveats, prefix) {
         println!("{}", line);
     }
@@ -255,12 +956,52 @@ pub(crate) fn build_keg_only_link_suggestion(formula: &str) -> String {
     format!("zb link {} --force", formula)
 }
 
+/// Format "1 executable" / "N executables".
+/// Extracted for testability.
+pub(crate) fn format_executables(count: usize) -> String {
+    if count == 1 {
+        "1 executable".to_string()
+    } else {
+        format!("{} executables", count)
+    }
+}
+
+/// Describe an executable that can't be linked and what is in its way.
+/// Extracted for testability.
+pub(crate) fn format_link_conflict(conflict: &PlannedLinkConflict, prefix: &Path) -> String {
+    let path = conflict.path.strip_prefix(prefix).unwrap_or(&conflict.path);
+    let existing = match (&conflict.owner, &conflict.existing_type) {
+        (Some(owner), _) => format!("{}'s link", owner),
+        (None, LinkConflictType::SymlinkToOther { target }) => {
+            format!("a link to {}", target.display())
+        }
+        (None, LinkConflictType::RegularFile) => "a file".to_string(),
+        (None, LinkConflictType::Directory) => "a directory".to_string(),
+        (None, LinkConflictType::Unknown) => "what is there".to_string(),
+    };
+    format!(
+        "{}: {} would replace {}",
+        path.display(),
+        conflict.formula,
+        existing
+    )
+}
+
 /// Determine if we should build from source based on flags.
 /// Extracted for testability.
 pub(crate) fn should_build_from_source(build_from_source: bool, head: bool) -> bool {
     build_from_source || head
 }
 
+/// Format the note for a formula built because it has no bottle.
+/// Extracted for testability.
+pub(crate) fn format_source_fallback_message(formula: &str) -> String {
+    format!(
+        "No bottle of {} for this platform, building it instead",
+        formula
+    )
+}
+
 /// Get the build type label for display.
 /// Extracted for testability.
 pub(crate) fn get_build_type_label(head: bool) -> &'static str {
@@ -373,6 +1114,15 @@ pub(crate) fn process_caveats_lines(caveats: &str, prefix: &Path) -> Vec<String>
     substituted.lines().map(|s| s.to_string()).collect()
 }
 
+/// Format the caveats header, naming the formula when several were installed.
+/// Extracted for testability.
+pub(crate) fn format_caveats_header(formula: Option<&str>) -> String {
+    match formula {
+        Some(formula) => format!("==> Caveats for {}", formula),
+        None => "==> Caveats".to_string(),
+    }
+}
+
 /// Check if caveats should be displayed.
 /// Extracted for testability.
 pub(crate) fn should_show_caveats(caveats: Option<&String>) -> bool {
@@ -400,6 +1150,216 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_plan_packages_json() {
+        let file: PlanFile = serde_json::from_value(json!({
+            "format_version": 1,
+            "root": "jq",
+            "created_at": "2024-06-01T12:00:00Z",
+            "packages": [{
+                "name": "jq",
+                "version": "1.7.1",
+                "bottle": { "tag": "x86_64_linux", "url": "https://x/jq", "sha256": "aaa" },
+                "formula": { "name": "jq" },
+            }],
+        }))
+        .unwrap();
+
+        assert_eq!(
+            plan_packages_json(&file),
+            vec![json!({
+                "name": "jq",
+                "version": "1.7.1",
+                "bottle": { "tag": "x86_64_linux", "url": "https://x/jq", "sha256": "aaa" },
+            })]
+        );
+    }
+
+    // ========================================================================
+    // Large Install Confirmation Tests
+    // ========================================================================
+
+    #[test]
+    fn test_large_install_thresholds_defaults() {
+        let thresholds = LargeInstallThresholds::parse(None, Some("not a number"));
+        assert_eq!(thresholds.packages, 50);
+        assert_eq!(thresholds.bytes, 1024 * 1024 * 1024);
+
+        let thresholds = LargeInstallThresholds::parse(Some("10"), Some("0"));
+        assert_eq!(thresholds.packages, 10);
+        assert_eq!(thresholds.bytes, 0);
+    }
+
+    #[test]
+    fn test_large_install_thresholds_exceeded() {
+        let thresholds = LargeInstallThresholds {
+            packages: 50,
+            bytes: 1000,
+        };
+        let small = DownloadEstimate {
+            bytes: 1000,
+            ..Default::default()
+        };
+        let big = DownloadEstimate {
+            bytes: 1001,
+            ..Default::default()
+        };
+
+        assert!(!thresholds.exceeded(50, Some(&small)));
+        assert!(thresholds.exceeded(51, Some(&small)));
+        assert!(thresholds.exceeded(1, Some(&big)));
+        assert!(!thresholds.exceeded(1, None));
+    }
+
+    #[test]
+    fn test_large_install_thresholds_disabled() {
+        let thresholds = LargeInstallThresholds {
+            packages: 0,
+            bytes: 0,
+        };
+        let huge = DownloadEstimate {
+            bytes: u64::MAX,
+            ..Default::default()
+        };
+        assert!(!thresholds.exceeded(10_000, Some(&huge)));
+    }
+
+    #[test]
+    fn test_ask_large_install_aborts_when_declined() {
+        let thresholds = LargeInstallThresholds {
+            packages: 0,
+            bytes: 1000,
+        };
+        let big = DownloadEstimate {
+            bytes: 1001,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            ask_large_install(&big, thresholds, Some(&b"n\n"[..])),
+            Err(zb_core::Error::Aborted)
+        );
+        assert_eq!(
+            ask_large_install(&big, thresholds, Some(&b""[..])),
+            Err(zb_core::Error::Aborted)
+        );
+        assert_eq!(
+            ask_large_install(&big, thresholds, Some(&b"y\n"[..])),
+            Ok(())
+        );
+        // Nothing to ask on, or nothing to ask about
+        assert_eq!(ask_large_install(&big, thresholds, None::<&[u8]>), Ok(()));
+        let small = DownloadEstimate::default();
+        assert_eq!(
+            ask_large_install(&small, thresholds, Some(&b"n\n"[..])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_format_large_install_warning() {
+        assert_eq!(
+            format_large_install_warning(120, None),
+            "this will install 120 packages"
+        );
+
+        let estimate = DownloadEstimate {
+            bytes: 2 * 1024 * 1024 * 1024,
+            cached: 3,
+            unknown: 2,
+            ..Default::default()
+        };
+        let message = format_large_install_warning(120, Some(&estimate));
+        assert!(message.starts_with("this will install 120 packages, downloading "));
+        assert!(message.contains("plus 2 bottles of unknown size"));
+        assert!(message.ends_with("(3 already cached)"));
+    }
+
+    fn package(download_bytes: Option<u64>, installed_bytes: Option<u64>) -> PackageEstimate {
+        PackageEstimate {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            download_bytes,
+            installed_bytes,
+            cached: download_bytes == Some(0),
+        }
+    }
+
+    #[test]
+    fn test_format_package_sizes() {
+        assert_eq!(
+            format_package_sizes(&package(Some(2048), Some(6144))),
+            "2.0 KB download, ~6.0 KB installed"
+        );
+        assert_eq!(
+            format_package_sizes(&package(Some(0), Some(6144))),
+            "cached, ~6.0 KB installed"
+        );
+        assert_eq!(
+            format_package_sizes(&package(None, None)),
+            "download size unknown"
+        );
+    }
+
+    #[test]
+    fn test_format_plan_totals() {
+        let estimate = DownloadEstimate {
+            bytes: 2048,
+            installed_bytes: 6144,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_plan_totals(&estimate),
+            "2.0 KB to download, ~6.0 KB installed"
+        );
+
+        let estimate = DownloadEstimate {
+            unknown: 2,
+            ..estimate
+        };
+        assert!(format_plan_totals(&estimate).ends_with("(plus 2 bottles of unknown size)"));
+    }
+
+    #[test]
+    fn test_plan_estimate_json() {
+        let estimate = DownloadEstimate {
+            bytes: 2048,
+            cached: 1,
+            unknown: 1,
+            installed_bytes: 6144,
+            packages: vec![package(Some(0), Some(6144)), package(None, None)],
+        };
+        let json = plan_estimate_json(&estimate);
+
+        assert_eq!(json["download_bytes"], 2048);
+        assert_eq!(json["installed_bytes"], 6144);
+        assert_eq!(json["cached"], 1);
+        assert_eq!(json["unknown"], 1);
+        assert_eq!(json["packages"][0]["name"], "jq");
+        assert_eq!(json["packages"][0]["cached"], true);
+        assert_eq!(json["packages"][0]["installed_bytes"], 6144);
+        assert!(json["packages"][1]["download_bytes"].is_null());
+    }
+
+    #[test]
+    fn test_format_deprecation_warning() {
+        assert_eq!(
+            format_deprecation_warning(
+                "python@3.8",
+                "has been disabled because it is not supported upstream"
+            ),
+            "python@3.8 has been disabled because it is not supported upstream"
+        );
+    }
+
+    #[test]
+    fn test_is_confirmation() {
+        assert!(is_confirmation("y\n"));
+        assert!(is_confirmation(" YES "));
+        assert!(!is_confirmation(""));
+        assert!(!is_confirmation("n"));
+    }
+
     // ========================================================================
     // Prefix Substitution Tests
     // ========================================================================
@@ -560,6 +1520,43 @@ mod tests {
         assert_eq!(get_build_type_label(true), "HEAD");
     }
 
+    #[test]
+    fn test_format_link_conflict() {
+        let prefix = Path::new("/opt/zerobrew/prefix");
+        let conflict = |owner: Option<&str>, existing_type| PlannedLinkConflict {
+            formula: "jq".to_string(),
+            path: prefix.join("bin/jq"),
+            existing_type,
+            owner: owner.map(str::to_string),
+        };
+        let target = LinkConflictType::SymlinkToOther {
+            target: "/usr/local/jq".into(),
+        };
+
+        assert_eq!(
+            format_link_conflict(&conflict(Some("jq-legacy"), target.clone()), prefix),
+            "bin/jq: jq would replace jq-legacy's link"
+        );
+        assert_eq!(
+            format_link_conflict(&conflict(None, target), prefix),
+            "bin/jq: jq would replace a link to /usr/local/jq"
+        );
+        assert_eq!(
+            format_link_conflict(&conflict(None, LinkConflictType::RegularFile), prefix),
+            "bin/jq: jq would replace a file"
+        );
+        assert_eq!(format_executables(1), "1 executable");
+        assert_eq!(format_executables(3), "3 executables");
+    }
+
+    #[test]
+    fn test_format_source_fallback_message() {
+        assert_eq!(
+            format_source_fallback_message("user/tools/tool"),
+            "No bottle of user/tools/tool for this platform, building it instead"
+        );
+    }
+
     #[test]
     fn test_get_build_type_label_source() {
         assert_eq!(get_build_type_label(false), "source");
@@ -848,6 +1845,15 @@ mod tests {
         assert!(!should_show_caveats(None));
     }
 
+    #[test]
+    fn test_format_caveats_header() {
+        assert_eq!(format_caveats_header(None), "==> Caveats");
+        assert_eq!(
+            format_caveats_header(Some("postgresql@16")),
+            "==> Caveats for postgresql@16"
+        );
+    }
+
     // ========================================================================
     // Error Context Tests
     // ========================================================================
@@ -883,4 +1889,65 @@ mod tests {
         assert!(result.contains("llvm@17"));
         assert!(result.contains("dependencies"));
     }
+
+    // ========================================================================
+    // Profile Output Tests
+    // ========================================================================
+
+    fn sample_timings() -> InstallTimings {
+        InstallTimings {
+            resolve: Duration::from_millis(120),
+            execute: Duration::from_millis(600),
+            packages: vec![
+                zb_io::PackageTiming {
+                    name: "jq".to_string(),
+                    download: Duration::from_millis(300),
+                    extract: Duration::from_millis(50),
+                    link: Duration::from_millis(2),
+                    ..Default::default()
+                },
+                zb_io::PackageTiming {
+                    name: "oniguruma".to_string(),
+                    blob_cached: true,
+                    ..Default::default()
+                },
+            ],
+            spans: vec![zb_io::Span {
+                phase: Phase::Download,
+                package: "jq".to_string(),
+                start: Duration::ZERO,
+                end: Duration::from_millis(300),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_format_profile_lines_summary() {
+        let lines = format_profile_lines(&sample_timings(), Duration::from_millis(600));
+        assert!(lines.iter().any(|l| l.contains("API resolution:  120ms")));
+        assert!(lines.iter().any(|l| l.contains("download 300ms")));
+        assert!(lines.iter().any(|l| l.contains("Parallelism:     0.5x")));
+        assert!(lines.iter().any(|l| l.contains("Wall clock:      600ms")));
+    }
+
+    #[test]
+    fn test_format_profile_lines_per_package() {
+        let lines = format_profile_lines(&sample_timings(), Duration::from_millis(600));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("  jq ") && !l.contains("(cached)"))
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("oniguruma") && l.contains("(cached)"))
+        );
+    }
+
+    #[test]
+    fn test_format_profile_lines_empty_plan() {
+        let lines = format_profile_lines(&InstallTimings::default(), Duration::ZERO);
+        assert!(!lines.iter().any(|l| l.is_empty()));
+    }
 }
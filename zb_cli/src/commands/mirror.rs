@@ -0,0 +1,306 @@
+//! Mirror, pack and unpack command implementations.
+
+use console::style;
+use std::path::{Path, PathBuf};
+
+use zb_io::install::Installer;
+use zb_io::{BrewfileEntry, MirrorResult, UnpackResult};
+
+use crate::MirrorAction;
+
+/// Run the mirror command.
+pub async fn run(installer: &mut Installer, action: MirrorAction) -> Result<(), zb_core::Error> {
+    match action {
+        MirrorAction::Create {
+            dir,
+            names,
+            brewfile,
+            platforms,
+        } => run_create(installer, &dir, names, brewfile, &platforms).await,
+        MirrorAction::Sync {
+            dir,
+            names,
+            brewfile,
+            platforms,
+        } => run_sync(installer, &dir, names, brewfile, &platforms).await,
+    }
+}
+
+async fn run_create(
+    installer: &mut Installer,
+    dir: &Path,
+    mut names: Vec<String>,
+    brewfile: Option<PathBuf>,
+    platforms: &[String],
+) -> Result<(), zb_core::Error> {
+    if let Some(path) = brewfile {
+        let entries = installer.parse_brewfile(&path)?;
+        names.extend(brewfile_formula_names(&entries));
+    }
+
+    if names.is_empty() {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "no formulas to mirror (pass formula names or --formulas <Brewfile>)"
+                .to_string(),
+        });
+    }
+
+    println!(
+        "{} Mirroring {} into {}...",
+        style("==>").cyan().bold(),
+        format_mirror_targets(&names, platforms),
+        dir.display()
+    );
+
+    let result = installer.create_mirror(dir, &names, platforms).await?;
+    print_mirror_warnings(&result);
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_mirror_summary("Mirrored", &result)
+    );
+    println!();
+    println!(
+        "Serve {} with any static web server (or copy it to the target machine), then run:",
+        dir.display()
+    );
+    println!("    zb --mirror <url-or-dir> install <formula>");
+
+    Ok(())
+}
+
+async fn run_sync(
+    installer: &mut Installer,
+    dir: &Path,
+    mut names: Vec<String>,
+    brewfile: Option<PathBuf>,
+    platforms: &[String],
+) -> Result<(), zb_core::Error> {
+    if let Some(path) = brewfile {
+        let entries = installer.parse_brewfile(&path)?;
+        names.extend(brewfile_formula_names(&entries));
+    }
+
+    println!(
+        "{} Syncing {}...",
+        style("==>").cyan().bold(),
+        dir.display()
+    );
+
+    let result = installer.sync_mirror(dir, &names, platforms).await?;
+    print_mirror_warnings(&result);
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_mirror_summary("Synced", &result)
+    );
+
+    Ok(())
+}
+
+/// Run the pack command.
+pub async fn run_pack(
+    installer: &Installer,
+    formulas: &[String],
+    output: &Path,
+    platforms: &[String],
+) -> Result<(), zb_core::Error> {
+    println!(
+        "{} Packing {} into {}...",
+        style("==>").cyan().bold(),
+        format_mirror_targets(formulas, platforms),
+        output.display()
+    );
+
+    let result = installer.create_pack(output, formulas, platforms).await?;
+    print_mirror_warnings(&result);
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_mirror_summary("Packed", &result)
+    );
+    println!();
+    println!("On the target machine, run:");
+    println!(
+        "    zb unpack {} && zb install --from-pack <formula>",
+        output.display()
+    );
+
+    Ok(())
+}
+
+/// Run the unpack command.
+pub fn run_unpack(installer: &Installer, file: &Path) -> Result<(), zb_core::Error> {
+    let result = installer.unpack(file)?;
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_unpack_summary(&result)
+    );
+    for name in &result.formulas {
+        println!("    {}", name);
+    }
+
+    Ok(())
+}
+
+fn print_mirror_warnings(result: &MirrorResult) {
+    for (name, reason) in &result.skipped {
+        println!(
+            "    {} {}: {}",
+            style("⚠").yellow(),
+            style(name).bold(),
+            reason
+        );
+    }
+    for (name, platform) in &result.missing_bottles {
+        println!(
+            "    {} {}: no bottle for {}",
+            style("⚠").yellow(),
+            style(name).bold(),
+            platform
+        );
+    }
+}
+
+/// Summarize an unpacked pack.
+/// Extracted for testability.
+pub(crate) fn format_unpack_summary(result: &UnpackResult) -> String {
+    let formula_word = if result.formulas.len() == 1 {
+        "formula"
+    } else {
+        "formulas"
+    };
+    let bottle_word = if result.bottles == 1 {
+        "bottle"
+    } else {
+        "bottles"
+    };
+    format!(
+        "Unpacked {} {} and {} {}; install with `zb install --from-pack <formula>`",
+        result.formulas.len(),
+        formula_word,
+        result.bottles,
+        bottle_word
+    )
+}
+
+/// Formula names listed in a Brewfile.
+/// Extracted for testability.
+pub(crate) fn brewfile_formula_names(entries: &[BrewfileEntry]) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            BrewfileEntry::Brew { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Describe what is being mirrored.
+/// Extracted for testability.
+pub(crate) fn format_mirror_targets(names: &[String], platforms: &[String]) -> String {
+    let formulas = if names.len() == 1 {
+        names[0].clone()
+    } else {
+        format!("{} formulas", names.len())
+    };
+
+    if platforms.is_empty() {
+        formulas
+    } else {
+        format!("{} ({})", formulas, platforms.join(", "))
+    }
+}
+
+/// Summarize a finished mirror.
+/// Extracted for testability.
+pub(crate) fn format_mirror_summary(verb: &str, result: &MirrorResult) -> String {
+    let formula_word = if result.formulas.len() == 1 {
+        "formula"
+    } else {
+        "formulas"
+    };
+    let bottle_word = if result.bottles.len() == 1 {
+        "bottle"
+    } else {
+        "bottles"
+    };
+    format!(
+        "{} {} {} and {} {}",
+        verb,
+        result.formulas.len(),
+        formula_word,
+        result.bottles.len(),
+        bottle_word
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_brewfile_formula_names_skips_taps_and_comments() {
+        let entries = vec![
+            BrewfileEntry::Tap {
+                name: "user/repo".to_string(),
+            },
+            BrewfileEntry::Brew {
+                name: "jq".to_string(),
+                args: vec![],
+            },
+            BrewfileEntry::Comment("# tools".to_string()),
+            BrewfileEntry::Brew {
+                name: "wget".to_string(),
+                args: vec!["--HEAD".to_string()],
+            },
+        ];
+
+        assert_eq!(brewfile_formula_names(&entries), vec!["jq", "wget"]);
+    }
+
+    #[test]
+    fn test_format_mirror_targets() {
+        let one = vec!["jq".to_string()];
+        let many = vec!["jq".to_string(), "wget".to_string()];
+        let platforms = vec!["arm64_sonoma".to_string(), "x86_64_linux".to_string()];
+
+        assert_eq!(format_mirror_targets(&one, &[]), "jq");
+        assert_eq!(
+            format_mirror_targets(&many, &platforms),
+            "2 formulas (arm64_sonoma, x86_64_linux)"
+        );
+    }
+
+    #[test]
+    fn test_format_mirror_summary() {
+        let result = MirrorResult {
+            formulas: vec!["jq".to_string(), "oniguruma".to_string()],
+            bottles: vec![PathBuf::from("bottles/jq")],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_mirror_summary("Mirrored", &result),
+            "Mirrored 2 formulas and 1 bottle"
+        );
+    }
+
+    #[test]
+    fn test_format_unpack_summary() {
+        let result = UnpackResult {
+            formulas: vec!["jq".to_string()],
+            bottles: 2,
+        };
+
+        assert_eq!(
+            format_unpack_summary(&result),
+            "Unpacked 1 formula and 2 bottles; install with `zb install --from-pack <formula>`"
+        );
+    }
+}
@@ -2,12 +2,25 @@
 //!
 //! Each submodule handles a specific command or group of related commands.
 
+pub mod badge;
+pub mod benchmark;
 pub mod bundle;
+pub mod config;
 pub mod deps;
 pub mod doctor;
+pub mod exec;
 pub mod info;
 pub mod install;
+pub mod mirror;
+pub mod post_install;
+pub mod query;
 pub mod services;
+pub mod shellenv;
+pub mod shim;
+pub mod stats;
 pub mod tap;
 pub mod update;
 pub mod upgrade;
+pub mod verify;
+pub mod versions;
+pub mod which;
@@ -0,0 +1,94 @@
+//! Post-install command implementation.
+
+use console::style;
+
+use zb_io::install::{Installer, PostInstallResult};
+
+/// Run `formula`'s post-install steps, turn them off or on after installs,
+/// or list the formulas they are turned off for.
+pub async fn run(
+    installer: &mut Installer,
+    formula: Option<&str>,
+    disable: bool,
+    enable: bool,
+) -> Result<(), zb_core::Error> {
+    let Some(formula) = formula else {
+        let disabled = installer.list_post_install_disabled()?;
+        if disabled.is_empty() {
+            println!("Post-install steps run for every formula.");
+        } else {
+            for name in &disabled {
+                println!("{}", name);
+            }
+        }
+        return Ok(());
+    };
+
+    if disable || enable {
+        let changed = if disable {
+            installer.disable_post_install(formula)?
+        } else {
+            installer.enable_post_install(formula)?
+        };
+        let message = match (disable, changed) {
+            (true, true) => format!("{} post-install steps will no longer run", formula),
+            (true, false) => format!("{} post-install steps are already off", formula),
+            (false, true) => format!("{} post-install steps will run after installs", formula),
+            (false, false) => format!("{} post-install steps are already on", formula),
+        };
+        println!("{} {}", style("==>").cyan().bold(), message);
+        return Ok(());
+    }
+
+    let result = installer.post_install(formula).await?;
+    for line in format_post_install_result(formula, &result) {
+        println!("{}", line);
+    }
+    if let Some(error) = &result.error {
+        return Err(zb_core::Error::StoreCorruption {
+            message: format!("post-install of {} failed: {}", formula, error),
+        });
+    }
+    Ok(())
+}
+
+/// Summary lines for a post-install run.
+/// Extracted for testability.
+pub(crate) fn format_post_install_result(formula: &str, result: &PostInstallResult) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{} Ran {} post-install step(s) for {}",
+        style("==>").cyan().bold(),
+        result.ran,
+        style(formula).bold()
+    )];
+    for source in &result.unsupported {
+        lines.push(format!("    Skipped (unsupported): {}", source));
+    }
+    lines.push(format!("    Log: {}", result.log_path.display()));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_format_post_install_result() {
+        let result = PostInstallResult {
+            ran: 2,
+            unsupported: vec!["ohai \"Done\"".to_string()],
+            error: None,
+            log_path: PathBuf::from("/zb/logs/pgtool/post_install.log"),
+        };
+
+        assert_eq!(
+            format_post_install_result("pgtool", &result),
+            [
+                "==> Ran 2 post-install step(s) for pgtool",
+                "    Skipped (unsupported): ohai \"Done\"",
+                "    Log: /zb/logs/pgtool/post_install.log",
+            ]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Query command implementation.
+//!
+//! `zb query` is the supported scripting surface for GUIs and other tools.
+//! Every subcommand prints a single JSON document wrapped in a versioned
+//! envelope. Its layout only changes together with [`SCHEMA_VERSION`], however
+//! the human-facing output of `list`, `info` and `outdated` evolves.
+
+use zb_core::{Formula, OutdatedPackage};
+use zb_io::InstalledKeg;
+use zb_io::install::Installer;
+
+use crate::QueryAction;
+
+/// Version of the `zb query` JSON schemas. Bump on any breaking change:
+/// removed or renamed fields, or changed value types. Adding fields is not
+/// breaking.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// Run the query command.
+pub async fn run(installer: &mut Installer, action: QueryAction) -> Result<(), zb_core::Error> {
+    let document = match action {
+        QueryAction::Installed => {
+            let mut kegs = Vec::new();
+            for keg in installer.list_installed()? {
+                let versions: Vec<String> = installer
+                    .list_versions(&keg.name)?
+                    .into_iter()
+                    .map(|v| v.version)
+                    .collect();
+                kegs.push(installed_keg_json(&keg, &versions));
+            }
+            envelope("installed", serde_json::Value::Array(kegs))
+        }
+        QueryAction::Formula { name } => {
+            let formula = installer.get_formula(&name).await?;
+            let keg = installer.get_installed(&formula.name);
+            envelope("formula", formula_json(&formula, keg.as_ref()))
+        }
+        QueryAction::Outdated => {
+            let outdated = installer.get_outdated_with_pinned(true).await?;
+            let pinned: Vec<String> = installer
+                .list_pinned()?
+                .into_iter()
+                .map(|k| k.name)
+                .collect();
+            envelope("outdated", outdated_json(&outdated, &pinned))
+        }
+    };
+
+    match serde_json::to_string_pretty(&document) {
+        Ok(s) => println!("{}", s),
+        Err(e) => {
+            return Err(zb_core::Error::StoreCorruption {
+                message: format!("failed to serialize query result: {}", e),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Wrap query data in the versioned envelope.
+/// Extracted for testability.
+pub(crate) fn envelope(kind: &str, data: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "schema": format!("zb.query.{}", kind),
+        "schema_version": SCHEMA_VERSION,
+        "data": data,
+    })
+}
+
+/// Build the `installed` schema entry for one keg.
+/// Extracted for testability.
+pub(crate) fn installed_keg_json(keg: &InstalledKeg, versions: &[String]) -> serde_json::Value {
+    serde_json::json!({
+        "name": keg.name,
+        "version": keg.version,
+        "versions": versions,
+        "installed_at": keg.installed_at,
+        "explicit": keg.explicit,
+        "pinned": keg.pinned,
+        "linked": keg.linked,
+        "keg_only": keg.keg_only,
+        "head_commit": keg.head_commit,
+        "provenance": keg.provenance.as_ref().map(|p| p.kind()),
+    })
+}
+
+/// Build the `formula` schema document.
+/// Extracted for testability.
+pub(crate) fn formula_json(formula: &Formula, keg: Option<&InstalledKeg>) -> serde_json::Value {
+    serde_json::json!({
+        "name": formula.name,
+        "version": formula.effective_version(),
+        "description": formula.desc,
+        "homepage": formula.homepage,
+        "license": formula.license,
+        "dependencies": formula.dependencies,
+        "build_dependencies": formula.build_dependencies,
+        "keg_only": formula.keg_only,
+        "installed_version": keg.map(|k| k.version.as_str()),
+    })
+}
+
+/// Build the `outdated` schema entries.
+/// Extracted for testability.
+pub(crate) fn outdated_json(outdated: &[OutdatedPackage], pinned: &[String]) -> serde_json::Value {
+    outdated
+        .iter()
+        .map(|pkg| {
+            serde_json::json!({
+                "name": pkg.name,
+                "installed_version": pkg.installed_version,
+                "available_version": pkg.available_version,
+                "pinned": pinned.contains(&pkg.name),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zb_io::Provenance;
+
+    fn sample_keg() -> InstalledKeg {
+        InstalledKeg {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            store_key: "abc123".to_string(),
+            installed_at: 1_700_000_000,
+            pinned: true,
+            explicit: true,
+            linked: true,
+            keg_only: false,
+            provenance: Some(Provenance::Cli),
+            head_commit: None,
+            receipt: None,
+        }
+    }
+
+    #[test]
+    fn test_envelope_is_versioned() {
+        let doc = envelope("installed", serde_json::json!([]));
+        assert_eq!(doc["schema"], "zb.query.installed");
+        assert_eq!(doc["schema_version"], SCHEMA_VERSION);
+        assert!(doc["data"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_installed_keg_json_fields() {
+        let json = installed_keg_json(&sample_keg(), &["1.6".to_string(), "1.7.1".to_string()]);
+        assert_eq!(json["name"], "jq");
+        assert_eq!(json["version"], "1.7.1");
+        assert_eq!(json["versions"], serde_json::json!(["1.6", "1.7.1"]));
+        assert_eq!(json["installed_at"], 1_700_000_000);
+        assert_eq!(json["pinned"], true);
+        assert_eq!(json["linked"], true);
+        assert!(json["head_commit"].is_null());
+        assert_eq!(json["provenance"], "cli");
+    }
+
+    #[test]
+    fn test_formula_json_with_and_without_install() {
+        let formula: Formula = serde_json::from_str(
+            r#"{
+                "name": "jq",
+                "versions": { "stable": "1.8.0" },
+                "desc": "Command-line JSON processor",
+                "dependencies": ["oniguruma"]
+            }"#,
+        )
+        .unwrap();
+
+        let json = formula_json(&formula, None);
+        assert_eq!(json["version"], "1.8.0");
+        assert_eq!(json["description"], "Command-line JSON processor");
+        assert_eq!(json["dependencies"], serde_json::json!(["oniguruma"]));
+        assert!(json["homepage"].is_null());
+        assert!(json["installed_version"].is_null());
+
+        let json = formula_json(&formula, Some(&sample_keg()));
+        assert_eq!(json["installed_version"], "1.7.1");
+    }
+
+    #[test]
+    fn test_outdated_json_marks_pinned() {
+        let outdated = vec![
+            OutdatedPackage {
+                name: "jq".to_string(),
+                installed_version: "1.7.1".to_string(),
+                available_version: "1.8.0".to_string(),
+            },
+            OutdatedPackage {
+                name: "wget".to_string(),
+                installed_version: "1.24".to_string(),
+                available_version: "1.25".to_string(),
+            },
+        ];
+
+        let json = outdated_json(&outdated, &["jq".to_string()]);
+        assert_eq!(json[0]["name"], "jq");
+        assert_eq!(json[0]["available_version"], "1.8.0");
+        assert_eq!(json[0]["pinned"], true);
+        assert_eq!(json[1]["pinned"], false);
+    }
+}
@@ -1,11 +1,13 @@
-//! Service control commands (start/stop/restart/enable/disable).
+//! Service control commands (start/stop/restart/enable/disable/config).
 
 use console::style;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use zb_io::ServiceManager;
 use zb_io::install::Installer;
+use zb_io::{ServiceManager, ServiceSettings};
+
+use crate::render::print_json;
 
 // ============================================================================
 // Pure Helper Functions (Extracted for Testability)
@@ -132,6 +134,16 @@ pub(crate) fn format_creating_service_message(formula: &str) -> String {
     format!("Creating service file for {}...", formula)
 }
 
+/// Format the "Adopting <formula>" message.
+pub(crate) fn format_adopting_message(formula: &str) -> String {
+    format!("Adopting {} from brew services...", formula)
+}
+
+/// Format the "Adopted <formula>" completion message.
+pub(crate) fn format_adopted_message(formula: &str) -> String {
+    format!("Adopted {}", formula)
+}
+
 /// Format the "Running in foreground" message.
 pub(crate) fn format_foreground_message(formula: &str) -> String {
     format!("Running {} in foreground...", formula)
@@ -142,6 +154,50 @@ pub(crate) fn format_foreground_command(program: &Path, args: &[String]) -> Stri
     format!("Command: {} {}", program.display(), args.join(" "))
 }
 
+/// Format stored service settings, one `key: value` line per setting.
+/// Extracted for testability.
+pub(crate) fn format_service_settings(settings: &ServiceSettings) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(ref user) = settings.user {
+        lines.push(format!("user: {}", user));
+    }
+    if let Some(ref group) = settings.group {
+        lines.push(format!("group: {}", group));
+    }
+    if let Some(umask) = settings.umask {
+        lines.push(format!("umask: {:03o}", umask));
+    }
+    if let Some(nice) = settings.nice {
+        lines.push(format!("nice: {}", nice));
+    }
+    if let Some(io_nice) = settings.io_nice {
+        lines.push(format!("io-nice: {}", io_nice));
+    }
+    if let Some(open_files) = settings.open_files {
+        lines.push(format!("limit-nofile: {}", open_files));
+    }
+    if let Some(processes) = settings.processes {
+        lines.push(format!("limit-nproc: {}", processes));
+    }
+    if let Some(delay) = settings.restart_delay {
+        lines.push(format!("restart-delay: {}s", delay));
+    }
+    if let Some(max_delay) = settings.restart_max_delay {
+        lines.push(format!("restart-max-delay: {}s", max_delay));
+    }
+    lines
+}
+
+/// Format the "Updated settings" message.
+pub(crate) fn format_settings_updated_message(formula: &str) -> String {
+    format!("Updated service settings for {}", formula)
+}
+
+/// Format the "no settings" message.
+pub(crate) fn format_no_settings_message(formula: &str) -> String {
+    format!("No service settings for {}.", formula)
+}
+
 /// Format the log header message.
 pub(crate) fn format_log_header(formula: &str, lines: usize) -> String {
     format!("Logs for {} (last {} lines):", formula, lines)
@@ -152,6 +208,14 @@ pub(crate) fn format_log_follow_header(formula: &str) -> String {
     format!("Following logs for {} (Ctrl+C to stop)...", formula)
 }
 
+/// Format the header shown when logs come from the system journal.
+pub(crate) fn format_journal_header(formula: &str, backend: &str) -> String {
+    format!(
+        "No log files for {} yet, showing the {} journal:",
+        formula, backend
+    )
+}
+
 // ============================================================================
 // Error Message Formatters
 // ============================================================================
@@ -359,11 +423,15 @@ pub(crate) fn parse_exit_code(code: Option<i32>) -> i32 {
 }
 
 /// Start a service.
+///
+/// Settings given on the command line are stored and the service file is
+/// rewritten with them.
 pub fn run_start(
     installer: &mut Installer,
     service_manager: &ServiceManager,
     prefix: &Path,
     formula: &str,
+    settings: ServiceSettings,
 ) -> Result<(), zb_core::Error> {
     if !installer.is_installed(formula) {
         eprintln!(
@@ -375,24 +443,20 @@ pub fn run_start(
     }
 
     let service_info = service_manager.get_service_info(formula);
-    let needs_setup = needs_service_setup(&service_info);
+    let needs_setup = needs_service_setup(&service_info) || !settings.is_empty();
+    let settings = if settings.is_empty() {
+        installer.service_settings(formula)?
+    } else {
+        installer.update_service_settings(formula, settings)?
+    };
 
     if needs_setup {
-        let keg = installer
-            .get_installed(formula)
-            .ok_or_else(|| zb_core::Error::NotInstalled {
-                name: formula.to_string(),
-            })?;
-        let keg_path = compute_keg_path(prefix, formula, &keg.version);
-
-        if let Some(config) = service_manager.detect_service_config(formula, &keg_path) {
-            println!(
-                "{} {}",
-                style("==>").cyan().bold(),
-                format_creating_service_message(formula)
-            );
-            service_manager.create_service(formula, &config)?;
-        } else {
+        println!(
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_creating_service_message(formula)
+        );
+        if !write_service_file(installer, service_manager, prefix, formula, settings)? {
             eprintln!(
                 "{} {}",
                 style("error:").red().bold(),
@@ -423,6 +487,129 @@ pub fn run_start(
     Ok(())
 }
 
+/// Show or change the stored settings of a service.
+pub fn run_config(
+    installer: &Installer,
+    service_manager: &ServiceManager,
+    prefix: &Path,
+    formula: &str,
+    settings: ServiceSettings,
+    reset: bool,
+) -> Result<(), zb_core::Error> {
+    if !installer.is_installed(formula) {
+        eprintln!(
+            "{} {}",
+            style("error:").red().bold(),
+            format_not_installed_error(formula)
+        );
+        std::process::exit(1);
+    }
+
+    if settings.is_empty() && !reset {
+        let current = installer.service_settings(formula)?;
+        let lines = format_service_settings(&current);
+        if lines.is_empty() {
+            println!("{}", format_no_settings_message(formula));
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if reset {
+        installer.reset_service_settings(formula)?;
+    }
+    let current = installer.update_service_settings(formula, settings)?;
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_settings_updated_message(formula)
+    );
+    for line in format_service_settings(&current) {
+        println!("    {}", line);
+    }
+
+    // Rewrite an existing service file so a restart picks the settings up
+    if !needs_service_setup(&service_manager.get_service_info(formula))
+        && write_service_file(installer, service_manager, prefix, formula, current)?
+    {
+        println!();
+        println!("    Restart to apply: zb services restart {}", formula);
+    }
+
+    Ok(())
+}
+
+/// Write a formula's service file with the given settings.
+/// Returns false if the formula has no service definition.
+fn write_service_file(
+    installer: &Installer,
+    service_manager: &ServiceManager,
+    prefix: &Path,
+    formula: &str,
+    settings: ServiceSettings,
+) -> Result<bool, zb_core::Error> {
+    let keg = installer
+        .get_installed(formula)
+        .ok_or_else(|| zb_core::Error::NotInstalled {
+            name: formula.to_string(),
+        })?;
+    let keg_path = compute_keg_path(prefix, formula, &keg.version);
+
+    let Some(mut config) = service_manager.detect_service_config(formula, &keg_path) else {
+        return Ok(false);
+    };
+    config.settings = settings;
+    service_manager.create_service(formula, &config)?;
+    Ok(true)
+}
+
+/// Take over the service `brew services` set up for a formula.
+pub fn run_adopt(
+    installer: &Installer,
+    service_manager: &ServiceManager,
+    formula: &str,
+) -> Result<(), zb_core::Error> {
+    if !installer.is_installed(formula) {
+        eprintln!(
+            "{} {}",
+            style("error:").red().bold(),
+            format_not_installed_error(formula)
+        );
+        std::process::exit(1);
+    }
+
+    let mut config = service_manager.foreign_service_config(formula)?;
+    config.settings = installer.service_settings(formula)?;
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_adopting_message(formula)
+    );
+    if service_manager.service_spec(formula).is_some() {
+        println!("    Note: replacing the existing zerobrew service for {formula}");
+    }
+    println!(
+        "    {}",
+        format_foreground_command(&config.program, &config.args)
+    );
+
+    service_manager.adopt(formula, &config)?;
+
+    println!(
+        "{} {} {}",
+        style("==>").cyan().bold(),
+        style("✓").green(),
+        format_adopted_message(formula)
+    );
+    println!("    Start it with: zb services start {}", formula);
+
+    Ok(())
+}
+
 /// Stop a service.
 pub fn run_stop(service_manager: &ServiceManager, formula: &str) -> Result<(), zb_core::Error> {
     println!(
@@ -638,6 +825,11 @@ pub fn run_log(
     let (stdout_log, stderr_log) = service_manager.get_log_paths(formula);
 
     let log_file = match select_log_file(&stdout_log, &stderr_log) {
+        // The journal still has what the init system logged, such as a
+        // program that failed to start before writing anything
+        None if let Some(journal) = service_manager.journal_command(formula, lines, follow) => {
+            return run_journal(journal, formula, service_manager.backend().name());
+        }
         None => {
             eprintln!(
                 "{} {}",
@@ -711,11 +903,34 @@ pub fn run_log(
     Ok(())
 }
 
+/// Show a service's output from the system journal.
+fn run_journal(mut journal: Command, formula: &str, backend: &str) -> Result<(), zb_core::Error> {
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_journal_header(formula, backend)
+    );
+    println!();
+
+    let status = journal
+        .status()
+        .map_err(|e| zb_core::Error::StoreCorruption {
+            message: format!("failed to read journal: {}", e),
+        })?;
+
+    if !status.success() {
+        std::process::exit(parse_exit_code(status.code()));
+    }
+
+    Ok(())
+}
+
 /// Clean up orphaned services.
 pub fn run_cleanup(
     installer: &mut Installer,
     service_manager: &ServiceManager,
     dry_run: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let installed: Vec<String> = installer
         .list_installed()?
@@ -725,6 +940,18 @@ pub fn run_cleanup(
 
     let orphaned = service_manager.find_orphaned_services(&installed)?;
 
+    if json {
+        if !dry_run && !orphaned.is_empty() {
+            service_manager.cleanup_services(&orphaned)?;
+        }
+        let services: Vec<_> = format_orphaned_service_list(&orphaned)
+            .into_iter()
+            .map(|(name, path)| serde_json::json!({ "name": name, "file": path }))
+            .collect();
+        print_json(&serde_json::json!({ "dry_run": dry_run, "orphaned": services }));
+        return Ok(());
+    }
+
     if orphaned.is_empty() {
         println!(
             "{} {}",
@@ -821,6 +1048,23 @@ mod tests {
         assert_eq!(pluralize(2, "child", "children"), "children");
     }
 
+    #[test]
+    fn test_format_journal_header() {
+        assert_eq!(
+            format_journal_header("redis", "systemd"),
+            "No log files for redis yet, showing the systemd journal:"
+        );
+    }
+
+    #[test]
+    fn test_format_adopt_messages() {
+        assert_eq!(
+            format_adopting_message("postgresql@14"),
+            "Adopting postgresql@14 from brew services..."
+        );
+        assert_eq!(format_adopted_message("redis"), "Adopted redis");
+    }
+
     // ============================================================================
     // format_orphan_count_message Tests
     // ============================================================================
@@ -2053,4 +2297,54 @@ mod tests {
         assert!(msg.contains("stdout.log"));
         assert!(msg.contains("stderr.log"));
     }
+
+    // ============================================================================
+    // format_service_settings Tests
+    // ============================================================================
+
+    #[test]
+    fn test_format_service_settings_empty() {
+        assert!(format_service_settings(&ServiceSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_format_service_settings_all_fields() {
+        let settings = ServiceSettings {
+            user: Some("redis".to_string()),
+            group: Some("staff".to_string()),
+            umask: Some(0o22),
+            nice: Some(-5),
+            io_nice: Some(7),
+            open_files: Some(4096),
+            processes: Some(64),
+            restart_delay: Some(5),
+            restart_max_delay: Some(120),
+        };
+        assert_eq!(
+            format_service_settings(&settings),
+            vec![
+                "user: redis",
+                "group: staff",
+                "umask: 022",
+                "nice: -5",
+                "io-nice: 7",
+                "limit-nofile: 4096",
+                "limit-nproc: 64",
+                "restart-delay: 5s",
+                "restart-max-delay: 120s",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_settings_messages() {
+        assert_eq!(
+            format_settings_updated_message("redis"),
+            "Updated service settings for redis"
+        );
+        assert_eq!(
+            format_no_settings_message("redis"),
+            "No service settings for redis."
+        );
+    }
 }
@@ -3,7 +3,16 @@
 use console::style;
 use std::path::Path;
 
-use zb_io::{ServiceManager, ServiceStatus};
+use zb_io::install::Installer;
+use zb_io::{
+    ForeignService, RestartHistory, ServiceInfo, ServiceManager, ServiceSettings, ServiceSpec,
+    ServiceStatus,
+};
+
+use crate::render::print_json;
+
+/// Number of recent exit codes shown for a service.
+const RECENT_EXIT_CODES: usize = 5;
 
 /// Format a service status for display.
 /// Extracted for testability.
@@ -68,26 +77,180 @@ pub(crate) fn service_to_json(
     })
 }
 
+/// Format exit codes as a comma-separated list, or "none".
+/// Extracted for testability.
+pub(crate) fn format_exit_codes(codes: &[i32]) -> String {
+    if codes.is_empty() {
+        return "none".to_string();
+    }
+    codes
+        .iter()
+        .map(|code| code.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format the warning shown for a crash-looping service.
+/// Extracted for testability.
+pub(crate) fn format_crash_loop_warning(name: &str, history: &RestartHistory) -> String {
+    format!(
+        "{} is crash-looping: {} restarts, recent exit codes {}",
+        name,
+        history.restarts,
+        format_exit_codes(&history.recent_exit_codes(RECENT_EXIT_CODES))
+    )
+}
+
+/// Add restart count, recent exit codes and crash-loop flag to a service's JSON.
+/// Extracted for testability.
+pub(crate) fn add_restart_history_json(
+    value: &mut serde_json::Value,
+    history: &RestartHistory,
+    crash_looping: bool,
+) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert("restarts".to_string(), history.restarts.into());
+        object.insert(
+            "recent_exit_codes".to_string(),
+            history.recent_exit_codes(RECENT_EXIT_CODES).into(),
+        );
+        object.insert("crash_looping".to_string(), crash_looping.into());
+    }
+}
+
+/// Path and size of a log file; the size is null until the file exists.
+/// Extracted for testability.
+pub(crate) fn log_file_json(path: &Path) -> serde_json::Value {
+    serde_json::json!({
+        "path": path.to_string_lossy(),
+        "size": std::fs::metadata(path).ok().map(|m| m.len())
+    })
+}
+
+/// Build JSON representation of `zb services info`.
+/// Extracted for testability.
+pub(crate) fn service_info_json(
+    info: &ServiceInfo,
+    spec: Option<&ServiceSpec>,
+    settings: &ServiceSettings,
+    history: &RestartHistory,
+    crash_looping: bool,
+    (stdout_log, stderr_log): (&Path, &Path),
+) -> serde_json::Value {
+    let mut value = service_to_json(
+        &info.name,
+        &info.status,
+        info.pid,
+        &info.file_path,
+        info.auto_start,
+    );
+    add_restart_history_json(&mut value, history, crash_looping);
+
+    if let Some(object) = value.as_object_mut() {
+        let spec = spec.cloned().unwrap_or_default();
+        object.insert(
+            "last_exit_status".to_string(),
+            history.exits.last().map(|exit| exit.code).into(),
+        );
+        object.insert("command".to_string(), spec.command.into());
+        object.insert(
+            "working_directory".to_string(),
+            spec.working_directory
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .into(),
+        );
+        object.insert(
+            "environment".to_string(),
+            serde_json::to_value(&spec.environment).unwrap_or_default(),
+        );
+        object.insert(
+            "settings".to_string(),
+            serde_json::to_value(settings).unwrap_or_default(),
+        );
+        object.insert("schedule".to_string(), spec.schedule.into());
+        object.insert(
+            "logs".to_string(),
+            serde_json::json!({
+                "stdout": log_file_json(stdout_log),
+                "stderr": log_file_json(stderr_log),
+            }),
+        );
+    }
+    value
+}
+
+/// Format a service set up by `brew services` for the list.
+/// Extracted for testability.
+pub(crate) fn format_foreign_service_line(service: &ForeignService) -> String {
+    format!("{:<20} {}", service.name, service.file_path.display())
+}
+
+/// Format the warning shown when both zerobrew and `brew services` manage
+/// a formula's service.
+/// Extracted for testability.
+pub(crate) fn format_duplicate_service_warning(name: &str) -> String {
+    format!(
+        "{} has a service from both zerobrew and brew services; they will compete for the same ports and data",
+        name
+    )
+}
+
+/// Print services set up by `brew services`, with duplicate warnings.
+fn print_foreign_services(foreign: &[ForeignService]) {
+    println!();
+    println!(
+        "{} {} services from brew services:",
+        style("==>").cyan().bold(),
+        foreign.len()
+    );
+    println!();
+    for service in foreign {
+        println!("{}", format_foreign_service_line(service));
+    }
+
+    let duplicates: Vec<_> = foreign.iter().filter(|s| s.duplicate).collect();
+    if !duplicates.is_empty() {
+        println!();
+        for service in &duplicates {
+            println!(
+                "{} {}",
+                style("warning:").yellow().bold(),
+                format_duplicate_service_warning(&service.name)
+            );
+        }
+    }
+    println!();
+    println!(
+        "    Take one over with: {} services adopt <formula>",
+        style("zb").cyan()
+    );
+}
+
 /// List all available services.
 pub fn run_list(service_manager: &ServiceManager, json: bool) -> Result<(), zb_core::Error> {
     let services = service_manager.list()?;
+    let foreign = service_manager.foreign_services()?;
+    let histories: Vec<(RestartHistory, bool)> = services
+        .iter()
+        .map(|s| {
+            let history = service_manager.restart_history(&s.name);
+            let crash_looping = history.is_crash_looping_now();
+            (history, crash_looping)
+        })
+        .collect();
 
     if json {
         let json_services: Vec<serde_json::Value> = services
             .iter()
-            .map(|s| service_to_json(&s.name, &s.status, s.pid, &s.file_path, s.auto_start))
+            .zip(&histories)
+            .map(|(s, (history, crash_looping))| {
+                let mut value =
+                    service_to_json(&s.name, &s.status, s.pid, &s.file_path, s.auto_start);
+                add_restart_history_json(&mut value, history, *crash_looping);
+                value
+            })
             .collect();
-        match serde_json::to_string_pretty(&json_services) {
-            Ok(json) => println!("{}", json),
-            Err(e) => {
-                eprintln!(
-                    "{} Failed to serialize JSON: {}",
-                    style("error:").red().bold(),
-                    e
-                );
-                std::process::exit(1);
-            }
-        }
+        print_json(&serde_json::Value::Array(json_services));
     } else if services.is_empty() {
         println!("{} No services available.", style("==>").cyan().bold());
         println!();
@@ -132,15 +295,69 @@ pub fn run_list(service_manager: &ServiceManager, json: bool) -> Result<(), zb_c
                 service.file_path.display()
             );
         }
+
+        let looping: Vec<_> = services
+            .iter()
+            .zip(&histories)
+            .filter(|(_, (_, crash_looping))| *crash_looping)
+            .collect();
+        if !looping.is_empty() {
+            println!();
+            for (service, (history, _)) in &looping {
+                println!(
+                    "{} {}",
+                    style("warning:").yellow().bold(),
+                    format_crash_loop_warning(&service.name, history)
+                );
+            }
+            println!();
+            println!("    Check the logs: zb services log <formula>");
+            println!(
+                "    Slow restarts down: zb services config <formula> --restart-delay 30 --restart-max-delay 600"
+            );
+        }
+    }
+
+    if !json && !foreign.is_empty() {
+        print_foreign_services(&foreign);
     }
 
     Ok(())
 }
 
 /// Show detailed info for a specific service.
-pub fn run_info(service_manager: &ServiceManager, formula: &str) -> Result<(), zb_core::Error> {
+pub fn run_info(
+    installer: &Installer,
+    service_manager: &ServiceManager,
+    formula: &str,
+    json: bool,
+) -> Result<(), zb_core::Error> {
     let info = service_manager.get_service_info(formula)?;
-    let (stdout_log, stderr_log) = service_manager.get_log_paths(formula);
+    let spec = service_manager.service_spec(formula);
+    let (default_stdout, default_stderr) = service_manager.get_log_paths(formula);
+    let stdout_log = spec
+        .as_ref()
+        .and_then(|s| s.stdout_log.clone())
+        .unwrap_or(default_stdout);
+    let stderr_log = spec
+        .as_ref()
+        .and_then(|s| s.stderr_log.clone())
+        .unwrap_or(default_stderr);
+    let history = service_manager.restart_history(formula);
+
+    if json {
+        let settings = installer.service_settings(formula).unwrap_or_default();
+        let value = service_info_json(
+            &info,
+            spec.as_ref(),
+            &settings,
+            &history,
+            history.is_crash_looping_now(),
+            (&stdout_log, &stderr_log),
+        );
+        print_json(&value);
+        return Ok(());
+    }
 
     println!(
         "{} Service: {}",
@@ -171,6 +388,30 @@ pub fn run_info(service_manager: &ServiceManager, formula: &str) -> Result<(), z
     println!("Auto-start:    {}", auto_start_display);
 
     println!("Service file:  {}", info.file_path.display());
+    if let Some(spec) = &spec {
+        if !spec.command.is_empty() {
+            println!("Command:       {}", spec.command.join(" "));
+        }
+        if let Some(schedule) = &spec.schedule {
+            println!("Schedule:      {}", schedule);
+        }
+        for (key, value) in &spec.environment {
+            println!("Environment:   {}={}", key, value);
+        }
+    }
+
+    let restarts = if history.is_crash_looping_now() {
+        style(format!("{} (crash-looping)", history.restarts))
+            .red()
+            .to_string()
+    } else {
+        history.restarts.to_string()
+    };
+    println!("Restarts:      {}", restarts);
+    println!(
+        "Recent exits:  {}",
+        format_exit_codes(&history.recent_exit_codes(RECENT_EXIT_CODES))
+    );
 
     println!();
     println!("{} Log files:", style("==>").cyan().bold());
@@ -200,6 +441,24 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    // ==================== foreign service Tests ====================
+
+    #[test]
+    fn test_format_foreign_service_line() {
+        let service = ForeignService {
+            name: "postgresql@14".to_string(),
+            file_path: PathBuf::from("/home/u/.config/systemd/user/homebrew.postgresql@14.service"),
+            duplicate: false,
+        };
+        assert_eq!(
+            format_foreign_service_line(&service),
+            "postgresql@14        /home/u/.config/systemd/user/homebrew.postgresql@14.service"
+        );
+        assert!(
+            format_duplicate_service_warning("redis").starts_with("redis has a service from both")
+        );
+    }
+
     // ==================== format_status_display Tests ====================
 
     #[test]
@@ -486,4 +745,112 @@ mod tests {
         assert_eq!(parsed[1]["name"], "postgres");
         assert_eq!(parsed[2]["name"], "nginx");
     }
+
+    // ==================== Restart History Tests ====================
+
+    fn history(restarts: u32, codes: &[i32]) -> RestartHistory {
+        RestartHistory {
+            restarts,
+            exits: codes
+                .iter()
+                .map(|&code| zb_io::ServiceExit { code, at: None })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_format_exit_codes() {
+        assert_eq!(format_exit_codes(&[]), "none");
+        assert_eq!(format_exit_codes(&[1]), "1");
+        assert_eq!(format_exit_codes(&[1, 137, 0]), "1, 137, 0");
+    }
+
+    #[test]
+    fn test_format_crash_loop_warning() {
+        let history = history(6, &[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(
+            format_crash_loop_warning("redis", &history),
+            "redis is crash-looping: 6 restarts, recent exit codes 3, 4, 5, 6, 7"
+        );
+    }
+
+    #[test]
+    fn test_add_restart_history_json() {
+        let mut value = service_to_json(
+            "redis",
+            &ServiceStatus::Stopped,
+            None,
+            &PathBuf::from("/tmp/redis.service"),
+            false,
+        );
+        add_restart_history_json(&mut value, &history(4, &[78]), true);
+
+        assert_eq!(value["name"], "redis");
+        assert_eq!(value["restarts"], 4);
+        assert_eq!(value["recent_exit_codes"], serde_json::json!([78]));
+        assert_eq!(value["crash_looping"], true);
+    }
+
+    #[test]
+    fn test_service_info_json() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let stdout_log = tmp.path().join("redis.log");
+        std::fs::write(&stdout_log, "ready\n").unwrap();
+        let stderr_log = tmp.path().join("redis.error.log");
+
+        let info = ServiceInfo {
+            name: "redis".to_string(),
+            status: ServiceStatus::Running,
+            pid: Some(42),
+            file_path: PathBuf::from("/tmp/redis.service"),
+            auto_start: true,
+        };
+        let spec = ServiceSpec {
+            command: vec!["redis-server".to_string(), "redis.conf".to_string()],
+            environment: [("REDIS_PORT".to_string(), "6380".to_string())].into(),
+            schedule: Some("daily".to_string()),
+            ..Default::default()
+        };
+        let settings = ServiceSettings {
+            nice: Some(5),
+            ..Default::default()
+        };
+
+        let value = service_info_json(
+            &info,
+            Some(&spec),
+            &settings,
+            &history(1, &[0, 143]),
+            false,
+            (&stdout_log, &stderr_log),
+        );
+
+        assert_eq!(value["status"], "running");
+        assert_eq!(value["pid"], 42);
+        assert_eq!(value["auto_start"], true);
+        assert_eq!(
+            value["command"],
+            serde_json::json!(["redis-server", "redis.conf"])
+        );
+        assert_eq!(value["environment"]["REDIS_PORT"], "6380");
+        assert_eq!(value["settings"]["nice"], 5);
+        assert_eq!(value["schedule"], "daily");
+        assert_eq!(value["last_exit_status"], 143);
+        assert_eq!(value["restarts"], 1);
+        assert_eq!(value["logs"]["stdout"]["size"], 6);
+        assert!(value["logs"]["stderr"]["size"].is_null());
+
+        // Without a service file there is nothing to run yet
+        let value = service_info_json(
+            &info,
+            None,
+            &ServiceSettings::default(),
+            &RestartHistory::default(),
+            false,
+            (&stdout_log, &stderr_log),
+        );
+        assert_eq!(value["command"], serde_json::json!([]));
+        assert!(value["last_exit_status"].is_null());
+        assert!(value["schedule"].is_null());
+    }
 }
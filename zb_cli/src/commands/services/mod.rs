@@ -4,6 +4,8 @@
 //! - Listing and inspecting services
 //! - Starting, stopping, and restarting services
 //! - Enabling/disabling auto-start at login
+//! - Adopting services set up by `brew services`
+//! - Configuring the user, priorities and limits services run with
 //! - Viewing logs and running in foreground
 
 mod control;
@@ -18,7 +20,8 @@ use crate::ServicesAction;
 
 // Re-export submodule functions for use in dispatch
 pub use control::{
-    run_cleanup, run_disable, run_enable, run_foreground, run_log, run_restart, run_start, run_stop,
+    run_adopt, run_cleanup, run_config, run_disable, run_enable, run_foreground, run_log,
+    run_restart, run_start, run_stop,
 };
 pub use list::{run_info, run_list};
 
@@ -27,15 +30,35 @@ pub fn run(
     installer: &mut Installer,
     prefix: &Path,
     action: Option<ServicesAction>,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let service_manager = ServiceManager::new(prefix);
 
+    if json {
+        return run_json(installer, &service_manager, action);
+    }
+
     match action {
-        None | Some(ServicesAction::List { json: false }) => run_list(&service_manager, false),
-        Some(ServicesAction::List { json: true }) => run_list(&service_manager, true),
-        Some(ServicesAction::Start { formula }) => {
-            run_start(installer, &service_manager, prefix, &formula)
-        }
+        None | Some(ServicesAction::List) => run_list(&service_manager, json),
+        Some(ServicesAction::Start { formula, settings }) => run_start(
+            installer,
+            &service_manager,
+            prefix,
+            &formula,
+            settings.into(),
+        ),
+        Some(ServicesAction::Config {
+            formula,
+            settings,
+            reset,
+        }) => run_config(
+            installer,
+            &service_manager,
+            prefix,
+            &formula,
+            settings.into(),
+            reset,
+        ),
         Some(ServicesAction::Stop { formula }) => run_stop(&service_manager, &formula),
         Some(ServicesAction::Restart { formula }) => run_restart(&service_manager, &formula),
         Some(ServicesAction::Enable { formula }) => run_enable(&service_manager, &formula),
@@ -43,14 +66,55 @@ pub fn run(
         Some(ServicesAction::Run { formula }) => {
             run_foreground(installer, &service_manager, prefix, &formula)
         }
-        Some(ServicesAction::Info { formula }) => run_info(&service_manager, &formula),
+        Some(ServicesAction::Info { formula }) => {
+            run_info(installer, &service_manager, &formula, json)
+        }
+        Some(ServicesAction::Adopt { formula }) => run_adopt(installer, &service_manager, &formula),
         Some(ServicesAction::Log {
             formula,
             lines,
             follow,
         }) => run_log(&service_manager, &formula, lines, follow),
         Some(ServicesAction::Cleanup { dry_run }) => {
-            run_cleanup(installer, &service_manager, dry_run)
+            run_cleanup(installer, &service_manager, dry_run, false)
         }
     }
 }
+
+/// Run a services action for `--json`. Actions that change a service print
+/// its state afterwards, in the same layout as `zb services info --json`.
+fn run_json(
+    installer: &mut Installer,
+    service_manager: &ServiceManager,
+    action: Option<ServicesAction>,
+) -> Result<(), zb_core::Error> {
+    let formula = match action {
+        None | Some(ServicesAction::List) => return run_list(service_manager, true),
+        Some(ServicesAction::Info { formula }) => formula,
+        Some(ServicesAction::Cleanup { dry_run }) => {
+            return run_cleanup(installer, service_manager, dry_run, true);
+        }
+        Some(ServicesAction::Stop { formula }) => {
+            service_manager.stop(&formula)?;
+            formula
+        }
+        Some(ServicesAction::Restart { formula }) => {
+            service_manager.restart(&formula)?;
+            formula
+        }
+        Some(ServicesAction::Enable { formula }) => {
+            service_manager.enable_auto_start(&formula)?;
+            formula
+        }
+        Some(ServicesAction::Disable { formula }) => {
+            service_manager.disable_auto_start(&formula)?;
+            formula
+        }
+        Some(_) => {
+            return Err(zb_core::Error::StoreCorruption {
+                message: "--json is not supported by this services command".to_string(),
+            });
+        }
+    };
+    run_info(installer, service_manager, &formula, true)
+}
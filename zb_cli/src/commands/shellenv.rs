@@ -0,0 +1,79 @@
+//! `zb shellenv --check` implementation.
+
+use console::style;
+use std::path::Path;
+
+use zb_io::{EnvDirCheck, EnvDirState, check_shellenv};
+
+/// Report whether PATH and MANPATH hold the prefix's directories, exiting
+/// non-zero if any is missing or out of order.
+pub fn run_check(prefix: &Path) -> Result<(), zb_core::Error> {
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    let manpath_var = std::env::var("MANPATH").ok();
+    let checks = check_shellenv(prefix, &path_var, manpath_var.as_deref());
+
+    for check in &checks {
+        let marker = if check.is_ok() {
+            style("✓").green()
+        } else {
+            style("✗").red()
+        };
+        println!("{} {}", marker, format_check_line(check));
+    }
+
+    if !checks.iter().all(EnvDirCheck::is_ok) {
+        println!();
+        println!(
+            "Add {} to your shell profile to set it up.",
+            style("eval \"$(zb shellenv)\"").cyan()
+        );
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Describe where a directory stands in its search path.
+/// Extracted for testability.
+pub(crate) fn format_check_line(check: &EnvDirCheck) -> String {
+    let dir = check.dir.display();
+    match &check.state {
+        EnvDirState::Ok => format!("{} is in {}", dir, check.var),
+        EnvDirState::Missing => format!("{} is not in {}", dir, check.var),
+        EnvDirState::Late { after } => {
+            format!("{} is in {}, but after {}", dir, check.var, after.display())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn check(state: EnvDirState) -> EnvDirCheck {
+        EnvDirCheck {
+            var: "PATH",
+            dir: PathBuf::from("/opt/zerobrew/prefix/bin"),
+            state,
+        }
+    }
+
+    #[test]
+    fn test_format_check_line() {
+        assert_eq!(
+            format_check_line(&check(EnvDirState::Ok)),
+            "/opt/zerobrew/prefix/bin is in PATH"
+        );
+        assert_eq!(
+            format_check_line(&check(EnvDirState::Missing)),
+            "/opt/zerobrew/prefix/bin is not in PATH"
+        );
+        assert_eq!(
+            format_check_line(&check(EnvDirState::Late {
+                after: PathBuf::from("/usr/bin")
+            })),
+            "/opt/zerobrew/prefix/bin is in PATH, but after /usr/bin"
+        );
+    }
+}
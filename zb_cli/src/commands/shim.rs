@@ -0,0 +1,543 @@
+//! `brew` compatibility shim.
+//!
+//! When the `zb` binary is invoked through a link named `brew`, the most common
+//! Homebrew invocations are translated into their zb equivalents. Commands
+//! without an equivalent are explained instead of failing with a parse error.
+
+use console::style;
+use std::path::{Path, PathBuf};
+
+/// Name of the shim executable installed into `prefix/bin`.
+pub const SHIM_NAME: &str = "brew";
+
+/// Outcome of translating a `brew` command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BrewTranslation {
+    /// Run one or more zb invocations in order (arguments exclude the program name).
+    /// `dropped` are the brew flags with no zb equivalent, which were left out.
+    Run {
+        invocations: Vec<Vec<String>>,
+        dropped: Vec<String>,
+    },
+    /// Print the prefix, or the opt path of a formula when one is given.
+    Prefix(Option<String>),
+    /// Print the Cellar path, or the Cellar path of a formula when one is given.
+    Cellar(Option<String>),
+    /// No zb equivalent exists; the message explains why or what to use instead.
+    Unsupported(String),
+}
+
+/// Check whether the program was invoked as `brew` (e.g. through the shim link).
+pub fn invoked_as_brew(argv0: &str) -> bool {
+    Path::new(argv0)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n == SHIM_NAME)
+        .unwrap_or(false)
+}
+
+/// Translate `brew` arguments (excluding the program name) into zb invocations.
+pub fn translate(args: &[String]) -> BrewTranslation {
+    if args.iter().any(|a| a == "--cask" || a == "--casks") {
+        return BrewTranslation::Unsupported(
+            "casks are not supported by zerobrew; only formulae can be installed".to_string(),
+        );
+    }
+
+    // Drop flags that only select formulae, since zb never deals with anything else
+    let args: Vec<&str> = args
+        .iter()
+        .map(|s| s.as_str())
+        .filter(|a| !matches!(*a, "--formula" | "--formulae"))
+        .collect();
+
+    let Some((&command, rest)) = args.split_first() else {
+        return run_one("--help");
+    };
+
+    match command {
+        "--version" | "-v" => run_one("--version"),
+        "--prefix" => BrewTranslation::Prefix(first_name(rest)),
+        "--cellar" => BrewTranslation::Cellar(first_name(rest)),
+        "install" => per_formula("install", rest, translate_install_flag),
+        "reinstall" => BrewTranslation::Unsupported(format!(
+            "'brew reinstall' has no zerobrew equivalent, as 'zb install' leaves installed formulae alone{}",
+            names(rest)
+                .iter()
+                .map(|name| format!("\n  hint: run 'zb uninstall {name} && zb install {name}'"))
+                .collect::<String>()
+        )),
+        "uninstall" | "remove" | "rm" => per_formula("uninstall", rest, drop_flag),
+        "upgrade" => {
+            if names(rest).is_empty() {
+                single("upgrade", rest, translate_upgrade_flag)
+            } else {
+                per_formula("upgrade", rest, translate_upgrade_flag)
+            }
+        }
+        "info" | "abv" => per_formula("info", rest, translate_json_flag),
+        "list" | "ls" => single("list", rest, translate_list_flag),
+        "search" => single("search", rest, translate_json_flag),
+        "outdated" => single("outdated", rest, translate_json_flag),
+        "pin" => per_formula("pin", rest, drop_flag),
+        "unpin" => per_formula("unpin", rest, drop_flag),
+        "link" | "ln" => per_formula("link", rest, translate_link_flag),
+        "unlink" => per_formula("unlink", rest, drop_flag),
+        "deps" => single("deps", rest, translate_deps_flag),
+        "uses" => single("uses", rest, translate_uses_flag),
+        "leaves" => run_one("leaves"),
+        "autoremove" => single("autoremove", rest, translate_dry_run_flag),
+        "cleanup" => single("cleanup", rest, translate_dry_run_flag),
+        "doctor" | "dr" => run_one("doctor"),
+        "tap" => single("tap", rest, drop_flag),
+        "untap" => per_formula("untap", rest, drop_flag),
+        "shellenv" => run_one("shellenv"),
+        "commands" => run_one("commands"),
+        "services" | "bundle" => passthrough(command, rest),
+        "update" => run_one("update"),
+        "edit" | "create" | "audit" | "style" | "bump-formula-pr" | "tap-new" | "extract" => {
+            BrewTranslation::Unsupported(format!(
+                "'brew {}' is a formula authoring command and has no zerobrew equivalent",
+                command
+            ))
+        }
+        other => BrewTranslation::Unsupported(format!(
+            "'brew {}' has no zerobrew equivalent\n  hint: run 'zb commands' to see what is available",
+            other
+        )),
+    }
+}
+
+/// Positional (non-flag) arguments.
+fn names<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    args.iter()
+        .copied()
+        .filter(|a| !a.starts_with('-'))
+        .collect()
+}
+
+fn first_name(args: &[&str]) -> Option<String> {
+    names(args).first().map(|s| s.to_string())
+}
+
+/// Run a single zb command without arguments.
+fn run_one(command: &str) -> BrewTranslation {
+    BrewTranslation::Run {
+        invocations: vec![vec![command.to_string()]],
+        dropped: Vec::new(),
+    }
+}
+
+/// Translate flags for a single zb invocation that takes all positional arguments.
+fn single(
+    command: &str,
+    rest: &[&str],
+    translate_flag: fn(&str) -> Option<&'static str>,
+) -> BrewTranslation {
+    let mut args = vec![command.to_string()];
+    let mut dropped = Vec::new();
+    for arg in rest {
+        if arg.starts_with('-') {
+            match translate_flag(arg) {
+                Some(flag) => args.push(flag.to_string()),
+                None => dropped.push(arg.to_string()),
+            }
+        } else {
+            args.push(arg.to_string());
+        }
+    }
+    BrewTranslation::Run {
+        invocations: vec![args],
+        dropped,
+    }
+}
+
+/// Expand a multi-formula brew invocation into one zb invocation per formula.
+fn per_formula(
+    command: &str,
+    rest: &[&str],
+    translate_flag: fn(&str) -> Option<&'static str>,
+) -> BrewTranslation {
+    let formulas = names(rest);
+    if formulas.is_empty() {
+        return BrewTranslation::Unsupported(format!(
+            "'brew {}' requires at least one formula name",
+            command
+        ));
+    }
+
+    let mut flags = Vec::new();
+    let mut dropped = Vec::new();
+    for arg in rest.iter().filter(|a| a.starts_with('-')) {
+        match translate_flag(arg) {
+            Some(flag) => flags.push(flag.to_string()),
+            None => dropped.push(arg.to_string()),
+        }
+    }
+
+    BrewTranslation::Run {
+        invocations: formulas
+            .into_iter()
+            .map(|name| {
+                let mut args = vec![command.to_string(), name.to_string()];
+                args.extend(flags.iter().cloned());
+                args
+            })
+            .collect(),
+        dropped,
+    }
+}
+
+/// Pass arguments through unchanged (for subcommand groups with matching syntax).
+fn passthrough(command: &str, rest: &[&str]) -> BrewTranslation {
+    let mut args = vec![command.to_string()];
+    args.extend(rest.iter().map(|s| s.to_string()));
+    BrewTranslation::Run {
+        invocations: vec![args],
+        dropped: Vec::new(),
+    }
+}
+
+fn drop_flag(_flag: &str) -> Option<&'static str> {
+    None
+}
+
+fn translate_install_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "-s" | "--build-from-source" => Some("--build-from-source"),
+        "--HEAD" | "--head" => Some("--head"),
+        _ => None,
+    }
+}
+
+fn translate_upgrade_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "-n" | "--dry-run" => Some("--dry-run"),
+        _ => None,
+    }
+}
+
+fn translate_json_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "--json" | "--json=v1" | "--json=v2" => Some("--json"),
+        _ => None,
+    }
+}
+
+fn translate_list_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "--pinned" => Some("--pinned"),
+        _ => None,
+    }
+}
+
+fn translate_link_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "--overwrite" => Some("--overwrite"),
+        "-f" | "--force" => Some("--force"),
+        _ => None,
+    }
+}
+
+fn translate_deps_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "--tree" => Some("--tree"),
+        "--installed" => Some("--installed"),
+        _ => None,
+    }
+}
+
+fn translate_uses_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "--installed" => Some("--installed"),
+        "--recursive" => Some("--recursive"),
+        _ => None,
+    }
+}
+
+fn translate_dry_run_flag(flag: &str) -> Option<&'static str> {
+    match flag {
+        "-n" | "--dry-run" => Some("--dry-run"),
+        _ => None,
+    }
+}
+
+/// Path of the shim link inside the prefix.
+pub fn shim_path(prefix: &Path) -> PathBuf {
+    prefix.join("bin").join(SHIM_NAME)
+}
+
+/// Install the `brew` shim as a symlink to the running zb executable.
+pub fn run_install(prefix: &Path) -> Result<(), zb_core::Error> {
+    let exe = std::env::current_exe().map_err(|e| zb_core::Error::StoreCorruption {
+        message: format!("failed to locate zb executable: {}", e),
+    })?;
+    let link = shim_path(prefix);
+
+    match std::fs::symlink_metadata(&link) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            if std::fs::read_link(&link).ok().as_deref() == Some(exe.as_path()) {
+                println!(
+                    "{} brew shim is already installed at {}",
+                    style("==>").cyan().bold(),
+                    link.display()
+                );
+                return Ok(());
+            }
+            std::fs::remove_file(&link).map_err(|e| zb_core::Error::StoreCorruption {
+                message: format!("failed to replace {}: {}", link.display(), e),
+            })?;
+        }
+        Ok(_) => {
+            return Err(zb_core::Error::LinkConflict {
+                path: link,
+                existing_type: zb_core::LinkConflictType::RegularFile,
+            });
+        }
+        Err(_) => {}
+    }
+
+    if let Some(parent) = link.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| zb_core::Error::StoreCorruption {
+            message: format!("failed to create {}: {}", parent.display(), e),
+        })?;
+    }
+
+    std::os::unix::fs::symlink(&exe, &link).map_err(|e| zb_core::Error::StoreCorruption {
+        message: format!("failed to create {}: {}", link.display(), e),
+    })?;
+
+    println!(
+        "{} {} Installed brew shim at {}",
+        style("==>").cyan().bold(),
+        style("✓").green(),
+        link.display()
+    );
+    println!(
+        "    {} 'brew install', 'brew list', 'brew services' etc. now run zb",
+        style("→").dim()
+    );
+
+    Ok(())
+}
+
+/// Remove the `brew` shim if it points at a zb executable.
+pub fn run_uninstall(prefix: &Path) -> Result<(), zb_core::Error> {
+    let link = shim_path(prefix);
+
+    let Ok(meta) = std::fs::symlink_metadata(&link) else {
+        println!("brew shim is not installed.");
+        return Ok(());
+    };
+
+    if !meta.file_type().is_symlink() {
+        return Err(zb_core::Error::StoreCorruption {
+            message: format!("{} is not a zb shim; refusing to remove it", link.display()),
+        });
+    }
+
+    std::fs::remove_file(&link).map_err(|e| zb_core::Error::StoreCorruption {
+        message: format!("failed to remove {}: {}", link.display(), e),
+    })?;
+
+    println!(
+        "{} {} Removed brew shim from {}",
+        style("==>").cyan().bold(),
+        style("✓").green(),
+        link.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|s| s.to_string()).collect()
+    }
+
+    fn run(invocations: &[&str]) -> BrewTranslation {
+        run_dropping(invocations, &[])
+    }
+
+    fn run_dropping(invocations: &[&str], dropped: &[&str]) -> BrewTranslation {
+        BrewTranslation::Run {
+            invocations: invocations.iter().map(|s| args(s)).collect(),
+            dropped: dropped.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_invoked_as_brew() {
+        assert!(invoked_as_brew("brew"));
+        assert!(invoked_as_brew("/opt/zerobrew/prefix/bin/brew"));
+        assert!(!invoked_as_brew("zb"));
+        assert!(!invoked_as_brew("/usr/local/bin/brewer"));
+    }
+
+    #[test]
+    fn test_translate_install_multiple_formulas() {
+        assert_eq!(
+            translate(&args("install wget jq")),
+            run(&["install wget", "install jq"])
+        );
+    }
+
+    #[test]
+    fn test_translate_install_flags() {
+        assert_eq!(
+            translate(&args("install --HEAD -s neovim")),
+            run(&["install neovim --head --build-from-source"])
+        );
+        assert_eq!(
+            translate(&args("install --formula --verbose jq")),
+            run_dropping(&["install jq"], &["--verbose"])
+        );
+    }
+
+    #[test]
+    fn test_translate_reports_dropped_flags() {
+        assert_eq!(
+            translate(&args("install --verbose --debug wget jq")),
+            run_dropping(&["install wget", "install jq"], &["--verbose", "--debug"])
+        );
+        assert_eq!(
+            translate(&args("list --versions")),
+            run_dropping(&["list"], &["--versions"])
+        );
+        assert_eq!(
+            translate(&args("search --desc ripgrep")),
+            run_dropping(&["search ripgrep"], &["--desc"])
+        );
+    }
+
+    #[test]
+    fn test_translate_reinstall_is_unsupported() {
+        match translate(&args("reinstall wget jq")) {
+            BrewTranslation::Unsupported(msg) => {
+                assert!(msg.contains("'zb uninstall wget && zb install wget'"));
+                assert!(msg.contains("zb uninstall jq && zb install jq"));
+            }
+            other => panic!("expected Unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_translate_uninstall_aliases() {
+        assert_eq!(translate(&args("rm jq")), run(&["uninstall jq"]));
+        assert_eq!(translate(&args("remove jq")), run(&["uninstall jq"]));
+    }
+
+    #[test]
+    fn test_translate_list_and_info() {
+        assert_eq!(translate(&args("ls --pinned")), run(&["list --pinned"]));
+        assert_eq!(
+            translate(&args("info --json=v2 git")),
+            run(&["info git --json"])
+        );
+    }
+
+    #[test]
+    fn test_translate_upgrade_all_and_single() {
+        assert_eq!(translate(&args("upgrade")), run(&["upgrade"]));
+        assert_eq!(
+            translate(&args("upgrade -n git")),
+            run(&["upgrade git --dry-run"])
+        );
+    }
+
+    #[test]
+    fn test_translate_services_passthrough() {
+        assert_eq!(
+            translate(&args("services start redis")),
+            run(&["services start redis"])
+        );
+    }
+
+    #[test]
+    fn test_translate_prefix() {
+        assert_eq!(translate(&args("--prefix")), BrewTranslation::Prefix(None));
+        assert_eq!(
+            translate(&args("--prefix openssl@3")),
+            BrewTranslation::Prefix(Some("openssl@3".to_string()))
+        );
+        assert_eq!(translate(&args("--cellar")), BrewTranslation::Cellar(None));
+    }
+
+    #[test]
+    fn test_translate_cask_is_unsupported() {
+        match translate(&args("install --cask firefox")) {
+            BrewTranslation::Unsupported(msg) => assert!(msg.contains("casks")),
+            other => panic!("expected Unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_translate_update_refreshes_metadata() {
+        assert_eq!(translate(&args("update")), run(&["update"]));
+    }
+
+    #[test]
+    fn test_translate_unknown_command() {
+        match translate(&args("frobnicate")) {
+            BrewTranslation::Unsupported(msg) => {
+                assert!(msg.contains("frobnicate"));
+                assert!(msg.contains("zb commands"));
+            }
+            other => panic!("expected Unsupported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_translate_install_without_formula() {
+        assert!(matches!(
+            translate(&args("install")),
+            BrewTranslation::Unsupported(_)
+        ));
+    }
+
+    #[test]
+    fn test_translate_empty_shows_help() {
+        assert_eq!(translate(&[]), run(&["--help"]));
+    }
+
+    #[test]
+    fn test_shim_path() {
+        assert_eq!(
+            shim_path(Path::new("/opt/zerobrew/prefix")),
+            PathBuf::from("/opt/zerobrew/prefix/bin/brew")
+        );
+    }
+
+    #[test]
+    fn test_uninstall_refuses_regular_file() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        std::fs::write(shim_path(tmp.path()), "#!/bin/sh\n").unwrap();
+
+        assert!(run_uninstall(tmp.path()).is_err());
+        assert!(shim_path(tmp.path()).exists());
+    }
+
+    #[test]
+    fn test_install_then_uninstall() {
+        let tmp = tempfile::TempDir::new().unwrap();
+
+        run_install(tmp.path()).unwrap();
+        let link = shim_path(tmp.path());
+        assert!(
+            std::fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+
+        // Installing twice is a no-op
+        run_install(tmp.path()).unwrap();
+
+        run_uninstall(tmp.path()).unwrap();
+        assert!(std::fs::symlink_metadata(&link).is_err());
+    }
+}
@@ -0,0 +1,493 @@
+//! Stats command implementation.
+
+use console::style;
+
+use zb_io::install::Installer;
+use zb_io::{DedupeStats, InstallStats, MonthlyActivity, PourEvent, PourStats};
+
+use crate::display::{format_age, format_bytes};
+
+/// Run the stats command.
+pub fn run(installer: &mut Installer, json: bool, reset: bool) -> Result<(), zb_core::Error> {
+    if reset {
+        let removed = installer.clear_install_stats()?;
+        println!(
+            "{} Cleared {} recorded installs",
+            style("==>").cyan().bold(),
+            removed
+        );
+        return Ok(());
+    }
+
+    let stats = installer.install_stats()?;
+    let dedupe = installer.dedupe_stats()?;
+    let api_lookups = installer.api_lookup_stats();
+    let activity = installer.install_activity_by_month()?;
+    let pours = installer.pour_stats()?;
+
+    if json {
+        let mut value = stats_to_json(&stats);
+        value["dedupe"] = dedupe_to_json(&dedupe);
+        value["pours"] = pour_stats_to_json(&pours);
+        value["api_cache"] = api_cache_to_json(api_lookups);
+        value["activity"] = serde_json::json!(activity);
+        match serde_json::to_string_pretty(&value) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                return Err(zb_core::Error::StoreCorruption {
+                    message: format!("failed to serialize stats: {}", e),
+                });
+            }
+        }
+        return Ok(());
+    }
+
+    if stats.runs == 0 {
+        println!("No installs recorded yet.");
+    } else {
+        for line in format_stats_lines(&stats) {
+            println!("{}", line);
+        }
+    }
+
+    if let Some(line) = format_api_cache_line(api_lookups) {
+        println!("{}", line);
+    }
+
+    if let Some(line) = format_pour_summary(&pours) {
+        println!("{}", line);
+    }
+
+    if dedupe.kegs > 0 {
+        println!();
+        for line in format_dedupe_lines(&dedupe) {
+            println!("{}", line);
+        }
+    }
+
+    if !activity.is_empty() {
+        println!();
+        for line in format_activity_lines(&activity) {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Run `zb stats --pours`: list the last `limit` entries of the pour log.
+pub fn run_pours(installer: &Installer, json: bool, limit: usize) -> Result<(), zb_core::Error> {
+    let events = installer.pour_events(limit)?;
+
+    if json {
+        match serde_json::to_string_pretty(&events) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                return Err(zb_core::Error::StoreCorruption {
+                    message: format!("failed to serialize pour log: {}", e),
+                });
+            }
+        }
+        return Ok(());
+    }
+
+    if events.is_empty() {
+        println!("No bottles poured yet.");
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    for event in &events {
+        let marker = if event.kind.is_integrity_failure() {
+            style("✗").red()
+        } else {
+            style("✓").green()
+        };
+        let [summary, source] = format_pour_lines(event, now);
+        println!("{} {}", marker, summary);
+        println!("  {}", style(source).dim());
+    }
+
+    Ok(())
+}
+
+/// Format a pour log entry as what happened and where the bottle came from.
+/// Extracted for testability.
+pub(crate) fn format_pour_lines(event: &PourEvent, now: i64) -> [String; 2] {
+    let what = if event.kind.is_integrity_failure() {
+        match &event.detail {
+            Some(detail) => format!("{}: {}", event.kind, detail.lines().next().unwrap_or("")),
+            None => event.kind.to_string(),
+        }
+    } else {
+        let mut what = format!(
+            "poured ({}, {}",
+            format_bytes(event.bytes),
+            format_ms(event.duration_ms as f64)
+        );
+        if event.relocated {
+            what.push_str(", relocated");
+        }
+        what.push(')');
+        what
+    };
+
+    [
+        format!(
+            "{:>7}  {} {} {}",
+            format_age(now - event.at),
+            event.name,
+            event.version,
+            what
+        ),
+        format!("{} sha256:{}", event.url, event.sha256),
+    ]
+}
+
+/// Build the JSON representation of the pour log totals.
+/// Extracted for testability.
+pub(crate) fn pour_stats_to_json(pours: &PourStats) -> serde_json::Value {
+    serde_json::json!({
+        "poured": pours.poured,
+        "relocated": pours.relocated,
+        "integrity_failures": pours.integrity_failures,
+        "bytes": pours.bytes,
+    })
+}
+
+/// Format the pour log totals, if anything was logged.
+/// Extracted for testability.
+pub(crate) fn format_pour_summary(pours: &PourStats) -> Option<String> {
+    if pours.poured == 0 && pours.integrity_failures == 0 {
+        return None;
+    }
+    Some(format!(
+        "  Bottles poured:        {} ({} relocated, {} integrity failures)",
+        pours.poured, pours.relocated, pours.integrity_failures
+    ))
+}
+
+/// Fraction of `hits` out of `total` (0.0 when nothing was counted).
+/// Extracted for testability.
+pub(crate) fn hit_rate(hits: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        hits as f64 / total as f64
+    }
+}
+
+/// Build the JSON representation of store dedupe savings.
+/// Extracted for testability.
+pub(crate) fn dedupe_to_json(dedupe: &DedupeStats) -> serde_json::Value {
+    serde_json::json!({
+        "kegs": dedupe.kegs,
+        "store_entries": dedupe.store_entries,
+        "store_bytes": dedupe.store_bytes,
+        "naive_bytes": dedupe.naive_bytes,
+        "bytes_saved": dedupe.bytes_saved(),
+    })
+}
+
+/// Build the JSON representation of API cache lookups; null when the
+/// cache is disabled.
+/// Extracted for testability.
+pub(crate) fn api_cache_to_json(lookups: Option<(u64, u64)>) -> serde_json::Value {
+    match lookups {
+        Some((hits, misses)) => serde_json::json!({
+            "hits": hits,
+            "misses": misses,
+            "hit_rate": hit_rate(hits, hits + misses),
+        }),
+        None => serde_json::Value::Null,
+    }
+}
+
+/// Format the API cache hit rate, if any lookups were recorded.
+/// Extracted for testability.
+pub(crate) fn format_api_cache_line(lookups: Option<(u64, u64)>) -> Option<String> {
+    let (hits, misses) = lookups?;
+    let total = hits + misses;
+    if total == 0 {
+        return None;
+    }
+    Some(format!(
+        "  API cache hit rate:    {:.0}% ({}/{})",
+        hit_rate(hits, total) * 100.0,
+        hits,
+        total
+    ))
+}
+
+/// Format store dedupe savings as display lines.
+/// Extracted for testability.
+pub(crate) fn format_dedupe_lines(dedupe: &DedupeStats) -> Vec<String> {
+    vec![
+        format!(
+            "{} Store dedupe: {} kegs share {} store entries",
+            style("==>").cyan().bold(),
+            dedupe.kegs,
+            dedupe.store_entries
+        ),
+        format!(
+            "  Store size:            {}",
+            format_bytes(dedupe.store_bytes)
+        ),
+        format!(
+            "  Per-keg copies:        {}",
+            format_bytes(dedupe.naive_bytes)
+        ),
+        format!(
+            "  Saved by dedupe:       {}",
+            format_bytes(dedupe.bytes_saved())
+        ),
+    ]
+}
+
+/// Format installs and upgrades per month as display lines.
+/// Extracted for testability.
+pub(crate) fn format_activity_lines(activity: &[MonthlyActivity]) -> Vec<String> {
+    let mut lines = vec![format!("{} Activity by month", style("==>").cyan().bold())];
+    for month in activity {
+        lines.push(format!(
+            "  {}  {} installs, {} upgrades",
+            month.month, month.installs, month.upgrades
+        ));
+    }
+    lines
+}
+
+/// Build the JSON representation of install statistics.
+/// Extracted for testability.
+pub(crate) fn stats_to_json(stats: &InstallStats) -> serde_json::Value {
+    serde_json::json!({
+        "installs": stats.runs,
+        "packages": stats.packages,
+        "avg_install_ms": stats.avg_run_ms,
+        "avg_resolve_ms": stats.avg_resolve_ms,
+        "avg_download_ms": stats.avg_download_ms,
+        "avg_extract_ms": stats.avg_extract_ms,
+        "avg_link_ms": stats.avg_link_ms,
+        "blob_cache_hits": stats.blob_cache_hits,
+        "cache_hit_rate": stats.cache_hit_rate(),
+        "store_reuses": stats.store_reuses,
+        "bytes_downloaded": stats.bytes_downloaded,
+        "bytes_saved": stats.bytes_saved,
+    })
+}
+
+/// Format a millisecond average for display.
+/// Extracted for testability.
+pub(crate) fn format_ms(ms: f64) -> String {
+    if ms >= 1000.0 {
+        format!("{:.2}s", ms / 1000.0)
+    } else {
+        format!("{:.0}ms", ms)
+    }
+}
+
+/// Format install statistics as display lines.
+/// Extracted for testability.
+pub(crate) fn format_stats_lines(stats: &InstallStats) -> Vec<String> {
+    vec![
+        format!(
+            "{} {} installs, {} packages",
+            style("==>").cyan().bold(),
+            stats.runs,
+            stats.packages
+        ),
+        String::new(),
+        format!("  Average install time:  {}", format_ms(stats.avg_run_ms)),
+        format!(
+            "  Average resolve time:  {}",
+            format_ms(stats.avg_resolve_ms)
+        ),
+        format!(
+            "  Per package:           download {}, extract {}, link {}",
+            format_ms(stats.avg_download_ms),
+            format_ms(stats.avg_extract_ms),
+            format_ms(stats.avg_link_ms)
+        ),
+        String::new(),
+        format!(
+            "  Bottle cache hit rate: {:.0}% ({}/{})",
+            stats.cache_hit_rate() * 100.0,
+            stats.blob_cache_hits,
+            stats.packages
+        ),
+        format!(
+            "  Store entries reused:  {}/{}",
+            stats.store_reuses, stats.packages
+        ),
+        format!(
+            "  Downloaded:            {}",
+            format_bytes(stats.bytes_downloaded)
+        ),
+        format!(
+            "  Saved by cache:        {}",
+            format_bytes(stats.bytes_saved)
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_stats() -> InstallStats {
+        InstallStats {
+            runs: 2,
+            packages: 4,
+            avg_run_ms: 1500.0,
+            avg_resolve_ms: 120.0,
+            avg_download_ms: 300.0,
+            avg_extract_ms: 80.0,
+            avg_link_ms: 5.0,
+            blob_cache_hits: 1,
+            store_reuses: 1,
+            bytes_downloaded: 2048,
+            bytes_saved: 1024,
+        }
+    }
+
+    fn sample_dedupe() -> DedupeStats {
+        DedupeStats {
+            kegs: 3,
+            store_entries: 2,
+            store_bytes: 2048,
+            naive_bytes: 3072,
+        }
+    }
+
+    #[test]
+    fn test_hit_rate_handles_zero_total() {
+        assert_eq!(hit_rate(0, 0), 0.0);
+        assert_eq!(hit_rate(3, 4), 0.75);
+    }
+
+    #[test]
+    fn test_dedupe_to_json_fields() {
+        let json = dedupe_to_json(&sample_dedupe());
+        assert_eq!(json["kegs"], 3);
+        assert_eq!(json["store_entries"], 2);
+        assert_eq!(json["naive_bytes"], 3072);
+        assert_eq!(json["bytes_saved"], 1024);
+    }
+
+    #[test]
+    fn test_api_cache_to_json() {
+        let json = api_cache_to_json(Some((3, 1)));
+        assert_eq!(json["hits"], 3);
+        assert_eq!(json["misses"], 1);
+        assert_eq!(json["hit_rate"], 0.75);
+        assert!(api_cache_to_json(None).is_null());
+    }
+
+    #[test]
+    fn test_format_api_cache_line() {
+        assert_eq!(
+            format_api_cache_line(Some((3, 1))).unwrap(),
+            "  API cache hit rate:    75% (3/4)"
+        );
+        assert!(format_api_cache_line(Some((0, 0))).is_none());
+        assert!(format_api_cache_line(None).is_none());
+    }
+
+    #[test]
+    fn test_format_dedupe_lines_shows_savings() {
+        let lines = format_dedupe_lines(&sample_dedupe());
+        assert!(lines[0].contains("3 kegs share 2 store entries"));
+        assert!(lines.iter().any(|l| l.contains("Saved by dedupe")));
+    }
+
+    #[test]
+    fn test_format_activity_lines() {
+        let lines = format_activity_lines(&[MonthlyActivity {
+            month: "2026-09".to_string(),
+            installs: 4,
+            upgrades: 2,
+        }]);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], "  2026-09  4 installs, 2 upgrades");
+    }
+
+    fn sample_pour(kind: zb_io::PourEventKind) -> PourEvent {
+        PourEvent {
+            at: 1000,
+            kind,
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            url: "https://mirror.example.com/jq.tar.gz".to_string(),
+            sha256: "abc".to_string(),
+            bytes: 2048,
+            duration_ms: 120,
+            relocated: true,
+            detail: Some("got sha256 def".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_format_pour_lines() {
+        let [summary, source] = format_pour_lines(&sample_pour(zb_io::PourEventKind::Poured), 1300);
+        assert_eq!(
+            summary,
+            format!(
+                " 5m ago  jq 1.7.1 poured ({}, 120ms, relocated)",
+                format_bytes(2048)
+            )
+        );
+        assert_eq!(source, "https://mirror.example.com/jq.tar.gz sha256:abc");
+
+        let [summary, _] =
+            format_pour_lines(&sample_pour(zb_io::PourEventKind::ChecksumMismatch), 1300);
+        assert_eq!(
+            summary,
+            " 5m ago  jq 1.7.1 checksum mismatch: got sha256 def"
+        );
+    }
+
+    #[test]
+    fn test_format_pour_summary() {
+        assert!(format_pour_summary(&PourStats::default()).is_none());
+        let pours = PourStats {
+            poured: 4,
+            relocated: 3,
+            integrity_failures: 1,
+            bytes: 4096,
+        };
+        assert_eq!(
+            format_pour_summary(&pours).unwrap(),
+            "  Bottles poured:        4 (3 relocated, 1 integrity failures)"
+        );
+        assert_eq!(pour_stats_to_json(&pours)["integrity_failures"], 1);
+    }
+
+    #[test]
+    fn test_format_ms_milliseconds() {
+        assert_eq!(format_ms(250.4), "250ms");
+    }
+
+    #[test]
+    fn test_format_ms_seconds() {
+        assert_eq!(format_ms(1500.0), "1.50s");
+    }
+
+    #[test]
+    fn test_stats_to_json_fields() {
+        let json = stats_to_json(&sample_stats());
+        assert_eq!(json["installs"], 2);
+        assert_eq!(json["packages"], 4);
+        assert_eq!(json["cache_hit_rate"], 0.25);
+        assert_eq!(json["bytes_saved"], 1024);
+    }
+
+    #[test]
+    fn test_format_stats_lines_includes_hit_rate() {
+        let lines = format_stats_lines(&sample_stats());
+        assert!(lines.iter().any(|l| l.contains("25% (1/4)")));
+        assert!(lines.iter().any(|l| l.contains("1.50s")));
+    }
+}
@@ -1,9 +1,13 @@
-//! Tap and untap command implementations.
+//! Tap, untap and tap pin command implementations.
 
 use console::style;
+use serde_json::{Value, json};
 
+use zb_io::InstalledTap;
 use zb_io::install::Installer;
 
+use crate::render::{Render, emit};
+
 /// Parse a tap name in user/repo format.
 ///
 /// Returns `Ok((user, repo))` if the format is valid, or an error message if not.
@@ -63,39 +67,104 @@ pub fn format_tap_list(tap_names: &[String]) -> Vec<String> {
     }
 }
 
+/// Tap name as listed, marking pinned taps.
+///
+/// Extracted for testability.
+pub(crate) fn format_tap_name(name: &str, pinned: bool) -> String {
+    if pinned {
+        format!("{} {}", name, style("(pinned)").yellow())
+    } else {
+        name.to_string()
+    }
+}
+
+/// Installed taps
+struct TapListOutput(Vec<InstalledTap>);
+
+impl Render for TapListOutput {
+    fn render_human(&self) {
+        let tap_names: Vec<String> = self
+            .0
+            .iter()
+            .map(|t| format_tap_name(&t.name, t.pinned))
+            .collect();
+        for line in format_tap_list(&tap_names) {
+            println!("{}", line);
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        json!(self.0.iter().map(tap_json).collect::<Vec<_>>())
+    }
+}
+
+/// An installed tap as JSON.
+/// Extracted for testability.
+pub(crate) fn tap_json(tap: &InstalledTap) -> Value {
+    json!({
+        "name": tap.name,
+        "url": tap.url,
+        "added_at": tap.added_at,
+        "pinned": tap.pinned,
+    })
+}
+
+/// A tap that was added, removed, pinned or unpinned
+struct TapChangeOutput<'a> {
+    tap: &'a str,
+    /// What happened to it, e.g. "tapped"
+    action: &'static str,
+    message: String,
+}
+
+impl Render for TapChangeOutput<'_> {
+    fn render_human(&self) {
+        println!(
+            "{} {} {}",
+            style("==>").cyan().bold(),
+            style("✓").green().bold(),
+            self.message
+        );
+    }
+
+    fn render_json(&self) -> Value {
+        json!({ "tap": self.tap, "action": self.action })
+    }
+}
+
 /// Run the tap command.
 pub async fn run_tap(
     installer: &mut Installer,
     user_repo: Option<String>,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     match user_repo {
         None => {
             // List taps
-            let taps = installer.list_taps()?;
-            let tap_names: Vec<String> = taps.iter().map(|t| t.name.clone()).collect();
-
-            for line in format_tap_list(&tap_names) {
-                println!("{}", line);
-            }
+            emit(&TapListOutput(installer.list_taps()?), json);
         }
         Some(user_repo) => {
             // Add tap
             let (user, repo) = parse_tap_name(&user_repo)
                 .map_err(|message| zb_core::Error::StoreCorruption { message })?;
 
-            println!(
-                "{} Tapping {}...",
-                style("==>").cyan().bold(),
-                style(&user_repo).bold()
-            );
+            if !json {
+                println!(
+                    "{} Tapping {}...\n",
+                    style("==>").cyan().bold(),
+                    style(&user_repo).bold()
+                );
+            }
 
             installer.add_tap(user, repo).await?;
 
-            println!(
-                "\n{} {} Tapped {}",
-                style("==>").cyan().bold(),
-                style("✓").green().bold(),
-                style(&user_repo).bold()
+            emit(
+                &TapChangeOutput {
+                    tap: &user_repo,
+                    action: "tapped",
+                    message: format!("Tapped {}", style(&user_repo).bold()),
+                },
+                json,
             );
         }
     }
@@ -104,23 +173,72 @@ pub async fn run_tap(
 }
 
 /// Run the untap command.
-pub fn run_untap(installer: &mut Installer, user_repo: String) -> Result<(), zb_core::Error> {
+pub fn run_untap(
+    installer: &mut Installer,
+    user_repo: String,
+    json: bool,
+) -> Result<(), zb_core::Error> {
     let (user, repo) = parse_tap_name(&user_repo)
         .map_err(|message| zb_core::Error::StoreCorruption { message })?;
 
-    println!(
-        "{} Untapping {}...",
-        style("==>").cyan().bold(),
-        style(&user_repo).bold()
-    );
+    if !json {
+        println!(
+            "{} Untapping {}...\n",
+            style("==>").cyan().bold(),
+            style(&user_repo).bold()
+        );
+    }
 
     installer.remove_tap(user, repo)?;
 
-    println!(
-        "\n{} {} Untapped {}",
-        style("==>").cyan().bold(),
-        style("✓").green().bold(),
-        style(&user_repo).bold()
+    emit(
+        &TapChangeOutput {
+            tap: &user_repo,
+            action: "untapped",
+            message: format!("Untapped {}", style(&user_repo).bold()),
+        },
+        json,
+    );
+
+    Ok(())
+}
+
+/// Run `zb tap pin`.
+pub fn run_pin(installer: &Installer, user_repo: &str, json: bool) -> Result<(), zb_core::Error> {
+    let (user, repo) =
+        parse_tap_name(user_repo).map_err(|message| zb_core::Error::StoreCorruption { message })?;
+
+    installer.pin_tap(user, repo)?;
+
+    emit(
+        &TapChangeOutput {
+            tap: user_repo,
+            action: "pinned",
+            message: format!(
+                "Pinned {}; its formulas now take precedence over homebrew/core",
+                style(user_repo).bold()
+            ),
+        },
+        json,
+    );
+
+    Ok(())
+}
+
+/// Run `zb tap unpin`.
+pub fn run_unpin(installer: &Installer, user_repo: &str, json: bool) -> Result<(), zb_core::Error> {
+    let (user, repo) =
+        parse_tap_name(user_repo).map_err(|message| zb_core::Error::StoreCorruption { message })?;
+
+    installer.unpin_tap(user, repo)?;
+
+    emit(
+        &TapChangeOutput {
+            tap: user_repo,
+            action: "unpinned",
+            message: format!("Unpinned {}", style(user_repo).bold()),
+        },
+        json,
     );
 
     Ok(())
@@ -247,6 +365,18 @@ mod tests {
             assert!(result[3].contains("my-user/my-tap"));
         }
 
+        #[test]
+        fn pinned_taps_are_marked() {
+            let taps = vec![
+                format_tap_name("homebrew/cask", false),
+                format_tap_name("user/tools", true),
+            ];
+            let result = format_tap_list(&taps);
+            assert!(result[1].ends_with("homebrew/cask"));
+            assert!(result[2].contains("user/tools"));
+            assert!(result[2].contains("(pinned)"));
+        }
+
         #[test]
         fn taps_are_indented() {
             let taps = vec!["test/tap".to_string()];
@@ -273,4 +403,24 @@ mod tests {
             assert!(err.contains("expected user/repo"));
         }
     }
+
+    #[test]
+    fn test_tap_json() {
+        let tap = InstalledTap {
+            name: "user/tools".to_string(),
+            url: "https://github.com/user/homebrew-tools".to_string(),
+            added_at: 1700000000,
+            pinned: true,
+        };
+
+        assert_eq!(
+            tap_json(&tap),
+            json!({
+                "name": "user/tools",
+                "url": "https://github.com/user/homebrew-tools",
+                "added_at": 1700000000,
+                "pinned": true,
+            })
+        );
+    }
 }
@@ -1,10 +1,16 @@
-//! Update command - self-update zb to the latest release.
+//! Update command - refresh formula metadata, or self-update zb with `--self`.
 
 use console::style;
+use serde_json::{Value, json};
 use std::env;
 use std::fs;
 use std::io::Write;
 
+use zb_io::UpdateSummary;
+use zb_io::install::Installer;
+
+use crate::render::{Render, emit};
+
 const GITHUB_RELEASES_API: &str = "https://api.github.com/repos/carlulsoe/zerobrew/releases/latest";
 
 /// Get the binary name for the current platform.
@@ -225,7 +231,7 @@ async fn download_and_replace(download_url: &str) -> Result<(), zb_core::Error>
     Ok(())
 }
 
-/// Run the update command.
+/// Run `zb update --self`.
 pub async fn run(dry_run: bool, force: bool) -> Result<(), zb_core::Error> {
     println!("{} Checking for updates...", style("==>").cyan().bold());
 
@@ -264,7 +270,7 @@ pub async fn run(dry_run: bool, force: bool) -> Result<(), zb_core::Error> {
         println!(
             "    {} Run {} to install the update",
             style("→").dim(),
-            style("zb update").cyan()
+            style("zb update --self").cyan()
         );
         return Ok(());
     }
@@ -292,9 +298,228 @@ pub async fn run(dry_run: bool, force: bool) -> Result<(), zb_core::Error> {
     Ok(())
 }
 
+/// Formula names listed in a summary line before the rest are counted
+const MAX_LISTED: usize = 10;
+
+/// `names` joined for a summary line, counting the ones past `MAX_LISTED`.
+/// Extracted for testability.
+pub(crate) fn format_names(names: &[String]) -> String {
+    let mut listed: Vec<String> = names.iter().take(MAX_LISTED).cloned().collect();
+    if names.len() > MAX_LISTED {
+        listed.push(format!("and {} more", names.len() - MAX_LISTED));
+    }
+    listed.join(", ")
+}
+
+/// Lines describing what a metadata refresh changed.
+/// Extracted for testability.
+pub(crate) fn format_update_summary(summary: &UpdateSummary) -> Vec<String> {
+    let header = style("==>").cyan().bold();
+    let mut lines = Vec::new();
+
+    if !summary.had_previous {
+        lines.push(format!(
+            "{} Fetched the formula list ({} formulae)",
+            header, summary.total
+        ));
+    } else if summary.added.is_empty() && summary.updated.is_empty() && summary.removed.is_empty() {
+        lines.push(format!(
+            "{} Formula list already up to date ({} formulae)",
+            header, summary.total
+        ));
+    }
+    if !summary.added.is_empty() {
+        lines.push(format!(
+            "{} New formulae ({}): {}",
+            header,
+            summary.added.len(),
+            format_names(&summary.added)
+        ));
+    }
+    if !summary.updated.is_empty() {
+        lines.push(format!(
+            "{} Updated formulae ({}):",
+            header,
+            summary.updated.len()
+        ));
+        for change in &summary.updated {
+            lines.push(format!(
+                "    {} {} → {}",
+                change.name,
+                style(&change.from).yellow(),
+                style(&change.to).green()
+            ));
+        }
+    }
+    if !summary.removed.is_empty() {
+        lines.push(format!(
+            "{} Deleted formulae ({}): {}",
+            header,
+            summary.removed.len(),
+            format_names(&summary.removed)
+        ));
+    }
+
+    for tap in &summary.taps {
+        lines.push(format!(
+            "{} Refreshed {} ({} formulae)",
+            header,
+            style(&tap.tap).bold(),
+            tap.refreshed
+        ));
+        for (name, from, to) in &tap.updated {
+            lines.push(format!(
+                "    {} {} → {}",
+                name,
+                style(from).yellow(),
+                style(to).green()
+            ));
+        }
+        if !tap.removed.is_empty() {
+            lines.push(format!("    deleted: {}", format_names(&tap.removed)));
+        }
+    }
+
+    lines
+}
+
+/// A metadata refresh as JSON.
+/// Extracted for testability.
+pub(crate) fn update_summary_json(summary: &UpdateSummary) -> Value {
+    let change = |name: &str, from: &str, to: &str| json!({ "name": name, "from": from, "to": to });
+    json!({
+        "total": summary.total,
+        "had_previous": summary.had_previous,
+        "added": summary.added,
+        "updated": summary
+            .updated
+            .iter()
+            .map(|c| change(&c.name, &c.from, &c.to))
+            .collect::<Vec<_>>(),
+        "removed": summary.removed,
+        "taps": summary
+            .taps
+            .iter()
+            .map(|tap| json!({
+                "tap": tap.tap,
+                "refreshed": tap.refreshed,
+                "updated": tap
+                    .updated
+                    .iter()
+                    .map(|(name, from, to)| change(name, from, to))
+                    .collect::<Vec<_>>(),
+                "removed": tap.removed,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// What refreshing formula metadata changed
+struct RefreshOutput(UpdateSummary);
+
+impl Render for RefreshOutput {
+    fn render_human(&self) {
+        for line in format_update_summary(&self.0) {
+            println!("{}", line);
+        }
+    }
+
+    fn render_json(&self) -> Value {
+        update_summary_json(&self.0)
+    }
+}
+
+/// Run `zb update`: refresh the formula list and taps.
+pub async fn run_refresh(installer: &Installer, json: bool) -> Result<(), zb_core::Error> {
+    if !json {
+        println!("{} Updating formulae...", style("==>").cyan().bold());
+    }
+    let summary = installer.update().await?;
+    emit(&RefreshOutput(summary), json);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use zb_io::{FormulaChange, TapRefresh};
+
+    fn summary() -> UpdateSummary {
+        UpdateSummary {
+            total: 3,
+            had_previous: true,
+            added: vec!["fresh".to_string()],
+            updated: vec![FormulaChange {
+                name: "wget".to_string(),
+                from: "1.24".to_string(),
+                to: "1.25".to_string(),
+            }],
+            removed: vec!["old".to_string()],
+            taps: vec![TapRefresh {
+                tap: "user/repo".to_string(),
+                refreshed: 2,
+                updated: vec![("tool".to_string(), "1.0".to_string(), "2.0".to_string())],
+                removed: vec!["gone".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_format_names_counts_past_limit() {
+        let names: Vec<String> = (0..12).map(|i| format!("f{i}")).collect();
+        let line = format_names(&names);
+        assert!(line.starts_with("f0, f1"));
+        assert!(line.contains("f9"));
+        assert!(!line.contains("f10"));
+        assert!(line.ends_with("and 2 more"));
+        assert_eq!(format_names(&names[..2]), "f0, f1");
+    }
+
+    #[test]
+    fn test_format_update_summary_lists_changes() {
+        let text = format_update_summary(&summary()).join("\n");
+        assert!(text.contains("New formulae (1): fresh"));
+        assert!(text.contains("Updated formulae (1):"));
+        assert!(text.contains("wget"));
+        assert!(text.contains("1.25"));
+        assert!(text.contains("Deleted formulae (1): old"));
+        assert!(text.contains("user/repo"));
+        assert!(text.contains("deleted: gone"));
+        assert!(!text.contains("already up to date"));
+    }
+
+    #[test]
+    fn test_format_update_summary_without_changes() {
+        let unchanged = UpdateSummary {
+            total: 3,
+            had_previous: true,
+            ..UpdateSummary::default()
+        };
+        let lines = format_update_summary(&unchanged);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains("already up to date (3 formulae)"));
+
+        let first = UpdateSummary {
+            total: 3,
+            ..UpdateSummary::default()
+        };
+        assert!(format_update_summary(&first)[0].contains("Fetched the formula list"));
+    }
+
+    #[test]
+    fn test_update_summary_json() {
+        let value = update_summary_json(&summary());
+        assert_eq!(value["total"], 3);
+        assert_eq!(value["added"], json!(["fresh"]));
+        assert_eq!(
+            value["updated"],
+            json!([{ "name": "wget", "from": "1.24", "to": "1.25" }])
+        );
+        assert_eq!(value["removed"], json!(["old"]));
+        assert_eq!(value["taps"][0]["tap"], "user/repo");
+        assert_eq!(value["taps"][0]["updated"][0]["to"], "2.0");
+        assert_eq!(value["taps"][0]["removed"], json!(["gone"]));
+    }
 
     #[test]
     fn test_get_platform_binary_name() {
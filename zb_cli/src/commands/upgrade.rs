@@ -1,15 +1,24 @@
 //! Upgrade and outdated command implementations.
 
 use console::style;
-use indicatif::MultiProgress;
+use indicatif::{MultiProgress, ProgressBar};
 use std::time::Instant;
 
 use zb_io::install::Installer;
+use zb_io::{
+    OutdatedGroup, RollbackResult, RollbackSource, TapFormula, UpgradeReason, UpgradeStep,
+    UpgradeWindowStatus,
+};
 
-use crate::display::{ProgressStyles, create_progress_callback, finish_progress_bars};
+use crate::display::{ProgressStyles, create_progress_callback, finish_progress_bars, format_age};
+use crate::render::print_json;
 
 /// Run the outdated command.
-pub async fn run_outdated(installer: &mut Installer, json: bool) -> Result<(), zb_core::Error> {
+pub async fn run_outdated(
+    installer: &mut Installer,
+    json: bool,
+    fetch_head: bool,
+) -> Result<(), zb_core::Error> {
     if !json {
         println!(
             "{} Checking for outdated packages...",
@@ -17,7 +26,26 @@ pub async fn run_outdated(installer: &mut Installer, json: bool) -> Result<(), z
         );
     }
 
-    let outdated = installer.get_outdated().await?;
+    let spinner = if json {
+        ProgressBar::hidden()
+    } else {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyles::default().spinner);
+        pb.set_prefix("formulas");
+        pb.enable_steady_tick(std::time::Duration::from_millis(80));
+        pb
+    };
+    let groups = installer
+        .get_outdated_report(false, fetch_head, |done, total| {
+            spinner.set_message(format!("checked {done}/{total}"));
+        })
+        .await;
+    spinner.finish_and_clear();
+    let groups = groups?;
+    let outdated: Vec<_> = groups
+        .iter()
+        .flat_map(|group| group.packages.iter().cloned())
+        .collect();
     let pinned = installer.list_pinned()?;
     let pinned_count = pinned.len();
 
@@ -25,7 +53,7 @@ pub async fn run_outdated(installer: &mut Installer, json: bool) -> Result<(), z
 
     match output_kind {
         OutdatedOutputKind::Json => {
-            let json_output = build_outdated_json(&outdated);
+            let json_output = build_outdated_report_json(&groups);
             match serde_json::to_string_pretty(&json_output) {
                 Ok(json_str) => println!("{}", json_str),
                 Err(e) => {
@@ -56,24 +84,39 @@ pub async fn run_outdated(installer: &mut Installer, json: bool) -> Result<(), z
                     .yellow()
                     .bold()
             );
-            println!();
 
-            let sorted = sort_outdated_packages(outdated);
-            for pkg in &sorted {
-                println!(
-                    "  {} {} → {}",
-                    style(&pkg.name).bold(),
-                    style(&pkg.installed_version).red(),
-                    style(&pkg.available_version).green()
-                );
+            // Only name the sources when packages come from more than one
+            let show_sources = groups.len() > 1;
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            for group in groups.iter().filter(|group| !group.packages.is_empty()) {
+                println!();
+                if show_sources {
+                    println!("  {}", style(format_source_header(group, now)).dim());
+                }
+                for pkg in &sort_outdated_packages(group.packages.clone()) {
+                    println!(
+                        "  {} {} → {}",
+                        style(&pkg.name).bold(),
+                        style(&pkg.installed_version).red(),
+                        style(&pkg.available_version).green()
+                    );
+                }
             }
 
+            let sorted = sort_outdated_packages(outdated);
+
             println!();
             println!(
                 "    {} {}",
                 style("→").cyan(),
                 style(format_upgrade_suggestion()).cyan()
             );
+            if let Some(hint) = format_head_upgrade_hint(&sorted) {
+                println!("    {} {}", style("→").cyan(), style(hint).cyan());
+            }
             if pinned_count > 0 {
                 println!(
                     "    {} {}",
@@ -81,23 +124,125 @@ pub async fn run_outdated(installer: &mut Installer, json: bool) -> Result<(), z
                     format_pinned_footer(pinned_count)
                 );
             }
+            let window = installer.upgrade_window_status()?;
+            if window.is_closed() {
+                println!(
+                    "    {} {}",
+                    style("→").dim(),
+                    format_window_closed_message(window.next_opening().as_deref())
+                );
+            }
         }
     }
 
     Ok(())
 }
 
+/// Note shown while scheduled upgrades wait for a maintenance window.
+/// Extracted for testability.
+pub(crate) fn format_window_closed_message(next: Option<&str>) -> String {
+    match next {
+        Some(next) => format!(
+            "Outside the upgrade window; scheduled upgrades wait until {}",
+            next
+        ),
+        None => "Outside the upgrade window".to_string(),
+    }
+}
+
 /// Run the upgrade command.
+///
+/// Exits with status 2 when one or more packages failed to upgrade, so
+/// unattended jobs can tell a partial failure apart from success.
 pub async fn run_upgrade(
     installer: &mut Installer,
     formula: Option<String>,
     dry_run: bool,
+    fetch_head: bool,
+    greedy: bool,
+    scheduled: bool,
+    json: bool,
 ) -> Result<(), zb_core::Error> {
     let start = Instant::now();
 
-    // Get list of packages to upgrade
-    let outdated = installer.get_outdated().await?;
-    let to_upgrade = filter_outdated_by_name(outdated, formula.as_deref());
+    let window = if scheduled {
+        installer.upgrade_window_status()?
+    } else {
+        UpgradeWindowStatus::Unrestricted
+    };
+    if window.is_closed() {
+        let next = window.next_opening();
+        if json {
+            print_json(&serde_json::json!({
+                "skipped": "outside upgrade window",
+                "next_window": next,
+            }));
+        } else {
+            println!(
+                "{} {}",
+                style("==>").cyan().bold(),
+                format_window_closed_message(next.as_deref())
+            );
+            println!(
+                "    {} Run {} to upgrade anyway",
+                style("→").dim(),
+                style("zb upgrade --now").cyan()
+            );
+        }
+        return Ok(());
+    }
+    // Installed kegs are named without their tap
+    let formula = formula.map(|name| TapFormula::formula_name(&name).to_string());
+
+    // Get list of packages to upgrade, keeping pinned ones aside so they can
+    // be reported as skipped
+    let outdated = if fetch_head {
+        installer.get_outdated_fetch_head_with_pinned(true).await?
+    } else {
+        installer.get_outdated_with_pinned(true).await?
+    };
+    let pinned: Vec<String> = installer
+        .list_pinned()?
+        .into_iter()
+        .map(|keg| keg.name)
+        .collect();
+    let (skipped, candidates): (Vec<_>, Vec<_>) = outdated
+        .into_iter()
+        .partition(|pkg| should_exclude_pinned(&pkg.name, &pinned));
+    let skipped = filter_outdated_by_name(skipped, formula.as_deref());
+
+    // One plan across everything, so shared dependencies go first
+    let to_upgrade = installer
+        .plan_upgrade(candidates, formula.as_deref(), greedy)
+        .await?
+        .steps;
+
+    let mut summary = UpgradeSummary::new();
+    for pkg in &skipped {
+        summary.record_skipped_pinned(
+            pkg.name.clone(),
+            pkg.installed_version.clone(),
+            pkg.available_version.clone(),
+        );
+    }
+
+    if json {
+        if dry_run {
+            print_json(&build_upgrade_dry_run_json(&to_upgrade, &summary));
+            return Ok(());
+        }
+        for step in &to_upgrade {
+            let name = step.package.name.clone();
+            match installer.upgrade_step(step, true, None).await {
+                Ok(Some((old_ver, new_ver))) => summary.record_success(name, old_ver, new_ver),
+                Ok(None) => summary.record_up_to_date(name),
+                Err(e) => summary.record_error(name, &e),
+            }
+        }
+        print_json(&build_upgrade_json(&summary));
+        exit_with_upgrade_status(&summary);
+        return Ok(());
+    }
 
     // Check if formula is installed (for status messages)
     let is_installed = formula
@@ -140,12 +285,14 @@ pub async fn run_upgrade(
                 style(format_dry_run_header(count)).yellow().bold()
             );
             println!();
-            for pkg in &to_upgrade {
+            for step in &to_upgrade {
+                let pkg = &step.package;
                 println!(
-                    "  {} {} → {}",
+                    "  {} {} → {}{}",
                     style(&pkg.name).bold(),
                     style(&pkg.installed_version).red(),
-                    style(&pkg.available_version).green()
+                    style(&pkg.available_version).green(),
+                    style(format_upgrade_reason(&step.reason)).dim()
                 );
             }
             return Ok(());
@@ -164,17 +311,28 @@ pub async fn run_upgrade(
     let (progress_callback, bars) = create_progress_callback(multi, styles, "upgraded");
 
     // Perform the upgrades using UpgradeSummary to track results
-    let mut summary = UpgradeSummary::new();
-    for pkg in &to_upgrade {
+    for step in &to_upgrade {
+        let pkg = &step.package;
         println!();
+        let announcement = match step.reason {
+            UpgradeReason::Rebuild { .. } => {
+                format_rebuild_announcement(&pkg.name, &pkg.installed_version)
+            }
+            _ => format_upgrade_announcement(
+                &pkg.name,
+                &pkg.installed_version,
+                &pkg.available_version,
+            ),
+        };
         println!(
-            "{} {}",
+            "{} {}{}",
             style("==>").cyan().bold(),
-            format_upgrade_announcement(&pkg.name, &pkg.installed_version, &pkg.available_version)
+            announcement,
+            style(format_upgrade_reason(&step.reason)).dim()
         );
 
         match installer
-            .upgrade_one(&pkg.name, true, Some(progress_callback.clone()))
+            .upgrade_step(step, true, Some(progress_callback.clone()))
             .await
         {
             Ok(Some((old_ver, new_ver))) => {
@@ -194,7 +352,7 @@ pub async fn run_upgrade(
                     style("✗").red(),
                     format_upgrade_failure(&pkg.name, &e.to_string())
                 );
-                summary.record_failure(pkg.name.clone(), e.to_string());
+                summary.record_error(pkg.name.clone(), &e);
             }
         }
     }
@@ -228,10 +386,26 @@ pub async fn run_upgrade(
             );
         }
     }
+    if summary.has_failures() {
+        eprintln!(
+            "{} {}",
+            style("error:").red().bold(),
+            format_upgrade_failures_footer(summary.failed_count())
+        );
+    }
 
+    exit_with_upgrade_status(&summary);
     Ok(())
 }
 
+/// Exit with the upgrade exit code when it is non-zero.
+fn exit_with_upgrade_status(summary: &UpgradeSummary) {
+    let code = upgrade_exit_code(summary);
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
 /// Run the pin command.
 pub fn run_pin(installer: &mut Installer, formula: &str) -> Result<(), zb_core::Error> {
     if !is_valid_formula_name(formula) {
@@ -294,6 +468,53 @@ pub fn run_unpin(installer: &mut Installer, formula: &str) -> Result<(), zb_core
     Ok(())
 }
 
+/// Run the rollback command.
+pub fn run_rollback(
+    installer: &mut Installer,
+    formula: &str,
+    no_pin: bool,
+) -> Result<(), zb_core::Error> {
+    let result = installer.rollback(formula, !no_pin)?;
+
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_rollback_summary(formula, &result)
+    );
+    if result.pinned {
+        println!(
+            "    {}",
+            style(format!(
+                "Run 'zb unpin {}' to allow upgrades again",
+                formula
+            ))
+            .dim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Summarize a rollback in one line.
+/// Extracted for testability.
+pub(crate) fn format_rollback_summary(formula: &str, result: &RollbackResult) -> String {
+    let source = match result.source {
+        RollbackSource::Keg => "kept keg",
+        RollbackSource::Store => "store",
+    };
+    let mut line = format!(
+        "Rolled back {} {} -> {} (from {})",
+        formula, result.from_version, result.to_version, source
+    );
+    if result.files_linked > 0 {
+        line.push_str(&format!(", linked {} files", result.files_linked));
+    }
+    if result.pinned {
+        line.push_str(", pinned");
+    }
+    line
+}
+
 /// Filter outdated packages by name.
 /// Returns packages matching the given name, or all packages if name is None.
 /// Extracted for testability.
@@ -335,6 +556,33 @@ pub(crate) fn build_outdated_json(
         .collect()
 }
 
+/// Build JSON output for an outdated report, naming each package's source
+/// and when that source was last updated.
+/// Extracted for testability.
+pub(crate) fn build_outdated_report_json(groups: &[OutdatedGroup]) -> Vec<serde_json::Value> {
+    groups
+        .iter()
+        .flat_map(|group| {
+            build_outdated_json(&group.packages)
+                .into_iter()
+                .map(|mut pkg| {
+                    pkg["source"] = serde_json::json!(group.source);
+                    pkg["source_updated_at"] = serde_json::json!(group.updated_at);
+                    pkg
+                })
+        })
+        .collect()
+}
+
+/// Name an outdated report's source and how fresh its formulas are.
+/// Extracted for testability.
+pub(crate) fn format_source_header(group: &OutdatedGroup, now: i64) -> String {
+    match group.updated_at {
+        Some(at) => format!("{} (updated {})", group.source, format_age(now - at)),
+        None => group.source.clone(),
+    }
+}
+
 /// Format the dry-run header message.
 /// Extracted for testability.
 pub(crate) fn format_dry_run_header(count: usize) -> String {
@@ -365,6 +613,18 @@ pub(crate) fn format_upgrade_suggestion() -> String {
     "Run zb upgrade to upgrade all".to_string()
 }
 
+/// Format the hint for rebuilding outdated HEAD installs, which a plain
+/// `zb upgrade` doesn't check.
+/// Extracted for testability.
+pub(crate) fn format_head_upgrade_hint(
+    outdated: &[zb_core::version::OutdatedPackage],
+) -> Option<String> {
+    outdated
+        .iter()
+        .any(|pkg| pkg.installed_version.starts_with("HEAD"))
+        .then(|| "Run zb upgrade --fetch-HEAD to rebuild HEAD installs".to_string())
+}
+
 /// Format a single upgraded package line.
 /// Extracted for testability.
 pub(crate) fn format_upgraded_package(name: &str, old_version: &str, new_version: &str) -> String {
@@ -439,6 +699,33 @@ pub(crate) fn format_upgrade_announcement(
     format!("Upgrading {} {} → {}...", name, old_version, new_version)
 }
 
+/// Format a rebuild announcement line.
+/// Extracted for testability.
+pub(crate) fn format_rebuild_announcement(name: &str, version: &str) -> String {
+    format!("Rebuilding {} {}...", name, version)
+}
+
+/// Format why a package is part of the upgrade, as a suffix.
+/// Empty for packages that were asked for.
+pub(crate) fn format_upgrade_reason(reason: &UpgradeReason) -> String {
+    match reason {
+        UpgradeReason::Requested => String::new(),
+        UpgradeReason::Dependency { of } => format!(" (dependency of {})", of),
+        UpgradeReason::Dependent { of } => format!(" (depends on {})", of),
+        UpgradeReason::Rebuild { of } => format!(" (built from source against {})", of),
+    }
+}
+
+/// Short name of an upgrade reason for JSON output
+fn upgrade_reason_json(reason: &UpgradeReason) -> serde_json::Value {
+    match reason {
+        UpgradeReason::Requested => serde_json::json!({ "kind": "requested" }),
+        UpgradeReason::Dependency { of } => serde_json::json!({ "kind": "dependency", "of": of }),
+        UpgradeReason::Dependent { of } => serde_json::json!({ "kind": "dependent", "of": of }),
+        UpgradeReason::Rebuild { of } => serde_json::json!({ "kind": "rebuild", "of": of }),
+    }
+}
+
 /// Format upgrade success message for a single package.
 /// Extracted for testability.
 pub(crate) fn format_upgrade_success(name: &str) -> String {
@@ -561,8 +848,11 @@ pub(crate) struct UpgradeSummary {
     pub upgraded: Vec<(String, String, String)>,
     /// Packages that were already up to date
     pub already_up_to_date: Vec<String>,
-    /// Failed upgrades: (name, error_message)
-    pub failed: Vec<(String, String)>,
+    /// Failed upgrades: (name, error_message, error_code)
+    pub failed: Vec<(String, String, &'static str)>,
+    /// Outdated packages left alone because they are pinned:
+    /// (name, installed_version, available_version)
+    pub skipped_pinned: Vec<(String, String, String)>,
 }
 
 impl UpgradeSummary {
@@ -581,9 +871,20 @@ impl UpgradeSummary {
         self.already_up_to_date.push(name);
     }
 
-    /// Record a failed upgrade.
-    pub fn record_failure(&mut self, name: String, error: String) {
-        self.failed.push((name, error));
+    /// Record a failed upgrade along with the error's code.
+    pub fn record_error(&mut self, name: String, error: &zb_core::Error) {
+        self.failed.push((name, error.to_string(), error.code()));
+    }
+
+    /// Record an outdated package that was skipped because it is pinned.
+    pub fn record_skipped_pinned(
+        &mut self,
+        name: String,
+        installed_version: String,
+        available_version: String,
+    ) {
+        self.skipped_pinned
+            .push((name, installed_version, available_version));
     }
 
     /// Get the count of successfully upgraded packages.
@@ -592,7 +893,6 @@ impl UpgradeSummary {
     }
 
     /// Get the count of failed upgrades.
-    #[allow(dead_code)]
     pub fn failed_count(&self) -> usize {
         self.failed.len()
     }
@@ -603,7 +903,6 @@ impl UpgradeSummary {
     }
 
     /// Check if any upgrades failed.
-    #[allow(dead_code)]
     pub fn has_failures(&self) -> bool {
         !self.failed.is_empty()
     }
@@ -645,7 +944,7 @@ pub(crate) fn format_upgrade_summary_output(
             "Failed to upgrade {} packages:",
             summary.failed_count()
         ));
-        for (name, error) in &summary.failed {
+        for (name, error, _) in &summary.failed {
             lines.push(format!("    ✗ {}: {}", name, error));
         }
     }
@@ -653,6 +952,112 @@ pub(crate) fn format_upgrade_summary_output(
     lines
 }
 
+/// Exit code for an upgrade run: 0 when everything succeeded (pinned skips
+/// included), 2 when one or more packages failed to upgrade.
+/// Extracted for testability.
+pub(crate) fn upgrade_exit_code(summary: &UpgradeSummary) -> i32 {
+    if summary.has_failures() { 2 } else { 0 }
+}
+
+/// Format the footer printed when some upgrades failed.
+/// Extracted for testability.
+pub(crate) fn format_upgrade_failures_footer(count: usize) -> String {
+    if count == 1 {
+        "1 package failed to upgrade".to_string()
+    } else {
+        format!("{} packages failed to upgrade", count)
+    }
+}
+
+/// Build the per-package JSON results for `zb upgrade --json`.
+/// Extracted for testability.
+pub(crate) fn build_upgrade_json(summary: &UpgradeSummary) -> serde_json::Value {
+    let mut results = Vec::new();
+    for (name, old_ver, new_ver) in &summary.upgraded {
+        results.push(serde_json::json!({
+            "name": name,
+            "status": "upgraded",
+            "from": old_ver,
+            "to": new_ver,
+        }));
+    }
+    for name in &summary.already_up_to_date {
+        results.push(serde_json::json!({
+            "name": name,
+            "status": "up_to_date",
+        }));
+    }
+    results.extend(skipped_pinned_json(summary));
+    for (name, error, code) in &summary.failed {
+        results.push(serde_json::json!({
+            "name": name,
+            "status": "failed",
+            "error": { "code": code, "message": error },
+        }));
+    }
+
+    serde_json::json!({
+        "dry_run": false,
+        "results": results,
+        "summary": {
+            "upgraded": summary.upgraded.len(),
+            "up_to_date": summary.already_up_to_date.len(),
+            "skipped_pinned": summary.skipped_pinned.len(),
+            "failed": summary.failed.len(),
+        },
+        "exit_code": upgrade_exit_code(summary),
+    })
+}
+
+/// Build the JSON output for `zb upgrade --dry-run --json`.
+/// Extracted for testability.
+pub(crate) fn build_upgrade_dry_run_json(
+    steps: &[UpgradeStep],
+    summary: &UpgradeSummary,
+) -> serde_json::Value {
+    let mut results: Vec<serde_json::Value> = steps
+        .iter()
+        .map(|step| {
+            let status = match step.reason {
+                UpgradeReason::Rebuild { .. } => "would_rebuild",
+                _ => "would_upgrade",
+            };
+            serde_json::json!({
+                "name": step.package.name,
+                "status": status,
+                "from": step.package.installed_version,
+                "to": step.package.available_version,
+                "reason": upgrade_reason_json(&step.reason),
+            })
+        })
+        .collect();
+    results.extend(skipped_pinned_json(summary));
+
+    serde_json::json!({
+        "dry_run": true,
+        "results": results,
+        "summary": {
+            "would_upgrade": steps.len(),
+            "skipped_pinned": summary.skipped_pinned.len(),
+        },
+        "exit_code": 0,
+    })
+}
+
+fn skipped_pinned_json(summary: &UpgradeSummary) -> impl Iterator<Item = serde_json::Value> + '_ {
+    summary
+        .skipped_pinned
+        .iter()
+        .map(|(name, installed, available)| {
+            serde_json::json!({
+                "name": name,
+                "status": "skipped_pinned",
+                "from": installed,
+                "to": available,
+            })
+        })
+}
+
 /// Format the dry-run output lines.
 /// Extracted for testability. Used in tests and available for batch output formatting.
 #[allow(dead_code)]
@@ -670,10 +1075,7 @@ pub(crate) fn format_dry_run_output(packages: &[zb_core::version::OutdatedPackag
 }
 
 /// Check if a package should be excluded from upgrade due to pinning.
-/// In the current implementation, pinned packages are excluded at the Installer level,
-/// but this function documents the logic.
-/// Extracted for testability. Used in tests and available for custom filtering.
-#[allow(dead_code)]
+/// Extracted for testability.
 pub(crate) fn should_exclude_pinned(package_name: &str, pinned_packages: &[String]) -> bool {
     pinned_packages.contains(&package_name.to_string())
 }
@@ -832,6 +1234,18 @@ mod tests {
     // Filter Outdated Tests
     // ========================================================================
 
+    #[test]
+    fn test_format_window_closed_message() {
+        assert_eq!(
+            format_window_closed_message(Some("Sat 02:00")),
+            "Outside the upgrade window; scheduled upgrades wait until Sat 02:00"
+        );
+        assert_eq!(
+            format_window_closed_message(None),
+            "Outside the upgrade window"
+        );
+    }
+
     #[test]
     fn test_filter_outdated_by_name_with_match() {
         let outdated = vec![
@@ -982,6 +1396,45 @@ mod tests {
         assert_eq!(obj.len(), 3);
     }
 
+    #[test]
+    fn test_build_outdated_report_json() {
+        let groups = vec![
+            OutdatedGroup {
+                source: "homebrew/core".to_string(),
+                updated_at: Some(1000),
+                packages: vec![make_outdated_pkg("git", "2.43.0", "2.44.0")],
+            },
+            OutdatedGroup {
+                source: "user/repo".to_string(),
+                updated_at: None,
+                packages: vec![make_outdated_pkg("foo", "1.0", "2.0")],
+            },
+        ];
+        let json = build_outdated_report_json(&groups);
+
+        assert_eq!(json.len(), 2);
+        assert_eq!(json[0]["name"], "git");
+        assert_eq!(json[0]["source"], "homebrew/core");
+        assert_eq!(json[0]["source_updated_at"], 1000);
+        assert_eq!(json[1]["source"], "user/repo");
+        assert!(json[1]["source_updated_at"].is_null());
+    }
+
+    #[test]
+    fn test_format_source_header() {
+        let mut group = OutdatedGroup {
+            source: "user/repo".to_string(),
+            updated_at: Some(1000),
+            packages: vec![],
+        };
+        assert_eq!(
+            format_source_header(&group, 1300),
+            "user/repo (updated 5m ago)"
+        );
+        group.updated_at = None;
+        assert_eq!(format_source_header(&group, 1300), "user/repo");
+    }
+
     // ========================================================================
     // Dry Run Header Tests
     // ========================================================================
@@ -1122,6 +1575,29 @@ mod tests {
     // Pin Status Message Tests
     // ========================================================================
 
+    #[test]
+    fn test_format_rollback_summary() {
+        let mut result = RollbackResult {
+            from_version: "2.0.0".to_string(),
+            to_version: "1.0.0".to_string(),
+            source: RollbackSource::Keg,
+            files_linked: 3,
+            pinned: true,
+        };
+        assert_eq!(
+            format_rollback_summary("jq", &result),
+            "Rolled back jq 2.0.0 -> 1.0.0 (from kept keg), linked 3 files, pinned"
+        );
+
+        result.source = RollbackSource::Store;
+        result.files_linked = 0;
+        result.pinned = false;
+        assert_eq!(
+            format_rollback_summary("jq", &result),
+            "Rolled back jq 2.0.0 -> 1.0.0 (from store)"
+        );
+    }
+
     #[test]
     fn test_format_pin_status_message_pinned() {
         let result = format_pin_status_message("git", true);
@@ -1412,13 +1888,130 @@ mod tests {
     }
 
     #[test]
-    fn test_upgrade_summary_record_failure() {
+    fn test_upgrade_exit_code_is_two_on_partial_failure() {
+        let mut summary = UpgradeSummary::new();
+        summary.record_success("jq".to_string(), "1.6".to_string(), "1.7".to_string());
+        summary.record_skipped_pinned("git".to_string(), "2.40".to_string(), "2.41".to_string());
+        assert_eq!(upgrade_exit_code(&summary), 0);
+
+        summary.record_error(
+            "curl".to_string(),
+            &zb_core::Error::NetworkFailure {
+                message: "timeout".to_string(),
+            },
+        );
+        assert_eq!(upgrade_exit_code(&summary), 2);
+    }
+
+    #[test]
+    fn test_build_upgrade_json_reports_each_package() {
         let mut summary = UpgradeSummary::new();
-        summary.record_failure("git".to_string(), "network error".to_string());
+        summary.record_success("jq".to_string(), "1.6".to_string(), "1.7".to_string());
+        summary.record_skipped_pinned("git".to_string(), "2.40".to_string(), "2.41".to_string());
+        summary.record_error(
+            "curl".to_string(),
+            &zb_core::Error::NetworkFailure {
+                message: "timeout".to_string(),
+            },
+        );
+
+        let json = build_upgrade_json(&summary);
+        let results = json["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0]["status"], "upgraded");
+        assert_eq!(results[0]["to"], "1.7");
+        assert_eq!(results[1]["name"], "git");
+        assert_eq!(results[1]["status"], "skipped_pinned");
+        assert_eq!(results[2]["status"], "failed");
+        assert_eq!(results[2]["error"]["code"], "network_failure");
+        assert_eq!(json["summary"]["failed"], 1);
+        assert_eq!(json["exit_code"], 2);
+    }
+
+    #[test]
+    fn test_build_upgrade_dry_run_json() {
+        let steps = vec![
+            UpgradeStep {
+                package: zb_core::version::OutdatedPackage {
+                    name: "oniguruma".to_string(),
+                    installed_version: "6.9.8".to_string(),
+                    available_version: "6.9.9".to_string(),
+                },
+                reason: UpgradeReason::Dependency {
+                    of: "jq".to_string(),
+                },
+            },
+            UpgradeStep {
+                package: zb_core::version::OutdatedPackage {
+                    name: "jq".to_string(),
+                    installed_version: "1.6".to_string(),
+                    available_version: "1.7".to_string(),
+                },
+                reason: UpgradeReason::Requested,
+            },
+        ];
+        let mut summary = UpgradeSummary::new();
+        summary.record_skipped_pinned("git".to_string(), "2.40".to_string(), "2.41".to_string());
+
+        let json = build_upgrade_dry_run_json(&steps, &summary);
+        assert_eq!(json["dry_run"], true);
+        assert_eq!(json["results"][0]["status"], "would_upgrade");
+        assert_eq!(json["results"][0]["reason"]["kind"], "dependency");
+        assert_eq!(json["results"][0]["reason"]["of"], "jq");
+        assert_eq!(json["results"][1]["reason"]["kind"], "requested");
+        assert_eq!(json["results"][2]["status"], "skipped_pinned");
+        assert_eq!(json["summary"]["would_upgrade"], 2);
+    }
+
+    #[test]
+    fn test_format_upgrade_reason() {
+        assert_eq!(format_upgrade_reason(&UpgradeReason::Requested), "");
+        assert_eq!(
+            format_upgrade_reason(&UpgradeReason::Dependency {
+                of: "git".to_string()
+            }),
+            " (dependency of git)"
+        );
+        assert_eq!(
+            format_upgrade_reason(&UpgradeReason::Rebuild {
+                of: "openssl@3".to_string()
+            }),
+            " (built from source against openssl@3)"
+        );
+        assert_eq!(
+            format_rebuild_announcement("curl", "8.9.0"),
+            "Rebuilding curl 8.9.0..."
+        );
+    }
+
+    #[test]
+    fn test_format_upgrade_failures_footer() {
+        assert_eq!(
+            format_upgrade_failures_footer(1),
+            "1 package failed to upgrade"
+        );
+        assert_eq!(
+            format_upgrade_failures_footer(3),
+            "3 packages failed to upgrade"
+        );
+    }
+
+    fn network_failure(message: &str) -> zb_core::Error {
+        zb_core::Error::NetworkFailure {
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_upgrade_summary_record_error() {
+        let mut summary = UpgradeSummary::new();
+        let error = network_failure("network error");
+        summary.record_error("git".to_string(), &error);
 
         assert_eq!(summary.failed.len(), 1);
         assert_eq!(summary.failed[0].0, "git");
-        assert_eq!(summary.failed[0].1, "network error");
+        assert_eq!(summary.failed[0].1, error.to_string());
+        assert_eq!(summary.failed[0].2, "network_failure");
     }
 
     #[test]
@@ -1441,8 +2034,8 @@ mod tests {
     #[test]
     fn test_upgrade_summary_failed_count() {
         let mut summary = UpgradeSummary::new();
-        summary.record_failure("git".to_string(), "error1".to_string());
-        summary.record_failure("ripgrep".to_string(), "error2".to_string());
+        summary.record_error("git".to_string(), &network_failure("error1"));
+        summary.record_error("ripgrep".to_string(), &network_failure("error2"));
 
         assert_eq!(summary.failed_count(), 2);
     }
@@ -1465,7 +2058,7 @@ mod tests {
         let mut summary = UpgradeSummary::new();
         assert!(!summary.has_failures());
 
-        summary.record_failure("git".to_string(), "error".to_string());
+        summary.record_error("git".to_string(), &network_failure("error"));
         assert!(summary.has_failures());
     }
 
@@ -1478,7 +2071,7 @@ mod tests {
             "2.44.0".to_string(),
         );
         summary.record_up_to_date("ripgrep".to_string());
-        summary.record_failure("jq".to_string(), "error".to_string());
+        summary.record_error("jq".to_string(), &network_failure("error"));
 
         assert_eq!(summary.total_attempted(), 3);
     }
@@ -1497,7 +2090,7 @@ mod tests {
             "14.1.0".to_string(),
         );
         summary.record_up_to_date("jq".to_string());
-        summary.record_failure("curl".to_string(), "checksum mismatch".to_string());
+        summary.record_error("curl".to_string(), &network_failure("checksum mismatch"));
 
         assert_eq!(summary.upgraded_count(), 2);
         assert_eq!(summary.failed_count(), 1);
@@ -1550,7 +2143,7 @@ mod tests {
             "2.43.0".to_string(),
             "2.44.0".to_string(),
         );
-        summary.record_failure("ripgrep".to_string(), "network error".to_string());
+        summary.record_error("ripgrep".to_string(), &network_failure("network error"));
 
         let lines = format_upgrade_summary_output(&summary, 3.0);
 
@@ -1566,8 +2159,8 @@ mod tests {
     #[test]
     fn test_format_upgrade_summary_output_only_failures() {
         let mut summary = UpgradeSummary::new();
-        summary.record_failure("git".to_string(), "error1".to_string());
-        summary.record_failure("ripgrep".to_string(), "error2".to_string());
+        summary.record_error("git".to_string(), &network_failure("error1"));
+        summary.record_error("ripgrep".to_string(), &network_failure("error2"));
 
         let lines = format_upgrade_summary_output(&summary, 2.0);
 
@@ -2015,4 +2608,25 @@ mod tests {
         // Unpin message should indicate upgrades will happen
         assert!(unpin_msg.contains("will be upgraded when outdated"));
     }
+
+    // ========================================================================
+    // HEAD Upgrade Hint Tests
+    // ========================================================================
+
+    #[test]
+    fn test_format_head_upgrade_hint() {
+        assert_eq!(
+            format_head_upgrade_hint(&[make_outdated_pkg("jq", "1.6", "1.7")]),
+            None
+        );
+        assert_eq!(
+            format_head_upgrade_hint(&[
+                make_outdated_pkg("jq", "1.6", "1.7"),
+                make_outdated_pkg("neovim", "HEAD-1a2b3c4", "HEAD-5d6e7f8"),
+            ])
+            .as_deref(),
+            Some("Run zb upgrade --fetch-HEAD to rebuild HEAD installs")
+        );
+        assert_eq!(format_head_upgrade_hint(&[]), None);
+    }
 }
@@ -0,0 +1,201 @@
+//! Verify command implementation.
+
+use console::style;
+
+use zb_io::install::Installer;
+use zb_io::{Attestation, AttestationStatus, KegVerification, ManifestMismatch};
+
+use crate::render::print_json;
+
+/// Mismatched files listed per keg before the rest are summarized
+const MAX_LISTED_MISMATCHES: usize = 5;
+
+/// Re-check installed kegs against their store entries' recorded hashes,
+/// exiting non-zero if any differ or their attestation failed.
+pub fn run(installer: &Installer, formulas: &[String], json: bool) -> Result<(), zb_core::Error> {
+    let names: Vec<String> = if formulas.is_empty() {
+        installer
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect()
+    } else {
+        formulas.to_vec()
+    };
+
+    let verifications = names
+        .iter()
+        .map(|name| installer.verify_installed(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if json {
+        print_json(&serde_json::Value::Array(
+            verifications.iter().map(verification_to_json).collect(),
+        ));
+    } else {
+        if verifications.is_empty() {
+            println!("No formulas installed.");
+        }
+        for verification in &verifications {
+            let marker = if verification.is_ok() {
+                style("✓").green()
+            } else {
+                style("✗").red()
+            };
+            println!(
+                "{} {} {}",
+                marker,
+                style(&verification.name).bold(),
+                style(&verification.version).dim()
+            );
+            for line in format_mismatch_lines(&verification.mismatches) {
+                println!("    {}", line);
+            }
+            println!(
+                "    {}",
+                style(format_attestation(verification.attestation.as_ref())).dim()
+            );
+        }
+    }
+
+    if !verifications.iter().all(KegVerification::is_ok) {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// List a keg's mismatched files, summarizing past the first few.
+/// Extracted for testability.
+pub(crate) fn format_mismatch_lines(mismatches: &[ManifestMismatch]) -> Vec<String> {
+    let mut lines: Vec<String> = mismatches
+        .iter()
+        .take(MAX_LISTED_MISMATCHES)
+        .map(|mismatch| match mismatch {
+            ManifestMismatch::Missing(path) => format!("missing: {}", path),
+            ManifestMismatch::Modified(path) => format!("modified: {}", path),
+        })
+        .collect();
+    if mismatches.len() > MAX_LISTED_MISMATCHES {
+        lines.push(format!(
+            "and {} more",
+            mismatches.len() - MAX_LISTED_MISMATCHES
+        ));
+    }
+    lines
+}
+
+/// Describe the attestation recorded for a keg's bottle.
+/// Extracted for testability.
+pub(crate) fn format_attestation(attestation: Option<&Attestation>) -> String {
+    let Some(attestation) = attestation else {
+        return "attestation not checked (install with --verify to check it)".to_string();
+    };
+    match (attestation.status, &attestation.repo, &attestation.detail) {
+        (AttestationStatus::Verified, Some(repo), _) => {
+            format!("attestation verified for {}", repo)
+        }
+        (status, _, Some(detail)) => format!("attestation {}: {}", status, detail),
+        (status, _, None) => format!("attestation {}", status),
+    }
+}
+
+/// Build the JSON object for one verified keg.
+/// Extracted for testability.
+pub(crate) fn verification_to_json(verification: &KegVerification) -> serde_json::Value {
+    let mismatches: Vec<serde_json::Value> = verification
+        .mismatches
+        .iter()
+        .map(|mismatch| {
+            let kind = match mismatch {
+                ManifestMismatch::Missing(_) => "missing",
+                ManifestMismatch::Modified(_) => "modified",
+            };
+            serde_json::json!({ "path": mismatch.path(), "kind": kind })
+        })
+        .collect();
+
+    serde_json::json!({
+        "name": verification.name,
+        "version": verification.version,
+        "store_key": verification.store_key,
+        "ok": verification.is_ok(),
+        "mismatches": mismatches,
+        "attestation": verification.attestation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attestation(status: AttestationStatus, detail: Option<&str>) -> Attestation {
+        Attestation {
+            status,
+            repo: Some("Homebrew/homebrew-core".to_string()),
+            detail: detail.map(str::to_string),
+            checked_at: 1000,
+        }
+    }
+
+    #[test]
+    fn test_format_mismatch_lines() {
+        let mismatches = vec![
+            ManifestMismatch::Modified("bin/jq".to_string()),
+            ManifestMismatch::Missing("share/man/man1/jq.1".to_string()),
+        ];
+        assert_eq!(
+            format_mismatch_lines(&mismatches),
+            ["modified: bin/jq", "missing: share/man/man1/jq.1"]
+        );
+
+        let many: Vec<ManifestMismatch> = (0..8)
+            .map(|i| ManifestMismatch::Modified(format!("lib/{i}.so")))
+            .collect();
+        let lines = format_mismatch_lines(&many);
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[5], "and 3 more");
+    }
+
+    #[test]
+    fn test_format_attestation() {
+        assert_eq!(
+            format_attestation(None),
+            "attestation not checked (install with --verify to check it)"
+        );
+        assert_eq!(
+            format_attestation(Some(&attestation(AttestationStatus::Verified, None))),
+            "attestation verified for Homebrew/homebrew-core"
+        );
+        assert_eq!(
+            format_attestation(Some(&attestation(
+                AttestationStatus::Failed,
+                Some("no attestations found")
+            ))),
+            "attestation failed: no attestations found"
+        );
+        assert_eq!(
+            format_attestation(Some(&attestation(AttestationStatus::Unavailable, None))),
+            "attestation unavailable"
+        );
+    }
+
+    #[test]
+    fn test_verification_to_json() {
+        let verification = KegVerification {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            store_key: "abc".to_string(),
+            mismatches: vec![ManifestMismatch::Missing("bin/jq".to_string())],
+            attestation: Some(attestation(AttestationStatus::Verified, None)),
+        };
+        let json = verification_to_json(&verification);
+
+        assert_eq!(json["name"], "jq");
+        assert_eq!(json["ok"], false);
+        assert_eq!(json["mismatches"][0]["path"], "bin/jq");
+        assert_eq!(json["mismatches"][0]["kind"], "missing");
+        assert_eq!(json["attestation"]["status"], "verified");
+        assert_eq!(json["attestation"]["checked_at"], 1000);
+    }
+}
@@ -0,0 +1,170 @@
+//! Versions command implementation.
+
+use console::style;
+
+use zb_io::FormulaVersions;
+use zb_io::install::Installer;
+
+/// Run the versions command.
+pub async fn run(installer: &Installer, formula: &str, json: bool) -> Result<(), zb_core::Error> {
+    let versions = installer.formula_versions(formula).await?;
+
+    if json {
+        match serde_json::to_string_pretty(&versions_to_json(&versions)) {
+            Ok(s) => println!("{}", s),
+            Err(e) => {
+                return Err(zb_core::Error::StoreCorruption {
+                    message: format!("failed to serialize versions: {}", e),
+                });
+            }
+        }
+        return Ok(());
+    }
+
+    println!("{} {}", style("==>").cyan().bold(), style(formula).bold());
+    for line in format_versions_lines(&versions) {
+        println!("{}", line);
+    }
+
+    if !versions.versioned_formulas.is_empty() {
+        println!();
+        println!("{} Versioned formulas:", style("==>").cyan().bold());
+        for line in format_versioned_formula_lines(&versions.versioned_formulas) {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// One line per known version, newest first, saying where it comes from.
+/// Extracted for testability.
+pub(crate) fn format_versions_lines(versions: &FormulaVersions) -> Vec<String> {
+    let mut rows: Vec<(String, String)> = Vec::new();
+
+    if let Some(available) = &versions.available
+        && !versions.installed.contains(available)
+    {
+        rows.push((available.clone(), "available".to_string()));
+    }
+    for version in versions.installed.iter().rev() {
+        let mut source = "installed".to_string();
+        if versions.active.as_ref() == Some(version) {
+            source.push_str(", active");
+        }
+        if versions.available.as_ref() == Some(version) {
+            source.push_str(", latest");
+        }
+        rows.push((version.clone(), source));
+    }
+    for version in versions.in_store.iter().rev() {
+        rows.push((version.clone(), "in store (removed by zb gc)".to_string()));
+    }
+
+    let width = rows.iter().map(|(v, _)| v.len()).max().unwrap_or(0);
+    rows.into_iter()
+        .map(|(version, source)| format!("  {:width$}  {}", version, source, width = width))
+        .collect()
+}
+
+/// Lines for versioned formulas and their current versions.
+/// Extracted for testability.
+pub(crate) fn format_versioned_formula_lines(
+    versioned: &[(String, Option<String>)],
+) -> Vec<String> {
+    let width = versioned.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+    versioned
+        .iter()
+        .map(|(name, version)| {
+            format!(
+                "  {:width$}  {}",
+                name,
+                version.as_deref().unwrap_or("unknown"),
+                width = width
+            )
+        })
+        .collect()
+}
+
+/// Build the `--json` document.
+/// Extracted for testability.
+pub(crate) fn versions_to_json(versions: &FormulaVersions) -> serde_json::Value {
+    serde_json::json!({
+        "name": versions.name,
+        "available": versions.available,
+        "installed": versions.installed,
+        "active": versions.active,
+        "in_store": versions.in_store,
+        "versioned_formulas": versions
+            .versioned_formulas
+            .iter()
+            .map(|(name, version)| serde_json::json!({"name": name, "version": version}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> FormulaVersions {
+        FormulaVersions {
+            name: "node".to_string(),
+            available: Some("22.2.0".to_string()),
+            versioned_formulas: vec![
+                ("node@20".to_string(), Some("20.14.0".to_string())),
+                ("node@18".to_string(), None),
+            ],
+            installed: vec!["21.7.3".to_string(), "22.1.0".to_string()],
+            active: Some("22.1.0".to_string()),
+            in_store: vec!["20.11.1".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_format_versions_lines_newest_first() {
+        assert_eq!(
+            format_versions_lines(&sample()),
+            vec![
+                "  22.2.0   available",
+                "  22.1.0   installed, active",
+                "  21.7.3   installed",
+                "  20.11.1  in store (removed by zb gc)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_versions_lines_latest_installed() {
+        let versions = FormulaVersions {
+            name: "jq".to_string(),
+            available: Some("1.7.1".to_string()),
+            installed: vec!["1.7.1".to_string()],
+            active: Some("1.7.1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_versions_lines(&versions),
+            vec!["  1.7.1  installed, active, latest"]
+        );
+    }
+
+    #[test]
+    fn test_format_versioned_formula_lines() {
+        assert_eq!(
+            format_versioned_formula_lines(&sample().versioned_formulas),
+            vec!["  node@20  20.14.0", "  node@18  unknown"]
+        );
+    }
+
+    #[test]
+    fn test_versions_to_json() {
+        let json = versions_to_json(&sample());
+        assert_eq!(json["available"], "22.2.0");
+        assert_eq!(json["installed"], serde_json::json!(["21.7.3", "22.1.0"]));
+        assert_eq!(json["active"], "22.1.0");
+        assert_eq!(json["in_store"], serde_json::json!(["20.11.1"]));
+        assert_eq!(json["versioned_formulas"][0]["name"], "node@20");
+        assert!(json["versioned_formulas"][1]["version"].is_null());
+    }
+}
@@ -0,0 +1,236 @@
+//! Which command implementation and PATH shadowing warnings.
+
+use console::style;
+use std::path::Path;
+
+use zb_io::install::Installer;
+use zb_io::{CommandResolution, ShadowedCommand};
+
+/// Makes install and link fail when a linked executable is shadowed
+const BLOCK_SHADOWED_ENV: &str = "ZB_BLOCK_SHADOWED";
+
+/// Run the which command.
+pub fn run(installer: &Installer, command: &str) -> Result<(), zb_core::Error> {
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    let resolution = installer.which(command, &path_var);
+
+    if resolution.candidates.is_empty() && resolution.linked.is_none() {
+        eprintln!(
+            "{} {} was not found in PATH",
+            style("error:").red().bold(),
+            command
+        );
+        std::process::exit(1);
+    }
+
+    for line in format_resolution_lines(&resolution) {
+        println!("{}", line);
+    }
+
+    if let Some(note) = format_resolution_note(&resolution) {
+        println!();
+        println!("{} {}", style("Note:").yellow().bold(), note);
+    }
+
+    Ok(())
+}
+
+/// Warn about executables linked for `formulas` that an earlier PATH entry
+/// hides. With `ZB_BLOCK_SHADOWED` set, shadowing is an error and the
+/// command exits non-zero once everything has been reported.
+pub fn warn_shadowed(installer: &Installer, prefix: &Path, formulas: &[String]) {
+    let path_var = std::env::var("PATH").unwrap_or_default();
+    let mut shadowed = Vec::new();
+    for formula in formulas {
+        if let Ok(found) = installer.shadowed_commands(Some(formula), &path_var) {
+            shadowed.extend(found);
+        }
+    }
+    if shadowed.is_empty() {
+        return;
+    }
+
+    let block = is_block_enabled(std::env::var(BLOCK_SHADOWED_ENV).ok().as_deref());
+    let label = if block {
+        style("error:").red().bold()
+    } else {
+        style("Warning:").yellow().bold()
+    };
+
+    eprintln!();
+    for command in &shadowed {
+        eprintln!("{} {}", label, format_shadow_warning(command));
+    }
+    eprintln!(
+        "    {} {}",
+        style("→").dim(),
+        format_shadow_hint(&prefix.join("bin"))
+    );
+
+    if block {
+        std::process::exit(1);
+    }
+}
+
+/// Whether an environment value turns shadowing warnings into errors.
+/// Extracted for testability.
+pub(crate) fn is_block_enabled(value: Option<&str>) -> bool {
+    matches!(
+        value.map(|v| v.trim().to_ascii_lowercase()).as_deref(),
+        Some("1" | "true" | "yes")
+    )
+}
+
+/// Extracted for testability.
+pub(crate) fn format_shadow_warning(command: &ShadowedCommand) -> String {
+    format!(
+        "{} runs {} instead of {}",
+        command.name,
+        command.shadowed_by.display(),
+        command.linked.display()
+    )
+}
+
+/// Extracted for testability.
+pub(crate) fn format_shadow_hint(bin_dir: &Path) -> String {
+    format!(
+        "put {} earlier in PATH; see resolution order with: zb which <command>",
+        bin_dir.display()
+    )
+}
+
+/// One line per executable in PATH order, marking the one the shell runs
+/// and the one zerobrew linked.
+/// Extracted for testability.
+pub(crate) fn format_resolution_lines(resolution: &CommandResolution) -> Vec<String> {
+    resolution
+        .candidates
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let marker = if i == 0 { "→" } else { " " };
+            let mut line = format!("{} {}", marker, path.display());
+            if resolution.linked.as_ref() == Some(path) {
+                match &resolution.formula {
+                    Some(formula) => line.push_str(&format!(" (zerobrew: {})", formula)),
+                    None => line.push_str(" (zerobrew)"),
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// Explain why the zerobrew executable isn't the one that runs, if it isn't.
+/// Extracted for testability.
+pub(crate) fn format_resolution_note(resolution: &CommandResolution) -> Option<String> {
+    let linked = resolution.linked.as_ref()?;
+    let bin_dir = linked.parent()?;
+    if !resolution.prefix_bin_in_path {
+        return Some(format!(
+            "{} is not in PATH, so {} is never used",
+            bin_dir.display(),
+            linked.display()
+        ));
+    }
+    if resolution.is_shadowed() {
+        return Some(format!(
+            "{} is shadowed; {}",
+            linked.display(),
+            format_shadow_hint(bin_dir)
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn resolution(candidates: &[&str], linked: Option<&str>, in_path: bool) -> CommandResolution {
+        CommandResolution {
+            name: "python3".to_string(),
+            candidates: candidates.iter().map(PathBuf::from).collect(),
+            linked: linked.map(PathBuf::from),
+            formula: linked.map(|_| "python@3.12".to_string()),
+            prefix_bin_in_path: in_path,
+        }
+    }
+
+    #[test]
+    fn test_is_block_enabled() {
+        assert!(is_block_enabled(Some("1")));
+        assert!(is_block_enabled(Some("TRUE")));
+        assert!(is_block_enabled(Some(" yes ")));
+        assert!(!is_block_enabled(Some("0")));
+        assert!(!is_block_enabled(Some("")));
+        assert!(!is_block_enabled(None));
+    }
+
+    #[test]
+    fn test_format_shadow_warning() {
+        let command = ShadowedCommand {
+            name: "python3".to_string(),
+            linked: PathBuf::from("/opt/zerobrew/prefix/bin/python3"),
+            shadowed_by: PathBuf::from("/usr/bin/python3"),
+        };
+        assert_eq!(
+            format_shadow_warning(&command),
+            "python3 runs /usr/bin/python3 instead of /opt/zerobrew/prefix/bin/python3"
+        );
+    }
+
+    #[test]
+    fn test_format_resolution_lines_marks_winner_and_zerobrew() {
+        let res = resolution(
+            &["/usr/bin/python3", "/opt/zerobrew/prefix/bin/python3"],
+            Some("/opt/zerobrew/prefix/bin/python3"),
+            true,
+        );
+        assert_eq!(
+            format_resolution_lines(&res),
+            vec![
+                "→ /usr/bin/python3".to_string(),
+                "  /opt/zerobrew/prefix/bin/python3 (zerobrew: python@3.12)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_resolution_note() {
+        // Resolution only compares real directories, so these paths fall back
+        // to a plain comparison
+        let shadowed = resolution(
+            &["/usr/bin/python3", "/opt/zerobrew/prefix/bin/python3"],
+            Some("/opt/zerobrew/prefix/bin/python3"),
+            true,
+        );
+        assert!(
+            format_resolution_note(&shadowed)
+                .unwrap()
+                .contains("is shadowed")
+        );
+
+        let winner = resolution(
+            &["/opt/zerobrew/prefix/bin/python3", "/usr/bin/python3"],
+            Some("/opt/zerobrew/prefix/bin/python3"),
+            true,
+        );
+        assert!(format_resolution_note(&winner).is_none());
+
+        let not_in_path = resolution(
+            &["/usr/bin/python3"],
+            Some("/opt/zerobrew/prefix/bin/python3"),
+            false,
+        );
+        assert!(
+            format_resolution_note(&not_in_path)
+                .unwrap()
+                .contains("is not in PATH")
+        );
+
+        let system_only = resolution(&["/usr/bin/python3"], None, false);
+        assert!(format_resolution_note(&system_only).is_none());
+    }
+}
@@ -5,7 +5,7 @@ use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use zb_io::{DepsTree, InstallProgress, ProgressCallback};
+use zb_io::{DepAnnotation, DepsTree, InstallProgress, ProgressCallback};
 
 /// Create progress styles used by install/upgrade operations.
 pub struct ProgressStyles {
@@ -157,6 +157,16 @@ pub fn chrono_lite_format(timestamp: i64) -> String {
     format!("{:?}", dt)
 }
 
+/// How long ago something happened, e.g. "5m ago".
+pub fn format_age(secs: i64) -> String {
+    match secs.max(0) {
+        s if s < 60 => format!("{}s ago", s),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
+
 /// Calculate the tree connector string based on position.
 pub fn tree_connector(prefix: &str, is_last: bool) -> &'static str {
     if prefix.is_empty() {
@@ -195,11 +205,42 @@ pub fn format_tree_line(name: &str, installed: bool, prefix: &str, is_last: bool
     format!("{}{}{} {}", prefix, connector, marker, name)
 }
 
+/// Format the versions and keg state shown after a formula by
+/// `zb deps --annotate`, e.g. `1.7.1 → 1.7.2 [pinned, keg-only]`.
+pub fn format_dep_annotation(annotation: &DepAnnotation) -> String {
+    let mut parts = Vec::new();
+    match (&annotation.installed_version, &annotation.available_version) {
+        (Some(installed), Some(available)) if installed != available => {
+            parts.push(format!("{} → {}", installed, available))
+        }
+        (Some(installed), _) => parts.push(installed.clone()),
+        (None, Some(available)) => parts.push(format!("({} available)", available)),
+        (None, None) => {}
+    }
+
+    let mut flags = Vec::new();
+    if annotation.pinned {
+        flags.push("pinned");
+    }
+    if annotation.keg_only {
+        flags.push("keg-only");
+    }
+    if !flags.is_empty() {
+        parts.push(format!("[{}]", flags.join(", ")));
+    }
+    parts.join(" ")
+}
+
 /// Format a dependency tree as a string (for testing).
 /// Returns a vector of lines representing the tree.
-pub fn format_deps_tree_lines(tree: &DepsTree, prefix: &str, is_last: bool) -> Vec<String> {
+pub fn format_deps_tree_lines(
+    tree: &DepsTree,
+    prefix: &str,
+    is_last: bool,
+    annotate: bool,
+) -> Vec<String> {
     let mut lines = Vec::new();
-    format_deps_tree_recursive(tree, prefix, is_last, &mut lines);
+    format_deps_tree_recursive(tree, prefix, is_last, annotate, &mut lines);
     lines
 }
 
@@ -207,26 +248,27 @@ fn format_deps_tree_recursive(
     tree: &DepsTree,
     prefix: &str,
     is_last: bool,
+    annotate: bool,
     lines: &mut Vec<String>,
 ) {
-    lines.push(format_tree_line(
-        &tree.name,
-        tree.installed,
-        prefix,
-        is_last,
-    ));
+    let mut line = format_tree_line(&tree.name, tree.installed, prefix, is_last);
+    let annotation = format_dep_annotation(&tree.annotation);
+    if annotate && !annotation.is_empty() {
+        line.push_str(&format!(" {}", style(annotation).dim()));
+    }
+    lines.push(line);
 
     let new_prefix = tree_child_prefix(prefix, is_last);
 
     for (i, child) in tree.children.iter().enumerate() {
         let is_last_child = i == tree.children.len() - 1;
-        format_deps_tree_recursive(child, &new_prefix, is_last_child, lines);
+        format_deps_tree_recursive(child, &new_prefix, is_last_child, annotate, lines);
     }
 }
 
 /// Print a dependency tree with ASCII art formatting.
-pub fn print_deps_tree(tree: &DepsTree, prefix: &str, is_last: bool) {
-    for line in format_deps_tree_lines(tree, prefix, is_last) {
+pub fn print_deps_tree(tree: &DepsTree, prefix: &str, is_last: bool, annotate: bool) {
+    for line in format_deps_tree_lines(tree, prefix, is_last, annotate) {
         println!("{}", line);
     }
 }
@@ -252,6 +294,10 @@ pub fn generate_shellenv(prefix: &std::path::Path, shell: &str) -> String {
     let man_path = prefix.join("share").join("man");
     let info_path = prefix.join("share").join("info");
     let cellar_path = prefix.join("Cellar");
+    let share_path = prefix.join("share");
+    let zsh_completions = share_path.join("zsh").join("site-functions");
+    let fish_completions = share_path.join("fish").join("vendor_completions.d");
+    let bash_compat_completions = prefix.join("etc").join("bash_completion.d");
 
     match shell {
         "fish" => {
@@ -260,13 +306,15 @@ pub fn generate_shellenv(prefix: &std::path::Path, shell: &str) -> String {
 set -gx HOMEBREW_CELLAR "{}";
 set -gx PATH "{}" "{}" $PATH;
 set -q MANPATH; or set MANPATH ''; set -gx MANPATH "{}" $MANPATH;
-set -q INFOPATH; or set INFOPATH ''; set -gx INFOPATH "{}" $INFOPATH;"#,
+set -q INFOPATH; or set INFOPATH ''; set -gx INFOPATH "{}" $INFOPATH;
+set -g fish_complete_path "{}" $fish_complete_path;"#,
                 prefix.display(),
                 cellar_path.display(),
                 bin_path.display(),
                 sbin_path.display(),
                 man_path.display(),
-                info_path.display()
+                info_path.display(),
+                fish_completions.display()
             )
         }
         "csh" | "tcsh" => {
@@ -285,18 +333,32 @@ setenv INFOPATH "{}:${{INFOPATH}}";"#,
             )
         }
         _ => {
+            let completions = if shell == "zsh" {
+                format!(r#"fpath=("{}" $fpath);"#, zsh_completions.display())
+            } else {
+                // bash-completion looks for completions under $XDG_DATA_DIRS
+                // and sources the compat dir for older-style completion files
+                format!(
+                    r#"export XDG_DATA_DIRS="{}:${{XDG_DATA_DIRS:-/usr/local/share:/usr/share}}";
+export BASH_COMPLETION_COMPAT_DIR="{}";"#,
+                    share_path.display(),
+                    bash_compat_completions.display()
+                )
+            };
             format!(
                 r#"export HOMEBREW_PREFIX="{}";
 export HOMEBREW_CELLAR="{}";
 export PATH="{}:{}:$PATH";
 export MANPATH="{}:${{MANPATH:-}}";
-export INFOPATH="{}:${{INFOPATH:-}}";"#,
+export INFOPATH="{}:${{INFOPATH:-}}";
+{}"#,
                 prefix.display(),
                 cellar_path.display(),
                 bin_path.display(),
                 sbin_path.display(),
                 man_path.display(),
-                info_path.display()
+                info_path.display(),
+                completions
             )
         }
     }
@@ -358,6 +420,24 @@ mod tests {
 
         assert!(output.contains("export HOMEBREW_PREFIX="));
         assert!(output.contains("export PATH="));
+        assert!(
+            output.contains("fpath=(\"/opt/zerobrew/prefix/share/zsh/site-functions\" $fpath);")
+        );
+        assert!(!output.contains("XDG_DATA_DIRS"));
+    }
+
+    #[test]
+    fn test_generate_shellenv_bash_completions() {
+        let prefix = PathBuf::from("/opt/zerobrew/prefix");
+        let output = generate_shellenv(&prefix, "bash");
+
+        assert!(output.contains(
+            "export XDG_DATA_DIRS=\"/opt/zerobrew/prefix/share:${XDG_DATA_DIRS:-/usr/local/share:/usr/share}\""
+        ));
+        assert!(output.contains(
+            "export BASH_COMPLETION_COMPAT_DIR=\"/opt/zerobrew/prefix/etc/bash_completion.d\""
+        ));
+        assert!(!output.contains("fpath"));
     }
 
     #[test]
@@ -372,6 +452,9 @@ mod tests {
         ));
         assert!(output.contains("set -q MANPATH; or set MANPATH ''"));
         assert!(output.contains("set -q INFOPATH; or set INFOPATH ''"));
+        assert!(output.contains(
+            "set -g fish_complete_path \"/opt/zerobrew/prefix/share/fish/vendor_completions.d\" $fish_complete_path"
+        ));
     }
 
     #[test]
@@ -447,6 +530,15 @@ mod tests {
         assert_eq!(format_bytes(2 * 1024 * 1024 * 1024), "2.0 GB");
     }
 
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(42), "42s ago");
+        assert_eq!(format_age(300), "5m ago");
+        assert_eq!(format_age(7200), "2h ago");
+        assert_eq!(format_age(3 * 86400), "3d ago");
+        assert_eq!(format_age(-5), "0s ago");
+    }
+
     // ========================================================================
     // Tree Formatting Tests
     // ========================================================================
@@ -526,9 +618,10 @@ mod tests {
         let tree = DepsTree {
             name: "ripgrep".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 1);
         assert!(lines[0].contains("ripgrep"));
         assert!(lines[0].contains("✓"));
@@ -539,20 +632,23 @@ mod tests {
         let tree = DepsTree {
             name: "neovim".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![
                 DepsTree {
                     name: "luajit".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "libuv".to_string(),
                     installed: false,
+                    annotation: Default::default(),
                     children: vec![],
                 },
             ],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 3);
         assert!(lines[0].contains("neovim"));
         assert!(lines[1].contains("luajit"));
@@ -568,17 +664,20 @@ mod tests {
         let tree = DepsTree {
             name: "git".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![DepsTree {
                 name: "openssl".to_string(),
                 installed: true,
+                annotation: Default::default(),
                 children: vec![DepsTree {
                     name: "zlib".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 }],
             }],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 3);
         assert!(lines[0].contains("git"));
         assert!(lines[1].contains("openssl"));
@@ -590,25 +689,29 @@ mod tests {
         let tree = DepsTree {
             name: "python@3.11".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![
                 DepsTree {
                     name: "openssl".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "readline".to_string(),
                     installed: false,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "sqlite".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
             ],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 4);
 
         // Check install markers
@@ -626,24 +729,28 @@ mod tests {
         let tree = DepsTree {
             name: "neovim".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![
                 DepsTree {
                     name: "luajit".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![DepsTree {
                         name: "libgit2".to_string(),
                         installed: false,
+                        annotation: Default::default(),
                         children: vec![],
                     }],
                 },
                 DepsTree {
                     name: "libuv".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
             ],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 4);
 
         // Verify all names are present
@@ -665,35 +772,41 @@ mod tests {
         let tree = DepsTree {
             name: "cmake".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![
                 DepsTree {
                     name: "curl".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "expat".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "libuv".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "ncurses".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "zlib".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
             ],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert_eq!(lines.len(), 6);
 
         // Verify all names present in order
@@ -717,21 +830,24 @@ mod tests {
         let tree = DepsTree {
             name: "openssl".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![
                 DepsTree {
                     name: "zlib".to_string(),
                     installed: true,
+                    annotation: Default::default(),
                     children: vec![],
                 },
                 DepsTree {
                     name: "ca-certificates".to_string(),
                     installed: false,
+                    annotation: Default::default(),
                     children: vec![],
                 },
             ],
         };
         // Call with non-empty prefix to simulate being a child node
-        let lines = format_deps_tree_lines(&tree, "  ", false);
+        let lines = format_deps_tree_lines(&tree, "  ", false, false);
 
         // First line should have middle-child connector
         assert!(lines[0].contains("├── "));
@@ -749,14 +865,79 @@ mod tests {
         let tree = DepsTree {
             name: "python@3.11".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![DepsTree {
                 name: "node@20".to_string(),
                 installed: false,
+                annotation: Default::default(),
                 children: vec![],
             }],
         };
-        let lines = format_deps_tree_lines(&tree, "", true);
+        let lines = format_deps_tree_lines(&tree, "", true, false);
         assert!(lines[0].contains("python@3.11"));
         assert!(lines[1].contains("node@20"));
     }
+    #[test]
+    fn test_format_dep_annotation() {
+        let annotation = |installed: Option<&str>, available: Option<&str>| DepAnnotation {
+            installed_version: installed.map(String::from),
+            available_version: available.map(String::from),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_dep_annotation(&annotation(Some("1.7.1"), Some("1.7.1"))),
+            "1.7.1"
+        );
+        assert_eq!(
+            format_dep_annotation(&annotation(Some("1.7.1"), Some("1.8.0"))),
+            "1.7.1 → 1.8.0"
+        );
+        assert_eq!(
+            format_dep_annotation(&annotation(None, Some("1.8.0"))),
+            "(1.8.0 available)"
+        );
+        assert_eq!(format_dep_annotation(&DepAnnotation::default()), "");
+
+        let flagged = DepAnnotation {
+            pinned: true,
+            keg_only: true,
+            ..annotation(Some("3.3.1"), None)
+        };
+        assert_eq!(format_dep_annotation(&flagged), "3.3.1 [pinned, keg-only]");
+    }
+
+    #[test]
+    fn test_format_deps_tree_lines_annotated() {
+        let tree = DepsTree {
+            name: "wget".to_string(),
+            installed: true,
+            annotation: DepAnnotation {
+                installed_version: Some("1.24.5".to_string()),
+                available_version: Some("1.24.5".to_string()),
+                ..Default::default()
+            },
+            children: vec![DepsTree {
+                name: "openssl@3".to_string(),
+                installed: false,
+                annotation: DepAnnotation {
+                    available_version: Some("3.3.2".to_string()),
+                    keg_only: true,
+                    ..Default::default()
+                },
+                children: vec![],
+            }],
+        };
+
+        let lines = format_deps_tree_lines(&tree, "", true, true);
+        assert!(lines[0].ends_with("wget 1.24.5"), "{}", lines[0]);
+        assert!(
+            lines[1].ends_with("openssl@3 (3.3.2 available) [keg-only]"),
+            "{}",
+            lines[1]
+        );
+
+        let plain = format_deps_tree_lines(&tree, "", true, false);
+        assert!(plain[1].ends_with("openssl@3"));
+    }
 }
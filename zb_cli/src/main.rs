@@ -11,17 +11,26 @@
 
 //! Zerobrew CLI - A fast Homebrew-compatible package installer.
 
-use clap::{Parser, Subcommand};
+use clap::parser::ValueSource;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
 use console::style;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use zb_io::install::create_installer;
+use zb_core::{ApiLayout, ColorMode, Config};
+use zb_io::fd_limit::{FdLimitAdjustment, fit_fd_limit};
+use zb_io::install::create_installer_from_context;
+use zb_io::{ConcurrencyLimits, HttpFixtures, HttpMode};
 
 mod commands;
 mod display;
+mod render;
 
 use display::{format_bytes, print_shellenv};
+use render::{Render, emit};
+
+use zb_core::context::{DEFAULT_PREFIX, DEFAULT_ROOT};
 
 #[derive(Parser)]
 #[command(name = "zb")]
@@ -29,27 +38,96 @@ use display::{format_bytes, print_shellenv};
 #[command(version)]
 struct Cli {
     /// Root directory for zerobrew data
-    #[arg(long, default_value = "/opt/zerobrew")]
+    #[arg(long, default_value = DEFAULT_ROOT)]
     root: PathBuf,
 
     /// Prefix directory for linked binaries
-    #[arg(long, default_value = "/opt/zerobrew/prefix")]
+    #[arg(long, default_value = DEFAULT_PREFIX)]
     prefix: PathBuf,
 
     /// Number of parallel downloads
     #[arg(long, default_value = "48")]
     concurrency: usize,
 
+    /// Number of bottles extracted in parallel (default: number of CPUs)
+    #[arg(long, value_name = "N")]
+    extract_concurrency: Option<usize>,
+
+    /// Record API responses and bottle downloads into this directory
+    /// (replay them later with ZB_HTTP_REPLAY=<dir>)
+    #[arg(long, value_name = "DIR")]
+    record_http: Option<PathBuf>,
+
+    /// Fetch formula metadata from this API domain, e.g. a `zb mirror`
+    /// served over HTTP (also: ZB_API_DOMAIN)
+    #[arg(long, value_name = "URL")]
+    api_domain: Option<String>,
+
+    /// Where the API domain serves formula JSON: `homebrew` for
+    /// <domain>/formula/<name>.json, `flat` for <domain>/<name>.json
+    /// (also: ZB_API_LAYOUT)
+    #[arg(long, value_name = "LAYOUT")]
+    api_layout: Option<ApiLayout>,
+
+    /// Download bottles from this domain instead of their upstream hosts
+    #[arg(long, value_name = "URL")]
+    bottle_domain: Option<String>,
+
+    /// Upgrade from bottle patches published here when the old bottle is
+    /// still cached (also: ZB_DELTA_URL)
+    #[arg(long, value_name = "URL")]
+    delta_url: Option<String>,
+
+    /// Resolve formulas and bottles only from a `zb mirror` directory or URL
+    /// (also: ZB_MIRROR)
+    #[arg(long, value_name = "DIR|URL", conflicts_with_all = ["api_domain", "bottle_domain"])]
+    mirror: Option<String>,
+
+    /// Ignore cached formula metadata and bottles, downloading them again
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Never touch the network: use cached formula metadata however old,
+    /// and install only bottles already in the cache
+    #[arg(long, conflicts_with_all = ["no_cache", "refresh"])]
+    offline: bool,
+
+    /// Fetch this formula's metadata again instead of using the cache
+    /// (repeatable)
+    #[arg(long, value_name = "FORMULA")]
+    refresh: Vec<String>,
+
+    /// Install bottles built for this tag (e.g. arm64_sonoma), falling back
+    /// to older macOS releases
+    #[arg(long, value_name = "TAG")]
+    bottle_tag: Option<String>,
+
+    /// Unpack and compile source builds here instead of under the root
+    /// (overrides ZB_TEMP)
+    #[arg(long, value_name = "DIR")]
+    temp_dir: Option<PathBuf>,
+
+    /// Print the result as a single JSON document on stdout
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Settings from the config file and environment, for what the command
+    /// line leaves unset
+    #[arg(skip)]
+    config: Config,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Install a formula
+    /// Install one or more formulas
     Install {
-        /// Formula name to install
-        formula: String,
+        /// Formulas to install; their dependencies are resolved together
+        /// and shared ones installed once
+        #[arg(required = true, value_name = "FORMULA")]
+        formulas: Vec<String>,
 
         /// Skip linking executables
         #[arg(long)]
@@ -62,12 +140,94 @@ enum Commands {
         /// Install the HEAD version (requires building from source)
         #[arg(long, short = 'H')]
         head: bool,
+
+        /// Pass an extra argument to the build system, e.g. a configure flag
+        /// (repeatable; implies --build-from-source)
+        #[arg(
+            long = "build-arg",
+            value_name = "ARG",
+            allow_hyphen_values = true,
+            conflicts_with = "from_pack"
+        )]
+        build_args: Vec<String>,
+
+        /// Print a per-phase timing breakdown when done
+        #[arg(long)]
+        profile: bool,
+
+        /// Check each keg against its store manifest before linking, and
+        /// its bottle's attestation with `gh`
+        #[arg(long)]
+        verify: bool,
+
+        /// Install from unpacked packs (see `zb unpack`) without network access
+        #[arg(long, conflicts_with = "build_from_source")]
+        from_pack: bool,
+
+        /// Don't ask for confirmation before large installs
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        /// Install even if the formula has been disabled upstream
+        #[arg(long)]
+        force: bool,
+
+        /// Don't run the formulas' post-install steps
+        #[arg(long)]
+        skip_post_install: bool,
+
+        /// Write the resolved plan (exact versions, bottle URLs and checksums)
+        /// to a file for review instead of installing
+        #[arg(
+            long,
+            value_name = "PATH",
+            conflicts_with_all = ["build_from_source", "head", "build_args", "from_pack"]
+        )]
+        emit_plan: Option<PathBuf>,
+
+        /// Show the plan with each package's download and installed size
+        /// without installing anything
+        #[arg(
+            long,
+            conflicts_with_all = ["build_from_source", "head", "build_args", "from_pack", "emit_plan"]
+        )]
+        dry_run: bool,
+    },
+
+    /// Install exactly the bottles of a plan written by `zb install --emit-plan`
+    ExecutePlan {
+        /// Plan file
+        plan: PathBuf,
+
+        /// Skip linking executables
+        #[arg(long)]
+        no_link: bool,
+
+        /// Don't ask for confirmation before large installs
+        #[arg(long, short = 'y')]
+        yes: bool,
+
+        /// Don't run the formulas' post-install steps
+        #[arg(long)]
+        skip_post_install: bool,
     },
 
     /// Uninstall a formula (or all formulas if no name given)
     Uninstall {
         /// Formula name to uninstall (omit to uninstall all)
         formula: Option<String>,
+
+        /// Only remove this installed version
+        #[arg(long, requires = "formula")]
+        version: Option<String>,
+
+        /// Also uninstall every installed package that depends on the formula
+        #[arg(long, requires = "formula", conflicts_with = "version")]
+        with_dependents: bool,
+
+        /// Skip confirmation prompt (with --with-dependents)
+        #[arg(long, short = 'y', requires = "with_dependents")]
+        yes: bool,
     },
 
     /// List installed formulas
@@ -75,6 +235,37 @@ enum Commands {
         /// Show only pinned formulas
         #[arg(long)]
         pinned: bool,
+
+        /// Show only formulas installed by a source: cli, brewfile[:<path or run ID>]
+        /// or dependency_of[:<formula>]
+        #[arg(long, value_name = "SOURCE")]
+        installed_by: Option<String>,
+
+        /// Formula whose files to list (with --files)
+        #[arg(requires = "files")]
+        formula: Option<String>,
+
+        /// List the files in the formula's keg and its links in the prefix
+        #[arg(long, requires = "formula", conflicts_with_all = ["pinned", "installed_by"])]
+        files: bool,
+
+        /// Show only formulas upstream has deprecated or disabled
+        #[arg(long, conflicts_with_all = ["pinned", "installed_by", "files"])]
+        deprecated: bool,
+
+        /// Print each formula with every installed version, the active one last
+        #[arg(long, conflicts_with_all = ["files", "deprecated", "full"])]
+        versions: bool,
+
+        /// Show a table with each keg's size, install date, whether it was
+        /// installed explicitly or as a dependency, pin and link state, and tap
+        #[arg(long, conflicts_with_all = ["files", "deprecated"])]
+        full: bool,
+
+        /// Order formulas by name, install date (newest first) or size
+        /// (largest first)
+        #[arg(long, value_name = "name|date|size", value_parser = parse_list_sort, default_value = "name", conflicts_with_all = ["files", "deprecated"])]
+        sort: zb_io::InventorySort,
     },
 
     /// Show info about an installed formula
@@ -82,9 +273,21 @@ enum Commands {
         /// Formula name
         formula: String,
 
-        /// Output as JSON
+        /// List the files in the keg and its links in the prefix
         #[arg(long)]
-        json: bool,
+        files: bool,
+
+        /// Write an SVG or JSON badge (version, platforms with bottles,
+        /// dependency count) to FILE, by its extension
+        #[arg(long, value_name = "FILE", conflicts_with = "files")]
+        badge: Option<PathBuf>,
+    },
+
+    /// List versions from the API, versioned formulas, and versions still
+    /// installed or in the store
+    Versions {
+        /// Formula name
+        formula: String,
     },
 
     /// Search for formulas
@@ -92,20 +295,28 @@ enum Commands {
         /// Search query (use /regex/ for regex search)
         query: String,
 
-        /// Output as JSON
-        #[arg(long)]
-        json: bool,
-
         /// Only show installed packages
         #[arg(long)]
         installed: bool,
+
+        /// Only match against tap-qualified full names
+        #[arg(long, conflicts_with = "alias")]
+        full_name: bool,
+
+        /// Only match against formula aliases
+        #[arg(long)]
+        alias: bool,
     },
 
     /// List outdated formulas
     Outdated {
-        /// Output as JSON
+        /// Check upstream git repositories for new commits to HEAD installs
+        #[arg(long = "fetch-HEAD")]
+        fetch_head: bool,
+
+        /// Include casks (not supported; zerobrew only installs formulae)
         #[arg(long)]
-        json: bool,
+        cask: bool,
     },
 
     /// Upgrade outdated formulas
@@ -116,6 +327,35 @@ enum Commands {
         /// Show what would be upgraded without doing it
         #[arg(long)]
         dry_run: bool,
+
+        /// Rebuild HEAD installs whose upstream branch has new commits
+        #[arg(long = "fetch-HEAD")]
+        fetch_head: bool,
+
+        /// Also upgrade outdated dependents and rebuild source-built dependents
+        #[arg(long)]
+        greedy: bool,
+
+        /// Only upgrade inside a maintenance window (see `zb upgrade-window`);
+        /// for unattended jobs
+        #[arg(long)]
+        scheduled: bool,
+
+        /// Upgrade even outside the maintenance window
+        #[arg(long)]
+        now: bool,
+    },
+
+    /// Allow scheduled upgrades only at certain times, or list the windows.
+    /// e.g. `zb upgrade-window "Sat,Sun 02:00-05:00"`
+    UpgradeWindow {
+        /// Days and local times, like "Mon-Fri 22:00-04:00", "Sun" or "01:00-03:00"
+        /// (lists the windows when omitted)
+        window: Option<zb_io::UpgradeWindow>,
+
+        /// Remove the window
+        #[arg(long, requires = "window")]
+        remove: bool,
     },
 
     /// Pin a formula to prevent automatic upgrades
@@ -130,7 +370,7 @@ enum Commands {
         formula: String,
     },
 
-    /// Garbage collect unreferenced store entries
+    /// Garbage collect old versions and unreferenced store entries
     Gc,
 
     /// Remove orphaned dependencies (packages no longer needed by any explicit install)
@@ -138,6 +378,35 @@ enum Commands {
         /// Show what would be removed without doing it
         #[arg(long)]
         dry_run: bool,
+
+        /// Keep these formulas (and their dependencies), comma-separated
+        #[arg(long, value_delimiter = ',', value_name = "FORMULAS")]
+        except: Vec<String>,
+    },
+
+    /// Mark formulas as explicitly installed or as dependencies
+    Mark {
+        /// Formula names to mark
+        #[arg(required = true)]
+        formulas: Vec<String>,
+
+        /// Mark as explicitly installed, so autoremove never removes it
+        #[arg(long, conflicts_with = "auto", required_unless_present = "auto")]
+        explicit: bool,
+
+        /// Mark as installed as a dependency, so autoremove removes it once unneeded
+        #[arg(long)]
+        auto: bool,
+    },
+
+    /// Keep a formula (and its dependencies) out of autoremove, or list kept formulas
+    Keep {
+        /// Formula name to keep (lists the keep-list when omitted)
+        formula: Option<String>,
+
+        /// Remove the formula from the keep-list
+        #[arg(long, requires = "formula")]
+        remove: bool,
     },
 
     /// Remove old versions and cache files
@@ -149,6 +418,12 @@ enum Commands {
         /// Remove cache files older than specified days (default: remove all unused)
         #[arg(long)]
         prune: Option<u32>,
+
+        /// Also reconcile the Cellar with the database: remove or adopt keg
+        /// directories it has no record of, and forget recorded kegs that
+        /// are gone
+        #[arg(long, value_name = "remove|adopt", value_parser = parse_untracked_action)]
+        untracked: Option<zb_io::UntrackedAction>,
     },
 
     /// Reset zerobrew (delete all data for cold install testing)
@@ -166,10 +441,19 @@ enum Commands {
         /// Shell type (bash, zsh, fish, csh). Auto-detected if not specified.
         #[arg(long, short)]
         shell: Option<String>,
+
+        /// Report whether PATH and MANPATH are already set up, exiting
+        /// non-zero if they aren't
+        #[arg(long, conflicts_with = "shell")]
+        check: bool,
     },
 
     /// Manage third-party repositories (taps)
+    #[command(args_conflicts_with_subcommands = true)]
     Tap {
+        #[command(subcommand)]
+        action: Option<TapAction>,
+
         /// Tap to add (in user/repo format). If omitted, lists installed taps.
         user_repo: Option<String>,
     },
@@ -185,7 +469,7 @@ enum Commands {
         /// Formula name to link
         formula: String,
 
-        /// Overwrite existing symlinks from other kegs
+        /// Replace conflicting files, backing them up so `zb unlink` can restore them
         #[arg(long)]
         overwrite: bool,
 
@@ -200,10 +484,45 @@ enum Commands {
         formula: String,
     },
 
+    /// Switch the active version of a formula and relink it
+    Switch {
+        /// Formula name
+        formula: String,
+
+        /// Installed version to activate
+        version: String,
+    },
+
+    /// Manage a keg placed in the Cellar by hand: copy it into the store,
+    /// record it and link it
+    Adopt {
+        /// Formula name
+        formula: String,
+
+        /// Version of the keg, as named in the Cellar
+        version: String,
+
+        /// Skip linking executables
+        #[arg(long)]
+        no_link: bool,
+    },
+
+    /// Return a formula to its previous version after a bad upgrade
+    Rollback {
+        /// Formula name
+        formula: String,
+
+        /// Don't pin the formula after rolling back
+        #[arg(long)]
+        no_pin: bool,
+    },
+
     /// Show dependencies for a formula
     Deps {
-        /// Formula name to show dependencies for
-        formula: String,
+        /// Formula name to show dependencies for; with --graph, omit it to
+        /// export the graph of everything installed
+        #[arg(required_unless_present_any = ["graph", "dot"])]
+        formula: Option<String>,
 
         /// Show dependencies as a tree
         #[arg(long)]
@@ -216,6 +535,25 @@ enum Commands {
         /// Include all recursive (transitive) dependencies
         #[arg(long, short = '1')]
         all: bool,
+
+        /// Export the dependency graph as dot, mermaid or json (an adjacency list)
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            num_args = 0..=1,
+            default_missing_value = "dot",
+            conflicts_with_all = ["tree", "all"]
+        )]
+        graph: Option<commands::deps::GraphFormat>,
+
+        /// Export the dependency graph as Graphviz DOT (same as --graph dot)
+        #[arg(long, conflicts_with_all = ["graph", "tree", "all"])]
+        dot: bool,
+
+        /// Show installed and available versions and pinned or keg-only
+        /// state on each node (implies --tree)
+        #[arg(long, conflicts_with_all = ["graph", "dot"])]
+        annotate: bool,
     },
 
     /// Show which installed formulas use (depend on) a given formula
@@ -227,6 +565,10 @@ enum Commands {
         #[arg(long)]
         installed: bool,
 
+        /// Search every formula in the catalog, not just installed ones
+        #[arg(long, conflicts_with = "installed")]
+        eval_all: bool,
+
         /// Include packages that transitively depend on this formula
         #[arg(long)]
         recursive: bool,
@@ -235,8 +577,103 @@ enum Commands {
     /// List installed formulas that are not dependencies of any other installed formula
     Leaves,
 
+    /// Explain why a formula is installed
+    Why {
+        /// Installed formula name
+        formula: String,
+    },
+
     /// Diagnose common issues with the zerobrew installation
-    Doctor,
+    Doctor {
+        /// Also time the formula API, the bottle registry and configured
+        /// mirrors, and look for captive portals and proxies
+        #[arg(long)]
+        network: bool,
+    },
+
+    /// Re-check installed kegs against the hashes recorded for their bottles
+    Verify {
+        /// Formulas to check (omit to check every installed formula)
+        formulas: Vec<String>,
+    },
+
+    /// Compare a formula's configs in etc/ with the defaults it ships
+    ConfigDiff {
+        /// Installed formula name
+        formula: String,
+    },
+
+    /// Run a command with a formula's keg first on PATH, without linking it
+    Exec {
+        /// Installed formula, or formula@version for an installed inactive version
+        formula: String,
+
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Run a formula's post-install steps, or turn them off after installs
+    PostInstall {
+        /// Installed formula (lists formulas with post-install turned off when omitted)
+        formula: Option<String>,
+
+        /// Stop running the formula's post-install steps after installs
+        #[arg(long, requires = "formula", conflicts_with = "enable")]
+        disable: bool,
+
+        /// Run the formula's post-install steps after installs again
+        #[arg(long, requires = "formula")]
+        enable: bool,
+    },
+
+    /// Set environment variables a formula's executables run with, or list them
+    Env {
+        /// Formula name (lists every formula's variables when omitted)
+        formula: Option<String>,
+
+        /// Variables to set, as KEY=VALUE; `{prefix}` and `{opt}` in values
+        /// expand to the prefix and the formula's opt directory
+        #[arg(value_parser = parse_env_assignment, requires = "formula")]
+        assignments: Vec<(String, String)>,
+
+        /// Variables to remove
+        #[arg(long, value_name = "KEY", requires = "formula")]
+        unset: Vec<String>,
+    },
+
+    /// Show every executable a command resolves to, in PATH order
+    Which {
+        /// Command name
+        command: String,
+    },
+
+    /// Show install timing history, cache hit rates, dedupe savings, and monthly activity
+    Stats {
+        /// Clear the recorded install history (the pour log is kept)
+        #[arg(long)]
+        reset: bool,
+
+        /// List the last N bottles poured and integrity failures, newest first
+        #[arg(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "50",
+            conflicts_with = "reset"
+        )]
+        pours: Option<usize>,
+    },
+
+    /// Measure a cold install of a formula into a throwaway prefix
+    Benchmark {
+        /// Formula to install
+        formula: String,
+
+        /// Number of cold installs to average over
+        #[arg(long, short = 'n', default_value = "3")]
+        iterations: u32,
+    },
 
     /// Manage background services for installed formulas
     Services {
@@ -250,17 +687,60 @@ enum Commands {
         action: Option<BundleAction>,
     },
 
-    /// Update zb to the latest version
+    /// Refresh the formula list and taps, or update zb itself with --self
     Update {
-        /// Show what would be updated without installing
-        #[arg(long)]
+        /// Update zb to the latest version instead
+        #[arg(long = "self")]
+        self_update: bool,
+
+        /// Show what would be updated without installing (with --self)
+        #[arg(long, requires = "self_update")]
         dry_run: bool,
 
-        /// Force update even if already on latest version
-        #[arg(long)]
+        /// Force update even if already on latest version (with --self)
+        #[arg(long, requires = "self_update")]
         force: bool,
     },
 
+    /// Bundle formulas and their bottles into a single file for offline installs
+    Pack {
+        /// Formulas to pack (dependencies are included)
+        #[arg(required = true)]
+        formulas: Vec<String>,
+
+        /// Pack file to write
+        #[arg(short, long, default_value = "bundle.zbpack")]
+        output: PathBuf,
+
+        /// Bottle tag to include (repeatable, default: this platform)
+        #[arg(long = "platform", value_name = "TAG")]
+        platforms: Vec<String>,
+    },
+
+    /// Import a pack so its formulas can be installed with `zb install --from-pack`
+    Unpack {
+        /// Pack file to import
+        file: PathBuf,
+    },
+
+    /// Stable, versioned JSON for scripts and GUIs
+    Query {
+        #[command(subcommand)]
+        action: QueryAction,
+    },
+
+    /// Create static mirrors for air-gapped installs
+    Mirror {
+        #[command(subcommand)]
+        action: MirrorAction,
+    },
+
+    /// Manage the `brew` compatibility shim
+    Shim {
+        #[command(subcommand)]
+        action: ShimAction,
+    },
+
     /// List all available commands (built-in and external)
     #[command(alias = "zb-commands")]
     #[allow(clippy::enum_variant_names)]
@@ -274,16 +754,28 @@ enum Commands {
 #[derive(Subcommand, Clone)]
 pub enum ServicesAction {
     /// List all managed services and their status
-    List {
-        /// Output in JSON format
-        #[arg(long)]
-        json: bool,
-    },
+    List,
 
     /// Start a service
     Start {
         /// Formula name to start
         formula: String,
+
+        #[command(flatten)]
+        settings: ServiceSettingsArgs,
+    },
+
+    /// Show or change the process settings of a service
+    Config {
+        /// Formula name to configure
+        formula: String,
+
+        #[command(flatten)]
+        settings: ServiceSettingsArgs,
+
+        /// Clear all stored settings before applying the given ones
+        #[arg(long)]
+        reset: bool,
     },
 
     /// Enable a service to start automatically at login
@@ -322,6 +814,12 @@ pub enum ServicesAction {
         formula: String,
     },
 
+    /// Take over a service set up by `brew services`
+    Adopt {
+        /// Formula name to adopt
+        formula: String,
+    },
+
     /// View service logs
     Log {
         /// Formula name to view logs for
@@ -344,6 +842,177 @@ pub enum ServicesAction {
     },
 }
 
+/// Process settings accepted by `zb services start` and `zb services config`
+#[derive(Args, Clone, Debug, Default)]
+pub struct ServiceSettingsArgs {
+    /// User to run the service as (system services only)
+    #[arg(long)]
+    pub user: Option<String>,
+
+    /// Group to run the service as (system services only)
+    #[arg(long)]
+    pub group: Option<String>,
+
+    /// File mode creation mask, in octal (e.g. 022)
+    #[arg(long, value_parser = parse_umask)]
+    pub umask: Option<u32>,
+
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19)
+    )]
+    pub nice: Option<i32>,
+
+    /// Best-effort I/O priority, from 0 (highest) to 7 (lowest)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=7))]
+    pub io_nice: Option<u8>,
+
+    /// Maximum number of open files
+    #[arg(long, value_name = "N")]
+    pub limit_nofile: Option<u64>,
+
+    /// Maximum number of processes
+    #[arg(long, value_name = "N")]
+    pub limit_nproc: Option<u64>,
+
+    /// Seconds to wait before restarting a crashed service
+    #[arg(long, value_name = "SECS")]
+    pub restart_delay: Option<u64>,
+
+    /// Back off restarts up to this many seconds (systemd only)
+    #[arg(long, value_name = "SECS")]
+    pub restart_max_delay: Option<u64>,
+}
+
+impl From<ServiceSettingsArgs> for zb_io::ServiceSettings {
+    fn from(args: ServiceSettingsArgs) -> Self {
+        Self {
+            user: args.user,
+            group: args.group,
+            umask: args.umask,
+            nice: args.nice,
+            io_nice: args.io_nice,
+            open_files: args.limit_nofile,
+            processes: args.limit_nproc,
+            restart_delay: args.restart_delay,
+            restart_max_delay: args.restart_max_delay,
+        }
+    }
+}
+
+/// Parse an octal umask such as `022` or `0o027`.
+fn parse_umask(value: &str) -> Result<u32, String> {
+    let digits = value.strip_prefix("0o").unwrap_or(value);
+    match u32::from_str_radix(digits, 8) {
+        Ok(umask) if umask <= 0o777 => Ok(umask),
+        _ => Err(format!("'{}' is not an octal umask (000-777)", value)),
+    }
+}
+
+/// Parse what `zb cleanup --untracked` does with untracked kegs.
+fn parse_untracked_action(value: &str) -> Result<zb_io::UntrackedAction, String> {
+    match value {
+        "remove" => Ok(zb_io::UntrackedAction::Remove),
+        "adopt" => Ok(zb_io::UntrackedAction::Adopt),
+        _ => Err(format!(
+            "'{}' is not an action for untracked kegs (remove, adopt)",
+            value
+        )),
+    }
+}
+
+/// Parse the `--sort` order of `zb list`.
+fn parse_list_sort(value: &str) -> Result<zb_io::InventorySort, String> {
+    match value {
+        "name" => Ok(zb_io::InventorySort::Name),
+        "date" => Ok(zb_io::InventorySort::Date),
+        "size" => Ok(zb_io::InventorySort::Size),
+        _ => Err(format!(
+            "'{}' is not a sort order (name, date, size)",
+            value
+        )),
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum QueryAction {
+    /// All installed formulas
+    Installed,
+
+    /// Metadata and install state of a formula
+    Formula {
+        /// Formula name
+        name: String,
+    },
+
+    /// Installed formulas with a newer version available, including pinned ones
+    Outdated,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum TapAction {
+    /// Prefer this tap's formulas over homebrew/core when names clash
+    Pin {
+        /// Tap to pin (in user/repo format)
+        user_repo: String,
+    },
+
+    /// Resolve formulas from homebrew/core first again
+    Unpin {
+        /// Tap to unpin (in user/repo format)
+        user_repo: String,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ShimAction {
+    /// Install a `brew` link in prefix/bin that translates brew commands to zb
+    Install,
+
+    /// Remove the `brew` shim
+    Uninstall,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum MirrorAction {
+    /// Download metadata and bottles for formulas and their dependencies
+    Create {
+        /// Directory to write the mirror into
+        dir: PathBuf,
+
+        /// Formulas to mirror
+        names: Vec<String>,
+
+        /// Also mirror every formula listed in this Brewfile
+        #[arg(long = "formulas", value_name = "BREWFILE")]
+        brewfile: Option<PathBuf>,
+
+        /// Bottle tag to mirror (repeatable, default: this platform)
+        #[arg(long = "platform", value_name = "TAG")]
+        platforms: Vec<String>,
+    },
+
+    /// Refresh every formula in a mirror from upstream and add new ones
+    Sync {
+        /// Mirror directory to refresh
+        dir: PathBuf,
+
+        /// Formulas to add to the mirror
+        names: Vec<String>,
+
+        /// Also add every formula listed in this Brewfile
+        #[arg(long = "formulas", value_name = "BREWFILE")]
+        brewfile: Option<PathBuf>,
+
+        /// Bottle tag to mirror (repeatable, default: the tags the mirror
+        /// was created with)
+        #[arg(long = "platform", value_name = "TAG")]
+        platforms: Vec<String>,
+    },
+}
+
 #[derive(Subcommand, Clone)]
 pub enum BundleAction {
     /// Install all entries from a Brewfile (default when running 'zb bundle')
@@ -351,6 +1020,19 @@ pub enum BundleAction {
         /// Path to Brewfile (default: ./Brewfile or parent directories)
         #[arg(short, long)]
         file: Option<PathBuf>,
+
+        /// Install exactly the versions and bottles in Brewfile.lock.json,
+        /// failing if anything changed since it was written
+        #[arg(long)]
+        locked: bool,
+    },
+
+    /// Write Brewfile.lock.json with the exact versions, bottle URLs and
+    /// checksums the Brewfile resolves to
+    Lock {
+        /// Path to Brewfile (default: ./Brewfile or parent directories)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
     },
 
     /// Generate a Brewfile from installed packages
@@ -385,43 +1067,195 @@ pub enum BundleAction {
         #[arg(short, long)]
         file: Option<PathBuf>,
     },
+
+    /// Print shell exports that activate the Brewfile's formulas from their
+    /// kegs, e.g. in a direnv .envrc: eval "$(zb bundle env)"
+    Env {
+        /// Path to Brewfile (default: ./Brewfile or parent directories)
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Shell syntax to emit (bash, zsh, fish, csh); defaults to bash
+        #[arg(long)]
+        shell: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    let args: Vec<String> = std::env::args().collect();
 
-    if let Err(e) = run(cli).await {
-        eprintln!("{} {}", style("error:").red().bold(), e);
-        std::process::exit(1);
+    if args
+        .first()
+        .is_some_and(|argv0| commands::shim::invoked_as_brew(argv0))
+    {
+        run_brew_shim(&args[1..]).await;
+        return;
+    }
+
+    let result = match parse_cli(args) {
+        Ok(cli) => run(cli).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        exit_with_error(e);
     }
 }
 
-/// Check if zerobrew directories need initialization.
-fn needs_init(root: &Path, prefix: &Path) -> bool {
-    let root_ok = root.exists() && is_writable(root);
-    let prefix_ok = prefix.exists() && is_writable(prefix);
-    !(root_ok && prefix_ok)
+/// Report a failed command and exit with status 1. A declined confirmation
+/// prompt is only acknowledged, as it isn't an error.
+fn exit_with_error(error: zb_core::Error) -> ! {
+    match error {
+        zb_core::Error::Aborted => eprintln!("Aborted."),
+        e => eprintln!("{} {}", style("error:").red().bold(), e),
+    }
+    std::process::exit(1);
 }
 
-fn is_writable(path: &Path) -> bool {
-    if !path.exists() {
-        return false;
+/// Parse a command line, then fill the settings it leaves unset from the
+/// config file and environment.
+fn parse_cli(
+    args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+) -> Result<Cli, zb_core::Error> {
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand_name().unwrap_or_default();
+    let config = Config::from_env()?.for_command(command, |name| std::env::var(name).ok());
+    apply_config(&mut cli, config, |id| {
+        matches.value_source(id) == Some(ValueSource::CommandLine)
+    });
+    Ok(cli)
+}
+
+/// Take the global settings not given on the command line from `config`.
+/// Extracted for testability.
+pub(crate) fn apply_config(cli: &mut Cli, config: Config, given: impl Fn(&str) -> bool) {
+    if let Some(root) = &config.root
+        && !given("root")
+    {
+        cli.root = root.clone();
     }
-    let test_file = path.join(".zb_write_test");
-    match std::fs::write(&test_file, b"test") {
-        Ok(_) => {
-            let _ = std::fs::remove_file(&test_file);
-            true
-        }
-        Err(_) => false,
+    if let Some(prefix) = &config.prefix
+        && !given("prefix")
+    {
+        cli.prefix = prefix.clone();
+    }
+    if let Some(concurrency) = config.concurrency
+        && !given("concurrency")
+    {
+        cli.concurrency = concurrency;
+    }
+    if !given("extract_concurrency") {
+        cli.extract_concurrency = cli.extract_concurrency.or(config.extract_concurrency);
     }
+    cli.config = config;
 }
 
-/// Run initialization - create directories and set permissions.
-fn run_init(root: &Path, prefix: &Path) -> Result<(), String> {
-    println!("{} Initializing zerobrew...", style("==>").cyan().bold());
-
+/// Whether `command` changes what is installed, so it is followed by a
+/// cleanup when `[cleanup] auto` is set.
+/// Extracted for testability.
+pub(crate) fn cleans_up_after(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Install {
+            emit_plan: None,
+            dry_run: false,
+            ..
+        } | Commands::ExecutePlan { .. }
+            | Commands::Uninstall { .. }
+            | Commands::Upgrade { dry_run: false, .. }
+    )
+}
+
+/// Run a `brew` command line by translating it into zb invocations.
+async fn run_brew_shim(args: &[String]) {
+    use commands::shim::BrewTranslation;
+
+    let prefix = match Config::from_env() {
+        Ok(config) => shim_prefix(&config),
+        Err(e) => {
+            eprintln!("{} {}", style("error:").red().bold(), e);
+            std::process::exit(1);
+        }
+    };
+
+    match commands::shim::translate(args) {
+        BrewTranslation::Run {
+            invocations,
+            dropped,
+        } => {
+            for flag in &dropped {
+                eprintln!(
+                    "{} {} has no zerobrew equivalent and was ignored",
+                    style("Note:").yellow().bold(),
+                    flag
+                );
+            }
+            for invocation in invocations {
+                let args = std::iter::once("zb".to_string()).chain(invocation);
+                let result = match parse_cli(args) {
+                    Ok(cli) => run(cli).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    exit_with_error(e);
+                }
+            }
+        }
+        BrewTranslation::Prefix(None) => println!("{}", prefix.display()),
+        BrewTranslation::Prefix(Some(name)) => {
+            println!("{}", prefix.join("opt").join(name).display())
+        }
+        BrewTranslation::Cellar(None) => println!("{}", prefix.join("Cellar").display()),
+        BrewTranslation::Cellar(Some(name)) => {
+            println!("{}", prefix.join("Cellar").join(name).display())
+        }
+        BrewTranslation::Unsupported(message) => {
+            eprintln!(
+                "{} {}",
+                style("brew (zerobrew shim):").yellow().bold(),
+                message
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The prefix `brew --prefix` and `brew --cellar` report: the one from
+/// `ZB_PREFIX` or the config file, as `apply_config` resolves it for zb
+/// commands. Extracted for testability.
+pub(crate) fn shim_prefix(config: &Config) -> PathBuf {
+    config
+        .prefix
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_PREFIX))
+}
+
+/// Check if zerobrew directories need initialization.
+fn needs_init(root: &Path, prefix: &Path) -> bool {
+    let root_ok = root.exists() && is_writable(root);
+    let prefix_ok = prefix.exists() && is_writable(prefix);
+    !(root_ok && prefix_ok)
+}
+
+fn is_writable(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    let test_file = path.join(".zb_write_test");
+    match std::fs::write(&test_file, b"test") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&test_file);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Run initialization - create directories and set permissions.
+fn run_init(root: &Path, prefix: &Path) -> Result<(), String> {
+    println!("{} Initializing zerobrew...", style("==>").cyan().bold());
+
     let dirs_to_create: Vec<PathBuf> = vec![
         root.to_path_buf(),
         root.join("store"),
@@ -613,7 +1447,97 @@ fn ensure_init(root: &Path, prefix: &Path) -> Result<(), zb_core::Error> {
     run_init(root, prefix).map_err(|e| zb_core::Error::StoreCorruption { message: e })
 }
 
+/// Whether `command` prints a JSON document with `--json`.
+/// Extracted for testability.
+pub(crate) fn supports_json(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Install { .. }
+            | Commands::ExecutePlan { .. }
+            | Commands::List { .. }
+            | Commands::Info { .. }
+            | Commands::Versions { .. }
+            | Commands::Search { .. }
+            | Commands::Outdated { .. }
+            | Commands::Upgrade { .. }
+            | Commands::Gc
+            | Commands::Autoremove { .. }
+            | Commands::Cleanup { .. }
+            | Commands::Tap { .. }
+            | Commands::Untap { .. }
+            | Commands::Deps { .. }
+            | Commands::Uses { .. }
+            | Commands::Leaves
+            | Commands::Why { .. }
+            | Commands::Doctor { .. }
+            | Commands::Verify { .. }
+            | Commands::Stats { .. }
+            | Commands::Services { .. }
+            | Commands::Bundle { .. }
+            | Commands::Query { .. }
+            | Commands::Update { .. }
+            | Commands::Adopt { .. }
+    )
+}
+
+/// Paths, concurrency and network settings from the global flags.
+/// Extracted for testability.
+pub(crate) fn installer_context(cli: &Cli) -> zb_core::Context {
+    let mut context = zb_core::Context::new(&cli.root, &cli.prefix);
+    let mut concurrency = ConcurrencyLimits::new(cli.concurrency);
+    if let Some(extractions) = cli.extract_concurrency {
+        concurrency.extractions = extractions;
+    }
+    context.concurrency = concurrency;
+    context.offline = cli.offline;
+    context.mirror = cli.config.mirror.clone();
+    context.api_domain = cli.api_domain.clone().or(cli.config.api_domain.clone());
+    context.api_layout = cli.api_layout.or(cli.config.api_layout).unwrap_or_default();
+    context
+}
+
+/// Raise the open file limit for the configured concurrency, or run fewer
+/// downloads and extractions at once when it can't be raised far enough
+fn fit_open_files(context: &mut zb_core::Context) {
+    if let FdLimitAdjustment::Clamped { limit, limits } = fit_fd_limit(context.concurrency) {
+        eprintln!(
+            "{} {}",
+            style("Warning:").yellow().bold(),
+            format_fd_clamp_warning(limit, limits)
+        );
+        context.concurrency = limits;
+    }
+}
+
+/// Explain why fewer downloads and extractions run at once.
+/// Extracted for testability.
+pub(crate) fn format_fd_clamp_warning(limit: u64, limits: ConcurrencyLimits) -> String {
+    format!(
+        "the open file limit is {limit} and can't be raised, so only {} downloads and {} \
+         extractions will run at once (raise it with `ulimit -n`, or lower --concurrency)",
+        limits.downloads, limits.extractions
+    )
+}
+
 async fn run(cli: Cli) -> Result<(), zb_core::Error> {
+    if cli.json && !supports_json(&cli.command) {
+        return Err(zb_core::Error::StoreCorruption {
+            message: "--json is not supported by this command".to_string(),
+        });
+    }
+
+    match cli.config.color {
+        Some(ColorMode::Always) => {
+            console::set_colors_enabled(true);
+            console::set_colors_enabled_stderr(true);
+        }
+        Some(ColorMode::Never) => {
+            console::set_colors_enabled(false);
+            console::set_colors_enabled_stderr(false);
+        }
+        Some(ColorMode::Auto) | None => {}
+    }
+
     // Handle init separately - it doesn't need the installer
     if matches!(cli.command, Commands::Init) {
         return run_init(&cli.root, &cli.prefix)
@@ -621,13 +1545,29 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
     }
 
     // Handle shellenv separately - it only outputs environment setup
-    if let Commands::Shellenv { ref shell } = cli.command {
+    if let Commands::Shellenv { ref shell, check } = cli.command {
+        if check {
+            return commands::shellenv::run_check(&cli.prefix);
+        }
         print_shellenv(&cli.prefix, shell.as_deref());
         return Ok(());
     }
 
-    // Handle update separately - it doesn't need zerobrew directories
-    if let Commands::Update { dry_run, force } = cli.command {
+    // Handle shim separately - it only manages a link in prefix/bin
+    if let Commands::Shim { ref action } = cli.command {
+        return match action {
+            ShimAction::Install => commands::shim::run_install(&cli.prefix),
+            ShimAction::Uninstall => commands::shim::run_uninstall(&cli.prefix),
+        };
+    }
+
+    // Handle self-update separately - it doesn't need zerobrew directories
+    if let Commands::Update {
+        self_update: true,
+        dry_run,
+        force,
+    } = cli.command
+    {
         return commands::update::run(dry_run, force).await;
     }
 
@@ -638,64 +1578,244 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
         ensure_init(&cli.root, &cli.prefix)?;
     }
 
-    let mut installer = create_installer(&cli.root, &cli.prefix, cli.concurrency)?;
+    let mut context = installer_context(&cli);
+    fit_open_files(&mut context);
+    let mut installer = create_installer_from_context(context)?;
+    if let Some(dir) = &cli.record_http {
+        installer.set_http_mode(HttpMode::Record(HttpFixtures::new(dir)));
+    }
+    if let Some(domain) = &cli.bottle_domain {
+        installer.set_bottle_domain(domain);
+    }
+    if let Some(location) = &cli.mirror {
+        installer.set_mirror(location)?;
+    }
+    if let Some(url) = &cli.delta_url {
+        installer.set_delta_url(url);
+    }
+    if let Some(tag) = &cli.bottle_tag {
+        installer.set_bottle_tag(tag);
+    }
+    if cli.no_cache {
+        installer.set_no_cache();
+    }
+    for name in &cli.refresh {
+        installer.refresh_formula(name);
+    }
+    if let Some(dir) = &cli.temp_dir {
+        installer.set_temp_dir(dir)?;
+    }
 
-    match cli.command {
+    let json = cli.json;
+    let auto_cleanup = cli.config.cleanup.auto && cleans_up_after(&cli.command);
+    let prune_days = cli.config.cleanup.prune_days;
+    let result = match cli.command {
         Commands::Init => unreachable!(),
         Commands::Shellenv { .. } => unreachable!(),
 
         Commands::Install {
-            formula,
+            formulas,
             no_link,
             build_from_source,
             head,
+            build_args,
+            profile,
+            verify,
+            from_pack,
+            yes,
+            force,
+            skip_post_install,
+            emit_plan,
+            dry_run,
         } => {
+            installer.set_verify(verify);
+            installer.set_allow_disabled(force);
+            installer.set_skip_post_install(skip_post_install);
+            if let Some(path) = emit_plan {
+                let [formula] = formulas.as_slice() else {
+                    return Err(zb_core::Error::StoreCorruption {
+                        message: "--emit-plan takes a single formula".to_string(),
+                    });
+                };
+                return commands::install::emit_plan(&installer, formula, &path, json).await;
+            }
+            if dry_run {
+                return commands::install::dry_run(&installer, &formulas, json).await;
+            }
+            if from_pack {
+                installer.use_packs()?;
+            }
             commands::install::run(
                 &mut installer,
                 &cli.prefix,
-                formula,
+                formulas,
                 no_link,
                 build_from_source,
                 head,
+                build_args,
+                profile,
+                yes,
+                json,
             )
             .await
         }
 
-        Commands::Uninstall { formula } => run_uninstall(&mut installer, formula),
+        Commands::Uninstall {
+            formula: Some(formula),
+            with_dependents: true,
+            yes,
+            ..
+        } => run_uninstall_with_dependents(&mut installer, &formula, yes).await,
+
+        Commands::Uninstall {
+            formula, version, ..
+        } => run_uninstall(&mut installer, formula, version),
+
+        Commands::List {
+            formula: Some(formula),
+            ..
+        } => commands::info::run_files(&installer, &formula, json),
+
+        Commands::List {
+            deprecated: true, ..
+        } => commands::info::run_list_deprecated(&installer, json).await,
+
+        Commands::List {
+            pinned,
+            installed_by,
+            versions,
+            full,
+            sort,
+            ..
+        } => commands::info::run_list(
+            &installer,
+            pinned,
+            installed_by.as_deref(),
+            versions,
+            full,
+            sort,
+            json,
+        ),
+
+        Commands::Info {
+            formula,
+            files: true,
+            ..
+        } => commands::info::run_files(&installer, &formula, json),
 
-        Commands::List { pinned } => commands::info::run_list(&installer, pinned),
+        Commands::Info {
+            formula,
+            badge: Some(path),
+            ..
+        } => commands::badge::run(&installer, &formula, path, json).await,
 
-        Commands::Info { formula, json } => {
+        Commands::Info { formula, .. } => {
             commands::info::run_info(&mut installer, &cli.prefix, formula, json).await
         }
 
+        Commands::Versions { formula } => commands::versions::run(&installer, &formula, json).await,
+
         Commands::Search {
             query,
-            json,
             installed,
-        } => commands::info::run_search(&installer, &cli.root, query, json, installed).await,
+            full_name,
+            alias,
+        } => {
+            let scope = commands::info::search_scope(full_name, alias);
+            commands::info::run_search(&installer, query, json, installed, scope).await
+        }
+
+        Commands::Outdated { fetch_head, cask } => {
+            if cask {
+                eprintln!(
+                    "{} casks are not supported by zerobrew; only formulae can be installed",
+                    style("error:").red().bold()
+                );
+                std::process::exit(1);
+            }
+            commands::upgrade::run_outdated(&mut installer, json, fetch_head).await
+        }
+
+        Commands::Upgrade {
+            formula,
+            dry_run,
+            fetch_head,
+            greedy,
+            scheduled,
+            now,
+        } => {
+            commands::upgrade::run_upgrade(
+                &mut installer,
+                formula,
+                dry_run,
+                fetch_head,
+                greedy,
+                scheduled && !now,
+                json,
+            )
+            .await
+        }
 
-        Commands::Outdated { json } => commands::upgrade::run_outdated(&mut installer, json).await,
+        Commands::ExecutePlan {
+            plan,
+            no_link,
+            yes,
+            skip_post_install,
+        } => {
+            installer.set_skip_post_install(skip_post_install);
+            commands::install::execute_plan(&mut installer, &cli.prefix, &plan, no_link, yes, json)
+                .await
+        }
 
-        Commands::Upgrade { formula, dry_run } => {
-            commands::upgrade::run_upgrade(&mut installer, formula, dry_run).await
+        Commands::UpgradeWindow { window, remove } => {
+            run_upgrade_window(&installer, window.as_ref(), remove)
         }
 
         Commands::Pin { formula } => commands::upgrade::run_pin(&mut installer, &formula),
 
         Commands::Unpin { formula } => commands::upgrade::run_unpin(&mut installer, &formula),
 
-        Commands::Gc => run_gc(&mut installer),
+        Commands::Gc => run_gc(&mut installer, json),
 
-        Commands::Autoremove { dry_run } => run_autoremove(&mut installer, dry_run).await,
+        Commands::Autoremove { dry_run, except } => {
+            run_autoremove(&mut installer, dry_run, &except, json).await
+        }
 
-        Commands::Cleanup { dry_run, prune } => run_cleanup(&mut installer, dry_run, prune),
+        Commands::Mark {
+            formulas,
+            explicit,
+            auto: _,
+        } => run_mark(&installer, &formulas, explicit),
+
+        Commands::Keep { formula, remove } => run_keep(&installer, formula.as_deref(), remove),
+
+        Commands::Cleanup {
+            dry_run,
+            prune,
+            untracked,
+        } => run_cleanup(
+            &mut installer,
+            dry_run,
+            prune.or(prune_days),
+            untracked,
+            json,
+        ),
 
         Commands::Reset { yes } => run_reset(&cli.root, &cli.prefix, yes),
 
-        Commands::Tap { user_repo } => commands::tap::run_tap(&mut installer, user_repo).await,
+        Commands::Tap {
+            action: Some(TapAction::Pin { user_repo }),
+            ..
+        } => commands::tap::run_pin(&installer, &user_repo, json),
+        Commands::Tap {
+            action: Some(TapAction::Unpin { user_repo }),
+            ..
+        } => commands::tap::run_unpin(&installer, &user_repo, json),
+        Commands::Tap { user_repo, .. } => {
+            commands::tap::run_tap(&mut installer, user_repo, json).await
+        }
 
-        Commands::Untap { user_repo } => commands::tap::run_untap(&mut installer, user_repo),
+        Commands::Untap { user_repo } => commands::tap::run_untap(&mut installer, user_repo, json),
 
         Commands::Link {
             formula,
@@ -705,35 +1825,122 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
 
         Commands::Unlink { formula } => run_unlink(&mut installer, &formula),
 
+        Commands::Switch { formula, version } => run_switch(&mut installer, &formula, &version),
+
+        Commands::Adopt {
+            formula,
+            version,
+            no_link,
+        } => run_adopt(&mut installer, &formula, &version, !no_link, json).await,
+
+        Commands::Rollback { formula, no_pin } => {
+            commands::upgrade::run_rollback(&mut installer, &formula, no_pin)
+        }
+
         Commands::Deps {
             formula,
             tree,
             installed,
             all,
-        } => commands::deps::run_deps(&mut installer, formula, tree, installed, all).await,
+            graph,
+            dot,
+            annotate,
+        } => {
+            let graph = graph.or(dot.then_some(commands::deps::GraphFormat::Dot));
+            commands::deps::run_deps(
+                &mut installer,
+                formula,
+                tree,
+                installed,
+                all,
+                graph,
+                annotate,
+                json,
+            )
+            .await
+        }
 
         Commands::Uses {
             formula,
             installed: _,
+            eval_all,
             recursive,
-        } => commands::deps::run_uses(&mut installer, formula, recursive).await,
+        } => commands::deps::run_uses(&mut installer, formula, recursive, eval_all, json).await,
+
+        Commands::Leaves => commands::deps::run_leaves(&mut installer, json).await,
+
+        Commands::Why { formula } => commands::deps::run_why(&installer, &formula, json).await,
+
+        Commands::Doctor { network } => commands::doctor::run(&mut installer, network, json).await,
+
+        Commands::Verify { formulas } => commands::verify::run(&installer, &formulas, json),
+
+        Commands::ConfigDiff { formula } => commands::config::run_diff(&installer, &formula),
+
+        Commands::Exec { formula, command } => {
+            commands::exec::run(&installer, &formula, &command).await
+        }
+
+        Commands::PostInstall {
+            formula,
+            disable,
+            enable,
+        } => commands::post_install::run(&mut installer, formula.as_deref(), disable, enable).await,
+
+        Commands::Env {
+            formula,
+            assignments,
+            unset,
+        } => run_env(&mut installer, formula.as_deref(), &assignments, &unset),
+
+        Commands::Which { command } => commands::which::run(&installer, &command),
 
-        Commands::Leaves => commands::deps::run_leaves(&mut installer).await,
+        Commands::Stats { reset, pours } => match pours {
+            Some(limit) => commands::stats::run_pours(&installer, json, limit),
+            None => commands::stats::run(&mut installer, json, reset),
+        },
 
-        Commands::Doctor => commands::doctor::run(&mut installer).await,
+        Commands::Benchmark {
+            formula,
+            iterations,
+        } => commands::benchmark::run(&mut installer, &formula, iterations, cli.concurrency).await,
 
         Commands::Services { action } => {
-            commands::services::run(&mut installer, &cli.prefix, action)
+            commands::services::run(&mut installer, &cli.prefix, action, json)
         }
 
-        Commands::Bundle { action } => commands::bundle::run(&mut installer, action).await,
+        Commands::Bundle { action } => commands::bundle::run(&mut installer, action, json).await,
+
+        Commands::Update { .. } => commands::update::run_refresh(&installer, json).await,
 
-        Commands::Update { .. } => unreachable!(), // Handled early
+        Commands::Query { action } => commands::query::run(&mut installer, action).await,
+
+        Commands::Mirror { action } => commands::mirror::run(&mut installer, action).await,
+
+        Commands::Pack {
+            formulas,
+            output,
+            platforms,
+        } => commands::mirror::run_pack(&installer, &formulas, &output, &platforms).await,
+
+        Commands::Unpack { file } => commands::mirror::run_unpack(&installer, &file),
+
+        Commands::Shim { .. } => unreachable!(), // Handled early
 
         Commands::Commands => run_commands(&cli.root),
 
         Commands::External(args) => run_external(&cli.root, &cli.prefix, args),
+    };
+
+    result?;
+    if auto_cleanup && let Err(e) = installer.cleanup(prune_days) {
+        eprintln!(
+            "{} automatic cleanup failed: {}",
+            style("Warning:").yellow().bold(),
+            e
+        );
     }
+    Ok(())
 }
 
 // ============================================================================
@@ -743,9 +1950,25 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
 fn run_uninstall(
     installer: &mut zb_io::install::Installer,
     formula: Option<String>,
+    version: Option<String>,
 ) -> Result<(), zb_core::Error> {
-    match formula {
-        Some(name) => {
+    match (formula, version) {
+        (Some(name), Some(version)) => {
+            println!(
+                "{} Uninstalling {} {}...",
+                style("==>").cyan().bold(),
+                style(&name).bold(),
+                version
+            );
+            installer.uninstall_version(&name, &version)?;
+            println!(
+                "{} Uninstalled {} {}",
+                style("==>").cyan().bold(),
+                style(&name).green(),
+                version
+            );
+        }
+        (Some(name), None) => {
             println!(
                 "{} Uninstalling {}...",
                 style("==>").cyan().bold(),
@@ -758,7 +1981,7 @@ fn run_uninstall(
                 style(&name).green()
             );
         }
-        None => {
+        (None, _) => {
             let installed = installer.list_installed()?;
             if installed.is_empty() {
                 println!("No formulas installed.");
@@ -783,156 +2006,539 @@ fn run_uninstall(
     Ok(())
 }
 
-fn run_gc(installer: &mut zb_io::install::Installer) -> Result<(), zb_core::Error> {
+async fn run_uninstall_with_dependents(
+    installer: &mut zb_io::install::Installer,
+    formula: &str,
+    yes: bool,
+) -> Result<(), zb_core::Error> {
+    let closure = installer.dependents_closure(formula).await?;
+
     println!(
-        "{} Running garbage collection...",
-        style("==>").cyan().bold()
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_with_dependents_header(formula, closure.len())
     );
-    let removed = installer.gc()?;
+    for name in &closure {
+        println!("    {} {}", style("○").dim(), name);
+    }
 
-    if removed.is_empty() {
-        println!("No unreferenced store entries to remove.");
-    } else {
-        for key in &removed {
-            println!("    {} Removed {}", style("✓").green(), &key[..12]);
+    if closure.len() > 1 && !yes {
+        use std::io::{self, IsTerminal, Write};
+        if !io::stdin().is_terminal() {
+            eprintln!(
+                "{} refusing to uninstall dependents without a terminal; pass --yes to confirm",
+                style("error:").red().bold()
+            );
+            std::process::exit(1);
+        }
+
+        print!("Continue? [y/N] ");
+        if io::stdout().flush().is_err() {
+            eprintln!("{} Failed to flush stdout", style("error:").red().bold());
+            std::process::exit(1);
+        }
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).is_err() {
+            eprintln!("{} Failed to read user input", style("error:").red().bold());
+            std::process::exit(1);
+        }
+        if !commands::install::is_confirmation(&input) {
+            println!("Aborted.");
+            return Ok(());
         }
-        println!(
-            "{} Removed {} store entries",
-            style("==>").cyan().bold(),
-            style(removed.len()).green().bold()
-        );
     }
-    Ok(())
-}
 
-async fn run_autoremove(
-    installer: &mut zb_io::install::Installer,
-    dry_run: bool,
-) -> Result<(), zb_core::Error> {
+    for name in &closure {
+        print!("    {} {}...", style("○").dim(), name);
+        installer.uninstall(name)?;
+        println!(" {}", style("✓").green());
+    }
+
     println!(
-        "{} Finding orphaned dependencies...",
-        style("==>").cyan().bold()
+        "{} Uninstalled {} packages",
+        style("==>").cyan().bold(),
+        style(closure.len()).green().bold()
     );
+    Ok(())
+}
 
-    let orphans = installer.find_orphans().await?;
-
-    if orphans.is_empty() {
-        println!("No orphaned dependencies to remove.");
-        return Ok(());
+/// Header listing what `zb uninstall --with-dependents` will remove.
+/// Extracted for testability.
+pub(crate) fn format_with_dependents_header(formula: &str, count: usize) -> String {
+    match count.saturating_sub(1) {
+        0 => format!("No installed packages depend on {}; uninstalling:", formula),
+        1 => format!("Uninstalling {} and 1 package that depends on it:", formula),
+        n => format!(
+            "Uninstalling {} and {} packages that depend on it:",
+            formula, n
+        ),
     }
+}
 
-    if dry_run {
-        println!(
-            "{} Would remove {} orphaned packages:\n",
-            style("==>").cyan().bold(),
-            style(orphans.len()).yellow().bold()
-        );
-        for name in &orphans {
-            println!("  {}", name);
-        }
-        println!(
-            "\n    {} Run {} to remove",
-            style("→").dim(),
-            style("zb autoremove").cyan()
-        );
-    } else {
-        println!(
-            "{} Removing {} orphaned packages...\n",
-            style("==>").cyan().bold(),
-            style(orphans.len()).yellow().bold()
-        );
+/// What garbage collection removed
+struct GcOutput {
+    /// Inactive versions removed, as (name, version)
+    versions: Vec<(String, String)>,
+    /// Store keys removed
+    store_entries: Vec<String>,
+}
 
-        let removed = installer.autoremove().await?;
+impl Render for GcOutput {
+    fn render_human(&self) {
+        for (name, version) in &self.versions {
+            println!("    {} Removed {} {}", style("✓").green(), name, version);
+        }
 
-        if removed.is_empty() {
-            println!("No packages were removed.");
+        if self.store_entries.is_empty() {
+            println!("No unreferenced store entries to remove.");
         } else {
-            for name in &removed {
-                println!("    {} Removed {}", style("✓").green(), name);
+            for key in &self.store_entries {
+                println!("    {} Removed {}", style("✓").green(), &key[..12]);
             }
             println!(
-                "\n{} Removed {} orphaned packages",
+                "{} Removed {} store entries",
                 style("==>").cyan().bold(),
-                style(removed.len()).green().bold()
+                style(self.store_entries.len()).green().bold()
             );
         }
     }
-    Ok(())
+
+    fn render_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "versions": self.versions.iter().map(|(name, version)| {
+                serde_json::json!({ "name": name, "version": version })
+            }).collect::<Vec<_>>(),
+            "store_entries": self.store_entries,
+        })
+    }
 }
 
-fn run_cleanup(
-    installer: &mut zb_io::install::Installer,
-    dry_run: bool,
-    prune: Option<u32>,
-) -> Result<(), zb_core::Error> {
-    if dry_run {
+fn run_gc(installer: &mut zb_io::install::Installer, json: bool) -> Result<(), zb_core::Error> {
+    if !json {
         println!(
-            "{} Checking for files to clean up...",
+            "{} Running garbage collection...",
             style("==>").cyan().bold()
         );
+    }
+    let old_versions = installer.list_inactive_versions()?;
+    let removed = installer.gc()?;
 
-        let result = installer.cleanup_dry_run(prune)?;
-
-        if result.store_entries_removed == 0
-            && result.blobs_removed == 0
-            && result.http_cache_removed == 0
-        {
-            println!("Nothing to clean up.");
-            return Ok(());
-        }
-
-        println!("{} Would remove:\n", style("==>").cyan().bold());
+    emit(
+        &GcOutput {
+            versions: old_versions
+                .into_iter()
+                .map(|keg| (keg.name, keg.version))
+                .collect(),
+            store_entries: removed,
+        },
+        json,
+    );
+    Ok(())
+}
 
-        if result.store_entries_removed > 0 {
-            println!(
-                "  {} unreferenced store entries",
-                style(result.store_entries_removed).yellow()
-            );
-        }
+/// Orphaned dependencies autoremove found, and those it removed
+struct AutoremoveOutput {
+    orphans: Vec<String>,
+    /// None for a dry run
+    removed: Option<Vec<String>>,
+}
 
-        if result.blobs_removed > 0 {
-            println!(
-                "  {} cached bottle downloads",
-                style(result.blobs_removed).yellow()
-            );
+impl Render for AutoremoveOutput {
+    fn render_human(&self) {
+        if self.orphans.is_empty() {
+            println!("No orphaned dependencies to remove.");
+            return;
         }
 
-        if result.http_cache_removed > 0 {
-            println!(
-                "  {} cached API responses",
-                style(result.http_cache_removed).yellow()
-            );
+        match &self.removed {
+            None => {
+                println!(
+                    "{} Would remove {} orphaned packages:\n",
+                    style("==>").cyan().bold(),
+                    style(self.orphans.len()).yellow().bold()
+                );
+                for name in &self.orphans {
+                    println!("  {}", name);
+                }
+                println!(
+                    "\n    {} Run {} to remove",
+                    style("→").dim(),
+                    style("zb autoremove").cyan()
+                );
+            }
+            Some(removed) if removed.is_empty() => println!("No packages were removed."),
+            Some(removed) => {
+                for name in removed {
+                    println!("    {} Removed {}", style("✓").green(), name);
+                }
+                println!(
+                    "\n{} Removed {} orphaned packages",
+                    style("==>").cyan().bold(),
+                    style(removed.len()).green().bold()
+                );
+            }
         }
+    }
 
-        if result.bytes_freed > 0 {
+    fn render_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "dry_run": self.removed.is_none(),
+            "orphans": self.orphans,
+            "removed": self.removed.as_deref().unwrap_or_default(),
+        })
+    }
+}
+
+async fn run_autoremove(
+    installer: &mut zb_io::install::Installer,
+    dry_run: bool,
+    except: &[String],
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    if !json {
+        println!(
+            "{} Finding orphaned dependencies...",
+            style("==>").cyan().bold()
+        );
+    }
+
+    let orphans = installer.find_orphans_except(except).await?;
+
+    let removed = if dry_run || orphans.is_empty() {
+        None
+    } else {
+        if !json {
             println!(
-                "\n  Total: {}",
-                style(format_bytes(result.bytes_freed)).yellow()
+                "{} Removing {} orphaned packages...\n",
+                style("==>").cyan().bold(),
+                style(orphans.len()).yellow().bold()
             );
         }
+        Some(installer.autoremove_except(except).await?)
+    };
+
+    emit(&AutoremoveOutput { orphans, removed }, json);
+    Ok(())
+}
 
+fn run_mark(
+    installer: &zb_io::install::Installer,
+    formulas: &[String],
+    explicit: bool,
+) -> Result<(), zb_core::Error> {
+    for formula in formulas {
+        let changed = installer.is_explicit(formula) != explicit;
+        if explicit {
+            installer.mark_explicit(formula)?;
+        } else {
+            installer.mark_dependency(formula)?;
+        }
         println!(
-            "\n    {} Run {} to clean up",
-            style("→").dim(),
-            style("zb cleanup").cyan()
+            "{} {}",
+            style("==>").cyan().bold(),
+            format_mark_message(formula, explicit, changed)
         );
+    }
+    Ok(())
+}
+
+/// Describe the result of marking a formula.
+/// Extracted for testability.
+fn format_mark_message(formula: &str, explicit: bool, changed: bool) -> String {
+    let status = if explicit {
+        "installed explicitly"
+    } else {
+        "installed as a dependency"
+    };
+    if changed {
+        format!("Marked {} as {}", formula, status)
+    } else {
+        format!("{} is already marked as {}", formula, status)
+    }
+}
+
+fn run_keep(
+    installer: &zb_io::install::Installer,
+    formula: Option<&str>,
+    remove: bool,
+) -> Result<(), zb_core::Error> {
+    let Some(formula) = formula else {
+        let kept = installer.list_kept()?;
+        if kept.is_empty() {
+            println!("No formulas are kept.");
+        } else {
+            for name in &kept {
+                println!("{}", name);
+            }
+        }
+        return Ok(());
+    };
+
+    let changed = if remove {
+        installer.unkeep(formula)?
+    } else {
+        installer.keep(formula)?
+    };
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_keep_message(formula, remove, changed)
+    );
+    Ok(())
+}
+
+fn run_upgrade_window(
+    installer: &zb_io::install::Installer,
+    window: Option<&zb_io::UpgradeWindow>,
+    remove: bool,
+) -> Result<(), zb_core::Error> {
+    let Some(window) = window else {
+        let windows = installer.list_upgrade_windows()?;
+        if windows.is_empty() {
+            println!("Scheduled upgrades may run at any time.");
+            return Ok(());
+        }
+        for window in &windows {
+            println!("{}", window);
+        }
+        let status = installer.upgrade_window_status()?;
+        if status.is_closed() {
+            println!(
+                "    {} {}",
+                style("→").dim(),
+                commands::upgrade::format_window_closed_message(status.next_opening().as_deref())
+            );
+        }
+        return Ok(());
+    };
+
+    let changed = if remove {
+        installer.remove_upgrade_window(window)?
+    } else {
+        installer.add_upgrade_window(window)?
+    };
+    println!(
+        "{} {}",
+        style("==>").cyan().bold(),
+        format_upgrade_window_message(&window.to_string(), remove, changed)
+    );
+    Ok(())
+}
+
+/// Message for adding or removing an upgrade window.
+/// Extracted for testability.
+pub(crate) fn format_upgrade_window_message(window: &str, remove: bool, changed: bool) -> String {
+    match (remove, changed) {
+        (false, true) => format!("Scheduled upgrades may run during {}", window),
+        (false, false) => format!("{} is already an upgrade window", window),
+        (true, true) => format!("Removed upgrade window {}", window),
+        (true, false) => format!("{} is not an upgrade window", window),
+    }
+}
+
+/// Parse a `KEY=VALUE` argument of `zb env`
+fn parse_env_assignment(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", arg))?;
+    if !zb_io::link::is_env_name(key) {
+        return Err(format!("invalid environment variable name '{}'", key));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn run_env(
+    installer: &mut zb_io::install::Installer,
+    formula: Option<&str>,
+    assignments: &[(String, String)],
+    unset: &[String],
+) -> Result<(), zb_core::Error> {
+    let Some(formula) = formula else {
+        let all = installer.list_link_env()?;
+        if all.is_empty() {
+            println!("No formulas have environment variables set.");
+        }
+        for (name, env) in &all {
+            println!("{} {}", style("==>").cyan().bold(), style(name).bold());
+            for line in format_env_lines(env) {
+                println!("{}", line);
+            }
+        }
+        return Ok(());
+    };
+
+    let env = if assignments.is_empty() && unset.is_empty() {
+        installer.link_env(formula)?
     } else {
-        println!("{} Cleaning up...", style("==>").cyan().bold());
+        let env = installer.set_link_env(formula, assignments, unset)?;
+        let message = if env.is_empty() {
+            format!("{} executables are linked directly", formula)
+        } else {
+            format!("{} executables are linked through wrappers", formula)
+        };
+        println!("{} {}", style("==>").cyan().bold(), message);
+        env
+    };
 
-        let result = installer.cleanup(prune)?;
+    if env.is_empty() {
+        println!("No environment variables set for {}.", formula);
+    }
+    for line in format_env_lines(&env) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// `KEY=VALUE` lines for a formula's wrapper environment.
+/// Extracted for testability.
+fn format_env_lines(env: &std::collections::BTreeMap<String, String>) -> Vec<String> {
+    env.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+}
+
+/// Describe the result of a keep or `keep --remove`.
+/// Extracted for testability.
+fn format_keep_message(formula: &str, remove: bool, changed: bool) -> String {
+    match (remove, changed) {
+        (false, true) => format!("Kept {}; autoremove will not remove it", formula),
+        (false, false) => format!("{} is already kept", formula),
+        (true, true) => format!("{} is no longer kept", formula),
+        (true, false) => format!("{} is not kept", formula),
+    }
+}
+
+/// What cleanup removed, or would remove on a dry run
+struct CleanupOutput {
+    result: zb_io::install::CleanupResult,
+    dry_run: bool,
+    /// Kegs the Cellar and the database disagree about
+    drift: zb_io::CellarDrift,
+    /// What `--untracked` did, or would do, with them
+    untracked: Option<zb_io::UntrackedAction>,
+}
+
+impl CleanupOutput {
+    fn is_empty(&self) -> bool {
+        let r = &self.result;
+        r.store_entries_removed == 0
+            && r.blobs_removed == 0
+            && r.temp_files_removed == 0
+            && r.locks_removed == 0
+            && r.http_cache_removed == 0
+            && (self.untracked.is_none() || self.drift.is_empty())
+    }
+}
+
+/// Describe what `--untracked` did with the drift between the Cellar and
+/// the database, or would do on a dry run.
+/// Extracted for testability.
+fn format_drift_lines(
+    drift: &zb_io::CellarDrift,
+    untracked: zb_io::UntrackedAction,
+    dry_run: bool,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if !drift.missing.is_empty() {
+        let verb = if dry_run { "Would forget" } else { "Forgot" };
+        lines.push(format!(
+            "{} {} kegs missing from the Cellar",
+            verb,
+            drift.missing.len()
+        ));
+    }
+    if !drift.untracked.is_empty() {
+        let verb = match (untracked, dry_run) {
+            (zb_io::UntrackedAction::Remove, true) => "Would remove",
+            (zb_io::UntrackedAction::Remove, false) => "Removed",
+            (zb_io::UntrackedAction::Adopt, true) => "Would adopt",
+            (zb_io::UntrackedAction::Adopt, false) => "Adopted",
+        };
+        lines.push(format!("{} {} untracked kegs", verb, drift.untracked.len()));
+    }
+    lines
+}
 
-        if result.store_entries_removed == 0
-            && result.blobs_removed == 0
-            && result.temp_files_removed == 0
-            && result.locks_removed == 0
-            && result.http_cache_removed == 0
+/// Point out drift between the Cellar and the database that cleanup was not
+/// asked to fix.
+/// Extracted for testability.
+fn format_drift_note(drift: &zb_io::CellarDrift) -> Option<String> {
+    if drift.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} untracked kegs in the Cellar and {} recorded kegs missing from it; run `zb cleanup --untracked adopt` or `--untracked remove`",
+        drift.untracked.len(),
+        drift.missing.len()
+    ))
+}
+
+impl Render for CleanupOutput {
+    fn render_human(&self) {
+        let result = &self.result;
+        let drift_lines = self
+            .untracked
+            .map(|untracked| format_drift_lines(&self.drift, untracked, self.dry_run))
+            .unwrap_or_default();
+        if self.untracked.is_none()
+            && let Some(note) = format_drift_note(&self.drift)
         {
+            eprintln!("{} {}", style("Note:").yellow().bold(), note);
+        }
+        if self.is_empty() {
             println!("Nothing to clean up.");
-            return Ok(());
+            return;
+        }
+
+        if self.dry_run {
+            println!("{} Would remove:\n", style("==>").cyan().bold());
+
+            if result.store_entries_removed > 0 {
+                println!(
+                    "  {} unreferenced store entries",
+                    style(result.store_entries_removed).yellow()
+                );
+            }
+
+            if result.blobs_removed > 0 {
+                println!(
+                    "  {} cached bottle downloads",
+                    style(result.blobs_removed).yellow()
+                );
+            }
+
+            if result.http_cache_removed > 0 {
+                println!(
+                    "  {} cached API responses",
+                    style(result.http_cache_removed).yellow()
+                );
+            }
+
+            for line in &drift_lines {
+                println!("  {}", line);
+            }
+
+            if result.bytes_freed > 0 {
+                println!(
+                    "\n  Total: {}",
+                    style(format_bytes(result.bytes_freed)).yellow()
+                );
+            }
+
+            println!(
+                "\n    {} Run {} to clean up",
+                style("→").dim(),
+                style("zb cleanup").cyan()
+            );
+            return;
         }
 
         println!();
 
+        for line in &drift_lines {
+            println!("    {} {}", style("✓").green(), line);
+        }
+
         if result.store_entries_removed > 0 {
             println!(
                 "    {} Removed {} unreferenced store entries",
@@ -981,6 +2587,79 @@ fn run_cleanup(
             );
         }
     }
+
+    fn render_json(&self) -> serde_json::Value {
+        let r = &self.result;
+        serde_json::json!({
+            "dry_run": self.dry_run,
+            "store_entries_removed": r.store_entries_removed,
+            "blobs_removed": r.blobs_removed,
+            "http_cache_removed": r.http_cache_removed,
+            "temp_files_removed": r.temp_files_removed,
+            "locks_removed": r.locks_removed,
+            "bytes_freed": r.bytes_freed,
+            "untracked_action": self.untracked.map(|action| match action {
+                zb_io::UntrackedAction::Remove => "remove",
+                zb_io::UntrackedAction::Adopt => "adopt",
+            }),
+            "untracked": drifted_kegs_json(&self.drift.untracked),
+            "missing": drifted_kegs_json(&self.drift.missing),
+        })
+    }
+}
+
+/// Name, version and path of each drifted keg.
+/// Extracted for testability.
+fn drifted_kegs_json(kegs: &[zb_io::DriftedKeg]) -> Vec<serde_json::Value> {
+    kegs.iter()
+        .map(|keg| {
+            serde_json::json!({
+                "name": keg.name,
+                "version": keg.version,
+                "path": keg.path.display().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn run_cleanup(
+    installer: &mut zb_io::install::Installer,
+    dry_run: bool,
+    prune: Option<u32>,
+    untracked: Option<zb_io::UntrackedAction>,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    // Forgetting missing kegs releases their store entries, so reconcile
+    // before the store is collected
+    let drift = match untracked {
+        Some(action) if !dry_run => installer.reconcile_cellar(action)?,
+        _ => installer.cellar_drift()?,
+    };
+
+    let result = if dry_run {
+        if !json {
+            println!(
+                "{} Checking for files to clean up...",
+                style("==>").cyan().bold()
+            );
+        }
+        installer.cleanup_dry_run(prune)?
+    } else {
+        if !json {
+            println!("{} Cleaning up...", style("==>").cyan().bold());
+        }
+        installer.cleanup(prune)?
+    };
+
+    emit(
+        &CleanupOutput {
+            result,
+            dry_run,
+            drift,
+            untracked,
+        },
+        json,
+    );
     Ok(())
 }
 
@@ -1128,6 +2807,15 @@ async fn run_link(
                         style("→").dim()
                     );
                 }
+                for displaced in &result.backed_up {
+                    println!(
+                        "    {} Moved {} to {}",
+                        style("→").dim(),
+                        displaced.link_path.display(),
+                        displaced.backup_path.display()
+                    );
+                }
+                commands::which::warn_shadowed(installer, prefix, &[formula.to_string()]);
             }
         }
         Err(zb_core::Error::LinkConflict { path, .. }) => {
@@ -1172,6 +2860,7 @@ fn run_unlink(
     );
 
     let unlinked = installer.unlink(formula)?;
+    let restored = installer.restore_backups(formula)?;
 
     if unlinked == 0 {
         println!(
@@ -1189,43 +2878,215 @@ fn run_unlink(
         );
     }
 
+    for path in &restored {
+        println!("    {} Restored {}", style("→").dim(), path.display());
+    }
+
+    Ok(())
+}
+
+fn run_switch(
+    installer: &mut zb_io::install::Installer,
+    formula: &str,
+    version: &str,
+) -> Result<(), zb_core::Error> {
+    println!(
+        "{} Switching {} to {}...",
+        style("==>").cyan().bold(),
+        style(formula).bold(),
+        version
+    );
+
+    let result = installer.switch(formula, version)?;
+
+    if result.already_linked {
+        println!(
+            "{} {} {} is already active",
+            style("==>").cyan().bold(),
+            style(formula).bold(),
+            version
+        );
+    } else if !installer.is_linked(formula) {
+        println!(
+            "{} {} {} {} is now active (not linked)",
+            style("==>").cyan().bold(),
+            style("✓").green(),
+            style(formula).bold(),
+            version
+        );
+    } else {
+        println!(
+            "{} {} {} {} is now active ({} files linked)",
+            style("==>").cyan().bold(),
+            style("✓").green(),
+            style(formula).bold(),
+            version,
+            result.files_linked
+        );
+    }
+
+    Ok(())
+}
+
+/// A keg taken over by `zb adopt`
+struct AdoptOutput(zb_io::AdoptResult);
+
+/// Summary of an adopted keg and how it was linked.
+/// Extracted for testability.
+fn format_adopt_line(result: &zb_io::AdoptResult) -> String {
+    let linking = if result.linked {
+        format!("{} files linked", result.files_linked)
+    } else if result.keg_only {
+        "keg-only, not linked".to_string()
+    } else {
+        "not linked".to_string()
+    };
+    format!(
+        "Adopted {} {} ({})",
+        style(&result.name).bold(),
+        result.version,
+        linking
+    )
+}
+
+/// An adopted keg as JSON.
+/// Extracted for testability.
+fn adopt_json(result: &zb_io::AdoptResult) -> serde_json::Value {
+    serde_json::json!({
+        "name": result.name,
+        "version": result.version,
+        "keg": result.keg_path.display().to_string(),
+        "store_key": result.store_key,
+        "known_formula": result.known_formula,
+        "keg_only": result.keg_only,
+        "linked": result.linked,
+        "files_linked": result.files_linked,
+    })
+}
+
+impl Render for AdoptOutput {
+    fn render_human(&self) {
+        println!(
+            "{} {} {}",
+            style("==>").cyan().bold(),
+            style("✓").green(),
+            format_adopt_line(&self.0)
+        );
+        if !self.0.known_formula {
+            println!(
+                "    {} No formula metadata found for {}, so its dependencies aren't recorded",
+                style("→").dim(),
+                self.0.name
+            );
+        }
+    }
+
+    fn render_json(&self) -> serde_json::Value {
+        adopt_json(&self.0)
+    }
+}
+
+async fn run_adopt(
+    installer: &mut zb_io::install::Installer,
+    formula: &str,
+    version: &str,
+    link: bool,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    if !json {
+        println!(
+            "{} Adopting {} {}...",
+            style("==>").cyan().bold(),
+            style(formula).bold(),
+            version
+        );
+    }
+
+    let result = installer.adopt(formula, version, link).await?;
+    emit(&AdoptOutput(result), json);
     Ok(())
 }
 
 fn run_commands(root: &Path) -> Result<(), zb_core::Error> {
     let builtin_commands = [
+        ("adopt", "Manage a keg placed in the Cellar by hand"),
         ("autoremove", "Remove orphaned dependencies"),
+        ("benchmark", "Time cold installs of a formula"),
         (
             "bundle",
             "Install from a Brewfile or manage Brewfile configuration",
         ),
         ("cleanup", "Remove old versions and cache files"),
+        (
+            "config-diff",
+            "Compare a formula's configs with its defaults",
+        ),
         ("deps", "Show dependencies for a formula"),
         ("doctor", "Diagnose common issues"),
-        ("gc", "Garbage collect unreferenced store entries"),
+        (
+            "env",
+            "Set environment variables for a formula's executables",
+        ),
+        ("exec", "Run a command with a formula's keg first on PATH"),
+        (
+            "execute-plan",
+            "Install exactly the bottles of an exported plan",
+        ),
+        ("gc", "Garbage collect old versions and store entries"),
         ("info", "Show info about an installed formula"),
         ("init", "Initialize zerobrew directories"),
         ("install", "Install a formula"),
+        ("keep", "Keep a formula out of autoremove"),
         (
             "leaves",
             "List installed formulas that are not dependencies",
         ),
         ("link", "Create symlinks for a keg"),
         ("list", "List installed formulas"),
+        ("mark", "Mark formulas as explicit or dependencies"),
+        ("mirror", "Create static mirrors for air-gapped installs"),
         ("outdated", "List outdated formulas"),
+        (
+            "pack",
+            "Bundle formulas and bottles into a file for offline installs",
+        ),
         ("pin", "Pin a formula to prevent upgrades"),
+        ("post-install", "Run a formula's post-install steps"),
+        ("query", "Print stable JSON for scripts and GUIs"),
         ("reset", "Reset zerobrew (delete all data)"),
+        ("rollback", "Return a formula to its previous version"),
         ("search", "Search for formulas"),
         ("services", "Manage background services"),
         ("shellenv", "Print shell environment setup"),
+        ("shim", "Manage the brew compatibility shim"),
+        ("stats", "Show install timing and cache statistics"),
+        ("switch", "Switch the active version of a formula"),
         ("tap", "Manage third-party repositories"),
         ("uninstall", "Uninstall a formula"),
         ("unlink", "Remove symlinks for a keg"),
+        ("unpack", "Import a pack for offline installs"),
         ("unpin", "Unpin a formula"),
         ("untap", "Remove a tap repository"),
-        ("update", "Update zb to the latest version"),
+        ("update", "Refresh the formula list and taps"),
         ("upgrade", "Upgrade outdated formulas"),
+        (
+            "upgrade-window",
+            "Limit scheduled upgrades to maintenance windows",
+        ),
         ("uses", "Show which formulas use a given formula"),
+        (
+            "verify",
+            "Re-check installed kegs against their recorded hashes",
+        ),
+        (
+            "versions",
+            "List available and installed versions of a formula",
+        ),
+        (
+            "which",
+            "Show which executable a command runs, in PATH order",
+        ),
+        ("why", "Explain why a formula is installed"),
         ("commands", "List all available commands"),
     ];
 
@@ -1387,45 +3248,69 @@ mod tests {
         use clap::Parser;
 
         let cli = Cli::try_parse_from(["zb", "services", "list", "--json"]).unwrap();
+        assert!(cli.json);
         assert!(
             matches!(
                 cli.command,
                 Commands::Services {
-                    action: Some(ServicesAction::List { json: true }),
+                    action: Some(ServicesAction::List),
                 }
             ),
-            "Expected Services List command with json=true"
+            "Expected Services List command"
         );
 
         let cli = Cli::try_parse_from(["zb", "services", "list"]).unwrap();
-        assert!(
-            matches!(
-                cli.command,
-                Commands::Services {
-                    action: Some(ServicesAction::List { json: false }),
-                }
-            ),
-            "Expected Services List command with json=false"
-        );
+        assert!(!cli.json);
     }
 
     #[test]
-    fn test_services_enable_parsing() {
+    fn test_services_info_json_flag_parsing() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "services", "enable", "redis"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "services", "info", "redis", "--json"]).unwrap();
+        assert!(cli.json);
         match cli.command {
             Commands::Services {
-                action: Some(ServicesAction::Enable { formula }),
+                action: Some(ServicesAction::Info { formula }),
             } => {
                 assert_eq!(formula, "redis");
             }
-            _ => panic!("Expected Services Enable command"),
+            _ => panic!("Expected Services Info command"),
         }
     }
 
     #[test]
-    fn test_services_disable_parsing() {
+    fn test_services_adopt_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "services", "adopt", "postgresql@14"]).unwrap();
+        match cli.command {
+            Commands::Services {
+                action: Some(ServicesAction::Adopt { formula }),
+            } => {
+                assert_eq!(formula, "postgresql@14");
+            }
+            _ => panic!("Expected Services Adopt command"),
+        }
+    }
+
+    #[test]
+    fn test_services_enable_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "services", "enable", "redis"]).unwrap();
+        match cli.command {
+            Commands::Services {
+                action: Some(ServicesAction::Enable { formula }),
+            } => {
+                assert_eq!(formula, "redis");
+            }
+            _ => panic!("Expected Services Enable command"),
+        }
+    }
+
+    #[test]
+    fn test_services_disable_parsing() {
         use clap::Parser;
 
         let cli = Cli::try_parse_from(["zb", "services", "disable", "postgresql"]).unwrap();
@@ -1439,6 +3324,134 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_services_start_settings_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "services",
+            "start",
+            "redis",
+            "--user",
+            "redis",
+            "--umask",
+            "027",
+            "--nice",
+            "-5",
+            "--io-nice",
+            "6",
+            "--limit-nofile",
+            "4096",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Services {
+                action: Some(ServicesAction::Start { formula, settings }),
+            } => {
+                assert_eq!(formula, "redis");
+                let settings: zb_io::ServiceSettings = settings.into();
+                assert_eq!(settings.user.as_deref(), Some("redis"));
+                assert_eq!(settings.umask, Some(0o027));
+                assert_eq!(settings.nice, Some(-5));
+                assert_eq!(settings.io_nice, Some(6));
+                assert_eq!(settings.open_files, Some(4096));
+                assert_eq!(settings.processes, None);
+            }
+            _ => panic!("Expected Services Start command"),
+        }
+    }
+
+    #[test]
+    fn test_services_start_without_settings() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "services", "start", "redis"]).unwrap();
+        match cli.command {
+            Commands::Services {
+                action: Some(ServicesAction::Start { settings, .. }),
+            } => {
+                let settings: zb_io::ServiceSettings = settings.into();
+                assert!(settings.is_empty());
+            }
+            _ => panic!("Expected Services Start command"),
+        }
+    }
+
+    #[test]
+    fn test_services_config_reset_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb", "services", "config", "redis", "--reset", "--nice", "10",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Services {
+                action:
+                    Some(ServicesAction::Config {
+                        formula,
+                        settings,
+                        reset,
+                    }),
+            } => {
+                assert_eq!(formula, "redis");
+                assert!(reset);
+                assert_eq!(settings.nice, Some(10));
+            }
+            _ => panic!("Expected Services Config command"),
+        }
+    }
+
+    #[test]
+    fn test_services_settings_reject_out_of_range() {
+        use clap::Parser;
+
+        assert!(Cli::try_parse_from(["zb", "services", "start", "redis", "--nice", "20"]).is_err());
+        assert!(
+            Cli::try_parse_from(["zb", "services", "start", "redis", "--io-nice", "8"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["zb", "services", "start", "redis", "--umask", "888"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_services_config_restart_backoff_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "services",
+            "config",
+            "redis",
+            "--restart-delay",
+            "10",
+            "--restart-max-delay",
+            "300",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Services {
+                action: Some(ServicesAction::Config { settings, .. }),
+            } => {
+                let settings: zb_io::ServiceSettings = settings.into();
+                assert_eq!(settings.restart_delay, Some(10));
+                assert_eq!(settings.restart_max_delay, Some(300));
+            }
+            _ => panic!("Expected Services Config command"),
+        }
+    }
+
+    #[test]
+    fn test_parse_umask() {
+        assert_eq!(parse_umask("022"), Ok(0o022));
+        assert_eq!(parse_umask("0o077"), Ok(0o077));
+        assert_eq!(parse_umask("7"), Ok(0o7));
+        assert!(parse_umask("1000").is_err());
+        assert!(parse_umask("abc").is_err());
+    }
+
     #[test]
     fn test_commands_subcommand_parsing() {
         use clap::Parser;
@@ -1471,8 +3484,8 @@ mod tests {
         // Test versioned formula name parsing (e.g., python@3.11)
         let cli = Cli::try_parse_from(["zb", "install", "python@3.11"]).unwrap();
         match cli.command {
-            Commands::Install { formula, .. } => {
-                assert_eq!(formula, "python@3.11");
+            Commands::Install { formulas, .. } => {
+                assert_eq!(formulas, ["python@3.11"]);
             }
             _ => panic!("Expected Install command"),
         }
@@ -1548,9 +3561,9 @@ mod tests {
         let cli = Cli::try_parse_from(["zb", "install", "git", "--no-link"]).unwrap();
         match cli.command {
             Commands::Install {
-                formula, no_link, ..
+                formulas, no_link, ..
             } => {
-                assert_eq!(formula, "git");
+                assert_eq!(formulas, ["git"]);
                 assert!(no_link);
             }
             _ => panic!("Expected Install command"),
@@ -1564,25 +3577,49 @@ mod tests {
         let cli = Cli::try_parse_from(["zb", "install", "git", "-s"]).unwrap();
         match cli.command {
             Commands::Install {
-                formula,
+                formulas,
                 build_from_source,
                 ..
             } => {
-                assert_eq!(formula, "git");
+                assert_eq!(formulas, ["git"]);
                 assert!(build_from_source);
             }
             _ => panic!("Expected Install command"),
         }
     }
 
+    #[test]
+    fn test_install_build_args_are_repeatable() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "jq",
+            "--build-arg",
+            "--disable-docs",
+            "--build-arg=--with-oniguruma=builtin",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Install { build_args, .. } => {
+                assert_eq!(
+                    build_args,
+                    vec!["--disable-docs", "--with-oniguruma=builtin"]
+                );
+            }
+            _ => panic!("Expected Install command"),
+        }
+    }
+
     #[test]
     fn test_install_head_flag() {
         use clap::Parser;
 
         let cli = Cli::try_parse_from(["zb", "install", "neovim", "--head"]).unwrap();
         match cli.command {
-            Commands::Install { formula, head, .. } => {
-                assert_eq!(formula, "neovim");
+            Commands::Install { formulas, head, .. } => {
+                assert_eq!(formulas, ["neovim"]);
                 assert!(head);
             }
             _ => panic!("Expected Install command"),
@@ -1595,14 +3632,31 @@ mod tests {
 
         let cli = Cli::try_parse_from(["zb", "install", "neovim", "-H"]).unwrap();
         match cli.command {
-            Commands::Install { formula, head, .. } => {
-                assert_eq!(formula, "neovim");
+            Commands::Install { formulas, head, .. } => {
+                assert_eq!(formulas, ["neovim"]);
                 assert!(head);
             }
             _ => panic!("Expected Install command"),
         }
     }
 
+    #[test]
+    fn test_install_several_formulas() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "wget", "git", "--no-link"]).unwrap();
+        match cli.command {
+            Commands::Install {
+                formulas, no_link, ..
+            } => {
+                assert_eq!(formulas, ["jq", "wget", "git"]);
+                assert!(no_link);
+            }
+            _ => panic!("Expected Install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "install"]).is_err());
+    }
+
     // ========================================================================
     // Upgrade Command Tests
     // ========================================================================
@@ -1613,7 +3667,9 @@ mod tests {
 
         let cli = Cli::try_parse_from(["zb", "upgrade"]).unwrap();
         match cli.command {
-            Commands::Upgrade { formula, dry_run } => {
+            Commands::Upgrade {
+                formula, dry_run, ..
+            } => {
                 assert!(formula.is_none());
                 assert!(!dry_run);
             }
@@ -1621,13 +3677,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_upgrade_greedy() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "upgrade", "openssl@3", "--greedy"]).unwrap();
+        match cli.command {
+            Commands::Upgrade {
+                formula, greedy, ..
+            } => {
+                assert_eq!(formula.as_deref(), Some("openssl@3"));
+                assert!(greedy);
+            }
+            _ => panic!("Expected Upgrade command"),
+        }
+    }
+
     #[test]
     fn test_upgrade_specific_formula() {
         use clap::Parser;
 
         let cli = Cli::try_parse_from(["zb", "upgrade", "git"]).unwrap();
         match cli.command {
-            Commands::Upgrade { formula, dry_run } => {
+            Commands::Upgrade {
+                formula, dry_run, ..
+            } => {
                 assert_eq!(formula, Some("git".to_string()));
                 assert!(!dry_run);
             }
@@ -1641,7 +3715,9 @@ mod tests {
 
         let cli = Cli::try_parse_from(["zb", "upgrade", "--dry-run"]).unwrap();
         match cli.command {
-            Commands::Upgrade { formula, dry_run } => {
+            Commands::Upgrade {
+                formula, dry_run, ..
+            } => {
                 assert!(formula.is_none());
                 assert!(dry_run);
             }
@@ -1650,650 +3726,2213 @@ mod tests {
     }
 
     #[test]
-    fn test_outdated_json_flag() {
+    fn test_exec_command_takes_trailing_args() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "outdated", "--json"]).unwrap();
+        let cli =
+            Cli::try_parse_from(["zb", "exec", "node@18", "--", "node", "--version"]).unwrap();
         match cli.command {
-            Commands::Outdated { json } => {
-                assert!(json);
+            Commands::Exec { formula, command } => {
+                assert_eq!(formula, "node@18");
+                assert_eq!(command, vec!["node", "--version"]);
             }
-            _ => panic!("Expected Outdated command"),
+            _ => panic!("Expected Exec command"),
         }
+        assert!(Cli::try_parse_from(["zb", "exec", "node@18"]).is_err());
     }
 
-    // ========================================================================
-    // Info Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_info_json_flag() {
+    fn test_which_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "info", "git", "--json"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "which", "python3"]).unwrap();
         match cli.command {
-            Commands::Info { formula, json } => {
-                assert_eq!(formula, "git");
-                assert!(json);
-            }
-            _ => panic!("Expected Info command"),
+            Commands::Which { command } => assert_eq!(command, "python3"),
+            _ => panic!("Expected Which command"),
         }
+        assert!(Cli::try_parse_from(["zb", "which"]).is_err());
     }
 
     #[test]
-    fn test_list_pinned_flag() {
+    fn test_verify_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "list", "--pinned"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "verify", "jq", "wget"]).unwrap();
         match cli.command {
-            Commands::List { pinned } => {
-                assert!(pinned);
-            }
-            _ => panic!("Expected List command"),
+            Commands::Verify { formulas } => assert_eq!(formulas, ["jq", "wget"]),
+            _ => panic!("Expected Verify command"),
         }
+        let cli = Cli::try_parse_from(["zb", "verify"]).unwrap();
+        assert!(matches!(cli.command, Commands::Verify { formulas } if formulas.is_empty()));
     }
 
-    // ========================================================================
-    // Bundle Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_bundle_default_install() {
+    fn test_config_diff_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "bundle"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "config-diff", "redis"]).unwrap();
         match cli.command {
-            Commands::Bundle { action } => {
-                assert!(action.is_none());
-            }
-            _ => panic!("Expected Bundle command"),
+            Commands::ConfigDiff { formula } => assert_eq!(formula, "redis"),
+            _ => panic!("Expected ConfigDiff command"),
         }
+        assert!(Cli::try_parse_from(["zb", "config-diff"]).is_err());
     }
 
     #[test]
-    fn test_bundle_install_with_file() {
+    fn test_upgrade_json_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "bundle", "install", "--file", "MyBrewfile"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "upgrade", "--json", "--dry-run"]).unwrap();
+        assert!(cli.json);
         match cli.command {
-            Commands::Bundle {
-                action: Some(BundleAction::Install { file }),
-            } => {
-                assert_eq!(file, Some(PathBuf::from("MyBrewfile")));
+            Commands::Upgrade { dry_run, .. } => {
+                assert!(dry_run);
             }
-            _ => panic!("Expected Bundle Install command"),
+            _ => panic!("Expected Upgrade command"),
         }
     }
 
     #[test]
-    fn test_bundle_dump_describe() {
+    fn test_outdated_json_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "bundle", "dump", "--describe"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "outdated", "--json"]).unwrap();
+        assert!(cli.json);
         match cli.command {
-            Commands::Bundle {
-                action:
-                    Some(BundleAction::Dump {
-                        describe,
-                        force,
-                        file,
-                    }),
-            } => {
-                assert!(describe);
-                assert!(!force);
-                assert!(file.is_none());
+            Commands::Outdated { fetch_head, cask } => {
+                assert!(!fetch_head);
+                assert!(!cask);
             }
-            _ => panic!("Expected Bundle Dump command"),
+            _ => panic!("Expected Outdated command"),
         }
     }
 
     #[test]
-    fn test_bundle_dump_force_file() {
+    fn test_upgrade_fetch_head_flag() {
         use clap::Parser;
 
-        let cli =
-            Cli::try_parse_from(["zb", "bundle", "dump", "--force", "--file", "out.txt"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "upgrade", "neovim", "--fetch-HEAD"]).unwrap();
         match cli.command {
-            Commands::Bundle {
-                action:
-                    Some(BundleAction::Dump {
-                        describe,
-                        force,
-                        file,
-                    }),
+            Commands::Upgrade {
+                formula,
+                fetch_head,
+                ..
             } => {
-                assert!(!describe);
-                assert!(force);
-                assert_eq!(file, Some(PathBuf::from("out.txt")));
+                assert_eq!(formula.as_deref(), Some("neovim"));
+                assert!(fetch_head);
             }
-            _ => panic!("Expected Bundle Dump command"),
+            _ => panic!("Expected Upgrade command"),
         }
     }
 
     #[test]
-    fn test_bundle_dump_short_flags() {
+    fn test_upgrade_scheduled_flags() {
         use clap::Parser;
 
-        // -F for force (uppercase to avoid conflict with -f for file)
-        let cli = Cli::try_parse_from(["zb", "bundle", "dump", "-F", "-f", "Brewfile"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "upgrade", "--scheduled", "--now"]).unwrap();
         match cli.command {
-            Commands::Bundle {
-                action:
-                    Some(BundleAction::Dump {
-                        describe,
-                        force,
-                        file,
-                    }),
-            } => {
-                assert!(!describe);
-                assert!(force);
-                assert_eq!(file, Some(PathBuf::from("Brewfile")));
+            Commands::Upgrade { scheduled, now, .. } => {
+                assert!(scheduled);
+                assert!(now);
             }
-            _ => panic!("Expected Bundle Dump command"),
+            _ => panic!("Expected Upgrade command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "upgrade-window", "sat,sun 2:00-5:00"]).unwrap();
+        match cli.command {
+            Commands::UpgradeWindow { window, remove } => {
+                assert_eq!(window.unwrap().to_string(), "Sat,Sun 02:00-05:00");
+                assert!(!remove);
+            }
+            _ => panic!("Expected UpgradeWindow command"),
         }
+
+        assert!(Cli::try_parse_from(["zb", "upgrade-window", "someday"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "upgrade-window", "--remove"]).is_err());
     }
 
     #[test]
-    fn test_bundle_check_strict() {
+    fn test_format_upgrade_window_message() {
+        assert_eq!(
+            format_upgrade_window_message("Sun", false, true),
+            "Scheduled upgrades may run during Sun"
+        );
+        assert_eq!(
+            format_upgrade_window_message("Sun", true, false),
+            "Sun is not an upgrade window"
+        );
+    }
+
+    #[test]
+    fn test_outdated_fetch_head_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "bundle", "check", "--strict"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "outdated", "--fetch-HEAD"]).unwrap();
         match cli.command {
-            Commands::Bundle {
-                action: Some(BundleAction::Check { strict, file }),
-            } => {
-                assert!(strict);
-                assert!(file.is_none());
-            }
-            _ => panic!("Expected Bundle Check command"),
+            Commands::Outdated { fetch_head, .. } => assert!(fetch_head),
+            _ => panic!("Expected Outdated command"),
         }
     }
 
     #[test]
-    fn test_bundle_list() {
+    fn test_outdated_cask_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "bundle", "list"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "outdated", "--cask"]).unwrap();
         match cli.command {
-            Commands::Bundle {
-                action: Some(BundleAction::List { file }),
-            } => {
-                assert!(file.is_none());
-            }
-            _ => panic!("Expected Bundle List command"),
+            Commands::Outdated { cask, .. } => assert!(cask),
+            _ => panic!("Expected Outdated command"),
         }
     }
 
     // ========================================================================
-    // Search Command Tests
+    // Info Command Tests
     // ========================================================================
 
     #[test]
-    fn test_search_basic() {
+    fn test_info_json_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "info", "git", "--json"]).unwrap();
+        assert!(cli.json);
+        match cli.command {
+            Commands::Info { formula, files, .. } => {
+                assert_eq!(formula, "git");
+                assert!(!files);
+            }
+            _ => panic!("Expected Info command"),
+        }
+    }
+
+    #[test]
+    fn test_versions_command() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "versions", "node", "--json"]).unwrap();
+        assert!(cli.json);
+        match cli.command {
+            Commands::Versions { formula } => {
+                assert_eq!(formula, "node");
+            }
+            _ => panic!("Expected Versions command"),
+        }
+    }
+
+    #[test]
+    fn test_list_pinned_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "list", "--pinned"]).unwrap();
+        match cli.command {
+            Commands::List { pinned, .. } => {
+                assert!(pinned);
+            }
+            _ => panic!("Expected List command"),
+        }
+    }
+
+    #[test]
+    fn test_list_installed_by() {
+        use clap::Parser;
+
+        let cli =
+            Cli::try_parse_from(["zb", "list", "--installed-by", "dependency_of:node"]).unwrap();
+        match cli.command {
+            Commands::List { installed_by, .. } => {
+                assert_eq!(installed_by.as_deref(), Some("dependency_of:node"));
+            }
+            _ => panic!("Expected List command"),
+        }
+    }
+
+    #[test]
+    fn test_list_full_and_sort() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "list", "--full", "--sort", "size"]).unwrap();
+        match cli.command {
+            Commands::List {
+                full,
+                sort,
+                versions,
+                ..
+            } => {
+                assert!(full);
+                assert!(!versions);
+                assert_eq!(sort, zb_io::InventorySort::Size);
+            }
+            _ => panic!("Expected List command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "list", "--versions"]).unwrap();
+        match cli.command {
+            Commands::List { versions, sort, .. } => {
+                assert!(versions);
+                assert_eq!(sort, zb_io::InventorySort::Name);
+            }
+            _ => panic!("Expected List command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "list", "--sort", "age"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "list", "--versions", "--full"]).is_err());
+    }
+
+    #[test]
+    fn test_info_files_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "info", "jq", "--files"]).unwrap();
+        match cli.command {
+            Commands::Info { formula, files, .. } => {
+                assert_eq!(formula, "jq");
+                assert!(files);
+            }
+            _ => panic!("Expected Info command"),
+        }
+    }
+
+    #[test]
+    fn test_info_badge_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "info", "jq", "--badge", "docs/jq.svg"]).unwrap();
+        match cli.command {
+            Commands::Info { formula, badge, .. } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(badge, Some(PathBuf::from("docs/jq.svg")));
+            }
+            _ => panic!("Expected Info command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "info", "jq", "--badge", "a.svg", "--files"]).is_err());
+    }
+
+    #[test]
+    fn test_list_formula_files() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "list", "jq", "--files"]).unwrap();
+        match cli.command {
+            Commands::List { formula, files, .. } => {
+                assert_eq!(formula.as_deref(), Some("jq"));
+                assert!(files);
+            }
+            _ => panic!("Expected List command"),
+        }
+
+        // A formula needs --files and vice versa
+        assert!(Cli::try_parse_from(["zb", "list", "jq"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "list", "--files"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "list", "jq", "--files", "--pinned"]).is_err());
+    }
+
+    #[test]
+    fn test_list_deprecated() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "list", "--deprecated"]).unwrap();
+        match cli.command {
+            Commands::List { deprecated, .. } => assert!(deprecated),
+            _ => panic!("Expected List command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "list", "--deprecated", "--pinned"]).is_err());
+    }
+
+    // ========================================================================
+    // Bundle Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_bundle_default_install() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle"]).unwrap();
+        match cli.command {
+            Commands::Bundle { action } => {
+                assert!(action.is_none());
+            }
+            _ => panic!("Expected Bundle command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_install_with_file() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "install", "--file", "MyBrewfile"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action: Some(BundleAction::Install { file, locked }),
+            } => {
+                assert_eq!(file, Some(PathBuf::from("MyBrewfile")));
+                assert!(!locked);
+            }
+            _ => panic!("Expected Bundle Install command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_lock_and_locked_install() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "lock"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Bundle {
+                action: Some(BundleAction::Lock { file: None })
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "install", "--locked"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Bundle {
+                action: Some(BundleAction::Install {
+                    file: None,
+                    locked: true
+                })
+            }
+        ));
+    }
+
+    #[test]
+    fn test_bundle_dump_describe() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "dump", "--describe"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action:
+                    Some(BundleAction::Dump {
+                        describe,
+                        force,
+                        file,
+                    }),
+            } => {
+                assert!(describe);
+                assert!(!force);
+                assert!(file.is_none());
+            }
+            _ => panic!("Expected Bundle Dump command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_dump_force_file() {
+        use clap::Parser;
+
+        let cli =
+            Cli::try_parse_from(["zb", "bundle", "dump", "--force", "--file", "out.txt"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action:
+                    Some(BundleAction::Dump {
+                        describe,
+                        force,
+                        file,
+                    }),
+            } => {
+                assert!(!describe);
+                assert!(force);
+                assert_eq!(file, Some(PathBuf::from("out.txt")));
+            }
+            _ => panic!("Expected Bundle Dump command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_dump_short_flags() {
+        use clap::Parser;
+
+        // -F for force (uppercase to avoid conflict with -f for file)
+        let cli = Cli::try_parse_from(["zb", "bundle", "dump", "-F", "-f", "Brewfile"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action:
+                    Some(BundleAction::Dump {
+                        describe,
+                        force,
+                        file,
+                    }),
+            } => {
+                assert!(!describe);
+                assert!(force);
+                assert_eq!(file, Some(PathBuf::from("Brewfile")));
+            }
+            _ => panic!("Expected Bundle Dump command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_check_strict() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "check", "--strict"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action: Some(BundleAction::Check { strict, file }),
+            } => {
+                assert!(strict);
+                assert!(file.is_none());
+            }
+            _ => panic!("Expected Bundle Check command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_list() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "list"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action: Some(BundleAction::List { file }),
+            } => {
+                assert!(file.is_none());
+            }
+            _ => panic!("Expected Bundle List command"),
+        }
+    }
+
+    #[test]
+    fn test_bundle_env() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "env", "--shell", "fish"]).unwrap();
+        match cli.command {
+            Commands::Bundle {
+                action: Some(BundleAction::Env { file, shell }),
+            } => {
+                assert!(file.is_none());
+                assert_eq!(shell.as_deref(), Some("fish"));
+            }
+            _ => panic!("Expected Bundle Env command"),
+        }
+    }
+
+    // ========================================================================
+    // Search Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_search_basic() {
         use clap::Parser;
 
         let cli = Cli::try_parse_from(["zb", "search", "git"]).unwrap();
+        assert!(!cli.json);
+        match cli.command {
+            Commands::Search {
+                query, installed, ..
+            } => {
+                assert_eq!(query, "git");
+                assert!(!installed);
+            }
+            _ => panic!("Expected Search command"),
+        }
+    }
+
+    #[test]
+    fn test_search_json_installed() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "search", "python", "--json", "--installed"]).unwrap();
+        assert!(cli.json);
+        match cli.command {
+            Commands::Search {
+                query, installed, ..
+            } => {
+                assert_eq!(query, "python");
+                assert!(installed);
+            }
+            _ => panic!("Expected Search command"),
+        }
+    }
+
+    #[test]
+    fn test_search_scope_flags() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "search", "acme", "--full-name"]).unwrap();
+        match cli.command {
+            Commands::Search {
+                full_name, alias, ..
+            } => {
+                assert!(full_name);
+                assert!(!alias);
+            }
+            _ => panic!("Expected Search command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "search", "rg", "--alias"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Search {
+                full_name: false,
+                alias: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_search_scope_flags_conflict() {
+        use clap::Parser;
+
+        let result = Cli::try_parse_from(["zb", "search", "x", "--full-name", "--alias"]);
+        assert!(result.is_err());
+    }
+
+    // ========================================================================
+    // Deps Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_deps_tree_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "deps", "git", "--tree"]).unwrap();
+        match cli.command {
+            Commands::Deps {
+                formula,
+                tree,
+                installed,
+                all,
+                ..
+            } => {
+                assert_eq!(formula, Some("git".to_string()));
+                assert!(tree);
+                assert!(!installed);
+                assert!(!all);
+            }
+            _ => panic!("Expected Deps command"),
+        }
+    }
+
+    #[test]
+    fn test_deps_all_installed() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "deps", "neovim", "--installed", "-1"]).unwrap();
+        match cli.command {
+            Commands::Deps {
+                formula,
+                tree,
+                installed,
+                all,
+                ..
+            } => {
+                assert_eq!(formula, Some("neovim".to_string()));
+                assert!(!tree);
+                assert!(installed);
+                assert!(all);
+            }
+            _ => panic!("Expected Deps command"),
+        }
+    }
+
+    #[test]
+    fn test_deps_graph_flags() {
+        use clap::Parser;
+        use commands::deps::GraphFormat;
+
+        let cli = Cli::try_parse_from(["zb", "deps", "--graph", "mermaid"]).unwrap();
+        match cli.command {
+            Commands::Deps { formula, graph, .. } => {
+                assert_eq!(formula, None);
+                assert_eq!(graph, Some(GraphFormat::Mermaid));
+            }
+            _ => panic!("Expected Deps command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "deps", "wget", "--graph"]).unwrap();
+        match cli.command {
+            Commands::Deps { formula, graph, .. } => {
+                assert_eq!(formula.as_deref(), Some("wget"));
+                assert_eq!(graph, Some(GraphFormat::Dot));
+            }
+            _ => panic!("Expected Deps command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "deps", "--dot"]).unwrap();
+        assert!(matches!(cli.command, Commands::Deps { dot: true, .. }));
+
+        assert!(Cli::try_parse_from(["zb", "deps"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "deps", "--graph", "svg"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "deps", "git", "--graph", "--tree"]).is_err());
+    }
+
+    #[test]
+    fn test_deps_annotate_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "deps", "wget", "--annotate"]).unwrap();
+        match cli.command {
+            Commands::Deps {
+                formula, annotate, ..
+            } => {
+                assert_eq!(formula.as_deref(), Some("wget"));
+                assert!(annotate);
+            }
+            _ => panic!("Expected Deps command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "deps", "wget", "--annotate", "--dot"]).is_err());
+    }
+
+    // ========================================================================
+    // Link/Unlink Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_link_overwrite_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "link", "openssl", "--overwrite"]).unwrap();
+        match cli.command {
+            Commands::Link {
+                formula,
+                overwrite,
+                force,
+            } => {
+                assert_eq!(formula, "openssl");
+                assert!(overwrite);
+                assert!(!force);
+            }
+            _ => panic!("Expected Link command"),
+        }
+    }
+
+    #[test]
+    fn test_unlink_parsing() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "unlink", "python@3.11"]).unwrap();
+        match cli.command {
+            Commands::Unlink { formula } => {
+                assert_eq!(formula, "python@3.11");
+            }
+            _ => panic!("Expected Unlink command"),
+        }
+    }
+
+    // ========================================================================
+    // Cleanup Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_cleanup_prune_days() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "cleanup", "--prune", "30"]).unwrap();
+        match cli.command {
+            Commands::Cleanup { dry_run, prune, .. } => {
+                assert!(!dry_run);
+                assert_eq!(prune, Some(30));
+            }
+            _ => panic!("Expected Cleanup command"),
+        }
+    }
+
+    #[test]
+    fn test_cleanup_dry_run() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "cleanup", "--dry-run"]).unwrap();
+        match cli.command {
+            Commands::Cleanup { dry_run, prune, .. } => {
+                assert!(dry_run);
+                assert!(prune.is_none());
+            }
+            _ => panic!("Expected Cleanup command"),
+        }
+    }
+
+    #[test]
+    fn test_cleanup_untracked() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "cleanup", "--untracked", "adopt"]).unwrap();
+        match cli.command {
+            Commands::Cleanup { untracked, .. } => {
+                assert_eq!(untracked, Some(zb_io::UntrackedAction::Adopt));
+            }
+            _ => panic!("Expected Cleanup command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "cleanup", "--untracked", "delete"]).is_err());
+    }
+
+    #[test]
+    fn test_format_drift_lines_and_note() {
+        let keg = |name: &str| zb_io::DriftedKeg {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            path: PathBuf::from(format!("/opt/zerobrew/Cellar/{name}/1.0")),
+        };
+        let drift = zb_io::CellarDrift {
+            untracked: vec![keg("stray"), keg("leftover")],
+            missing: vec![keg("ghost")],
+        };
+
+        assert_eq!(
+            format_drift_lines(&drift, zb_io::UntrackedAction::Adopt, true),
+            [
+                "Would forget 1 kegs missing from the Cellar",
+                "Would adopt 2 untracked kegs"
+            ]
+        );
+        assert_eq!(
+            format_drift_lines(&drift, zb_io::UntrackedAction::Remove, false),
+            [
+                "Forgot 1 kegs missing from the Cellar",
+                "Removed 2 untracked kegs"
+            ]
+        );
+        assert!(
+            format_drift_note(&drift)
+                .unwrap()
+                .starts_with("2 untracked kegs in the Cellar and 1 recorded kegs missing")
+        );
+        assert_eq!(format_drift_note(&zb_io::CellarDrift::default()), None);
+
+        let json = drifted_kegs_json(&drift.missing);
+        assert_eq!(json[0]["name"], "ghost");
+        assert_eq!(json[0]["path"], "/opt/zerobrew/Cellar/ghost/1.0");
+    }
+
+    // ========================================================================
+    // Global Options Tests
+    // ========================================================================
+
+    #[test]
+    fn test_custom_root_and_prefix() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--root",
+            "/custom/root",
+            "--prefix",
+            "/custom/prefix",
+            "list",
+        ])
+        .unwrap();
+        assert_eq!(cli.root, PathBuf::from("/custom/root"));
+        assert_eq!(cli.prefix, PathBuf::from("/custom/prefix"));
+    }
+
+    #[test]
+    fn test_format_fd_clamp_warning() {
+        let limits = ConcurrencyLimits {
+            downloads: 41,
+            extractions: 54,
+        };
+        let warning = format_fd_clamp_warning(256, limits);
+        assert!(warning.contains("open file limit is 256"));
+        assert!(warning.contains("only 41 downloads and 54 extractions"));
+        assert!(warning.contains("ulimit -n"));
+    }
+
+    #[test]
+    fn test_custom_concurrency() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "--concurrency", "16", "install", "git"]).unwrap();
+        assert_eq!(cli.concurrency, 16);
+    }
+
+    #[test]
+    fn test_default_values() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        assert_eq!(cli.root, PathBuf::from("/opt/zerobrew"));
+        assert_eq!(cli.prefix, PathBuf::from("/opt/zerobrew/prefix"));
+        assert_eq!(cli.concurrency, 48);
+        assert_eq!(cli.extract_concurrency, None);
+    }
+
+    #[test]
+    fn test_extract_concurrency() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "--extract-concurrency", "2", "list"]).unwrap();
+        assert_eq!(cli.extract_concurrency, Some(2));
+    }
+
+    #[test]
+    fn test_apply_config_fills_unset_flags() {
+        use clap::Parser;
+
+        let config = Config::parse(
+            r#"
+            root = "/srv/zb"
+            prefix = "/srv/zb/prefix"
+            concurrency = 8
+            extract_concurrency = 2
+            mirror = "https://mirror.example.com"
+            "#,
+        )
+        .unwrap();
+
+        let mut cli = Cli::try_parse_from(["zb", "--concurrency", "4", "list"]).unwrap();
+        apply_config(&mut cli, config.clone(), |id| id == "concurrency");
+        assert_eq!(cli.root, PathBuf::from("/srv/zb"));
+        assert_eq!(cli.prefix, PathBuf::from("/srv/zb/prefix"));
+        assert_eq!(cli.concurrency, 4);
+        assert_eq!(cli.extract_concurrency, Some(2));
+        assert_eq!(
+            installer_context(&cli).mirror.as_deref(),
+            Some("https://mirror.example.com")
+        );
+
+        let mut cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        apply_config(&mut cli, Config::default(), |_| false);
+        assert_eq!(cli.root, PathBuf::from(DEFAULT_ROOT));
+        assert_eq!(cli.concurrency, 48);
+        assert_eq!(cli.extract_concurrency, None);
+    }
+
+    #[test]
+    fn test_shim_prefix_follows_config() {
+        let config = Config::parse(r#"prefix = "/srv/zb/prefix""#).unwrap();
+        assert_eq!(shim_prefix(&config), PathBuf::from("/srv/zb/prefix"));
+        assert_eq!(
+            shim_prefix(&Config::default()),
+            PathBuf::from(DEFAULT_PREFIX)
+        );
+    }
+
+    #[test]
+    fn test_cleans_up_after() {
+        use clap::Parser;
+
+        let cleans_up =
+            |args: &[&str]| cleans_up_after(&Cli::try_parse_from(args).unwrap().command);
+        assert!(cleans_up(&["zb", "install", "jq"]));
+        assert!(cleans_up(&["zb", "uninstall", "jq"]));
+        assert!(cleans_up(&["zb", "upgrade"]));
+        assert!(!cleans_up(&["zb", "upgrade", "--dry-run"]));
+        assert!(!cleans_up(&[
+            "zb",
+            "install",
+            "jq",
+            "--emit-plan",
+            "plan.json"
+        ]));
+        assert!(!cleans_up(&["zb", "list"]));
+    }
+
+    #[test]
+    fn test_installer_context_from_global_flags() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--root",
+            "/tmp/zb",
+            "--prefix",
+            "/tmp/zb/p",
+            "--concurrency",
+            "8",
+            "--extract-concurrency",
+            "2",
+            "--offline",
+            "list",
+        ])
+        .unwrap();
+        let context = installer_context(&cli);
+        assert_eq!(context.paths.root, PathBuf::from("/tmp/zb"));
+        assert_eq!(context.paths.cellar, PathBuf::from("/tmp/zb/p/Cellar"));
+        assert_eq!(context.concurrency.downloads, 8);
+        assert_eq!(context.concurrency.extractions, 2);
+        assert!(context.offline);
+
+        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        assert!(!installer_context(&cli).offline);
+        assert_eq!(
+            installer_context(&cli).concurrency,
+            ConcurrencyLimits::default()
+        );
+
+        assert!(Cli::try_parse_from(["zb", "--offline", "--no-cache", "list"]).is_err());
+    }
+
+    // ========================================================================
+    // Services Log Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_services_log_with_lines() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "services", "log", "redis", "-n", "50"]).unwrap();
+        match cli.command {
+            Commands::Services {
+                action:
+                    Some(ServicesAction::Log {
+                        formula,
+                        lines,
+                        follow,
+                    }),
+            } => {
+                assert_eq!(formula, "redis");
+                assert_eq!(lines, 50);
+                assert!(!follow);
+            }
+            _ => panic!("Expected Services Log command"),
+        }
+    }
+
+    #[test]
+    fn test_services_log_follow() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "services", "log", "postgresql", "--follow"]).unwrap();
+        match cli.command {
+            Commands::Services {
+                action:
+                    Some(ServicesAction::Log {
+                        formula,
+                        lines,
+                        follow,
+                    }),
+            } => {
+                assert_eq!(formula, "postgresql");
+                assert_eq!(lines, 20); // default
+                assert!(follow);
+            }
+            _ => panic!("Expected Services Log command"),
+        }
+    }
+
+    // ========================================================================
+    // Pin/Unpin Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_pin_versioned_formula() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "pin", "node@20"]).unwrap();
+        match cli.command {
+            Commands::Pin { formula } => {
+                assert_eq!(formula, "node@20");
+            }
+            _ => panic!("Expected Pin command"),
+        }
+    }
+
+    #[test]
+    fn test_unpin_formula() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "unpin", "git"]).unwrap();
+        match cli.command {
+            Commands::Unpin { formula } => {
+                assert_eq!(formula, "git");
+            }
+            _ => panic!("Expected Unpin command"),
+        }
+    }
+
+    // ========================================================================
+    // Tap/Untap Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_tap_list() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "tap"]).unwrap();
+        match cli.command {
+            Commands::Tap { user_repo, .. } => {
+                assert!(user_repo.is_none());
+            }
+            _ => panic!("Expected Tap command"),
+        }
+    }
+
+    #[test]
+    fn test_tap_add() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "tap", "homebrew/cask"]).unwrap();
+        match cli.command {
+            Commands::Tap { user_repo, .. } => {
+                assert_eq!(user_repo, Some("homebrew/cask".to_string()));
+            }
+            _ => panic!("Expected Tap command"),
+        }
+    }
+
+    #[test]
+    fn test_tap_pin_and_unpin() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "tap", "pin", "user/tools"]).unwrap();
+        match cli.command {
+            Commands::Tap {
+                action: Some(TapAction::Pin { user_repo }),
+                user_repo: None,
+            } => assert_eq!(user_repo, "user/tools"),
+            _ => panic!("Expected Tap pin command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "tap", "unpin", "user/tools"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Tap {
+                action: Some(TapAction::Unpin { .. }),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_untap() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "untap", "homebrew/cask"]).unwrap();
+        match cli.command {
+            Commands::Untap { user_repo } => {
+                assert_eq!(user_repo, "homebrew/cask");
+            }
+            _ => panic!("Expected Untap command"),
+        }
+    }
+
+    // ========================================================================
+    // Reset Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_reset_with_yes_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "reset", "--yes"]).unwrap();
+        match cli.command {
+            Commands::Reset { yes } => {
+                assert!(yes);
+            }
+            _ => panic!("Expected Reset command"),
+        }
+    }
+
+    #[test]
+    fn test_reset_short_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "reset", "-y"]).unwrap();
+        match cli.command {
+            Commands::Reset { yes } => {
+                assert!(yes);
+            }
+            _ => panic!("Expected Reset command"),
+        }
+    }
+
+    // ========================================================================
+    // Autoremove Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_autoremove_dry_run() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "autoremove", "--dry-run"]).unwrap();
+        match cli.command {
+            Commands::Autoremove { dry_run, except } => {
+                assert!(dry_run);
+                assert!(except.is_empty());
+            }
+            _ => panic!("Expected Autoremove command"),
+        }
+    }
+
+    #[test]
+    fn test_autoremove_except() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "autoremove", "--except", "python,libyaml"]).unwrap();
+        match cli.command {
+            Commands::Autoremove { except, .. } => {
+                assert_eq!(except, vec!["python", "libyaml"]);
+            }
+            _ => panic!("Expected Autoremove command"),
+        }
+    }
+
+    #[test]
+    fn test_mark_command() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "mark", "--explicit", "python", "jq"]).unwrap();
+        match cli.command {
+            Commands::Mark {
+                formulas,
+                explicit,
+                auto,
+            } => {
+                assert_eq!(formulas, vec!["python", "jq"]);
+                assert!(explicit);
+                assert!(!auto);
+            }
+            _ => panic!("Expected Mark command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "mark", "--auto", "python"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Mark {
+                explicit: false,
+                auto: true,
+                ..
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "mark", "python"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "mark", "--explicit", "--auto", "python"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "mark", "--explicit"]).is_err());
+    }
+
+    #[test]
+    fn test_format_mark_message() {
+        assert_eq!(
+            format_mark_message("python", true, true),
+            "Marked python as installed explicitly"
+        );
+        assert_eq!(
+            format_mark_message("python", false, false),
+            "python is already marked as installed as a dependency"
+        );
+    }
+
+    #[test]
+    fn test_env_command() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "env",
+            "git",
+            "GIT_EXEC_PATH={opt}/libexec/git-core",
+            "--unset",
+            "GIT_TEMPLATE_DIR",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Env {
+                formula,
+                assignments,
+                unset,
+            } => {
+                assert_eq!(formula.as_deref(), Some("git"));
+                assert_eq!(
+                    assignments,
+                    [(
+                        "GIT_EXEC_PATH".to_string(),
+                        "{opt}/libexec/git-core".to_string()
+                    )]
+                );
+                assert_eq!(unset, ["GIT_TEMPLATE_DIR"]);
+            }
+            _ => panic!("expected env command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "env"]).unwrap();
+        assert!(matches!(cli.command, Commands::Env { formula: None, .. }));
+
+        assert!(Cli::try_parse_from(["zb", "env", "git", "NOVALUE"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "env", "git", "1BAD=x"]).is_err());
+    }
+
+    #[test]
+    fn test_format_env_lines() {
+        let env = std::collections::BTreeMap::from([
+            ("PYTHONHOME".to_string(), "{opt}".to_string()),
+            ("LANG".to_string(), "C".to_string()),
+        ]);
+        assert_eq!(format_env_lines(&env), ["LANG=C", "PYTHONHOME={opt}"]);
+    }
+
+    #[test]
+    fn test_keep_command() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "keep", "python"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Keep {
+                formula: Some(_),
+                remove: false
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "keep"]).unwrap();
+        assert!(matches!(cli.command, Commands::Keep { formula: None, .. }));
+
+        assert!(Cli::try_parse_from(["zb", "keep", "--remove"]).is_err());
+    }
+
+    #[test]
+    fn test_format_keep_message() {
+        assert_eq!(
+            format_keep_message("python", false, true),
+            "Kept python; autoremove will not remove it"
+        );
+        assert_eq!(
+            format_keep_message("python", false, false),
+            "python is already kept"
+        );
+        assert_eq!(
+            format_keep_message("python", true, true),
+            "python is no longer kept"
+        );
+        assert_eq!(
+            format_keep_message("python", true, false),
+            "python is not kept"
+        );
+    }
+
+    // ========================================================================
+    // Uses Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_uses_recursive() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "uses", "openssl", "--recursive"]).unwrap();
+        match cli.command {
+            Commands::Uses {
+                formula, recursive, ..
+            } => {
+                assert_eq!(formula, "openssl");
+                assert!(recursive);
+            }
+            _ => panic!("Expected Uses command"),
+        }
+    }
+
+    #[test]
+    fn test_uses_eval_all() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "uses", "openssl", "--eval-all"]).unwrap();
+        match cli.command {
+            Commands::Uses { eval_all, .. } => assert!(eval_all),
+            _ => panic!("Expected Uses command"),
+        }
+
+        assert!(
+            Cli::try_parse_from(["zb", "uses", "openssl", "--eval-all", "--installed"]).is_err()
+        );
+    }
+
+    // ========================================================================
+    // Update Command Tests
+    // ========================================================================
+
+    #[test]
+    fn test_update_default() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "update"]).unwrap();
         match cli.command {
-            Commands::Search {
-                query,
-                json,
-                installed,
+            Commands::Update {
+                self_update,
+                dry_run,
+                force,
             } => {
-                assert_eq!(query, "git");
-                assert!(!json);
-                assert!(!installed);
+                assert!(!self_update);
+                assert!(!dry_run);
+                assert!(!force);
             }
-            _ => panic!("Expected Search command"),
+            _ => panic!("Expected Update command"),
         }
     }
 
     #[test]
-    fn test_search_json_installed() {
+    fn test_update_dry_run() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "search", "python", "--json", "--installed"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "update", "--self", "--dry-run"]).unwrap();
         match cli.command {
-            Commands::Search {
-                query,
-                json,
-                installed,
+            Commands::Update {
+                self_update,
+                dry_run,
+                force,
             } => {
-                assert_eq!(query, "python");
-                assert!(json);
-                assert!(installed);
+                assert!(self_update);
+                assert!(dry_run);
+                assert!(!force);
             }
-            _ => panic!("Expected Search command"),
+            _ => panic!("Expected Update command"),
+        }
+    }
+
+    #[test]
+    fn test_update_force() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "update", "--self", "--force"]).unwrap();
+        match cli.command {
+            Commands::Update {
+                self_update,
+                dry_run,
+                force,
+            } => {
+                assert!(self_update);
+                assert!(!dry_run);
+                assert!(force);
+            }
+            _ => panic!("Expected Update command"),
+        }
+    }
+
+    #[test]
+    fn test_update_both_flags() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "update", "--self", "--dry-run", "--force"]).unwrap();
+        match cli.command {
+            Commands::Update {
+                self_update,
+                dry_run,
+                force,
+            } => {
+                assert!(self_update);
+                assert!(dry_run);
+                assert!(force);
+            }
+            _ => panic!("Expected Update command"),
         }
     }
 
+    #[test]
+    fn test_update_self_flags_require_self() {
+        use clap::Parser;
+
+        assert!(Cli::try_parse_from(["zb", "update", "--dry-run"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "update", "--force"]).is_err());
+        assert!(supports_json(
+            &Cli::try_parse_from(["zb", "update"]).unwrap().command
+        ));
+    }
+
     // ========================================================================
-    // Deps Command Tests
+    // Shellenv Command Tests
     // ========================================================================
 
     #[test]
-    fn test_deps_tree_flag() {
+    fn test_shellenv_default() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "deps", "git", "--tree"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "shellenv"]).unwrap();
         match cli.command {
-            Commands::Deps {
-                formula,
-                tree,
-                installed,
-                all,
-            } => {
-                assert_eq!(formula, "git");
-                assert!(tree);
-                assert!(!installed);
-                assert!(!all);
+            Commands::Shellenv { shell, check } => {
+                assert!(shell.is_none());
+                assert!(!check);
             }
-            _ => panic!("Expected Deps command"),
+            _ => panic!("Expected Shellenv command"),
         }
     }
 
     #[test]
-    fn test_deps_all_installed() {
+    fn test_shellenv_fish() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "deps", "neovim", "--installed", "-1"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "shellenv", "--shell", "fish"]).unwrap();
         match cli.command {
-            Commands::Deps {
-                formula,
-                tree,
-                installed,
-                all,
-            } => {
-                assert_eq!(formula, "neovim");
-                assert!(!tree);
-                assert!(installed);
-                assert!(all);
+            Commands::Shellenv { shell, .. } => {
+                assert_eq!(shell, Some("fish".to_string()));
             }
-            _ => panic!("Expected Deps command"),
+            _ => panic!("Expected Shellenv command"),
         }
     }
 
+    #[test]
+    fn test_shellenv_check() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "shellenv", "--check"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Shellenv { check: true, .. }
+        ));
+        assert!(Cli::try_parse_from(["zb", "shellenv", "--check", "--shell", "zsh"]).is_err());
+    }
+
     // ========================================================================
-    // Link/Unlink Command Tests
+    // Shim Command Tests
     // ========================================================================
 
     #[test]
-    fn test_link_overwrite_flag() {
+    fn test_shim_install_parsing() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "link", "openssl", "--overwrite"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "shim", "install"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Shim {
+                action: ShimAction::Install
+            }
+        ));
+    }
+
+    #[test]
+    fn test_shim_translation_parses_as_zb_command() {
+        use clap::Parser;
+        use commands::shim::{BrewTranslation, translate};
+
+        let brew_args = vec![
+            "install".to_string(),
+            "--HEAD".to_string(),
+            "neovim".to_string(),
+        ];
+        let BrewTranslation::Run { invocations, .. } = translate(&brew_args) else {
+            panic!("Expected Run translation");
+        };
+
+        let cli = Cli::try_parse_from(
+            std::iter::once("zb".to_string()).chain(invocations[0].iter().cloned()),
+        )
+        .unwrap();
         match cli.command {
-            Commands::Link {
-                formula,
-                overwrite,
-                force,
+            Commands::Install { formulas, head, .. } => {
+                assert_eq!(formulas, ["neovim"]);
+                assert!(head);
+            }
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_install_emit_plan_and_execute_plan() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--emit-plan", "plan.json"]).unwrap();
+        match cli.command {
+            Commands::Install { emit_plan, .. } => {
+                assert_eq!(emit_plan, Some(PathBuf::from("plan.json")));
+            }
+            _ => panic!("Expected Install command"),
+        }
+        assert!(
+            Cli::try_parse_from(["zb", "install", "jq", "-s", "--emit-plan", "plan.json"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from(["zb", "--json", "execute-plan", "plan.json", "-y"]).unwrap();
+        assert!(cli.json);
+        assert!(supports_json(&cli.command));
+        match cli.command {
+            Commands::ExecutePlan {
+                plan, yes, no_link, ..
             } => {
-                assert_eq!(formula, "openssl");
-                assert!(overwrite);
-                assert!(!force);
+                assert_eq!(plan, PathBuf::from("plan.json"));
+                assert!(yes);
+                assert!(!no_link);
             }
-            _ => panic!("Expected Link command"),
+            _ => panic!("Expected ExecutePlan command"),
+        }
+    }
+
+    #[test]
+    fn test_install_dry_run_flag() {
+        use clap::Parser;
+
+        let cli =
+            Cli::try_parse_from(["zb", "--json", "install", "jq", "wget", "--dry-run"]).unwrap();
+        assert!(supports_json(&cli.command));
+        assert!(!cleans_up_after(&cli.command));
+        match cli.command {
+            Commands::Install {
+                formulas, dry_run, ..
+            } => {
+                assert_eq!(formulas, ["jq", "wget"]);
+                assert!(dry_run);
+            }
+            _ => panic!("Expected Install command"),
+        }
+        assert!(Cli::try_parse_from(["zb", "install", "jq", "-s", "--dry-run"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "install",
+                "jq",
+                "--dry-run",
+                "--emit-plan",
+                "plan.json"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_install_profile_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--profile"]).unwrap();
+        match cli.command {
+            Commands::Install {
+                formulas, profile, ..
+            } => {
+                assert_eq!(formulas, ["jq"]);
+                assert!(profile);
+            }
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_install_yes_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "ffmpeg", "-y"]).unwrap();
+        match cli.command {
+            Commands::Install { yes, .. } => assert!(yes),
+            _ => panic!("Expected Install command"),
+        }
+
+        let cli = Cli::try_parse_from(["zb", "install", "ffmpeg"]).unwrap();
+        match cli.command {
+            Commands::Install { yes, .. } => assert!(!yes),
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_install_force_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "python@3.8", "--force"]).unwrap();
+        match cli.command {
+            Commands::Install { force, .. } => assert!(force),
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_install_skip_post_install_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--skip-post-install"]).unwrap();
+        match cli.command {
+            Commands::Install {
+                skip_post_install, ..
+            } => assert!(skip_post_install),
+            _ => panic!("Expected Install command"),
+        }
+    }
+
+    #[test]
+    fn test_post_install_command() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "post-install", "postgresql@14"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::PostInstall {
+                formula: Some(_),
+                disable: false,
+                enable: false
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "post-install", "redis", "--disable"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::PostInstall { disable: true, .. }
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "post-install"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::PostInstall { formula: None, .. }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "post-install", "--disable"]).is_err());
+        assert!(
+            Cli::try_parse_from(["zb", "post-install", "redis", "--disable", "--enable"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_install_verify_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--verify"]).unwrap();
+        match cli.command {
+            Commands::Install {
+                verify, profile, ..
+            } => {
+                assert!(verify);
+                assert!(!profile);
+            }
+            _ => panic!("Expected Install command"),
         }
     }
 
     #[test]
-    fn test_unlink_parsing() {
+    fn test_stats_json_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "stats", "--json"]).unwrap();
+        assert!(cli.json);
+        match cli.command {
+            Commands::Stats { reset, pours } => {
+                assert!(!reset);
+                assert!(pours.is_none());
+            }
+            _ => panic!("Expected Stats command"),
+        }
+    }
+
+    #[test]
+    fn test_stats_pours_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "stats", "--pours"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Stats {
+                pours: Some(50),
+                ..
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "stats", "--pours", "5"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Stats { pours: Some(5), .. }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "stats", "--pours", "--reset"]).is_err());
+    }
+
+    #[test]
+    fn test_doctor_network_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "doctor", "--network"]).unwrap();
+        assert!(matches!(cli.command, Commands::Doctor { network: true }));
+        let cli = Cli::try_parse_from(["zb", "doctor"]).unwrap();
+        assert!(matches!(cli.command, Commands::Doctor { network: false }));
+    }
+
+    #[test]
+    fn test_global_json_flag() {
+        use clap::Parser;
+
+        let cli = Cli::try_parse_from(["zb", "--json", "doctor"]).unwrap();
+        assert!(cli.json);
+        assert!(supports_json(&cli.command));
+
+        let cli = Cli::try_parse_from(["zb", "bundle", "check", "--json"]).unwrap();
+        assert!(cli.json);
+        assert!(supports_json(&cli.command));
+
+        let cli = Cli::try_parse_from(["zb", "pin", "jq", "--json"]).unwrap();
+        assert!(!supports_json(&cli.command));
+    }
+
+    #[test]
+    fn test_maintenance_outputs_render_json() {
+        let output = AutoremoveOutput {
+            orphans: vec!["oniguruma".to_string()],
+            removed: None,
+        };
+        assert_eq!(
+            output.render_json(),
+            serde_json::json!({ "dry_run": true, "orphans": ["oniguruma"], "removed": [] })
+        );
+
+        let output = GcOutput {
+            versions: vec![("jq".to_string(), "1.6".to_string())],
+            store_entries: vec!["abc123".to_string()],
+        };
+        assert_eq!(
+            output.render_json(),
+            serde_json::json!({
+                "versions": [{ "name": "jq", "version": "1.6" }],
+                "store_entries": ["abc123"],
+            })
+        );
+
+        let output = CleanupOutput {
+            result: zb_io::install::CleanupResult {
+                blobs_removed: 2,
+                bytes_freed: 4096,
+                ..Default::default()
+            },
+            dry_run: false,
+            drift: zb_io::CellarDrift::default(),
+            untracked: None,
+        };
+        let json = output.render_json();
+        assert_eq!(json["blobs_removed"], 2);
+        assert_eq!(json["bytes_freed"], 4096);
+        assert_eq!(json["dry_run"], false);
+        assert!(json["untracked_action"].is_null());
+        assert_eq!(json["untracked"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_query_subcommands() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "unlink", "python@3.11"]).unwrap();
-        match cli.command {
-            Commands::Unlink { formula } => {
-                assert_eq!(formula, "python@3.11");
+        let cli = Cli::try_parse_from(["zb", "query", "installed"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Query {
+                action: QueryAction::Installed
             }
-            _ => panic!("Expected Unlink command"),
+        ));
+
+        let cli = Cli::try_parse_from(["zb", "query", "formula", "jq"]).unwrap();
+        match cli.command {
+            Commands::Query {
+                action: QueryAction::Formula { name },
+            } => assert_eq!(name, "jq"),
+            _ => panic!("Expected Query formula command"),
         }
-    }
 
-    // ========================================================================
-    // Cleanup Command Tests
-    // ========================================================================
+        let cli = Cli::try_parse_from(["zb", "query", "outdated"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Query {
+                action: QueryAction::Outdated
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "query", "formula"]).is_err());
+    }
 
     #[test]
-    fn test_cleanup_prune_days() {
+    fn test_benchmark_default_iterations() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "cleanup", "--prune", "30"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "benchmark", "jq"]).unwrap();
         match cli.command {
-            Commands::Cleanup { dry_run, prune } => {
-                assert!(!dry_run);
-                assert_eq!(prune, Some(30));
+            Commands::Benchmark {
+                formula,
+                iterations,
+            } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(iterations, 3);
             }
-            _ => panic!("Expected Cleanup command"),
+            _ => panic!("Expected Benchmark command"),
         }
     }
 
     #[test]
-    fn test_cleanup_dry_run() {
+    fn test_benchmark_iterations_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "cleanup", "--dry-run"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "benchmark", "jq", "--iterations", "5"]).unwrap();
         match cli.command {
-            Commands::Cleanup { dry_run, prune } => {
-                assert!(dry_run);
-                assert!(prune.is_none());
-            }
-            _ => panic!("Expected Cleanup command"),
+            Commands::Benchmark { iterations, .. } => assert_eq!(iterations, 5),
+            _ => panic!("Expected Benchmark command"),
         }
     }
 
-    // ========================================================================
-    // Global Options Tests
-    // ========================================================================
-
     #[test]
-    fn test_custom_root_and_prefix() {
+    fn test_cache_bypass_global_flags() {
         use clap::Parser;
 
         let cli = Cli::try_parse_from([
             "zb",
-            "--root",
-            "/custom/root",
-            "--prefix",
-            "/custom/prefix",
-            "list",
+            "--no-cache",
+            "--refresh",
+            "jq",
+            "--refresh",
+            "oniguruma",
+            "install",
+            "jq",
         ])
         .unwrap();
-        assert_eq!(cli.root, PathBuf::from("/custom/root"));
-        assert_eq!(cli.prefix, PathBuf::from("/custom/prefix"));
+        assert!(cli.no_cache);
+        assert_eq!(cli.refresh, vec!["jq", "oniguruma"]);
+
+        let cli = Cli::try_parse_from(["zb", "install", "jq"]).unwrap();
+        assert!(!cli.no_cache);
+        assert!(cli.refresh.is_empty());
     }
 
     #[test]
-    fn test_custom_concurrency() {
+    fn test_bottle_tag_global_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "--concurrency", "16", "install", "git"]).unwrap();
-        assert_eq!(cli.concurrency, 16);
+        let cli =
+            Cli::try_parse_from(["zb", "--bottle-tag", "arm64_sonoma", "install", "jq"]).unwrap();
+        assert_eq!(cli.bottle_tag.as_deref(), Some("arm64_sonoma"));
     }
 
     #[test]
-    fn test_default_values() {
+    fn test_delta_url_global_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
-        assert_eq!(cli.root, PathBuf::from("/opt/zerobrew"));
-        assert_eq!(cli.prefix, PathBuf::from("/opt/zerobrew/prefix"));
-        assert_eq!(cli.concurrency, 48);
+        let cli =
+            Cli::try_parse_from(["zb", "--delta-url", "https://deltas.example.com", "upgrade"])
+                .unwrap();
+        assert_eq!(cli.delta_url.as_deref(), Some("https://deltas.example.com"));
     }
 
-    // ========================================================================
-    // Services Log Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_services_log_with_lines() {
+    fn test_temp_dir_global_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "services", "log", "redis", "-n", "50"]).unwrap();
-        match cli.command {
-            Commands::Services {
-                action:
-                    Some(ServicesAction::Log {
-                        formula,
-                        lines,
-                        follow,
-                    }),
-            } => {
-                assert_eq!(formula, "redis");
-                assert_eq!(lines, 50);
-                assert!(!follow);
-            }
-            _ => panic!("Expected Services Log command"),
-        }
+        let cli =
+            Cli::try_parse_from(["zb", "--temp-dir", "/var/tmp/zb", "install", "jq"]).unwrap();
+        assert_eq!(cli.temp_dir, Some(PathBuf::from("/var/tmp/zb")));
     }
 
     #[test]
-    fn test_services_log_follow() {
+    fn test_record_http_global_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "services", "log", "postgresql", "--follow"]).unwrap();
-        match cli.command {
-            Commands::Services {
-                action:
-                    Some(ServicesAction::Log {
-                        formula,
-                        lines,
-                        follow,
-                    }),
-            } => {
-                assert_eq!(formula, "postgresql");
-                assert_eq!(lines, 20); // default
-                assert!(follow);
-            }
-            _ => panic!("Expected Services Log command"),
-        }
+        let cli =
+            Cli::try_parse_from(["zb", "--record-http", "/tmp/fixtures", "install", "jq"]).unwrap();
+        assert_eq!(cli.record_http, Some(PathBuf::from("/tmp/fixtures")));
     }
 
-    // ========================================================================
-    // Pin/Unpin Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_pin_versioned_formula() {
+    fn test_switch_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "pin", "node@20"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "switch", "jq", "1.6"]).unwrap();
         match cli.command {
-            Commands::Pin { formula } => {
-                assert_eq!(formula, "node@20");
+            Commands::Switch { formula, version } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(version, "1.6");
             }
-            _ => panic!("Expected Pin command"),
+            _ => panic!("Expected Switch command"),
         }
     }
 
     #[test]
-    fn test_unpin_formula() {
+    fn test_adopt_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "unpin", "git"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "adopt", "jq", "1.7.1", "--no-link"]).unwrap();
         match cli.command {
-            Commands::Unpin { formula } => {
-                assert_eq!(formula, "git");
+            Commands::Adopt {
+                ref formula,
+                ref version,
+                no_link,
+            } => {
+                assert_eq!(formula, "jq");
+                assert_eq!(version, "1.7.1");
+                assert!(no_link);
             }
-            _ => panic!("Expected Unpin command"),
+            _ => panic!("Expected Adopt command"),
         }
+        assert!(supports_json(&cli.command));
+        assert!(Cli::try_parse_from(["zb", "adopt", "jq"]).is_err());
     }
 
-    // ========================================================================
-    // Tap/Untap Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_tap_list() {
-        use clap::Parser;
-
-        let cli = Cli::try_parse_from(["zb", "tap"]).unwrap();
-        match cli.command {
-            Commands::Tap { user_repo } => {
-                assert!(user_repo.is_none());
-            }
-            _ => panic!("Expected Tap command"),
-        }
+    fn test_format_adopt_line_and_json() {
+        let mut result = zb_io::AdoptResult {
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            keg_path: PathBuf::from("/opt/zerobrew/prefix/Cellar/jq/1.7.1"),
+            store_key: "adopted-jq-1.7.1".to_string(),
+            known_formula: true,
+            keg_only: false,
+            linked: true,
+            files_linked: 2,
+        };
+        assert!(format_adopt_line(&result).ends_with("1.7.1 (2 files linked)"));
+
+        let value = adopt_json(&result);
+        assert_eq!(value["store_key"], "adopted-jq-1.7.1");
+        assert_eq!(value["keg"], "/opt/zerobrew/prefix/Cellar/jq/1.7.1");
+        assert_eq!(value["files_linked"], 2);
+
+        result.linked = false;
+        result.keg_only = true;
+        assert!(format_adopt_line(&result).ends_with("(keg-only, not linked)"));
+        result.keg_only = false;
+        assert!(format_adopt_line(&result).ends_with("(not linked)"));
     }
 
     #[test]
-    fn test_tap_add() {
+    fn test_rollback_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "tap", "homebrew/cask"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "rollback", "jq"]).unwrap();
         match cli.command {
-            Commands::Tap { user_repo } => {
-                assert_eq!(user_repo, Some("homebrew/cask".to_string()));
+            Commands::Rollback { formula, no_pin } => {
+                assert_eq!(formula, "jq");
+                assert!(!no_pin);
             }
-            _ => panic!("Expected Tap command"),
+            _ => panic!("Expected Rollback command"),
         }
+
+        let cli = Cli::try_parse_from(["zb", "rollback", "jq", "--no-pin"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Rollback { no_pin: true, .. }
+        ));
     }
 
     #[test]
-    fn test_untap() {
+    fn test_uninstall_version_requires_formula() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "untap", "homebrew/cask"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "uninstall", "jq", "--version", "1.6"]).unwrap();
         match cli.command {
-            Commands::Untap { user_repo } => {
-                assert_eq!(user_repo, "homebrew/cask");
+            Commands::Uninstall {
+                formula, version, ..
+            } => {
+                assert_eq!(formula.as_deref(), Some("jq"));
+                assert_eq!(version.as_deref(), Some("1.6"));
             }
-            _ => panic!("Expected Untap command"),
+            _ => panic!("Expected Uninstall command"),
         }
-    }
 
-    // ========================================================================
-    // Reset Command Tests
-    // ========================================================================
+        assert!(Cli::try_parse_from(["zb", "uninstall", "--version", "1.6"]).is_err());
+    }
 
     #[test]
-    fn test_reset_with_yes_flag() {
+    fn test_uninstall_with_dependents_flags() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "reset", "--yes"]).unwrap();
-        match cli.command {
-            Commands::Reset { yes } => {
-                assert!(yes);
+        let cli =
+            Cli::try_parse_from(["zb", "uninstall", "openssl", "--with-dependents", "-y"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Uninstall {
+                with_dependents: true,
+                yes: true,
+                ..
             }
-            _ => panic!("Expected Reset command"),
-        }
+        ));
+
+        assert!(Cli::try_parse_from(["zb", "uninstall", "--with-dependents"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "uninstall",
+                "jq",
+                "--with-dependents",
+                "--version",
+                "1.6"
+            ])
+            .is_err()
+        );
+        assert!(Cli::try_parse_from(["zb", "uninstall", "jq", "--yes"]).is_err());
     }
 
     #[test]
-    fn test_reset_short_flag() {
-        use clap::Parser;
-
-        let cli = Cli::try_parse_from(["zb", "reset", "-y"]).unwrap();
-        match cli.command {
-            Commands::Reset { yes } => {
-                assert!(yes);
-            }
-            _ => panic!("Expected Reset command"),
-        }
+    fn test_format_with_dependents_header() {
+        assert_eq!(
+            format_with_dependents_header("jq", 1),
+            "No installed packages depend on jq; uninstalling:"
+        );
+        assert_eq!(
+            format_with_dependents_header("openssl", 2),
+            "Uninstalling openssl and 1 package that depends on it:"
+        );
+        assert_eq!(
+            format_with_dependents_header("openssl", 4),
+            "Uninstalling openssl and 3 packages that depend on it:"
+        );
     }
 
     // ========================================================================
-    // Autoremove Command Tests
+    // Mirror Command Tests
     // ========================================================================
 
     #[test]
-    fn test_autoremove_dry_run() {
+    fn test_mirror_create_with_brewfile_and_platforms() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "autoremove", "--dry-run"]).unwrap();
+        let cli = Cli::try_parse_from([
+            "zb",
+            "mirror",
+            "create",
+            "/srv/mirror",
+            "jq",
+            "--formulas",
+            "Brewfile",
+            "--platform",
+            "arm64_sonoma",
+            "--platform",
+            "x86_64_linux",
+        ])
+        .unwrap();
         match cli.command {
-            Commands::Autoremove { dry_run } => {
-                assert!(dry_run);
+            Commands::Mirror {
+                action:
+                    MirrorAction::Create {
+                        dir,
+                        names,
+                        brewfile,
+                        platforms,
+                    },
+            } => {
+                assert_eq!(dir, PathBuf::from("/srv/mirror"));
+                assert_eq!(names, vec!["jq"]);
+                assert_eq!(brewfile, Some(PathBuf::from("Brewfile")));
+                assert_eq!(platforms, vec!["arm64_sonoma", "x86_64_linux"]);
             }
-            _ => panic!("Expected Autoremove command"),
+            _ => panic!("Expected Mirror create command"),
         }
     }
 
-    // ========================================================================
-    // Uses Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_uses_recursive() {
+    fn test_mirror_sync_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "uses", "openssl", "--recursive"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "mirror", "sync", "/srv/mirror", "wget"]).unwrap();
         match cli.command {
-            Commands::Uses {
-                formula, recursive, ..
+            Commands::Mirror {
+                action:
+                    MirrorAction::Sync {
+                        dir,
+                        names,
+                        brewfile,
+                        platforms,
+                    },
             } => {
-                assert_eq!(formula, "openssl");
-                assert!(recursive);
+                assert_eq!(dir, PathBuf::from("/srv/mirror"));
+                assert_eq!(names, vec!["wget"]);
+                assert!(brewfile.is_none());
+                assert!(platforms.is_empty());
             }
-            _ => panic!("Expected Uses command"),
+            _ => panic!("Expected Mirror sync command"),
         }
     }
 
-    // ========================================================================
-    // Update Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_update_default() {
+    fn test_mirror_global_flag() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "update"]).unwrap();
-        match cli.command {
-            Commands::Update { dry_run, force } => {
-                assert!(!dry_run);
-                assert!(!force);
-            }
-            _ => panic!("Expected Update command"),
-        }
+        let cli = Cli::try_parse_from(["zb", "--mirror", "/srv/mirror", "install", "jq"]).unwrap();
+        assert_eq!(cli.mirror.as_deref(), Some("/srv/mirror"));
+
+        assert!(
+            Cli::try_parse_from([
+                "zb",
+                "--mirror",
+                "/srv/mirror",
+                "--api-domain",
+                "http://mirror.local/api",
+                "install",
+                "jq",
+            ])
+            .is_err()
+        );
     }
 
     #[test]
-    fn test_update_dry_run() {
+    fn test_api_and_bottle_domain_flags() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "update", "--dry-run"]).unwrap();
-        match cli.command {
-            Commands::Update { dry_run, force } => {
-                assert!(dry_run);
-                assert!(!force);
-            }
-            _ => panic!("Expected Update command"),
-        }
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--api-domain",
+            "http://mirror.local/api",
+            "--bottle-domain",
+            "http://mirror.local/bottles",
+            "install",
+            "jq",
+        ])
+        .unwrap();
+        assert_eq!(cli.api_domain.as_deref(), Some("http://mirror.local/api"));
+        assert_eq!(
+            cli.bottle_domain.as_deref(),
+            Some("http://mirror.local/bottles")
+        );
+        assert_eq!(cli.api_layout, None);
     }
 
     #[test]
-    fn test_update_force() {
+    fn test_api_layout_flag_and_config() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "update", "--force"]).unwrap();
-        match cli.command {
-            Commands::Update { dry_run, force } => {
-                assert!(!dry_run);
-                assert!(force);
-            }
-            _ => panic!("Expected Update command"),
-        }
+        let cli = Cli::try_parse_from([
+            "zb",
+            "--api-domain",
+            "http://127.0.0.1:8080",
+            "--api-layout",
+            "flat",
+            "info",
+            "jq",
+        ])
+        .unwrap();
+        let context = installer_context(&cli);
+        assert_eq!(context.api_domain.as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(context.api_layout, ApiLayout::Flat);
+        assert!(Cli::try_parse_from(["zb", "--api-layout", "nested", "info", "jq"]).is_err());
+
+        // The config file fills in what the command line leaves out
+        let mut cli = Cli::try_parse_from(["zb", "info", "jq"]).unwrap();
+        cli.config = Config::parse(
+            r#"
+            api_domain = "https://artifacts.example.com/brew"
+            api_layout = "flat"
+            "#,
+        )
+        .unwrap();
+        let context = installer_context(&cli);
+        assert_eq!(
+            context.api_domain.as_deref(),
+            Some("https://artifacts.example.com/brew")
+        );
+        assert_eq!(context.api_layout, ApiLayout::Flat);
     }
 
+    // ========================================================================
+    // Pack Command Tests
+    // ========================================================================
+
     #[test]
-    fn test_update_both_flags() {
+    fn test_pack_requires_formula() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "update", "--dry-run", "--force"]).unwrap();
+        assert!(Cli::try_parse_from(["zb", "pack", "-o", "out.zbpack"]).is_err());
+
+        let cli = Cli::try_parse_from(["zb", "pack", "jq", "wget", "-o", "tools.zbpack"]).unwrap();
         match cli.command {
-            Commands::Update { dry_run, force } => {
-                assert!(dry_run);
-                assert!(force);
+            Commands::Pack {
+                formulas,
+                output,
+                platforms,
+            } => {
+                assert_eq!(formulas, vec!["jq", "wget"]);
+                assert_eq!(output, PathBuf::from("tools.zbpack"));
+                assert!(platforms.is_empty());
             }
-            _ => panic!("Expected Update command"),
+            _ => panic!("Expected Pack command"),
         }
     }
 
-    // ========================================================================
-    // Shellenv Command Tests
-    // ========================================================================
-
     #[test]
-    fn test_shellenv_default() {
+    fn test_install_from_pack() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "shellenv"]).unwrap();
-        match cli.command {
-            Commands::Shellenv { shell } => {
-                assert!(shell.is_none());
+        let cli = Cli::try_parse_from(["zb", "install", "jq", "--from-pack"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Install {
+                from_pack: true,
+                ..
             }
-            _ => panic!("Expected Shellenv command"),
-        }
+        ));
+        assert!(Cli::try_parse_from(["zb", "install", "jq", "--from-pack", "-s"]).is_err());
     }
 
     #[test]
-    fn test_shellenv_fish() {
+    fn test_why_command() {
         use clap::Parser;
 
-        let cli = Cli::try_parse_from(["zb", "shellenv", "--shell", "fish"]).unwrap();
+        let cli = Cli::try_parse_from(["zb", "why", "icu4c"]).unwrap();
         match cli.command {
-            Commands::Shellenv { shell } => {
-                assert_eq!(shell, Some("fish".to_string()));
-            }
-            _ => panic!("Expected Shellenv command"),
+            Commands::Why { formula } => assert_eq!(formula, "icu4c"),
+            _ => panic!("Expected Why command"),
         }
+        assert!(Cli::try_parse_from(["zb", "why"]).is_err());
     }
 }
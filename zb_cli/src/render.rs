@@ -0,0 +1,42 @@
+//! Output for people and for scripts.
+//!
+//! A command that supports the global `--json` flag describes its result
+//! with a type implementing [`Render`]. [`emit`] prints it as text for a
+//! terminal, or as a single pretty-printed JSON document on stdout that
+//! scripts and CI jobs can parse. Progress and warnings go to stderr so they
+//! never mix with the document.
+
+use console::style;
+
+/// A command result that can be printed for people or as JSON
+pub trait Render {
+    /// Print the result for a person at a terminal
+    fn render_human(&self);
+
+    /// The result as a JSON document
+    fn render_json(&self) -> serde_json::Value;
+}
+
+/// Print `output` as JSON when `json` is set, as text otherwise
+pub fn emit(output: &impl Render, json: bool) {
+    if json {
+        print_json(&output.render_json());
+    } else {
+        output.render_human();
+    }
+}
+
+/// Print a JSON value, exiting if it can't be serialized.
+pub fn print_json(value: &serde_json::Value) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json_str) => println!("{}", json_str),
+        Err(e) => {
+            eprintln!(
+                "{} Failed to serialize JSON: {}",
+                style("error:").red().bold(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::platform::MACOS_NAMES;
 use crate::{Error, Formula};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -72,6 +73,60 @@ fn is_compatible_fallback_tag(tag: &str) -> bool {
     }
 }
 
+/// Tags to try, in order, when a bottle tag is forced.
+///
+/// A macOS tag falls back to each older macOS release for the same
+/// architecture, since bottles built for an older release run on newer ones:
+/// `arm64_sonoma` → `arm64_ventura` → `arm64_monterey` → ... and `sonoma` →
+/// `ventura` → `monterey` → ... for Intel. Any other tag (`x86_64_linux`,
+/// `all`, or a release newer than zb knows about) is tried on its own.
+pub fn bottle_tag_fallbacks(tag: &str) -> Vec<String> {
+    let (arch_prefix, release) = match tag.strip_prefix("arm64_") {
+        Some(release) => ("arm64_", release),
+        None => ("", tag),
+    };
+
+    match MACOS_NAMES.iter().position(|(name, _, _)| *name == release) {
+        Some(start) => MACOS_NAMES[start..]
+            .iter()
+            .map(|(name, _, _)| format!("{arch_prefix}{name}"))
+            .collect(),
+        None => vec![tag.to_string()],
+    }
+}
+
+/// Select a bottle, forcing `tag` and its fallbacks when one is given.
+///
+/// With a forced tag, the platform-independent `all` bottle is still used
+/// when none of the fallbacks exist.
+pub fn select_bottle_with_tag(
+    formula: &Formula,
+    tag: Option<&str>,
+) -> Result<SelectedBottle, Error> {
+    let Some(tag) = tag else {
+        return select_bottle(formula);
+    };
+
+    for candidate in bottle_tag_fallbacks(tag)
+        .iter()
+        .map(String::as_str)
+        .chain(["all"])
+    {
+        if let Some(file) = formula.bottle.stable.files.get(candidate) {
+            return Ok(SelectedBottle {
+                tag: candidate.to_string(),
+                url: file.url.clone(),
+                sha256: file.sha256.clone(),
+            });
+        }
+    }
+
+    Err(Error::UnsupportedBottle {
+        name: formula.name.clone(),
+        available_platforms: formula.bottle.stable.files.keys().cloned().collect(),
+    })
+}
+
 pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
     let platform_tags = get_platform_tags();
 
@@ -479,6 +534,68 @@ mod tests {
         }
     }
 
+    fn formula_with_tags(tags: &[&str]) -> Formula {
+        let files = tags
+            .iter()
+            .map(|tag| {
+                (
+                    tag.to_string(),
+                    BottleFile {
+                        url: format!("https://example.com/foo.{tag}.bottle.tar.gz"),
+                        sha256: format!("sha-{tag}"),
+                    },
+                )
+            })
+            .collect();
+        Formula {
+            name: "foo".to_string(),
+            versions: Versions {
+                stable: "1.0.0".to_string(),
+            },
+            bottle: Bottle {
+                stable: BottleStable { files, rebuild: 0 },
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bottle_tag_fallbacks_walk_older_macos_releases() {
+        assert_eq!(
+            &bottle_tag_fallbacks("arm64_sonoma")[..3],
+            ["arm64_sonoma", "arm64_ventura", "arm64_monterey"]
+        );
+        assert_eq!(
+            &bottle_tag_fallbacks("sonoma")[..3],
+            ["sonoma", "ventura", "monterey"]
+        );
+        assert_eq!(bottle_tag_fallbacks("x86_64_linux"), ["x86_64_linux"]);
+        assert_eq!(bottle_tag_fallbacks("arm64_future"), ["arm64_future"]);
+    }
+
+    #[test]
+    fn forced_tag_falls_back_to_older_release() {
+        let formula = formula_with_tags(&["arm64_ventura", "arm64_monterey", "x86_64_linux"]);
+
+        let selected = select_bottle_with_tag(&formula, Some("arm64_sequoia")).unwrap();
+        assert_eq!(selected.tag, "arm64_ventura");
+        assert_eq!(selected.sha256, "sha-arm64_ventura");
+
+        // Never crosses architectures
+        let err = select_bottle_with_tag(&formula, Some("sonoma")).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedBottle { .. }));
+
+        let selected = select_bottle_with_tag(&formula, Some("x86_64_linux")).unwrap();
+        assert_eq!(selected.tag, "x86_64_linux");
+    }
+
+    #[test]
+    fn forced_tag_uses_all_bottle_last() {
+        let formula = formula_with_tags(&["all"]);
+        let selected = select_bottle_with_tag(&formula, Some("arm64_sonoma")).unwrap();
+        assert_eq!(selected.tag, "all");
+    }
+
     /// Test compatible fallback tag logic
     #[test]
     fn is_compatible_fallback_tag_logic() {
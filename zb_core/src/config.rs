@@ -0,0 +1,408 @@
+//! User configuration, read from `~/.config/zerobrew/config.toml`.
+//!
+//! Every setting is optional and falls back to zerobrew's default. Settings
+//! under `[commands.<name>]` apply only to that subcommand. Environment
+//! variables take precedence over the file, and command-line flags over both.
+//!
+//! ```toml
+//! root = "/opt/zerobrew"
+//! prefix = "/opt/zerobrew/prefix"
+//! concurrency = 32
+//! extract_concurrency = 8
+//! mirror = "https://mirror.example.com/zerobrew"
+//! api_domain = "https://artifacts.example.com/brew"
+//! api_layout = "homebrew"     # or "flat"
+//! color = "auto"              # "always" or "never"
+//!
+//! [cleanup]
+//! auto = true                 # clean up after install, upgrade and uninstall
+//! prune_days = 30             # keep cache files used in the last 30 days
+//!
+//! [commands.upgrade]
+//! concurrency = 4
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// Path of the config file, instead of the default location
+pub const CONFIG_ENV: &str = "ZB_CONFIG";
+pub const ROOT_ENV: &str = "ZB_ROOT";
+pub const PREFIX_ENV: &str = "ZB_PREFIX";
+pub const CONCURRENCY_ENV: &str = "ZB_CONCURRENCY";
+pub const EXTRACT_CONCURRENCY_ENV: &str = "ZB_EXTRACT_CONCURRENCY";
+pub const MIRROR_ENV: &str = "ZB_MIRROR";
+pub const API_DOMAIN_ENV: &str = "ZB_API_DOMAIN";
+/// `homebrew` or `flat`
+pub const API_LAYOUT_ENV: &str = "ZB_API_LAYOUT";
+/// `auto`, `always` or `never`
+pub const COLOR_ENV: &str = "ZB_COLOR";
+/// Disables colors when set to anything, see <https://no-color.org>
+pub const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// When to color terminal output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Color output going to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl std::str::FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!(
+                "invalid color mode '{}': expected auto, always or never",
+                s
+            )),
+        }
+    }
+}
+
+/// Where a formula API serves each formula's JSON, relative to its domain
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiLayout {
+    /// `<domain>/formula/<name>.json` and `<domain>/formula.json`, as served
+    /// by formulae.brew.sh/api and `zb mirror`
+    #[default]
+    Homebrew,
+    /// `<domain>/<name>.json` and `<domain>.json`
+    Flat,
+}
+
+impl std::str::FromStr for ApiLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "homebrew" => Ok(ApiLayout::Homebrew),
+            "flat" => Ok(ApiLayout::Flat),
+            _ => Err(format!(
+                "invalid API layout '{}': expected homebrew or flat",
+                s
+            )),
+        }
+    }
+}
+
+/// Whether to clean up caches automatically
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CleanupPolicy {
+    /// Run `zb cleanup` after install, upgrade and uninstall
+    #[serde(default)]
+    pub auto: bool,
+    /// Keep cache files used within this many days; `None` removes every
+    /// unused one
+    pub prune_days: Option<u32>,
+}
+
+/// Settings that can be overridden for a single subcommand
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandOverrides {
+    pub concurrency: Option<usize>,
+    pub extract_concurrency: Option<usize>,
+    pub mirror: Option<String>,
+    pub color: Option<ColorMode>,
+}
+
+/// Settings from the config file and environment. Unset fields use
+/// zerobrew's defaults.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub root: Option<PathBuf>,
+    pub prefix: Option<PathBuf>,
+    /// Parallel downloads
+    pub concurrency: Option<usize>,
+    /// Bottles extracted in parallel
+    pub extract_concurrency: Option<usize>,
+    /// Mirror to resolve formulas and bottles from
+    pub mirror: Option<String>,
+    /// Formula API to fetch metadata from, e.g. `https://formulae.brew.sh/api`
+    pub api_domain: Option<String>,
+    pub api_layout: Option<ApiLayout>,
+    pub color: Option<ColorMode>,
+    #[serde(default)]
+    pub cleanup: CleanupPolicy,
+    /// Overrides keyed by subcommand name, e.g. `install`
+    #[serde(default)]
+    pub commands: BTreeMap<String, CommandOverrides>,
+}
+
+impl Config {
+    /// Parse a config file's contents
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        toml::from_str(contents).map_err(|e| e.message().to_string())
+    }
+
+    /// Read the config at `path`. A missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => {
+                return Err(Error::StoreCorruption {
+                    message: format!("failed to read config at {}: {e}", path.display()),
+                });
+            }
+        };
+        Self::parse(&contents).map_err(|e| Error::StoreCorruption {
+            message: format!("invalid config at {}: {e}", path.display()),
+        })
+    }
+
+    /// Where the config file is looked for: `$ZB_CONFIG`, else
+    /// `zerobrew/config.toml` under `$XDG_CONFIG_HOME` or `~/.config`
+    pub fn default_path(var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        if let Some(path) = var(CONFIG_ENV) {
+            return Some(PathBuf::from(path));
+        }
+        let config_home = var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_home.join("zerobrew").join("config.toml"))
+    }
+
+    /// Load the config file and apply environment variables over it
+    pub fn from_env() -> Result<Self, Error> {
+        let var = |name: &str| std::env::var(name).ok();
+        let mut config = match Self::default_path(var) {
+            Some(path) => Self::load(&path)?,
+            None => Config::default(),
+        };
+        config.apply_env(var)?;
+        Ok(config)
+    }
+
+    /// Replace settings with the environment variables that are set
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), Error> {
+        let var = |name: &str| var(name).filter(|value| !value.trim().is_empty());
+        let number = |name: &str| -> Result<Option<usize>, Error> {
+            var(name)
+                .map(|value| {
+                    value.trim().parse().map_err(|_| Error::StoreCorruption {
+                        message: format!("{name} must be a number, not '{value}'"),
+                    })
+                })
+                .transpose()
+        };
+
+        if let Some(root) = var(ROOT_ENV) {
+            self.root = Some(PathBuf::from(root));
+        }
+        if let Some(prefix) = var(PREFIX_ENV) {
+            self.prefix = Some(PathBuf::from(prefix));
+        }
+        if let Some(concurrency) = number(CONCURRENCY_ENV)? {
+            self.concurrency = Some(concurrency);
+        }
+        if let Some(extractions) = number(EXTRACT_CONCURRENCY_ENV)? {
+            self.extract_concurrency = Some(extractions);
+        }
+        if let Some(mirror) = var(MIRROR_ENV) {
+            self.mirror = Some(mirror);
+        }
+        if let Some(domain) = var(API_DOMAIN_ENV) {
+            self.api_domain = Some(domain);
+        }
+        if let Some(layout) = var(API_LAYOUT_ENV) {
+            self.api_layout = Some(
+                layout
+                    .parse()
+                    .map_err(|message| Error::StoreCorruption { message })?,
+            );
+        }
+        if let Some(color) = var(COLOR_ENV) {
+            self.color = Some(
+                color
+                    .parse()
+                    .map_err(|message| Error::StoreCorruption { message })?,
+            );
+        } else if var(NO_COLOR_ENV).is_some() {
+            self.color = Some(ColorMode::Never);
+        }
+        Ok(())
+    }
+
+    /// The settings in effect for subcommand `command`, with its overrides
+    /// from the file applied. Environment variables still win over them.
+    pub fn for_command(&self, command: &str, var: impl Fn(&str) -> Option<String>) -> Self {
+        let mut config = self.clone();
+        let Some(overrides) = self.commands.get(command) else {
+            return config;
+        };
+        let set = |name: &str| var(name).is_some_and(|value| !value.trim().is_empty());
+
+        if let Some(concurrency) = overrides.concurrency
+            && !set(CONCURRENCY_ENV)
+        {
+            config.concurrency = Some(concurrency);
+        }
+        if let Some(extractions) = overrides.extract_concurrency
+            && !set(EXTRACT_CONCURRENCY_ENV)
+        {
+            config.extract_concurrency = Some(extractions);
+        }
+        if let Some(mirror) = &overrides.mirror
+            && !set(MIRROR_ENV)
+        {
+            config.mirror = Some(mirror.clone());
+        }
+        if let Some(color) = overrides.color
+            && !set(COLOR_ENV)
+            && !set(NO_COLOR_ENV)
+        {
+            config.color = Some(color);
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    const EXAMPLE: &str = r#"
+        root = "/srv/zb"
+        concurrency = 32
+        mirror = "https://mirror.example.com"
+        api_domain = "https://artifacts.example.com/brew"
+        api_layout = "flat"
+        color = "never"
+
+        [cleanup]
+        auto = true
+        prune_days = 30
+
+        [commands.upgrade]
+        concurrency = 4
+        color = "always"
+    "#;
+
+    #[test]
+    fn parses_every_setting() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        assert_eq!(config.root, Some(PathBuf::from("/srv/zb")));
+        assert_eq!(config.prefix, None);
+        assert_eq!(config.concurrency, Some(32));
+        assert_eq!(config.color, Some(ColorMode::Never));
+        assert_eq!(
+            config.api_domain.as_deref(),
+            Some("https://artifacts.example.com/brew")
+        );
+        assert_eq!(config.api_layout, Some(ApiLayout::Flat));
+        assert_eq!(
+            config.cleanup,
+            CleanupPolicy {
+                auto: true,
+                prune_days: Some(30)
+            }
+        );
+        assert_eq!(config.commands["upgrade"].concurrency, Some(4));
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        let err = Config::parse("concurency = 4").unwrap_err();
+        assert!(err.contains("concurency"), "{err}");
+        assert!(Config::parse("color = \"sometimes\"").is_err());
+        assert!(Config::parse("api_layout = \"nested\"").is_err());
+    }
+
+    #[test]
+    fn missing_files_are_empty_configs() {
+        let tmp = std::env::temp_dir().join("zb-config-test-missing.toml");
+        assert_eq!(Config::load(&tmp).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn config_path_follows_the_environment() {
+        assert_eq!(
+            Config::default_path(env(&[("HOME", "/home/u")])),
+            Some(PathBuf::from("/home/u/.config/zerobrew/config.toml"))
+        );
+        assert_eq!(
+            Config::default_path(env(&[("HOME", "/home/u"), ("XDG_CONFIG_HOME", "/xdg")])),
+            Some(PathBuf::from("/xdg/zerobrew/config.toml"))
+        );
+        assert_eq!(
+            Config::default_path(env(&[("HOME", "/home/u"), (CONFIG_ENV, "/etc/zb.toml")])),
+            Some(PathBuf::from("/etc/zb.toml"))
+        );
+        assert_eq!(Config::default_path(env(&[])), None);
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let mut config = Config::parse(EXAMPLE).unwrap();
+        config
+            .apply_env(env(&[
+                (ROOT_ENV, "/tmp/zb"),
+                (CONCURRENCY_ENV, "8"),
+                (MIRROR_ENV, ""),
+                (API_LAYOUT_ENV, "Homebrew"),
+                (COLOR_ENV, "Always"),
+            ]))
+            .unwrap();
+        assert_eq!(config.root, Some(PathBuf::from("/tmp/zb")));
+        assert_eq!(config.concurrency, Some(8));
+        assert_eq!(config.mirror.as_deref(), Some("https://mirror.example.com"));
+        assert_eq!(config.api_layout, Some(ApiLayout::Homebrew));
+        assert_eq!(config.color, Some(ColorMode::Always));
+
+        let mut config = Config::default();
+        config.apply_env(env(&[(NO_COLOR_ENV, "1")])).unwrap();
+        assert_eq!(config.color, Some(ColorMode::Never));
+
+        let err = Config::default()
+            .apply_env(env(&[(CONCURRENCY_ENV, "lots")]))
+            .unwrap_err();
+        assert!(err.to_string().contains("ZB_CONCURRENCY"), "{err}");
+        assert!(
+            Config::default()
+                .apply_env(env(&[(API_LAYOUT_ENV, "nested")]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn command_overrides_apply_below_the_environment() {
+        let config = Config::parse(EXAMPLE).unwrap();
+
+        let upgrade = config.for_command("upgrade", env(&[]));
+        assert_eq!(upgrade.concurrency, Some(4));
+        assert_eq!(upgrade.color, Some(ColorMode::Always));
+        assert_eq!(config.for_command("install", env(&[])), config);
+
+        let vars = [(CONCURRENCY_ENV, "8")];
+        let mut config = config;
+        config.apply_env(env(&vars)).unwrap();
+        let upgrade = config.for_command("upgrade", env(&vars));
+        assert_eq!(upgrade.concurrency, Some(8));
+        assert_eq!(upgrade.color, Some(ColorMode::Always));
+    }
+}
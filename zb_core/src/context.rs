@@ -1,63 +1,105 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
+use crate::config::{ApiLayout, Config};
+
+pub const DEFAULT_ROOT: &str = "/opt/zerobrew";
+pub const DEFAULT_PREFIX: &str = "/opt/zerobrew/prefix";
+
+/// Where zerobrew keeps its data and links packages
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Paths {
     pub root: PathBuf,
+    /// Where packages are linked, e.g. `/opt/zerobrew/prefix`
+    pub prefix: PathBuf,
     pub store: PathBuf,
+    /// Kegs live in the prefix so bottles' hardcoded paths resolve
     pub cellar: PathBuf,
     pub cache: PathBuf,
     pub db: PathBuf,
     pub locks: PathBuf,
+    pub taps: PathBuf,
 }
 
 impl Paths {
-    pub fn from_root(root: PathBuf) -> Self {
-        let store = root.join("store");
-        let cellar = root.join("cellar");
-        let cache = root.join("cache");
-        let db = root.join("db").join("zb.sqlite3");
-        let locks = root.join("locks");
-
+    pub fn new(root: &Path, prefix: &Path) -> Self {
         Self {
-            root,
-            store,
-            cellar,
-            cache,
-            db,
-            locks,
+            root: root.to_path_buf(),
+            prefix: prefix.to_path_buf(),
+            store: root.join("store"),
+            cellar: prefix.join("Cellar"),
+            cache: root.join("cache"),
+            db: root.join("db").join("zb.sqlite3"),
+            locks: root.join("locks"),
+            taps: root.join("taps"),
         }
     }
+
+    /// Paths with the prefix inside the root, as in the default layout
+    pub fn from_root(root: PathBuf) -> Self {
+        Self::new(&root, &root.join("prefix"))
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// How much work an install runs in parallel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ConcurrencyLimits {
-    pub download: usize,
-    pub unpack: usize,
-    pub materialize: usize,
+    /// Bottles downloaded at once
+    pub downloads: usize,
+    /// Bottles extracted into the store at once
+    pub extractions: usize,
 }
 
-impl Default for ConcurrencyLimits {
-    fn default() -> Self {
+impl ConcurrencyLimits {
+    /// `downloads` parallel downloads and one extraction worker per CPU
+    pub fn new(downloads: usize) -> Self {
         Self {
-            download: 16,
-            unpack: 4,
-            materialize: 4,
+            downloads,
+            extractions: std::thread::available_parallelism().map_or(4, |n| n.get()),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+impl Default for ConcurrencyLimits {
+    fn default() -> Self {
+        Self::new(48)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Info,
     Warn,
     Error,
 }
 
+/// Where library code reports problems it recovers from
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LoggerHandle {
+    /// Messages below this level are dropped
     pub level: LogLevel,
 }
 
+impl LoggerHandle {
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    /// Print a note to stderr, unless informational messages are silenced
+    pub fn note(&self, message: impl fmt::Display) {
+        if self.enabled(LogLevel::Info) {
+            eprintln!("    Note: {}", message);
+        }
+    }
+
+    /// Print a warning to stderr, unless warnings are silenced
+    pub fn warn(&self, message: impl fmt::Display) {
+        if self.enabled(LogLevel::Warn) {
+            eprintln!("    Warning: {}", message);
+        }
+    }
+}
+
 impl Default for LoggerHandle {
     fn default() -> Self {
         Self {
@@ -66,21 +108,62 @@ impl Default for LoggerHandle {
     }
 }
 
+/// Everything an installer is configured with
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Context {
     pub paths: Paths,
     pub concurrency: ConcurrencyLimits,
     pub logger: LoggerHandle,
+    /// Answer from caches only and never touch the network
+    pub offline: bool,
+    /// Mirror to resolve formulas and bottles from
+    pub mirror: Option<String>,
+    /// Formula API to fetch metadata from, instead of formulae.brew.sh
+    pub api_domain: Option<String>,
+    /// How `api_domain` lays out formula JSON
+    pub api_layout: ApiLayout,
 }
 
 impl Context {
-    pub fn from_defaults() -> Self {
+    pub fn new(root: &Path, prefix: &Path) -> Self {
         Self {
-            paths: Paths::from_root(PathBuf::from("/opt/zerobrew")),
+            paths: Paths::new(root, prefix),
             concurrency: ConcurrencyLimits::default(),
             logger: LoggerHandle::default(),
+            offline: false,
+            mirror: None,
+            api_domain: None,
+            api_layout: ApiLayout::default(),
         }
     }
+
+    pub fn from_defaults() -> Self {
+        Self::new(Path::new(DEFAULT_ROOT), Path::new(DEFAULT_PREFIX))
+    }
+
+    /// A context with the paths and limits of `config`, defaulting the rest
+    pub fn from_config(config: &Config) -> Self {
+        let root = config
+            .root
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_ROOT));
+        let prefix = config
+            .prefix
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_PREFIX));
+
+        let mut context = Self::new(&root, &prefix);
+        if let Some(downloads) = config.concurrency {
+            context.concurrency = ConcurrencyLimits::new(downloads);
+        }
+        if let Some(extractions) = config.extract_concurrency {
+            context.concurrency.extractions = extractions;
+        }
+        context.mirror = config.mirror.clone();
+        context.api_domain = config.api_domain.clone();
+        context.api_layout = config.api_layout.unwrap_or_default();
+        context
+    }
 }
 
 #[cfg(test)]
@@ -96,9 +179,13 @@ mod tests {
             context.paths.store,
             PathBuf::from("/opt/zerobrew").join("store")
         );
+        assert_eq!(
+            context.paths.prefix,
+            PathBuf::from("/opt/zerobrew").join("prefix")
+        );
         assert_eq!(
             context.paths.cellar,
-            PathBuf::from("/opt/zerobrew").join("cellar")
+            PathBuf::from("/opt/zerobrew/prefix").join("Cellar")
         );
         assert_eq!(
             context.paths.cache,
@@ -112,5 +199,56 @@ mod tests {
             context.paths.locks,
             PathBuf::from("/opt/zerobrew").join("locks")
         );
+        assert!(!context.offline);
+    }
+
+    #[test]
+    fn from_config_applies_configured_settings() {
+        let config = Config::parse(
+            r#"
+            root = "/srv/zb"
+            concurrency = 8
+            extract_concurrency = 2
+            mirror = "https://mirror.example.com"
+            api_domain = "https://artifacts.example.com/brew"
+            api_layout = "flat"
+            "#,
+        )
+        .unwrap();
+        let context = Context::from_config(&config);
+
+        assert_eq!(context.paths.root, PathBuf::from("/srv/zb"));
+        assert_eq!(context.paths.prefix, PathBuf::from(DEFAULT_PREFIX));
+        assert_eq!(
+            context.concurrency,
+            ConcurrencyLimits {
+                downloads: 8,
+                extractions: 2
+            }
+        );
+        assert_eq!(
+            context.mirror.as_deref(),
+            Some("https://mirror.example.com")
+        );
+        assert_eq!(
+            context.api_domain.as_deref(),
+            Some("https://artifacts.example.com/brew")
+        );
+        assert_eq!(context.api_layout, ApiLayout::Flat);
+        assert_eq!(
+            Context::from_config(&Config::default()),
+            Context::from_defaults()
+        );
+    }
+
+    #[test]
+    fn logger_drops_messages_below_its_level() {
+        let logger = LoggerHandle {
+            level: LogLevel::Warn,
+        };
+        assert!(!logger.enabled(LogLevel::Info));
+        assert!(logger.enabled(LogLevel::Warn));
+        assert!(logger.enabled(LogLevel::Error));
+        assert!(LoggerHandle::default().enabled(LogLevel::Info));
     }
 }
@@ -31,6 +31,32 @@ pub enum Error {
     NotInstalled {
         name: String,
     },
+    UnsupportedPlatform {
+        name: String,
+        requirement: String,
+        host: String,
+    },
+    MissingRequirement {
+        name: String,
+        requirement: String,
+        hint: String,
+    },
+    NoPreviousVersion {
+        name: String,
+    },
+    /// Upstream disabled the formula
+    DisabledFormula {
+        name: String,
+        /// e.g. "has been disabled because it does not build"
+        message: String,
+    },
+    RateLimited {
+        host: String,
+        /// Seconds until the host may be contacted again, if known
+        retry_after: Option<u64>,
+    },
+    /// The user declined to go on when asked
+    Aborted,
 }
 
 /// Type of existing file at a link conflict path
@@ -147,6 +173,77 @@ impl fmt::Display for Error {
                     name, name
                 )
             }
+            Error::UnsupportedPlatform {
+                name,
+                requirement,
+                host,
+            } => {
+                write!(
+                    f,
+                    "formula '{}' requires {} (this system runs {})\n  hint: try 'zb install --build-from-source {}' to build from source",
+                    name, requirement, host, name
+                )
+            }
+            Error::MissingRequirement {
+                name,
+                requirement,
+                hint,
+            } => {
+                write!(
+                    f,
+                    "formula '{}' requires {}\n  hint: {}",
+                    name, requirement, hint
+                )
+            }
+            Error::NoPreviousVersion { name } => {
+                write!(
+                    f,
+                    "no previous version of '{}' is available to roll back to\n  hint: run 'zb versions {}' to see the versions kept locally",
+                    name, name
+                )
+            }
+            Error::DisabledFormula { name, message } => {
+                write!(
+                    f,
+                    "formula '{}' {}\n  hint: run 'zb install --force {}' to install it anyway",
+                    name, message, name
+                )
+            }
+            Error::RateLimited { host, retry_after } => {
+                write!(f, "rate limited by {}", host)?;
+                match retry_after {
+                    Some(secs) => write!(
+                        f,
+                        " (retry after {}s)\n  hint: zb won't contact {} again until then",
+                        secs, host
+                    ),
+                    None => write!(f, "\n  hint: wait a while and try again"),
+                }
+            }
+            Error::Aborted => write!(f, "aborted"),
+        }
+    }
+}
+
+impl Error {
+    /// Stable machine-readable identifier for this error, used in JSON output
+    /// so scripts don't have to match on human-readable messages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::UnsupportedBottle { .. } => "unsupported_bottle",
+            Error::ChecksumMismatch { .. } => "checksum_mismatch",
+            Error::LinkConflict { .. } => "link_conflict",
+            Error::StoreCorruption { .. } => "store_corruption",
+            Error::NetworkFailure { .. } => "network_failure",
+            Error::MissingFormula { .. } => "missing_formula",
+            Error::DependencyCycle { .. } => "dependency_cycle",
+            Error::NotInstalled { .. } => "not_installed",
+            Error::UnsupportedPlatform { .. } => "unsupported_platform",
+            Error::MissingRequirement { .. } => "missing_requirement",
+            Error::NoPreviousVersion { .. } => "no_previous_version",
+            Error::DisabledFormula { .. } => "disabled_formula",
+            Error::RateLimited { .. } => "rate_limited",
+            Error::Aborted => "aborted",
         }
     }
 }
@@ -207,4 +304,75 @@ mod tests {
         assert!(msg.contains("nonexistent"));
         assert!(msg.contains("zb search"));
     }
+
+    #[test]
+    fn missing_requirement_display_includes_hint() {
+        let err = Error::MissingRequirement {
+            name: "foo".to_string(),
+            requirement: "the Xcode Command Line Tools".to_string(),
+            hint: "install Xcode Command Line Tools: xcode-select --install".to_string(),
+        };
+
+        let msg = err.to_string();
+        assert!(msg.contains("formula 'foo' requires the Xcode Command Line Tools"));
+        assert!(msg.contains("hint: install Xcode Command Line Tools"));
+    }
+
+    #[test]
+    fn no_previous_version_display_includes_versions_hint() {
+        let err = Error::NoPreviousVersion {
+            name: "jq".to_string(),
+        };
+
+        let msg = err.to_string();
+        assert!(msg.contains("no previous version of 'jq'"));
+        assert!(msg.contains("zb versions jq"));
+    }
+
+    #[test]
+    fn disabled_formula_display_includes_force_hint() {
+        let err = Error::DisabledFormula {
+            name: "foo".to_string(),
+            message: "has been disabled because it does not build".to_string(),
+        };
+
+        let msg = err.to_string();
+        assert!(msg.contains("formula 'foo' has been disabled because it does not build"));
+        assert!(msg.contains("zb install --force foo"));
+        assert_eq!(err.code(), "disabled_formula");
+    }
+
+    #[test]
+    fn rate_limited_display_includes_retry_after() {
+        let err = Error::RateLimited {
+            host: "ghcr.io".to_string(),
+            retry_after: Some(42),
+        };
+
+        let msg = err.to_string();
+        assert!(msg.contains("rate limited by ghcr.io (retry after 42s)"));
+        assert!(msg.contains("hint:"));
+        assert_eq!(err.code(), "rate_limited");
+    }
+
+    #[test]
+    fn aborted_has_its_own_code() {
+        assert_eq!(Error::Aborted.to_string(), "aborted");
+        assert_eq!(Error::Aborted.code(), "aborted");
+    }
+
+    #[test]
+    fn error_codes_are_stable_identifiers() {
+        let err = Error::NetworkFailure {
+            message: "timeout".to_string(),
+        };
+        assert_eq!(err.code(), "network_failure");
+
+        let err = Error::ChecksumMismatch {
+            expected: "a".to_string(),
+            actual: "b".to_string(),
+            file_name: None,
+        };
+        assert_eq!(err.code(), "checksum_mismatch");
+    }
 }
@@ -35,6 +35,110 @@ pub struct Formula {
     /// Source URLs for building from source
     #[serde(default)]
     pub urls: SourceUrls,
+    /// Platform requirements, e.g. a minimum macOS version
+    #[serde(default)]
+    pub requirements: Vec<Requirement>,
+    /// Other major versions packaged as their own formulas, e.g. `python@3.12`
+    #[serde(default)]
+    pub versioned_formulas: Vec<String>,
+    /// Tap the formula belongs to, e.g. `homebrew/core`
+    #[serde(default)]
+    pub tap: Option<String>,
+    /// Commit of the tap this metadata was generated from
+    #[serde(default)]
+    pub tap_git_head: Option<String>,
+    /// Whether upstream deprecated the formula; it still installs
+    #[serde(default)]
+    pub deprecated: bool,
+    /// When it was deprecated, e.g. `2024-05-01`
+    #[serde(default)]
+    pub deprecation_date: Option<String>,
+    /// Why it was deprecated: a reason such as `unmaintained`, or free text
+    #[serde(default)]
+    pub deprecation_reason: Option<String>,
+    /// Whether upstream disabled the formula; it no longer installs
+    #[serde(default)]
+    pub disabled: bool,
+    /// When it was, or for a deprecated formula will be, disabled
+    #[serde(default)]
+    pub disable_date: Option<String>,
+    /// Why it was disabled
+    #[serde(default)]
+    pub disable_reason: Option<String>,
+    /// Whether the formula defines a `post_install` method
+    #[serde(default)]
+    pub post_install_defined: bool,
+    /// Steps of `post_install`, when parsed from the formula's Ruby source
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<PostInstallStep>,
+}
+
+/// A statement of a formula's `post_install` method.
+///
+/// Paths are templates over the Ruby path helpers they were written with:
+/// `{var}/log` for `var/"log"`, `{bin}/initdb` for `bin/"initdb"`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PostInstallStep {
+    /// Create a directory and its parents: `(var/"log").mkpath`
+    Mkpath { path: String },
+    /// Run a command: `system bin/"initdb", "--locale=C"`
+    System { args: Vec<String> },
+    /// A statement zerobrew can't run, as written in the formula
+    Unsupported { source: String },
+}
+
+/// A platform requirement from `depends_on`, such as `macos: :ventura`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Requirement {
+    /// Requirement kind: `macos`, `maximum_macos`, `linux`, `xcode`, ...
+    pub name: String,
+    /// Version bound, e.g. "13" or "ventura"; absent for plain platform requirements
+    #[serde(default, deserialize_with = "deserialize_requirement_version")]
+    pub version: Option<String>,
+    /// Phases the requirement applies to (e.g. "build"); empty means always
+    #[serde(default)]
+    pub contexts: Vec<String>,
+}
+
+impl Requirement {
+    /// Whether the requirement still applies when installing a prebuilt bottle
+    pub fn applies_to_bottles(&self) -> bool {
+        !self.contexts.iter().any(|c| c == "build" || c == "test")
+    }
+}
+
+/// Homebrew's wording for its standard deprecation and disable reasons;
+/// any other reason is upstream's own text
+fn describe_deprecation_reason(reason: &str) -> &str {
+    match reason {
+        "does_not_build" => "does not build",
+        "no_license" => "has no license",
+        "repo_archived" => "has an archived upstream repository",
+        "repo_removed" => "has a removed upstream repository",
+        "unmaintained" => "is not maintained upstream",
+        "unsupported" => "is not supported upstream",
+        "deprecated_upstream" => "is deprecated upstream",
+        "versioned_formula" => "is a versioned formula",
+        "checksum_mismatch" => {
+            "was built with a source file whose checksum has since changed upstream"
+        }
+        other => other,
+    }
+}
+
+/// Requirement versions are usually strings but may be bare numbers
+fn deserialize_requirement_version<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(s)) => Some(s),
+            Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+            _ => None,
+        },
+    )
 }
 
 /// Source URLs for building from source
@@ -202,6 +306,31 @@ impl Formula {
         }
     }
 
+    /// What upstream did to the formula, worded like Homebrew's warning,
+    /// e.g. "has been deprecated because it is not maintained upstream".
+    /// `None` unless the formula is deprecated or disabled.
+    pub fn deprecation_message(&self) -> Option<String> {
+        let (mut message, reason) = if self.disabled {
+            ("has been disabled".to_string(), &self.disable_reason)
+        } else if self.deprecated {
+            ("has been deprecated".to_string(), &self.deprecation_reason)
+        } else {
+            return None;
+        };
+
+        if let Some(reason) = reason {
+            message.push_str(" because it ");
+            message.push_str(describe_deprecation_reason(reason));
+        }
+        match &self.disable_date {
+            Some(date) if self.disabled => message.push_str(&format!(" (since {date})")),
+            Some(date) => message.push_str(&format!("; it will be disabled on {date}")),
+            None => {}
+        }
+
+        Some(message)
+    }
+
     /// Returns the effective dependencies for the current platform.
     /// On Linux, this includes `uses_from_macos` dependencies since they
     /// aren't available as system libraries like on macOS.
@@ -297,6 +426,59 @@ mod tests {
         assert_eq!(formula.bottle.stable.rebuild, 0);
     }
 
+    #[test]
+    fn deprecation_message_follows_homebrew_wording() {
+        let json = r#"{
+            "name": "foo",
+            "versions": { "stable": "1.0" },
+            "deprecated": true,
+            "deprecation_date": "2024-05-01",
+            "deprecation_reason": "unmaintained",
+            "disabled": false,
+            "disable_date": "2025-05-01",
+            "disable_reason": null
+        }"#;
+        let mut formula: Formula = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            formula.deprecation_message().as_deref(),
+            Some(
+                "has been deprecated because it is not maintained upstream; \
+                 it will be disabled on 2025-05-01"
+            )
+        );
+
+        formula.disabled = true;
+        formula.disable_reason = Some("uses an insecure protocol".to_string());
+        assert_eq!(
+            formula.deprecation_message().as_deref(),
+            Some("has been disabled because it uses an insecure protocol (since 2025-05-01)")
+        );
+
+        assert_eq!(Formula::default().deprecation_message(), None);
+    }
+
+    #[test]
+    fn deserializes_requirements() {
+        let json = r#"{
+            "name": "foo",
+            "versions": { "stable": "1.0" },
+            "requirements": [
+                { "name": "macos", "cask": null, "download": null, "version": "13", "contexts": [], "specs": ["stable"] },
+                { "name": "xcode", "version": 15.3, "contexts": ["build"] },
+                { "name": "linux", "version": null, "contexts": [] }
+            ]
+        }"#;
+
+        let formula: Formula = serde_json::from_str(json).unwrap();
+        assert_eq!(formula.requirements.len(), 3);
+        assert_eq!(formula.requirements[0].name, "macos");
+        assert_eq!(formula.requirements[0].version.as_deref(), Some("13"));
+        assert!(formula.requirements[0].applies_to_bottles());
+        assert_eq!(formula.requirements[1].version.as_deref(), Some("15.3"));
+        assert!(!formula.requirements[1].applies_to_bottles());
+        assert_eq!(formula.requirements[2].version, None);
+    }
+
     #[test]
     fn uses_from_macos_handles_mixed_formats() {
         // Test that uses_from_macos handles both strings and objects:
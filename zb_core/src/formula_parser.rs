@@ -1,8 +1,11 @@
 //! Ruby formula parser for Homebrew tap formulas.
 //!
 //! Parses a subset of the Ruby DSL used in Homebrew formulas to extract
-//! the metadata needed for bottle installation. This is intentionally limited
+//! the metadata needed for bottle installation, and the sources to build
+//! from when there is no bottle. This is intentionally limited
 //! to the parts we need and ignores `install`, `test`, `caveats`, and `service` blocks.
+//! Of `post_install`, only directory creation and `system` commands are
+//! understood; other statements are kept as unsupported steps.
 //!
 //! # Supported DSL Elements
 //!
@@ -13,25 +16,35 @@
 //!   url "https://..."
 //!   sha256 "..."
 //!   license "MIT"
+//!   head "https://...git", branch: "main"
 //!   version "1.2.3"
 //!   revision 1
 //!
 //!   depends_on "dep1"
 //!   depends_on "dep2" => :build
+//!   depends_on arch: :x86_64
+//!   depends_on xcode: ["15.3", :build]
 //!   uses_from_macos "zlib"
 //!   uses_from_macos "flex" => :build
 //!
 //!   bottle do
+//!     root_url "https://..."
 //!     rebuild 1
 //!     sha256 cellar: :any, arm64_sonoma: "..."
 //!     sha256 cellar: :any_skip_relocation, x86_64_linux: "..."
 //!   end
+//!
+//!   def post_install
+//!     (var/"log").mkpath
+//!     mkdir_p var/"foo"
+//!     system bin/"foo", "--init", "#{var}/foo"
+//!   end
 //! end
 //! ```
 
 use tree_sitter::{Node, Parser};
 
-use crate::formula::{BottleFile, Formula};
+use crate::formula::{BottleFile, Formula, HeadSource, PostInstallStep, Requirement, StableSource};
 
 /// Error type for formula parsing failures.
 #[derive(Debug)]
@@ -160,15 +173,23 @@ fn parse_class_body(
 
     let mut cursor = body.walk();
     let mut revision: u32 = 0;
+    let mut root_url: Option<String> = None;
 
     for child in body.children(&mut cursor) {
         match child.kind() {
             "call" | "method_call" => {
-                parse_method_call(&child, source, formula, &mut revision)?;
+                parse_method_call(&child, source, formula, &mut revision, &mut root_url)?;
             }
             "do_block" | "block" => {
                 // Handle blocks that might be at class level
             }
+            "method" => {
+                let name = child.child_by_field_name("name");
+                if name.is_some_and(|n| get_node_text(&n, source) == "post_install") {
+                    formula.post_install_defined = true;
+                    formula.post_install = parse_post_install(&child, source);
+                }
+            }
             _ => {}
         }
     }
@@ -179,15 +200,62 @@ fn parse_class_body(
         // but we track it for the effective_version calculation
     }
 
+    // Bottles hosted somewhere other than homebrew/core, now that the
+    // version they are named after is known
+    if let Some(root_url) = root_url {
+        let pkg_version = if revision > 0 {
+            format!("{}_{}", formula.versions.stable, revision)
+        } else {
+            formula.versions.stable.clone()
+        };
+        let rebuild = formula.bottle.stable.rebuild;
+        for (tag, file) in formula.bottle.stable.files.iter_mut() {
+            file.url = bottle_url(
+                &root_url,
+                &formula.name,
+                &pkg_version,
+                tag,
+                rebuild,
+                &file.sha256,
+            );
+        }
+    }
+
     Ok(())
 }
 
+/// URL of a bottle under a `root_url`, laid out the way Homebrew uploads it:
+/// as a blob of the formula's image on GitHub Packages, otherwise as
+/// `<name>-<version>.<tag>.bottle[.<rebuild>].tar.gz`.
+fn bottle_url(
+    root_url: &str,
+    name: &str,
+    version: &str,
+    tag: &str,
+    rebuild: u32,
+    sha256: &str,
+) -> String {
+    let root_url = root_url.trim_end_matches('/');
+    if root_url.starts_with("https://ghcr.io/v2/") {
+        // Image names can't contain `@` or `+`
+        let image = name.replace('@', "/").replace('+', "x");
+        return format!("{root_url}/{image}/blobs/sha256:{sha256}");
+    }
+    let rebuild = if rebuild > 0 {
+        format!(".{rebuild}")
+    } else {
+        String::new()
+    };
+    format!("{root_url}/{name}-{version}.{tag}.bottle{rebuild}.tar.gz")
+}
+
 /// Parses a method call and extracts relevant metadata.
 fn parse_method_call(
     node: &Node,
     source: &str,
     formula: &mut Formula,
     revision: &mut u32,
+    root_url: &mut Option<String>,
 ) -> Result<(), ParseError> {
     // Get the method name
     let method_name = if let Some(method_node) = node.child_by_field_name("method") {
@@ -214,13 +282,44 @@ fn parse_method_call(
             }
         }
         "url" => {
-            // Extract version from URL if not explicitly set
+            let Some(url) = extract_string_arg(node, source) else {
+                return Ok(());
+            };
+            let tag = extract_keyword_arg(node, source, "tag");
+            // Extract version from URL, or the tag of a git checkout, if
+            // not explicitly set
             if formula.versions.stable.is_empty()
-                && let Some(url) = extract_string_arg(node, source)
-                && let Some(v) = extract_version_from_url(&url)
+                && let Some(v) = extract_version_from_url(&url).or_else(|| {
+                    tag.as_ref()
+                        .and_then(|tag| extract_version_from_url(&format!("/{tag}")))
+                })
             {
                 formula.versions.stable = v;
             }
+            formula.urls.stable = Some(StableSource {
+                url,
+                checksum: None,
+                tag,
+                revision: extract_keyword_arg(node, source, "revision"),
+                using: extract_keyword_arg(node, source, "using"),
+            });
+        }
+        "sha256" => {
+            // The checksum of the source tarball, which follows its `url`
+            if let Some(stable) = formula.urls.stable.as_mut() {
+                stable.checksum = extract_string_arg(node, source);
+            }
+        }
+        "head" => {
+            // Only the `head "url", branch: "..."` form; `head do` blocks
+            // are left alone
+            if let Some(url) = extract_string_arg(node, source) {
+                formula.urls.head = Some(HeadSource {
+                    url,
+                    branch: extract_keyword_arg(node, source, "branch"),
+                    using: extract_keyword_arg(node, source, "using"),
+                });
+            }
         }
         "revision" => {
             if let Some(rev) = extract_integer_arg(node, source) {
@@ -234,7 +333,7 @@ fn parse_method_call(
             parse_uses_from_macos(node, source, formula);
         }
         "bottle" => {
-            parse_bottle_block(node, source, formula)?;
+            parse_bottle_block(node, source, formula, root_url)?;
         }
         _ => {}
     }
@@ -277,6 +376,36 @@ fn extract_string_value(node: &Node, source: &str) -> Option<String> {
     }
 }
 
+/// Extracts the string or symbol value of a keyword argument, such as the
+/// `tag:` of `url "...", tag: "v1.0"`.
+fn extract_keyword_arg(node: &Node, source: &str, key: &str) -> Option<String> {
+    let args = node.child_by_field_name("arguments")?;
+
+    let mut cursor = args.walk();
+    for child in args.children(&mut cursor) {
+        if child.kind() != "pair" {
+            continue;
+        }
+        let (Some(k), Some(v)) = (
+            child.child_by_field_name("key"),
+            child.child_by_field_name("value"),
+        ) else {
+            continue;
+        };
+        if get_node_text(&k, source).trim_start_matches(':') == key {
+            return extract_string_value(&v, source).or_else(|| {
+                (v.kind() == "simple_symbol").then(|| {
+                    get_node_text(&v, source)
+                        .trim_start_matches(':')
+                        .to_string()
+                })
+            });
+        }
+    }
+
+    None
+}
+
 /// Extracts an integer argument from a method call.
 fn extract_integer_arg(node: &Node, source: &str) -> Option<i64> {
     let args = node.child_by_field_name("arguments")?;
@@ -304,6 +433,11 @@ fn parse_depends_on(node: &Node, source: &str, formula: &mut Formula) {
     let mut is_build_only = false;
 
     for child in args.children(&mut cursor) {
+        if let Some(requirement) = parse_requirement(&child, source) {
+            formula.requirements.push(requirement);
+            continue;
+        }
+
         match child.kind() {
             "string" | "bare_string" => {
                 dep_name = extract_string_value(&child, source);
@@ -343,6 +477,51 @@ fn parse_depends_on(node: &Node, source: &str, formula: &mut Formula) {
     }
 }
 
+/// Parses a platform requirement: `depends_on :linux`, `depends_on arch: :x86_64`,
+/// `depends_on macos: :ventura` or `depends_on xcode: ["15.3", :build]`.
+fn parse_requirement(node: &Node, source: &str) -> Option<Requirement> {
+    let symbol_text = |n: &Node| get_node_text(n, source).trim_start_matches(':').to_string();
+
+    match node.kind() {
+        "simple_symbol" => Some(Requirement {
+            name: symbol_text(node),
+            ..Default::default()
+        }),
+        "pair" => {
+            let key = node.child_by_field_name("key")?;
+            if key.kind() != "hash_key_symbol" {
+                return None;
+            }
+            let mut requirement = Requirement {
+                name: get_node_text(&key, source),
+                ..Default::default()
+            };
+
+            let value = node.child_by_field_name("value")?;
+            let mut values = vec![value];
+            if value.kind() == "array" {
+                let mut cursor = value.walk();
+                values = value.named_children(&mut cursor).collect();
+            }
+            for value in values {
+                if let Some(version) = extract_string_value(&value, source) {
+                    requirement.version = Some(version);
+                } else if value.kind() == "simple_symbol" {
+                    let symbol = symbol_text(&value);
+                    match symbol.as_str() {
+                        "build" | "test" | "optional" | "recommended" => {
+                            requirement.contexts.push(symbol)
+                        }
+                        _ => requirement.version = Some(symbol),
+                    }
+                }
+            }
+            Some(requirement)
+        }
+        _ => None,
+    }
+}
+
 /// Parses a dependency pair like "name" => :build.
 fn parse_dependency_pair(node: &Node, source: &str) -> Option<(String, String)> {
     let key = node.child_by_field_name("key")?;
@@ -405,8 +584,13 @@ fn parse_uses_from_macos(node: &Node, source: &str, formula: &mut Formula) {
     }
 }
 
-/// Parses a bottle block.
-fn parse_bottle_block(node: &Node, source: &str, formula: &mut Formula) -> Result<(), ParseError> {
+/// Parses a bottle block, returning its `root_url` in `root_url`.
+fn parse_bottle_block(
+    node: &Node,
+    source: &str,
+    formula: &mut Formula,
+    root_url: &mut Option<String>,
+) -> Result<(), ParseError> {
     // Find the do_block
     let block = find_child_by_kind(node, "do_block").or_else(|| find_child_by_kind(node, "block"));
 
@@ -427,7 +611,7 @@ fn parse_bottle_block(node: &Node, source: &str, formula: &mut Formula) -> Resul
 
     for child in body.children(&mut cursor) {
         if child.kind() == "call" || child.kind() == "method_call" {
-            parse_bottle_statement(&child, source, formula)?;
+            parse_bottle_statement(&child, source, formula, root_url)?;
         }
     }
 
@@ -439,6 +623,7 @@ fn parse_bottle_statement(
     node: &Node,
     source: &str,
     formula: &mut Formula,
+    root_url: &mut Option<String>,
 ) -> Result<(), ParseError> {
     let method_name = if let Some(method_node) = node.child_by_field_name("method") {
         get_node_text(&method_node, source)
@@ -457,6 +642,11 @@ fn parse_bottle_statement(
         "sha256" => {
             parse_bottle_sha256(node, source, formula)?;
         }
+        "root_url" => {
+            // `root_url "https://...", using: :strategy`; the strategy only
+            // matters to Homebrew's own download code
+            *root_url = extract_string_arg(node, source);
+        }
         _ => {}
     }
 
@@ -566,6 +756,135 @@ fn extract_version_from_url(url: &str) -> Option<String> {
         .map(|m| m.as_str().to_string())
 }
 
+/// Path helpers available in `post_install`, kept as `{name}` in step paths
+const PATH_HELPERS: &[&str] = &[
+    "prefix",
+    "bin",
+    "sbin",
+    "lib",
+    "libexec",
+    "include",
+    "share",
+    "pkgshare",
+    "etc",
+    "var",
+    "opt_prefix",
+    "opt_bin",
+    "opt_sbin",
+    "opt_lib",
+    "opt_libexec",
+    "opt_include",
+    "opt_share",
+    "opt_pkgshare",
+    "HOMEBREW_PREFIX",
+];
+
+/// Parses the statements of a `def post_install` method into steps.
+fn parse_post_install(method: &Node, source: &str) -> Vec<PostInstallStep> {
+    let Some(body) = method
+        .child_by_field_name("body")
+        .or_else(|| find_child_by_kind(method, "body_statement"))
+    else {
+        return Vec::new();
+    };
+
+    let mut cursor = body.walk();
+    body.named_children(&mut cursor)
+        .filter(|statement| statement.kind() != "comment")
+        .map(|statement| {
+            parse_post_install_statement(&statement, source).unwrap_or_else(|| {
+                PostInstallStep::Unsupported {
+                    source: get_node_text(&statement, source),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Parses `(path).mkpath`, `mkdir_p path` or `system args...`; `None` for
+/// anything else, including conditional statements.
+fn parse_post_install_statement(node: &Node, source: &str) -> Option<PostInstallStep> {
+    if node.kind() != "call" {
+        return None;
+    }
+    let method = get_node_text(&node.child_by_field_name("method")?, source);
+    let receiver = node.child_by_field_name("receiver");
+    let args: Vec<Node> = match node.child_by_field_name("arguments") {
+        Some(args) => {
+            let mut cursor = args.walk();
+            args.named_children(&mut cursor).collect()
+        }
+        None => Vec::new(),
+    };
+
+    match (method.as_str(), receiver, args.as_slice()) {
+        ("mkpath", Some(receiver), []) => Some(PostInstallStep::Mkpath {
+            path: eval_path(&receiver, source)?,
+        }),
+        ("mkdir_p", None, [path]) => Some(PostInstallStep::Mkpath {
+            path: eval_path(path, source)?,
+        }),
+        ("system", None, [_, ..]) => Some(PostInstallStep::System {
+            args: args
+                .iter()
+                .map(|arg| eval_path(arg, source))
+                .collect::<Option<_>>()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Evaluates a path or string expression built from path helpers, `/` and
+/// string literals with interpolated helpers, e.g. `var/"log"` or
+/// `"#{bin}/foo"`.
+fn eval_path(node: &Node, source: &str) -> Option<String> {
+    match node.kind() {
+        "identifier" | "constant" => {
+            let name = get_node_text(node, source);
+            PATH_HELPERS
+                .contains(&name.as_str())
+                .then(|| format!("{{{name}}}"))
+        }
+        "parenthesized_statements" => {
+            let mut cursor = node.walk();
+            let mut children = node.named_children(&mut cursor);
+            match (children.next(), children.next()) {
+                (Some(inner), None) => eval_path(&inner, source),
+                _ => None,
+            }
+        }
+        "binary" => {
+            let operator = node.child_by_field_name("operator")?;
+            if get_node_text(&operator, source) != "/" {
+                return None;
+            }
+            let left = eval_path(&node.child_by_field_name("left")?, source)?;
+            let right = eval_path(&node.child_by_field_name("right")?, source)?;
+            Some(format!("{left}/{right}"))
+        }
+        "string" => {
+            let mut value = String::new();
+            let mut cursor = node.walk();
+            for part in node.named_children(&mut cursor) {
+                match part.kind() {
+                    "string_content" => value.push_str(&get_node_text(&part, source)),
+                    "interpolation" => {
+                        let mut inner_cursor = part.walk();
+                        let mut inner = part.named_children(&mut inner_cursor);
+                        match (inner.next(), inner.next()) {
+                            (Some(expr), None) => value.push_str(&eval_path(&expr, source)?),
+                            _ => return None,
+                        }
+                    }
+                    _ => return None,
+                }
+            }
+            Some(value)
+        }
+        _ => None,
+    }
+}
+
 /// Gets the text content of a node.
 fn get_node_text(node: &Node, source: &str) -> String {
     let start = node.start_byte();
@@ -626,6 +945,50 @@ end
         assert!(formula.bottle.stable.files.contains_key("x86_64_linux"));
     }
 
+    #[test]
+    fn parse_formula_sources() {
+        let source = r#"
+class Tool < Formula
+  url "https://example.com/tool-2.0.tar.gz"
+  sha256 "feedface"
+  head "https://github.com/user/tool.git", branch: "main"
+
+  bottle do
+    sha256 arm64_sonoma: "abc123"
+  end
+end
+"#;
+
+        let formula = parse_ruby_formula(source, "tool").unwrap();
+
+        let stable = formula.urls.stable.unwrap();
+        assert_eq!(stable.url, "https://example.com/tool-2.0.tar.gz");
+        assert_eq!(stable.checksum.as_deref(), Some("feedface"));
+        let head = formula.urls.head.unwrap();
+        assert_eq!(head.url, "https://github.com/user/tool.git");
+        assert_eq!(head.branch.as_deref(), Some("main"));
+        // The bottle's checksum stays with the bottle
+        assert_eq!(formula.bottle.stable.files["arm64_sonoma"].sha256, "abc123");
+    }
+
+    #[test]
+    fn parse_formula_git_source() {
+        let source = r#"
+class Tool < Formula
+  url "https://github.com/user/tool.git", tag: "v2.0", revision: "0123abc", using: :git
+end
+"#;
+
+        let formula = parse_ruby_formula(source, "tool").unwrap();
+
+        let stable = formula.urls.stable.unwrap();
+        assert_eq!(formula.versions.stable, "2.0");
+        assert_eq!(stable.tag.as_deref(), Some("v2.0"));
+        assert_eq!(stable.revision.as_deref(), Some("0123abc"));
+        assert_eq!(stable.using.as_deref(), Some("git"));
+        assert_eq!(stable.checksum, None);
+    }
+
     #[test]
     fn parse_formula_with_build_deps() {
         let source = r#"
@@ -657,6 +1020,45 @@ end
         assert!(!formula.dependencies.contains(&"rust".to_string()));
     }
 
+    #[test]
+    fn parse_formula_with_requirements() {
+        let source = r#"
+class Foo < Formula
+  url "https://example.com/foo-1.0.tar.gz"
+  sha256 "abc"
+
+  depends_on "pkgconf" => :build
+  depends_on :macos
+  depends_on arch: :x86_64
+  depends_on macos: :ventura
+  depends_on xcode: ["15.3", :build]
+
+  def install
+  end
+end
+"#;
+
+        let formula = parse_ruby_formula(source, "foo").unwrap();
+
+        assert_eq!(formula.build_dependencies, vec!["pkgconf"]);
+        assert!(formula.dependencies.is_empty());
+
+        let requirements: Vec<_> = formula
+            .requirements
+            .iter()
+            .map(|r| (r.name.as_str(), r.version.as_deref(), r.contexts.clone()))
+            .collect();
+        assert_eq!(
+            requirements,
+            vec![
+                ("macos", None, vec![]),
+                ("arch", Some("x86_64"), vec![]),
+                ("macos", Some("ventura"), vec![]),
+                ("xcode", Some("15.3"), vec!["build".to_string()]),
+            ]
+        );
+    }
+
     #[test]
     fn parse_formula_with_uses_from_macos() {
         let source = r#"
@@ -716,6 +1118,58 @@ end
         assert_eq!(formula.effective_version(), "1.0.0_2");
     }
 
+    #[test]
+    fn parse_formula_with_root_url() {
+        let source = r#"
+class Foo < Formula
+  url "https://example.com/foo-1.0.0.tar.gz"
+  revision 1
+
+  bottle do
+    root_url "https://github.com/user/homebrew-tools/releases/download/foo-1.0.0_1"
+    rebuild 2
+    sha256 cellar: :any, arm64_sonoma: "def456"
+    sha256 x86_64_linux: "789abc"
+  end
+end
+"#;
+
+        let formula = parse_ruby_formula(source, "foo").unwrap();
+        let files = &formula.bottle.stable.files;
+
+        assert_eq!(
+            files["arm64_sonoma"].url,
+            "https://github.com/user/homebrew-tools/releases/download/foo-1.0.0_1/foo-1.0.0_1.arm64_sonoma.bottle.2.tar.gz"
+        );
+        assert_eq!(files["x86_64_linux"].sha256, "789abc");
+    }
+
+    #[test]
+    fn bottle_url_uses_blobs_on_github_packages() {
+        assert_eq!(
+            bottle_url(
+                "https://ghcr.io/v2/user/tools/",
+                "python@3.12",
+                "3.12.1",
+                "x86_64_linux",
+                0,
+                "abc"
+            ),
+            "https://ghcr.io/v2/user/tools/python/3.12/blobs/sha256:abc"
+        );
+        assert_eq!(
+            bottle_url(
+                "https://dl.example.com",
+                "foo",
+                "1.0",
+                "x86_64_linux",
+                0,
+                "abc"
+            ),
+            "https://dl.example.com/foo-1.0.x86_64_linux.bottle.tar.gz"
+        );
+    }
+
     #[test]
     fn parse_formula_with_explicit_version() {
         let source = r#"
@@ -978,4 +1432,66 @@ end
             Some("3.12.0".to_string())
         );
     }
+
+    #[test]
+    fn parses_post_install_steps() {
+        let source = r##"
+class Pgtool < Formula
+  url "https://example.com/pgtool-14.0.tar.gz"
+
+  def post_install
+    (var/"log").mkpath
+    mkdir_p var/"pgtool"
+    # Initialize the cluster
+    system bin/"initdb", "--locale=C", "#{var}/pgtool"
+    system "#{opt_bin}/pgtool", "setup" unless File.exist?(var/"pgtool/PG_VERSION")
+    ohai "Done"
+  end
+end
+"##;
+
+        let formula = parse_ruby_formula(source, "pgtool").unwrap();
+        assert!(formula.post_install_defined);
+        assert_eq!(
+            formula.post_install,
+            vec![
+                PostInstallStep::Mkpath {
+                    path: "{var}/log".to_string()
+                },
+                PostInstallStep::Mkpath {
+                    path: "{var}/pgtool".to_string()
+                },
+                PostInstallStep::System {
+                    args: vec![
+                        "{bin}/initdb".to_string(),
+                        "--locale=C".to_string(),
+                        "{var}/pgtool".to_string()
+                    ]
+                },
+                PostInstallStep::Unsupported {
+                    source: "system \"#{opt_bin}/pgtool\", \"setup\" unless File.exist?(var/\"pgtool/PG_VERSION\")".to_string()
+                },
+                PostInstallStep::Unsupported {
+                    source: "ohai \"Done\"".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn formula_without_post_install_has_no_steps() {
+        let source = r#"
+class Plain < Formula
+  url "https://example.com/plain-1.0.tar.gz"
+
+  def install
+    system "make", "install"
+  end
+end
+"#;
+
+        let formula = parse_ruby_formula(source, "plain").unwrap();
+        assert!(!formula.post_install_defined);
+        assert!(formula.post_install.is_empty());
+    }
 }
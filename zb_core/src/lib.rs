@@ -2,17 +2,21 @@
 #![allow(clippy::nonminimal_bool)]
 
 pub mod bottle;
+pub mod config;
 pub mod context;
 pub mod errors;
 pub mod formula;
 pub mod formula_parser;
+pub mod platform;
 pub mod resolve;
 pub mod version;
 
-pub use bottle::{SelectedBottle, select_bottle};
+pub use bottle::{SelectedBottle, bottle_tag_fallbacks, select_bottle, select_bottle_with_tag};
+pub use config::{ApiLayout, CleanupPolicy, ColorMode, Config};
 pub use context::{ConcurrencyLimits, Context, LogLevel, LoggerHandle, Paths};
 pub use errors::{Error, LinkConflictType};
 pub use formula::Formula;
 pub use formula_parser::{ParseError, parse_ruby_formula};
+pub use platform::{DeveloperTools, Host, HostPlatform, MacOSVersion, check_requirements};
 pub use resolve::resolve_closure;
 pub use version::{OutdatedPackage, Version};
@@ -0,0 +1,592 @@
+//! Host platform detection and formula platform requirements.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::{Error, Formula};
+
+/// macOS release names as used by Homebrew, newest first
+pub(crate) const MACOS_NAMES: &[(&str, u32, u32)] = &[
+    ("tahoe", 26, 0),
+    ("sequoia", 15, 0),
+    ("sonoma", 14, 0),
+    ("ventura", 13, 0),
+    ("monterey", 12, 0),
+    ("big_sur", 11, 0),
+    ("catalina", 10, 15),
+    ("mojave", 10, 14),
+    ("high_sierra", 10, 13),
+    ("sierra", 10, 12),
+    ("el_capitan", 10, 11),
+];
+
+/// A macOS release, compared by major and minor version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MacOSVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl MacOSVersion {
+    pub fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parse a version number ("13", "10.15", "14.6.1") or a release name
+    /// ("ventura", ":big_sur")
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches(':');
+
+        if let Some((_, major, minor)) = MACOS_NAMES.iter().find(|(name, _, _)| *name == s) {
+            return Some(Self::new(*major, *minor));
+        }
+
+        let (major, minor) = parse_major_minor(s)?;
+        Some(Self::new(major, minor))
+    }
+
+    /// Compare releases, ignoring the minor version from macOS 11 onwards
+    /// where a requirement like "13" covers every 13.x release
+    fn cmp_release(&self, other: &Self) -> Ordering {
+        if self.major >= 11 && other.major >= 11 {
+            self.major.cmp(&other.major)
+        } else {
+            self.cmp(other)
+        }
+    }
+}
+
+impl fmt::Display for MacOSVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.major == 10 || self.minor > 0 {
+            write!(f, "{}.{}", self.major, self.minor)
+        } else {
+            write!(f, "{}", self.major)
+        }
+    }
+}
+
+/// The operating system zb is running on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostPlatform {
+    /// macOS, with its version if it could be determined
+    MacOS(Option<MacOSVersion>),
+    Linux,
+    Other,
+}
+
+impl HostPlatform {
+    /// Detect the running platform. On macOS the version comes from `sw_vers`.
+    pub fn detect() -> Self {
+        #[cfg(target_os = "macos")]
+        {
+            let version = std::process::Command::new("sw_vers")
+                .arg("-productVersion")
+                .output()
+                .ok()
+                .filter(|out| out.status.success())
+                .and_then(|out| MacOSVersion::parse(&String::from_utf8_lossy(&out.stdout)));
+            HostPlatform::MacOS(version)
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            HostPlatform::Linux
+        }
+
+        #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+        {
+            HostPlatform::Other
+        }
+    }
+}
+
+impl fmt::Display for HostPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostPlatform::MacOS(Some(version)) => write!(f, "macOS {}", version),
+            HostPlatform::MacOS(None) => write!(f, "macOS"),
+            HostPlatform::Linux => write!(f, "Linux"),
+            HostPlatform::Other => write!(f, "an unsupported platform"),
+        }
+    }
+}
+
+/// Apple developer tools found on a macOS host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeveloperTools {
+    /// Only the Command Line Tools
+    CommandLineTools,
+    /// A full Xcode install, with its (major, minor) version if known
+    Xcode(Option<(u32, u32)>),
+}
+
+/// The machine zb runs on: its OS, CPU architecture and the tools formula
+/// requirements can ask for. Tools are probed on first use since few
+/// formulas need them.
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub platform: HostPlatform,
+    /// Homebrew architecture name: `arm64` or `x86_64`
+    pub arch: String,
+    developer_tools: OnceLock<Option<DeveloperTools>>,
+    java: OnceLock<Option<Option<u32>>>,
+}
+
+impl Host {
+    pub fn new(platform: HostPlatform, arch: &str) -> Self {
+        Self {
+            platform,
+            arch: normalize_arch(arch).to_string(),
+            developer_tools: OnceLock::new(),
+            java: OnceLock::new(),
+        }
+    }
+
+    /// Detect the running platform and architecture
+    pub fn detect() -> Self {
+        Self::new(HostPlatform::detect(), std::env::consts::ARCH)
+    }
+
+    /// Use the given developer tools instead of probing `xcode-select`
+    pub fn with_developer_tools(mut self, tools: Option<DeveloperTools>) -> Self {
+        self.developer_tools = OnceLock::from(tools);
+        self
+    }
+
+    /// Use the given Java runtime instead of probing `java -version`.
+    /// `Some(None)` is a runtime whose version could not be determined.
+    pub fn with_java(mut self, major: Option<Option<u32>>) -> Self {
+        self.java = OnceLock::from(major);
+        self
+    }
+
+    /// Installed Xcode or Command Line Tools, if any
+    pub fn developer_tools(&self) -> Option<DeveloperTools> {
+        *self.developer_tools.get_or_init(probe_developer_tools)
+    }
+
+    /// Major version of the Java runtime on PATH, if one is installed
+    pub fn java(&self) -> Option<Option<u32>> {
+        *self.java.get_or_init(probe_java)
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.platform, self.arch)
+    }
+}
+
+/// Map architecture spellings (`aarch64`, `intel`, ...) to Homebrew's names
+fn normalize_arch(arch: &str) -> &str {
+    match arch.trim().trim_start_matches(':') {
+        "arm64" | "arm" | "aarch64" => "arm64",
+        "x86_64" | "intel" | "amd64" => "x86_64",
+        other => other,
+    }
+}
+
+/// Parse the leading "major[.minor]" of a dotted version
+fn parse_major_minor(s: &str) -> Option<(u32, u32)> {
+    let mut parts = s.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(minor) => minor.parse().ok()?,
+        None => 0,
+    };
+    Some((major, minor))
+}
+
+/// Java major version from a version string: "1.8.0_292" is 8, "17.0.2" is 17
+fn parse_java_major(s: &str) -> Option<u32> {
+    let mut parts = s.trim().split(['.', '_', '-', '+']);
+    match parts.next()?.parse().ok()? {
+        1 => parts.next()?.parse().ok(),
+        major => Some(major),
+    }
+}
+
+fn probe_developer_tools() -> Option<DeveloperTools> {
+    if !cfg!(target_os = "macos") {
+        return None;
+    }
+
+    let output = std::process::Command::new("xcode-select")
+        .arg("-p")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    if String::from_utf8_lossy(&output.stdout).contains("CommandLineTools") {
+        return Some(DeveloperTools::CommandLineTools);
+    }
+
+    // "Xcode 15.3\nBuild version 15E204a"
+    let version = std::process::Command::new("xcodebuild")
+        .arg("-version")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| {
+            String::from_utf8_lossy(&out.stdout)
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("Xcode "))
+                .and_then(parse_major_minor)
+        });
+    Some(DeveloperTools::Xcode(version))
+}
+
+fn probe_java() -> Option<Option<u32>> {
+    // Prints e.g. `openjdk version "17.0.2" 2022-01-18` to stderr. The macOS
+    // /usr/bin/java stub exits non-zero when no JDK is installed.
+    let output = std::process::Command::new("java")
+        .arg("-version")
+        .output()
+        .ok()
+        .filter(|out| out.status.success())?;
+    let text = String::from_utf8_lossy(&output.stderr);
+    Some(text.split('"').nth(1).and_then(parse_java_major))
+}
+
+/// Check a formula's platform requirements against the host.
+///
+/// Only requirements that matter for prebuilt bottles are checked. macOS
+/// version bounds are skipped on Linux and when the macOS version is unknown;
+/// bottle selection already rejects formulas without a Linux bottle.
+pub fn check_requirements(formula: &Formula, host: &Host) -> Result<(), Error> {
+    for requirement in formula
+        .requirements
+        .iter()
+        .filter(|r| r.applies_to_bottles())
+    {
+        let unsupported = |needs: String| Error::UnsupportedPlatform {
+            name: formula.name.clone(),
+            requirement: needs,
+            host: host.platform.to_string(),
+        };
+        let missing = |needs: String, hint: String| Error::MissingRequirement {
+            name: formula.name.clone(),
+            requirement: needs,
+            hint,
+        };
+
+        match (requirement.name.as_str(), &host.platform) {
+            ("macos", HostPlatform::MacOS(Some(running))) => {
+                if let Some(min) = requirement.version.as_deref().and_then(MacOSVersion::parse)
+                    && running.cmp_release(&min) == Ordering::Less
+                {
+                    return Err(unsupported(format!("macOS {}+", min)));
+                }
+            }
+            ("maximum_macos", HostPlatform::MacOS(Some(running))) => {
+                if let Some(max) = requirement.version.as_deref().and_then(MacOSVersion::parse)
+                    && running.cmp_release(&max) == Ordering::Greater
+                {
+                    return Err(unsupported(format!("macOS {} or older", max)));
+                }
+            }
+            ("linux", HostPlatform::MacOS(_)) => {
+                return Err(unsupported("Linux".to_string()));
+            }
+            ("arch", _) => {
+                let Some(arch) = requirement.version.as_deref().map(normalize_arch) else {
+                    continue;
+                };
+                if arch != host.arch {
+                    let hint = if arch == "x86_64" && host.arch == "arm64" {
+                        format!(
+                            "run zb under Rosetta 2: arch -x86_64 zb install {}",
+                            formula.name
+                        )
+                    } else {
+                        format!("'{}' cannot run on {} machines", formula.name, host.arch)
+                    };
+                    return Err(missing(format!("an {} CPU", arch), hint));
+                }
+            }
+            ("xcode", HostPlatform::MacOS(_)) => {
+                let min = requirement.version.as_deref().and_then(parse_major_minor);
+                match (host.developer_tools(), min) {
+                    (None, None) => {
+                        return Err(missing(
+                            "the Xcode Command Line Tools".to_string(),
+                            "install Xcode Command Line Tools: xcode-select --install".to_string(),
+                        ));
+                    }
+                    (None | Some(DeveloperTools::CommandLineTools), Some((major, minor))) => {
+                        return Err(missing(
+                            format!("Xcode {}.{}+", major, minor),
+                            "install Xcode from the App Store, then run: sudo xcode-select --switch /Applications/Xcode.app".to_string(),
+                        ));
+                    }
+                    (Some(DeveloperTools::Xcode(Some(installed))), Some(min))
+                        if installed < min =>
+                    {
+                        return Err(missing(
+                            format!("Xcode {}.{}+", min.0, min.1),
+                            format!(
+                                "update Xcode from the App Store (found {}.{})",
+                                installed.0, installed.1
+                            ),
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            ("java", _) => {
+                let spec = requirement.version.as_deref().unwrap_or("");
+                let at_least = spec.ends_with('+');
+                let wanted = parse_java_major(spec.trim_end_matches('+'));
+                let satisfied = match (host.java(), wanted) {
+                    (None, _) => false,
+                    (Some(_), None) | (Some(None), Some(_)) => true,
+                    (Some(Some(found)), Some(wanted)) => {
+                        if at_least {
+                            found >= wanted
+                        } else {
+                            found == wanted
+                        }
+                    }
+                };
+                if !satisfied {
+                    let (needs, hint) = match wanted {
+                        Some(wanted) if at_least => (
+                            format!("Java {}+", wanted),
+                            "install a Java runtime: zb install openjdk".to_string(),
+                        ),
+                        Some(wanted) => (
+                            format!("Java {}", wanted),
+                            format!("install a Java runtime: zb install openjdk@{}", wanted),
+                        ),
+                        None => (
+                            "Java".to_string(),
+                            "install a Java runtime: zb install openjdk".to_string(),
+                        ),
+                    };
+                    return Err(missing(needs, hint));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::Requirement;
+
+    fn formula_requiring(name: &str, version: Option<&str>, contexts: &[&str]) -> Formula {
+        Formula {
+            name: "foo".to_string(),
+            requirements: vec![Requirement {
+                name: name.to_string(),
+                version: version.map(str::to_string),
+                contexts: contexts.iter().map(|c| c.to_string()).collect(),
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn macos(version: &str) -> Host {
+        Host::new(HostPlatform::MacOS(MacOSVersion::parse(version)), "arm64")
+            .with_developer_tools(None)
+            .with_java(None)
+    }
+
+    fn linux() -> Host {
+        Host::new(HostPlatform::Linux, "x86_64")
+    }
+
+    #[test]
+    fn parses_numbers_and_names() {
+        assert_eq!(MacOSVersion::parse("13"), Some(MacOSVersion::new(13, 0)));
+        assert_eq!(
+            MacOSVersion::parse("14.6.1\n"),
+            Some(MacOSVersion::new(14, 6))
+        );
+        assert_eq!(
+            MacOSVersion::parse("10.15"),
+            Some(MacOSVersion::new(10, 15))
+        );
+        assert_eq!(
+            MacOSVersion::parse("ventura"),
+            Some(MacOSVersion::new(13, 0))
+        );
+        assert_eq!(
+            MacOSVersion::parse(":big_sur"),
+            Some(MacOSVersion::new(11, 0))
+        );
+        assert_eq!(
+            MacOSVersion::parse("catalina"),
+            Some(MacOSVersion::new(10, 15))
+        );
+        assert_eq!(MacOSVersion::parse("not-a-version"), None);
+    }
+
+    #[test]
+    fn displays_versions() {
+        assert_eq!(MacOSVersion::new(13, 0).to_string(), "13");
+        assert_eq!(MacOSVersion::new(12, 7).to_string(), "12.7");
+        assert_eq!(MacOSVersion::new(10, 15).to_string(), "10.15");
+        assert_eq!(macos("12.7").to_string(), "macOS 12.7 (arm64)");
+    }
+
+    #[test]
+    fn minimum_macos_version_is_enforced() {
+        let formula = formula_requiring("macos", Some("13"), &[]);
+
+        let err = check_requirements(&formula, &macos("12.7")).unwrap_err();
+        assert_eq!(
+            err,
+            Error::UnsupportedPlatform {
+                name: "foo".to_string(),
+                requirement: "macOS 13+".to_string(),
+                host: "macOS 12.7".to_string(),
+            }
+        );
+        assert!(err.to_string().contains("requires macOS 13+"));
+
+        assert!(check_requirements(&formula, &macos("13.0")).is_ok());
+        assert!(check_requirements(&formula, &macos("15.1")).is_ok());
+    }
+
+    #[test]
+    fn minimum_macos_release_name_is_enforced() {
+        let formula = formula_requiring("macos", Some("sonoma"), &[]);
+        assert!(check_requirements(&formula, &macos("13.6")).is_err());
+        assert!(check_requirements(&formula, &macos("14.0")).is_ok());
+    }
+
+    #[test]
+    fn maximum_macos_version_is_enforced() {
+        let formula = formula_requiring("maximum_macos", Some("monterey"), &[]);
+        let err = check_requirements(&formula, &macos("14.2")).unwrap_err();
+        assert!(err.to_string().contains("requires macOS 12 or older"));
+        assert!(check_requirements(&formula, &macos("12.7")).is_ok());
+    }
+
+    #[test]
+    fn build_only_requirements_are_ignored() {
+        let formula = formula_requiring("macos", Some("15"), &["build"]);
+        assert!(check_requirements(&formula, &macos("12.7")).is_ok());
+    }
+
+    #[test]
+    fn macos_requirements_are_skipped_off_macos_or_when_unknown() {
+        let formula = formula_requiring("macos", Some("13"), &[]);
+        assert!(check_requirements(&formula, &linux()).is_ok());
+        assert!(
+            check_requirements(&formula, &Host::new(HostPlatform::MacOS(None), "arm64")).is_ok()
+        );
+    }
+
+    #[test]
+    fn linux_requirement_fails_on_macos() {
+        let formula = formula_requiring("linux", None, &[]);
+        let err = check_requirements(&formula, &macos("14.0")).unwrap_err();
+        assert!(err.to_string().contains("requires Linux"));
+        assert!(check_requirements(&formula, &linux()).is_ok());
+    }
+
+    #[test]
+    fn normalizes_architectures() {
+        assert_eq!(Host::new(HostPlatform::Linux, "aarch64").arch, "arm64");
+        assert_eq!(Host::new(HostPlatform::Linux, "x86_64").arch, "x86_64");
+        assert_eq!(normalize_arch(":intel"), "x86_64");
+    }
+
+    #[test]
+    fn arch_requirement_is_enforced() {
+        let formula = formula_requiring("arch", Some("x86_64"), &[]);
+
+        let err = check_requirements(&formula, &macos("14.0")).unwrap_err();
+        assert_eq!(
+            err,
+            Error::MissingRequirement {
+                name: "foo".to_string(),
+                requirement: "an x86_64 CPU".to_string(),
+                hint: "run zb under Rosetta 2: arch -x86_64 zb install foo".to_string(),
+            }
+        );
+        assert!(check_requirements(&formula, &linux()).is_ok());
+
+        let formula = formula_requiring("arch", Some("arm64"), &[]);
+        let err = check_requirements(&formula, &linux()).unwrap_err();
+        assert!(err.to_string().contains("cannot run on x86_64"));
+    }
+
+    #[test]
+    fn xcode_requirement_suggests_command_line_tools() {
+        let formula = formula_requiring("xcode", None, &[]);
+
+        let err = check_requirements(&formula, &macos("14.0")).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("install Xcode Command Line Tools: xcode-select --install")
+        );
+
+        let host = macos("14.0").with_developer_tools(Some(DeveloperTools::CommandLineTools));
+        assert!(check_requirements(&formula, &host).is_ok());
+
+        // Xcode requirements only apply on macOS
+        assert!(check_requirements(&formula, &linux()).is_ok());
+    }
+
+    #[test]
+    fn xcode_version_requirement_needs_full_xcode() {
+        let formula = formula_requiring("xcode", Some("15.3"), &[]);
+
+        let clt = Host::new(HostPlatform::MacOS(None), "arm64")
+            .with_developer_tools(Some(DeveloperTools::CommandLineTools));
+        let err = check_requirements(&formula, &clt).unwrap_err();
+        assert!(err.to_string().contains("requires Xcode 15.3+"));
+
+        let old = Host::new(HostPlatform::MacOS(None), "arm64")
+            .with_developer_tools(Some(DeveloperTools::Xcode(Some((15, 0)))));
+        let err = check_requirements(&formula, &old).unwrap_err();
+        assert!(err.to_string().contains("found 15.0"));
+
+        let new = Host::new(HostPlatform::MacOS(None), "arm64")
+            .with_developer_tools(Some(DeveloperTools::Xcode(Some((16, 1)))));
+        assert!(check_requirements(&formula, &new).is_ok());
+    }
+
+    #[test]
+    fn build_only_xcode_requirement_is_ignored() {
+        let formula = formula_requiring("xcode", Some("15.3"), &["build"]);
+        assert!(check_requirements(&formula, &macos("14.0")).is_ok());
+    }
+
+    #[test]
+    fn java_requirement_is_enforced() {
+        let formula = formula_requiring("java", Some("1.8+"), &[]);
+
+        let err = check_requirements(&formula, &linux().with_java(None)).unwrap_err();
+        assert!(err.to_string().contains("requires Java 8+"));
+        assert!(err.to_string().contains("zb install openjdk"));
+
+        let host = linux().with_java(Some(Some(17)));
+        assert!(check_requirements(&formula, &host).is_ok());
+
+        let exact = formula_requiring("java", Some("11"), &[]);
+        let err = check_requirements(&exact, &host).unwrap_err();
+        assert!(err.to_string().contains("zb install openjdk@11"));
+
+        // A runtime of unknown version satisfies any requirement
+        let unknown = linux().with_java(Some(None));
+        assert!(check_requirements(&exact, &unknown).is_ok());
+    }
+
+    #[test]
+    fn parses_java_versions() {
+        assert_eq!(parse_java_major("1.8.0_292"), Some(8));
+        assert_eq!(parse_java_major("17.0.2"), Some(17));
+        assert_eq!(parse_java_major("21"), Some(21));
+        assert_eq!(parse_java_major("latest"), None);
+    }
+}
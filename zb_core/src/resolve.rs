@@ -31,6 +31,11 @@ type AdjacencyMap = BTreeMap<String, BTreeSet<String>>;
 /// Uses Kahn's algorithm for topological sorting, which naturally handles cycles
 /// by detecting when not all packages can be processed.
 ///
+/// Formulas may be keyed by an alias as well as by their own name, e.g. both
+/// `postgresql` and `postgresql@17` for the `postgresql@17` formula. Packages
+/// are returned under the formula's own name, so an alias and its target are
+/// only installed once and versioned formulas stay distinct packages.
+///
 /// # Returns
 /// A vector of package names in installation order (dependencies before dependents).
 ///
@@ -91,10 +96,12 @@ fn compute_closure(
     root: &str,
     formulas: &BTreeMap<String, Formula>,
 ) -> Result<BTreeSet<String>, Error> {
+    let root = canonical_name(root, formulas);
     let mut closure = BTreeSet::new();
-    let mut stack = vec![root.to_string()];
+    let mut stack = vec![root.clone()];
 
     while let Some(name) = stack.pop() {
+        let name = canonical_name(&name, formulas);
         if !closure.insert(name.clone()) {
             continue;
         }
@@ -143,9 +150,13 @@ fn build_graph(
         let Some(formula) = formulas.get(name) else {
             continue;
         };
-        // Use effective_dependencies() to include uses_from_macos on Linux
-        let mut deps = formula.effective_dependencies();
-        deps.sort();
+        // Use effective_dependencies() to include uses_from_macos on Linux.
+        // A formula may list the same dependency by name and by alias
+        let deps: BTreeSet<String> = formula
+            .effective_dependencies()
+            .iter()
+            .map(|dep| canonical_name(dep, formulas))
+            .collect();
         for dep in deps {
             if !closure.contains(&dep) {
                 continue;
@@ -160,6 +171,17 @@ fn build_graph(
     Ok((indegree, adjacency))
 }
 
+/// Name `name` is installed under: the formula's own name when `formulas`
+/// also holds it under an alias
+fn canonical_name(name: &str, formulas: &BTreeMap<String, Formula>) -> String {
+    match formulas.get(name) {
+        Some(formula) if formula.name != name && formulas.contains_key(&formula.name) => {
+            formula.name.clone()
+        }
+        _ => name.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -289,6 +311,29 @@ mod tests {
         assert_eq!(order1, vec!["a", "m", "z", "pkg"]);
     }
 
+    #[test]
+    fn aliases_resolve_to_versioned_formula_once() {
+        let mut formulas = BTreeMap::new();
+        let postgres = formula("postgresql@17", &["icu4c"]);
+        formulas.insert(
+            "app".to_string(),
+            formula("app", &["postgresql", "postgresql@17"]),
+        );
+        formulas.insert("postgresql".to_string(), postgres.clone());
+        formulas.insert("postgresql@17".to_string(), postgres);
+        formulas.insert(
+            "postgresql@15".to_string(),
+            formula("postgresql@15", &["icu4c"]),
+        );
+        formulas.insert("icu4c".to_string(), formula("icu4c", &[]));
+
+        let order = resolve_closure("app", &formulas).unwrap();
+        assert_eq!(order, vec!["icu4c", "postgresql@17", "app"]);
+
+        let order = resolve_closure("postgresql", &formulas).unwrap();
+        assert_eq!(order, vec!["icu4c", "postgresql@17"]);
+    }
+
     // =========================================================================
     // Property-based tests with proptest
     // =========================================================================
@@ -1,12 +1,23 @@
 use crate::cache::{ApiCache, CacheEntry, CachedFormula};
-use serde::Deserialize;
+use crate::credentials::Credentials;
+use crate::http_fixtures::HttpMode;
+use crate::index::{FormulaIndex, IndexProvider};
+use crate::mirror::{self, rewrite_bottle_url};
+use crate::ratelimit::RateLimits;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use tokio::sync::OnceCell;
 use zb_core::{Error, Formula};
 
 /// TTL for formula list cache in seconds (5 minutes)
 const FORMULA_LIST_CACHE_TTL_SECS: i64 = 300;
 
+/// How long `zb doctor` waits for the formula API to answer
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// Minimal formula info for search results (faster to deserialize than full Formula)
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormulaInfo {
     pub name: String,
     pub full_name: String,
@@ -19,25 +30,52 @@ pub struct FormulaInfo {
     pub deprecated: bool,
     #[serde(default)]
     pub disabled: bool,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FormulaVersions {
     pub stable: Option<String>,
 }
 
 pub struct ApiClient {
-    base_url: String,
+    provider: IndexProvider,
+    /// Single-file index, loaded on first use
+    index: OnceCell<FormulaIndex>,
+    /// The API's full formula index, once [`prefetch_index`](Self::prefetch_index)
+    /// has loaded it; `None` when loading it failed
+    api_index: OnceCell<Option<FormulaIndex>>,
+    /// Rewrites bottle URLs to a mirror (see `mirror::rewrite_bottle_url`)
+    bottle_domain: Option<String>,
     client: reqwest::Client,
+    /// Basic auth for private formula APIs (see [`crate::credentials`])
+    credentials: Credentials,
     cache: Option<ApiCache>,
+    /// Skip cached responses, but still store fresh ones
+    bypass_cache: bool,
+    /// Hosts answering 429 are not contacted again until they cool down
+    rate_limits: RateLimits,
+    http_mode: HttpMode,
+    /// Answer from the cache only, never contacting the network
+    offline: bool,
 }
 
 impl ApiClient {
     pub fn new() -> Self {
-        Self::with_base_url("https://formulae.brew.sh/api/formula".to_string())
+        Self::with_provider(IndexProvider::default())
+    }
+
+    /// Create a client for the index selected by `ZB_FORMULA_INDEX`
+    pub fn from_env() -> Result<Self, Error> {
+        IndexProvider::from_env().map(Self::with_provider)
     }
 
     pub fn with_base_url(base_url: String) -> Self {
+        Self::with_provider(IndexProvider::Api(base_url))
+    }
+
+    pub fn with_provider(provider: IndexProvider) -> Self {
         // Use HTTP/2 with connection pooling for better multiplexing of parallel requests
         let client = reqwest::Client::builder()
             .user_agent("zerobrew/0.1")
@@ -46,9 +84,17 @@ impl ApiClient {
             .unwrap_or_else(|_| reqwest::Client::new());
 
         Self {
-            base_url,
+            provider,
+            index: OnceCell::new(),
+            api_index: OnceCell::new(),
+            bottle_domain: None,
             client,
+            credentials: Credentials::none(),
             cache: None,
+            bypass_cache: false,
+            rate_limits: RateLimits::new(),
+            http_mode: HttpMode::Live,
+            offline: false,
         }
     }
 
@@ -57,17 +103,284 @@ impl ApiClient {
         self
     }
 
+    pub fn with_http_mode(mut self, mode: HttpMode) -> Self {
+        self.http_mode = mode;
+        self
+    }
+
+    /// Authenticate requests with `credentials`, e.g. those in `~/.netrc` and
+    /// the keychain ([`Credentials::from_env`]); none by default
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    pub fn set_http_mode(&mut self, mode: HttpMode) {
+        self.http_mode = mode;
+    }
+
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Share rate-limit cool-downs with other clients (see `crate::ratelimit`)
+    pub fn set_rate_limits(&mut self, rate_limits: RateLimits) {
+        self.rate_limits = rate_limits;
+    }
+
+    /// Answer from the cache only, however old the cached responses are, and
+    /// fail lookups the cache can't answer instead of contacting the network
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    pub fn provider(&self) -> &IndexProvider {
+        &self.provider
+    }
+
+    pub fn set_provider(&mut self, provider: IndexProvider) {
+        self.provider = provider;
+        self.index = OnceCell::new();
+        self.api_index = OnceCell::new();
+    }
+
+    pub fn set_bottle_domain(&mut self, bottle_domain: Option<String>) {
+        self.bottle_domain = bottle_domain;
+    }
+
+    /// Domain bottle URLs are rewritten to, if any
+    pub fn bottle_domain(&self) -> Option<&str> {
+        self.bottle_domain.as_deref()
+    }
+
+    /// Whether requests may go to the network: not offline, and not
+    /// replaying recorded responses
+    pub fn is_live(&self) -> bool {
+        !self.offline && self.http_mode.is_live()
+    }
+
+    /// Ignore cached responses and fetch everything again. Fresh responses
+    /// still replace the cached ones.
+    pub fn set_bypass_cache(&mut self, bypass: bool) {
+        self.bypass_cache = bypass;
+    }
+
+    /// Drop the cached metadata for one formula so the next lookup fetches
+    /// it in full. Returns whether anything was cached.
+    pub fn invalidate_formula(&self, name: &str) -> bool {
+        match (&self.provider, &self.cache) {
+            (IndexProvider::Api(base_url), Some(cache)) => cache
+                .remove(&format!("{}/{}.json", base_url, name))
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// When a formula's metadata was last fetched or revalidated, as a Unix
+    /// timestamp, if it is in the cache
+    pub fn formula_fetched_at(&self, name: &str) -> Option<i64> {
+        match (&self.provider, &self.cache) {
+            (IndexProvider::Api(base_url), Some(cache)) => cache
+                .get(&format!("{}/{}.json", base_url, name))
+                .map(|entry| entry.cached_at),
+            _ => None,
+        }
+    }
+
+    /// Ask the formula API for the headers of its formula list, to check that
+    /// it answers. Returns the URL asked and the HTTP status it answered with,
+    /// or `None` when formulas don't come from an API or the network is off
+    /// limits.
+    pub async fn probe(&self) -> Option<(String, Result<reqwest::StatusCode, Error>)> {
+        let IndexProvider::Api(base_url) = &self.provider else {
+            return None;
+        };
+        if !self.is_live() {
+            return None;
+        }
+
+        let url = format!("{}.json", base_url);
+        let status = self
+            .credentials
+            .authorize(&url, self.client.head(&url))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .map(|response| response.status())
+            .map_err(|e| Error::NetworkFailure {
+                message: e.to_string(),
+            });
+        Some((url, status))
+    }
+
+    /// Whether cached responses may answer requests
+    fn reads_cache(&self) -> bool {
+        self.http_mode.is_live() && !self.bypass_cache
+    }
+
+    /// Fail a request for `url` that the cache couldn't answer while offline
+    fn check_online(&self, url: &str) -> Result<(), Error> {
+        if self.offline {
+            return Err(Error::NetworkFailure {
+                message: format!("offline: {url} is not in the cache"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Record a successful response body when in record mode
+    fn record(&self, url: &str, body: &str) {
+        if let HttpMode::Record(ref fixtures) = self.http_mode
+            && let Err(e) = fixtures.save(url, body.as_bytes())
+        {
+            eprintln!("    Warning: {}", e);
+        }
+    }
+
+    /// Count a formula lookup against the cache hit rate
+    fn record_lookup(&self, hit: bool) {
+        if let Some(ref cache) = self.cache {
+            let _ = cache.record_lookup(hit);
+        }
+    }
+
     pub async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
+        let formula = match self.get_provider_formula(name).await {
+            Err(Error::MissingFormula { .. }) => self.get_versioned_formula(name).await?,
+            result => result?,
+        };
+        Ok(self.rewrite_bottles(formula))
+    }
+
+    /// Like [`get_formula`](Self::get_formula), but a formula fetched within
+    /// the formula list TTL is answered from the cache without revalidating it.
+    ///
+    /// Meant for bulk checks such as `zb outdated`, which would otherwise send
+    /// a conditional request for every installed formula.
+    pub async fn get_recent_formula(&self, name: &str) -> Result<Formula, Error> {
+        if let IndexProvider::Api(base_url) = &self.provider
+            && let Some(ref cache) = self.cache
+            && self.reads_cache()
+            && let Some(entry) = cache.get(&format!("{base_url}/{name}.json"))
+            && entry.is_fresh(FORMULA_LIST_CACHE_TTL_SECS)
+            && let Ok(formula) = serde_json::from_str::<Formula>(&entry.body)
+        {
+            self.record_lookup(true);
+            return Ok(self.rewrite_bottles(formula));
+        }
+
+        self.get_formula(name).await
+    }
+
+    fn rewrite_bottles(&self, mut formula: Formula) -> Formula {
+        if let Some(ref domain) = self.bottle_domain {
+            for file in formula.bottle.stable.files.values_mut() {
+                file.url = rewrite_bottle_url(&file.url, domain);
+            }
+        }
+        formula
+    }
+
+    async fn get_provider_formula(&self, name: &str) -> Result<Formula, Error> {
+        match &self.provider {
+            IndexProvider::Api(base_url) => match self.api_index.get() {
+                // The full index lists every formula and alias the API serves
+                Some(Some(index)) => index.formula(name),
+                _ => self.get_api_formula(base_url, name).await,
+            },
+            IndexProvider::Mirror(dir) => get_mirror_formula(dir, name),
+            _ => self.load_index().await?.formula(name),
+        }
+    }
+
+    /// Find the formula packaging `foo@X.Y` when no formula has that name:
+    /// `foo` itself when its stable version is X.Y, otherwise one of its
+    /// versioned formulas (e.g. `foo@X`) currently at X.Y.
+    async fn get_versioned_formula(&self, name: &str) -> Result<Formula, Error> {
+        let missing = || Error::MissingFormula {
+            name: name.to_string(),
+        };
+        let Some((base, version)) = name
+            .rsplit_once('@')
+            .filter(|(base, version)| !base.is_empty() && !version.is_empty())
+        else {
+            return Err(missing());
+        };
+
+        let base_formula = match self.get_provider_formula(base).await {
+            Err(Error::MissingFormula { .. }) => return Err(missing()),
+            result => result?,
+        };
+        if same_release(version, &base_formula.versions.stable) {
+            return Ok(base_formula);
+        }
+
+        for candidate in &base_formula.versioned_formulas {
+            let Some((_, series)) = candidate.rsplit_once('@') else {
+                continue;
+            };
+            if candidate == name || !same_release(version, series) {
+                continue;
+            }
+            match self.get_provider_formula(candidate).await {
+                Ok(formula) if same_release(version, &formula.versions.stable) => {
+                    return Ok(formula);
+                }
+                Ok(_) | Err(Error::MissingFormula { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(missing())
+    }
+
+    async fn get_api_formula(&self, base_url: &str, name: &str) -> Result<Formula, Error> {
         // Use a loop to handle alias resolution without recursion
         let mut current_name = name.to_string();
         let mut alias_resolved = false;
 
         loop {
-            let url = format!("{}/{}.json", self.base_url, current_name);
+            let url = format!("{}/{}.json", base_url, current_name);
+
+            if let HttpMode::Replay(ref fixtures) = self.http_mode {
+                let Some(body) = fixtures.load(&url)? else {
+                    if !alias_resolved && let Some(target) = self.resolve_alias(&current_name).await
+                    {
+                        current_name = target;
+                        alias_resolved = true;
+                        continue;
+                    }
+                    return Err(Error::MissingFormula {
+                        name: name.to_string(),
+                    });
+                };
+                return serde_json::from_slice(&body).map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to parse formula JSON: {e}"),
+                });
+            }
 
-            let cached_entry = self.cache.as_ref().and_then(|c| c.get(&url));
+            // Skip conditional requests while recording so the full body is captured
+            let cached_entry = if self.reads_cache() {
+                self.cache.as_ref().and_then(|c| c.get(&url))
+            } else {
+                None
+            };
 
-            let mut request = self.client.get(&url);
+            if self.offline
+                && let Some(entry) = cached_entry
+            {
+                self.record_lookup(true);
+                return serde_json::from_str(&entry.body).map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to parse cached formula JSON: {e}"),
+                });
+            }
+            self.check_online(&url)?;
+            self.rate_limits.check(&url)?;
+            let mut request = self.get(&url);
 
             if let Some(ref entry) = cached_entry {
                 if let Some(ref etag) = entry.etag {
@@ -81,10 +394,16 @@ impl ApiClient {
             let response = request.send().await.map_err(|e| Error::NetworkFailure {
                 message: e.to_string(),
             })?;
+            self.rate_limits.check_response(&url, &response)?;
 
             if response.status() == reqwest::StatusCode::NOT_MODIFIED
                 && let Some(entry) = cached_entry
             {
+                self.record_lookup(true);
+                // Restart the entry's TTL now that it has been revalidated
+                if let Some(ref cache) = self.cache {
+                    let _ = cache.put(&url, &entry);
+                }
                 let formula: Formula =
                     serde_json::from_str(&entry.body).map_err(|e| Error::NetworkFailure {
                         message: format!("failed to parse cached formula JSON: {e}"),
@@ -125,7 +444,9 @@ impl ApiClient {
             let body = response.text().await.map_err(|e| Error::NetworkFailure {
                 message: format!("failed to read response body: {e}"),
             })?;
+            self.record(&url, &body);
 
+            self.record_lookup(false);
             if let Some(ref cache) = self.cache {
                 let entry = CacheEntry {
                     etag,
@@ -152,38 +473,67 @@ impl ApiClient {
     /// Uses SQLite cache for parsed formulas to avoid JSON parsing overhead.
     /// Cache freshness is checked with a 5-minute TTL before making network requests.
     pub async fn get_all_formulas(&self) -> Result<Vec<FormulaInfo>, Error> {
-        // Phase 2: Try SQLite formula cache first
-        if let Some(ref cache) = self.cache {
-            // Check if formula cache is fresh (< 5 minutes old)
-            if cache.is_formula_cache_fresh(FORMULA_LIST_CACHE_TTL_SECS) {
-                if let Ok(cached_formulas) = cache.get_formulas() {
-                    if !cached_formulas.is_empty() {
-                        return Ok(cached_formulas
-                            .into_iter()
-                            .map(|f| FormulaInfo {
-                                name: f.name,
-                                full_name: f.full_name,
-                                desc: f.description,
-                                homepage: None,
-                                versions: FormulaVersions { stable: f.version },
-                                aliases: f.aliases,
-                                deprecated: f.deprecated,
-                                disabled: f.disabled,
-                            })
-                            .collect());
-                    }
-                }
-            }
+        self.formula_list(false).await
+    }
+
+    /// Fetch the formula list again even if the cached copy is fresh. The
+    /// request is still conditional, so an unchanged list isn't downloaded.
+    /// The index prefetched for dependency resolution is the same document,
+    /// so its cached copy is dropped too.
+    pub async fn refresh_formula_list(&self) -> Result<Vec<FormulaInfo>, Error> {
+        let formulas = self.formula_list(true).await?;
+        if let (IndexProvider::Api(base_url), Some(cache)) = (&self.provider, &self.cache) {
+            let _ = cache.remove(&format!("{}.json", base_url));
         }
+        Ok(formulas)
+    }
+
+    /// The formula list last cached, however old
+    pub fn cached_formula_list(&self) -> Option<Vec<FormulaInfo>> {
+        self.cache.as_ref().and_then(cached_formula_infos)
+    }
+
+    async fn formula_list(&self, revalidate: bool) -> Result<Vec<FormulaInfo>, Error> {
+        let base_url = match &self.provider {
+            IndexProvider::Api(base_url) => base_url,
+            IndexProvider::Mirror(dir) => return read_mirror_list(dir),
+            _ => return Ok(self.load_index().await?.infos()),
+        };
 
         // The base_url is like "https://formulae.brew.sh/api/formula"
         // We need "https://formulae.brew.sh/api/formula.json"
-        let url = format!("{}.json", self.base_url);
+        let url = format!("{}.json", base_url);
+
+        if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            let body = fixtures.load(&url)?.ok_or_else(|| fixtures.missing(&url))?;
+            return serde_json::from_slice(&body).map_err(|e| Error::NetworkFailure {
+                message: format!("failed to parse formula list: {e}"),
+            });
+        }
+
+        // Phase 2: Try SQLite formula cache first (skipped while recording)
+        if let Some(ref cache) = self.cache
+            && self.reads_cache()
+        {
+            // Check if formula cache is fresh (< 5 minutes old)
+            if (self.offline
+                || !revalidate && cache.is_formula_cache_fresh(FORMULA_LIST_CACHE_TTL_SECS))
+                && let Some(formulas) = cached_formula_infos(cache)
+            {
+                return Ok(formulas);
+            }
+        }
 
         // Get cache metadata for conditional requests
-        let cache_meta = self.cache.as_ref().and_then(|c| c.get_formula_cache_meta());
+        let cache_meta = if self.reads_cache() {
+            self.cache.as_ref().and_then(|c| c.get_formula_cache_meta())
+        } else {
+            None
+        };
 
-        let mut request = self.client.get(&url);
+        self.check_online(&url)?;
+        self.rate_limits.check(&url)?;
+        let mut request = self.get(&url);
 
         if let Some(ref meta) = cache_meta {
             if let Some(ref etag) = meta.etag {
@@ -197,28 +547,14 @@ impl ApiClient {
         let response = request.send().await.map_err(|e| Error::NetworkFailure {
             message: e.to_string(),
         })?;
+        self.rate_limits.check_response(&url, &response)?;
 
         // 304 Not Modified - use cached formulas from SQLite
-        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
-            if let Some(ref cache) = self.cache {
-                if let Ok(cached_formulas) = cache.get_formulas() {
-                    if !cached_formulas.is_empty() {
-                        return Ok(cached_formulas
-                            .into_iter()
-                            .map(|f| FormulaInfo {
-                                name: f.name,
-                                full_name: f.full_name,
-                                desc: f.description,
-                                homepage: None,
-                                versions: FormulaVersions { stable: f.version },
-                                aliases: f.aliases,
-                                deprecated: f.deprecated,
-                                disabled: f.disabled,
-                            })
-                            .collect());
-                    }
-                }
-            }
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED
+            && let Some(ref cache) = self.cache
+            && let Some(formulas) = cached_formula_infos(cache)
+        {
+            return Ok(formulas);
         }
 
         if !response.status().is_success() {
@@ -242,6 +578,7 @@ impl ApiClient {
         let body = response.text().await.map_err(|e| Error::NetworkFailure {
             message: format!("failed to read response body: {e}"),
         })?;
+        self.record(&url, &body);
 
         let formulas: Vec<FormulaInfo> =
             serde_json::from_str(&body).map_err(|e| Error::NetworkFailure {
@@ -260,6 +597,7 @@ impl ApiClient {
                     aliases: f.aliases.clone(),
                     deprecated: f.deprecated,
                     disabled: f.disabled,
+                    dependencies: f.dependencies.clone(),
                 })
                 .collect();
 
@@ -273,6 +611,210 @@ impl ApiClient {
         Ok(formulas)
     }
 
+    /// Formulas in the whole catalog that depend on `name`, sorted by name
+    ///
+    /// With the API provider this is answered from the reverse-dependency
+    /// index kept next to the formula list cache, which is rebuilt whenever
+    /// the list is refreshed. Other providers scan their formula list.
+    pub async fn get_catalog_dependents(
+        &self,
+        name: &str,
+        recursive: bool,
+    ) -> Result<Vec<String>, Error> {
+        let indexed = match (&self.provider, &self.cache) {
+            (IndexProvider::Api(_), Some(cache)) if self.http_mode.is_live() => Some(cache),
+            _ => None,
+        };
+
+        if let Some(cache) = indexed
+            && self.reads_cache()
+            && cache.is_formula_cache_fresh(FORMULA_LIST_CACHE_TTL_SECS)
+            && let Ok(dependents) = cache.get_dependents(name, recursive)
+        {
+            return Ok(dependents);
+        }
+
+        // Refreshing the list rebuilds the persisted index
+        let formulas = self.get_all_formulas().await?;
+        if let Some(cache) = indexed
+            && let Ok(dependents) = cache.get_dependents(name, recursive)
+        {
+            return Ok(dependents);
+        }
+
+        Ok(catalog_dependents(&formulas, name, recursive))
+    }
+
+    /// Download the API's full formula index, so that later lookups are
+    /// answered from it instead of with one request per formula.
+    ///
+    /// Meant for resolving large dependency trees. The index is cached like
+    /// any other response: within the formula list TTL it is used as is, after
+    /// that it is revalidated with a conditional request, so an unchanged index
+    /// is only downloaded once. Returns whether the index is in use; when it
+    /// can't be loaded, lookups keep using the per-formula endpoint.
+    pub async fn prefetch_index(&self) -> bool {
+        let IndexProvider::Api(base_url) = &self.provider else {
+            return false;
+        };
+        self.api_index
+            .get_or_init(|| async { self.fetch_api_index(base_url).await.ok() })
+            .await
+            .is_some()
+    }
+
+    async fn fetch_api_index(&self, base_url: &str) -> Result<FormulaIndex, Error> {
+        let url = format!("{}.json", base_url);
+
+        if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            let body = fixtures.load(&url)?.ok_or_else(|| fixtures.missing(&url))?;
+            return FormulaIndex::parse(&body, &url);
+        }
+
+        let cached_entry = if self.reads_cache() {
+            self.cache.as_ref().and_then(|c| c.get(&url))
+        } else {
+            None
+        };
+        if let Some(ref entry) = cached_entry
+            && (self.offline || entry.is_fresh(FORMULA_LIST_CACHE_TTL_SECS))
+        {
+            return FormulaIndex::parse(entry.body.as_bytes(), &url);
+        }
+
+        self.check_online(&url)?;
+        self.rate_limits.check(&url)?;
+        let mut request = self.get(&url);
+
+        if let Some(ref entry) = cached_entry {
+            if let Some(ref etag) = entry.etag {
+                request = request.header("If-None-Match", etag.as_str());
+            }
+            if let Some(ref last_modified) = entry.last_modified {
+                request = request.header("If-Modified-Since", last_modified.as_str());
+            }
+        }
+
+        let response = request.send().await.map_err(|e| Error::NetworkFailure {
+            message: e.to_string(),
+        })?;
+        self.rate_limits.check_response(&url, &response)?;
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED
+            && let Some(entry) = cached_entry
+        {
+            // Restart the entry's TTL now that it has been revalidated
+            if let Some(ref cache) = self.cache {
+                let _ = cache.put(&url, &entry);
+            }
+            return FormulaIndex::parse(entry.body.as_bytes(), &url);
+        }
+
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("HTTP {} fetching formula index {url}", response.status()),
+            });
+        }
+
+        let etag = response
+            .headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let last_modified = response
+            .headers()
+            .get("last-modified")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string());
+
+        let body = response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read response body: {e}"),
+        })?;
+        self.record(&url, &body);
+
+        // Only a valid index is worth caching
+        let index = FormulaIndex::parse(body.as_bytes(), &url)?;
+        if let Some(ref cache) = self.cache {
+            let entry = CacheEntry {
+                etag,
+                last_modified,
+                body,
+                cached_at: 0, // Will be overwritten by put()
+            };
+            if let Err(e) = cache.put(&url, &entry) {
+                eprintln!("    Warning: failed to cache formula index: {}", e);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Load and validate the single-file index, once per client
+    async fn load_index(&self) -> Result<&FormulaIndex, Error> {
+        self.index
+            .get_or_try_init(|| async {
+                let body = match &self.provider {
+                    IndexProvider::File(path) => {
+                        std::fs::read(path).map_err(|e| Error::NetworkFailure {
+                            message: format!(
+                                "failed to read formula index {}: {e}",
+                                path.display()
+                            ),
+                        })?
+                    }
+                    IndexProvider::Remote(url) => self.fetch_index(url).await?,
+                    IndexProvider::Api(url) => {
+                        return Err(Error::NetworkFailure {
+                            message: format!("{url} is an API base URL, not an index file"),
+                        });
+                    }
+                    IndexProvider::Mirror(dir) => {
+                        return Err(Error::NetworkFailure {
+                            message: format!("{} is a mirror, not an index file", dir.display()),
+                        });
+                    }
+                };
+                FormulaIndex::parse(&body, &self.provider.to_string())
+            })
+            .await
+    }
+
+    /// A GET request for `url`, with basic auth if its host has a login
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.credentials.authorize(url, self.client.get(url))
+    }
+
+    async fn fetch_index(&self, url: &str) -> Result<Vec<u8>, Error> {
+        if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            return fixtures.load(url)?.ok_or_else(|| fixtures.missing(url));
+        }
+
+        self.check_online(url)?;
+        self.rate_limits.check(url)?;
+        let response = self
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: e.to_string(),
+            })?;
+        self.rate_limits.check_response(url, &response)?;
+
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("HTTP {} fetching formula index {url}", response.status()),
+            });
+        }
+
+        let body = response.text().await.map_err(|e| Error::NetworkFailure {
+            message: format!("failed to read response body: {e}"),
+        })?;
+        self.record(url, &body);
+
+        Ok(body.into_bytes())
+    }
+
     /// Check if a formula name is an alias and return the target formula name
     async fn resolve_alias(&self, name: &str) -> Option<String> {
         let alias_url = format!(
@@ -280,13 +822,24 @@ impl ApiClient {
             name
         );
 
-        let response = self.client.get(&alias_url).send().await.ok()?;
+        let body = if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            String::from_utf8(fixtures.load(&alias_url).ok()??).ok()?
+        } else {
+            self.check_online(&alias_url).ok()?;
+            self.rate_limits.check(&alias_url).ok()?;
+            let response = self.get(&alias_url).send().await.ok()?;
+            self.rate_limits
+                .check_response(&alias_url, &response)
+                .ok()?;
 
-        if !response.status().is_success() {
-            return None;
-        }
+            if !response.status().is_success() {
+                return None;
+            }
 
-        let body = response.text().await.ok()?;
+            let body = response.text().await.ok()?;
+            self.record(&alias_url, &body);
+            body
+        };
         // Alias file contains a relative path like "../Formula/p/python@3.14.rb"
         // Extract the formula name from the path
         let formula_name = body
@@ -336,6 +889,21 @@ impl ApiClient {
             (count, size)
         })
     }
+
+    /// Formula lookups answered from the cache versus fetched in full,
+    /// as (hits, misses)
+    pub fn lookup_stats(&self) -> Option<(u64, u64)> {
+        self.cache
+            .as_ref()
+            .map(|c| c.lookup_stats().unwrap_or((0, 0)))
+    }
+
+    /// Forget recorded lookup hits and misses
+    pub fn clear_lookup_stats(&self) {
+        if let Some(ref cache) = self.cache {
+            let _ = cache.clear_lookup_stats();
+        }
+    }
 }
 
 impl Default for ApiClient {
@@ -344,6 +912,97 @@ impl Default for ApiClient {
     }
 }
 
+/// The formula list cached in SQLite, if there is one
+fn cached_formula_infos(cache: &ApiCache) -> Option<Vec<FormulaInfo>> {
+    let cached_formulas = cache.get_formulas().ok()?;
+    if cached_formulas.is_empty() {
+        return None;
+    }
+    Some(
+        cached_formulas
+            .into_iter()
+            .map(|f| FormulaInfo {
+                name: f.name,
+                full_name: f.full_name,
+                desc: f.description,
+                homepage: None,
+                versions: FormulaVersions { stable: f.version },
+                aliases: f.aliases,
+                deprecated: f.deprecated,
+                disabled: f.disabled,
+                dependencies: f.dependencies,
+            })
+            .collect(),
+    )
+}
+
+/// Read a formula from a local mirror, resolving aliases through the
+/// mirror's formula list
+/// Whether two versions agree on every component the shorter one has, so
+/// `15` matches `15.8` but `15.4` doesn't
+fn same_release(a: &str, b: &str) -> bool {
+    !a.is_empty() && !b.is_empty() && a.split('.').zip(b.split('.')).all(|(x, y)| x == y)
+}
+
+fn get_mirror_formula(dir: &Path, name: &str) -> Result<Formula, Error> {
+    let mut path = mirror::formula_path(dir, name);
+    if !path.exists() {
+        // Aliases only have their own file when they were mirrored by alias
+        let target = read_mirror_list(dir)?
+            .into_iter()
+            .find(|info| info.full_name == name || info.aliases.iter().any(|a| a == name))
+            .ok_or_else(|| Error::MissingFormula {
+                name: name.to_string(),
+            })?;
+        path = mirror::formula_path(dir, &target.name);
+    }
+
+    let body = std::fs::read(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::MissingFormula {
+            name: name.to_string(),
+        },
+        _ => Error::NetworkFailure {
+            message: format!("failed to read {}: {e}", path.display()),
+        },
+    })?;
+    serde_json::from_slice(&body).map_err(|e| Error::NetworkFailure {
+        message: format!("failed to parse {}: {e}", path.display()),
+    })
+}
+
+/// Formulas in `formulas` that depend on `name`, sorted by name
+fn catalog_dependents(formulas: &[FormulaInfo], name: &str, recursive: bool) -> Vec<String> {
+    let mut reverse: HashMap<&str, Vec<&str>> = HashMap::new();
+    for formula in formulas {
+        for dep in &formula.dependencies {
+            reverse.entry(dep.as_str()).or_default().push(&formula.name);
+        }
+    }
+
+    let mut found = BTreeSet::new();
+    let mut queue = vec![name];
+    while let Some(current) = queue.pop() {
+        for &dependent in reverse.get(current).into_iter().flatten() {
+            if dependent != name && found.insert(dependent) && recursive {
+                queue.push(dependent);
+            }
+        }
+    }
+
+    found.into_iter().map(str::to_string).collect()
+}
+
+/// Read the formula list of a local mirror
+fn read_mirror_list(dir: &Path) -> Result<Vec<FormulaInfo>, Error> {
+    let path = mirror::formula_list_path(dir);
+    let body = std::fs::read(&path).map_err(|e| Error::NetworkFailure {
+        message: format!("failed to read mirror formula list {}: {e}", path.display()),
+    })?;
+    serde_json::from_slice(&body).map_err(|e| Error::NetworkFailure {
+        message: format!("failed to parse {}: {e}", path.display()),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -352,22 +1011,82 @@ mod tests {
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
-    async fn fetches_formula_from_mock_server() {
-        let mock_server = MockServer::start().await;
+    async fn fetches_formula_from_mock_server() {
+        let mock_server = MockServer::start().await;
+
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri());
+        let formula = client.get_formula("foo").await.unwrap();
+
+        assert_eq!(formula.name, "foo");
+        assert_eq!(formula.versions.stable, "1.2.3");
+    }
+
+    #[tokio::test]
+    async fn set_credentials_authenticates_formula_fetches() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        // Basic auth for `alice:s3cret`
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .and(header("authorization", "Basic YWxpY2U6czNjcmV0"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let mut client = ApiClient::with_base_url(mock_server.uri());
+        assert!(client.get_formula("foo").await.is_err());
+
+        client.set_credentials(
+            Credentials::none().with_netrc(crate::credentials::parse_netrc(
+                "machine 127.0.0.1 login alice password s3cret",
+            )),
+        );
+        assert_eq!(client.get_formula("foo").await.unwrap().name, "foo");
+    }
+
+    #[tokio::test]
+    async fn recorded_formula_replays_without_network() {
+        use crate::http_fixtures::HttpFixtures;
 
+        let mock_server = MockServer::start().await;
         let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
 
         Mock::given(method("GET"))
             .and(path("/foo.json"))
             .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
             .mount(&mock_server)
             .await;
 
-        let client = ApiClient::with_base_url(mock_server.uri());
-        let formula = client.get_formula("foo").await.unwrap();
+        let tmp = tempfile::TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path());
+
+        let recorder = ApiClient::with_base_url(mock_server.uri())
+            .with_http_mode(HttpMode::Record(fixtures.clone()));
+        recorder.get_formula("foo").await.unwrap();
 
+        let replayer =
+            ApiClient::with_base_url(mock_server.uri()).with_http_mode(HttpMode::Replay(fixtures));
+        let formula = replayer.get_formula("foo").await.unwrap();
         assert_eq!(formula.name, "foo");
-        assert_eq!(formula.versions.stable, "1.2.3");
+
+        let err = replayer.get_formula("bar").await.unwrap_err();
+        assert!(matches!(err, Error::MissingFormula { name } if name == "bar"));
     }
 
     #[tokio::test]
@@ -389,6 +1108,44 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn versioned_request_resolves_to_formula_at_that_version() {
+        let mock_server = MockServer::start().await;
+        for (name, version, versioned) in [
+            ("node", "23.1.0", r#"["node@22"]"#),
+            ("node@22", "22.11.0", "[]"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"name": "{name}", "versions": {{"stable": "{version}"}}, "versioned_formulas": {versioned}}}"#
+                )))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let client = ApiClient::with_base_url(mock_server.uri());
+        assert_eq!(client.get_formula("node@23").await.unwrap().name, "node");
+        assert_eq!(client.get_formula("node@22").await.unwrap().name, "node@22");
+        assert_eq!(
+            client.get_formula("node@22.11").await.unwrap().name,
+            "node@22"
+        );
+        for missing in ["node@22.10", "node@21", "nope@23"] {
+            let err = client.get_formula(missing).await.unwrap_err();
+            assert!(matches!(err, Error::MissingFormula { name } if name == missing));
+        }
+    }
+
+    #[test]
+    fn same_release_compares_shared_components() {
+        assert!(same_release("15", "15.8"));
+        assert!(same_release("3.12.8", "3.12"));
+        assert!(!same_release("15.4", "15.8"));
+        assert!(!same_release("3.1", "3.12"));
+        assert!(!same_release("", "1.0"));
+    }
+
     #[tokio::test]
     async fn first_request_stores_etag() {
         let mock_server = MockServer::start().await;
@@ -418,6 +1175,85 @@ mod tests {
         assert_eq!(cached.etag, Some("\"abc123\"".to_string()));
     }
 
+    #[tokio::test]
+    async fn bypass_cache_skips_conditional_request_and_refreshes_entry() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        let cache = ApiCache::in_memory().unwrap();
+        let url = format!("{}/foo.json", mock_server.uri());
+        cache
+            .put(
+                &url,
+                &CacheEntry {
+                    etag: Some("\"stale\"".to_string()),
+                    last_modified: None,
+                    body: "not json".to_string(),
+                    cached_at: 0,
+                },
+            )
+            .unwrap();
+
+        // A conditional request would get the poisoned body back
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .and(header("If-None-Match", "\"stale\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(fixture)
+                    .insert_header("etag", "\"fresh\""),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut client = ApiClient::with_base_url(mock_server.uri()).with_cache(cache);
+        client.set_bypass_cache(true);
+
+        let formula = client.get_formula("foo").await.unwrap();
+        assert_eq!(formula.name, "foo");
+        let cached = client.cache.as_ref().unwrap().get(&url).unwrap();
+        assert_eq!(cached.etag, Some("\"fresh\"".to_string()));
+    }
+
+    #[test]
+    fn invalidate_formula_removes_cached_entry() {
+        let cache = ApiCache::in_memory().unwrap();
+        let client = ApiClient::with_base_url("https://example.com/api/formula".to_string())
+            .with_cache(cache);
+        let entry = CacheEntry {
+            etag: None,
+            last_modified: None,
+            body: "{}".to_string(),
+            cached_at: 0,
+        };
+        let cache = client.cache.as_ref().unwrap();
+        cache
+            .put("https://example.com/api/formula/foo.json", &entry)
+            .unwrap();
+        cache
+            .put("https://example.com/api/formula/bar.json", &entry)
+            .unwrap();
+
+        assert!(client.invalidate_formula("foo"));
+        assert!(!client.invalidate_formula("foo"));
+        assert!(
+            cache
+                .get("https://example.com/api/formula/foo.json")
+                .is_none()
+        );
+        assert!(
+            cache
+                .get("https://example.com/api/formula/bar.json")
+                .is_some()
+        );
+    }
+
     #[tokio::test]
     async fn second_request_sends_if_none_match() {
         let mock_server = MockServer::start().await;
@@ -493,6 +1329,61 @@ mod tests {
         let formula = client.get_formula("foo").await.unwrap();
         assert_eq!(formula.name, "foo");
         assert_eq!(formula.versions.stable, "1.2.3");
+
+        // One full fetch, then one answered by the cache
+        assert_eq!(client.lookup_stats(), Some((1, 1)));
+        client.clear_lookup_stats();
+        assert_eq!(client.lookup_stats(), Some((0, 0)));
+    }
+
+    #[tokio::test]
+    async fn recent_formula_is_answered_from_cache() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(fixture)
+                    .insert_header("etag", "\"abc123\""),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let cache = ApiCache::in_memory().unwrap();
+        let client = ApiClient::with_base_url(mock_server.uri()).with_cache(cache);
+
+        // Fetched once, then answered from the cache without a request
+        client.get_recent_formula("foo").await.unwrap();
+        let formula = client.get_recent_formula("foo").await.unwrap();
+        assert_eq!(formula.versions.stable, "1.2.3");
+        assert_eq!(client.lookup_stats(), Some((1, 1)));
+    }
+
+    #[tokio::test]
+    async fn offline_lookups_are_answered_from_cache_only() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let cache = ApiCache::in_memory().unwrap();
+        let mut client = ApiClient::with_base_url(mock_server.uri()).with_cache(cache);
+        client.get_formula("foo").await.unwrap();
+
+        // Cached formulas are served without revalidating, others fail
+        client.set_offline(true);
+        let formula = client.get_formula("foo").await.unwrap();
+        assert_eq!(formula.versions.stable, "1.2.3");
+        let err = client.get_formula("bar").await.unwrap_err().to_string();
+        assert!(err.contains("offline"), "{err}");
     }
 
     // ========================================================================
@@ -681,11 +1572,32 @@ mod tests {
         let err = client.get_formula("ratelimited").await.unwrap_err();
 
         match err {
-            Error::NetworkFailure { message } => {
-                assert!(message.contains("429"));
+            Error::RateLimited { retry_after, .. } => {
+                assert_eq!(retry_after, Some(60));
             }
-            _ => panic!("expected NetworkFailure, got {:?}", err),
+            _ => panic!("expected RateLimited, got {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_requests_while_host_cools_down() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(format!("{}/api/formula", mock_server.uri()));
+        for name in ["foo", "foo", "bar"] {
+            let err = client.get_formula(name).await.unwrap_err();
+            assert!(matches!(err, Error::RateLimited { .. }));
         }
+        assert!(matches!(
+            client.get_all_formulas().await,
+            Err(Error::RateLimited { .. })
+        ));
     }
 
     #[tokio::test]
@@ -851,6 +1763,56 @@ mod tests {
         assert_eq!(formulas[0].name, "cached");
     }
 
+    const CATALOG_JSON: &str = r#"[
+        {"name": "zlib", "full_name": "zlib", "versions": {"stable": "1.3"}},
+        {"name": "openssl@3", "full_name": "openssl@3", "versions": {"stable": "3.3"}},
+        {"name": "curl", "full_name": "curl", "versions": {"stable": "8.9"},
+         "dependencies": ["openssl@3", "zlib"]},
+        {"name": "git", "full_name": "git", "versions": {"stable": "2.46"},
+         "dependencies": ["curl"]}
+    ]"#;
+
+    #[tokio::test]
+    async fn catalog_dependents_answered_from_persisted_index() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(CATALOG_JSON))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let cache = ApiCache::in_memory().unwrap();
+        let base_url = format!("{}/api/formula", mock_server.uri());
+        let client = ApiClient::with_base_url(base_url).with_cache(cache);
+
+        assert_eq!(
+            client.get_catalog_dependents("zlib", false).await.unwrap(),
+            vec!["curl"]
+        );
+        // Served from the index built by the first refresh
+        assert_eq!(
+            client.get_catalog_dependents("zlib", true).await.unwrap(),
+            vec!["curl", "git"]
+        );
+    }
+
+    #[test]
+    fn catalog_dependents_without_cache() {
+        let formulas: Vec<FormulaInfo> = serde_json::from_str(CATALOG_JSON).unwrap();
+
+        assert_eq!(
+            catalog_dependents(&formulas, "openssl@3", false),
+            vec!["curl"]
+        );
+        assert_eq!(
+            catalog_dependents(&formulas, "openssl@3", true),
+            vec!["curl", "git"]
+        );
+        assert!(catalog_dependents(&formulas, "git", true).is_empty());
+    }
+
     #[tokio::test]
     async fn get_all_formulas_returns_error_on_500() {
         let mock_server = MockServer::start().await;
@@ -902,6 +1864,105 @@ mod tests {
     // Note: resolve_alias uses a hardcoded GitHub URL, so we can't fully mock it.
     // These tests verify the behavior when alias resolution fails or is not attempted.
 
+    #[tokio::test]
+    async fn prefetched_index_answers_lookups_and_is_cached() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(format!("[{fixture}]"))
+                    .insert_header("etag", "\"index1\""),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let base_url = format!("{}/api/formula", mock_server.uri());
+        let cache = ApiCache::in_memory().unwrap();
+        let client = ApiClient::with_base_url(base_url.clone()).with_cache(cache);
+        assert!(client.prefetch_index().await);
+        assert_eq!(client.get_formula("foo").await.unwrap().name, "foo");
+        assert!(matches!(
+            client.get_formula("nope").await,
+            Err(Error::MissingFormula { .. })
+        ));
+
+        // A fresh cached index is used without asking again
+        assert!(client.prefetch_index().await);
+        let cache = client.cache.unwrap();
+        let client = ApiClient::with_base_url(base_url).with_cache(cache);
+        assert!(client.prefetch_index().await);
+        assert_eq!(client.get_formula("foo").await.unwrap().name, "foo");
+    }
+
+    #[tokio::test]
+    async fn stale_index_is_revalidated() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+        let url = format!("{}/api/formula.json", mock_server.uri());
+
+        // An index cached long ago
+        let tmp = tempfile::TempDir::new().unwrap();
+        let db_path = tmp.path().join("cache.sqlite3");
+        let cache = ApiCache::open(&db_path).unwrap();
+        rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .execute(
+                "INSERT INTO api_cache (url, etag, last_modified, body, cached_at)
+                 VALUES (?1, '\"index1\"', NULL, ?2, 0)",
+                rusqlite::params![url, format!("[{fixture}]")],
+            )
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .and(header("If-None-Match", "\"index1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let base_url = format!("{}/api/formula", mock_server.uri());
+        let client = ApiClient::with_base_url(base_url).with_cache(cache);
+        assert!(client.prefetch_index().await);
+        assert_eq!(client.get_formula("foo").await.unwrap().name, "foo");
+    }
+
+    #[tokio::test]
+    async fn lookups_fall_back_when_the_index_is_unavailable() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let base_url = format!("{}/api/formula", mock_server.uri());
+        let client = ApiClient::with_base_url(base_url);
+        assert!(!client.prefetch_index().await);
+        // A failed download isn't retried
+        assert!(!client.prefetch_index().await);
+        assert_eq!(client.get_formula("foo").await.unwrap().name, "foo");
+    }
+
     #[tokio::test]
     async fn formula_404_without_alias_returns_missing_formula() {
         let mock_server = MockServer::start().await;
@@ -1261,4 +2322,85 @@ mod tests {
         assert_eq!(formulas.len(), 1);
         assert_eq!(formulas[0].name, "cached");
     }
+
+    // ========================================================================
+    // Single-file index providers
+    // ========================================================================
+
+    const INDEX_JSON: &str = r#"[
+        {
+            "name": "jq",
+            "full_name": "jq",
+            "desc": "JSON processor",
+            "versions": { "stable": "1.7.1" },
+            "aliases": ["jq-cli"]
+        }
+    ]"#;
+
+    #[tokio::test]
+    async fn file_index_serves_formulas_and_search_list() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let index_path = tmp.path().join("index.json");
+        std::fs::write(&index_path, INDEX_JSON).unwrap();
+
+        let client = ApiClient::with_provider(IndexProvider::File(index_path));
+
+        assert_eq!(client.get_formula("jq").await.unwrap().name, "jq");
+        assert_eq!(client.get_formula("jq-cli").await.unwrap().name, "jq");
+        assert!(matches!(
+            client.get_formula("missing").await,
+            Err(Error::MissingFormula { .. })
+        ));
+
+        let formulas = client.get_all_formulas().await.unwrap();
+        assert_eq!(formulas.len(), 1);
+        assert_eq!(formulas[0].desc.as_deref(), Some("JSON processor"));
+    }
+
+    #[tokio::test]
+    async fn file_index_reports_invalid_format() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let index_path = tmp.path().join("index.json");
+        std::fs::write(&index_path, r#"{"formulas": []}"#).unwrap();
+
+        let client = ApiClient::with_provider(IndexProvider::File(index_path));
+        let err = client.get_formula("jq").await.unwrap_err();
+
+        assert!(err.to_string().contains("invalid formula index"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn remote_index_is_fetched_once() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/catalog/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(INDEX_JSON))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/catalog/formula.json", mock_server.uri());
+        let client = ApiClient::with_provider(IndexProvider::parse(&url).unwrap());
+
+        assert_eq!(client.get_formula("jq").await.unwrap().name, "jq");
+        assert_eq!(client.get_all_formulas().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn remote_index_http_error() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/catalog/formula.json"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&mock_server)
+            .await;
+
+        let url = format!("{}/catalog/formula.json", mock_server.uri());
+        let client = ApiClient::with_provider(IndexProvider::Remote(url));
+
+        let err = client.get_all_formulas().await.unwrap_err();
+        assert!(err.to_string().contains("403"), "{err}");
+    }
 }
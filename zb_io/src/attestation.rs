@@ -0,0 +1,239 @@
+//! Build provenance attestations for bottles.
+//!
+//! homebrew/core publishes a GitHub build provenance attestation, a sigstore
+//! bundle signed by its CI workflow, for every bottle it builds. zerobrew has
+//! no sigstore implementation of its own, so like Homebrew it has the GitHub
+//! CLI verify them: `gh attestation verify <bottle> --repo
+//! Homebrew/homebrew-core`. Where `gh` isn't installed or signed in, or the
+//! bottle comes from a tap that publishes no attestations, verification is
+//! unavailable rather than failed.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Serialize;
+
+/// Path of the `gh` executable to verify attestations with, instead of the
+/// one on `PATH`
+pub const GH_ENV: &str = "ZB_GH";
+
+/// Repository homebrew/core's bottles are attested by
+pub const CORE_ATTESTATION_REPO: &str = "Homebrew/homebrew-core";
+
+/// Outcome of checking a bottle's attestation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationStatus {
+    /// A valid attestation from the expected repository covers the bottle
+    Verified,
+    /// The bottle has no valid attestation from the expected repository
+    Failed,
+    /// The attestation couldn't be checked
+    Unavailable,
+}
+
+impl AttestationStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AttestationStatus::Verified => "verified",
+            AttestationStatus::Failed => "failed",
+            AttestationStatus::Unavailable => "unavailable",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "verified" => Some(AttestationStatus::Verified),
+            "failed" => Some(AttestationStatus::Failed),
+            "unavailable" => Some(AttestationStatus::Unavailable),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for AttestationStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A bottle's attestation check, as recorded for its store entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Attestation {
+    pub status: AttestationStatus,
+    /// Repository the attestation was checked against
+    pub repo: Option<String>,
+    /// Why verification failed or was unavailable
+    pub detail: Option<String>,
+    /// Unix timestamp
+    pub checked_at: i64,
+}
+
+impl Attestation {
+    pub fn unavailable(repo: Option<&str>, detail: impl Into<String>) -> Self {
+        Self::new(AttestationStatus::Unavailable, repo, Some(detail.into()))
+    }
+
+    fn new(status: AttestationStatus, repo: Option<&str>, detail: Option<String>) -> Self {
+        Self {
+            status,
+            repo: repo.map(str::to_string),
+            detail,
+            checked_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// Verifies bottles' attestations with the GitHub CLI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationVerifier {
+    program: PathBuf,
+}
+
+impl AttestationVerifier {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+
+    /// The `gh` named by `ZB_GH`, or the first on `PATH`
+    pub fn from_env() -> Option<Self> {
+        if let Some(program) = std::env::var_os(GH_ENV).filter(|p| !p.is_empty()) {
+            return Some(Self::new(program));
+        }
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .map(|dir| dir.join("gh"))
+            .find(|candidate| is_executable(candidate))
+            .map(Self::new)
+    }
+
+    /// Check that `bottle` is attested by a workflow in `repo`
+    pub fn verify(&self, bottle: &Path, repo: &str) -> Attestation {
+        let output = Command::new(&self.program)
+            .args(["attestation", "verify"])
+            .arg(bottle)
+            .args(["--repo", repo])
+            .output();
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => {
+                return Attestation::unavailable(
+                    Some(repo),
+                    format!("failed to run {}: {}", self.program.display(), e),
+                );
+            }
+        };
+        if output.status.success() {
+            return Attestation::new(AttestationStatus::Verified, Some(repo), None);
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or("gh attestation verify failed")
+            .to_string();
+        let status = if is_setup_error(&stderr) {
+            AttestationStatus::Unavailable
+        } else {
+            AttestationStatus::Failed
+        };
+        Attestation::new(status, Some(repo), Some(detail))
+    }
+}
+
+/// True if `gh` failed because it can't reach or sign in to GitHub, rather
+/// than because the bottle's attestation didn't verify
+fn is_setup_error(stderr: &str) -> bool {
+    const SETUP_ERRORS: &[&str] = &[
+        "gh auth login",
+        "GH_TOKEN",
+        "HTTP 401",
+        "rate limit",
+        "unknown command",
+        "error connecting",
+    ];
+    SETUP_ERRORS.iter().any(|marker| stderr.contains(marker))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn fake_gh(dir: &TempDir, script: &str) -> AttestationVerifier {
+        let path = dir.path().join("gh");
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        AttestationVerifier::new(path)
+    }
+
+    #[test]
+    fn status_round_trips() {
+        for status in [
+            AttestationStatus::Verified,
+            AttestationStatus::Failed,
+            AttestationStatus::Unavailable,
+        ] {
+            assert_eq!(AttestationStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(AttestationStatus::parse("bogus"), None);
+    }
+
+    #[test]
+    fn verify_passes_the_bottle_and_repo_to_gh() {
+        let tmp = TempDir::new().unwrap();
+        let args = tmp.path().join("args");
+        let verifier = fake_gh(&tmp, &format!("echo \"$@\" > {}", args.display()));
+
+        let attestation = verifier.verify(Path::new("/tmp/jq.tar.gz"), CORE_ATTESTATION_REPO);
+
+        assert_eq!(attestation.status, AttestationStatus::Verified);
+        assert_eq!(attestation.repo.as_deref(), Some(CORE_ATTESTATION_REPO));
+        assert_eq!(
+            fs::read_to_string(args).unwrap().trim(),
+            "attestation verify /tmp/jq.tar.gz --repo Homebrew/homebrew-core"
+        );
+    }
+
+    #[test]
+    fn verify_reports_failures_and_setup_errors() {
+        let tmp = TempDir::new().unwrap();
+        let verifier = fake_gh(
+            &tmp,
+            "echo 'Error: no attestations found for subject' >&2; exit 1",
+        );
+        let attestation = verifier.verify(Path::new("/tmp/jq.tar.gz"), CORE_ATTESTATION_REPO);
+        assert_eq!(attestation.status, AttestationStatus::Failed);
+        assert_eq!(
+            attestation.detail.as_deref(),
+            Some("Error: no attestations found for subject")
+        );
+
+        let verifier = fake_gh(
+            &tmp,
+            "echo 'To get started with GitHub CLI, please run:  gh auth login' >&2; exit 4",
+        );
+        let attestation = verifier.verify(Path::new("/tmp/jq.tar.gz"), CORE_ATTESTATION_REPO);
+        assert_eq!(attestation.status, AttestationStatus::Unavailable);
+
+        let verifier = AttestationVerifier::new(tmp.path().join("missing"));
+        let attestation = verifier.verify(Path::new("/tmp/jq.tar.gz"), CORE_ATTESTATION_REPO);
+        assert_eq!(attestation.status, AttestationStatus::Unavailable);
+    }
+}
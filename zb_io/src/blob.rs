@@ -4,6 +4,8 @@ use std::path::{Path, PathBuf};
 
 use zb_core::Error;
 
+use crate::download::Downloader;
+
 #[derive(Clone)]
 pub struct BlobCache {
     blobs_dir: PathBuf,
@@ -25,6 +27,11 @@ impl BlobCache {
         self.blobs_dir.join(format!("{sha256}.tar.gz"))
     }
 
+    /// Directory for files being written, on the same filesystem as the blobs
+    pub(crate) fn tmp_dir(&self) -> &Path {
+        &self.tmp_dir
+    }
+
     pub fn has_blob(&self, sha256: &str) -> bool {
         self.blob_path(sha256).exists()
     }
@@ -40,6 +47,16 @@ impl BlobCache {
         }
     }
 
+    /// Download `url` into the cache, verifying it against `sha256`, and
+    /// return the cached blob's path. Returns immediately on a cache hit.
+    ///
+    /// This is part of the stable API for external `zb-*` commands: it uses
+    /// the same registry authentication, retries and checksum verification
+    /// as installs, so plugins don't need their own download code.
+    pub async fn fetch(&self, url: &str, sha256: &str) -> Result<PathBuf, Error> {
+        Downloader::new(self.clone()).download(url, sha256).await
+    }
+
     pub fn start_write(&self, sha256: &str) -> io::Result<BlobWriter> {
         let final_path = self.blob_path(sha256);
         // Use unique temp filename to avoid corruption from concurrent racing downloads
@@ -254,6 +271,36 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    #[tokio::test]
+    async fn fetch_downloads_verifies_and_caches() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        Mock::given(method("GET"))
+            .and(path("/blob.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"hello world".to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+        let url = format!("{}/blob.tar.gz", mock_server.uri());
+
+        let blob = cache.fetch(&url, sha256).await.unwrap();
+        assert_eq!(blob, cache.blob_path(sha256));
+        assert_eq!(fs::read(&blob).unwrap(), b"hello world");
+
+        // Served from the cache the second time
+        assert_eq!(cache.fetch(&url, sha256).await.unwrap(), blob);
+        assert_eq!(mock_server.received_requests().await.unwrap().len(), 1);
+
+        let wrong = "0000000000000000000000000000000000000000000000000000000000000000";
+        assert!(cache.fetch(&url, wrong).await.is_err());
+        assert!(!cache.has_blob(wrong));
+    }
+
     #[test]
     fn completed_write_produces_final_blob() {
         let tmp = TempDir::new().unwrap();
@@ -21,6 +21,8 @@ use std::process::Command;
 
 use zb_core::{Error, Formula};
 
+use crate::extract::{ExtractOptions, extract_archive};
+
 /// Build system type detected from source
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BuildSystem {
@@ -475,6 +477,17 @@ fn collect_installed_files(staging_dir: &Path) -> Result<Vec<PathBuf>, Error> {
     Ok(files)
 }
 
+/// File name to save a source download as, taken from the URL so that
+/// extraction can fall back on its extension
+pub fn source_archive_name(url: &str) -> String {
+    url.split(['?', '#'])
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("source.tar.gz")
+        .to_string()
+}
+
 /// Download a source tarball and verify its checksum
 pub fn download_source(url: &str, dest: &Path, expected_sha256: Option<&str>) -> Result<(), Error> {
     // Use curl to download
@@ -569,32 +582,86 @@ pub fn clone_git_repo(url: &str, branch: Option<&str>, dest: &Path) -> Result<()
     Ok(())
 }
 
-/// Extract a tarball to a directory
-pub fn extract_tarball(tarball: &Path, dest: &Path) -> Result<PathBuf, Error> {
-    std::fs::create_dir_all(dest).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create directory {}: {}", dest.display(), e),
-    })?;
-
-    // Use tar to extract
-    let output = Command::new("tar")
-        .args([
-            "-xf",
-            &tarball.to_string_lossy(),
-            "-C",
-            &dest.to_string_lossy(),
-        ])
+/// Get the commit checked out in a git repository
+pub fn git_head_commit(repo: &Path) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["rev-parse", "HEAD"])
         .output()
         .map_err(|e| Error::StoreCorruption {
-            message: format!("failed to extract {}: {}", tarball.display(), e),
+            message: format!("failed to read commit of {}: {}", repo.display(), e),
         })?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         return Err(Error::StoreCorruption {
-            message: format!("failed to extract {}: {}", tarball.display(), stderr),
+            message: format!("failed to read commit of {}: {}", repo.display(), stderr),
         });
     }
 
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Get the commit at the tip of a remote branch (or the remote's default
+/// branch) without cloning
+pub fn remote_head_commit(url: &str, branch: Option<&str>) -> Result<String, Error> {
+    let reference = branch
+        .map(|b| format!("refs/heads/{}", b))
+        .unwrap_or_else(|| "HEAD".to_string());
+
+    let output = Command::new("git")
+        .args(["ls-remote", url, &reference])
+        .output()
+        .map_err(|e| Error::NetworkFailure {
+            message: format!("failed to query {}: {}", url, e),
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::NetworkFailure {
+            message: format!("failed to query {}: {}", url, stderr.trim()),
+        });
+    }
+
+    parse_ls_remote(&String::from_utf8_lossy(&output.stdout), &reference).ok_or_else(|| {
+        Error::NetworkFailure {
+            message: format!("{} has no ref {}", url, reference),
+        }
+    })
+}
+
+/// Find the commit for `reference` in `git ls-remote` output
+pub fn parse_ls_remote(output: &str, reference: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let (commit, name) = line.split_once('\t')?;
+        (name.trim() == reference).then(|| commit.trim().to_string())
+    })
+}
+
+/// Version string for a HEAD build of `commit`, e.g. `HEAD-1a2b3c4`
+pub fn head_version(commit: &str) -> String {
+    format!("HEAD-{}", &commit[..commit.len().min(7)])
+}
+
+/// Extract a tarball to a directory
+pub fn extract_tarball(tarball: &Path, dest: &Path) -> Result<PathBuf, Error> {
+    extract_source(tarball, dest, ExtractOptions::default())
+}
+
+/// Extract a source archive (any format [`crate::extract`] detects) to a
+/// directory, returning the source root
+pub fn extract_source(
+    archive: &Path,
+    dest: &Path,
+    options: ExtractOptions,
+) -> Result<PathBuf, Error> {
+    std::fs::create_dir_all(dest).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create directory {}: {}", dest.display(), e),
+    })?;
+
+    extract_archive(archive, dest, options)?;
+
     // Find the extracted directory (usually there's one top-level dir)
     let entries: Vec<_> = std::fs::read_dir(dest)
         .map_err(|e| Error::StoreCorruption {
@@ -1940,6 +2007,127 @@ mod tests {
         }
     }
 
+    // ==========================================================================
+    // HEAD Commit Tests
+    // ==========================================================================
+
+    mod head_commits {
+        use super::*;
+
+        fn git(dir: &Path, args: &[&str]) {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .env("GIT_AUTHOR_NAME", "test")
+                .env("GIT_AUTHOR_EMAIL", "test@example.com")
+                .env("GIT_COMMITTER_NAME", "test")
+                .env("GIT_COMMITTER_EMAIL", "test@example.com")
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        }
+
+        #[test]
+        fn parses_ls_remote_output() {
+            let output = "1111111111111111111111111111111111111111\tHEAD\n\
+                          2222222222222222222222222222222222222222\trefs/heads/main\n\
+                          3333333333333333333333333333333333333333\trefs/heads/main-old\n";
+            assert_eq!(
+                parse_ls_remote(output, "refs/heads/main").as_deref(),
+                Some("2222222222222222222222222222222222222222")
+            );
+            assert_eq!(
+                parse_ls_remote(output, "HEAD").as_deref(),
+                Some("1111111111111111111111111111111111111111")
+            );
+            assert_eq!(parse_ls_remote(output, "refs/heads/dev"), None);
+            assert_eq!(parse_ls_remote("", "HEAD"), None);
+        }
+
+        #[test]
+        fn formats_head_version() {
+            assert_eq!(
+                head_version("1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b"),
+                "HEAD-1a2b3c4"
+            );
+            assert_eq!(head_version("abc"), "HEAD-abc");
+        }
+
+        #[test]
+        fn reads_local_and_remote_commits() {
+            let tmp = TempDir::new().unwrap();
+            let repo = tmp.path().join("repo");
+            std::fs::create_dir(&repo).unwrap();
+            git(&repo, &["init", "-q", "-b", "main"]);
+            std::fs::write(repo.join("README"), "hi").unwrap();
+            git(&repo, &["add", "README"]);
+            git(&repo, &["commit", "-q", "-m", "first"]);
+
+            let local = git_head_commit(&repo).unwrap();
+            assert_eq!(local.len(), 40);
+
+            let url = format!("file://{}", repo.display());
+            assert_eq!(remote_head_commit(&url, Some("main")).unwrap(), local);
+            assert_eq!(remote_head_commit(&url, None).unwrap(), local);
+            assert!(remote_head_commit(&url, Some("missing")).is_err());
+        }
+    }
+
+    // ==========================================================================
+    // Source Archive Tests
+    // ==========================================================================
+
+    mod source_archive {
+        use super::*;
+
+        #[test]
+        fn names_download_after_url() {
+            assert_eq!(
+                source_archive_name("https://example.com/dl/foo-1.2.tar.xz"),
+                "foo-1.2.tar.xz"
+            );
+            assert_eq!(
+                source_archive_name("https://example.com/foo-1.2.zip?raw=true#x"),
+                "foo-1.2.zip"
+            );
+            assert_eq!(source_archive_name("https://example.com/"), "source.tar.gz");
+        }
+
+        #[test]
+        fn strips_components_from_source() {
+            let tmp = TempDir::new().unwrap();
+            let tarball = tmp.path().join("nested.tar.gz");
+            let src_dir = tmp.path().join("wrapper/project-2.0");
+            std::fs::create_dir_all(&src_dir).unwrap();
+            std::fs::write(src_dir.join("configure"), "#!/bin/sh").unwrap();
+
+            let status = std::process::Command::new("tar")
+                .args([
+                    "-czf",
+                    &tarball.to_string_lossy(),
+                    "-C",
+                    &tmp.path().to_string_lossy(),
+                    "wrapper",
+                ])
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            let dest = tmp.path().join("extracted");
+            let root = extract_source(
+                &tarball,
+                &dest,
+                ExtractOptions {
+                    strip_components: 1,
+                },
+            )
+            .unwrap();
+            assert_eq!(root, dest.join("project-2.0"));
+            assert!(root.join("configure").exists());
+        }
+    }
+
     // ==========================================================================
     // Download Source Advanced Tests
     // ==========================================================================
@@ -30,6 +30,8 @@ use std::path::{Path, PathBuf};
 
 use zb_core::Error;
 
+use crate::tap::TapFormula;
+
 /// A parsed entry from a Brewfile
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BrewfileEntry {
@@ -89,6 +91,8 @@ impl BundleCheckResult {
 /// Result of installing a Brewfile
 #[derive(Debug, Default)]
 pub struct BundleInstallResult {
+    /// Identifies this run in the provenance of the formulas it installed
+    pub run_id: String,
     /// Taps that were added
     pub taps_added: Vec<String>,
     /// Formulas that were installed
@@ -99,6 +103,59 @@ pub struct BundleInstallResult {
     pub failed: Vec<(String, String)>,
 }
 
+/// Environment that activates a Brewfile's formulas straight from their kegs,
+/// without linking them into the prefix
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BundleEnv {
+    /// Keg directories of the installed formulas, in Brewfile order
+    pub kegs: Vec<PathBuf>,
+    /// Formulas in the Brewfile that are not installed
+    pub missing: Vec<String>,
+}
+
+impl BundleEnv {
+    /// Search path variables and the keg directories to prepend to each.
+    /// Only directories that exist are included; variables with none are left out.
+    pub fn path_vars(&self) -> Vec<(&'static str, Vec<PathBuf>)> {
+        const VARS: &[(&str, &[&str])] = &[
+            ("PATH", &["bin", "sbin"]),
+            ("MANPATH", &["share/man"]),
+            ("PKG_CONFIG_PATH", &["lib/pkgconfig", "share/pkgconfig"]),
+            ("CMAKE_PREFIX_PATH", &[""]),
+        ];
+
+        VARS.iter()
+            .filter_map(|(var, subdirs)| {
+                let dirs: Vec<PathBuf> = self
+                    .kegs
+                    .iter()
+                    .flat_map(|keg| subdirs.iter().map(move |sub| keg.join(sub)))
+                    .map(|dir| dir.components().collect::<PathBuf>())
+                    .filter(|dir| dir.is_dir())
+                    .collect();
+                (!dirs.is_empty()).then_some((*var, dirs))
+            })
+            .collect()
+    }
+
+    /// The dynamic loader's search path variable and the keg `lib`
+    /// directories to prepend to it, if any exist.
+    pub fn library_path_var(&self) -> Option<(&'static str, Vec<PathBuf>)> {
+        let var = if cfg!(target_os = "macos") {
+            "DYLD_FALLBACK_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+        let dirs: Vec<PathBuf> = self
+            .kegs
+            .iter()
+            .map(|keg| keg.join("lib"))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        (!dirs.is_empty()).then_some((var, dirs))
+    }
+}
+
 /// Parse a Brewfile into entries
 pub fn parse_brewfile(content: &str) -> Result<Vec<BrewfileEntry>, Error> {
     let mut entries = Vec::new();
@@ -373,14 +430,15 @@ fn normalize_tap_name(name: &str) -> String {
 }
 
 /// Extract the formula name from a potentially qualified name
-fn extract_formula_name(name: &str) -> String {
-    // user/repo/formula -> formula
-    let parts: Vec<_> = name.split('/').collect();
-    if parts.len() == 3 {
-        // user/repo/formula format
-        return parts[2].to_string();
-    }
-    name.to_string()
+pub(crate) fn extract_formula_name(name: &str) -> String {
+    TapFormula::formula_name(name).to_string()
+}
+
+/// Whether a `brew` entry's args ask for a HEAD build and for a source build
+pub(crate) fn build_flags(args: &[String]) -> (bool, bool) {
+    let is_head = args.iter().any(|a| a == "--HEAD" || a == "-H");
+    let is_source = args.iter().any(|a| a == "--build-from-source" || a == "-s");
+    (is_head, is_source)
 }
 
 #[cfg(test)]
@@ -0,0 +1,317 @@
+//! `Brewfile.lock.json`: the exact artifacts a Brewfile resolved to.
+//!
+//! `zb bundle lock` records the version, bottle URL and checksum of every
+//! formula a Brewfile installs, dependencies included, in the layout Homebrew
+//! Bundle uses for its lockfile. `zb bundle install --locked` only installs
+//! when the current metadata still resolves to exactly those artifacts.
+//!
+//! ```text
+//! {
+//!   "entries": {
+//!     "tap": { "user/repo": {} },
+//!     "brew": {
+//!       "jq": {
+//!         "version": "1.7.1",
+//!         "bottle": {
+//!           "rebuild": 0,
+//!           "files": {
+//!             "arm64_sonoma": { "url": "https://...", "sha256": "..." }
+//!           }
+//!         }
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use zb_core::{Error, Formula, SelectedBottle};
+
+/// Suffix appended to the Brewfile's path to name its lockfile
+pub const LOCKFILE_SUFFIX: &str = ".lock.json";
+
+/// Lockfile written next to `brewfile`, e.g. `Brewfile.lock.json`
+pub fn lockfile_path(brewfile: &Path) -> PathBuf {
+    let mut name = brewfile.as_os_str().to_os_string();
+    name.push(LOCKFILE_SUFFIX);
+    PathBuf::from(name)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleLock {
+    #[serde(default)]
+    pub entries: LockEntries,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockEntries {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tap: BTreeMap<String, LockedTap>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub brew: BTreeMap<String, LockedFormula>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedTap {
+    /// Commit of the tap, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedFormula {
+    /// Version including the bottle rebuild, e.g. `1.7.1_1`
+    pub version: String,
+    /// Bottles by platform tag; absent for formulas built from source
+    /// (Homebrew writes `false`)
+    #[serde(
+        default,
+        deserialize_with = "bottle_or_false",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub bottle: Option<LockedBottle>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedBottle {
+    #[serde(default)]
+    pub rebuild: u32,
+    #[serde(default)]
+    pub files: BTreeMap<String, LockedBottleFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedBottleFile {
+    pub url: String,
+    pub sha256: String,
+}
+
+fn bottle_or_false<'de, D>(deserializer: D) -> Result<Option<LockedBottle>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(false) | serde_json::Value::Null => Ok(None),
+        value => serde_json::from_value(value)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+impl BundleLock {
+    /// Read a lockfile
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read lockfile at {}: {e}", path.display()),
+        })?;
+        serde_json::from_slice(&data).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to parse lockfile at {}: {e}", path.display()),
+        })
+    }
+
+    /// Write the lockfile, replacing any existing one in a single rename
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut body = serde_json::to_vec_pretty(self).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize lockfile: {e}"),
+        })?;
+        body.push(b'\n');
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = path.with_file_name(format!(".{file_name}.tmp"));
+        fs::write(&tmp, body)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                Error::StoreCorruption {
+                    message: format!("failed to write {}: {e}", path.display()),
+                }
+            })
+    }
+
+    /// Record `formula` at its current version, poured from `bottle` or built
+    /// from source when there is none.
+    ///
+    /// Bottles locked earlier for other platforms are kept as long as the
+    /// version is unchanged, so one lockfile can serve several machines.
+    pub fn lock_formula(&mut self, formula: &Formula, bottle: Option<&SelectedBottle>) {
+        let version = formula.effective_version();
+        let previous = self
+            .entries
+            .brew
+            .remove(&formula.name)
+            .filter(|locked| locked.version == version)
+            .and_then(|locked| locked.bottle);
+
+        let bottle = bottle.map(|bottle| {
+            let mut locked = previous.unwrap_or_default();
+            locked.rebuild = formula.bottle.stable.rebuild;
+            locked.files.insert(
+                bottle.tag.clone(),
+                LockedBottleFile {
+                    url: bottle.url.clone(),
+                    sha256: bottle.sha256.clone(),
+                },
+            );
+            locked
+        });
+
+        self.entries
+            .brew
+            .insert(formula.name.clone(), LockedFormula { version, bottle });
+    }
+
+    /// How `formula`, poured from `bottle` or built from source, differs from
+    /// what was locked, or `None` if it matches
+    pub fn drift(&self, formula: &Formula, bottle: Option<&SelectedBottle>) -> Option<String> {
+        let name = &formula.name;
+        let Some(locked) = self.entries.brew.get(name) else {
+            return Some(format!("{name} is not in the lockfile"));
+        };
+
+        let version = formula.effective_version();
+        if locked.version != version {
+            return Some(format!(
+                "{name} is locked at {} but resolves to {version}",
+                locked.version
+            ));
+        }
+
+        let bottle = bottle?;
+        match locked
+            .bottle
+            .as_ref()
+            .and_then(|locked| locked.files.get(&bottle.tag))
+        {
+            None => Some(format!("{name} has no locked bottle for {}", bottle.tag)),
+            Some(file) if file.url != bottle.url || file.sha256 != bottle.sha256 => Some(format!(
+                "{name} bottle for {} changed since it was locked",
+                bottle.tag
+            )),
+            Some(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn formula(version: &str, rebuild: u32) -> Formula {
+        let json = format!(
+            r#"{{"name":"jq","versions":{{"stable":"{version}"}},"bottle":{{"stable":{{"rebuild":{rebuild},"files":{{}}}}}}}}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn bottle(tag: &str, sha256: &str) -> SelectedBottle {
+        SelectedBottle {
+            tag: tag.to_string(),
+            url: format!("https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:{sha256}"),
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn lockfile_sits_next_to_brewfile() {
+        assert_eq!(
+            lockfile_path(Path::new("/work/Brewfile")),
+            PathBuf::from("/work/Brewfile.lock.json")
+        );
+    }
+
+    #[test]
+    fn locked_artifacts_match_until_they_drift() {
+        let mut lock = BundleLock::default();
+        let jq = formula("1.7.1", 1);
+        lock.lock_formula(&jq, Some(&bottle("x86_64_linux", "aaa")));
+
+        assert_eq!(lock.entries.brew["jq"].version, "1.7.1_1");
+        assert_eq!(lock.drift(&jq, Some(&bottle("x86_64_linux", "aaa"))), None);
+
+        let drift = lock
+            .drift(&jq, Some(&bottle("x86_64_linux", "bbb")))
+            .unwrap();
+        assert!(drift.contains("changed"), "{drift}");
+        let drift = lock
+            .drift(&jq, Some(&bottle("arm64_sonoma", "aaa")))
+            .unwrap();
+        assert!(
+            drift.contains("no locked bottle for arm64_sonoma"),
+            "{drift}"
+        );
+        let drift = lock.drift(&formula("1.8.0", 0), None).unwrap();
+        assert!(
+            drift.contains("locked at 1.7.1_1 but resolves to 1.8.0"),
+            "{drift}"
+        );
+
+        let mut other = formula("1.0", 0);
+        other.name = "oniguruma".to_string();
+        assert_eq!(
+            lock.drift(&other, None).as_deref(),
+            Some("oniguruma is not in the lockfile")
+        );
+    }
+
+    #[test]
+    fn relocking_keeps_other_platforms_until_version_changes() {
+        let mut lock = BundleLock::default();
+        lock.lock_formula(&formula("1.7.1", 0), Some(&bottle("arm64_sonoma", "aaa")));
+        lock.lock_formula(&formula("1.7.1", 0), Some(&bottle("x86_64_linux", "bbb")));
+        let files = &lock.entries.brew["jq"].bottle.as_ref().unwrap().files;
+        assert_eq!(files.len(), 2);
+
+        lock.lock_formula(&formula("1.8.0", 0), Some(&bottle("x86_64_linux", "ccc")));
+        let files = &lock.entries.brew["jq"].bottle.as_ref().unwrap().files;
+        assert_eq!(files.keys().collect::<Vec<_>>(), vec!["x86_64_linux"]);
+    }
+
+    #[test]
+    fn reads_homebrew_lockfiles() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("Brewfile.lock.json");
+        fs::write(
+            &path,
+            r#"{
+              "entries": {
+                "tap": { "homebrew/core": { "revision": "abc123" } },
+                "brew": {
+                  "jq": {
+                    "version": "1.7.1",
+                    "bottle": {
+                      "rebuild": 0,
+                      "root_url": "https://ghcr.io/v2/homebrew/core",
+                      "files": {
+                        "arm64_sonoma": { "cellar": ":any", "url": "https://x/jq", "sha256": "aaa" }
+                      }
+                    }
+                  },
+                  "mytool": { "version": "2.0", "bottle": false }
+                }
+              },
+              "system": { "macos": {} }
+            }"#,
+        )
+        .unwrap();
+
+        let lock = BundleLock::read(&path).unwrap();
+        assert_eq!(
+            lock.entries.tap["homebrew/core"].revision.as_deref(),
+            Some("abc123")
+        );
+        assert_eq!(
+            lock.entries.brew["jq"].bottle.as_ref().unwrap().files["arm64_sonoma"].sha256,
+            "aaa"
+        );
+        assert_eq!(lock.entries.brew["mytool"].bottle, None);
+
+        lock.write(&path).unwrap();
+        assert_eq!(BundleLock::read(&path).unwrap(), lock);
+    }
+}
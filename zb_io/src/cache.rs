@@ -15,6 +15,8 @@ pub struct CachedFormula {
     pub aliases: Vec<String>,
     pub deprecated: bool,
     pub disabled: bool,
+    /// Runtime dependencies, used to build the reverse-dependency index
+    pub dependencies: Vec<String>,
 }
 
 /// Cache metadata for conditional requests
@@ -33,6 +35,17 @@ pub struct CacheEntry {
     pub cached_at: i64,
 }
 
+impl CacheEntry {
+    /// Whether the entry was fetched or revalidated less than `ttl_secs` ago
+    pub fn is_fresh(&self, ttl_secs: i64) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        now - self.cached_at < ttl_secs
+    }
+}
+
 impl ApiCache {
     pub fn open(path: &Path) -> Result<Self, rusqlite::Error> {
         let conn = Connection::open(path)?;
@@ -67,7 +80,20 @@ impl ApiCache {
                 version TEXT,
                 aliases TEXT,
                 deprecated INTEGER NOT NULL DEFAULT 0,
-                disabled INTEGER NOT NULL DEFAULT 0
+                disabled INTEGER NOT NULL DEFAULT 0,
+                dependencies TEXT
+            )",
+            [],
+        )?;
+        Self::migrate_add_dependencies_column(conn)?;
+
+        // Reverse-dependency index over the whole formula list, rebuilt
+        // together with `formulas`
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS reverse_deps (
+                dependency TEXT NOT NULL,
+                dependent TEXT NOT NULL,
+                PRIMARY KEY (dependency, dependent)
             )",
             [],
         )?;
@@ -82,6 +108,16 @@ impl ApiCache {
             [],
         )?;
 
+        // Running totals of formula lookups answered from the cache
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS lookup_stats (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                hits INTEGER NOT NULL DEFAULT 0,
+                misses INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+
         // FTS5 full-text search index (Phase 4)
         // Uses external content table to avoid data duplication
         conn.execute(
@@ -98,6 +134,23 @@ impl ApiCache {
         Ok(())
     }
 
+    fn migrate_add_dependencies_column(conn: &Connection) -> Result<(), rusqlite::Error> {
+        let has_column: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('formulas') WHERE name = 'dependencies'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        if !has_column {
+            conn.execute("ALTER TABLE formulas ADD COLUMN dependencies TEXT", [])?;
+            // Formulas cached before this column existed have no dependencies
+            // recorded, so drop the validators to force a full refresh
+            conn.execute("DELETE FROM formula_cache_meta", [])?;
+        }
+
+        Ok(())
+    }
+
     pub fn get(&self, url: &str) -> Option<CacheEntry> {
         self.conn
             .query_row(
@@ -129,6 +182,38 @@ impl ApiCache {
         Ok(())
     }
 
+    /// Count a formula lookup: a hit was answered from the cache (e.g. a 304),
+    /// a miss needed the full response body
+    pub fn record_lookup(&self, hit: bool) -> Result<(), rusqlite::Error> {
+        let (hits, misses) = if hit { (1, 0) } else { (0, 1) };
+        self.conn.execute(
+            "INSERT INTO lookup_stats (id, hits, misses) VALUES (1, ?1, ?2)
+             ON CONFLICT(id) DO UPDATE SET hits = hits + ?1, misses = misses + ?2",
+            params![hits, misses],
+        )?;
+        Ok(())
+    }
+
+    /// Recorded lookup hits and misses
+    pub fn lookup_stats(&self) -> Result<(u64, u64), rusqlite::Error> {
+        self.conn
+            .query_row(
+                "SELECT hits, misses FROM lookup_stats WHERE id = 1",
+                [],
+                |row| Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64)),
+            )
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok((0, 0)),
+                e => Err(e),
+            })
+    }
+
+    /// Forget recorded lookup hits and misses
+    pub fn clear_lookup_stats(&self) -> Result<(), rusqlite::Error> {
+        self.conn.execute("DELETE FROM lookup_stats", [])?;
+        Ok(())
+    }
+
     /// Remove all cache entries older than the specified number of days
     /// Returns the number of entries removed
     pub fn cleanup_older_than(&self, days: u32) -> Result<usize, rusqlite::Error> {
@@ -147,6 +232,15 @@ impl ApiCache {
         Ok(rows_affected)
     }
 
+    /// Remove the cache entry for one URL
+    /// Returns whether an entry was removed
+    pub fn remove(&self, url: &str) -> Result<bool, rusqlite::Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM api_cache WHERE url = ?1", params![url])?;
+        Ok(rows_affected > 0)
+    }
+
     /// Remove all cache entries
     /// Returns the number of entries removed
     pub fn clear(&self) -> Result<usize, rusqlite::Error> {
@@ -227,25 +321,11 @@ impl ApiCache {
     /// Get all cached formulas from SQLite
     pub fn get_formulas(&self) -> Result<Vec<CachedFormula>, rusqlite::Error> {
         let mut stmt = self.conn.prepare_cached(
-            "SELECT name, full_name, description, version, aliases, deprecated, disabled FROM formulas",
+            "SELECT name, full_name, description, version, aliases, deprecated, disabled, dependencies
+             FROM formulas",
         )?;
 
-        let rows = stmt.query_map([], |row| {
-            let aliases_json: Option<String> = row.get(4)?;
-            let aliases: Vec<String> = aliases_json
-                .and_then(|s| serde_json::from_str(&s).ok())
-                .unwrap_or_default();
-
-            Ok(CachedFormula {
-                name: row.get(0)?,
-                full_name: row.get(1)?,
-                description: row.get(2)?,
-                version: row.get(3)?,
-                aliases,
-                deprecated: row.get::<_, i64>(5)? != 0,
-                disabled: row.get::<_, i64>(6)? != 0,
-            })
-        })?;
+        let rows = stmt.query_map([], Self::cached_formula_from_row)?;
 
         rows.collect()
     }
@@ -268,12 +348,17 @@ impl ApiCache {
         // Clear existing formulas and FTS index
         self.conn.execute("DELETE FROM formulas", [])?;
         self.conn.execute("DELETE FROM formula_fts", [])?;
+        self.conn.execute("DELETE FROM reverse_deps", [])?;
 
         // Insert new formulas
         {
             let mut stmt = self.conn.prepare_cached(
-                "INSERT INTO formulas (name, full_name, description, version, aliases, deprecated, disabled)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                "INSERT INTO formulas (name, full_name, description, version, aliases, deprecated, disabled, dependencies)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+
+            let mut reverse_stmt = self.conn.prepare_cached(
+                "INSERT OR IGNORE INTO reverse_deps (dependency, dependent) VALUES (?1, ?2)",
             )?;
 
             let mut fts_stmt = self.conn.prepare_cached(
@@ -286,6 +371,8 @@ impl ApiCache {
                     serde_json::to_string(&f.aliases).unwrap_or_else(|_| "[]".to_string());
                 // Join aliases with spaces for FTS searchability
                 let aliases_text = f.aliases.join(" ");
+                let dependencies_json =
+                    serde_json::to_string(&f.dependencies).unwrap_or_else(|_| "[]".to_string());
 
                 stmt.execute(params![
                     &f.name,
@@ -295,8 +382,13 @@ impl ApiCache {
                     &aliases_json,
                     f.deprecated as i64,
                     f.disabled as i64,
+                    &dependencies_json,
                 ])?;
 
+                for dep in &f.dependencies {
+                    reverse_stmt.execute(params![dep, &f.name])?;
+                }
+
                 // Insert into FTS index (rowid is 1-based in SQLite)
                 fts_stmt.execute(params![
                     (idx + 1) as i64,
@@ -340,6 +432,31 @@ impl ApiCache {
             })
     }
 
+    /// Formulas in the cached list that depend on `name`, sorted by name
+    ///
+    /// With `recursive`, formulas that depend on it through other formulas
+    /// are included too.
+    pub fn get_dependents(
+        &self,
+        name: &str,
+        recursive: bool,
+    ) -> Result<Vec<String>, rusqlite::Error> {
+        let sql = if recursive {
+            "WITH RECURSIVE users(name) AS (
+                 SELECT dependent FROM reverse_deps WHERE dependency = ?1
+                 UNION
+                 SELECT r.dependent FROM reverse_deps r JOIN users u ON r.dependency = u.name
+             )
+             SELECT name FROM users WHERE name != ?1 ORDER BY name"
+        } else {
+            "SELECT dependent FROM reverse_deps WHERE dependency = ?1 ORDER BY dependent"
+        };
+
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let rows = stmt.query_map(params![name], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    }
+
     // ========================================================================
     // FTS5 search methods (Phase 4)
     // ========================================================================
@@ -368,33 +485,39 @@ impl ApiCache {
         let fts_query = Self::prepare_fts_query(query);
 
         let mut stmt = self.conn.prepare_cached(
-            "SELECT f.name, f.full_name, f.description, f.version, f.aliases, f.deprecated, f.disabled
+            "SELECT f.name, f.full_name, f.description, f.version, f.aliases, f.deprecated, f.disabled,
+                    f.dependencies
              FROM formulas f
              JOIN formula_fts fts ON f.rowid = fts.rowid
              WHERE formula_fts MATCH ?1
              AND f.deprecated = 0 AND f.disabled = 0",
         )?;
 
-        let rows = stmt.query_map(params![fts_query], |row| {
-            let aliases_json: Option<String> = row.get(4)?;
-            let aliases: Vec<String> = aliases_json
-                .and_then(|s| serde_json::from_str(&s).ok())
-                .unwrap_or_default();
-
-            Ok(CachedFormula {
-                name: row.get(0)?,
-                full_name: row.get(1)?,
-                description: row.get(2)?,
-                version: row.get(3)?,
-                aliases,
-                deprecated: row.get::<_, i64>(5)? != 0,
-                disabled: row.get::<_, i64>(6)? != 0,
-            })
-        })?;
+        let rows = stmt.query_map(params![fts_query], Self::cached_formula_from_row)?;
 
         rows.collect()
     }
 
+    fn cached_formula_from_row(row: &rusqlite::Row<'_>) -> Result<CachedFormula, rusqlite::Error> {
+        let json_list = |idx: usize| -> Result<Vec<String>, rusqlite::Error> {
+            let json: Option<String> = row.get(idx)?;
+            Ok(json
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default())
+        };
+
+        Ok(CachedFormula {
+            name: row.get(0)?,
+            full_name: row.get(1)?,
+            description: row.get(2)?,
+            version: row.get(3)?,
+            aliases: json_list(4)?,
+            deprecated: row.get::<_, i64>(5)? != 0,
+            disabled: row.get::<_, i64>(6)? != 0,
+            dependencies: json_list(7)?,
+        })
+    }
+
     /// Prepare a search query for FTS5
     /// - Escapes special characters
     /// - Adds prefix matching with '*'
@@ -441,6 +564,20 @@ mod tests {
         assert!(retrieved.cached_at > 0);
     }
 
+    #[test]
+    fn lookup_stats_accumulate_and_clear() {
+        let cache = ApiCache::in_memory().unwrap();
+        assert_eq!(cache.lookup_stats().unwrap(), (0, 0));
+
+        cache.record_lookup(true).unwrap();
+        cache.record_lookup(true).unwrap();
+        cache.record_lookup(false).unwrap();
+        assert_eq!(cache.lookup_stats().unwrap(), (2, 1));
+
+        cache.clear_lookup_stats().unwrap();
+        assert_eq!(cache.lookup_stats().unwrap(), (0, 0));
+    }
+
     #[test]
     fn returns_none_for_missing_entry() {
         let cache = ApiCache::in_memory().unwrap();
@@ -637,6 +774,7 @@ mod tests {
                 aliases: vec!["python3".to_string()],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
             CachedFormula {
                 name: "node".to_string(),
@@ -646,6 +784,7 @@ mod tests {
                 aliases: vec!["nodejs".to_string()],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
         ];
 
@@ -675,6 +814,7 @@ mod tests {
                 aliases: vec!["python3".to_string()],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
             CachedFormula {
                 name: "pyenv".to_string(),
@@ -684,6 +824,7 @@ mod tests {
                 aliases: vec![],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
             CachedFormula {
                 name: "node".to_string(),
@@ -693,6 +834,7 @@ mod tests {
                 aliases: vec![],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
         ];
 
@@ -721,6 +863,7 @@ mod tests {
                 aliases: vec![],
                 deprecated: false,
                 disabled: false,
+                dependencies: Vec::new(),
             },
             CachedFormula {
                 name: "old-pkg".to_string(),
@@ -730,6 +873,7 @@ mod tests {
                 aliases: vec![],
                 deprecated: true,
                 disabled: false,
+                dependencies: Vec::new(),
             },
             CachedFormula {
                 name: "broken-pkg".to_string(),
@@ -739,6 +883,7 @@ mod tests {
                 aliases: vec![],
                 deprecated: false,
                 disabled: true,
+                dependencies: Vec::new(),
             },
         ];
 
@@ -765,6 +910,7 @@ mod tests {
             aliases: vec![],
             deprecated: false,
             disabled: false,
+            dependencies: Vec::new(),
         }];
 
         cache.put_formulas(&formulas, None, None).unwrap();
@@ -775,4 +921,103 @@ mod tests {
         // With 0 TTL, should not be fresh
         assert!(!cache.is_formula_cache_fresh(0));
     }
+
+    fn formula_with_deps(name: &str, deps: &[&str]) -> CachedFormula {
+        CachedFormula {
+            name: name.to_string(),
+            full_name: name.to_string(),
+            description: None,
+            version: None,
+            aliases: vec![],
+            deprecated: false,
+            disabled: false,
+            dependencies: deps.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn reverse_dependency_index_follows_formula_list() {
+        let cache = ApiCache::in_memory().unwrap();
+        let formulas = vec![
+            formula_with_deps("openssl@3", &["ca-certificates"]),
+            formula_with_deps("curl", &["openssl@3", "zlib"]),
+            formula_with_deps("git", &["curl", "pcre2"]),
+            formula_with_deps("wget", &["openssl@3"]),
+        ];
+        cache.put_formulas(&formulas, None, None).unwrap();
+
+        assert_eq!(
+            cache.get_dependents("openssl@3", false).unwrap(),
+            vec!["curl", "wget"]
+        );
+        assert_eq!(
+            cache.get_dependents("openssl@3", true).unwrap(),
+            vec!["curl", "git", "wget"]
+        );
+        assert_eq!(
+            cache.get_formulas().unwrap()[1].dependencies,
+            vec!["openssl@3", "zlib"]
+        );
+
+        // A refreshed list replaces the index
+        cache
+            .put_formulas(&[formula_with_deps("wget", &["openssl@3"])], None, None)
+            .unwrap();
+        assert_eq!(
+            cache.get_dependents("openssl@3", true).unwrap(),
+            vec!["wget"]
+        );
+        assert!(cache.get_dependents("zlib", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn recursive_dependents_tolerate_cycles() {
+        let cache = ApiCache::in_memory().unwrap();
+        let formulas = vec![
+            formula_with_deps("a", &["b"]),
+            formula_with_deps("b", &["a"]),
+        ];
+        cache.put_formulas(&formulas, None, None).unwrap();
+
+        assert_eq!(cache.get_dependents("a", true).unwrap(), vec!["b"]);
+    }
+
+    #[test]
+    fn adding_dependencies_column_forces_full_refresh() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("api.sqlite3");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE formulas (
+                    name TEXT PRIMARY KEY,
+                    full_name TEXT NOT NULL,
+                    description TEXT,
+                    version TEXT,
+                    aliases TEXT,
+                    deprecated INTEGER NOT NULL DEFAULT 0,
+                    disabled INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE TABLE formula_cache_meta (
+                    id INTEGER PRIMARY KEY CHECK (id = 1),
+                    etag TEXT,
+                    last_modified TEXT,
+                    cached_at INTEGER NOT NULL
+                );
+                INSERT INTO formulas (name, full_name) VALUES ('jq', 'jq');
+                INSERT INTO formula_cache_meta (id, etag, cached_at) VALUES (1, '\"abc\"', 0);",
+            )
+            .unwrap();
+        }
+
+        let cache = ApiCache::open(&path).unwrap();
+        assert!(cache.get_formula_cache_meta().is_none());
+        assert!(cache.get_formulas().unwrap()[0].dependencies.is_empty());
+
+        // Reopening an up-to-date cache keeps its metadata
+        cache.put_formulas(&[], Some("\"def\""), None).unwrap();
+        drop(cache);
+        let cache = ApiCache::open(&path).unwrap();
+        assert!(cache.get_formula_cache_meta().is_some());
+    }
 }
@@ -0,0 +1,409 @@
+//! Default configuration files shipped in a keg's `etc/`.
+//!
+//! Bottles carry default configs such as `redis.conf` under `etc/`. Unlike
+//! executables these are copied into the prefix's `etc/` rather than linked,
+//! since users are expected to edit them. A config is only replaced on
+//! upgrade while it still matches the default that was installed before;
+//! an edited config is left alone and the new default is saved next to it
+//! with a [`DEFAULT_SUFFIX`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use zb_core::Error;
+
+/// Suffix of the new default saved next to a user-modified config
+pub const DEFAULT_SUFFIX: &str = ".default";
+
+/// A default config installed from a keg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigFile {
+    /// Path relative to `etc/`
+    pub path: PathBuf,
+    /// Checksum of the default shipped in the keg
+    pub sha256: String,
+}
+
+/// What installing a default config did to the prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigAction {
+    /// Nothing was there, the default was copied into place
+    Installed,
+    /// An unmodified earlier default was replaced
+    Updated,
+    /// The config already matched the new default
+    Unchanged,
+    /// The config was modified; the new default was saved alongside
+    KeptModified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    /// Absolute path of the config in the prefix
+    pub path: PathBuf,
+    pub action: ConfigAction,
+}
+
+impl ConfigChange {
+    /// Where the new default was saved, for a kept config
+    pub fn default_path(&self) -> Option<PathBuf> {
+        (self.action == ConfigAction::KeptModified).then(|| with_default_suffix(&self.path))
+    }
+}
+
+impl fmt::Display for ConfigAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigAction::Installed => "installed",
+            ConfigAction::Updated => "updated",
+            ConfigAction::Unchanged => "unchanged",
+            ConfigAction::KeptModified => "kept modified",
+        })
+    }
+}
+
+/// A config in the prefix that differs from the default in the keg
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// The config in use, in the prefix's `etc/`
+    pub current: PathBuf,
+    /// The default shipped in the keg
+    pub default: PathBuf,
+}
+
+/// Copy the default configs of `keg_path` into `etc_dir`.
+///
+/// `previous` maps configs installed earlier (relative to `etc/`) to the
+/// checksum of the default they were installed from; a config still matching
+/// that checksum was not edited and is safe to replace. Returns the defaults
+/// now installed, to be recorded for the next upgrade, and what happened to
+/// each file.
+pub fn install_configs(
+    keg_path: &Path,
+    etc_dir: &Path,
+    previous: &BTreeMap<PathBuf, String>,
+) -> Result<(Vec<ConfigFile>, Vec<ConfigChange>), Error> {
+    let mut files = Vec::new();
+    let mut changes = Vec::new();
+
+    for (rel, default) in keg_configs(keg_path)? {
+        let sha256 = file_sha256(&default)?;
+        let target = etc_dir.join(&rel);
+
+        let action = match fs::symlink_metadata(&target) {
+            Err(_) => {
+                copy_into_place(&default, &target)?;
+                ConfigAction::Installed
+            }
+            Ok(meta) => {
+                // Anything but a readable regular file, such as a symlink
+                // into the user's dotfiles or a dangling one, counts as
+                // modified and is never replaced
+                let current = file_sha256(&target).ok();
+                if current.as_ref() == Some(&sha256) {
+                    ConfigAction::Unchanged
+                } else if meta.is_file()
+                    && current.is_some()
+                    && previous.get(&rel) == current.as_ref()
+                {
+                    copy_into_place(&default, &target)?;
+                    ConfigAction::Updated
+                } else {
+                    copy_into_place(&default, &with_default_suffix(&target))?;
+                    ConfigAction::KeptModified
+                }
+            }
+        };
+
+        files.push(ConfigFile { path: rel, sha256 });
+        changes.push(ConfigChange {
+            path: target,
+            action,
+        });
+    }
+
+    Ok((files, changes))
+}
+
+/// Configs in `etc_dir` that differ from the defaults shipped in `keg_path`.
+///
+/// Configs that were removed from `etc_dir` are skipped.
+pub fn modified_configs(keg_path: &Path, etc_dir: &Path) -> Result<Vec<ConfigDiff>, Error> {
+    let mut diffs = Vec::new();
+    for (rel, default) in keg_configs(keg_path)? {
+        let current = etc_dir.join(&rel);
+        if !current.is_file() {
+            continue;
+        }
+        if file_sha256(&current)? != file_sha256(&default)? {
+            diffs.push(ConfigDiff { current, default });
+        }
+    }
+    Ok(diffs)
+}
+
+/// Regular files under the keg's `etc/`, sorted by path relative to it
+fn keg_configs(keg_path: &Path) -> Result<Vec<(PathBuf, PathBuf)>, Error> {
+    let etc = keg_path.join("etc");
+    if !etc.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut configs = Vec::new();
+    for entry in WalkDir::new(&etc).sort_by_file_name() {
+        let entry = entry.map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read {}: {e}", etc.display()),
+        })?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry
+            .path()
+            .strip_prefix(&etc)
+            .unwrap_or(entry.path())
+            .to_path_buf();
+        configs.push((rel, entry.into_path()));
+    }
+    Ok(configs)
+}
+
+fn file_sha256(path: &Path) -> Result<String, Error> {
+    let data = fs::read(path).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to read {}: {e}", path.display()),
+    })?;
+    Ok(format!("{:x}", Sha256::digest(&data)))
+}
+
+/// Copy `src` to `dst` through a temporary file, so a config is never seen
+/// half-written and keg files hard-linked into the store are not shared.
+/// The copy is owner-writable even though the store's files are read-only.
+fn copy_into_place(src: &Path, dst: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let parent = dst.parent().unwrap_or(Path::new("."));
+    let file_name = dst.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = parent.join(format!(".{file_name}.tmp"));
+
+    fs::create_dir_all(parent)
+        .and_then(|_| fs::copy(src, &tmp))
+        .and_then(|_| {
+            let mode = fs::metadata(&tmp)?.permissions().mode();
+            fs::set_permissions(&tmp, fs::Permissions::from_mode(mode | 0o200))
+        })
+        .and_then(|_| fs::rename(&tmp, dst))
+        .map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            Error::StoreCorruption {
+                message: format!("failed to install config {}: {e}", dst.display()),
+            }
+        })
+}
+
+fn with_default_suffix(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(DEFAULT_SUFFIX);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn keg_with_config(root: &Path, version: &str, body: &str) -> PathBuf {
+        let keg = root.join("Cellar/redis").join(version);
+        fs::create_dir_all(keg.join("etc/redis")).unwrap();
+        fs::write(keg.join("etc/redis.conf"), body).unwrap();
+        // Read-only, like files shared with the store
+        fs::set_permissions(
+            keg.join("etc/redis.conf"),
+            fs::Permissions::from_mode(0o444),
+        )
+        .unwrap();
+        fs::write(keg.join("etc/redis/sentinel.conf"), "sentinel").unwrap();
+        keg
+    }
+
+    fn recorded(files: &[ConfigFile]) -> BTreeMap<PathBuf, String> {
+        files
+            .iter()
+            .map(|f| (f.path.clone(), f.sha256.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn installs_missing_configs_as_copies() {
+        let tmp = TempDir::new().unwrap();
+        let keg = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+        let etc = tmp.path().join("etc");
+
+        let (files, changes) = install_configs(&keg, &etc, &BTreeMap::new()).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, PathBuf::from("redis/sentinel.conf"));
+        assert!(changes.iter().all(|c| c.action == ConfigAction::Installed));
+        let conf = etc.join("redis.conf");
+        assert!(!fs::symlink_metadata(&conf).unwrap().is_symlink());
+        assert_ne!(fs::metadata(&conf).unwrap().permissions().mode() & 0o200, 0);
+        assert_eq!(fs::read_to_string(&conf).unwrap(), "port 6379\n");
+        assert!(etc.join("redis/sentinel.conf").is_file());
+    }
+
+    #[test]
+    fn upgrade_replaces_untouched_config() {
+        let tmp = TempDir::new().unwrap();
+        let etc = tmp.path().join("etc");
+        let old = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+        let (files, _) = install_configs(&old, &etc, &BTreeMap::new()).unwrap();
+
+        let new = keg_with_config(tmp.path(), "7.4", "port 6379\nprotected-mode yes\n");
+        let (_, changes) = install_configs(&new, &etc, &recorded(&files)).unwrap();
+
+        // `redis/sentinel.conf` sorts before `redis.conf`
+        assert_eq!(changes[0].action, ConfigAction::Unchanged);
+        assert_eq!(changes[1].action, ConfigAction::Updated);
+        assert_eq!(
+            fs::read_to_string(etc.join("redis.conf")).unwrap(),
+            "port 6379\nprotected-mode yes\n"
+        );
+        assert!(!etc.join("redis.conf.default").exists());
+    }
+
+    #[test]
+    fn upgrade_keeps_modified_config_and_saves_default() {
+        let tmp = TempDir::new().unwrap();
+        let etc = tmp.path().join("etc");
+        let old = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+        let (files, _) = install_configs(&old, &etc, &BTreeMap::new()).unwrap();
+        fs::write(etc.join("redis.conf"), "port 6380\n").unwrap();
+
+        let new = keg_with_config(tmp.path(), "7.4", "port 6379\nprotected-mode yes\n");
+        let (files, changes) = install_configs(&new, &etc, &recorded(&files)).unwrap();
+
+        let kept = changes
+            .iter()
+            .find(|c| c.path == etc.join("redis.conf"))
+            .unwrap();
+        assert_eq!(kept.action, ConfigAction::KeptModified);
+        assert_eq!(kept.default_path(), Some(etc.join("redis.conf.default")));
+        assert_eq!(
+            fs::read_to_string(etc.join("redis.conf")).unwrap(),
+            "port 6380\n"
+        );
+        assert_eq!(
+            fs::read_to_string(etc.join("redis.conf.default")).unwrap(),
+            "port 6379\nprotected-mode yes\n"
+        );
+
+        // Still modified relative to the newly recorded default
+        let (_, changes) = install_configs(&new, &etc, &recorded(&files)).unwrap();
+        assert_eq!(changes[1].action, ConfigAction::KeptModified);
+    }
+
+    #[test]
+    fn symlinked_and_unreadable_configs_are_kept() {
+        let tmp = TempDir::new().unwrap();
+        let etc = tmp.path().join("etc");
+        let old = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+        let (files, _) = install_configs(&old, &etc, &BTreeMap::new()).unwrap();
+
+        // An untouched default behind a symlink, a dangling symlink and a
+        // directory where the keg has a file
+        let dotfile = tmp.path().join("dotfiles/redis.conf");
+        fs::create_dir_all(dotfile.parent().unwrap()).unwrap();
+        fs::write(&dotfile, "port 6379\n").unwrap();
+        fs::remove_file(etc.join("redis.conf")).unwrap();
+        std::os::unix::fs::symlink(&dotfile, etc.join("redis.conf")).unwrap();
+        fs::remove_file(etc.join("redis/sentinel.conf")).unwrap();
+        std::os::unix::fs::symlink(tmp.path().join("gone"), etc.join("redis/sentinel.conf"))
+            .unwrap();
+
+        let new = keg_with_config(tmp.path(), "7.4", "port 6379\nprotected-mode yes\n");
+        let (_, changes) = install_configs(&new, &etc, &recorded(&files)).unwrap();
+
+        assert!(
+            changes
+                .iter()
+                .all(|c| c.action == ConfigAction::KeptModified)
+        );
+        assert!(
+            fs::symlink_metadata(etc.join("redis.conf"))
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_to_string(&dotfile).unwrap(), "port 6379\n");
+        assert_eq!(
+            fs::read_to_string(etc.join("redis.conf.default")).unwrap(),
+            "port 6379\nprotected-mode yes\n"
+        );
+        assert!(etc.join("redis/sentinel.conf.default").is_file());
+
+        let other = tmp.path().join("other");
+        fs::create_dir_all(other.join("redis.conf")).unwrap();
+        let (_, changes) = install_configs(&new, &other, &BTreeMap::new()).unwrap();
+        let kept = changes
+            .iter()
+            .find(|c| c.path == other.join("redis.conf"))
+            .unwrap();
+        assert_eq!(kept.action, ConfigAction::KeptModified);
+        assert!(other.join("redis.conf").is_dir());
+        assert!(other.join("redis.conf.default").is_file());
+    }
+
+    #[test]
+    fn config_without_recorded_default_is_treated_as_modified() {
+        let tmp = TempDir::new().unwrap();
+        let etc = tmp.path().join("etc");
+        fs::create_dir_all(&etc).unwrap();
+        fs::write(etc.join("redis.conf"), "mine\n").unwrap();
+        let keg = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+
+        let (_, changes) = install_configs(&keg, &etc, &BTreeMap::new()).unwrap();
+
+        assert_eq!(changes[1].action, ConfigAction::KeptModified);
+        assert_eq!(
+            fs::read_to_string(etc.join("redis.conf")).unwrap(),
+            "mine\n"
+        );
+    }
+
+    #[test]
+    fn modified_configs_lists_only_differing_files() {
+        let tmp = TempDir::new().unwrap();
+        let etc = tmp.path().join("etc");
+        let keg = keg_with_config(tmp.path(), "7.2", "port 6379\n");
+        install_configs(&keg, &etc, &BTreeMap::new()).unwrap();
+        assert!(modified_configs(&keg, &etc).unwrap().is_empty());
+
+        fs::write(etc.join("redis.conf"), "port 6380\n").unwrap();
+        fs::remove_file(etc.join("redis/sentinel.conf")).unwrap();
+
+        let diffs = modified_configs(&keg, &etc).unwrap();
+        assert_eq!(
+            diffs,
+            vec![ConfigDiff {
+                current: etc.join("redis.conf"),
+                default: keg.join("etc/redis.conf"),
+            }]
+        );
+    }
+
+    #[test]
+    fn keg_without_etc_has_no_configs() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("Cellar/jq/1.7");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+
+        let (files, changes) =
+            install_configs(&keg, &tmp.path().join("etc"), &BTreeMap::new()).unwrap();
+        assert!(files.is_empty() && changes.is_empty());
+        assert!(!tmp.path().join("etc").exists());
+    }
+}
@@ -0,0 +1,389 @@
+//! Basic auth credentials for private mirrors.
+//!
+//! Bottle mirrors and formula APIs behind basic auth get their credentials
+//! from `~/.netrc` (or the file named by `NETRC`), as curl and Homebrew read
+//! them, and otherwise from the system keychain: the macOS keychain's
+//! internet passwords, or on Linux the secret service items zerobrew stores
+//! under `service zerobrew`:
+//!
+//! ```text
+//! secret-tool store --label="mirror.example.com" service zerobrew host mirror.example.com user alice
+//! ```
+//!
+//! Credentials are scoped to the host they are stored for and are only sent
+//! to that host. A netrc `default` entry is ignored, so a login meant for one
+//! server is never sent to GitHub or another mirror.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+/// Path of the netrc file to read instead of `~/.netrc`
+pub const NETRC_ENV: &str = "NETRC";
+
+/// Service attribute of the secret service items zerobrew reads
+pub const SECRET_SERVICE: &str = "zerobrew";
+
+/// Public hosts that never take basic auth, so the keychain isn't searched
+/// for them
+const PUBLIC_HOSTS: &[&str] = &[
+    "ghcr.io",
+    "formulae.brew.sh",
+    "github.com",
+    "api.github.com",
+    "raw.githubusercontent.com",
+    "pkg-containers.githubusercontent.com",
+];
+
+/// A login for one host
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub login: String,
+    pub password: String,
+}
+
+// Keep passwords out of logs and error messages
+impl std::fmt::Debug for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credential")
+            .field("login", &self.login)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Where to look up credentials that aren't in the netrc file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Keychain {
+    /// The macOS keychain, through `security`
+    Security(PathBuf),
+    /// The secret service (GNOME Keyring, KWallet), through `secret-tool`
+    SecretTool(PathBuf),
+}
+
+impl Keychain {
+    /// The platform's keychain, if its command-line tool is available
+    pub fn from_platform() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            return Some(Keychain::Security(PathBuf::from("/usr/bin/security")));
+        }
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .map(|dir| dir.join("secret-tool"))
+            .find(|candidate| candidate.is_file())
+            .map(Keychain::SecretTool)
+    }
+
+    /// Look up the login stored for `host`. Missing items and a locked or
+    /// unavailable keychain both count as no login.
+    pub fn lookup(&self, host: &str) -> Option<Credential> {
+        match self {
+            Keychain::Security(program) => {
+                let attributes = Command::new(program)
+                    .args(["find-internet-password", "-s", host])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())?;
+                let login = parse_security_account(&String::from_utf8_lossy(&attributes.stdout))?;
+                let password = Command::new(program)
+                    .args(["find-internet-password", "-s", host, "-w"])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())?;
+                let password = String::from_utf8_lossy(&password.stdout)
+                    .trim_end_matches('\n')
+                    .to_string();
+                Some(Credential { login, password })
+            }
+            Keychain::SecretTool(program) => {
+                let output = Command::new(program)
+                    .args([
+                        "search",
+                        "--unlock",
+                        "service",
+                        SECRET_SERVICE,
+                        "host",
+                        host,
+                    ])
+                    .output()
+                    .ok()
+                    .filter(|output| output.status.success())?;
+                parse_secret_tool_item(&String::from_utf8_lossy(&output.stdout))
+            }
+        }
+    }
+}
+
+/// Credentials for the hosts listed in the netrc file or the keychain.
+/// Clones share keychain lookups, which are made at most once per host.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    netrc: Arc<HashMap<String, Credential>>,
+    keychain: Option<Keychain>,
+    looked_up: Arc<Mutex<HashMap<String, Option<Credential>>>>,
+}
+
+impl Credentials {
+    /// No credentials at all
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// The netrc file named by `NETRC` or in the home directory, and the
+    /// platform's keychain
+    pub fn from_env() -> Self {
+        let netrc_path = std::env::var_os(NETRC_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".netrc")));
+        let netrc = netrc_path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| parse_netrc(&contents))
+            .unwrap_or_default();
+        Self::none()
+            .with_netrc(netrc)
+            .with_keychain(Keychain::from_platform())
+    }
+
+    pub fn with_netrc(mut self, netrc: HashMap<String, Credential>) -> Self {
+        self.netrc = Arc::new(netrc);
+        self
+    }
+
+    pub fn with_keychain(mut self, keychain: Option<Keychain>) -> Self {
+        self.keychain = keychain;
+        self
+    }
+
+    /// The login stored for `url`'s host
+    pub fn for_url(&self, url: &str) -> Option<Credential> {
+        let url = reqwest::Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        if let Some(credential) = self.netrc.get(host) {
+            return Some(credential.clone());
+        }
+
+        let keychain = self.keychain.as_ref()?;
+        if PUBLIC_HOSTS.contains(&host) {
+            return None;
+        }
+        let mut looked_up = self.looked_up.lock().unwrap_or_else(|e| e.into_inner());
+        looked_up
+            .entry(host.to_string())
+            .or_insert_with(|| keychain.lookup(host))
+            .clone()
+    }
+
+    /// Add basic auth to `request` if there is a login for `url`'s host
+    pub fn authorize(
+        &self,
+        url: &str,
+        request: reqwest::RequestBuilder,
+    ) -> reqwest::RequestBuilder {
+        match self.for_url(url) {
+            Some(credential) => request.basic_auth(credential.login, Some(credential.password)),
+            None => request,
+        }
+    }
+}
+
+/// Parse a netrc file into the login for each `machine`. Entries without a
+/// login or password, `default` entries and `macdef` macros are skipped.
+pub fn parse_netrc(contents: &str) -> HashMap<String, Credential> {
+    let mut machines = HashMap::new();
+    let mut machine: Option<String> = None;
+    let mut login: Option<String> = None;
+    let mut password: Option<String> = None;
+
+    let mut finish = |machine: &mut Option<String>,
+                      login: &mut Option<String>,
+                      password: &mut Option<String>| {
+        if let (Some(machine), Some(login), Some(password)) =
+            (machine.take(), login.take(), password.take())
+        {
+            machines
+                .entry(machine)
+                .or_insert(Credential { login, password });
+        }
+        *login = None;
+        *password = None;
+    };
+
+    let mut in_macdef = false;
+    for line in contents.lines() {
+        // A macro's body runs to the next blank line
+        if in_macdef {
+            in_macdef = !line.trim().is_empty();
+            continue;
+        }
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "machine" => {
+                    finish(&mut machine, &mut login, &mut password);
+                    machine = tokens.next().map(str::to_string);
+                }
+                "default" => {
+                    finish(&mut machine, &mut login, &mut password);
+                }
+                "login" => login = tokens.next().map(str::to_string),
+                "password" => password = tokens.next().map(str::to_string),
+                "account" => {
+                    tokens.next();
+                }
+                "macdef" => {
+                    finish(&mut machine, &mut login, &mut password);
+                    in_macdef = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+    finish(&mut machine, &mut login, &mut password);
+
+    machines
+}
+
+/// The account of the item `security find-internet-password` describes
+fn parse_security_account(attributes: &str) -> Option<String> {
+    attributes.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("\"acct\"<blob>=")?;
+        let value = value.strip_prefix('"')?.strip_suffix('"')?;
+        Some(value.to_string())
+    })
+}
+
+/// The login of the first item `secret-tool search` prints
+fn parse_secret_tool_item(output: &str) -> Option<Credential> {
+    let mut login = None;
+    let mut password = None;
+    for line in output.lines() {
+        // A second item starts with its object path
+        if line.starts_with('[') && (login.is_some() || password.is_some()) {
+            break;
+        }
+        match line.split_once(" = ") {
+            Some(("attribute.user", value)) => login = Some(value.to_string()),
+            Some(("secret", value)) => password = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Some(Credential {
+        login: login?,
+        password: password?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn credential(login: &str, password: &str) -> Credential {
+        Credential {
+            login: login.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_netrc_entries() {
+        let netrc = parse_netrc(
+            "# mirrors\n\
+             machine mirror.example.com login alice password s3cret\n\
+             machine api.example.com\n  login bob\n  account ops\n  password hunter2\n\
+             macdef init\n  machine evil.example.com login x password y\n\n\
+             machine incomplete.example.com login carol\n\
+             default login anonymous password guest\n",
+        );
+
+        assert_eq!(netrc.len(), 2);
+        assert_eq!(netrc["mirror.example.com"], credential("alice", "s3cret"));
+        assert_eq!(netrc["api.example.com"], credential("bob", "hunter2"));
+    }
+
+    #[test]
+    fn credentials_are_scoped_to_their_host() {
+        let credentials = Credentials::none().with_netrc(parse_netrc(
+            "machine mirror.example.com login alice password s3cret",
+        ));
+
+        assert_eq!(
+            credentials.for_url("https://mirror.example.com:8443/bottles/jq.tar.gz"),
+            Some(credential("alice", "s3cret"))
+        );
+        assert_eq!(
+            credentials.for_url("https://ghcr.io/v2/homebrew/core/jq"),
+            None
+        );
+        assert_eq!(
+            credentials.for_url("https://example.com/mirror.example.com/jq"),
+            None
+        );
+    }
+
+    #[test]
+    fn keychain_is_searched_once_per_private_host() {
+        let tmp = TempDir::new().unwrap();
+        let calls = tmp.path().join("calls");
+        let program = tmp.path().join("secret-tool");
+        fs::write(
+            &program,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {}\n\
+                 echo '[/org/freedesktop/secrets/collection/login/1]'\n\
+                 echo 'label = mirror'\n\
+                 echo 'secret = s3cret'\n\
+                 echo 'attribute.user = alice'\n",
+                calls.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        let credentials = Credentials::none().with_keychain(Some(Keychain::SecretTool(program)));
+
+        for _ in 0..2 {
+            assert_eq!(
+                credentials.for_url("https://mirror.example.com/jq.tar.gz"),
+                Some(credential("alice", "s3cret"))
+            );
+        }
+        assert_eq!(
+            credentials.for_url("https://ghcr.io/v2/homebrew/core/jq"),
+            None
+        );
+
+        assert_eq!(
+            fs::read_to_string(calls).unwrap(),
+            "search --unlock service zerobrew host mirror.example.com\n"
+        );
+    }
+
+    #[test]
+    fn parses_keychain_output() {
+        assert_eq!(
+            parse_security_account(
+                "keychain: \"/Users/me/Library/Keychains/login.keychain-db\"\n\
+                 attributes:\n    \"acct\"<blob>=\"alice\"\n    \"srvr\"<blob>=\"mirror.example.com\"\n"
+            ),
+            Some("alice".to_string())
+        );
+        assert_eq!(parse_security_account("attributes:\n"), None);
+
+        assert_eq!(
+            parse_secret_tool_item(
+                "[/1]\nsecret = first\nattribute.user = alice\n[/2]\nsecret = second\nattribute.user = bob\n"
+            ),
+            Some(credential("alice", "first"))
+        );
+        assert_eq!(parse_secret_tool_item("[/1]\nsecret = orphan\n"), None);
+    }
+}
@@ -1,9 +1,17 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 use rusqlite::{Connection, Transaction, params};
+use serde::Serialize;
 
 use zb_core::Error;
 
+use crate::attestation::{Attestation, AttestationStatus};
+use crate::config::ConfigFile;
+use crate::receipt::InstallReceipt;
+use crate::timing::{InstallTimings, duration_to_ms};
+
 pub struct Database {
     conn: Connection,
 }
@@ -17,6 +25,337 @@ pub struct InstalledKeg {
     pub pinned: bool,
     /// Whether this package was explicitly installed by the user (true) or as a dependency (false)
     pub explicit: bool,
+    /// Whether the active keg is linked into the prefix
+    pub linked: bool,
+    /// Whether the formula is keg-only (not linked by default)
+    pub keg_only: bool,
+    /// What requested the install; `None` for kegs installed before this was tracked
+    pub provenance: Option<Provenance>,
+    /// Upstream commit a HEAD build was made from
+    pub head_commit: Option<String>,
+    /// How the keg was produced; `None` for kegs installed before this was tracked
+    pub receipt: Option<InstallReceipt>,
+}
+
+/// What requested a keg's install
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Provenance {
+    /// Requested on the command line
+    Cli,
+    /// Listed in a Brewfile installed by `zb bundle install`
+    Brewfile { path: String, run_id: String },
+    /// Pulled in as a dependency of the requested formula
+    DependencyOf { name: String },
+}
+
+impl Provenance {
+    /// Short name of the variant, as accepted by [`Provenance::matches`]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Provenance::Cli => "cli",
+            Provenance::Brewfile { .. } => "brewfile",
+            Provenance::DependencyOf { .. } => "dependency_of",
+        }
+    }
+
+    /// Match a `kind` or `kind:value` filter.
+    ///
+    /// The value is compared with the Brewfile path or bundle run ID for
+    /// `brewfile`, and with the dependent formula for `dependency_of`.
+    pub fn matches(&self, filter: &str) -> bool {
+        let (kind, value) = match filter.split_once(':') {
+            Some((kind, value)) => (kind, Some(value)),
+            None => (filter, None),
+        };
+        if kind != self.kind() {
+            return false;
+        }
+        match (self, value) {
+            (_, None) => true,
+            (Provenance::Cli, Some(_)) => false,
+            (Provenance::Brewfile { path, run_id }, Some(value)) => {
+                value == path || value == run_id
+            }
+            (Provenance::DependencyOf { name }, Some(value)) => value == name,
+        }
+    }
+
+    fn to_db(&self) -> String {
+        match self {
+            Provenance::Cli => "cli".to_string(),
+            Provenance::Brewfile { path, run_id } => format!("brewfile\t{run_id}\t{path}"),
+            Provenance::DependencyOf { name } => format!("dependency_of\t{name}"),
+        }
+    }
+
+    fn from_db(value: &str) -> Option<Self> {
+        let mut fields = value.splitn(3, '\t');
+        match (fields.next()?, fields.next(), fields.next()) {
+            ("cli", None, None) => Some(Provenance::Cli),
+            ("brewfile", Some(run_id), Some(path)) => Some(Provenance::Brewfile {
+                path: path.to_string(),
+                run_id: run_id.to_string(),
+            }),
+            ("dependency_of", Some(name), None) => Some(Provenance::DependencyOf {
+                name: name.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Provenance::Cli => write!(f, "command line"),
+            Provenance::Brewfile { path, run_id } => {
+                write!(f, "Brewfile {path} (bundle run {run_id})")
+            }
+            Provenance::DependencyOf { name } => write!(f, "dependency of {name}"),
+        }
+    }
+}
+
+/// How an installed keg is exposed in the prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    Linked,
+    Unlinked,
+    /// Keg-only formula that is not linked, which is expected
+    KegOnly,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Linked => "linked",
+            LinkStatus::Unlinked => "unlinked",
+            LinkStatus::KegOnly => "keg-only",
+        }
+    }
+}
+
+impl InstalledKeg {
+    /// Link status according to the database
+    pub fn link_status(&self) -> LinkStatus {
+        if self.linked {
+            LinkStatus::Linked
+        } else if self.keg_only {
+            LinkStatus::KegOnly
+        } else {
+            LinkStatus::Unlinked
+        }
+    }
+
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(InstalledKeg {
+            name: row.get(0)?,
+            version: row.get(1)?,
+            store_key: row.get(2)?,
+            installed_at: row.get(3)?,
+            pinned: row.get::<_, i64>(4)? != 0,
+            explicit: row.get::<_, i64>(5)? != 0,
+            linked: row.get::<_, i64>(6)? != 0,
+            keg_only: row.get::<_, i64>(7)? != 0,
+            provenance: row
+                .get::<_, Option<String>>(8)?
+                .as_deref()
+                .and_then(Provenance::from_db),
+            head_commit: row.get(9)?,
+            receipt: row
+                .get::<_, Option<String>>(10)?
+                .as_deref()
+                .and_then(InstallReceipt::from_db),
+        })
+    }
+}
+
+/// Columns read by [`InstalledKeg::from_row`]
+const KEG_COLUMNS: &str = "name, version, store_key, installed_at, pinned, explicit, linked, keg_only, provenance, head_commit, receipt";
+
+/// Order of [`Database::list_inventory`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InventorySort {
+    /// Alphabetically
+    #[default]
+    Name,
+    /// Most recently installed first
+    Date,
+    /// Largest first, kegs of unknown size last
+    Size,
+}
+
+/// A version of a formula present in the Cellar.
+/// The active version is the one recorded in `installed_kegs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KegVersion {
+    pub name: String,
+    pub version: String,
+    pub store_key: String,
+    pub installed_at: i64,
+}
+
+/// A file moved aside by `zb link --overwrite`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkBackup {
+    pub id: i64,
+    /// Formula whose link displaced the file
+    pub name: String,
+    pub link_path: String,
+    pub backup_path: String,
+    pub created_at: i64,
+}
+
+/// Aggregated install statistics from the timing history
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstallStats {
+    /// Number of recorded install runs
+    pub runs: u64,
+    /// Number of packages installed across all runs
+    pub packages: u64,
+    /// Average wall clock time per run (resolve + execute), in milliseconds
+    pub avg_run_ms: f64,
+    /// Average dependency resolution time per run, in milliseconds
+    pub avg_resolve_ms: f64,
+    /// Average per-package download time, in milliseconds
+    pub avg_download_ms: f64,
+    /// Average per-package extract time, in milliseconds
+    pub avg_extract_ms: f64,
+    /// Average per-package link time, in milliseconds
+    pub avg_link_ms: f64,
+    /// Packages whose bottle was served from the blob cache
+    pub blob_cache_hits: u64,
+    /// Packages whose store entry already existed
+    pub store_reuses: u64,
+    /// Bytes transferred over the network
+    pub bytes_downloaded: u64,
+    /// Bytes not transferred thanks to the blob cache
+    pub bytes_saved: u64,
+}
+
+impl InstallStats {
+    /// Fraction of packages served from the blob cache (0.0 - 1.0)
+    pub fn cache_hit_rate(&self) -> f64 {
+        if self.packages == 0 {
+            0.0
+        } else {
+            self.blob_cache_hits as f64 / self.packages as f64
+        }
+    }
+}
+
+/// Packages installed and upgraded in one calendar month (UTC)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MonthlyActivity {
+    /// Month as `YYYY-MM`
+    pub month: String,
+    /// Packages newly installed or reinstalled
+    pub installs: u64,
+    /// Packages that replaced an older installed version
+    pub upgrades: u64,
+}
+
+/// What happened to a bottle, as recorded in the pour log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PourEventKind {
+    /// Installed into the Cellar
+    Poured,
+    /// The download didn't match the bottle's checksum
+    ChecksumMismatch,
+    /// The downloaded bottle couldn't be unpacked
+    CorruptArchive,
+    /// The keg didn't match the bottle it was poured from (`--verify`)
+    KegMismatch,
+    /// The bottle's build provenance attestation didn't verify (`--verify`)
+    AttestationFailed,
+}
+
+impl PourEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PourEventKind::Poured => "poured",
+            PourEventKind::ChecksumMismatch => "checksum_mismatch",
+            PourEventKind::CorruptArchive => "corrupt_archive",
+            PourEventKind::KegMismatch => "keg_mismatch",
+            PourEventKind::AttestationFailed => "attestation_failed",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "poured" => Some(PourEventKind::Poured),
+            "checksum_mismatch" => Some(PourEventKind::ChecksumMismatch),
+            "corrupt_archive" => Some(PourEventKind::CorruptArchive),
+            "keg_mismatch" => Some(PourEventKind::KegMismatch),
+            "attestation_failed" => Some(PourEventKind::AttestationFailed),
+            _ => None,
+        }
+    }
+
+    /// Whether the event records a bottle that failed an integrity check
+    pub fn is_integrity_failure(&self) -> bool {
+        *self != PourEventKind::Poured
+    }
+}
+
+impl fmt::Display for PourEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_str().replace('_', " "))
+    }
+}
+
+/// One entry of the append-only bottle pour log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PourEvent {
+    /// Unix timestamp, set when the event is recorded
+    pub at: i64,
+    pub kind: PourEventKind,
+    pub name: String,
+    pub version: String,
+    /// Where the bottle was downloaded from
+    pub url: String,
+    pub sha256: String,
+    /// Size of the bottle in bytes, 0 when it never arrived
+    pub bytes: u64,
+    /// Time spent unpacking, relocating and linking the keg, in milliseconds
+    pub duration_ms: i64,
+    /// True if the keg was created, and so relocated, by this pour rather
+    /// than already in the Cellar
+    pub relocated: bool,
+    /// What went wrong, for integrity failures
+    pub detail: Option<String>,
+}
+
+/// Totals over the pour log
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PourStats {
+    /// Bottles poured
+    pub poured: u64,
+    /// Pours that created and relocated a keg
+    pub relocated: u64,
+    /// Bottles that failed an integrity check
+    pub integrity_failures: u64,
+    /// Bytes of bottles poured
+    pub bytes: u64,
+}
+
+/// Averaged results of one `zb benchmark` invocation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchmarkRecord {
+    pub formula: String,
+    /// Unix timestamp when the benchmark ran
+    pub ran_at: i64,
+    pub iterations: u32,
+    /// Number of packages in the install plan
+    pub packages: u64,
+    pub total_ms: i64,
+    pub resolve_ms: i64,
+    /// Time until the last bottle finished downloading
+    pub download_ms: i64,
+    pub extract_ms: i64,
+    pub link_ms: i64,
 }
 
 /// Information about an installed tap
@@ -28,6 +367,8 @@ pub struct InstalledTap {
     pub url: String,
     /// Unix timestamp when the tap was added
     pub added_at: i64,
+    /// Formulas in a pinned tap take precedence over homebrew/core
+    pub pinned: bool,
 }
 
 impl Database {
@@ -46,6 +387,13 @@ impl Database {
             message: format!("failed to configure database pragmas: {e}"),
         })?;
 
+        // Concurrent zb processes share the database; wait for their writes
+        // instead of failing with SQLITE_BUSY
+        conn.busy_timeout(std::time::Duration::from_secs(30))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to set database busy timeout: {e}"),
+            })?;
+
         Self::init_schema(&conn)?;
 
         Ok(Self { conn })
@@ -76,7 +424,27 @@ impl Database {
                 store_key TEXT NOT NULL,
                 installed_at INTEGER NOT NULL,
                 pinned INTEGER NOT NULL DEFAULT 0,
-                explicit INTEGER NOT NULL DEFAULT 1
+                explicit INTEGER NOT NULL DEFAULT 1,
+                linked INTEGER NOT NULL DEFAULT 1,
+                keg_only INTEGER NOT NULL DEFAULT 0,
+                deps_recorded INTEGER NOT NULL DEFAULT 0,
+                provenance TEXT,
+                head_commit TEXT,
+                receipt TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_dependencies (
+                name TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+                PRIMARY KEY (name, dependency)
+            );
+
+            CREATE TABLE IF NOT EXISTS keg_versions (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                store_key TEXT NOT NULL,
+                installed_at INTEGER NOT NULL,
+                PRIMARY KEY (name, version)
             );
 
             CREATE TABLE IF NOT EXISTS store_refs (
@@ -92,10 +460,48 @@ impl Database {
                 PRIMARY KEY (name, linked_path)
             );
 
+            CREATE TABLE IF NOT EXISTS link_backups (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                link_path TEXT NOT NULL,
+                backup_path TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS config_files (
+                name TEXT NOT NULL,
+                path TEXT NOT NULL,
+                sha256 TEXT NOT NULL,
+                PRIMARY KEY (name, path)
+            );
+
+            CREATE TABLE IF NOT EXISTS keep_list (
+                name TEXT PRIMARY KEY,
+                added_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS post_install_skips (
+                name TEXT PRIMARY KEY,
+                added_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS upgrade_windows (
+                spec TEXT PRIMARY KEY,
+                added_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS link_env (
+                name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (name, key)
+            );
+
             CREATE TABLE IF NOT EXISTS taps (
                 name TEXT PRIMARY KEY,
                 url TEXT NOT NULL,
-                added_at INTEGER NOT NULL
+                added_at INTEGER NOT NULL,
+                pinned INTEGER NOT NULL DEFAULT 0
             );
 
             CREATE TABLE IF NOT EXISTS services (
@@ -107,6 +513,68 @@ impl Database {
                 config TEXT,
                 FOREIGN KEY (formula) REFERENCES installed_kegs(name) ON DELETE CASCADE
             );
+
+            CREATE TABLE IF NOT EXISTS install_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                root_name TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                resolve_ms INTEGER NOT NULL,
+                execute_ms INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS install_timings (
+                run_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                download_ms INTEGER NOT NULL,
+                extract_ms INTEGER NOT NULL,
+                link_ms INTEGER NOT NULL,
+                bytes INTEGER NOT NULL,
+                blob_cached INTEGER NOT NULL,
+                store_reused INTEGER NOT NULL,
+                upgraded INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY (run_id) REFERENCES install_runs(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS pour_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                at INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                url TEXT NOT NULL,
+                sha256 TEXT NOT NULL,
+                bytes INTEGER NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                relocated INTEGER NOT NULL,
+                detail TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS attestations (
+                store_key TEXT PRIMARY KEY,
+                status TEXT NOT NULL,
+                repo TEXT,
+                detail TEXT,
+                checked_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS store_sizes (
+                store_key TEXT PRIMARY KEY,
+                bytes INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS benchmark_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                formula TEXT NOT NULL,
+                ran_at INTEGER NOT NULL,
+                iterations INTEGER NOT NULL,
+                packages INTEGER NOT NULL,
+                total_ms INTEGER NOT NULL,
+                resolve_ms INTEGER NOT NULL,
+                download_ms INTEGER NOT NULL,
+                extract_ms INTEGER NOT NULL,
+                link_ms INTEGER NOT NULL
+            );
             ",
         )
         .map_err(|e| Error::StoreCorruption {
@@ -122,6 +590,30 @@ impl Database {
         // Migration: create services table if it doesn't exist (for existing databases)
         Self::migrate_add_services_table(conn)?;
 
+        // Migration: add link state columns if they don't exist (for existing databases)
+        Self::migrate_add_link_state_columns(conn)?;
+
+        // Migration: add deps_recorded column if it doesn't exist (for existing databases)
+        Self::migrate_add_deps_recorded_column(conn)?;
+
+        // Migration: add provenance column if it doesn't exist (for existing databases)
+        Self::migrate_add_provenance_column(conn)?;
+
+        // Migration: add head_commit column if it doesn't exist (for existing databases)
+        Self::migrate_add_head_commit_column(conn)?;
+
+        // Migration: add receipt column if it doesn't exist (for existing databases)
+        Self::migrate_add_receipt_column(conn)?;
+
+        // Migration: add upgraded column to install timings (for existing databases)
+        Self::migrate_add_upgraded_column(conn)?;
+
+        // Migration: add pinned column to taps (for existing databases)
+        Self::migrate_add_tap_pinned_column(conn)?;
+
+        // Migration: track active versions of existing databases in keg_versions
+        Self::migrate_backfill_keg_versions(conn)?;
+
         Ok(())
     }
 
@@ -203,6 +695,172 @@ impl Database {
         Ok(())
     }
 
+    fn migrate_add_link_state_columns(conn: &Connection) -> Result<(), Error> {
+        // Existing installs were linked unless --no-link was used, so default to linked
+        for (column, default) in [("linked", 1), ("keg_only", 0)] {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM pragma_table_info('installed_kegs') WHERE name = ?1",
+                    params![column],
+                    |row| row.get(0),
+                )
+                .unwrap_or(false);
+
+            if !exists {
+                conn.execute(
+                    &format!(
+                        "ALTER TABLE installed_kegs ADD COLUMN {column} INTEGER NOT NULL DEFAULT {default}"
+                    ),
+                    [],
+                )
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to add {column} column: {e}"),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_deps_recorded_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('installed_kegs') WHERE name = 'deps_recorded'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            // Kegs installed before dependency edges were tracked have none recorded
+            conn.execute(
+                "ALTER TABLE installed_kegs ADD COLUMN deps_recorded INTEGER NOT NULL DEFAULT 0",
+                [],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to add deps_recorded column: {e}"),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_provenance_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('installed_kegs') WHERE name = 'provenance'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute("ALTER TABLE installed_kegs ADD COLUMN provenance TEXT", [])
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to add provenance column: {e}"),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_head_commit_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('installed_kegs') WHERE name = 'head_commit'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute("ALTER TABLE installed_kegs ADD COLUMN head_commit TEXT", [])
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to add head_commit column: {e}"),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_receipt_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('installed_kegs') WHERE name = 'receipt'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute("ALTER TABLE installed_kegs ADD COLUMN receipt TEXT", [])
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to add receipt column: {e}"),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_upgraded_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('install_timings') WHERE name = 'upgraded'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute(
+                "ALTER TABLE install_timings ADD COLUMN upgraded INTEGER NOT NULL DEFAULT 0",
+                [],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to add upgraded column: {e}"),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_add_tap_pinned_column(conn: &Connection) -> Result<(), Error> {
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('taps') WHERE name = 'pinned'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
+
+        if !has_column {
+            conn.execute(
+                "ALTER TABLE taps ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0",
+                [],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to add tap pinned column: {e}"),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn migrate_backfill_keg_versions(conn: &Connection) -> Result<(), Error> {
+        // Every active version must also be listed as a keg version. The store ref
+        // it already holds carries over, so refcounts are left untouched.
+        conn.execute(
+            "INSERT OR IGNORE INTO keg_versions (name, version, store_key, installed_at)
+             SELECT name, version, store_key, installed_at FROM installed_kegs",
+            [],
+        )
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to backfill keg versions: {e}"),
+        })?;
+
+        Ok(())
+    }
+
     pub fn transaction(&mut self) -> Result<InstallTransaction<'_>, Error> {
         let tx = self
             .conn
@@ -217,18 +875,9 @@ impl Database {
     pub fn get_installed(&self, name: &str) -> Option<InstalledKeg> {
         self.conn
             .query_row(
-                "SELECT name, version, store_key, installed_at, pinned, explicit FROM installed_kegs WHERE name = ?1",
+                &format!("SELECT {KEG_COLUMNS} FROM installed_kegs WHERE name = ?1"),
                 params![name],
-                |row| {
-                    Ok(InstalledKeg {
-                        name: row.get(0)?,
-                        version: row.get(1)?,
-                        store_key: row.get(2)?,
-                        installed_at: row.get(3)?,
-                        pinned: row.get::<_, i64>(4)? != 0,
-                        explicit: row.get::<_, i64>(5)? != 0,
-                    })
-                },
+                InstalledKeg::from_row,
             )
             .ok()
     }
@@ -236,24 +885,15 @@ impl Database {
     pub fn list_installed(&self) -> Result<Vec<InstalledKeg>, Error> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT name, version, store_key, installed_at, pinned, explicit FROM installed_kegs ORDER BY name",
-            )
+            .prepare_cached(&format!(
+                "SELECT {KEG_COLUMNS} FROM installed_kegs ORDER BY name"
+            ))
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to prepare statement: {e}"),
             })?;
 
         let kegs = stmt
-            .query_map([], |row| {
-                Ok(InstalledKeg {
-                    name: row.get(0)?,
-                    version: row.get(1)?,
-                    store_key: row.get(2)?,
-                    installed_at: row.get(3)?,
-                    pinned: row.get::<_, i64>(4)? != 0,
-                    explicit: row.get::<_, i64>(5)? != 0,
-                })
-            })
+            .query_map([], InstalledKeg::from_row)
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to query installed kegs: {e}"),
             })?
@@ -269,24 +909,15 @@ impl Database {
     pub fn list_pinned(&self) -> Result<Vec<InstalledKeg>, Error> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT name, version, store_key, installed_at, pinned, explicit FROM installed_kegs WHERE pinned = 1 ORDER BY name",
-            )
+            .prepare_cached(&format!(
+                "SELECT {KEG_COLUMNS} FROM installed_kegs WHERE pinned = 1 ORDER BY name"
+            ))
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to prepare statement: {e}"),
             })?;
 
         let kegs = stmt
-            .query_map([], |row| {
-                Ok(InstalledKeg {
-                    name: row.get(0)?,
-                    version: row.get(1)?,
-                    store_key: row.get(2)?,
-                    installed_at: row.get(3)?,
-                    pinned: row.get::<_, i64>(4)? != 0,
-                    explicit: row.get::<_, i64>(5)? != 0,
-                })
-            })
+            .query_map([], InstalledKeg::from_row)
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to query pinned kegs: {e}"),
             })?
@@ -302,24 +933,15 @@ impl Database {
     pub fn list_dependencies(&self) -> Result<Vec<InstalledKeg>, Error> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT name, version, store_key, installed_at, pinned, explicit FROM installed_kegs WHERE explicit = 0 ORDER BY name",
-            )
+            .prepare_cached(&format!(
+                "SELECT {KEG_COLUMNS} FROM installed_kegs WHERE explicit = 0 ORDER BY name"
+            ))
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to prepare statement: {e}"),
             })?;
 
         let kegs = stmt
-            .query_map([], |row| {
-                Ok(InstalledKeg {
-                    name: row.get(0)?,
-                    version: row.get(1)?,
-                    store_key: row.get(2)?,
-                    installed_at: row.get(3)?,
-                    pinned: row.get::<_, i64>(4)? != 0,
-                    explicit: row.get::<_, i64>(5)? != 0,
-                })
-            })
+            .query_map([], InstalledKeg::from_row)
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to query dependency kegs: {e}"),
             })?
@@ -415,6 +1037,101 @@ impl Database {
             .unwrap_or(false)
     }
 
+    // ========== Keg Versions ==========
+
+    /// List every version of a formula present in the Cellar, oldest first
+    pub fn list_versions(&self, name: &str) -> Result<Vec<KegVersion>, Error> {
+        self.query_versions(
+            "SELECT name, version, store_key, installed_at FROM keg_versions
+             WHERE name = ?1 ORDER BY installed_at, version",
+            params![name],
+        )
+    }
+
+    /// Recorded dependencies of every installed package whose edges were recorded.
+    ///
+    /// Packages installed before dependency edges were tracked are absent from
+    /// the map; recorded packages without dependencies map to an empty list.
+    pub fn recorded_dependencies(&self) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT k.name, d.dependency FROM installed_kegs k
+                 LEFT JOIN keg_dependencies d ON d.name = k.name
+                 WHERE k.deps_recorded = 1 ORDER BY k.name, d.dependency",
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query dependencies: {e}"),
+            })?;
+
+        let mut graph: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, dependency) in rows {
+            let deps = graph.entry(name).or_default();
+            deps.extend(dependency);
+        }
+        Ok(graph)
+    }
+
+    /// List every installed version of every formula
+    pub fn list_all_versions(&self) -> Result<Vec<KegVersion>, Error> {
+        self.query_versions(
+            "SELECT name, version, store_key, installed_at FROM keg_versions
+             ORDER BY name, installed_at, version",
+            [],
+        )
+    }
+
+    /// List versions that are installed but not active, e.g. kegs kept after an upgrade
+    pub fn list_inactive_versions(&self) -> Result<Vec<KegVersion>, Error> {
+        self.query_versions(
+            "SELECT v.name, v.version, v.store_key, v.installed_at FROM keg_versions v
+             JOIN installed_kegs k ON k.name = v.name
+             WHERE v.version != k.version ORDER BY v.name, v.installed_at, v.version",
+            [],
+        )
+    }
+
+    fn query_versions(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<KegVersion>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(sql)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let versions = stmt
+            .query_map(params, |row| {
+                Ok(KegVersion {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    store_key: row.get(2)?,
+                    installed_at: row.get(3)?,
+                })
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query keg versions: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+
+        Ok(versions)
+    }
+
     pub fn get_store_refcount(&self, store_key: &str) -> i64 {
         self.conn
             .query_row(
@@ -482,6 +1199,21 @@ impl Database {
         Ok(rows_affected)
     }
 
+    /// Record whether a package's keg is linked into the prefix
+    pub fn set_linked(&self, name: &str, linked: bool) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute(
+                "UPDATE installed_kegs SET linked = ?1 WHERE name = ?2",
+                params![linked as i64, name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to update link state: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
     /// Record a linked file for a package (non-transactional version)
     pub fn record_linked_file(
         &self,
@@ -503,10 +1235,15 @@ impl Database {
         Ok(())
     }
 
-    // ========== Tap Operations ==========
+    // ========== Link Backups ==========
 
-    /// Add a tap to the database
-    pub fn add_tap(&self, name: &str, url: &str) -> Result<(), Error> {
+    /// Record a file displaced while linking `name`
+    pub fn record_link_backup(
+        &self,
+        name: &str,
+        link_path: &str,
+        backup_path: &str,
+    ) -> Result<(), Error> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -514,1452 +1251,3425 @@ impl Database {
 
         self.conn
             .execute(
-                "INSERT OR REPLACE INTO taps (name, url, added_at) VALUES (?1, ?2, ?3)",
-                params![name, url, now],
+                "INSERT INTO link_backups (name, link_path, backup_path, created_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, link_path, backup_path, now],
             )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to add tap: {e}"),
+                message: format!("failed to record link backup: {e}"),
             })?;
 
         Ok(())
     }
 
-    /// Remove a tap from the database
-    pub fn remove_tap(&self, name: &str) -> Result<bool, Error> {
-        let rows_affected = self
+    /// Default configs installed into `etc/` for `name`, by path
+    ///
+    /// These outlive uninstalls, like the configs themselves, so a reinstall
+    /// can still tell whether a config was edited.
+    pub fn config_files(&self, name: &str) -> Result<Vec<ConfigFile>, Error> {
+        let mut stmt = self
             .conn
-            .execute("DELETE FROM taps WHERE name = ?1", params![name])
+            .prepare_cached("SELECT path, sha256 FROM config_files WHERE name = ?1 ORDER BY path")
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove tap: {e}"),
+                message: format!("failed to prepare statement: {e}"),
             })?;
 
-        Ok(rows_affected > 0)
-    }
-
-    /// Check if a tap is installed
-    pub fn is_tapped(&self, name: &str) -> bool {
-        self.conn
-            .query_row("SELECT 1 FROM taps WHERE name = ?1", params![name], |_| {
-                Ok(())
+        let files = stmt
+            .query_map(params![name], |row| {
+                Ok(ConfigFile {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    sha256: row.get(1)?,
+                })
             })
-            .is_ok()
-    }
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query config files: {e}"),
+            })?;
 
-    /// Get information about a specific tap
-    pub fn get_tap(&self, name: &str) -> Option<InstalledTap> {
-        self.conn
-            .query_row(
-                "SELECT name, url, added_at FROM taps WHERE name = ?1",
-                params![name],
-                |row| {
-                    Ok(InstalledTap {
-                        name: row.get(0)?,
-                        url: row.get(1)?,
-                        added_at: row.get(2)?,
-                    })
-                },
-            )
-            .ok()
+        Ok(files)
     }
 
-    /// List all installed taps
-    pub fn list_taps(&self) -> Result<Vec<InstalledTap>, Error> {
+    /// List files displaced while linking `name`, most recent first
+    pub fn list_link_backups(&self, name: &str) -> Result<Vec<LinkBackup>, Error> {
         let mut stmt = self
             .conn
-            .prepare_cached("SELECT name, url, added_at FROM taps ORDER BY name")
+            .prepare_cached(
+                "SELECT id, name, link_path, backup_path, created_at FROM link_backups
+                 WHERE name = ?1 ORDER BY id DESC",
+            )
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to prepare statement: {e}"),
             })?;
 
-        let taps = stmt
-            .query_map([], |row| {
-                Ok(InstalledTap {
-                    name: row.get(0)?,
-                    url: row.get(1)?,
-                    added_at: row.get(2)?,
+        let backups = stmt
+            .query_map(params![name], |row| {
+                Ok(LinkBackup {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    link_path: row.get(2)?,
+                    backup_path: row.get(3)?,
+                    created_at: row.get(4)?,
                 })
             })
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to query taps: {e}"),
+                message: format!("failed to query link backups: {e}"),
             })?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to collect results: {e}"),
             })?;
 
-        Ok(taps)
+        Ok(backups)
     }
 
-    // ========== Service Operations ==========
-
-    /// Record a service for a formula
-    pub fn record_service(
-        &self,
-        name: &str,
-        formula: &str,
-        config: Option<&str>,
-    ) -> Result<(), Error> {
-        self.conn
-            .execute(
-                "INSERT OR REPLACE INTO services (name, formula, status, config)
-                 VALUES (?1, ?2, 'stopped', ?3)",
-                params![name, formula, config],
-            )
+    /// Forget a link backup once it has been restored
+    pub fn remove_link_backup(&self, id: i64) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM link_backups WHERE id = ?1", params![id])
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to record service: {e}"),
+                message: format!("failed to remove link backup: {e}"),
             })?;
 
-        Ok(())
+        Ok(rows_affected > 0)
     }
 
-    /// Update service status
-    pub fn update_service_status(
-        &self,
-        name: &str,
-        status: &str,
-        pid: Option<u32>,
-    ) -> Result<bool, Error> {
-        let started_at = if status == "running" {
-            Some(
-                std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as i64,
-            )
-        } else {
-            None
-        };
+    // ========== Keep List ==========
+
+    /// Add a package to the keep-list so autoremove never removes it.
+    /// Returns false if it was already kept.
+    pub fn add_keep(&self, name: &str) -> Result<bool, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
 
         let rows_affected = self
             .conn
             .execute(
-                "UPDATE services SET status = ?1, pid = ?2, started_at = ?3 WHERE name = ?4",
-                params![status, pid, started_at, name],
+                "INSERT OR IGNORE INTO keep_list (name, added_at) VALUES (?1, ?2)",
+                params![name, now],
             )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to update service status: {e}"),
+                message: format!("failed to keep package: {e}"),
             })?;
 
         Ok(rows_affected > 0)
     }
 
-    /// Get service by name
-    pub fn get_service(&self, name: &str) -> Option<ServiceRecord> {
-        self.conn
-            .query_row(
-                "SELECT name, formula, status, pid, started_at, config FROM services WHERE name = ?1",
-                params![name],
-                |row| {
-                    Ok(ServiceRecord {
-                        name: row.get(0)?,
-                        formula: row.get(1)?,
-                        status: row.get(2)?,
-                        pid: row.get(3)?,
-                        started_at: row.get(4)?,
-                        config: row.get(5)?,
-                    })
-                },
-            )
-            .ok()
-    }
+    /// Remove a package from the keep-list. Returns false if it wasn't kept.
+    pub fn remove_keep(&self, name: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM keep_list WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove package from keep-list: {e}"),
+            })?;
 
-    /// Get service for a formula
-    pub fn get_service_for_formula(&self, formula: &str) -> Option<ServiceRecord> {
-        self.conn
-            .query_row(
-                "SELECT name, formula, status, pid, started_at, config FROM services WHERE formula = ?1",
-                params![formula],
-                |row| {
-                    Ok(ServiceRecord {
-                        name: row.get(0)?,
-                        formula: row.get(1)?,
-                        status: row.get(2)?,
-                        pid: row.get(3)?,
-                        started_at: row.get(4)?,
-                        config: row.get(5)?,
-                    })
-                },
-            )
-            .ok()
+        Ok(rows_affected > 0)
     }
 
-    /// List all services
-    pub fn list_services(&self) -> Result<Vec<ServiceRecord>, Error> {
+    /// List kept packages, sorted by name
+    pub fn list_kept(&self) -> Result<Vec<String>, Error> {
         let mut stmt = self
             .conn
-            .prepare_cached(
-                "SELECT name, formula, status, pid, started_at, config FROM services ORDER BY name",
-            )
+            .prepare_cached("SELECT name FROM keep_list ORDER BY name")
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to prepare statement: {e}"),
             })?;
 
-        let services = stmt
-            .query_map([], |row| {
-                Ok(ServiceRecord {
-                    name: row.get(0)?,
-                    formula: row.get(1)?,
-                    status: row.get(2)?,
-                    pid: row.get(3)?,
-                    started_at: row.get(4)?,
-                    config: row.get(5)?,
-                })
-            })
+        let names = stmt
+            .query_map([], |row| row.get(0))
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to query services: {e}"),
+                message: format!("failed to query keep-list: {e}"),
             })?
             .collect::<Result<Vec<_>, _>>()
             .map_err(|e| Error::StoreCorruption {
                 message: format!("failed to collect results: {e}"),
             })?;
 
-        Ok(services)
+        Ok(names)
     }
 
-    /// Remove a service
-    pub fn remove_service(&self, name: &str) -> Result<bool, Error> {
+    // ========== Post-install Opt-out Operations ==========
+
+    /// Stop running `name`'s post-install steps after installs. Returns
+    /// false if they were already skipped.
+    pub fn add_post_install_skip(&self, name: &str) -> Result<bool, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
         let rows_affected = self
             .conn
-            .execute("DELETE FROM services WHERE name = ?1", params![name])
+            .execute(
+                "INSERT OR IGNORE INTO post_install_skips (name, added_at) VALUES (?1, ?2)",
+                params![name, now],
+            )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove service: {e}"),
+                message: format!("failed to skip post-install: {e}"),
             })?;
 
         Ok(rows_affected > 0)
     }
 
-    /// Check if formula has a service
-    pub fn has_service(&self, formula: &str) -> bool {
-        self.conn
-            .query_row(
-                "SELECT 1 FROM services WHERE formula = ?1",
-                params![formula],
-                |_| Ok(()),
+    /// Run `name`'s post-install steps after installs again. Returns false
+    /// if they weren't skipped.
+    pub fn remove_post_install_skip(&self, name: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute(
+                "DELETE FROM post_install_skips WHERE name = ?1",
+                params![name],
             )
-            .is_ok()
-    }
-}
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to re-enable post-install: {e}"),
+            })?;
 
-/// Information about a service stored in the database
-#[derive(Debug, Clone)]
-pub struct ServiceRecord {
-    /// Service name (e.g., "zerobrew.redis")
-    pub name: String,
-    /// Formula this service is for
-    pub formula: String,
-    /// Last known status
-    pub status: String,
-    /// Last known PID
-    pub pid: Option<u32>,
-    /// Unix timestamp when last started
-    pub started_at: Option<i64>,
-    /// JSON configuration (optional)
-    pub config: Option<String>,
-}
+        Ok(rows_affected > 0)
+    }
 
-pub struct InstallTransaction<'a> {
-    tx: Transaction<'a>,
-}
+    // ========== Upgrade Window Operations ==========
 
-impl<'a> InstallTransaction<'a> {
-    /// Record a package installation.
-    ///
-    /// # Arguments
-    /// * `name` - Package name
-    /// * `version` - Package version
-    /// * `store_key` - Content-addressable store key
-    /// * `explicit` - Whether this was explicitly installed by user (true) or as a dependency (false)
-    pub fn record_install(
-        &self,
-        name: &str,
-        version: &str,
-        store_key: &str,
-        explicit: bool,
-    ) -> Result<(), Error> {
+    /// Allow scheduled upgrades during the window written as `spec`.
+    /// Returns false if it was already allowed.
+    pub fn add_upgrade_window(&self, spec: &str) -> Result<bool, Error> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs() as i64;
 
-        let explicit_int: i64 = if explicit { 1 } else { 0 };
-
-        self.tx
+        let rows_affected = self
+            .conn
             .execute(
-                "INSERT OR REPLACE INTO installed_kegs (name, version, store_key, installed_at, explicit)
-                 VALUES (?1, ?2, ?3, ?4, ?5)",
-                params![name, version, store_key, now, explicit_int],
+                "INSERT OR IGNORE INTO upgrade_windows (spec, added_at) VALUES (?1, ?2)",
+                params![spec, now],
             )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to record install: {e}"),
+                message: format!("failed to add upgrade window: {e}"),
             })?;
 
-        // Increment store ref
-        self.tx
+        Ok(rows_affected > 0)
+    }
+
+    /// Remove an upgrade window. Returns false if there was no such window.
+    pub fn remove_upgrade_window(&self, spec: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM upgrade_windows WHERE spec = ?1", params![spec])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove upgrade window: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Upgrade windows in the order they were added
+    pub fn list_upgrade_windows(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT spec FROM upgrade_windows ORDER BY added_at, spec")
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let specs = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query upgrade windows: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+
+        Ok(specs)
+    }
+
+    /// Formulas whose post-install steps are skipped, sorted by name
+    pub fn list_post_install_skips(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT name FROM post_install_skips ORDER BY name")
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let names = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query post-install skips: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+
+        Ok(names)
+    }
+
+    // ========== Link Environment Operations ==========
+
+    /// Set an environment variable exported by `name`'s executable wrappers
+    pub fn set_link_env(&self, name: &str, key: &str, value: &str) -> Result<(), Error> {
+        self.conn
             .execute(
-                "INSERT INTO store_refs (store_key, refcount) VALUES (?1, 1)
-                 ON CONFLICT(store_key) DO UPDATE SET refcount = refcount + 1",
-                params![store_key],
+                "INSERT OR REPLACE INTO link_env (name, key, value) VALUES (?1, ?2, ?3)",
+                params![name, key, value],
             )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to increment store ref: {e}"),
+                message: format!("failed to set link environment: {e}"),
             })?;
 
         Ok(())
     }
 
-    pub fn record_linked_file(
-        &self,
-        name: &str,
-        version: &str,
-        linked_path: &str,
-        target_path: &str,
-    ) -> Result<(), Error> {
-        self.tx
+    /// Remove an environment variable from `name`'s wrappers. Returns false
+    /// if it wasn't set.
+    pub fn unset_link_env(&self, name: &str, key: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
             .execute(
-                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path)
-                 VALUES (?1, ?2, ?3, ?4)",
-                params![name, version, linked_path, target_path],
+                "DELETE FROM link_env WHERE name = ?1 AND key = ?2",
+                params![name, key],
             )
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to record linked file: {e}"),
+                message: format!("failed to unset link environment: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Environment exported by `name`'s wrappers
+    pub fn link_env(&self, name: &str) -> Result<BTreeMap<String, String>, Error> {
+        Ok(self.list_link_env()?.remove(name).unwrap_or_default())
+    }
+
+    /// Environment exported by wrappers, by formula
+    pub fn list_link_env(&self) -> Result<BTreeMap<String, BTreeMap<String, String>>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT name, key, value FROM link_env")
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query link environment: {e}"),
+            })?;
+
+        let mut env: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for row in rows {
+            let (name, key, value) = row.map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+            env.entry(name).or_default().insert(key, value);
+        }
+
+        Ok(env)
+    }
+
+    // ========== Tap Operations ==========
+
+    /// Add a tap to the database
+    pub fn add_tap(&self, name: &str, url: &str) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO taps (name, url, added_at) VALUES (?1, ?2, ?3)",
+                params![name, url, now],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to add tap: {e}"),
             })?;
 
         Ok(())
     }
 
-    pub fn record_uninstall(&self, name: &str) -> Result<Option<String>, Error> {
-        // Get the store_key before removing
-        let store_key: Option<String> = self
-            .tx
+    /// Remove a tap from the database
+    pub fn remove_tap(&self, name: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM taps WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove tap: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Check if a tap is installed
+    pub fn is_tapped(&self, name: &str) -> bool {
+        self.conn
+            .query_row("SELECT 1 FROM taps WHERE name = ?1", params![name], |_| {
+                Ok(())
+            })
+            .is_ok()
+    }
+
+    /// Get information about a specific tap
+    pub fn get_tap(&self, name: &str) -> Option<InstalledTap> {
+        self.conn
             .query_row(
-                "SELECT store_key FROM installed_kegs WHERE name = ?1",
+                "SELECT name, url, added_at, pinned FROM taps WHERE name = ?1",
                 params![name],
-                |row| row.get(0),
+                |row| {
+                    Ok(InstalledTap {
+                        name: row.get(0)?,
+                        url: row.get(1)?,
+                        added_at: row.get(2)?,
+                        pinned: row.get::<_, i64>(3)? != 0,
+                    })
+                },
             )
-            .ok();
+            .ok()
+    }
 
-        // Remove installed keg record
-        self.tx
-            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
+    /// List all installed taps
+    pub fn list_taps(&self) -> Result<Vec<InstalledTap>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT name, url, added_at, pinned FROM taps ORDER BY name")
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove install record: {e}"),
+                message: format!("failed to prepare statement: {e}"),
             })?;
 
-        // Remove linked files records
-        self.tx
-            .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
+        let taps = stmt
+            .query_map([], |row| {
+                Ok(InstalledTap {
+                    name: row.get(0)?,
+                    url: row.get(1)?,
+                    added_at: row.get(2)?,
+                    pinned: row.get::<_, i64>(3)? != 0,
+                })
+            })
             .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove keg files records: {e}"),
+                message: format!("failed to query taps: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
             })?;
 
-        // Decrement store ref if we had one (clamped to 0 to prevent negative values)
-        if let Some(ref key) = store_key {
-            self.tx
-                .execute(
-                    "UPDATE store_refs SET refcount = MAX(refcount - 1, 0) WHERE store_key = ?1",
-                    params![key],
-                )
-                .map_err(|e| Error::StoreCorruption {
-                    message: format!("failed to decrement store ref: {e}"),
-                })?;
-        }
+        Ok(taps)
+    }
 
-        Ok(store_key)
+    /// Pin or unpin a tap. Returns false if the tap is not installed.
+    pub fn set_tap_pinned(&self, name: &str, pinned: bool) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute(
+                "UPDATE taps SET pinned = ?2 WHERE name = ?1",
+                params![name, pinned as i64],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to update tap pin: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
     }
 
-    pub fn commit(self) -> Result<(), Error> {
-        self.tx.commit().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to commit transaction: {e}"),
+    // ========== Service Operations ==========
+
+    /// Record a service for a formula
+    pub fn record_service(
+        &self,
+        name: &str,
+        formula: &str,
+        config: Option<&str>,
+    ) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO services (name, formula, status, config)
+                 VALUES (?1, ?2, 'stopped', ?3)",
+                params![name, formula, config],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record service: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Update service status
+    pub fn update_service_status(
+        &self,
+        name: &str,
+        status: &str,
+        pid: Option<u32>,
+    ) -> Result<bool, Error> {
+        let started_at = if status == "running" {
+            Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+            )
+        } else {
+            None
+        };
+
+        let rows_affected = self
+            .conn
+            .execute(
+                "UPDATE services SET status = ?1, pid = ?2, started_at = ?3 WHERE name = ?4",
+                params![status, pid, started_at, name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to update service status: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Get service by name
+    pub fn get_service(&self, name: &str) -> Option<ServiceRecord> {
+        self.conn
+            .query_row(
+                "SELECT name, formula, status, pid, started_at, config FROM services WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok(ServiceRecord {
+                        name: row.get(0)?,
+                        formula: row.get(1)?,
+                        status: row.get(2)?,
+                        pid: row.get(3)?,
+                        started_at: row.get(4)?,
+                        config: row.get(5)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    /// Get service for a formula
+    pub fn get_service_for_formula(&self, formula: &str) -> Option<ServiceRecord> {
+        self.conn
+            .query_row(
+                "SELECT name, formula, status, pid, started_at, config FROM services WHERE formula = ?1",
+                params![formula],
+                |row| {
+                    Ok(ServiceRecord {
+                        name: row.get(0)?,
+                        formula: row.get(1)?,
+                        status: row.get(2)?,
+                        pid: row.get(3)?,
+                        started_at: row.get(4)?,
+                        config: row.get(5)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    /// List all services
+    pub fn list_services(&self) -> Result<Vec<ServiceRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT name, formula, status, pid, started_at, config FROM services ORDER BY name",
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let services = stmt
+            .query_map([], |row| {
+                Ok(ServiceRecord {
+                    name: row.get(0)?,
+                    formula: row.get(1)?,
+                    status: row.get(2)?,
+                    pid: row.get(3)?,
+                    started_at: row.get(4)?,
+                    config: row.get(5)?,
+                })
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query services: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+
+        Ok(services)
+    }
+
+    /// Remove a service
+    pub fn remove_service(&self, name: &str) -> Result<bool, Error> {
+        let rows_affected = self
+            .conn
+            .execute("DELETE FROM services WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove service: {e}"),
+            })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Check if formula has a service
+    pub fn has_service(&self, formula: &str) -> bool {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM services WHERE formula = ?1",
+                params![formula],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    // ========== Install Statistics ==========
+
+    /// Summarize the recorded install timing history
+    pub fn install_stats(&self) -> Result<InstallStats, Error> {
+        let (runs, avg_run_ms, avg_resolve_ms): (i64, f64, f64) = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*),
+                        COALESCE(AVG(resolve_ms + execute_ms), 0),
+                        COALESCE(AVG(resolve_ms), 0)
+                 FROM install_runs",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query install runs: {e}"),
+            })?;
+
+        self.conn
+            .query_row(
+                "SELECT COUNT(*),
+                        COALESCE(AVG(download_ms), 0),
+                        COALESCE(AVG(extract_ms), 0),
+                        COALESCE(AVG(link_ms), 0),
+                        COALESCE(SUM(blob_cached), 0),
+                        COALESCE(SUM(store_reused), 0),
+                        COALESCE(SUM(CASE WHEN blob_cached = 0 THEN bytes ELSE 0 END), 0),
+                        COALESCE(SUM(CASE WHEN blob_cached = 1 THEN bytes ELSE 0 END), 0)
+                 FROM install_timings",
+                [],
+                |row| {
+                    Ok(InstallStats {
+                        runs: runs as u64,
+                        packages: row.get::<_, i64>(0)? as u64,
+                        avg_run_ms,
+                        avg_resolve_ms,
+                        avg_download_ms: row.get(1)?,
+                        avg_extract_ms: row.get(2)?,
+                        avg_link_ms: row.get(3)?,
+                        blob_cache_hits: row.get::<_, i64>(4)? as u64,
+                        store_reuses: row.get::<_, i64>(5)? as u64,
+                        bytes_downloaded: row.get::<_, i64>(6)? as u64,
+                        bytes_saved: row.get::<_, i64>(7)? as u64,
+                    })
+                },
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query install timings: {e}"),
+            })
+    }
+
+    /// Count installed and upgraded packages per month, oldest month first
+    pub fn install_activity_by_month(&self) -> Result<Vec<MonthlyActivity>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT strftime('%Y-%m', r.started_at, 'unixepoch') AS month,
+                        SUM(CASE WHEN t.upgraded = 0 THEN 1 ELSE 0 END),
+                        SUM(t.upgraded)
+                 FROM install_timings t
+                 JOIN install_runs r ON r.id = t.run_id
+                 GROUP BY month
+                 ORDER BY month",
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(MonthlyActivity {
+                    month: row.get(0)?,
+                    installs: row.get::<_, i64>(1)? as u64,
+                    upgrades: row.get::<_, i64>(2)? as u64,
+                })
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query install activity: {e}"),
+            })?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read install activity: {e}"),
+            })
+    }
+
+    /// Delete all recorded install timing history
+    pub fn clear_install_stats(&self) -> Result<usize, Error> {
+        self.conn
+            .execute("DELETE FROM install_timings", [])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to clear install timings: {e}"),
+            })?;
+        self.conn
+            .execute("DELETE FROM install_runs", [])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to clear install runs: {e}"),
+            })
+    }
+
+    // ========== Pour Log ==========
+
+    /// Append an event to the pour log
+    ///
+    /// Integrity failures are recorded straight away, outside the install's
+    /// transaction, so they outlive the rollback of the install they broke.
+    pub fn record_pour_event(&self, event: &PourEvent) -> Result<(), Error> {
+        insert_pour_event(&self.conn, event)
+    }
+
+    /// The most recent `limit` events of the pour log, newest first
+    pub fn pour_events(&self, limit: usize) -> Result<Vec<PourEvent>, Error> {
+        self.query_pour_events(
+            "SELECT at, kind, name, version, url, sha256, bytes, duration_ms, relocated, detail
+             FROM pour_events ORDER BY id DESC LIMIT ?1",
+            params![limit as i64],
+        )
+    }
+
+    /// Integrity failures recorded at or after the Unix timestamp `since`,
+    /// newest first
+    pub fn integrity_failures_since(&self, since: i64) -> Result<Vec<PourEvent>, Error> {
+        self.query_pour_events(
+            "SELECT at, kind, name, version, url, sha256, bytes, duration_ms, relocated, detail
+             FROM pour_events WHERE kind != 'poured' AND at >= ?1 ORDER BY id DESC",
+            params![since],
+        )
+    }
+
+    fn query_pour_events(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<PourEvent>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(sql)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let rows = stmt
+            .query_map(params, |row| {
+                let kind: String = row.get(1)?;
+                Ok(PourEvent {
+                    at: row.get(0)?,
+                    // Events written by a newer zerobrew count as failures
+                    kind: PourEventKind::parse(&kind).unwrap_or(PourEventKind::CorruptArchive),
+                    name: row.get(2)?,
+                    version: row.get(3)?,
+                    url: row.get(4)?,
+                    sha256: row.get(5)?,
+                    bytes: row.get::<_, i64>(6)? as u64,
+                    duration_ms: row.get(7)?,
+                    relocated: row.get::<_, i64>(8)? != 0,
+                    detail: row.get(9)?,
+                })
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query pour log: {e}"),
+            })?;
+
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read pour log: {e}"),
+            })
+    }
+
+    /// Count the pours and integrity failures in the pour log
+    pub fn pour_stats(&self) -> Result<PourStats, Error> {
+        self.conn
+            .query_row(
+                "SELECT COALESCE(SUM(kind = 'poured'), 0),
+                        COALESCE(SUM(kind = 'poured' AND relocated), 0),
+                        COALESCE(SUM(kind != 'poured'), 0),
+                        COALESCE(SUM(CASE WHEN kind = 'poured' THEN bytes ELSE 0 END), 0)
+                 FROM pour_events",
+                [],
+                |row| {
+                    Ok(PourStats {
+                        poured: row.get::<_, i64>(0)? as u64,
+                        relocated: row.get::<_, i64>(1)? as u64,
+                        integrity_failures: row.get::<_, i64>(2)? as u64,
+                        bytes: row.get::<_, i64>(3)? as u64,
+                    })
+                },
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query pour log: {e}"),
+            })
+    }
+
+    // ========== Attestations ==========
+
+    /// Record the attestation check of the bottle a store entry was
+    /// extracted from, replacing any earlier one
+    pub fn record_attestation(
+        &self,
+        store_key: &str,
+        attestation: &Attestation,
+    ) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO attestations (store_key, status, repo, detail, checked_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    store_key,
+                    attestation.status.as_str(),
+                    attestation.repo,
+                    attestation.detail,
+                    attestation.checked_at,
+                ],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record attestation: {e}"),
+            })?;
+        Ok(())
+    }
+
+    /// The last attestation check recorded for a store entry
+    pub fn attestation(&self, store_key: &str) -> Option<Attestation> {
+        self.conn
+            .query_row(
+                "SELECT status, repo, detail, checked_at FROM attestations WHERE store_key = ?1",
+                params![store_key],
+                |row| {
+                    let status: String = row.get(0)?;
+                    Ok(Attestation {
+                        status: AttestationStatus::parse(&status)
+                            .unwrap_or(AttestationStatus::Unavailable),
+                        repo: row.get(1)?,
+                        detail: row.get(2)?,
+                        checked_at: row.get(3)?,
+                    })
+                },
+            )
+            .ok()
+    }
+
+    // ========== Keg Sizes ==========
+
+    /// Record the disk size of a store entry (or, for source builds, of the
+    /// keg), which never changes once it is written
+    pub fn record_store_size(&self, store_key: &str, bytes: u64) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO store_sizes (store_key, bytes) VALUES (?1, ?2)",
+                params![store_key, bytes as i64],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record store size: {e}"),
+            })?;
+        Ok(())
+    }
+
+    /// The recorded disk size of a store entry
+    pub fn store_size(&self, store_key: &str) -> Option<u64> {
+        self.conn
+            .query_row(
+                "SELECT bytes FROM store_sizes WHERE store_key = ?1",
+                params![store_key],
+                |row| row.get::<_, i64>(0),
+            )
+            .ok()
+            .map(|bytes| bytes as u64)
+    }
+
+    /// Installed kegs whose size hasn't been recorded yet
+    pub fn list_unsized_kegs(&self) -> Result<Vec<InstalledKeg>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {KEG_COLUMNS} FROM installed_kegs
+                 WHERE store_key NOT IN (SELECT store_key FROM store_sizes) ORDER BY name"
+            ))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        stmt.query_map([], InstalledKeg::from_row)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query unsized kegs: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })
+    }
+
+    /// Installed kegs with their recorded sizes, in `sort` order
+    pub fn list_inventory(
+        &self,
+        sort: InventorySort,
+    ) -> Result<Vec<(InstalledKeg, Option<u64>)>, Error> {
+        let order = match sort {
+            InventorySort::Name => "name",
+            InventorySort::Date => "installed_at DESC, name",
+            InventorySort::Size => "bytes IS NULL, bytes DESC, name",
+        };
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!(
+                "SELECT {KEG_COLUMNS}, bytes FROM installed_kegs
+                 LEFT JOIN store_sizes USING (store_key) ORDER BY {order}"
+            ))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        stmt.query_map([], |row| {
+            let bytes: Option<i64> = row.get(11)?;
+            Ok((InstalledKeg::from_row(row)?, bytes.map(|b| b as u64)))
+        })
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to query inventory: {e}"),
+        })?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to collect results: {e}"),
+        })
+    }
+
+    // ========== Benchmarks ==========
+
+    /// Record the result of a benchmark run
+    pub fn record_benchmark(&self, record: &BenchmarkRecord) -> Result<(), Error> {
+        self.conn
+            .execute(
+                "INSERT INTO benchmark_runs
+                 (formula, ran_at, iterations, packages, total_ms, resolve_ms, download_ms, extract_ms, link_ms)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    record.formula,
+                    record.ran_at,
+                    record.iterations,
+                    record.packages as i64,
+                    record.total_ms,
+                    record.resolve_ms,
+                    record.download_ms,
+                    record.extract_ms,
+                    record.link_ms
+                ],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record benchmark: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// List previous benchmark runs for a formula, most recent first
+    pub fn list_benchmarks(
+        &self,
+        formula: &str,
+        limit: usize,
+    ) -> Result<Vec<BenchmarkRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT formula, ran_at, iterations, packages, total_ms, resolve_ms, download_ms, extract_ms, link_ms
+                 FROM benchmark_runs WHERE formula = ?1 ORDER BY id DESC LIMIT ?2",
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prepare statement: {e}"),
+            })?;
+
+        let records = stmt
+            .query_map(params![formula, limit as i64], |row| {
+                Ok(BenchmarkRecord {
+                    formula: row.get(0)?,
+                    ran_at: row.get(1)?,
+                    iterations: row.get(2)?,
+                    packages: row.get::<_, i64>(3)? as u64,
+                    total_ms: row.get(4)?,
+                    resolve_ms: row.get(5)?,
+                    download_ms: row.get(6)?,
+                    extract_ms: row.get(7)?,
+                    link_ms: row.get(8)?,
+                })
+            })
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to query benchmarks: {e}"),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to collect results: {e}"),
+            })?;
+
+        Ok(records)
+    }
+}
+
+/// Information about a service stored in the database
+#[derive(Debug, Clone)]
+pub struct ServiceRecord {
+    /// Service name (e.g., "zerobrew.redis")
+    pub name: String,
+    /// Formula this service is for
+    pub formula: String,
+    /// Last known status
+    pub status: String,
+    /// Last known PID
+    pub pid: Option<u32>,
+    /// Unix timestamp when last started
+    pub started_at: Option<i64>,
+    /// JSON configuration (optional)
+    pub config: Option<String>,
+}
+
+pub struct InstallTransaction<'a> {
+    tx: Transaction<'a>,
+}
+
+impl<'a> InstallTransaction<'a> {
+    /// Record a package installation.
+    ///
+    /// # Arguments
+    /// * `name` - Package name
+    /// * `version` - Package version
+    /// * `store_key` - Content-addressable store key
+    /// * `explicit` - Whether this was explicitly installed by user (true) or as a dependency (false)
+    pub fn record_install(
+        &self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+        explicit: bool,
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let explicit_int: i64 = if explicit { 1 } else { 0 };
+
+        // Upsert rather than INSERT OR REPLACE: replacing deletes the row, which
+        // would cascade to the formula's service record
+        self.tx
+            .execute(
+                "INSERT INTO installed_kegs (name, version, store_key, installed_at, explicit)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(name) DO UPDATE SET
+                     version = excluded.version,
+                     store_key = excluded.store_key,
+                     installed_at = excluded.installed_at,
+                     explicit = excluded.explicit,
+                     pinned = 0,
+                     linked = 1,
+                     keg_only = 0,
+                     deps_recorded = 0,
+                     provenance = NULL,
+                     head_commit = NULL,
+                     receipt = NULL",
+                params![name, version, store_key, now, explicit_int],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record install: {e}"),
+            })?;
+
+        self.record_keg_version(name, version, store_key)
+    }
+
+    /// Record a keg version without making it the active one, e.g. an old
+    /// version restored from the store
+    pub fn record_keg_version(
+        &self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        // Each keg version holds one store ref. Reinstalling a version that is
+        // already present only moves its ref if the store key changed.
+        let previous_key: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT store_key FROM keg_versions WHERE name = ?1 AND version = ?2",
+                params![name, version],
+                |row| row.get(0),
+            )
+            .ok();
+
+        self.tx
+            .execute(
+                "INSERT OR REPLACE INTO keg_versions (name, version, store_key, installed_at)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, version, store_key, now],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record keg version: {e}"),
+            })?;
+
+        if previous_key.as_deref() == Some(store_key) {
+            return Ok(());
+        }
+        if let Some(ref key) = previous_key {
+            self.decrement_store_ref(key)?;
+        }
+
+        // Increment store ref
+        self.tx
+            .execute(
+                "INSERT INTO store_refs (store_key, refcount) VALUES (?1, 1)
+                 ON CONFLICT(store_key) DO UPDATE SET refcount = refcount + 1",
+                params![store_key],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to increment store ref: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    pub fn record_linked_file(
+        &self,
+        name: &str,
+        version: &str,
+        linked_path: &str,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![name, version, linked_path, target_path],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record linked file: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Remove a formula and all of its versions.
+    /// Returns the store key of the active version.
+    pub fn record_uninstall(&self, name: &str) -> Result<Option<String>, Error> {
+        // Get the store_key before removing
+        let store_key: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT store_key FROM installed_kegs WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .ok();
+
+        // Remove installed keg record
+        self.tx
+            .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove install record: {e}"),
+            })?;
+
+        // Remove linked files records
+        self.tx
+            .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove keg files records: {e}"),
+            })?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove dependency records: {e}"),
+            })?;
+
+        // Drop every version, releasing the store ref each one holds
+        let versions: Vec<String> = {
+            let mut stmt = self
+                .tx
+                .prepare_cached("SELECT version FROM keg_versions WHERE name = ?1")
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to prepare statement: {e}"),
+                })?;
+            stmt.query_map(params![name], |row| row.get(0))
+                .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to query keg versions: {e}"),
+                })?
+        };
+
+        if versions.is_empty() {
+            // Decrement store ref if we had one (clamped to 0 to prevent negative values)
+            if let Some(ref key) = store_key {
+                self.decrement_store_ref(key)?;
+            }
+        }
+        for version in versions {
+            self.record_uninstall_version(name, &version)?;
+        }
+
+        Ok(store_key)
+    }
+
+    /// Remove a single version of a formula, leaving the active record alone.
+    /// Returns the store key the version referenced.
+    pub fn record_uninstall_version(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<String>, Error> {
+        let store_key: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT store_key FROM keg_versions WHERE name = ?1 AND version = ?2",
+                params![name, version],
+                |row| row.get(0),
+            )
+            .ok();
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_versions WHERE name = ?1 AND version = ?2",
+                params![name, version],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove keg version: {e}"),
+            })?;
+
+        if let Some(ref key) = store_key {
+            self.decrement_store_ref(key)?;
+        }
+
+        Ok(store_key)
+    }
+
+    /// Record the link state of a package after installing it
+    pub fn record_link_state(&self, name: &str, linked: bool, keg_only: bool) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET linked = ?1, keg_only = ?2 WHERE name = ?3",
+                params![linked as i64, keg_only as i64, name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record link state: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Record what requested an installed package
+    pub fn record_provenance(&self, name: &str, provenance: &Provenance) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET provenance = ?1 WHERE name = ?2",
+                params![provenance.to_db(), name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record provenance: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Record the upstream commit a HEAD build was made from
+    pub fn record_head_commit(&self, name: &str, commit: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET head_commit = ?1 WHERE name = ?2",
+                params![commit, name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record HEAD commit: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Record how an installed package was produced
+    pub fn record_receipt(&self, name: &str, receipt: &InstallReceipt) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET receipt = ?1 WHERE name = ?2",
+                params![receipt.to_db(), name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record install receipt: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Record the default configs installed for a package, replacing the
+    /// checksums of earlier defaults at the same paths
+    pub fn record_config_files(&self, name: &str, files: &[ConfigFile]) -> Result<(), Error> {
+        for file in files {
+            self.tx
+                .execute(
+                    "INSERT OR REPLACE INTO config_files (name, path, sha256) VALUES (?1, ?2, ?3)",
+                    params![name, file.path.to_string_lossy(), file.sha256],
+                )
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to record config file: {e}"),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the recorded dependency edges of an installed package
+    pub fn record_dependencies(&self, name: &str, dependencies: &[String]) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "DELETE FROM keg_dependencies WHERE name = ?1",
+                params![name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to clear dependencies: {e}"),
+            })?;
+
+        for dependency in dependencies {
+            self.tx
+                .execute(
+                    "INSERT OR IGNORE INTO keg_dependencies (name, dependency) VALUES (?1, ?2)",
+                    params![name, dependency],
+                )
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to record dependency: {e}"),
+                })?;
+        }
+
+        self.tx
+            .execute(
+                "UPDATE installed_kegs SET deps_recorded = 1 WHERE name = ?1",
+                params![name],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record dependencies: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Make an installed version the active one and forget the old version's links
+    pub fn record_switch(&self, name: &str, version: &str) -> Result<(), Error> {
+        let rows = self
+            .tx
+            .execute(
+                "UPDATE installed_kegs SET version = v.version, store_key = v.store_key
+                 FROM (SELECT version, store_key FROM keg_versions WHERE name = ?1 AND version = ?2) AS v
+                 WHERE installed_kegs.name = ?1",
+                params![name, version],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to switch version: {e}"),
+            })?;
+
+        if rows == 0 {
+            return Err(Error::NotInstalled {
+                name: format!("{name} {version}"),
+            });
+        }
+
+        self.tx
+            .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove keg files records: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    fn decrement_store_ref(&self, store_key: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "UPDATE store_refs SET refcount = MAX(refcount - 1, 0) WHERE store_key = ?1",
+                params![store_key],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to decrement store ref: {e}"),
+            })?;
+
+        Ok(())
+    }
+
+    /// Record the timings of an install run and its packages
+    pub fn record_install_timings(
+        &self,
+        root_name: &str,
+        timings: &InstallTimings,
+    ) -> Result<(), Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        self.tx
+            .execute(
+                "INSERT INTO install_runs (root_name, started_at, resolve_ms, execute_ms)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    root_name,
+                    now,
+                    duration_to_ms(timings.resolve),
+                    duration_to_ms(timings.execute)
+                ],
+            )
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to record install run: {e}"),
+            })?;
+        let run_id = self.tx.last_insert_rowid();
+
+        for package in &timings.packages {
+            self.tx
+                .execute(
+                    "INSERT INTO install_timings
+                     (run_id, name, version, download_ms, extract_ms, link_ms, bytes, blob_cached, store_reused, upgraded)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        run_id,
+                        package.name,
+                        package.version,
+                        duration_to_ms(package.download),
+                        duration_to_ms(package.extract),
+                        duration_to_ms(package.link),
+                        package.bytes as i64,
+                        package.blob_cached as i64,
+                        package.store_reused as i64,
+                        package.upgraded as i64
+                    ],
+                )
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to record install timing: {e}"),
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Append the pours of an install run to the pour log
+    pub fn record_pour_events(&self, events: &[PourEvent]) -> Result<(), Error> {
+        for event in events {
+            insert_pour_event(&self.tx, event)?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        self.tx.commit().map_err(|e| Error::StoreCorruption {
+            message: format!("failed to commit transaction: {e}"),
         })
     }
 
-    // Transaction is rolled back automatically when dropped without commit
-}
+    // Transaction is rolled back automatically when dropped without commit
+}
+
+fn insert_pour_event(conn: &Connection, event: &PourEvent) -> Result<(), Error> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    conn.execute(
+        "INSERT INTO pour_events
+         (at, kind, name, version, url, sha256, bytes, duration_ms, relocated, detail)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            now,
+            event.kind.as_str(),
+            event.name,
+            event.version,
+            event.url,
+            event.sha256,
+            event.bytes as i64,
+            event.duration_ms,
+            event.relocated as i64,
+            event.detail
+        ],
+    )
+    .map_err(|e| Error::StoreCorruption {
+        message: format!("failed to record pour event: {e}"),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_and_list() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let installed = db.list_installed().unwrap();
+        assert_eq!(installed.len(), 1);
+        assert_eq!(installed[0].name, "foo");
+        assert_eq!(installed[0].version, "1.0.0");
+        assert_eq!(installed[0].store_key, "abc123");
+        assert!(!installed[0].pinned);
+        assert!(installed[0].explicit);
+    }
+
+    #[test]
+    fn pin_and_unpin_package() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("pinnable", "1.0.0", "abc123", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Initially not pinned
+        assert!(!db.is_pinned("pinnable"));
+        let keg = db.get_installed("pinnable").unwrap();
+        assert!(!keg.pinned);
+
+        // Pin the package
+        let result = db.pin("pinnable").unwrap();
+        assert!(result); // Should return true (rows affected)
+        assert!(db.is_pinned("pinnable"));
+
+        // Verify via get_installed
+        let keg = db.get_installed("pinnable").unwrap();
+        assert!(keg.pinned);
+
+        // Unpin the package
+        let result = db.unpin("pinnable").unwrap();
+        assert!(result);
+        assert!(!db.is_pinned("pinnable"));
+
+        // Verify via get_installed
+        let keg = db.get_installed("pinnable").unwrap();
+        assert!(!keg.pinned);
+    }
+
+    #[test]
+    fn pin_nonexistent_package_returns_false() {
+        let db = Database::in_memory().unwrap();
+
+        // Pinning a non-existent package should return false (no rows affected)
+        let result = db.pin("doesnotexist").unwrap();
+        assert!(!result);
+
+        // is_pinned should return false for non-existent packages
+        assert!(!db.is_pinned("doesnotexist"));
+    }
+
+    #[test]
+    fn list_pinned_only_returns_pinned_packages() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("pinned1", "1.0.0", "abc123", true)
+                .unwrap();
+            tx.record_install("unpinned", "1.0.0", "def456", true)
+                .unwrap();
+            tx.record_install("pinned2", "2.0.0", "ghi789", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Pin two packages
+        db.pin("pinned1").unwrap();
+        db.pin("pinned2").unwrap();
+
+        // list_installed should return all 3
+        let all = db.list_installed().unwrap();
+        assert_eq!(all.len(), 3);
+
+        // list_pinned should return only 2
+        let pinned = db.list_pinned().unwrap();
+        assert_eq!(pinned.len(), 2);
+        assert!(pinned.iter().all(|k| k.pinned));
+        assert!(pinned.iter().any(|k| k.name == "pinned1"));
+        assert!(pinned.iter().any(|k| k.name == "pinned2"));
+        assert!(!pinned.iter().any(|k| k.name == "unpinned"));
+    }
+
+    #[test]
+    fn rollback_leaves_no_partial_state() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            // Don't commit - transaction will be rolled back when dropped
+        }
+
+        let installed = db.list_installed().unwrap();
+        assert!(installed.is_empty());
+
+        // Store ref should also not exist
+        assert_eq!(db.get_store_refcount("abc123"), 0);
+    }
+
+    #[test]
+    fn uninstall_decrements_refcount() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "shared123", true)
+                .unwrap();
+            tx.record_install("bar", "2.0.0", "shared123", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.get_store_refcount("shared123"), 2);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.get_store_refcount("shared123"), 1);
+        assert!(db.get_installed("foo").is_none());
+        assert!(db.get_installed("bar").is_some());
+    }
+
+    #[test]
+    fn get_unreferenced_store_keys() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "key1", true).unwrap();
+            tx.record_install("bar", "2.0.0", "key2", true).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Uninstall both
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.record_uninstall("bar").unwrap();
+            tx.commit().unwrap();
+        }
+
+        let unreferenced = db.get_unreferenced_store_keys().unwrap();
+        assert_eq!(unreferenced.len(), 2);
+        assert!(unreferenced.contains(&"key1".to_string()));
+        assert!(unreferenced.contains(&"key2".to_string()));
+    }
+
+    #[test]
+    fn linked_files_are_recorded() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            tx.record_linked_file(
+                "foo",
+                "1.0.0",
+                "/opt/homebrew/bin/foo",
+                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Verify via uninstall that removes records
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert!(db.get_installed("foo").is_none());
+    }
+
+    #[test]
+    fn explicit_vs_dependency_tracking() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            // Explicit install
+            tx.record_install("user-requested", "1.0.0", "key1", true)
+                .unwrap();
+            // Dependency installs
+            tx.record_install("dep1", "1.0.0", "key2", false).unwrap();
+            tx.record_install("dep2", "2.0.0", "key3", false).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Verify explicit flag
+        let user_pkg = db.get_installed("user-requested").unwrap();
+        assert!(user_pkg.explicit);
+
+        let dep1 = db.get_installed("dep1").unwrap();
+        assert!(!dep1.explicit);
+
+        let dep2 = db.get_installed("dep2").unwrap();
+        assert!(!dep2.explicit);
+
+        // is_explicit method
+        assert!(db.is_explicit("user-requested"));
+        assert!(!db.is_explicit("dep1"));
+        assert!(!db.is_explicit("dep2"));
+        assert!(!db.is_explicit("nonexistent")); // Not installed
+
+        // list_dependencies should only return deps
+        let deps = db.list_dependencies().unwrap();
+        assert_eq!(deps.len(), 2);
+        assert!(deps.iter().all(|k| !k.explicit));
+        assert!(deps.iter().any(|k| k.name == "dep1"));
+        assert!(deps.iter().any(|k| k.name == "dep2"));
+    }
+
+    #[test]
+    fn mark_explicit_and_dependency() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("pkg", "1.0.0", "key1", false).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Initially a dependency
+        assert!(!db.is_explicit("pkg"));
+
+        // Mark as explicit (user explicitly installs a previously auto-installed dep)
+        let result = db.mark_explicit("pkg").unwrap();
+        assert!(result);
+        assert!(db.is_explicit("pkg"));
+
+        // Mark back as dependency
+        let result = db.mark_dependency("pkg").unwrap();
+        assert!(result);
+        assert!(!db.is_explicit("pkg"));
+
+        // Non-existent package
+        let result = db.mark_explicit("nonexistent").unwrap();
+        assert!(!result);
+    }
+
+    #[test]
+    fn get_linked_files_returns_linked_files_for_package() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("test-pkg", "1.0.0", "key1", true)
+                .unwrap();
+            tx.record_linked_file(
+                "test-pkg",
+                "1.0.0",
+                "/opt/zerobrew/prefix/bin/test",
+                "/opt/zerobrew/cellar/test-pkg/1.0.0/bin/test",
+            )
+            .unwrap();
+            tx.record_linked_file(
+                "test-pkg",
+                "1.0.0",
+                "/opt/zerobrew/prefix/lib/libtest.so",
+                "/opt/zerobrew/cellar/test-pkg/1.0.0/lib/libtest.so",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let files = db.get_linked_files("test-pkg").unwrap();
+        assert_eq!(files.len(), 2);
+
+        // Files should be sorted by link path
+        assert_eq!(files[0].0, "/opt/zerobrew/prefix/bin/test");
+        assert_eq!(files[1].0, "/opt/zerobrew/prefix/lib/libtest.so");
+    }
+
+    #[test]
+    fn get_linked_files_returns_empty_for_nonexistent_package() {
+        let db = Database::in_memory().unwrap();
+        let files = db.get_linked_files("nonexistent").unwrap();
+        assert!(files.is_empty());
+    }
+
+    // ========== Tap Tests ==========
+
+    #[test]
+    fn add_and_list_taps() {
+        let db = Database::in_memory().unwrap();
+
+        // Initially no taps
+        let taps = db.list_taps().unwrap();
+        assert!(taps.is_empty());
+
+        // Add a tap
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+
+        // Should be listed
+        let taps = db.list_taps().unwrap();
+        assert_eq!(taps.len(), 1);
+        assert_eq!(taps[0].name, "user/repo");
+        assert_eq!(taps[0].url, "https://github.com/user/homebrew-repo");
+        assert!(taps[0].added_at > 0);
+    }
+
+    #[test]
+    fn is_tapped_returns_correct_status() {
+        let db = Database::in_memory().unwrap();
+
+        assert!(!db.is_tapped("user/repo"));
+
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+
+        assert!(db.is_tapped("user/repo"));
+        assert!(!db.is_tapped("other/tap"));
+    }
+
+    #[test]
+    fn get_tap_returns_tap_info() {
+        let db = Database::in_memory().unwrap();
+
+        assert!(db.get_tap("user/repo").is_none());
+
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+
+        let tap = db.get_tap("user/repo").unwrap();
+        assert_eq!(tap.name, "user/repo");
+        assert_eq!(tap.url, "https://github.com/user/homebrew-repo");
+    }
+
+    #[test]
+    fn remove_tap_deletes_tap() {
+        let db = Database::in_memory().unwrap();
+
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+        assert!(db.is_tapped("user/repo"));
+
+        let removed = db.remove_tap("user/repo").unwrap();
+        assert!(removed);
+        assert!(!db.is_tapped("user/repo"));
+    }
+
+    #[test]
+    fn remove_tap_returns_false_for_nonexistent() {
+        let db = Database::in_memory().unwrap();
+
+        let removed = db.remove_tap("nonexistent/tap").unwrap();
+        assert!(!removed);
+    }
+
+    #[test]
+    fn set_tap_pinned_toggles_pin() {
+        let db = Database::in_memory().unwrap();
+
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+        assert!(!db.get_tap("user/repo").unwrap().pinned);
+
+        assert!(db.set_tap_pinned("user/repo", true).unwrap());
+        assert!(db.list_taps().unwrap()[0].pinned);
+
+        assert!(db.set_tap_pinned("user/repo", false).unwrap());
+        assert!(!db.get_tap("user/repo").unwrap().pinned);
+
+        assert!(!db.set_tap_pinned("other/tap", true).unwrap());
+    }
+
+    #[test]
+    fn add_tap_updates_existing() {
+        let db = Database::in_memory().unwrap();
+
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+            .unwrap();
+
+        // Add again with different URL (should update)
+        db.add_tap("user/repo", "https://github.com/user/homebrew-repo-new")
+            .unwrap();
+
+        let tap = db.get_tap("user/repo").unwrap();
+        assert_eq!(tap.url, "https://github.com/user/homebrew-repo-new");
+
+        // Should still be just one tap
+        let taps = db.list_taps().unwrap();
+        assert_eq!(taps.len(), 1);
+    }
+
+    #[test]
+    fn list_taps_sorted_by_name() {
+        let db = Database::in_memory().unwrap();
+
+        db.add_tap("zulu/tools", "https://github.com/zulu/homebrew-tools")
+            .unwrap();
+        db.add_tap("alpha/utils", "https://github.com/alpha/homebrew-utils")
+            .unwrap();
+        db.add_tap("mike/apps", "https://github.com/mike/homebrew-apps")
+            .unwrap();
+
+        let taps = db.list_taps().unwrap();
+        assert_eq!(taps.len(), 3);
+        assert_eq!(taps[0].name, "alpha/utils");
+        assert_eq!(taps[1].name, "mike/apps");
+        assert_eq!(taps[2].name, "zulu/tools");
+    }
+
+    #[test]
+    fn clear_linked_files_removes_all_links_for_package() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("linktest", "1.0.0", "abc123", true)
+                .unwrap();
+            tx.record_linked_file(
+                "linktest",
+                "1.0.0",
+                "/opt/homebrew/bin/linktest",
+                "/opt/zerobrew/cellar/linktest/1.0.0/bin/linktest",
+            )
+            .unwrap();
+            tx.record_linked_file(
+                "linktest",
+                "1.0.0",
+                "/opt/homebrew/bin/linktest-tool",
+                "/opt/zerobrew/cellar/linktest/1.0.0/bin/linktest-tool",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Verify links recorded
+        let links = db.get_linked_files("linktest").unwrap();
+        assert_eq!(links.len(), 2);
+
+        // Clear links
+        let cleared = db.clear_linked_files("linktest").unwrap();
+        assert_eq!(cleared, 2);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // Verify links removed
+        let links_after = db.get_linked_files("linktest").unwrap();
+        assert!(links_after.is_empty());
+
+        // Package still installed
+        assert!(db.get_installed("linktest").is_some());
+    }
 
     #[test]
-    fn install_and_list() {
+    fn clear_linked_files_returns_zero_for_no_links() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            tx.record_install("nolinks", "1.0.0", "def456", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
-        let installed = db.list_installed().unwrap();
-        assert_eq!(installed.len(), 1);
-        assert_eq!(installed[0].name, "foo");
-        assert_eq!(installed[0].version, "1.0.0");
-        assert_eq!(installed[0].store_key, "abc123");
-        assert!(!installed[0].pinned);
-        assert!(installed[0].explicit);
+        // No links to clear
+        let cleared = db.clear_linked_files("nolinks").unwrap();
+        assert_eq!(cleared, 0);
     }
 
     #[test]
-    fn pin_and_unpin_package() {
+    fn record_linked_file_non_transactional() {
         let mut db = Database::in_memory().unwrap();
 
+        // First install the package
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("pinnable", "1.0.0", "abc123", true)
-                .unwrap();
+            tx.record_install("nontx", "1.0.0", "ghi789", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Initially not pinned
-        assert!(!db.is_pinned("pinnable"));
-        let keg = db.get_installed("pinnable").unwrap();
-        assert!(!keg.pinned);
-
-        // Pin the package
-        let result = db.pin("pinnable").unwrap();
-        assert!(result); // Should return true (rows affected)
-        assert!(db.is_pinned("pinnable"));
-
-        // Verify via get_installed
-        let keg = db.get_installed("pinnable").unwrap();
-        assert!(keg.pinned);
-
-        // Unpin the package
-        let result = db.unpin("pinnable").unwrap();
-        assert!(result);
-        assert!(!db.is_pinned("pinnable"));
+        // Use non-transactional record_linked_file
+        db.record_linked_file(
+            "nontx",
+            "1.0.0",
+            "/opt/homebrew/bin/nontx",
+            "/opt/zerobrew/cellar/nontx/1.0.0/bin/nontx",
+        )
+        .unwrap();
 
-        // Verify via get_installed
-        let keg = db.get_installed("pinnable").unwrap();
-        assert!(!keg.pinned);
+        // Verify recorded
+        let links = db.get_linked_files("nontx").unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].0, "/opt/homebrew/bin/nontx");
     }
 
     #[test]
-    fn pin_nonexistent_package_returns_false() {
-        let db = Database::in_memory().unwrap();
+    fn record_linked_file_non_transactional_replaces_existing() {
+        let mut db = Database::in_memory().unwrap();
 
-        // Pinning a non-existent package should return false (no rows affected)
-        let result = db.pin("doesnotexist").unwrap();
-        assert!(!result);
+        // First install the package with a link
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("replace", "1.0.0", "jkl012", true)
+                .unwrap();
+            tx.record_linked_file(
+                "replace",
+                "1.0.0",
+                "/opt/homebrew/bin/replace",
+                "/opt/zerobrew/cellar/replace/1.0.0/bin/replace-old",
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
 
-        // is_pinned should return false for non-existent packages
-        assert!(!db.is_pinned("doesnotexist"));
+        // Use non-transactional to update the same link path
+        db.record_linked_file(
+            "replace",
+            "1.0.0",
+            "/opt/homebrew/bin/replace",
+            "/opt/zerobrew/cellar/replace/1.0.0/bin/replace-new",
+        )
+        .unwrap();
+
+        // Should have 1 link with new target
+        let links = db.get_linked_files("replace").unwrap();
+        assert_eq!(links.len(), 1);
+        assert!(links[0].1.contains("replace-new"));
     }
 
+    // =========================================================================
+    // Service Operations Tests
+    // =========================================================================
+
     #[test]
-    fn list_pinned_only_returns_pinned_packages() {
+    fn record_and_get_service() {
         let mut db = Database::in_memory().unwrap();
 
+        // First install a formula (services have FK to installed_kegs)
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("pinned1", "1.0.0", "abc123", true)
-                .unwrap();
-            tx.record_install("unpinned", "1.0.0", "def456", true)
-                .unwrap();
-            tx.record_install("pinned2", "2.0.0", "ghi789", true)
-                .unwrap();
+            tx.record_install("redis", "7.0.0", "abc123", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Pin two packages
-        db.pin("pinned1").unwrap();
-        db.pin("pinned2").unwrap();
-
-        // list_installed should return all 3
-        let all = db.list_installed().unwrap();
-        assert_eq!(all.len(), 3);
+        // Record a service
+        db.record_service("zerobrew.redis", "redis", Some(r#"{"port": 6379}"#))
+            .unwrap();
 
-        // list_pinned should return only 2
-        let pinned = db.list_pinned().unwrap();
-        assert_eq!(pinned.len(), 2);
-        assert!(pinned.iter().all(|k| k.pinned));
-        assert!(pinned.iter().any(|k| k.name == "pinned1"));
-        assert!(pinned.iter().any(|k| k.name == "pinned2"));
-        assert!(!pinned.iter().any(|k| k.name == "unpinned"));
+        // Get the service
+        let service = db.get_service("zerobrew.redis").unwrap();
+        assert_eq!(service.name, "zerobrew.redis");
+        assert_eq!(service.formula, "redis");
+        assert_eq!(service.status, "stopped"); // Default status
+        assert!(service.pid.is_none());
+        assert!(service.started_at.is_none());
+        assert_eq!(service.config.as_deref(), Some(r#"{"port": 6379}"#));
     }
 
     #[test]
-    fn rollback_leaves_no_partial_state() {
+    fn service_survives_reinstall() {
         let mut db = Database::in_memory().unwrap();
-
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
-            // Don't commit - transaction will be rolled back when dropped
+            tx.record_install("redis", "7.0.0", "abc123", true).unwrap();
+            tx.commit().unwrap();
         }
+        db.record_service("redis", "redis", Some(r#"{"nice": 5}"#))
+            .unwrap();
 
-        let installed = db.list_installed().unwrap();
-        assert!(installed.is_empty());
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("redis", "7.2.0", "def456", true).unwrap();
+            tx.commit().unwrap();
+        }
 
-        // Store ref should also not exist
-        assert_eq!(db.get_store_refcount("abc123"), 0);
+        let service = db.get_service_for_formula("redis").unwrap();
+        assert_eq!(service.config.as_deref(), Some(r#"{"nice": 5}"#));
     }
 
     #[test]
-    fn uninstall_decrements_refcount() {
+    fn record_service_without_config() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "shared123", true)
-                .unwrap();
-            tx.record_install("bar", "2.0.0", "shared123", true)
+            tx.record_install("nginx", "1.25.0", "def456", true)
                 .unwrap();
             tx.commit().unwrap();
         }
 
-        assert_eq!(db.get_store_refcount("shared123"), 2);
+        db.record_service("zerobrew.nginx", "nginx", None).unwrap();
+
+        let service = db.get_service("zerobrew.nginx").unwrap();
+        assert!(service.config.is_none());
+    }
+
+    #[test]
+    fn update_service_status_running() {
+        let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_uninstall("foo").unwrap();
+            tx.record_install("postgres", "16.0", "ghi789", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
-        assert_eq!(db.get_store_refcount("shared123"), 1);
-        assert!(db.get_installed("foo").is_none());
-        assert!(db.get_installed("bar").is_some());
+        db.record_service("zerobrew.postgres", "postgres", None)
+            .unwrap();
+
+        // Update to running with PID
+        let updated = db
+            .update_service_status("zerobrew.postgres", "running", Some(12345))
+            .unwrap();
+        assert!(updated);
+
+        let service = db.get_service("zerobrew.postgres").unwrap();
+        assert_eq!(service.status, "running");
+        assert_eq!(service.pid, Some(12345));
+        assert!(service.started_at.is_some()); // Should have timestamp
     }
 
     #[test]
-    fn get_unreferenced_store_keys() {
+    fn update_service_status_stopped_clears_started_at() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "key1", true).unwrap();
-            tx.record_install("bar", "2.0.0", "key2", true).unwrap();
+            tx.record_install("mysql", "8.0", "jkl012", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Uninstall both
+        db.record_service("zerobrew.mysql", "mysql", None).unwrap();
+
+        // Start then stop
+        db.update_service_status("zerobrew.mysql", "running", Some(9999))
+            .unwrap();
+        db.update_service_status("zerobrew.mysql", "stopped", None)
+            .unwrap();
+
+        let service = db.get_service("zerobrew.mysql").unwrap();
+        assert_eq!(service.status, "stopped");
+        assert!(service.pid.is_none());
+        assert!(service.started_at.is_none());
+    }
+
+    #[test]
+    fn update_nonexistent_service_returns_false() {
+        let db = Database::in_memory().unwrap();
+
+        let updated = db
+            .update_service_status("nonexistent", "running", Some(123))
+            .unwrap();
+        assert!(!updated);
+    }
+
+    #[test]
+    fn get_service_for_formula() {
+        let mut db = Database::in_memory().unwrap();
+
         {
             let tx = db.transaction().unwrap();
-            tx.record_uninstall("foo").unwrap();
-            tx.record_uninstall("bar").unwrap();
+            tx.record_install("memcached", "1.6.0", "mno345", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
-        let unreferenced = db.get_unreferenced_store_keys().unwrap();
-        assert_eq!(unreferenced.len(), 2);
-        assert!(unreferenced.contains(&"key1".to_string()));
-        assert!(unreferenced.contains(&"key2".to_string()));
+        db.record_service("zerobrew.memcached", "memcached", None)
+            .unwrap();
+
+        // Get by formula name
+        let service = db.get_service_for_formula("memcached").unwrap();
+        assert_eq!(service.name, "zerobrew.memcached");
+        assert_eq!(service.formula, "memcached");
+    }
+
+    #[test]
+    fn get_service_for_formula_not_found() {
+        let db = Database::in_memory().unwrap();
+
+        let service = db.get_service_for_formula("nonexistent");
+        assert!(service.is_none());
+    }
+
+    #[test]
+    fn list_services_empty() {
+        let db = Database::in_memory().unwrap();
+
+        let services = db.list_services().unwrap();
+        assert!(services.is_empty());
     }
 
     #[test]
-    fn linked_files_are_recorded() {
+    fn list_services_multiple() {
         let mut db = Database::in_memory().unwrap();
 
+        // Install multiple formulas
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
-            tx.record_linked_file(
-                "foo",
-                "1.0.0",
-                "/opt/homebrew/bin/foo",
-                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
-            )
-            .unwrap();
+            tx.record_install("redis", "7.0", "key1", true).unwrap();
+            tx.record_install("nginx", "1.25", "key2", true).unwrap();
+            tx.record_install("postgres", "16.0", "key3", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Verify via uninstall that removes records
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_uninstall("foo").unwrap();
-            tx.commit().unwrap();
-        }
+        // Record services (in non-alphabetical order to test sorting)
+        db.record_service("zerobrew.redis", "redis", None).unwrap();
+        db.record_service("zerobrew.nginx", "nginx", None).unwrap();
+        db.record_service("zerobrew.postgres", "postgres", None)
+            .unwrap();
 
-        assert!(db.get_installed("foo").is_none());
+        let services = db.list_services().unwrap();
+        assert_eq!(services.len(), 3);
+
+        // Should be sorted by name
+        assert_eq!(services[0].name, "zerobrew.nginx");
+        assert_eq!(services[1].name, "zerobrew.postgres");
+        assert_eq!(services[2].name, "zerobrew.redis");
     }
 
     #[test]
-    fn explicit_vs_dependency_tracking() {
+    fn remove_service() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            // Explicit install
-            tx.record_install("user-requested", "1.0.0", "key1", true)
-                .unwrap();
-            // Dependency installs
-            tx.record_install("dep1", "1.0.0", "key2", false).unwrap();
-            tx.record_install("dep2", "2.0.0", "key3", false).unwrap();
+            tx.record_install("mongodb", "7.0", "pqr678", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Verify explicit flag
-        let user_pkg = db.get_installed("user-requested").unwrap();
-        assert!(user_pkg.explicit);
-
-        let dep1 = db.get_installed("dep1").unwrap();
-        assert!(!dep1.explicit);
+        db.record_service("zerobrew.mongodb", "mongodb", None)
+            .unwrap();
+        assert!(db.get_service("zerobrew.mongodb").is_some());
 
-        let dep2 = db.get_installed("dep2").unwrap();
-        assert!(!dep2.explicit);
+        let removed = db.remove_service("zerobrew.mongodb").unwrap();
+        assert!(removed);
+        assert!(db.get_service("zerobrew.mongodb").is_none());
+    }
 
-        // is_explicit method
-        assert!(db.is_explicit("user-requested"));
-        assert!(!db.is_explicit("dep1"));
-        assert!(!db.is_explicit("dep2"));
-        assert!(!db.is_explicit("nonexistent")); // Not installed
+    #[test]
+    fn remove_nonexistent_service_returns_false() {
+        let db = Database::in_memory().unwrap();
 
-        // list_dependencies should only return deps
-        let deps = db.list_dependencies().unwrap();
-        assert_eq!(deps.len(), 2);
-        assert!(deps.iter().all(|k| !k.explicit));
-        assert!(deps.iter().any(|k| k.name == "dep1"));
-        assert!(deps.iter().any(|k| k.name == "dep2"));
+        let removed = db.remove_service("nonexistent").unwrap();
+        assert!(!removed);
     }
 
     #[test]
-    fn mark_explicit_and_dependency() {
+    fn has_service_returns_correct_status() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("pkg", "1.0.0", "key1", false).unwrap();
+            tx.record_install("elasticsearch", "8.0", "stu901", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
-        // Initially a dependency
-        assert!(!db.is_explicit("pkg"));
-
-        // Mark as explicit (user explicitly installs a previously auto-installed dep)
-        let result = db.mark_explicit("pkg").unwrap();
-        assert!(result);
-        assert!(db.is_explicit("pkg"));
+        assert!(!db.has_service("elasticsearch"));
 
-        // Mark back as dependency
-        let result = db.mark_dependency("pkg").unwrap();
-        assert!(result);
-        assert!(!db.is_explicit("pkg"));
+        db.record_service("zerobrew.elasticsearch", "elasticsearch", None)
+            .unwrap();
 
-        // Non-existent package
-        let result = db.mark_explicit("nonexistent").unwrap();
-        assert!(!result);
+        assert!(db.has_service("elasticsearch"));
+        assert!(!db.has_service("nonexistent"));
     }
 
     #[test]
-    fn get_linked_files_returns_linked_files_for_package() {
+    fn service_foreign_key_cascade_on_formula_uninstall() {
         let mut db = Database::in_memory().unwrap();
 
+        // Install formula and create service
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("test-pkg", "1.0.0", "key1", true)
+            tx.record_install("rabbitmq", "3.12", "vwx234", true)
                 .unwrap();
-            tx.record_linked_file(
-                "test-pkg",
-                "1.0.0",
-                "/opt/zerobrew/prefix/bin/test",
-                "/opt/zerobrew/cellar/test-pkg/1.0.0/bin/test",
-            )
-            .unwrap();
-            tx.record_linked_file(
-                "test-pkg",
-                "1.0.0",
-                "/opt/zerobrew/prefix/lib/libtest.so",
-                "/opt/zerobrew/cellar/test-pkg/1.0.0/lib/libtest.so",
-            )
-            .unwrap();
             tx.commit().unwrap();
         }
 
-        let files = db.get_linked_files("test-pkg").unwrap();
-        assert_eq!(files.len(), 2);
+        db.record_service("zerobrew.rabbitmq", "rabbitmq", Some(r#"{"vhost": "/"}"#))
+            .unwrap();
 
-        // Files should be sorted by link path
-        assert_eq!(files[0].0, "/opt/zerobrew/prefix/bin/test");
-        assert_eq!(files[1].0, "/opt/zerobrew/prefix/lib/libtest.so");
-    }
+        // Verify service exists
+        assert!(db.has_service("rabbitmq"));
 
-    #[test]
-    fn get_linked_files_returns_empty_for_nonexistent_package() {
-        let db = Database::in_memory().unwrap();
-        let files = db.get_linked_files("nonexistent").unwrap();
-        assert!(files.is_empty());
-    }
+        // Uninstall the formula
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("rabbitmq").unwrap();
+            tx.commit().unwrap();
+        }
 
-    // ========== Tap Tests ==========
+        // Service should be cascade deleted due to FK constraint
+        assert!(!db.has_service("rabbitmq"));
+        assert!(db.get_service("zerobrew.rabbitmq").is_none());
+    }
 
     #[test]
-    fn add_and_list_taps() {
-        let db = Database::in_memory().unwrap();
+    fn record_service_replaces_existing() {
+        let mut db = Database::in_memory().unwrap();
 
-        // Initially no taps
-        let taps = db.list_taps().unwrap();
-        assert!(taps.is_empty());
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("kafka", "3.5", "yza567", true).unwrap();
+            tx.commit().unwrap();
+        }
 
-        // Add a tap
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+        // Record service with config
+        db.record_service("zerobrew.kafka", "kafka", Some(r#"{"brokers": 1}"#))
             .unwrap();
 
-        // Should be listed
-        let taps = db.list_taps().unwrap();
-        assert_eq!(taps.len(), 1);
-        assert_eq!(taps[0].name, "user/repo");
-        assert_eq!(taps[0].url, "https://github.com/user/homebrew-repo");
-        assert!(taps[0].added_at > 0);
-    }
-
-    #[test]
-    fn is_tapped_returns_correct_status() {
-        let db = Database::in_memory().unwrap();
-
-        assert!(!db.is_tapped("user/repo"));
-
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
+        // Re-record with different config (should replace)
+        db.record_service("zerobrew.kafka", "kafka", Some(r#"{"brokers": 3}"#))
             .unwrap();
 
-        assert!(db.is_tapped("user/repo"));
-        assert!(!db.is_tapped("other/tap"));
+        let service = db.get_service("zerobrew.kafka").unwrap();
+        assert_eq!(service.config.as_deref(), Some(r#"{"brokers": 3}"#));
+        // Status should be reset to stopped
+        assert_eq!(service.status, "stopped");
     }
 
+    // =========================================================================
+    // File-based Database Tests
+    // =========================================================================
+
     #[test]
-    fn get_tap_returns_tap_info() {
-        let db = Database::in_memory().unwrap();
+    fn database_open_creates_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("test.db");
 
-        assert!(db.get_tap("user/repo").is_none());
+        assert!(!db_path.exists());
 
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
-            .unwrap();
+        let db = Database::open(&db_path).unwrap();
+        drop(db);
 
-        let tap = db.get_tap("user/repo").unwrap();
-        assert_eq!(tap.name, "user/repo");
-        assert_eq!(tap.url, "https://github.com/user/homebrew-repo");
+        assert!(db_path.exists());
     }
 
     #[test]
-    fn remove_tap_deletes_tap() {
-        let db = Database::in_memory().unwrap();
+    fn database_open_persists_data() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path().join("persist.db");
 
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
-            .unwrap();
-        assert!(db.is_tapped("user/repo"));
+        // Create and populate database
+        {
+            let mut db = Database::open(&db_path).unwrap();
+            let tx = db.transaction().unwrap();
+            tx.record_install("persistent-pkg", "1.0.0", "key123", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
 
-        let removed = db.remove_tap("user/repo").unwrap();
-        assert!(removed);
-        assert!(!db.is_tapped("user/repo"));
+        // Reopen and verify data persisted
+        {
+            let db = Database::open(&db_path).unwrap();
+            let installed = db.get_installed("persistent-pkg");
+            assert!(installed.is_some());
+            assert_eq!(installed.unwrap().version, "1.0.0");
+        }
     }
 
+    // =========================================================================
+    // Edge Cases and Error Paths
+    // =========================================================================
+
     #[test]
-    fn remove_tap_returns_false_for_nonexistent() {
+    fn get_installed_nonexistent_returns_none() {
         let db = Database::in_memory().unwrap();
-
-        let removed = db.remove_tap("nonexistent/tap").unwrap();
-        assert!(!removed);
+        assert!(db.get_installed("nonexistent").is_none());
     }
 
     #[test]
-    fn add_tap_updates_existing() {
+    fn list_installed_empty_returns_empty_vec() {
         let db = Database::in_memory().unwrap();
-
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo")
-            .unwrap();
-
-        // Add again with different URL (should update)
-        db.add_tap("user/repo", "https://github.com/user/homebrew-repo-new")
-            .unwrap();
-
-        let tap = db.get_tap("user/repo").unwrap();
-        assert_eq!(tap.url, "https://github.com/user/homebrew-repo-new");
-
-        // Should still be just one tap
-        let taps = db.list_taps().unwrap();
-        assert_eq!(taps.len(), 1);
+        let installed = db.list_installed().unwrap();
+        assert!(installed.is_empty());
     }
 
     #[test]
-    fn list_taps_sorted_by_name() {
+    fn list_pinned_empty_returns_empty_vec() {
         let db = Database::in_memory().unwrap();
+        let pinned = db.list_pinned().unwrap();
+        assert!(pinned.is_empty());
+    }
 
-        db.add_tap("zulu/tools", "https://github.com/zulu/homebrew-tools")
-            .unwrap();
-        db.add_tap("alpha/utils", "https://github.com/alpha/homebrew-utils")
-            .unwrap();
-        db.add_tap("mike/apps", "https://github.com/mike/homebrew-apps")
-            .unwrap();
+    #[test]
+    fn list_dependencies_empty_returns_empty_vec() {
+        let db = Database::in_memory().unwrap();
+        let deps = db.list_dependencies().unwrap();
+        assert!(deps.is_empty());
+    }
 
-        let taps = db.list_taps().unwrap();
-        assert_eq!(taps.len(), 3);
-        assert_eq!(taps[0].name, "alpha/utils");
-        assert_eq!(taps[1].name, "mike/apps");
-        assert_eq!(taps[2].name, "zulu/tools");
+    #[test]
+    fn get_store_refcount_nonexistent_returns_zero() {
+        let db = Database::in_memory().unwrap();
+        assert_eq!(db.get_store_refcount("nonexistent-key"), 0);
     }
 
     #[test]
-    fn clear_linked_files_removes_all_links_for_package() {
+    fn uninstall_nonexistent_package_returns_none() {
         let mut db = Database::in_memory().unwrap();
 
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_install("linktest", "1.0.0", "abc123", true)
-                .unwrap();
-            tx.record_linked_file(
-                "linktest",
-                "1.0.0",
-                "/opt/homebrew/bin/linktest",
-                "/opt/zerobrew/cellar/linktest/1.0.0/bin/linktest",
-            )
-            .unwrap();
-            tx.record_linked_file(
-                "linktest",
-                "1.0.0",
-                "/opt/homebrew/bin/linktest-tool",
-                "/opt/zerobrew/cellar/linktest/1.0.0/bin/linktest-tool",
-            )
-            .unwrap();
-            tx.commit().unwrap();
-        }
-
-        // Verify links recorded
-        let links = db.get_linked_files("linktest").unwrap();
-        assert_eq!(links.len(), 2);
-
-        // Clear links
-        let cleared = db.clear_linked_files("linktest").unwrap();
-        assert_eq!(cleared, 2);
-
-        // Verify links removed
-        let links_after = db.get_linked_files("linktest").unwrap();
-        assert!(links_after.is_empty());
-
-        // Package still installed
-        assert!(db.get_installed("linktest").is_some());
+        let tx = db.transaction().unwrap();
+        let store_key = tx.record_uninstall("nonexistent").unwrap();
+        assert!(store_key.is_none());
+        tx.commit().unwrap();
     }
 
     #[test]
-    fn clear_linked_files_returns_zero_for_no_links() {
+    fn refcount_cannot_go_negative() {
         let mut db = Database::in_memory().unwrap();
 
+        // Install and uninstall a package
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("nolinks", "1.0.0", "def456", true)
+            tx.record_install("refcount-test", "1.0", "refkey", true)
                 .unwrap();
             tx.commit().unwrap();
         }
 
-        // No links to clear
-        let cleared = db.clear_linked_files("nolinks").unwrap();
-        assert_eq!(cleared, 0);
-    }
-
-    #[test]
-    fn record_linked_file_non_transactional() {
-        let mut db = Database::in_memory().unwrap();
-
-        // First install the package
+        // Uninstall twice (should not cause negative refcount)
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("nontx", "1.0.0", "ghi789", true).unwrap();
+            tx.record_uninstall("refcount-test").unwrap();
             tx.commit().unwrap();
         }
 
-        // Use non-transactional record_linked_file
-        db.record_linked_file(
-            "nontx",
-            "1.0.0",
-            "/opt/homebrew/bin/nontx",
-            "/opt/zerobrew/cellar/nontx/1.0.0/bin/nontx",
-        )
-        .unwrap();
+        // Refcount should be 0, not negative
+        assert_eq!(db.get_store_refcount("refkey"), 0);
 
-        // Verify recorded
-        let links = db.get_linked_files("nontx").unwrap();
-        assert_eq!(links.len(), 1);
-        assert_eq!(links[0].0, "/opt/homebrew/bin/nontx");
+        // Try to "uninstall" again by directly manipulating (shouldn't go negative)
+        db.conn
+            .execute(
+                "UPDATE store_refs SET refcount = MAX(refcount - 1, 0) WHERE store_key = ?1",
+                params!["refkey"],
+            )
+            .unwrap();
+
+        assert_eq!(db.get_store_refcount("refkey"), 0);
     }
 
     #[test]
-    fn record_linked_file_non_transactional_replaces_existing() {
+    fn multiple_transactions_sequential() {
         let mut db = Database::in_memory().unwrap();
 
-        // First install the package with a link
+        // First transaction
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("replace", "1.0.0", "jkl012", true)
-                .unwrap();
-            tx.record_linked_file(
-                "replace",
-                "1.0.0",
-                "/opt/homebrew/bin/replace",
-                "/opt/zerobrew/cellar/replace/1.0.0/bin/replace-old",
-            )
-            .unwrap();
+            tx.record_install("pkg1", "1.0", "key1", true).unwrap();
             tx.commit().unwrap();
         }
 
-        // Use non-transactional to update the same link path
-        db.record_linked_file(
-            "replace",
-            "1.0.0",
-            "/opt/homebrew/bin/replace",
-            "/opt/zerobrew/cellar/replace/1.0.0/bin/replace-new",
-        )
-        .unwrap();
+        // Second transaction
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("pkg2", "1.0", "key2", true).unwrap();
+            tx.commit().unwrap();
+        }
 
-        // Should have 1 link with new target
-        let links = db.get_linked_files("replace").unwrap();
-        assert_eq!(links.len(), 1);
-        assert!(links[0].1.contains("replace-new"));
+        let installed = db.list_installed().unwrap();
+        assert_eq!(installed.len(), 2);
     }
 
-    // =========================================================================
-    // Service Operations Tests
-    // =========================================================================
-
     #[test]
-    fn record_and_get_service() {
+    fn install_same_package_twice_updates() {
         let mut db = Database::in_memory().unwrap();
 
-        // First install a formula (services have FK to installed_kegs)
+        // First install
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("redis", "7.0.0", "abc123", true).unwrap();
+            tx.record_install("upgrade-me", "1.0.0", "oldkey", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
-        // Record a service
-        db.record_service("zerobrew.redis", "redis", Some(r#"{"port": 6379}"#))
-            .unwrap();
-
-        // Get the service
-        let service = db.get_service("zerobrew.redis").unwrap();
-        assert_eq!(service.name, "zerobrew.redis");
-        assert_eq!(service.formula, "redis");
-        assert_eq!(service.status, "stopped"); // Default status
-        assert!(service.pid.is_none());
-        assert!(service.started_at.is_none());
-        assert_eq!(service.config.as_deref(), Some(r#"{"port": 6379}"#));
-    }
-
-    #[test]
-    fn record_service_without_config() {
-        let mut db = Database::in_memory().unwrap();
+        assert_eq!(db.get_installed("upgrade-me").unwrap().version, "1.0.0");
+        assert_eq!(db.get_store_refcount("oldkey"), 1);
 
+        // "Upgrade" by installing again with new version
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("nginx", "1.25.0", "def456", true)
+            tx.record_install("upgrade-me", "2.0.0", "newkey", true)
                 .unwrap();
             tx.commit().unwrap();
         }
 
-        db.record_service("zerobrew.nginx", "nginx", None).unwrap();
+        // Should have new version
+        let pkg = db.get_installed("upgrade-me").unwrap();
+        assert_eq!(pkg.version, "2.0.0");
+        assert_eq!(pkg.store_key, "newkey");
 
-        let service = db.get_service("zerobrew.nginx").unwrap();
-        assert!(service.config.is_none());
+        // New key should have refcount 1
+        assert_eq!(db.get_store_refcount("newkey"), 1);
+        // Old key still has refcount 1 (not decremented by record_install)
+        assert_eq!(db.get_store_refcount("oldkey"), 1);
     }
 
     #[test]
-    fn update_service_status_running() {
+    fn service_status_transitions() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("postgres", "16.0", "ghi789", true)
+            tx.record_install("status-test", "1.0", "bcd890", true)
                 .unwrap();
             tx.commit().unwrap();
         }
 
-        db.record_service("zerobrew.postgres", "postgres", None)
+        db.record_service("zerobrew.status-test", "status-test", None)
             .unwrap();
 
-        // Update to running with PID
-        let updated = db
-            .update_service_status("zerobrew.postgres", "running", Some(12345))
+        // Initial: stopped
+        assert_eq!(
+            db.get_service("zerobrew.status-test").unwrap().status,
+            "stopped"
+        );
+
+        // Transition: stopped -> running
+        db.update_service_status("zerobrew.status-test", "running", Some(1000))
             .unwrap();
-        assert!(updated);
+        let svc = db.get_service("zerobrew.status-test").unwrap();
+        assert_eq!(svc.status, "running");
+        assert!(svc.started_at.is_some());
 
-        let service = db.get_service("zerobrew.postgres").unwrap();
-        assert_eq!(service.status, "running");
-        assert_eq!(service.pid, Some(12345));
-        assert!(service.started_at.is_some()); // Should have timestamp
+        // Transition: running -> error
+        db.update_service_status("zerobrew.status-test", "error", None)
+            .unwrap();
+        let svc = db.get_service("zerobrew.status-test").unwrap();
+        assert_eq!(svc.status, "error");
+        assert!(svc.started_at.is_none()); // cleared for non-running
+
+        // Transition: error -> running
+        db.update_service_status("zerobrew.status-test", "running", Some(2000))
+            .unwrap();
+        let svc = db.get_service("zerobrew.status-test").unwrap();
+        assert_eq!(svc.status, "running");
+        assert_eq!(svc.pid, Some(2000));
     }
 
     #[test]
-    fn update_service_status_stopped_clears_started_at() {
+    fn list_installed_sorted_alphabetically() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("mysql", "8.0", "jkl012", true).unwrap();
+            tx.record_install("zebra", "1.0", "z", true).unwrap();
+            tx.record_install("alpha", "1.0", "a", true).unwrap();
+            tx.record_install("middle", "1.0", "m", true).unwrap();
             tx.commit().unwrap();
         }
 
-        db.record_service("zerobrew.mysql", "mysql", None).unwrap();
-
-        // Start then stop
-        db.update_service_status("zerobrew.mysql", "running", Some(9999))
-            .unwrap();
-        db.update_service_status("zerobrew.mysql", "stopped", None)
-            .unwrap();
-
-        let service = db.get_service("zerobrew.mysql").unwrap();
-        assert_eq!(service.status, "stopped");
-        assert!(service.pid.is_none());
-        assert!(service.started_at.is_none());
-    }
-
-    #[test]
-    fn update_nonexistent_service_returns_false() {
-        let db = Database::in_memory().unwrap();
-
-        let updated = db
-            .update_service_status("nonexistent", "running", Some(123))
-            .unwrap();
-        assert!(!updated);
+        let installed = db.list_installed().unwrap();
+        assert_eq!(installed[0].name, "alpha");
+        assert_eq!(installed[1].name, "middle");
+        assert_eq!(installed[2].name, "zebra");
     }
 
     #[test]
-    fn get_service_for_formula() {
+    fn linked_files_with_special_characters_in_paths() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("memcached", "1.6.0", "mno345", true)
+            tx.record_install("special-pkg", "1.0", "special123", true)
                 .unwrap();
+            tx.record_linked_file(
+                "special-pkg",
+                "1.0",
+                "/opt/homebrew/bin/special pkg",
+                "/opt/zerobrew/cellar/special-pkg/1.0/bin/special pkg",
+            )
+            .unwrap();
+            tx.record_linked_file(
+                "special-pkg",
+                "1.0",
+                "/opt/homebrew/lib/lib'quoted'.so",
+                "/opt/zerobrew/cellar/special-pkg/1.0/lib/lib'quoted'.so",
+            )
+            .unwrap();
             tx.commit().unwrap();
         }
 
-        db.record_service("zerobrew.memcached", "memcached", None)
-            .unwrap();
-
-        // Get by formula name
-        let service = db.get_service_for_formula("memcached").unwrap();
-        assert_eq!(service.name, "zerobrew.memcached");
-        assert_eq!(service.formula, "memcached");
+        let files = db.get_linked_files("special-pkg").unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().any(|(l, _)| l.contains("special pkg")));
+        assert!(files.iter().any(|(l, _)| l.contains("'quoted'")));
     }
 
     #[test]
-    fn get_service_for_formula_not_found() {
+    fn tap_operations_with_various_formats() {
         let db = Database::in_memory().unwrap();
 
-        let service = db.get_service_for_formula("nonexistent");
-        assert!(service.is_none());
+        // Standard format
+        db.add_tap("homebrew/core", "https://github.com/homebrew/homebrew-core")
+            .unwrap();
+
+        // With dashes
+        db.add_tap("user/my-tap", "https://github.com/user/homebrew-my-tap")
+            .unwrap();
+
+        // Longer path
+        db.add_tap("org/team/repo", "https://github.com/org/team-homebrew-repo")
+            .unwrap();
+
+        let taps = db.list_taps().unwrap();
+        assert_eq!(taps.len(), 3);
+    }
+
+    // =========================================================================
+    // Install Statistics Tests
+    // =========================================================================
+
+    fn sample_timings() -> InstallTimings {
+        use crate::timing::PackageTiming;
+        use std::time::Duration;
+
+        InstallTimings {
+            resolve: Duration::from_millis(100),
+            execute: Duration::from_millis(900),
+            packages: vec![
+                PackageTiming {
+                    name: "jq".to_string(),
+                    version: "1.7".to_string(),
+                    download: Duration::from_millis(400),
+                    extract: Duration::from_millis(200),
+                    link: Duration::from_millis(10),
+                    bytes: 1000,
+                    blob_cached: false,
+                    store_reused: false,
+                    upgraded: false,
+                },
+                PackageTiming {
+                    name: "oniguruma".to_string(),
+                    version: "6.9".to_string(),
+                    download: Duration::from_millis(0),
+                    extract: Duration::from_millis(100),
+                    link: Duration::from_millis(30),
+                    bytes: 500,
+                    blob_cached: true,
+                    store_reused: true,
+                    upgraded: true,
+                },
+            ],
+            spans: Vec::new(),
+        }
     }
 
     #[test]
-    fn list_services_empty() {
+    fn install_stats_empty_history() {
         let db = Database::in_memory().unwrap();
+        let stats = db.install_stats().unwrap();
 
-        let services = db.list_services().unwrap();
-        assert!(services.is_empty());
+        assert_eq!(stats, InstallStats::default());
+        assert_eq!(stats.cache_hit_rate(), 0.0);
     }
 
     #[test]
-    fn list_services_multiple() {
+    fn install_stats_aggregates_recorded_timings() {
         let mut db = Database::in_memory().unwrap();
 
-        // Install multiple formulas
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_install("redis", "7.0", "key1", true).unwrap();
-            tx.record_install("nginx", "1.25", "key2", true).unwrap();
-            tx.record_install("postgres", "16.0", "key3", true).unwrap();
-            tx.commit().unwrap();
-        }
-
-        // Record services (in non-alphabetical order to test sorting)
-        db.record_service("zerobrew.redis", "redis", None).unwrap();
-        db.record_service("zerobrew.nginx", "nginx", None).unwrap();
-        db.record_service("zerobrew.postgres", "postgres", None)
-            .unwrap();
-
-        let services = db.list_services().unwrap();
-        assert_eq!(services.len(), 3);
+        let tx = db.transaction().unwrap();
+        tx.record_install_timings("jq", &sample_timings()).unwrap();
+        tx.commit().unwrap();
 
-        // Should be sorted by name
-        assert_eq!(services[0].name, "zerobrew.nginx");
-        assert_eq!(services[1].name, "zerobrew.postgres");
-        assert_eq!(services[2].name, "zerobrew.redis");
+        let stats = db.install_stats().unwrap();
+        assert_eq!(stats.runs, 1);
+        assert_eq!(stats.packages, 2);
+        assert_eq!(stats.avg_run_ms, 1000.0);
+        assert_eq!(stats.avg_resolve_ms, 100.0);
+        assert_eq!(stats.avg_download_ms, 200.0);
+        assert_eq!(stats.avg_extract_ms, 150.0);
+        assert_eq!(stats.avg_link_ms, 20.0);
+        assert_eq!(stats.blob_cache_hits, 1);
+        assert_eq!(stats.store_reuses, 1);
+        assert_eq!(stats.bytes_downloaded, 1000);
+        assert_eq!(stats.bytes_saved, 500);
+        assert_eq!(stats.cache_hit_rate(), 0.5);
     }
 
     #[test]
-    fn remove_service() {
+    fn install_activity_groups_by_month() {
         let mut db = Database::in_memory().unwrap();
+        assert!(db.install_activity_by_month().unwrap().is_empty());
 
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_install("mongodb", "7.0", "pqr678", true).unwrap();
-            tx.commit().unwrap();
-        }
+        let tx = db.transaction().unwrap();
+        tx.record_install_timings("jq", &sample_timings()).unwrap();
+        tx.record_install_timings("jq", &sample_timings()).unwrap();
+        tx.commit().unwrap();
 
-        db.record_service("zerobrew.mongodb", "mongodb", None)
+        // Backdate the first run to a fixed month
+        db.conn
+            .execute(
+                "UPDATE install_runs SET started_at = 1767225600 WHERE id = 1",
+                [],
+            )
             .unwrap();
-        assert!(db.get_service("zerobrew.mongodb").is_some());
 
-        let removed = db.remove_service("zerobrew.mongodb").unwrap();
-        assert!(removed);
-        assert!(db.get_service("zerobrew.mongodb").is_none());
+        let activity = db.install_activity_by_month().unwrap();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(
+            activity[0],
+            MonthlyActivity {
+                month: "2026-01".to_string(),
+                installs: 1,
+                upgrades: 1,
+            }
+        );
+        assert_eq!(activity[1].installs, 1);
+        assert_eq!(activity[1].upgrades, 1);
     }
 
     #[test]
-    fn remove_nonexistent_service_returns_false() {
-        let db = Database::in_memory().unwrap();
+    fn list_all_versions_includes_inactive_kegs() {
+        let mut db = Database::in_memory().unwrap();
 
-        let removed = db.remove_service("nonexistent").unwrap();
-        assert!(!removed);
+        let tx = db.transaction().unwrap();
+        tx.record_install("jq", "1.6", "key-16", true).unwrap();
+        tx.record_install("jq", "1.7", "key-17", true).unwrap();
+        tx.record_install("wget", "1.24", "key-wget", true).unwrap();
+        tx.commit().unwrap();
+
+        let versions: Vec<(String, String)> = db
+            .list_all_versions()
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.name, v.version))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                ("jq".to_string(), "1.6".to_string()),
+                ("jq".to_string(), "1.7".to_string()),
+                ("wget".to_string(), "1.24".to_string()),
+            ]
+        );
     }
 
     #[test]
-    fn has_service_returns_correct_status() {
+    fn clear_install_stats_removes_history() {
         let mut db = Database::in_memory().unwrap();
 
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_install("elasticsearch", "8.0", "stu901", true)
-                .unwrap();
-            tx.commit().unwrap();
+        let tx = db.transaction().unwrap();
+        tx.record_install_timings("jq", &sample_timings()).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(db.clear_install_stats().unwrap(), 1);
+        assert_eq!(db.install_stats().unwrap().packages, 0);
+    }
+
+    // =========================================================================
+    // Pour Log Tests
+    // =========================================================================
+
+    fn pour_event(name: &str, kind: PourEventKind) -> PourEvent {
+        PourEvent {
+            at: 0,
+            kind,
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            url: format!("https://mirror.example.com/{name}.tar.gz"),
+            sha256: "abc".to_string(),
+            bytes: 100,
+            duration_ms: 20,
+            relocated: true,
+            detail: kind.is_integrity_failure().then(|| "bad".to_string()),
         }
+    }
 
-        assert!(!db.has_service("elasticsearch"));
+    #[test]
+    fn pour_log_keeps_events_newest_first() {
+        let mut db = Database::in_memory().unwrap();
 
-        db.record_service("zerobrew.elasticsearch", "elasticsearch", None)
+        let tx = db.transaction().unwrap();
+        tx.record_pour_events(&[pour_event("jq", PourEventKind::Poured)])
+            .unwrap();
+        tx.commit().unwrap();
+        db.record_pour_event(&pour_event("wget", PourEventKind::ChecksumMismatch))
             .unwrap();
 
-        assert!(db.has_service("elasticsearch"));
-        assert!(!db.has_service("nonexistent"));
+        let events = db.pour_events(10).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "wget");
+        assert_eq!(events[0].kind, PourEventKind::ChecksumMismatch);
+        assert_eq!(events[0].detail.as_deref(), Some("bad"));
+        assert!(events[1].at > 0);
+        assert!(events[1].relocated);
+        assert_eq!(db.pour_events(1).unwrap().len(), 1);
+
+        let failures = db.integrity_failures_since(0).unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(db.integrity_failures_since(i64::MAX).unwrap().is_empty());
+
+        assert_eq!(
+            db.pour_stats().unwrap(),
+            PourStats {
+                poured: 1,
+                relocated: 1,
+                integrity_failures: 1,
+                bytes: 100,
+            }
+        );
     }
 
     #[test]
-    fn service_foreign_key_cascade_on_formula_uninstall() {
+    fn pour_log_outlives_install_stats_and_rollbacks() {
         let mut db = Database::in_memory().unwrap();
 
-        // Install formula and create service
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("rabbitmq", "3.12", "vwx234", true)
+            tx.record_pour_events(&[pour_event("jq", PourEventKind::Poured)])
                 .unwrap();
-            tx.commit().unwrap();
+            // Dropped without commit
         }
-
-        db.record_service("zerobrew.rabbitmq", "rabbitmq", Some(r#"{"vhost": "/"}"#))
+        db.record_pour_event(&pour_event("jq", PourEventKind::CorruptArchive))
             .unwrap();
+        db.clear_install_stats().unwrap();
 
-        // Verify service exists
-        assert!(db.has_service("rabbitmq"));
+        let events = db.pour_events(10).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, PourEventKind::CorruptArchive);
+    }
 
-        // Uninstall the formula
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_uninstall("rabbitmq").unwrap();
-            tx.commit().unwrap();
-        }
+    // =========================================================================
+    // Attestation Tests
+    // =========================================================================
 
-        // Service should be cascade deleted due to FK constraint
-        assert!(!db.has_service("rabbitmq"));
-        assert!(db.get_service("zerobrew.rabbitmq").is_none());
+    #[test]
+    fn attestations_are_kept_per_store_entry() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.attestation("abc").is_none());
+
+        let failed = Attestation {
+            status: AttestationStatus::Failed,
+            repo: Some("Homebrew/homebrew-core".to_string()),
+            detail: Some("no attestations found".to_string()),
+            checked_at: 100,
+        };
+        db.record_attestation("abc", &failed).unwrap();
+        assert_eq!(db.attestation("abc"), Some(failed));
+
+        let verified = Attestation {
+            status: AttestationStatus::Verified,
+            repo: Some("Homebrew/homebrew-core".to_string()),
+            detail: None,
+            checked_at: 200,
+        };
+        db.record_attestation("abc", &verified).unwrap();
+        assert_eq!(db.attestation("abc"), Some(verified));
+        assert!(db.attestation("def").is_none());
     }
 
+    // =========================================================================
+    // Inventory Tests
+    // =========================================================================
+
     #[test]
-    fn record_service_replaces_existing() {
+    fn inventory_is_sorted_by_name_date_or_size() {
         let mut db = Database::in_memory().unwrap();
-
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("kafka", "3.5", "yza567", true).unwrap();
+            tx.record_install("jq", "1.7.1", "jq-key", true).unwrap();
+            tx.record_install("oniguruma", "6.9.9", "onig-key", false)
+                .unwrap();
+            tx.record_install("wget", "1.24.5", "wget-key", true)
+                .unwrap();
             tx.commit().unwrap();
         }
+        for (name, installed_at) in [("jq", 300), ("oniguruma", 100), ("wget", 200)] {
+            db.conn
+                .execute(
+                    "UPDATE installed_kegs SET installed_at = ?2 WHERE name = ?1",
+                    params![name, installed_at],
+                )
+                .unwrap();
+        }
 
-        // Record service with config
-        db.record_service("zerobrew.kafka", "kafka", Some(r#"{"brokers": 1}"#))
-            .unwrap();
+        let not_measured: Vec<String> = db
+            .list_unsized_kegs()
+            .unwrap()
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect();
+        assert_eq!(not_measured, ["jq", "oniguruma", "wget"]);
+
+        db.record_store_size("onig-key", 2048).unwrap();
+        db.record_store_size("jq-key", 512).unwrap();
+        assert_eq!(db.list_unsized_kegs().unwrap().len(), 1);
+
+        let order = |sort| -> Vec<(String, Option<u64>)> {
+            db.list_inventory(sort)
+                .unwrap()
+                .into_iter()
+                .map(|(keg, bytes)| (keg.name, bytes))
+                .collect()
+        };
+        assert_eq!(
+            order(InventorySort::Name),
+            [
+                ("jq".to_string(), Some(512)),
+                ("oniguruma".to_string(), Some(2048)),
+                ("wget".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            order(InventorySort::Date)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            ["jq", "wget", "oniguruma"]
+        );
+        assert_eq!(
+            order(InventorySort::Size)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            ["oniguruma", "jq", "wget"]
+        );
+    }
 
-        // Re-record with different config (should replace)
-        db.record_service("zerobrew.kafka", "kafka", Some(r#"{"brokers": 3}"#))
+    // =========================================================================
+    // Benchmark Tests
+    // =========================================================================
+
+    #[test]
+    fn list_benchmarks_returns_most_recent_first() {
+        let db = Database::in_memory().unwrap();
+
+        for total_ms in [300, 200] {
+            db.record_benchmark(&BenchmarkRecord {
+                formula: "jq".to_string(),
+                iterations: 3,
+                total_ms,
+                ..Default::default()
+            })
             .unwrap();
+        }
+        db.record_benchmark(&BenchmarkRecord {
+            formula: "wget".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
 
-        let service = db.get_service("zerobrew.kafka").unwrap();
-        assert_eq!(service.config.as_deref(), Some(r#"{"brokers": 3}"#));
-        // Status should be reset to stopped
-        assert_eq!(service.status, "stopped");
+        let runs = db.list_benchmarks("jq", 10).unwrap();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].total_ms, 200);
+        assert_eq!(runs[0].iterations, 3);
+        assert_eq!(runs[1].total_ms, 300);
+
+        assert_eq!(db.list_benchmarks("jq", 1).unwrap().len(), 1);
+        assert!(db.list_benchmarks("git", 10).unwrap().is_empty());
     }
 
     // =========================================================================
-    // File-based Database Tests
+    // Keg Version Tests
     // =========================================================================
 
-    #[test]
-    fn database_open_creates_file() {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let db_path = temp_dir.path().join("test.db");
+    fn install(db: &mut Database, name: &str, version: &str, store_key: &str) {
+        let tx = db.transaction().unwrap();
+        tx.record_install(name, version, store_key, true).unwrap();
+        tx.commit().unwrap();
+    }
 
-        assert!(!db_path.exists());
+    #[test]
+    fn upgrade_keeps_old_version_listed() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.6", "oldkey");
+        install(&mut db, "jq", "1.7", "newkey");
 
-        let db = Database::open(&db_path).unwrap();
-        drop(db);
+        let versions: Vec<_> = db
+            .list_versions("jq")
+            .unwrap()
+            .into_iter()
+            .map(|v| v.version)
+            .collect();
+        assert_eq!(versions, vec!["1.6", "1.7"]);
+
+        let inactive = db.list_inactive_versions().unwrap();
+        assert_eq!(inactive.len(), 1);
+        assert_eq!(inactive[0].version, "1.6");
+        assert_eq!(inactive[0].store_key, "oldkey");
+    }
 
-        assert!(db_path.exists());
+    #[test]
+    fn reinstalling_same_version_does_not_add_ref() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.7", "key1");
+        install(&mut db, "jq", "1.7", "key1");
+        assert_eq!(db.get_store_refcount("key1"), 1);
+
+        // A rebuilt bottle for the same version moves the ref
+        install(&mut db, "jq", "1.7", "key2");
+        assert_eq!(db.get_store_refcount("key1"), 0);
+        assert_eq!(db.get_store_refcount("key2"), 1);
     }
 
     #[test]
-    fn database_open_persists_data() {
-        let temp_dir = tempfile::tempdir().unwrap();
-        let db_path = temp_dir.path().join("persist.db");
+    fn record_keg_version_keeps_active_version() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.7", "newkey");
 
-        // Create and populate database
         {
-            let mut db = Database::open(&db_path).unwrap();
             let tx = db.transaction().unwrap();
-            tx.record_install("persistent-pkg", "1.0.0", "key123", true)
-                .unwrap();
+            tx.record_keg_version("jq", "1.6", "oldkey").unwrap();
             tx.commit().unwrap();
         }
 
-        // Reopen and verify data persisted
-        {
-            let db = Database::open(&db_path).unwrap();
-            let installed = db.get_installed("persistent-pkg");
-            assert!(installed.is_some());
-            assert_eq!(installed.unwrap().version, "1.0.0");
-        }
+        assert_eq!(db.get_installed("jq").unwrap().version, "1.7");
+        assert_eq!(db.list_inactive_versions().unwrap()[0].version, "1.6");
+        assert_eq!(db.get_store_refcount("oldkey"), 1);
+        assert_eq!(db.get_store_refcount("newkey"), 1);
     }
 
-    // =========================================================================
-    // Edge Cases and Error Paths
-    // =========================================================================
+    #[test]
+    fn record_switch_changes_active_version() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.6", "oldkey");
+        install(&mut db, "jq", "1.7", "newkey");
+        db.record_linked_file("jq", "1.7", "/bin/jq", "/cellar/jq/1.7/bin/jq")
+            .unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_switch("jq", "1.6").unwrap();
+            tx.commit().unwrap();
+        }
 
-    #[test]
-    fn get_installed_nonexistent_returns_none() {
-        let db = Database::in_memory().unwrap();
-        assert!(db.get_installed("nonexistent").is_none());
-    }
+        let keg = db.get_installed("jq").unwrap();
+        assert_eq!(keg.version, "1.6");
+        assert_eq!(keg.store_key, "oldkey");
+        assert!(db.get_linked_files("jq").unwrap().is_empty());
+        assert_eq!(db.list_inactive_versions().unwrap()[0].version, "1.7");
 
-    #[test]
-    fn list_installed_empty_returns_empty_vec() {
-        let db = Database::in_memory().unwrap();
-        let installed = db.list_installed().unwrap();
-        assert!(installed.is_empty());
+        let tx = db.transaction().unwrap();
+        assert!(matches!(
+            tx.record_switch("jq", "9.9"),
+            Err(Error::NotInstalled { .. })
+        ));
     }
 
     #[test]
-    fn list_pinned_empty_returns_empty_vec() {
-        let db = Database::in_memory().unwrap();
-        let pinned = db.list_pinned().unwrap();
-        assert!(pinned.is_empty());
+    fn uninstall_version_releases_its_ref() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.6", "oldkey");
+        install(&mut db, "jq", "1.7", "newkey");
+
+        {
+            let tx = db.transaction().unwrap();
+            assert_eq!(
+                tx.record_uninstall_version("jq", "1.6").unwrap().as_deref(),
+                Some("oldkey")
+            );
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(db.get_store_refcount("oldkey"), 0);
+        assert_eq!(db.get_store_refcount("newkey"), 1);
+        assert_eq!(db.get_installed("jq").unwrap().version, "1.7");
+        assert_eq!(db.list_versions("jq").unwrap().len(), 1);
     }
 
     #[test]
-    fn list_dependencies_empty_returns_empty_vec() {
-        let db = Database::in_memory().unwrap();
-        let deps = db.list_dependencies().unwrap();
-        assert!(deps.is_empty());
+    fn uninstall_releases_every_version() {
+        let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.6", "oldkey");
+        install(&mut db, "jq", "1.7", "newkey");
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("jq").unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert!(db.list_versions("jq").unwrap().is_empty());
+        assert_eq!(db.get_store_refcount("oldkey"), 0);
+        assert_eq!(db.get_store_refcount("newkey"), 0);
     }
 
     #[test]
-    fn get_store_refcount_nonexistent_returns_zero() {
+    fn keg_versions_are_backfilled_from_installed_kegs() {
         let db = Database::in_memory().unwrap();
-        assert_eq!(db.get_store_refcount("nonexistent-key"), 0);
+        // Simulate a database created before keg_versions existed
+        db.conn
+            .execute(
+                "INSERT INTO installed_kegs (name, version, store_key, installed_at)
+                 VALUES ('jq', '1.7', 'key', 0)",
+                [],
+            )
+            .unwrap();
+
+        Database::init_schema(&db.conn).unwrap();
+
+        let versions = db.list_versions("jq").unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].store_key, "key");
+        assert!(db.list_inactive_versions().unwrap().is_empty());
     }
 
+    // =========================================================================
+    // Link State Tests
+    // =========================================================================
+
     #[test]
-    fn uninstall_nonexistent_package_returns_none() {
+    fn new_installs_default_to_linked() {
         let mut db = Database::in_memory().unwrap();
+        install(&mut db, "jq", "1.7", "key");
 
-        let tx = db.transaction().unwrap();
-        let store_key = tx.record_uninstall("nonexistent").unwrap();
-        assert!(store_key.is_none());
-        tx.commit().unwrap();
+        let keg = db.get_installed("jq").unwrap();
+        assert!(keg.linked);
+        assert!(!keg.keg_only);
+        assert_eq!(keg.link_status(), LinkStatus::Linked);
     }
 
     #[test]
-    fn refcount_cannot_go_negative() {
+    fn link_status_reflects_recorded_state() {
         let mut db = Database::in_memory().unwrap();
-
-        // Install and uninstall a package
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("refcount-test", "1.0", "refkey", true)
+            tx.record_install("openssl@3", "3.3.0", "key1", false)
                 .unwrap();
+            tx.record_link_state("openssl@3", false, true).unwrap();
+            tx.record_install("jq", "1.7", "key2", true).unwrap();
+            tx.record_link_state("jq", false, false).unwrap();
             tx.commit().unwrap();
         }
 
-        // Uninstall twice (should not cause negative refcount)
+        assert_eq!(
+            db.get_installed("openssl@3").unwrap().link_status(),
+            LinkStatus::KegOnly
+        );
+        assert_eq!(
+            db.get_installed("jq").unwrap().link_status(),
+            LinkStatus::Unlinked
+        );
+
+        assert!(db.set_linked("openssl@3", true).unwrap());
+        assert_eq!(
+            db.get_installed("openssl@3").unwrap().link_status(),
+            LinkStatus::Linked
+        );
+        assert!(!db.set_linked("missing", true).unwrap());
+    }
+
+    #[test]
+    fn link_state_columns_are_added_to_old_databases() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let path = tmp.path().join("old.sqlite3");
         {
-            let tx = db.transaction().unwrap();
-            tx.record_uninstall("refcount-test").unwrap();
-            tx.commit().unwrap();
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE installed_kegs (
+                    name TEXT PRIMARY KEY,
+                    version TEXT NOT NULL,
+                    store_key TEXT NOT NULL,
+                    installed_at INTEGER NOT NULL
+                );
+                INSERT INTO installed_kegs VALUES ('jq', '1.7', 'key', 0);",
+            )
+            .unwrap();
         }
 
-        // Refcount should be 0, not negative
-        assert_eq!(db.get_store_refcount("refkey"), 0);
+        let db = Database::open(&path).unwrap();
+        let keg = db.get_installed("jq").unwrap();
+        assert!(keg.linked);
+        assert!(!keg.keg_only);
+    }
 
-        // Try to "uninstall" again by directly manipulating (shouldn't go negative)
-        db.conn
-            .execute(
-                "UPDATE store_refs SET refcount = MAX(refcount - 1, 0) WHERE store_key = ?1",
-                params!["refkey"],
-            )
+    // =========================================================================
+    // Link Backup Tests
+    // =========================================================================
+
+    #[test]
+    fn link_backups_roundtrip() {
+        let db = Database::in_memory().unwrap();
+        db.record_link_backup("jq", "/prefix/bin/jq", "/root/backups/1/jq")
+            .unwrap();
+        db.record_link_backup("jq", "/prefix/bin/jq-old", "/root/backups/2/jq-old")
+            .unwrap();
+        db.record_link_backup("wget", "/prefix/bin/wget", "/root/backups/2/wget")
             .unwrap();
 
-        assert_eq!(db.get_store_refcount("refkey"), 0);
+        let backups = db.list_link_backups("jq").unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].link_path, "/prefix/bin/jq-old");
+
+        assert!(db.remove_link_backup(backups[0].id).unwrap());
+        assert!(!db.remove_link_backup(backups[0].id).unwrap());
+        assert_eq!(db.list_link_backups("jq").unwrap().len(), 1);
+        assert_eq!(db.list_link_backups("wget").unwrap().len(), 1);
     }
 
     #[test]
-    fn multiple_transactions_sequential() {
+    fn config_files_outlive_uninstall() {
         let mut db = Database::in_memory().unwrap();
-
-        // First transaction
+        let conf = |sha: &str| ConfigFile {
+            path: PathBuf::from("redis.conf"),
+            sha256: sha.to_string(),
+        };
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("pkg1", "1.0", "key1", true).unwrap();
+            tx.record_install("redis", "7.2", "k1", true).unwrap();
+            tx.record_config_files("redis", &[conf("aaa")]).unwrap();
             tx.commit().unwrap();
         }
-
-        // Second transaction
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("pkg2", "1.0", "key2", true).unwrap();
+            tx.record_config_files("redis", &[conf("bbb")]).unwrap();
+            tx.record_uninstall("redis").unwrap();
             tx.commit().unwrap();
         }
 
-        let installed = db.list_installed().unwrap();
-        assert_eq!(installed.len(), 2);
+        assert_eq!(db.config_files("redis").unwrap(), vec![conf("bbb")]);
+        assert!(db.config_files("valkey").unwrap().is_empty());
     }
 
+    // =========================================================================
+    // Dependency Edge Tests
+    // =========================================================================
+
     #[test]
-    fn install_same_package_twice_updates() {
+    fn recorded_dependencies_roundtrip() {
         let mut db = Database::in_memory().unwrap();
-
-        // First install
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("upgrade-me", "1.0.0", "oldkey", true)
+            tx.record_install("node", "22.0", "k1", true).unwrap();
+            tx.record_dependencies("node", &["icu4c".to_string(), "brotli".to_string()])
                 .unwrap();
+            tx.record_install("icu4c", "74.2", "k2", false).unwrap();
+            tx.record_dependencies("icu4c", &[]).unwrap();
+            // Installed without recorded edges, e.g. before they were tracked
+            tx.record_install("brotli", "1.1", "k3", false).unwrap();
             tx.commit().unwrap();
         }
 
-        assert_eq!(db.get_installed("upgrade-me").unwrap().version, "1.0.0");
-        assert_eq!(db.get_store_refcount("oldkey"), 1);
+        let graph = db.recorded_dependencies().unwrap();
+        assert_eq!(graph.len(), 2);
+        assert_eq!(graph["node"], vec!["brotli", "icu4c"]);
+        assert!(graph["icu4c"].is_empty());
+        assert!(!graph.contains_key("brotli"));
 
-        // "Upgrade" by installing again with new version
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("upgrade-me", "2.0.0", "newkey", true)
+            tx.record_dependencies("node", &["icu4c".to_string()])
                 .unwrap();
             tx.commit().unwrap();
         }
+        assert_eq!(db.recorded_dependencies().unwrap()["node"], vec!["icu4c"]);
 
-        // Should have new version
-        let pkg = db.get_installed("upgrade-me").unwrap();
-        assert_eq!(pkg.version, "2.0.0");
-        assert_eq!(pkg.store_key, "newkey");
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("node").unwrap();
+            tx.commit().unwrap();
+        }
+        let graph = db.recorded_dependencies().unwrap();
+        assert!(!graph.contains_key("node"));
+        let edges: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM keg_dependencies", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(edges, 0);
+    }
 
-        // New key should have refcount 1
-        assert_eq!(db.get_store_refcount("newkey"), 1);
-        // Old key still has refcount 1 (not decremented by record_install)
-        assert_eq!(db.get_store_refcount("oldkey"), 1);
+    // =========================================================================
+    // Keep List Tests
+    // =========================================================================
+
+    #[test]
+    fn keep_list_roundtrip() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.list_kept().unwrap().is_empty());
+
+        assert!(db.add_keep("python@3.12").unwrap());
+        assert!(!db.add_keep("python@3.12").unwrap());
+        assert!(db.add_keep("libyaml").unwrap());
+        assert_eq!(db.list_kept().unwrap(), vec!["libyaml", "python@3.12"]);
+
+        assert!(db.remove_keep("libyaml").unwrap());
+        assert!(!db.remove_keep("libyaml").unwrap());
+        assert_eq!(db.list_kept().unwrap(), vec!["python@3.12"]);
     }
 
     #[test]
-    fn service_status_transitions() {
-        let mut db = Database::in_memory().unwrap();
+    fn post_install_skips_roundtrip() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.list_post_install_skips().unwrap().is_empty());
 
-        {
-            let tx = db.transaction().unwrap();
-            tx.record_install("status-test", "1.0", "bcd890", true)
-                .unwrap();
-            tx.commit().unwrap();
-        }
+        assert!(db.add_post_install_skip("postgresql@14").unwrap());
+        assert!(!db.add_post_install_skip("postgresql@14").unwrap());
+        assert_eq!(db.list_post_install_skips().unwrap(), vec!["postgresql@14"]);
 
-        db.record_service("zerobrew.status-test", "status-test", None)
-            .unwrap();
+        assert!(db.remove_post_install_skip("postgresql@14").unwrap());
+        assert!(!db.remove_post_install_skip("postgresql@14").unwrap());
+    }
 
-        // Initial: stopped
+    #[test]
+    fn upgrade_windows_roundtrip() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.list_upgrade_windows().unwrap().is_empty());
+
+        assert!(db.add_upgrade_window("Sat,Sun 02:00-05:00").unwrap());
+        assert!(!db.add_upgrade_window("Sat,Sun 02:00-05:00").unwrap());
         assert_eq!(
-            db.get_service("zerobrew.status-test").unwrap().status,
-            "stopped"
+            db.list_upgrade_windows().unwrap(),
+            vec!["Sat,Sun 02:00-05:00"]
         );
 
-        // Transition: stopped -> running
-        db.update_service_status("zerobrew.status-test", "running", Some(1000))
-            .unwrap();
-        let svc = db.get_service("zerobrew.status-test").unwrap();
-        assert_eq!(svc.status, "running");
-        assert!(svc.started_at.is_some());
+        assert!(db.remove_upgrade_window("Sat,Sun 02:00-05:00").unwrap());
+        assert!(!db.remove_upgrade_window("Sat,Sun 02:00-05:00").unwrap());
+    }
 
-        // Transition: running -> error
-        db.update_service_status("zerobrew.status-test", "error", None)
-            .unwrap();
-        let svc = db.get_service("zerobrew.status-test").unwrap();
-        assert_eq!(svc.status, "error");
-        assert!(svc.started_at.is_none()); // cleared for non-running
+    #[test]
+    fn link_env_roundtrip() {
+        let db = Database::in_memory().unwrap();
+        assert!(db.link_env("git").unwrap().is_empty());
 
-        // Transition: error -> running
-        db.update_service_status("zerobrew.status-test", "running", Some(2000))
+        db.set_link_env("git", "GIT_EXEC_PATH", "{opt}/libexec/git-core")
             .unwrap();
-        let svc = db.get_service("zerobrew.status-test").unwrap();
-        assert_eq!(svc.status, "running");
-        assert_eq!(svc.pid, Some(2000));
+        db.set_link_env("git", "GIT_EXEC_PATH", "{prefix}/libexec")
+            .unwrap();
+        db.set_link_env("python@3.12", "PYTHONHOME", "{opt}")
+            .unwrap();
+        assert_eq!(
+            db.link_env("git").unwrap(),
+            BTreeMap::from([("GIT_EXEC_PATH".to_string(), "{prefix}/libexec".to_string())])
+        );
+        assert_eq!(db.list_link_env().unwrap().len(), 2);
+
+        assert!(db.unset_link_env("git", "GIT_EXEC_PATH").unwrap());
+        assert!(!db.unset_link_env("git", "GIT_EXEC_PATH").unwrap());
+        assert!(db.link_env("git").unwrap().is_empty());
     }
 
+    // =========================================================================
+    // Provenance Tests
+    // =========================================================================
+
     #[test]
-    fn list_installed_sorted_alphabetically() {
+    fn provenance_roundtrip() {
         let mut db = Database::in_memory().unwrap();
-
+        let brewfile = Provenance::Brewfile {
+            path: "/work/Brewfile".to_string(),
+            run_id: "20261016T120000Z".to_string(),
+        };
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("zebra", "1.0", "z", true).unwrap();
-            tx.record_install("alpha", "1.0", "a", true).unwrap();
-            tx.record_install("middle", "1.0", "m", true).unwrap();
+            tx.record_install("node", "22.0", "k1", true).unwrap();
+            tx.record_provenance("node", &brewfile).unwrap();
+            tx.record_install("icu4c", "74.2", "k2", false).unwrap();
+            tx.record_provenance(
+                "icu4c",
+                &Provenance::DependencyOf {
+                    name: "node".to_string(),
+                },
+            )
+            .unwrap();
+            tx.record_install("legacy", "1.0", "k3", true).unwrap();
             tx.commit().unwrap();
         }
 
-        let installed = db.list_installed().unwrap();
-        assert_eq!(installed[0].name, "alpha");
-        assert_eq!(installed[1].name, "middle");
-        assert_eq!(installed[2].name, "zebra");
+        assert_eq!(db.get_installed("node").unwrap().provenance, Some(brewfile));
+        assert_eq!(
+            db.get_installed("icu4c")
+                .unwrap()
+                .provenance
+                .unwrap()
+                .to_string(),
+            "dependency of node"
+        );
+        assert_eq!(db.get_installed("legacy").unwrap().provenance, None);
     }
 
     #[test]
-    fn linked_files_with_special_characters_in_paths() {
-        let mut db = Database::in_memory().unwrap();
+    fn provenance_matches_filters() {
+        let brewfile = Provenance::Brewfile {
+            path: "/work/Brewfile".to_string(),
+            run_id: "run1".to_string(),
+        };
+        assert!(brewfile.matches("brewfile"));
+        assert!(brewfile.matches("brewfile:/work/Brewfile"));
+        assert!(brewfile.matches("brewfile:run1"));
+        assert!(!brewfile.matches("brewfile:run2"));
+        assert!(!brewfile.matches("cli"));
+        assert!(Provenance::Cli.matches("cli"));
+        assert!(!Provenance::Cli.matches("cli:x"));
+        assert_eq!(Provenance::from_db("garbage"), None);
+    }
 
+    // =========================================================================
+    // HEAD Commit Tests
+    // =========================================================================
+
+    #[test]
+    fn head_commit_roundtrip_and_reset_on_reinstall() {
+        let mut db = Database::in_memory().unwrap();
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("special-pkg", "1.0", "special123", true)
+            tx.record_install("neovim", "HEAD-1a2b3c4", "source-neovim", true)
                 .unwrap();
-            tx.record_linked_file(
-                "special-pkg",
-                "1.0",
-                "/opt/homebrew/bin/special pkg",
-                "/opt/zerobrew/cellar/special-pkg/1.0/bin/special pkg",
-            )
-            .unwrap();
-            tx.record_linked_file(
-                "special-pkg",
-                "1.0",
-                "/opt/homebrew/lib/lib'quoted'.so",
-                "/opt/zerobrew/cellar/special-pkg/1.0/lib/lib'quoted'.so",
-            )
-            .unwrap();
+            tx.record_head_commit("neovim", "1a2b3c4d5e6f").unwrap();
             tx.commit().unwrap();
         }
+        assert_eq!(
+            db.get_installed("neovim").unwrap().head_commit.as_deref(),
+            Some("1a2b3c4d5e6f")
+        );
 
-        let files = db.get_linked_files("special-pkg").unwrap();
-        assert_eq!(files.len(), 2);
-        assert!(files.iter().any(|(l, _)| l.contains("special pkg")));
-        assert!(files.iter().any(|(l, _)| l.contains("'quoted'")));
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("neovim", "0.10.0", "abc", true).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.get_installed("neovim").unwrap().head_commit, None);
     }
 
     #[test]
-    fn tap_operations_with_various_formats() {
-        let db = Database::in_memory().unwrap();
-
-        // Standard format
-        db.add_tap("homebrew/core", "https://github.com/homebrew/homebrew-core")
-            .unwrap();
-
-        // With dashes
-        db.add_tap("user/my-tap", "https://github.com/user/homebrew-my-tap")
-            .unwrap();
+    fn receipt_roundtrip_and_reset_on_reinstall() {
+        use crate::receipt::BuiltFrom;
 
-        // Longer path
-        db.add_tap("org/team/repo", "https://github.com/org/team-homebrew-repo")
-            .unwrap();
+        let mut db = Database::in_memory().unwrap();
+        let receipt = InstallReceipt {
+            built_from: BuiltFrom::Source,
+            build_args: vec!["-DWITH_LUA=ON".to_string()],
+            tap: Some("homebrew/core".to_string()),
+            tap_revision: Some("abc123".to_string()),
+        };
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("neovim", "0.10.0", "source-neovim", true)
+                .unwrap();
+            tx.record_receipt("neovim", &receipt).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.get_installed("neovim").unwrap().receipt, Some(receipt));
 
-        let taps = db.list_taps().unwrap();
-        assert_eq!(taps.len(), 3);
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("neovim", "0.10.1", "abc", true).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.get_installed("neovim").unwrap().receipt, None);
     }
 
     // =========================================================================
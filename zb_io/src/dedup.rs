@@ -0,0 +1,239 @@
+//! File-level deduplication across store entries.
+//!
+//! Store entries are keyed by bottle, so two versions of a formula usually
+//! carry many identical files (headers, docs, scripts). When an entry is
+//! ingested, every file that materialization never rewrites is hard-linked
+//! into a pool keyed by its checksum and mode; a file already in the pool
+//! replaces the new copy with a link to the pooled one. Kegs materialized
+//! from the store link or clone those same files, so the savings carry over
+//! into the Cellar.
+//!
+//! A pooled file's link count is its refcount: once only the pool links to
+//! it, no store entry or keg uses it any more and [`FilePool::prune`] removes
+//! it.
+//!
+//! ```text
+//! ~/.zerobrew/files/<sha256[..2]>/<sha256>-<mode>
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::manifest::Manifest;
+
+/// What deduplicating a directory into the pool did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupResult {
+    /// Files added to the pool
+    pub pooled: usize,
+    /// Files replaced by a link to an identical pooled file
+    pub linked: usize,
+    /// Bytes no longer stored twice
+    pub bytes_saved: u64,
+}
+
+/// Pool of files shared between store entries
+#[derive(Debug, Clone)]
+pub struct FilePool {
+    dir: PathBuf,
+}
+
+impl FilePool {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Where a file with `sha256` and permission bits `mode` is pooled.
+    ///
+    /// The mode is part of the key because hard links share permissions.
+    pub fn object_path(&self, sha256: &str, mode: u32) -> PathBuf {
+        let shard = &sha256[..sha256.len().min(2)];
+        self.dir.join(shard).join(format!("{sha256}-{mode:o}"))
+    }
+
+    /// Share the files of `root`, described by `manifest`, through the pool.
+    ///
+    /// Files materialization may patch are skipped, as are empty files. This
+    /// is best effort: a file that can't be linked (e.g. the pool is on
+    /// another filesystem) simply keeps its own copy.
+    pub fn dedup(&self, root: &Path, manifest: &Manifest) -> DedupResult {
+        let mut result = DedupResult::default();
+
+        for entry in &manifest.entries {
+            if entry.patchable || entry.size == 0 {
+                continue;
+            }
+            let path = root.join(&entry.path);
+            let Ok(meta) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            if !meta.file_type().is_file() || meta.len() != entry.size {
+                continue;
+            }
+
+            let object = self.object_path(&entry.sha256, meta.mode() & 0o7777);
+            match fs::symlink_metadata(&object) {
+                Ok(pooled) if pooled.ino() == meta.ino() && pooled.dev() == meta.dev() => {}
+                Ok(pooled) if pooled.len() == entry.size => {
+                    if replace_with_link(&object, &path).is_ok() {
+                        result.linked += 1;
+                        result.bytes_saved += entry.size;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    let added = object
+                        .parent()
+                        .map_or(Ok(()), fs::create_dir_all)
+                        .and_then(|_| fs::hard_link(&path, &object));
+                    if added.is_ok() {
+                        result.pooled += 1;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Remove pooled files nothing else links to any more.
+    /// Returns the number of files removed and the bytes freed.
+    pub fn prune(&self) -> io::Result<(usize, u64)> {
+        let mut removed = 0;
+        let mut bytes = 0;
+
+        for shard in fs::read_dir(&self.dir)? {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for object in fs::read_dir(&shard)? {
+                let object = object?.path();
+                let Ok(meta) = fs::symlink_metadata(&object) else {
+                    continue;
+                };
+                if meta.nlink() <= 1 && fs::remove_file(&object).is_ok() {
+                    removed += 1;
+                    bytes += meta.len();
+                }
+            }
+            // Drop shards that are now empty
+            let _ = fs::remove_dir(&shard);
+        }
+
+        Ok((removed, bytes))
+    }
+}
+
+/// Replace `path` with a hard link to `object`, through a temporary name so
+/// `path` is never missing
+fn replace_with_link(object: &Path, path: &Path) -> io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{file_name}.dedup"));
+    let _ = fs::remove_file(&tmp);
+    fs::hard_link(object, &tmp)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Size of the files under `roots`, counting a file linked into several of
+/// them (or several times into one) once
+pub fn disk_usage<'a>(roots: impl IntoIterator<Item = &'a Path>) -> io::Result<u64> {
+    let mut seen = HashSet::new();
+    let mut total = 0;
+
+    for root in roots {
+        if !root.is_dir() {
+            continue;
+        }
+        for entry in walkdir::WalkDir::new(root).follow_links(false) {
+            let entry = entry.map_err(io::Error::other)?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let meta = entry.metadata().map_err(io::Error::other)?;
+            if meta.nlink() <= 1 || seen.insert((meta.dev(), meta.ino())) {
+                total += meta.len();
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn entry(tmp: &TempDir, key: &str, version: &str, readme: &str) -> PathBuf {
+        let root = tmp.path().join("store").join(key);
+        let keg = root.join("jq").join(version);
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("README"), readme).unwrap();
+        fs::write(keg.join("bin/jq-wrapper"), "#!/bin/sh\nexec jq \"$@\"\n").unwrap();
+        fs::set_permissions(
+            keg.join("bin/jq-wrapper"),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        fs::write(keg.join("bin/jq-config"), "prefix=@@HOMEBREW_PREFIX@@\n").unwrap();
+        root
+    }
+
+    #[test]
+    fn identical_files_share_one_inode() {
+        let tmp = TempDir::new().unwrap();
+        let pool = FilePool::new(&tmp.path().join("files")).unwrap();
+
+        let old = entry(&tmp, "aaa", "1.7", "jq manual\n");
+        let first = pool.dedup(&old, &Manifest::build(&old).unwrap());
+        assert_eq!(first.pooled, 2);
+        assert_eq!(first.linked, 0);
+
+        let new = entry(&tmp, "bbb", "1.8", "jq manual, updated\n");
+        let second = pool.dedup(&new, &Manifest::build(&new).unwrap());
+        assert_eq!(second.linked, 1);
+        assert_eq!(second.pooled, 1);
+
+        let meta = |path: PathBuf| fs::metadata(path).unwrap();
+        let old_wrapper = meta(old.join("jq/1.7/bin/jq-wrapper"));
+        let new_wrapper = meta(new.join("jq/1.8/bin/jq-wrapper"));
+        assert_eq!(old_wrapper.ino(), new_wrapper.ino());
+        assert_eq!(new_wrapper.mode() & 0o777, 0o755);
+        // Patchable files always keep their own copy
+        assert_ne!(
+            meta(old.join("jq/1.7/bin/jq-config")).ino(),
+            meta(new.join("jq/1.8/bin/jq-config")).ino()
+        );
+
+        assert!(
+            disk_usage([old.as_path(), new.as_path()]).unwrap()
+                < disk_usage([old.as_path()]).unwrap() * 2
+        );
+    }
+
+    #[test]
+    fn prune_removes_files_only_the_pool_links() {
+        let tmp = TempDir::new().unwrap();
+        let pool = FilePool::new(&tmp.path().join("files")).unwrap();
+        let old = entry(&tmp, "aaa", "1.7", "jq manual\n");
+        pool.dedup(&old, &Manifest::build(&old).unwrap());
+
+        assert_eq!(pool.prune().unwrap(), (0, 0));
+
+        fs::remove_file(old.join("jq/1.7/README")).unwrap();
+        let (removed, bytes) = pool.prune().unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(bytes, "jq manual\n".len() as u64);
+        assert!(old.join("jq/1.7/bin/jq-wrapper").exists());
+    }
+}
@@ -0,0 +1,120 @@
+//! Binary deltas between bottles, for upgrades.
+//!
+//! An upgrade usually changes a small part of a large bottle. A delta server
+//! publishes the difference between two bottles as a zstd patch, made with
+//! `zstd --patch-from` and named after both bottles' checksums:
+//!
+//! ```text
+//! <delta-url>/<old sha256>/<new sha256>.zst
+//! ```
+//!
+//! ```bash
+//! zstd --long=31 --patch-from=old.bottle.tar.gz new.bottle.tar.gz -o <new sha256>.zst
+//! ```
+//!
+//! When the bottle of the installed version is still in the blob cache, an
+//! upgrade fetches the patch and rebuilds the new bottle from it, checking
+//! the result against the new bottle's checksum. A missing patch or any other
+//! failure falls back to downloading the whole bottle.
+
+use std::io::{self, BufRead, Read, Write};
+
+/// Largest back-reference distance a patch may use, as `2^n` bytes. This is
+/// zstd's limit, which `--patch-from` reaches for bottles of up to 2 GiB.
+const MAX_WINDOW_LOG: u32 = 31;
+
+/// A rebuilt bottle may be this many times the size of the bottle it was
+/// patched from, plus [`REBUILT_SIZE_SLACK`]; a patch decoding to more is
+/// refused rather than filling the disk
+const MAX_REBUILT_GROWTH: u64 = 4;
+
+/// Room a rebuilt bottle gets on top of its growth limit, for small bottles
+const REBUILT_SIZE_SLACK: u64 = 64 * 1024 * 1024;
+
+/// Largest bottle a patch against a bottle of `old_size` bytes may rebuild
+pub fn max_rebuilt_size(old_size: u64) -> u64 {
+    old_size
+        .saturating_mul(MAX_REBUILT_GROWTH)
+        .saturating_add(REBUILT_SIZE_SLACK)
+}
+
+/// URL of the patch from bottle `from_sha256` to bottle `to_sha256`
+pub fn delta_url(base: &str, from_sha256: &str, to_sha256: &str) -> String {
+    format!(
+        "{}/{from_sha256}/{to_sha256}.zst",
+        base.trim_end_matches('/')
+    )
+}
+
+/// Rebuild a file from the `old` one and a patch made against it, streaming
+/// it to `out`. Fails once the rebuilt file would exceed `max_size` bytes.
+/// Returns the size of the rebuilt file.
+pub fn apply_patch(
+    old: &[u8],
+    patch: impl BufRead,
+    mut out: impl Write,
+    max_size: u64,
+) -> io::Result<u64> {
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(patch, old)?;
+    decoder.window_log_max(MAX_WINDOW_LOG)?;
+    let size = io::copy(&mut decoder.take(max_size.saturating_add(1)), &mut out)?;
+    if size > max_size {
+        return Err(io::Error::other(format!(
+            "patch rebuilds more than {max_size} bytes"
+        )));
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::make_delta_patch;
+
+    #[test]
+    fn delta_urls_name_both_bottles() {
+        assert_eq!(
+            delta_url("https://deltas.example.com/", "aaa", "bbb"),
+            "https://deltas.example.com/aaa/bbb.zst"
+        );
+    }
+
+    #[test]
+    fn patches_rebuild_the_new_file() {
+        let old: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut new = old.clone();
+        new[1234..1300].fill(7);
+        new.extend_from_slice(b"appended in the new version");
+
+        let patch = make_delta_patch(&old, &new);
+        assert!(patch.len() < new.len() / 100, "{} bytes", patch.len());
+
+        let mut rebuilt = Vec::new();
+        let size = apply_patch(&old, &patch[..], &mut rebuilt, u64::MAX).unwrap();
+        assert_eq!(size, new.len() as u64);
+        assert_eq!(rebuilt, new);
+
+        // Against the wrong base the patch doesn't reproduce the file
+        let mut wrong = Vec::new();
+        let _ = apply_patch(&new, &patch[..], &mut wrong, u64::MAX);
+        assert_ne!(wrong, new);
+    }
+
+    #[test]
+    fn patches_rebuilding_too_much_are_refused() {
+        let old: Vec<u8> = (0..1000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let new = vec![0u8; 100_000];
+        let patch = make_delta_patch(&old, &new);
+
+        let mut rebuilt = Vec::new();
+        let err = apply_patch(&old, &patch[..], &mut rebuilt, 50_000).unwrap_err();
+        assert!(err.to_string().contains("50000"), "{err}");
+        assert!(rebuilt.len() <= 50_001);
+
+        let mut rebuilt = Vec::new();
+        assert_eq!(
+            apply_patch(&old, &patch[..], &mut rebuilt, 100_000).unwrap(),
+            100_000
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -8,15 +8,28 @@ use std::time::{Duration, Instant};
 use futures_util::StreamExt;
 use futures_util::future::select_all;
 use reqwest::StatusCode;
-use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, HeaderValue, WWW_AUTHENTICATE};
+use reqwest::header::{CONTENT_LENGTH, WWW_AUTHENTICATE};
 use serde::Deserialize;
 use sha2::{Digest, Sha256};
 use tokio::sync::{Mutex, Notify, RwLock, Semaphore, mpsc};
+use tokio::task::JoinHandle;
 
 use crate::blob::BlobCache;
+use crate::credentials::Credentials;
+use crate::http_fixtures::{HttpFixtures, HttpMode};
+use crate::pipe::{BodySender, body_pipe};
 use crate::progress::InstallProgress;
+use crate::ratelimit::RateLimits;
+use crate::store::Store;
 use zb_core::Error;
 
+/// GitHub token used to authenticate registry token requests, as in Homebrew
+const GITHUB_TOKEN_ENV: &str = "HOMEBREW_GITHUB_API_TOKEN";
+
+/// Token servers the GitHub token is sent to. Any bottle host can name a
+/// token realm in its challenge, so others are asked anonymously.
+const GITHUB_TOKEN_HOSTS: &[&str] = &["ghcr.io"];
+
 /// Number of parallel connections to race when downloading (hits different CDN edges)
 const RACING_CONNECTIONS: usize = 4;
 
@@ -26,23 +39,26 @@ const RACING_STAGGER_MS: u64 = 200;
 /// Callback for download progress updates
 pub type DownloadProgressCallback = Arc<dyn Fn(InstallProgress) + Send + Sync>;
 
+/// Mirror domains configured in `HOMEBREW_BOTTLE_MIRRORS` (comma-separated)
+pub fn configured_mirrors() -> Vec<String> {
+    std::env::var("HOMEBREW_BOTTLE_MIRRORS")
+        .map(|mirrors| {
+            mirrors
+                .split(',')
+                .map(str::trim)
+                .filter(|mirror| !mirror.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Get alternate URLs for a given primary URL (from user-configured mirrors)
 fn get_alternate_urls(primary_url: &str) -> Vec<String> {
-    let mut alternates = Vec::new();
-
-    // Check for user-configured mirrors via environment variable (comma-separated)
-    if let Ok(mirrors) = std::env::var("HOMEBREW_BOTTLE_MIRRORS") {
-        for mirror in mirrors.split(',') {
-            let mirror = mirror.trim();
-            if !mirror.is_empty()
-                && let Some(alt) = transform_url_to_mirror(primary_url, mirror)
-            {
-                alternates.push(alt);
-            }
-        }
-    }
-
-    alternates
+    configured_mirrors()
+        .iter()
+        .filter_map(|mirror| transform_url_to_mirror(primary_url, mirror))
+        .collect()
 }
 
 /// Transform a URL to use a custom mirror domain
@@ -59,6 +75,24 @@ struct TokenResponse {
     token: String,
 }
 
+/// Passes writes through to `inner`, hashing them on the way
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 /// Result of a completed download, sent via channel for streaming processing
 #[derive(Debug, Clone)]
 pub struct DownloadResult {
@@ -66,6 +100,10 @@ pub struct DownloadResult {
     pub sha256: String,
     pub blob_path: PathBuf,
     pub index: usize,
+    /// Store entry unpacked from the body while it downloaded, if the
+    /// downloader has an ingest store, an extraction slot was free and the
+    /// entry didn't exist before
+    pub entry: Option<PathBuf>,
 }
 
 /// Cached auth token with expiry
@@ -78,31 +116,81 @@ struct CachedToken {
 type TokenCache = Arc<RwLock<HashMap<String, CachedToken>>>;
 
 pub struct Downloader {
-    client: reqwest::Client,
+    oci: OciClient,
     blob_cache: BlobCache,
-    token_cache: TokenCache,
+    http_mode: HttpMode,
 }
 
 impl Downloader {
     pub fn new(blob_cache: BlobCache) -> Self {
         // Use HTTP/2 with connection pooling for better performance
         // Note: don't use http2_prior_knowledge() as some servers (like ghcr.io) need ALPN negotiation
+        let client = reqwest::Client::builder()
+            .user_agent("zerobrew/0.1")
+            .pool_max_idle_per_host(10)
+            .tcp_nodelay(true)
+            .tcp_keepalive(Duration::from_secs(60))
+            .http2_adaptive_window(true)
+            .http2_initial_stream_window_size(Some(2 * 1024 * 1024))
+            .http2_initial_connection_window_size(Some(4 * 1024 * 1024))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        let github_token = std::env::var(GITHUB_TOKEN_ENV)
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+
         Self {
-            client: reqwest::Client::builder()
-                .user_agent("zerobrew/0.1")
-                .pool_max_idle_per_host(10)
-                .tcp_nodelay(true)
-                .tcp_keepalive(Duration::from_secs(60))
-                .http2_adaptive_window(true)
-                .http2_initial_stream_window_size(Some(2 * 1024 * 1024))
-                .http2_initial_connection_window_size(Some(4 * 1024 * 1024))
-                .build()
-                .unwrap_or_else(|_| reqwest::Client::new()),
+            oci: OciClient {
+                client,
+                token_cache: Arc::new(RwLock::new(HashMap::new())),
+                rate_limits: RateLimits::new(),
+                github_token,
+                github_token_hosts: GITHUB_TOKEN_HOSTS.iter().map(|h| h.to_string()).collect(),
+                credentials: Credentials::none(),
+                offline: false,
+            },
             blob_cache,
-            token_cache: Arc::new(RwLock::new(HashMap::new())),
+            http_mode: HttpMode::Live,
         }
     }
 
+    pub fn with_http_mode(mut self, mode: HttpMode) -> Self {
+        self.http_mode = mode;
+        self
+    }
+
+    /// Share rate-limit cool-downs with other clients (see [`crate::ratelimit`])
+    pub fn with_rate_limits(mut self, rate_limits: RateLimits) -> Self {
+        self.oci.rate_limits = rate_limits;
+        self
+    }
+
+    /// Authenticate registry token requests with a GitHub token instead of
+    /// `HOMEBREW_GITHUB_API_TOKEN`
+    pub fn with_github_token(mut self, token: Option<String>) -> Self {
+        self.oci.github_token = token;
+        self
+    }
+
+    /// Send the GitHub token to token servers on `hosts` instead of ghcr.io
+    pub fn with_github_token_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.oci.github_token_hosts = hosts;
+        self
+    }
+
+    /// Authenticate requests to private mirrors with `credentials`, e.g.
+    /// those in `~/.netrc` and the keychain; none by default
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.oci.credentials = credentials;
+        self
+    }
+
+    /// Never touch the network, failing downloads of bottles that aren't cached
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.oci.offline = offline;
+        self
+    }
+
     /// Remove a blob from the cache (used when extraction fails due to corruption)
     pub fn remove_blob(&self, sha256: &str) -> bool {
         self.blob_cache.remove_blob(sha256).unwrap_or(false)
@@ -119,6 +207,20 @@ impl Downloader {
         expected_sha256: &str,
         name: Option<String>,
         progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        self.download_teed(url, expected_sha256, name, progress, None)
+            .await
+    }
+
+    /// Download, also passing the body to `tee` as it arrives when it comes
+    /// from the network. `tee` is finished only once the body is verified.
+    async fn download_teed(
+        &self,
+        url: &str,
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
+        tee: Option<BodySender>,
     ) -> Result<PathBuf, Error> {
         if self.blob_cache.has_blob(expected_sha256) {
             // Report as already complete
@@ -131,12 +233,188 @@ impl Downloader {
             return Ok(self.blob_cache.blob_path(expected_sha256));
         }
 
+        if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            return self.replay_download(fixtures, url, expected_sha256, name, progress);
+        }
+
+        // Bottles in a local mirror directory
+        if let Some(path) = url.strip_prefix("file://") {
+            let body = std::fs::read(path).map_err(|e| Error::NetworkFailure {
+                message: format!("failed to read {path}: {e}"),
+            })?;
+            return self.write_verified(&body, expected_sha256, name, progress);
+        }
+
         // Get alternate mirror URLs (user-configured)
         let alternates = get_alternate_urls(url);
 
         // Always use racing to hit different CDN edges for faster downloads
-        self.download_with_racing(url, &alternates, expected_sha256, name, progress)
-            .await
+        let path = self
+            .download_with_racing(url, &alternates, expected_sha256, name, progress, tee)
+            .await?;
+
+        if let HttpMode::Record(ref fixtures) = self.http_mode {
+            fixtures.save_file(url, &path)?;
+        }
+
+        Ok(path)
+    }
+
+    /// Size of the file at `url` as reported by the server, without
+    /// downloading its body. `None` when the server doesn't say.
+    pub async fn remote_size(&self, url: &str) -> Result<Option<u64>, Error> {
+        if let HttpMode::Replay(ref fixtures) = self.http_mode {
+            return Ok(std::fs::metadata(fixtures.body_path(url))
+                .ok()
+                .map(|m| m.len()));
+        }
+        if let Some(path) = url.strip_prefix("file://") {
+            return Ok(std::fs::metadata(path).ok().map(|m| m.len()));
+        }
+
+        let response = self.oci.fetch(url).await?;
+        // Dropping the response abandons the body transfer
+        Ok(response.content_length())
+    }
+
+    /// Rebuild the bottle `expected_sha256` from the cached bottle
+    /// `from_sha256` and the patch at `url` (see [`crate::delta`]), and add
+    /// it to the cache. Returns the size of the patch.
+    pub async fn download_delta(
+        &self,
+        url: &str,
+        from_sha256: &str,
+        expected_sha256: &str,
+    ) -> Result<u64, Error> {
+        if matches!(self.http_mode, HttpMode::Replay(_)) {
+            return Err(Error::NetworkFailure {
+                message: "deltas are not recorded for replay".to_string(),
+            });
+        }
+
+        // Patches from a server are spooled to disk rather than held in memory
+        let mut patch = match url.strip_prefix("file://") {
+            Some(path) => std::fs::File::open(path).map_err(|e| Error::NetworkFailure {
+                message: format!("failed to read {path}: {e}"),
+            })?,
+            None => {
+                let spool_err = |e: std::io::Error| Error::NetworkFailure {
+                    message: format!("failed to spool delta from {url}: {e}"),
+                };
+                let mut spool =
+                    tempfile::tempfile_in(self.blob_cache.tmp_dir()).map_err(spool_err)?;
+                let mut response = self.oci.fetch(url).await?;
+                while let Some(chunk) =
+                    response.chunk().await.map_err(|e| Error::NetworkFailure {
+                        message: e.to_string(),
+                    })?
+                {
+                    spool.write_all(&chunk).map_err(spool_err)?;
+                }
+                spool
+            }
+        };
+        let patch_size = patch
+            .seek(SeekFrom::End(0))
+            .and_then(|size| patch.rewind().map(|()| size))
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("failed to read delta from {url}: {e}"),
+            })?;
+
+        // zstd needs the whole bottle the patch refers back to in memory
+        let old = std::fs::read(self.blob_cache.blob_path(from_sha256)).map_err(|e| {
+            Error::NetworkFailure {
+                message: format!("failed to read cached bottle {from_sha256}: {e}"),
+            }
+        })?;
+
+        // The rebuilt bottle goes straight to a temporary blob, removed
+        // unless it is committed
+        let blob =
+            self.blob_cache
+                .start_write(expected_sha256)
+                .map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to create blob writer: {e}"),
+                })?;
+        let mut writer = HashingWriter {
+            inner: blob,
+            hasher: Sha256::new(),
+        };
+        crate::delta::apply_patch(
+            &old,
+            std::io::BufReader::new(patch),
+            &mut writer,
+            crate::delta::max_rebuilt_size(old.len() as u64),
+        )
+        .map_err(|e| Error::NetworkFailure {
+            message: format!("failed to apply delta from {url}: {e}"),
+        })?;
+
+        let HashingWriter {
+            inner: blob,
+            hasher,
+        } = writer;
+        let actual_hash = format!("{:x}", hasher.finalize());
+        if actual_hash != expected_sha256.to_lowercase() {
+            return Err(Error::ChecksumMismatch {
+                expected: expected_sha256.to_string(),
+                actual: actual_hash,
+                file_name: None,
+            });
+        }
+        blob.commit()?;
+        Ok(patch_size)
+    }
+
+    /// Serve a bottle from recorded fixtures instead of the network
+    fn replay_download(
+        &self,
+        fixtures: &HttpFixtures,
+        url: &str,
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        let body = fixtures.load(url)?.ok_or_else(|| fixtures.missing(url))?;
+        self.write_verified(&body, expected_sha256, name, progress)
+    }
+
+    /// Check a downloaded body against its checksum and add it to the cache
+    fn write_verified(
+        &self,
+        body: &[u8],
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        let actual_hash = format!("{:x}", Sha256::digest(body));
+        if actual_hash != expected_sha256.to_lowercase() {
+            return Err(Error::ChecksumMismatch {
+                expected: expected_sha256.to_string(),
+                actual: actual_hash,
+                file_name: name,
+            });
+        }
+
+        let mut writer =
+            self.blob_cache
+                .start_write(expected_sha256)
+                .map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to create blob writer: {e}"),
+                })?;
+        writer.write_all(body).map_err(|e| Error::NetworkFailure {
+            message: format!("failed to write blob: {e}"),
+        })?;
+        let path = writer.commit()?;
+
+        if let (Some(cb), Some(n)) = (&progress, &name) {
+            cb(InstallProgress::DownloadCompleted {
+                name: n.clone(),
+                total_bytes: body.len() as u64,
+            });
+        }
+
+        Ok(path)
     }
 
     /// Download with racing: start multiple parallel connections simultaneously.
@@ -150,6 +428,8 @@ impl Downloader {
     /// a head start. The first successful download wins and cancels the others.
     ///
     /// Only the first connection reports progress updates to avoid duplicate messages.
+    /// `tee` goes to the first connection that downloads a body; if that one
+    /// fails, the others download without it.
     async fn download_with_racing(
         &self,
         primary_url: &str,
@@ -157,7 +437,9 @@ impl Downloader {
         expected_sha256: &str,
         name: Option<String>,
         progress: Option<DownloadProgressCallback>,
+        tee: Option<BodySender>,
     ) -> Result<PathBuf, Error> {
+        let tee = Arc::new(std::sync::Mutex::new(tee));
         let done = Arc::new(AtomicBool::new(false));
         let done_notify = Arc::new(Notify::new());
         let body_download_gate = Arc::new(Semaphore::new(1));
@@ -178,15 +460,15 @@ impl Downloader {
         // Spawn racing downloads
         let mut handles = Vec::new();
         for (idx, url) in all_urls.into_iter().enumerate() {
-            let downloader_client = self.client.clone();
+            let oci = self.oci.clone();
             let blob_cache = self.blob_cache.clone();
-            let token_cache = self.token_cache.clone();
             let expected_sha256 = expected_sha256.to_string();
             let name = name.clone();
             let progress = progress.clone();
             let done = done.clone();
             let done_notify = done_notify.clone();
             let body_download_gate = body_download_gate.clone();
+            let tee = tee.clone();
 
             // Stagger starts to give earlier connections a head start
             let delay = Duration::from_millis(idx as u64 * RACING_STAGGER_MS);
@@ -214,9 +496,7 @@ impl Downloader {
                     return Ok(blob_cache.blob_path(&expected_sha256));
                 }
 
-                let response =
-                    fetch_download_response_internal(&downloader_client, &token_cache, &url)
-                        .await?;
+                let response = oci.fetch(&url).await?;
 
                 let _permit = tokio::select! {
                     permit = body_download_gate.acquire_owned() => permit.map_err(|_| Error::NetworkFailure {
@@ -249,12 +529,14 @@ impl Downloader {
                     return Ok(blob_cache.blob_path(&expected_sha256));
                 }
 
+                let tee = tee.lock().ok().and_then(|mut tee| tee.take());
                 let result = download_response_internal(
                     &blob_cache,
                     response,
                     &expected_sha256,
                     name,
                     progress,
+                    tee,
                 )
                 .await;
 
@@ -284,6 +566,13 @@ impl Downloader {
                     }
                     return Ok(path);
                 }
+                // Every other connection would hit the same cool-down
+                Ok(Err(e @ Error::RateLimited { .. })) => {
+                    for handle in &pending {
+                        handle.abort();
+                    }
+                    return Err(e);
+                }
                 Ok(Err(e)) => last_error = Some(e),
                 Err(e) => {
                     last_error = Some(Error::NetworkFailure {
@@ -299,155 +588,250 @@ impl Downloader {
     }
 }
 
-/// Fetch a successful download response with GHCR auth handling.
-async fn fetch_download_response_internal(
-    client: &reqwest::Client,
-    token_cache: &TokenCache,
-    url: &str,
-) -> Result<reqwest::Response, Error> {
-    // Try with cached token first (for GHCR URLs)
-    let cached_token = get_cached_token_for_url_internal(token_cache, url).await;
-
-    let mut request = client.get(url);
-    if let Some(token) = &cached_token {
-        request = request.header(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
-        );
-    }
+/// Client for OCI registries such as ghcr.io, which serve Homebrew's
+/// bottles as blobs behind bearer-token auth.
+///
+/// A blob request answered with 401 names the token server in its
+/// `WWW-Authenticate` challenge. Tokens are requested anonymously, or with
+/// `HOMEBREW_GITHUB_API_TOKEN` as basic auth when it is set, and cached per
+/// scope until shortly before they expire. Requests to other hosts, such as
+/// private mirrors, carry basic auth when [`Credentials`] has a login for
+/// them.
+#[derive(Clone)]
+struct OciClient {
+    client: reqwest::Client,
+    token_cache: TokenCache,
+    rate_limits: RateLimits,
+    github_token: Option<String>,
+    /// Hosts of the token realms `github_token` may be sent to
+    github_token_hosts: Vec<String>,
+    /// Basic auth for private mirrors (see [`crate::credentials`])
+    credentials: Credentials,
+    /// Refuse every request; only cached bottles can be installed
+    offline: bool,
+}
 
-    let response = request.send().await.map_err(|e| Error::NetworkFailure {
-        message: e.to_string(),
-    })?;
+impl OciClient {
+    /// Fetch a successful response for `url`, answering auth challenges
+    async fn fetch(&self, url: &str) -> Result<reqwest::Response, Error> {
+        if self.offline {
+            return Err(Error::NetworkFailure {
+                message: format!("offline: {url} is not in the cache"),
+            });
+        }
+        self.rate_limits.check(url)?;
+
+        // Try with cached token first (for GHCR URLs)
+        let mut request = self.client.get(url);
+        if let Some(token) = self.cached_token_for_url(url).await {
+            request = request.bearer_auth(token);
+        } else {
+            request = self.credentials.authorize(url, request);
+        }
 
-    let response = if response.status() == StatusCode::UNAUTHORIZED {
-        handle_auth_challenge_internal(client, token_cache, url, response).await?
-    } else {
-        response
-    };
+        let response = request.send().await.map_err(|e| Error::NetworkFailure {
+            message: e.to_string(),
+        })?;
 
-    if !response.status().is_success() {
-        return Err(Error::NetworkFailure {
-            message: format!("HTTP {}", response.status()),
-        });
-    }
+        let response = if response.status() == StatusCode::UNAUTHORIZED {
+            self.answer_challenge(url, response).await?
+        } else {
+            response
+        };
+        self.rate_limits.check_response(url, &response)?;
 
-    Ok(response)
-}
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("HTTP {}", response.status()),
+            });
+        }
+
+        Ok(response)
+    }
 
-async fn get_cached_token_for_url_internal(token_cache: &TokenCache, url: &str) -> Option<String> {
-    let scope_prefix = extract_scope_prefix(url)?;
-    let cache = token_cache.read().await;
-    let now = Instant::now();
+    async fn cached_token_for_url(&self, url: &str) -> Option<String> {
+        let scope_prefix = extract_scope_prefix(url)?;
+        let cache = self.token_cache.read().await;
+        let now = Instant::now();
 
-    for (scope, cached) in cache.iter() {
-        if scope.starts_with(&scope_prefix) && cached.expires_at > now {
-            return Some(cached.token.clone());
+        for (scope, cached) in cache.iter() {
+            if scope.starts_with(&scope_prefix) && cached.expires_at > now {
+                return Some(cached.token.clone());
+            }
         }
+        None
     }
-    None
-}
 
-async fn handle_auth_challenge_internal(
-    client: &reqwest::Client,
-    token_cache: &TokenCache,
-    url: &str,
-    response: reqwest::Response,
-) -> Result<reqwest::Response, Error> {
-    let www_auth_header = response.headers().get(WWW_AUTHENTICATE);
-
-    let www_auth = match www_auth_header {
-        Some(value) => value.to_str().map_err(|_| Error::NetworkFailure {
-            message: "WWW-Authenticate header contains invalid characters".to_string(),
-        })?,
-        None => {
+    /// Retry `url` with a token for the scope `response` challenged. A cached
+    /// token the registry rejects (revoked, or issued for a different
+    /// credential) is dropped and a fresh one requested once.
+    async fn answer_challenge(
+        &self,
+        url: &str,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, Error> {
+        let www_auth = match response.headers().get(WWW_AUTHENTICATE) {
+            Some(value) => value.to_str().map_err(|_| Error::NetworkFailure {
+                message: "WWW-Authenticate header contains invalid characters".to_string(),
+            })?,
+            None => {
+                return Err(Error::NetworkFailure {
+                    message:
+                        "server returned 401 without WWW-Authenticate header (may be rate limited)"
+                            .to_string(),
+                });
+            }
+        };
+        if www_auth.starts_with("Basic") {
+            let hint = if self.credentials.for_url(url).is_some() {
+                "the login for this host was rejected"
+            } else {
+                "add a login for this host to ~/.netrc or the keychain"
+            };
             return Err(Error::NetworkFailure {
-                message:
-                    "server returned 401 without WWW-Authenticate header (may be rate limited)"
-                        .to_string(),
+                message: format!("authentication failed: {url} requires basic auth ({hint})"),
             });
         }
-    };
+        let (realm, service, scope) = parse_www_authenticate(www_auth)?;
+
+        let mut cached = self.cached_token(&scope).await;
+        loop {
+            let from_cache = cached.is_some();
+            let token = match cached.take() {
+                Some(token) => token,
+                None => self.request_token(&realm, &service, &scope).await?,
+            };
+
+            let response = self
+                .client
+                .get(url)
+                .bearer_auth(&token)
+                .send()
+                .await
+                .map_err(|e| Error::NetworkFailure {
+                    message: e.to_string(),
+                })?;
+
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+            if from_cache {
+                self.forget_token(&scope, &token).await;
+                continue;
+            }
 
-    let token = fetch_bearer_token_internal(client, token_cache, www_auth).await?;
+            let hint = if self.github_token.is_some() {
+                "check that HOMEBREW_GITHUB_API_TOKEN may read packages"
+            } else {
+                "set HOMEBREW_GITHUB_API_TOKEN or HOMEBREW_BOTTLE_MIRRORS if your network blocks anonymous access"
+            };
+            return Err(Error::NetworkFailure {
+                message: format!("authentication failed: token was rejected by server ({hint})"),
+            });
+        }
+    }
 
-    let response = client
-        .get(url)
-        .header(
-            AUTHORIZATION,
-            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
-        )
-        .send()
-        .await
-        .map_err(|e| Error::NetworkFailure {
-            message: e.to_string(),
-        })?;
+    async fn cached_token(&self, scope: &str) -> Option<String> {
+        let cache = self.token_cache.read().await;
+        cache
+            .get(scope)
+            .filter(|cached| cached.expires_at > Instant::now())
+            .map(|cached| cached.token.clone())
+    }
 
-    if response.status() == StatusCode::UNAUTHORIZED {
-        return Err(Error::NetworkFailure {
-            message: "authentication failed: token was rejected by server".to_string(),
-        });
+    /// Drop `token` from the cache unless another request already replaced it
+    async fn forget_token(&self, scope: &str, token: &str) {
+        let mut cache = self.token_cache.write().await;
+        if cache.get(scope).is_some_and(|cached| cached.token == token) {
+            cache.remove(scope);
+        }
     }
 
-    Ok(response)
-}
+    /// Request a token for `scope` and cache it. With a GitHub token and a
+    /// realm on one of `github_token_hosts` the request is authenticated,
+    /// falling back to an anonymous request if the token server refuses the
+    /// credential (bottles themselves are public).
+    async fn request_token(
+        &self,
+        realm: &str,
+        service: &str,
+        scope: &str,
+    ) -> Result<String, Error> {
+        let token_url =
+            reqwest::Url::parse_with_params(realm, &[("service", service), ("scope", scope)])
+                .map_err(|e| Error::NetworkFailure {
+                    message: format!("failed to construct token URL: {e}"),
+                })?;
+
+        let github_token = self.github_token.as_ref().filter(|_| {
+            token_url
+                .host_str()
+                .is_some_and(|host| self.github_token_hosts.iter().any(|h| h == host))
+        });
+        let mut response = None;
+        if let Some(github_token) = github_token {
+            let authenticated = self
+                .send_token_request(
+                    self.client
+                        .get(token_url.clone())
+                        .basic_auth("zerobrew", Some(github_token)),
+                    &token_url,
+                )
+                .await?;
+            if !matches!(
+                authenticated.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) {
+                response = Some(authenticated);
+            }
+        }
+        let response = match response {
+            Some(response) => response,
+            None => {
+                self.send_token_request(self.client.get(token_url.clone()), &token_url)
+                    .await?
+            }
+        };
 
-async fn fetch_bearer_token_internal(
-    client: &reqwest::Client,
-    token_cache: &TokenCache,
-    www_authenticate: &str,
-) -> Result<String, Error> {
-    let (realm, service, scope) = parse_www_authenticate(www_authenticate)?;
-
-    // Check cache first
-    {
-        let cache = token_cache.read().await;
-        if let Some(cached) = cache.get(&scope)
-            && cached.expires_at > Instant::now()
-        {
-            return Ok(cached.token.clone());
+        if !response.status().is_success() {
+            return Err(Error::NetworkFailure {
+                message: format!("token request returned HTTP {}", response.status()),
+            });
         }
-    }
 
-    let token_url =
-        reqwest::Url::parse_with_params(&realm, &[("service", &service), ("scope", &scope)])
-            .map_err(|e| Error::NetworkFailure {
-                message: format!("failed to construct token URL: {e}"),
+        let token_response: TokenResponse =
+            response.json().await.map_err(|e| Error::NetworkFailure {
+                message: format!("failed to parse token response: {e}"),
             })?;
 
-    // Anonymous token request (homebrew bottles are public)
-    let response = client
-        .get(token_url)
-        .send()
-        .await
-        .map_err(|e| Error::NetworkFailure {
-            message: format!("token request failed: {e}"),
-        })?;
+        // Cache the token
+        {
+            let mut cache = self.token_cache.write().await;
+            cache.insert(
+                scope.to_string(),
+                CachedToken {
+                    token: token_response.token.clone(),
+                    expires_at: Instant::now() + Duration::from_secs(240),
+                },
+            );
+        }
 
-    if !response.status().is_success() {
-        return Err(Error::NetworkFailure {
-            message: format!("token request returned HTTP {}", response.status()),
-        });
+        Ok(token_response.token)
     }
 
-    let token_response: TokenResponse =
-        response.json().await.map_err(|e| Error::NetworkFailure {
-            message: format!("failed to parse token response: {e}"),
+    async fn send_token_request(
+        &self,
+        request: reqwest::RequestBuilder,
+        token_url: &reqwest::Url,
+    ) -> Result<reqwest::Response, Error> {
+        self.rate_limits.check(token_url.as_str())?;
+        let response = request.send().await.map_err(|e| Error::NetworkFailure {
+            message: format!("token request failed: {e}"),
         })?;
-
-    // Cache the token
-    {
-        let mut cache = token_cache.write().await;
-        cache.insert(
-            scope,
-            CachedToken {
-                token: token_response.token.clone(),
-                expires_at: Instant::now() + Duration::from_secs(240),
-            },
-        );
+        self.rate_limits
+            .check_response(token_url.as_str(), &response)?;
+        Ok(response)
     }
-
-    Ok(token_response.token)
 }
 
 async fn download_response_internal(
@@ -456,6 +840,7 @@ async fn download_response_internal(
     expected_sha256: &str,
     name: Option<String>,
     progress: Option<DownloadProgressCallback>,
+    mut tee: Option<BodySender>,
 ) -> Result<PathBuf, Error> {
     let total_bytes = response
         .headers()
@@ -493,6 +878,12 @@ async fn download_response_internal(
             .map_err(|e| Error::NetworkFailure {
                 message: format!("failed to write chunk: {e}"),
             })?;
+        // The reader stops early when unpacking fails; keep downloading
+        if let Some(sender) = &tee
+            && !sender.send(chunk).await
+        {
+            tee = None;
+        }
 
         if let (Some(cb), Some(n)) = (&progress, &name) {
             cb(InstallProgress::DownloadProgress {
@@ -528,20 +919,25 @@ async fn download_response_internal(
         });
     }
 
-    writer.commit()
+    let path = writer.commit()?;
+    if let Some(sender) = tee {
+        sender.finish().await;
+    }
+    Ok(path)
 }
 
 /// Extract scope prefix from a GHCR URL for token cache matching.
 /// For URL like "https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:...",
 /// returns "repository:homebrew/core/" which matches scopes like "repository:homebrew/core/lz4:pull"
 fn extract_scope_prefix(url: &str) -> Option<String> {
-    if url.contains("ghcr.io/v2/homebrew/core/") {
-        // All homebrew/core packages use the same token server, but scopes are per-package
-        // We can't reuse tokens across packages, so return the full path prefix
-        Some("repository:homebrew/core/".to_string())
-    } else {
-        None
-    }
+    // Blobs live at <registry>/v2/<owner>/<repo>/<image>/blobs/sha256:...,
+    // for homebrew/core as for taps publishing to their own packages.
+    // Scopes are per image, so return the prefix all of the tap's share
+    let (_, path) = url.split_once("/v2/")?;
+    let (image, _) = path.split_once("/blobs/")?;
+    let mut parts = image.splitn(3, '/');
+    let (owner, repo, _) = (parts.next()?, parts.next()?, parts.next()?);
+    Some(format!("repository:{owner}/{repo}/"))
 }
 
 fn parse_www_authenticate(header: &str) -> Result<(String, String, String), Error> {
@@ -587,12 +983,38 @@ pub struct DownloadRequest {
     pub name: String,
 }
 
-type InflightMap = HashMap<String, Arc<tokio::sync::broadcast::Sender<Result<PathBuf, String>>>>;
+type InflightMap = HashMap<String, Arc<tokio::sync::broadcast::Sender<Result<PathBuf, Error>>>>;
+
+/// Where [`ParallelDownloader::download_streaming`] unpacks bottles
+#[derive(Clone)]
+struct IngestTarget {
+    store: Store,
+    /// Shared with extractions from the cache, so both count against
+    /// `ConcurrencyLimits::extractions`
+    slots: Arc<Semaphore>,
+}
+
+impl IngestTarget {
+    /// Start unpacking `sha256` from a pipe on the blocking pool, holding an
+    /// extraction slot until it finishes. `None` when no slot is free, in
+    /// which case the bottle is extracted from the cache once downloaded.
+    fn start(self, sha256: &str) -> Option<(BodySender, JoinHandle<Result<PathBuf, Error>>)> {
+        let slot = self.slots.try_acquire_owned().ok()?;
+        let (tee, reader) = body_pipe();
+        let key = sha256.to_string();
+        let ingest = tokio::task::spawn_blocking(move || {
+            let _slot = slot;
+            self.store.ingest_reader(&key, reader)
+        });
+        Some((tee, ingest))
+    }
+}
 
 pub struct ParallelDownloader {
     downloader: Arc<Downloader>,
     semaphore: Arc<Semaphore>,
     inflight: Arc<Mutex<InflightMap>>,
+    ingest: Option<IngestTarget>,
 }
 
 impl ParallelDownloader {
@@ -601,14 +1023,73 @@ impl ParallelDownloader {
             downloader: Arc::new(Downloader::new(blob_cache)),
             semaphore: Arc::new(Semaphore::new(concurrency)),
             inflight: Arc::new(Mutex::new(HashMap::new())),
+            ingest: None,
         }
     }
 
+    /// Unpack bottles into `store` while [`Self::download_streaming`]
+    /// downloads them, instead of reading them back from the cache afterwards.
+    /// Each unpack holds one of `slots`, the installer's extraction slots.
+    pub fn set_ingest_store(&mut self, store: Store, slots: Arc<Semaphore>) {
+        self.ingest = Some(IngestTarget { store, slots });
+    }
+
     /// Remove a blob from the cache (used when extraction fails due to corruption)
     pub fn remove_blob(&self, sha256: &str) -> bool {
         self.downloader.remove_blob(sha256)
     }
 
+    /// Change how many downloads may run at once
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.semaphore = Arc::new(Semaphore::new(concurrency));
+    }
+
+    /// Record or replay bottle downloads (see [`crate::http_fixtures`])
+    pub fn set_http_mode(&mut self, mode: HttpMode) {
+        let current = &self.downloader;
+        self.downloader = Arc::new(Downloader {
+            oci: current.oci.clone(),
+            blob_cache: current.blob_cache.clone(),
+            http_mode: mode,
+        });
+    }
+
+    /// Share rate-limit cool-downs with other clients (see [`crate::ratelimit`])
+    pub fn set_rate_limits(&mut self, rate_limits: RateLimits) {
+        let current = &self.downloader;
+        let mut oci = current.oci.clone();
+        oci.rate_limits = rate_limits;
+        self.downloader = Arc::new(Downloader {
+            oci,
+            blob_cache: current.blob_cache.clone(),
+            http_mode: current.http_mode.clone(),
+        });
+    }
+
+    /// Authenticate requests to private mirrors with `credentials`
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        let current = &self.downloader;
+        let mut oci = current.oci.clone();
+        oci.credentials = credentials;
+        self.downloader = Arc::new(Downloader {
+            oci,
+            blob_cache: current.blob_cache.clone(),
+            http_mode: current.http_mode.clone(),
+        });
+    }
+
+    /// Never touch the network, failing downloads of bottles that aren't cached
+    pub fn set_offline(&mut self, offline: bool) {
+        let current = &self.downloader;
+        let mut oci = current.oci.clone();
+        oci.offline = offline;
+        self.downloader = Arc::new(Downloader {
+            oci,
+            blob_cache: current.blob_cache.clone(),
+            http_mode: current.http_mode.clone(),
+        });
+    }
+
     /// Download a single file (used for retries after corruption)
     pub async fn download_single(
         &self,
@@ -621,8 +1102,10 @@ impl ParallelDownloader {
             self.inflight.clone(),
             request,
             progress,
+            None,
         )
         .await
+        .map(|(blob_path, _)| blob_path)
     }
 
     pub async fn download_all(
@@ -646,7 +1129,9 @@ impl ParallelDownloader {
                 let progress = progress.clone();
 
                 tokio::spawn(async move {
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await
+                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress, None)
+                        .await
+                        .map(|(blob_path, _)| blob_path)
                 })
             })
             .collect();
@@ -662,9 +1147,47 @@ impl ParallelDownloader {
         Ok(results)
     }
 
+    /// Look up the sizes of several files concurrently (see [`Downloader::remote_size`]).
+    /// Lookups that fail yield `None`.
+    pub async fn remote_sizes(&self, urls: Vec<String>) -> Vec<Option<u64>> {
+        let lookups = urls.into_iter().map(|url| {
+            let downloader = self.downloader.clone();
+            let semaphore = self.semaphore.clone();
+            async move {
+                let _permit = semaphore.acquire().await.ok()?;
+                downloader.remote_size(&url).await.ok().flatten()
+            }
+        });
+        futures::future::join_all(lookups).await
+    }
+
+    /// Rebuild a bottle from a cached one and a patch, within the download
+    /// limit (see [`Downloader::download_delta`])
+    pub async fn download_delta(
+        &self,
+        url: &str,
+        from_sha256: &str,
+        expected_sha256: &str,
+    ) -> Result<u64, Error> {
+        let _permit = self
+            .semaphore
+            .acquire()
+            .await
+            .map_err(|e| Error::NetworkFailure {
+                message: format!("semaphore error: {e}"),
+            })?;
+        self.downloader
+            .download_delta(url, from_sha256, expected_sha256)
+            .await
+    }
+
     /// Stream downloads as they complete, allowing concurrent extraction.
     /// Returns a receiver that yields DownloadResult for each completed download.
-    /// The downloads are started immediately and results are sent as soon as each completes.
+    /// Bottles already in the blob cache are yielded first, before any network
+    /// download, so their extraction can start while the rest are fetched.
+    /// The remaining downloads are started immediately and results are sent
+    /// as soon as each completes. With an ingest store, each body is also
+    /// unpacked into the store as it arrives (see [`crate::pipe`]).
     pub fn download_streaming(
         &self,
         requests: Vec<DownloadRequest>,
@@ -673,6 +1196,24 @@ impl ParallelDownloader {
         let (tx, rx) = mpsc::channel(requests.len().max(1));
 
         for (index, req) in requests.into_iter().enumerate() {
+            if self.downloader.blob_cache.has_blob(&req.sha256) {
+                if let Some(ref cb) = progress {
+                    cb(InstallProgress::DownloadCompleted {
+                        name: req.name.clone(),
+                        total_bytes: 0,
+                    });
+                }
+                // The channel has room for every request, so this can't fail
+                let _ = tx.try_send(Ok(DownloadResult {
+                    blob_path: self.downloader.blob_cache.blob_path(&req.sha256),
+                    name: req.name,
+                    sha256: req.sha256,
+                    index,
+                    entry: None,
+                }));
+                continue;
+            }
+
             let downloader = self.downloader.clone();
             let semaphore = self.semaphore.clone();
             let inflight = self.inflight.clone();
@@ -680,16 +1221,23 @@ impl ParallelDownloader {
             let tx = tx.clone();
             let name = req.name.clone();
             let sha256 = req.sha256.clone();
+            let ingest = self
+                .ingest
+                .clone()
+                .filter(|ingest| !ingest.store.has_entry(&req.sha256));
 
             tokio::spawn(async move {
-                let result =
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await;
+                let result = Self::download_with_dedup(
+                    downloader, semaphore, inflight, req, progress, ingest,
+                )
+                .await;
                 let _ = tx
-                    .send(result.map(|blob_path| DownloadResult {
+                    .send(result.map(|(blob_path, entry)| DownloadResult {
                         name,
                         sha256,
                         blob_path,
                         index,
+                        entry,
                     }))
                     .await;
             });
@@ -704,7 +1252,16 @@ impl ParallelDownloader {
         inflight: Arc<Mutex<InflightMap>>,
         req: DownloadRequest,
         progress: Option<DownloadProgressCallback>,
-    ) -> Result<PathBuf, Error> {
+        ingest: Option<IngestTarget>,
+    ) -> Result<(PathBuf, Option<PathBuf>), Error> {
+        // A cache hit needs no network, so don't queue it behind downloads
+        if downloader.blob_cache.has_blob(&req.sha256) {
+            return downloader
+                .download_with_progress(&req.url, &req.sha256, Some(req.name), progress)
+                .await
+                .map(|blob_path| (blob_path, None));
+        }
+
         // Check if there's already an inflight request for this sha256
         let mut receiver = {
             let mut map = inflight.lock().await;
@@ -726,7 +1283,7 @@ impl ParallelDownloader {
                 message: format!("broadcast recv error: {e}"),
             })?;
 
-            return result.map_err(|msg| Error::NetworkFailure { message: msg });
+            return result.map(|blob_path| (blob_path, None));
         }
 
         // We're the first request for this sha256, do the actual download
@@ -737,23 +1294,32 @@ impl ParallelDownloader {
                 message: format!("semaphore error: {e}"),
             })?;
 
+        // Start unpacking only now, so bottles queued for a permit don't
+        // tie up blocking threads and store locks
+        let (tee, ingest) = match ingest.and_then(|ingest| ingest.start(&req.sha256)) {
+            Some((tee, ingest)) => (Some(tee), Some(ingest)),
+            None => (None, None),
+        };
+
         let result = downloader
-            .download_with_progress(&req.url, &req.sha256, Some(req.name), progress)
+            .download_teed(&req.url, &req.sha256, Some(req.name), progress, tee)
             .await;
 
         // Notify waiters and clean up
         {
             let mut map = inflight.lock().await;
             if let Some(sender) = map.remove(&req.sha256) {
-                let broadcast_result = match &result {
-                    Ok(path) => Ok(path.clone()),
-                    Err(e) => Err(e.to_string()),
-                };
-                let _ = sender.send(broadcast_result);
+                let _ = sender.send(result.clone());
             }
         }
 
-        result
+        // Unpacking fails unless the download finished its pipe, in which
+        // case the bottle is extracted from the cache instead
+        let entry = match ingest {
+            Some(ingest) => ingest.await.ok().and_then(Result::ok),
+            None => None,
+        };
+        result.map(|blob_path| (blob_path, entry))
     }
 }
 
@@ -763,7 +1329,7 @@ mod tests {
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::Duration;
     use tempfile::TempDir;
-    use wiremock::matchers::{method, path};
+    use wiremock::matchers::{header, header_exists, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
@@ -791,6 +1357,130 @@ mod tests {
         assert_eq!(std::fs::read(&blob_path).unwrap(), content);
     }
 
+    #[tokio::test]
+    async fn rate_limit_stops_racing_and_queued_downloads() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let downloader = ParallelDownloader::new(blob_cache, 1);
+        let requests = ["a", "b"]
+            .into_iter()
+            .map(|name| DownloadRequest {
+                url: format!("{}/{name}.tar.gz", mock_server.uri()),
+                sha256: name.repeat(64),
+                name: name.to_string(),
+            })
+            .collect();
+
+        let err = downloader.download_all(requests).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(120),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn file_urls_are_read_and_verified() {
+        let tmp = TempDir::new().unwrap();
+        let bottle = tmp.path().join("mirror/bottles/foo.tar.gz");
+        std::fs::create_dir_all(bottle.parent().unwrap()).unwrap();
+        std::fs::write(&bottle, b"hello world").unwrap();
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        let blob_cache = BlobCache::new(&tmp.path().join("cache")).unwrap();
+        let downloader = Downloader::new(blob_cache);
+        let url = format!("file://{}", bottle.display());
+
+        assert_eq!(downloader.remote_size(&url).await.unwrap(), Some(11));
+        let blob = downloader.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob).unwrap(), b"hello world");
+
+        let wrong = "0000000000000000000000000000000000000000000000000000000000000000";
+        let err = downloader.download(&url, wrong).await.unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn deltas_are_streamed_into_the_cache_and_verified() {
+        let old: Vec<u8> = (0..50_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut new = old.clone();
+        new[100..200].fill(9);
+        let patch = crate::test_utils::make_delta_patch(&old, &new);
+        let old_sha = format!("{:x}", Sha256::digest(&old));
+        let new_sha = format!("{:x}", Sha256::digest(&new));
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/delta.zst"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(patch.clone()))
+            .mount(&mock_server)
+            .await;
+        let url = format!("{}/delta.zst", mock_server.uri());
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let mut writer = blob_cache.start_write(&old_sha).unwrap();
+        writer.write_all(&old).unwrap();
+        writer.commit().unwrap();
+        let downloader = Downloader::new(blob_cache.clone());
+
+        // A patch that doesn't rebuild the expected bottle leaves nothing behind
+        let wrong = "0000000000000000000000000000000000000000000000000000000000000000";
+        let err = downloader
+            .download_delta(&url, &old_sha, wrong)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+        assert!(!blob_cache.has_blob(wrong));
+        assert_eq!(std::fs::read_dir(blob_cache.tmp_dir()).unwrap().count(), 0);
+
+        let size = downloader
+            .download_delta(&url, &old_sha, &new_sha)
+            .await
+            .unwrap();
+        assert_eq!(size, patch.len() as u64);
+        assert_eq!(std::fs::read(blob_cache.blob_path(&new_sha)).unwrap(), new);
+    }
+
+    #[tokio::test]
+    async fn offline_downloads_only_serve_cached_bottles() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+
+        let offline = Downloader::new(blob_cache.clone()).with_offline(true);
+        let err = offline.download(&url, sha256).await.unwrap_err();
+        assert!(err.to_string().contains("offline"), "{err}");
+
+        Downloader::new(blob_cache)
+            .download(&url, sha256)
+            .await
+            .unwrap();
+        let blob = offline.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob).unwrap(), content);
+    }
+
     #[tokio::test]
     async fn mismatch_deletes_blob_and_errors() {
         let mock_server = MockServer::start().await;
@@ -854,6 +1544,52 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn record_then_replay_without_network() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path().join("fixtures"));
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+
+        let recorder = Downloader::new(BlobCache::new(&tmp.path().join("a")).unwrap())
+            .with_http_mode(HttpMode::Record(fixtures.clone()));
+        recorder.download(&url, sha256).await.unwrap();
+        drop(mock_server);
+
+        let replayer = Downloader::new(BlobCache::new(&tmp.path().join("b")).unwrap())
+            .with_http_mode(HttpMode::Replay(fixtures));
+        let blob_path = replayer.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn replay_verifies_checksum() {
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path().join("fixtures"));
+        let url = "https://ghcr.io/v2/homebrew/core/foo/blobs/sha256:abc";
+        fixtures.save(url, b"tampered").unwrap();
+
+        let downloader = Downloader::new(BlobCache::new(&tmp.path().join("cache")).unwrap())
+            .with_http_mode(HttpMode::Replay(fixtures));
+        let err = downloader
+            .download(
+                url,
+                "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+
     #[tokio::test]
     async fn peak_concurrent_downloads_within_limit() {
         let mock_server = MockServer::start().await;
@@ -903,6 +1639,161 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn streaming_yields_cached_blobs_before_downloads() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let cached_sha = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/cached.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(b"slow".to_vec())
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let mut writer = blob_cache.start_write(cached_sha).unwrap();
+        writer.write_all(content).unwrap();
+        writer.commit().unwrap();
+
+        // A single download slot, taken by the uncached bottles listed first
+        let downloader = ParallelDownloader::new(blob_cache, 1);
+        let mut requests: Vec<_> = (0..2)
+            .map(|i| DownloadRequest {
+                url: format!("{}/slow{i}.tar.gz", mock_server.uri()),
+                sha256: format!("{:064x}", i),
+                name: format!("slow{i}"),
+            })
+            .collect();
+        requests.push(DownloadRequest {
+            url: format!("{}/cached.tar.gz", mock_server.uri()),
+            sha256: cached_sha.to_string(),
+            name: "cached".to_string(),
+        });
+
+        let mut rx = downloader.download_streaming(requests, None);
+        let first = rx.recv().await.unwrap().unwrap();
+        assert_eq!(first.name, "cached");
+        assert_eq!(first.index, 2);
+        assert_eq!(std::fs::read(&first.blob_path).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn streaming_unpacks_bottles_into_the_store_while_downloading() {
+        use crate::test_utils::{mock_bottle_tarball, sha256_hex};
+
+        let mock_server = MockServer::start().await;
+        let bottle = mock_bottle_tarball("jq");
+        let sha256 = sha256_hex(&bottle);
+        Mock::given(method("GET"))
+            .and(path("/jq.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(&tmp.path().join("cache")).unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let mut downloader = ParallelDownloader::new(blob_cache, 4);
+        downloader.set_ingest_store(store.clone(), Arc::new(Semaphore::new(4)));
+
+        let mut rx = downloader.download_streaming(
+            vec![DownloadRequest {
+                url: format!("{}/jq.tar.gz", mock_server.uri()),
+                sha256: sha256.clone(),
+                name: "jq".to_string(),
+            }],
+            None,
+        );
+        let result = rx.recv().await.unwrap().unwrap();
+
+        assert_eq!(result.entry, Some(store.entry_path(&sha256)));
+        assert!(store.entry_path(&sha256).join("jq/1.0.0/bin/jq").exists());
+        assert!(store.manifest_path(&sha256).exists());
+        // The bottle is cached as well
+        assert_eq!(std::fs::read(&result.blob_path).unwrap(), bottle);
+    }
+
+    #[tokio::test]
+    async fn streaming_without_a_free_extraction_slot_leaves_the_bottle_cached() {
+        use crate::test_utils::mock_bottle_tarball;
+
+        let bottle = mock_bottle_tarball("jq");
+        let sha256 = format!("{:x}", Sha256::digest(&bottle));
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/jq.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(&tmp.path().join("cache")).unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let slots = Arc::new(Semaphore::new(1));
+        let mut downloader = ParallelDownloader::new(blob_cache, 4);
+        downloader.set_ingest_store(store.clone(), slots.clone());
+
+        let _busy = slots.acquire().await.unwrap();
+        let mut rx = downloader.download_streaming(
+            vec![DownloadRequest {
+                url: format!("{}/jq.tar.gz", mock_server.uri()),
+                sha256: sha256.clone(),
+                name: "jq".to_string(),
+            }],
+            None,
+        );
+        let result = rx.recv().await.unwrap().unwrap();
+
+        assert_eq!(result.entry, None);
+        assert!(!store.has_entry(&sha256));
+        assert_eq!(std::fs::read(&result.blob_path).unwrap(), bottle);
+    }
+
+    #[tokio::test]
+    async fn streaming_keeps_no_store_entry_for_mismatched_bottles() {
+        use crate::test_utils::mock_bottle_tarball;
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/jq.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(mock_bottle_tarball("jq")))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(&tmp.path().join("cache")).unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let mut downloader = ParallelDownloader::new(blob_cache, 4);
+        downloader.set_ingest_store(store.clone(), Arc::new(Semaphore::new(4)));
+
+        let sha256 = "0".repeat(64);
+        let mut rx = downloader.download_streaming(
+            vec![DownloadRequest {
+                url: format!("{}/jq.tar.gz", mock_server.uri()),
+                sha256: sha256.clone(),
+                name: "jq".to_string(),
+            }],
+            None,
+        );
+
+        assert!(matches!(
+            rx.recv().await.unwrap(),
+            Err(Error::ChecksumMismatch { .. })
+        ));
+        assert!(!store.has_entry(&sha256));
+    }
+
     #[tokio::test]
     async fn same_blob_requested_multiple_times_fetches_once() {
         let mock_server = MockServer::start().await;
@@ -1001,6 +1892,19 @@ mod tests {
         assert_eq!(prefix, Some("repository:homebrew/core/".to_string()));
     }
 
+    #[test]
+    fn extract_scope_prefix_for_tap_packages() {
+        let url = "https://ghcr.io/v2/user/tools/python/3.12/blobs/sha256:abc123";
+        assert_eq!(
+            extract_scope_prefix(url),
+            Some("repository:user/tools/".to_string())
+        );
+        assert_eq!(
+            extract_scope_prefix("https://ghcr.io/v2/user/blobs/sha256:abc"),
+            None
+        );
+    }
+
     #[test]
     fn extract_scope_prefix_returns_none_for_non_ghcr() {
         let url = "https://example.com/downloads/package.tar.gz";
@@ -1060,4 +1964,190 @@ mod tests {
         let err = result.unwrap_err();
         assert!(matches!(err, Error::NetworkFailure { message } if message.contains("404")));
     }
+
+    /// Mount a registry blob that challenges requests without one of `tokens`,
+    /// answering each token with a body of a different length
+    async fn mount_registry_blob(mock_server: &MockServer, tokens: &[&str]) -> String {
+        let blob = "/v2/homebrew/core/foo/blobs/sha256:abc";
+        for (i, token) in tokens.iter().enumerate() {
+            Mock::given(method("GET"))
+                .and(path(blob))
+                .and(header("authorization", format!("Bearer {token}")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; i + 1]))
+                .with_priority(1)
+                .mount(mock_server)
+                .await;
+        }
+        let challenge = format!(
+            r#"Bearer realm="{}/token",service="ghcr.io",scope="repository:homebrew/core/foo:pull""#,
+            mock_server.uri()
+        );
+        Mock::given(method("GET"))
+            .and(path(blob))
+            .respond_with(ResponseTemplate::new(401).insert_header("www-authenticate", challenge))
+            .mount(mock_server)
+            .await;
+        format!("{}{blob}", mock_server.uri())
+    }
+
+    async fn mount_token(mock_server: &MockServer, authorization: Option<&str>, token: &str) {
+        let response =
+            ResponseTemplate::new(200).set_body_string(format!(r#"{{"token": "{token}"}}"#));
+        let mock = match authorization {
+            Some(value) => Mock::given(method("GET"))
+                .and(path("/token"))
+                .and(header("authorization", value))
+                .respond_with(response)
+                .with_priority(1),
+            None => Mock::given(method("GET"))
+                .and(path("/token"))
+                .respond_with(response),
+        };
+        mock.mount(mock_server).await;
+    }
+
+    #[tokio::test]
+    async fn registry_tokens_use_github_token_when_accepted() {
+        let mock_server = MockServer::start().await;
+        let url = mount_registry_blob(&mock_server, &["public", "private"]).await;
+        // Basic auth for `zerobrew:good`
+        mount_token(&mock_server, Some("Basic emVyb2JyZXc6Z29vZA=="), "private").await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(401))
+            .with_priority(2)
+            .mount(&mock_server)
+            .await;
+        mount_token(&mock_server, None, "public").await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = |token: Option<&str>| {
+            Downloader::new(BlobCache::new(tmp.path()).unwrap())
+                .with_github_token(token.map(str::to_string))
+                .with_github_token_hosts(vec!["127.0.0.1".to_string()])
+        };
+
+        assert_eq!(
+            downloader(Some("good")).remote_size(&url).await.unwrap(),
+            Some(2)
+        );
+        // A rejected credential falls back to an anonymous token
+        assert_eq!(
+            downloader(Some("bad")).remote_size(&url).await.unwrap(),
+            Some(1)
+        );
+        assert_eq!(downloader(None).remote_size(&url).await.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn github_token_is_not_sent_to_other_token_servers() {
+        let mock_server = MockServer::start().await;
+        let url = mount_registry_blob(&mock_server, &["public"]).await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .and(header_exists("authorization"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"token": "stolen"}"#))
+            .with_priority(1)
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        mount_token(&mock_server, None, "public").await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap())
+            .with_github_token(Some("secret".to_string()));
+
+        assert_eq!(downloader.remote_size(&url).await.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn private_mirrors_get_basic_auth_from_credentials() {
+        let mock_server = MockServer::start().await;
+        let content = b"private bottle";
+        let sha256 = format!("{:x}", Sha256::digest(content));
+        // Basic auth for `alice:s3cret`
+        Mock::given(method("GET"))
+            .and(path("/jq.tar.gz"))
+            .and(header("authorization", "Basic YWxpY2U6czNjcmV0"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jq.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(401)
+                    .insert_header("www-authenticate", r#"Basic realm="mirror""#),
+            )
+            .mount(&mock_server)
+            .await;
+        let url = format!("{}/jq.tar.gz", mock_server.uri());
+
+        let tmp = TempDir::new().unwrap();
+        // Library clients don't read `~/.netrc` or the keychain unless asked
+        let anonymous = Downloader::new(BlobCache::new(&tmp.path().join("a")).unwrap());
+        let err = anonymous.download(&url, &sha256).await.unwrap_err();
+        assert!(
+            matches!(err, Error::NetworkFailure { message } if message.contains("requires basic auth"))
+        );
+
+        let credentials = Credentials::none().with_netrc(crate::credentials::parse_netrc(
+            "machine 127.0.0.1 login alice password s3cret",
+        ));
+        let downloader = Downloader::new(BlobCache::new(&tmp.path().join("b")).unwrap())
+            .with_credentials(credentials);
+        let blob = downloader.download(&url, &sha256).await.unwrap();
+        assert_eq!(std::fs::read(blob).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn rejected_cached_token_is_replaced() {
+        let mock_server = MockServer::start().await;
+        let url = mount_registry_blob(&mock_server, &["t1"]).await;
+        mount_token(&mock_server, None, "t1").await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_github_token(None);
+        assert_eq!(downloader.remote_size(&url).await.unwrap(), Some(1));
+
+        // The registry stops accepting the cached token
+        mock_server.reset().await;
+        let url = mount_registry_blob(&mock_server, &["t2"]).await;
+        mount_token(&mock_server, None, "t2").await;
+
+        assert_eq!(downloader.remote_size(&url).await.unwrap(), Some(1));
+        let requests = mock_server.received_requests().await.unwrap();
+        let token_requests = requests.iter().filter(|r| r.url.path() == "/token").count();
+        assert_eq!(token_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn remote_sizes_read_content_length() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/big.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 4096]))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/gone.tar.gz"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let downloader = ParallelDownloader::new(blob_cache, 4);
+
+        let sizes = downloader
+            .remote_sizes(vec![
+                format!("{}/big.tar.gz", mock_server.uri()),
+                format!("{}/gone.tar.gz", mock_server.uri()),
+            ])
+            .await;
+        assert_eq!(sizes, vec![Some(4096), None]);
+    }
 }
@@ -1,90 +1,214 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::{Path, PathBuf};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
 
-use flate2::read::GzDecoder;
+use flate2::read::{DeflateDecoder, GzDecoder};
 use tar::Archive;
 use xz2::read::XzDecoder;
 use zstd::stream::read::Decoder as ZstdDecoder;
 
 use zb_core::Error;
 
+/// Archive formats recognised by [`detect_archive_format`]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum CompressionFormat {
+pub enum ArchiveFormat {
+    /// gzip-compressed tar
     Gzip,
+    /// xz-compressed tar
     Xz,
+    /// zstd-compressed tar
     Zstd,
+    /// bzip2-compressed tar
+    Bzip2,
+    /// Uncompressed tar
+    Tar,
+    /// Zip archive (stored or deflated entries)
+    Zip,
+    /// Unrecognised; extracted as gzip for compatibility
     Unknown,
 }
 
-fn detect_compression(path: &Path) -> Result<CompressionFormat, Error> {
+/// Options for [`extract_archive`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractOptions {
+    /// Leading path components to drop from every entry, like
+    /// `tar --strip-components`. Entries with no components left are skipped.
+    pub strip_components: usize,
+}
+
+/// Sniff an archive's format from its magic bytes, falling back to the
+/// file extension for formats without reliable magic.
+pub fn detect_archive_format(path: &Path) -> Result<ArchiveFormat, Error> {
     let mut file = File::open(path).map_err(|e| Error::StoreCorruption {
         message: format!("failed to open tarball: {e}"),
     })?;
 
-    let mut magic = [0u8; 6];
-    let bytes_read = file.read(&mut magic).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read magic bytes: {e}"),
-    })?;
-
-    if bytes_read < 2 {
-        return Ok(CompressionFormat::Unknown);
+    // Large enough to reach the ustar magic at offset 257
+    let mut magic = [0u8; 263];
+    let mut bytes_read = 0;
+    while bytes_read < magic.len() {
+        let n = file
+            .read(&mut magic[bytes_read..])
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read magic bytes: {e}"),
+            })?;
+        if n == 0 {
+            break;
+        }
+        bytes_read += n;
     }
 
+    Ok(sniff_format(&magic[..bytes_read]).unwrap_or_else(|| format_from_extension(path)))
+}
+
+fn sniff_format(magic: &[u8]) -> Option<ArchiveFormat> {
     // Gzip: 1f 8b
-    if magic[0] == 0x1f && magic[1] == 0x8b {
-        return Ok(CompressionFormat::Gzip);
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        return Some(ArchiveFormat::Gzip);
     }
 
     // XZ: fd 37 7a 58 5a 00 (FD 7zXZ\0)
-    if bytes_read >= 6 && magic[0..6] == [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00] {
-        return Ok(CompressionFormat::Xz);
+    if magic.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+        return Some(ArchiveFormat::Xz);
     }
 
     // Zstd: 28 b5 2f fd
-    if bytes_read >= 4 && magic[0..4] == [0x28, 0xb5, 0x2f, 0xfd] {
-        return Ok(CompressionFormat::Zstd);
+    if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        return Some(ArchiveFormat::Zstd);
+    }
+
+    // Bzip2: "BZh" followed by the block size digit
+    if magic.len() >= 4 && magic.starts_with(b"BZh") && magic[3].is_ascii_digit() {
+        return Some(ArchiveFormat::Bzip2);
+    }
+
+    // Zip: local file header, or end of central directory for an empty archive
+    if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+        return Some(ArchiveFormat::Zip);
+    }
+
+    // POSIX and GNU tar: "ustar" at offset 257
+    if magic.len() >= 262 && &magic[257..262] == b"ustar" {
+        return Some(ArchiveFormat::Tar);
     }
 
-    Ok(CompressionFormat::Unknown)
+    None
+}
+
+fn format_from_extension(path: &Path) -> ArchiveFormat {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if name.ends_with(".tar") {
+        ArchiveFormat::Tar
+    } else if name.ends_with(".zip") {
+        ArchiveFormat::Zip
+    } else {
+        ArchiveFormat::Unknown
+    }
 }
 
 /// Buffer size for decompression (64KB provides better throughput than default 8KB)
 const DECOMPRESS_BUFFER_SIZE: usize = 64 * 1024;
 
 pub fn extract_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<(), Error> {
-    let format = detect_compression(tarball_path)?;
+    extract_archive(tarball_path, dest_dir, ExtractOptions::default())
+}
 
-    let file = File::open(tarball_path).map_err(|e| Error::StoreCorruption {
+/// Extract a tar (gzip, xz, zstd, bzip2 or uncompressed) or zip archive,
+/// detecting the format from its contents.
+pub fn extract_archive(
+    archive_path: &Path,
+    dest_dir: &Path,
+    options: ExtractOptions,
+) -> Result<(), Error> {
+    let format = detect_archive_format(archive_path)?;
+
+    std::fs::create_dir_all(dest_dir).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create {}: {e}", dest_dir.display()),
+    })?;
+
+    if format == ArchiveFormat::Zip {
+        return extract_zip_archive(archive_path, dest_dir, options);
+    }
+    if format == ArchiveFormat::Bzip2 {
+        return extract_bzip2_archive(archive_path, dest_dir, options);
+    }
+
+    let file = File::open(archive_path).map_err(|e| Error::StoreCorruption {
         message: format!("failed to open tarball: {e}"),
     })?;
     // Use larger buffer for better decompression throughput (10-20% faster)
     let reader = BufReader::with_capacity(DECOMPRESS_BUFFER_SIZE, file);
 
     match format {
-        CompressionFormat::Gzip => {
-            let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
-        }
-        CompressionFormat::Xz => {
+        ArchiveFormat::Xz => {
             let decoder = XzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, options)
         }
-        CompressionFormat::Zstd => {
+        ArchiveFormat::Zstd => {
             let decoder = ZstdDecoder::new(reader).map_err(|e| Error::StoreCorruption {
                 message: format!("failed to create zstd decoder: {e}"),
             })?;
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, options)
         }
-        CompressionFormat::Unknown => {
-            // Try gzip as fallback
+        ArchiveFormat::Tar => extract_tar_archive(reader, dest_dir, options),
+        // Unknown formats are tried as gzip, as they always have been
+        _ => {
             let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, options)
         }
     }
 }
 
-fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
+/// Decompress bzip2 with the system `bzip2`, which ships with macOS and
+/// virtually every Linux distribution.
+fn extract_bzip2_archive(
+    archive_path: &Path,
+    dest_dir: &Path,
+    options: ExtractOptions,
+) -> Result<(), Error> {
+    let mut child = Command::new("bzip2")
+        .arg("-dc")
+        .arg(archive_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to run bzip2 for {}: {e}", archive_path.display()),
+        })?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let result = extract_tar_archive(
+        BufReader::with_capacity(DECOMPRESS_BUFFER_SIZE, stdout),
+        dest_dir,
+        options,
+    );
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to run bzip2 for {}: {e}", archive_path.display()),
+        })?;
+    if !output.status.success() {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "failed to decompress {}: {}",
+                archive_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        });
+    }
+    result
+}
+
+fn extract_tar_archive<R: Read>(
+    reader: R,
+    dest_dir: &Path,
+    options: ExtractOptions,
+) -> Result<(), Error> {
     let mut archive = Archive::new(reader);
 
     archive.set_preserve_permissions(true);
@@ -97,9 +221,12 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
             message: format!("failed to read archive entry: {e}"),
         })?;
 
-        let entry_path = entry.path().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read entry path: {e}"),
-        })?;
+        let entry_path = entry
+            .path()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read entry path: {e}"),
+            })?
+            .into_owned();
 
         // Store path as owned string for error message
         let path_display = entry_path.display().to_string();
@@ -107,16 +234,306 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
         // Security check: validate path doesn't escape destination
         validate_path(&entry_path, dest_dir)?;
 
-        entry
-            .unpack_in(dest_dir)
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to unpack entry {path_display}: {e}"),
+        if options.strip_components == 0 {
+            entry
+                .unpack_in(dest_dir)
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to unpack entry {path_display}: {e}"),
+                })?;
+            continue;
+        }
+
+        let Some(stripped) = strip_components(&entry_path, options.strip_components) else {
+            continue;
+        };
+        let target = prepare_target(dest_dir, &stripped)?;
+
+        // Hard links name another archive member, which moved too
+        if entry.header().entry_type() == tar::EntryType::Link {
+            let link_name = entry
+                .link_name()
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to read link name of {path_display}: {e}"),
+                })?
+                .map(|name| name.into_owned())
+                .unwrap_or_default();
+            validate_path(&link_name, dest_dir)?;
+            let Some(link_target) = strip_components(&link_name, options.strip_components) else {
+                return Err(Error::StoreCorruption {
+                    message: format!("hard link {path_display} points outside stripped tree"),
+                });
+            };
+            std::fs::hard_link(dest_dir.join(link_target), &target).map_err(|e| {
+                Error::StoreCorruption {
+                    message: format!("failed to unpack entry {path_display}: {e}"),
+                }
+            })?;
+            continue;
+        }
+
+        entry.unpack(&target).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to unpack entry {path_display}: {e}"),
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Zip record signatures
+const ZIP_END_OF_CENTRAL_DIR: u32 = 0x0605_4b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x0201_4b50;
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// Host system value in "version made by" for archives with Unix modes
+const ZIP_HOST_UNIX: u16 = 3;
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    compressed_size: u64,
+    mode: Option<u32>,
+    local_header_offset: u64,
+}
+
+fn zip_error(archive_path: &Path, message: impl std::fmt::Display) -> Error {
+    Error::StoreCorruption {
+        message: format!("invalid zip archive {}: {message}", archive_path.display()),
+    }
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Read a zip's central directory.
+fn read_zip_entries(file: &mut File, archive_path: &Path) -> Result<Vec<ZipEntry>, Error> {
+    let io_error = |e: std::io::Error| zip_error(archive_path, e);
+
+    // The end of central directory record is 22 bytes plus a comment of up
+    // to 64KiB, so it's somewhere in the file's tail
+    let len = file.seek(SeekFrom::End(0)).map_err(io_error)?;
+    let tail_len = len.min(22 + u16::MAX as u64);
+    file.seek(SeekFrom::Start(len - tail_len))
+        .map_err(io_error)?;
+    let mut tail = vec![0u8; tail_len as usize];
+    file.read_exact(&mut tail).map_err(io_error)?;
+
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| le_u32(&tail, i) == ZIP_END_OF_CENTRAL_DIR)
+        .ok_or_else(|| zip_error(archive_path, "no end of central directory"))?;
+    let count = le_u16(&tail, eocd + 10);
+    let cd_size = le_u32(&tail, eocd + 12);
+    let cd_offset = le_u32(&tail, eocd + 16);
+    // zip64 archives mark overflowing fields with all-ones
+    if count == u16::MAX || cd_size == u32::MAX || cd_offset == u32::MAX {
+        return Err(zip_error(archive_path, "zip64 archives are not supported"));
+    }
+
+    file.seek(SeekFrom::Start(cd_offset as u64))
+        .map_err(io_error)?;
+    let mut cd = vec![0u8; cd_size as usize];
+    file.read_exact(&mut cd).map_err(io_error)?;
+
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut at = 0;
+    for _ in 0..count {
+        if at + 46 > cd.len() || le_u32(&cd, at) != ZIP_CENTRAL_HEADER {
+            return Err(zip_error(archive_path, "truncated central directory"));
+        }
+        let made_by = le_u16(&cd, at + 4);
+        let name_len = le_u16(&cd, at + 28) as usize;
+        let extra_len = le_u16(&cd, at + 30) as usize;
+        let comment_len = le_u16(&cd, at + 32) as usize;
+        let external_attrs = le_u32(&cd, at + 38);
+        let name_end = at + 46 + name_len;
+        if name_end > cd.len() {
+            return Err(zip_error(archive_path, "truncated central directory"));
+        }
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(&cd[at + 46..name_end]).into_owned(),
+            flags: le_u16(&cd, at + 8),
+            method: le_u16(&cd, at + 10),
+            crc32: le_u32(&cd, at + 16),
+            compressed_size: le_u32(&cd, at + 20) as u64,
+            mode: (made_by >> 8 == ZIP_HOST_UNIX)
+                .then_some(external_attrs >> 16)
+                .filter(|&mode| mode != 0),
+            local_header_offset: le_u32(&cd, at + 42) as u64,
+        });
+        at = name_end + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+/// Extract a zip archive. Supports stored and deflated entries, Unix
+/// permissions and symlinks.
+fn extract_zip_archive(
+    archive_path: &Path,
+    dest_dir: &Path,
+    options: ExtractOptions,
+) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let io_error = |e: std::io::Error| zip_error(archive_path, e);
+
+    let mut file = File::open(archive_path).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to open tarball: {e}"),
+    })?;
+    let entries = read_zip_entries(&mut file, archive_path)?;
+
+    // Directory modes are applied last so read-only directories can be filled
+    let mut dir_modes = Vec::new();
+
+    for entry in entries {
+        let entry_path = PathBuf::from(&entry.name);
+        validate_path(&entry_path, dest_dir)?;
+        let Some(relative) = strip_components(&entry_path, options.strip_components) else {
+            continue;
+        };
+
+        if entry.flags & 1 != 0 {
+            return Err(zip_error(
+                archive_path,
+                format!("{} is encrypted", entry.name),
+            ));
+        }
+
+        let is_dir =
+            entry.name.ends_with('/') || entry.mode.is_some_and(|mode| mode & 0o170000 == 0o040000);
+        if is_dir {
+            let dir = prepare_target(dest_dir, &relative)?;
+            std::fs::create_dir_all(&dir).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to unpack entry {}: {e}", entry.name),
             })?;
+            if let Some(mode) = entry.mode {
+                dir_modes.push((dir, mode & 0o7777));
+            }
+            continue;
+        }
+
+        // Local headers repeat the name and may carry a different extra field
+        let mut header = [0u8; 30];
+        file.seek(SeekFrom::Start(entry.local_header_offset))
+            .map_err(io_error)?;
+        file.read_exact(&mut header).map_err(io_error)?;
+        if le_u32(&header, 0) != ZIP_LOCAL_HEADER {
+            return Err(zip_error(
+                archive_path,
+                format!("bad local header for {}", entry.name),
+            ));
+        }
+        let data_offset = entry.local_header_offset
+            + 30
+            + le_u16(&header, 26) as u64
+            + le_u16(&header, 28) as u64;
+        file.seek(SeekFrom::Start(data_offset)).map_err(io_error)?;
+
+        let compressed = (&mut file).take(entry.compressed_size);
+        let mut reader: Box<dyn Read + '_> = match entry.method {
+            0 => Box::new(compressed),
+            8 => Box::new(DeflateDecoder::new(compressed)),
+            method => {
+                return Err(zip_error(
+                    archive_path,
+                    format!(
+                        "{} uses unsupported compression method {method}",
+                        entry.name
+                    ),
+                ));
+            }
+        };
+
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents).map_err(io_error)?;
+        drop(reader);
+
+        let mut crc = flate2::Crc::new();
+        crc.update(&contents);
+        if crc.sum() != entry.crc32 {
+            return Err(zip_error(
+                archive_path,
+                format!("checksum mismatch for {}", entry.name),
+            ));
+        }
+
+        let target = prepare_target(dest_dir, &relative)?;
+        let unpack_error = |e: std::io::Error| Error::StoreCorruption {
+            message: format!("failed to unpack entry {}: {e}", entry.name),
+        };
+
+        if entry.mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+            let link_target = String::from_utf8_lossy(&contents).into_owned();
+            let _ = std::fs::remove_file(&target);
+            std::os::unix::fs::symlink(link_target, &target).map_err(unpack_error)?;
+            continue;
+        }
+
+        std::fs::write(&target, &contents).map_err(unpack_error)?;
+        if let Some(mode) = entry.mode {
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode & 0o7777))
+                .map_err(unpack_error)?;
+        }
+    }
+
+    for (dir, mode) in dir_modes.into_iter().rev() {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(mode)).map_err(|e| {
+            Error::StoreCorruption {
+                message: format!("failed to set permissions on {}: {e}", dir.display()),
+            }
+        })?;
     }
 
     Ok(())
 }
 
+/// Drop the first `count` normal components of an archive path, ignoring
+/// `.` components. Returns `None` if nothing is left.
+fn strip_components(path: &Path, count: usize) -> Option<PathBuf> {
+    let stripped: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .skip(count)
+        .collect();
+    (!stripped.as_os_str().is_empty()).then_some(stripped)
+}
+
+/// Resolve where an entry lands, creating its parent directories and making
+/// sure no symlink extracted earlier redirects it outside `dest_dir`.
+fn prepare_target(dest_dir: &Path, relative: &Path) -> Result<PathBuf, Error> {
+    let target = dest_dir.join(relative);
+    let Some(parent) = target.parent() else {
+        return Ok(target);
+    };
+
+    std::fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create {}: {e}", parent.display()),
+    })?;
+    let canonical_parent = parent.canonicalize().map_err(|e| Error::StoreCorruption {
+        message: format!("failed to resolve {}: {e}", parent.display()),
+    })?;
+    let canonical_dest = dest_dir
+        .canonicalize()
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to resolve {}: {e}", dest_dir.display()),
+        })?;
+    if !canonical_parent.starts_with(&canonical_dest) {
+        return Err(Error::StoreCorruption {
+            message: format!("path escapes destination directory: {}", relative.display()),
+        });
+    }
+
+    Ok(target)
+}
+
 /// Validate that a path from a tar entry is safe to extract.
 ///
 /// This function ensures:
@@ -176,8 +593,6 @@ fn validate_path(path: &Path, dest_dir: &Path) -> Result<(), Error> {
 /// For absolute paths, .. components cannot escape above the root.
 /// For relative paths, leading .. components are preserved.
 fn normalize_path(path: &Path) -> PathBuf {
-    use std::path::Component;
-
     let mut components = Vec::new();
     let mut is_absolute = false;
 
@@ -223,9 +638,17 @@ fn normalize_path(path: &Path) -> PathBuf {
 
 /// Extract a tarball from a reader (assumes gzip compression).
 /// For file-based extraction with auto-detection, use `extract_tarball` instead.
-pub fn extract_tarball_from_reader<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
-    let decoder = GzDecoder::new(reader);
-    extract_tar_archive(decoder, dest_dir)
+///
+/// The reader is read to its end, past the end of the archive, so a reader
+/// that checks its input when it runs out (see [`crate::pipe`]) gets to fail
+/// the extraction.
+pub fn extract_tarball_from_reader<R: Read>(mut reader: R, dest_dir: &Path) -> Result<(), Error> {
+    let decoder = GzDecoder::new(&mut reader);
+    extract_tar_archive(decoder, dest_dir, ExtractOptions::default())?;
+    std::io::copy(&mut reader, &mut std::io::sink()).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to read tarball: {e}"),
+    })?;
+    Ok(())
 }
 
 #[cfg(test)]
@@ -522,4 +945,417 @@ mod tests {
         let result = validate_path(&safe_path, &dest);
         assert!(result.is_ok());
     }
+
+    // ==================== Format Detection Tests ====================
+
+    fn create_plain_tar(entries: Vec<(&str, &[u8], Option<u32>)>) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for (path, content, mode) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(path).unwrap();
+            header.set_size(content.len() as u64);
+            header.set_mode(mode.unwrap_or(0o644));
+            header.set_cksum();
+            builder.append(&header, content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn write_file(dir: &Path, name: &str, bytes: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn detects_formats_from_magic_bytes() {
+        let tmp = TempDir::new().unwrap();
+        let tar = create_plain_tar(vec![("a.txt", b"a", None)]);
+
+        let cases: Vec<(&str, Vec<u8>, ArchiveFormat)> = vec![
+            (
+                "a",
+                create_test_tarball(vec![("a.txt", b"a", None)]),
+                ArchiveFormat::Gzip,
+            ),
+            (
+                "b",
+                vec![0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, 0],
+                ArchiveFormat::Xz,
+            ),
+            ("c", vec![0x28, 0xb5, 0x2f, 0xfd, 0], ArchiveFormat::Zstd),
+            ("d", b"BZh91AY&SY".to_vec(), ArchiveFormat::Bzip2),
+            ("e", b"PK\x03\x04rest".to_vec(), ArchiveFormat::Zip),
+            ("f", tar, ArchiveFormat::Tar),
+            ("g", b"garbage".to_vec(), ArchiveFormat::Unknown),
+        ];
+        for (name, bytes, expected) in cases {
+            let path = write_file(tmp.path(), name, &bytes);
+            assert_eq!(detect_archive_format(&path).unwrap(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn falls_back_to_extension() {
+        let tmp = TempDir::new().unwrap();
+        let tar = write_file(tmp.path(), "old.TAR", b"no magic here");
+        let zip = write_file(tmp.path(), "odd.zip", b"no magic here");
+        assert_eq!(detect_archive_format(&tar).unwrap(), ArchiveFormat::Tar);
+        assert_eq!(detect_archive_format(&zip).unwrap(), ArchiveFormat::Zip);
+    }
+
+    // ==================== Tar Format Tests ====================
+
+    #[test]
+    fn extracts_uncompressed_tar() {
+        let tmp = TempDir::new().unwrap();
+        let tar = create_plain_tar(vec![("dir/plain.txt", b"plain", None)]);
+        let path = write_file(tmp.path(), "src.tar", &tar);
+        let dest = tmp.path().join("out");
+
+        extract_tarball(&path, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("dir/plain.txt")).unwrap(),
+            "plain"
+        );
+    }
+
+    #[test]
+    fn extracts_xz_tar() {
+        let tmp = TempDir::new().unwrap();
+        let tar = create_plain_tar(vec![("xz.txt", b"xz content", None)]);
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&tar).unwrap();
+        let path = write_file(tmp.path(), "src.tar.xz", &encoder.finish().unwrap());
+        let dest = tmp.path().join("out");
+
+        extract_tarball(&path, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("xz.txt")).unwrap(),
+            "xz content"
+        );
+    }
+
+    #[test]
+    fn extracts_bzip2_tar() {
+        let tmp = TempDir::new().unwrap();
+        let tar = create_plain_tar(vec![("pkg-1.0/bz.txt", b"bz content", None)]);
+        let path = write_file(tmp.path(), "src.tar", &tar);
+        let Ok(status) = std::process::Command::new("bzip2").arg(&path).status() else {
+            return; // bzip2 not available
+        };
+        assert!(status.success());
+        let path = tmp.path().join("src.tar.bz2");
+        assert_eq!(detect_archive_format(&path).unwrap(), ArchiveFormat::Bzip2);
+
+        let dest = tmp.path().join("out");
+        extract_archive(
+            &path,
+            &dest,
+            ExtractOptions {
+                strip_components: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("bz.txt")).unwrap(),
+            "bz content"
+        );
+    }
+
+    #[test]
+    fn reports_corrupt_bzip2() {
+        let tmp = TempDir::new().unwrap();
+        let path = write_file(tmp.path(), "bad.tar.bz2", b"BZh9 definitely not bzip2");
+        if std::process::Command::new("bzip2")
+            .arg("--help")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+
+        let err =
+            extract_archive(&path, &tmp.path().join("out"), ExtractOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("bad.tar.bz2"), "{err}");
+    }
+
+    // ==================== Strip Components Tests ====================
+
+    #[test]
+    fn strip_components_drops_leading_dirs() {
+        assert_eq!(
+            strip_components(Path::new("./pkg-1.0/src/main.c"), 1),
+            Some(PathBuf::from("src/main.c"))
+        );
+        assert_eq!(strip_components(Path::new("pkg-1.0/"), 1), None);
+        assert_eq!(
+            strip_components(Path::new("a/b/c"), 0),
+            Some(PathBuf::from("a/b/c"))
+        );
+        assert_eq!(strip_components(Path::new("a/b"), 5), None);
+    }
+
+    #[test]
+    fn extracts_tar_with_strip_components() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_test_tarball(vec![
+            ("./pkg-1.0/README", b"readme", None),
+            ("./pkg-1.0/bin/tool", b"#!/bin/sh", Some(0o755)),
+        ]);
+        let path = write_file(tmp.path(), "src.tar.gz", &tarball);
+        let dest = tmp.path().join("out");
+
+        extract_archive(
+            &path,
+            &dest,
+            ExtractOptions {
+                strip_components: 1,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(fs::read_to_string(dest.join("README")).unwrap(), "readme");
+        let mode = fs::metadata(dest.join("bin/tool"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert!(mode & 0o111 != 0, "executable bit not preserved: {mode:o}");
+        assert!(!dest.join("pkg-1.0").exists());
+    }
+
+    #[test]
+    fn strip_components_rewrites_hard_links() {
+        let tmp = TempDir::new().unwrap();
+        let mut builder = Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_path("pkg/original").unwrap();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"data"[..]).unwrap();
+
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Link);
+        link.set_size(0);
+        link.set_mode(0o644);
+        builder
+            .append_link(&mut link, "pkg/copy", "pkg/original")
+            .unwrap();
+        let path = write_file(tmp.path(), "links.tar", &builder.into_inner().unwrap());
+        let dest = tmp.path().join("out");
+
+        extract_archive(
+            &path,
+            &dest,
+            ExtractOptions {
+                strip_components: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(fs::read_to_string(dest.join("copy")).unwrap(), "data");
+    }
+
+    #[test]
+    fn strip_components_refuses_symlinked_parent() {
+        let tmp = TempDir::new().unwrap();
+        let outside = tmp.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+
+        let mut builder = Builder::new(Vec::new());
+        let mut link = tar::Header::new_gnu();
+        link.set_entry_type(tar::EntryType::Symlink);
+        link.set_size(0);
+        link.set_mode(0o777);
+        builder
+            .append_link(&mut link, "pkg/escape", &outside)
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_path("pkg/escape/evil.txt").unwrap();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"evil"[..]).unwrap();
+        let path = write_file(tmp.path(), "escape.tar", &builder.into_inner().unwrap());
+
+        let result = extract_archive(
+            &path,
+            &tmp.path().join("out"),
+            ExtractOptions {
+                strip_components: 1,
+            },
+        );
+        assert!(result.is_err());
+        assert!(!outside.join("evil.txt").exists());
+    }
+
+    // ==================== Zip Tests ====================
+
+    /// (name, contents, unix mode, deflate)
+    type ZipSpec<'a> = (&'a str, &'a [u8], Option<u32>, bool);
+
+    /// Build a zip archive in memory.
+    fn create_zip(entries: &[ZipSpec]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+
+        for &(name, contents, mode, deflate) in entries {
+            let mut crc = flate2::Crc::new();
+            crc.update(contents);
+            let data = if deflate {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(contents).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                contents.to_vec()
+            };
+            let method: u16 = if deflate { 8 } else { 0 };
+            let offset = out.len() as u32;
+
+            out.extend_from_slice(&ZIP_LOCAL_HEADER.to_le_bytes());
+            out.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            out.extend_from_slice(&0u16.to_le_bytes()); // flags
+            out.extend_from_slice(&method.to_le_bytes());
+            out.extend_from_slice(&[0; 4]); // mod time + date
+            out.extend_from_slice(&crc.sum().to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes()); // extra length
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(&data);
+
+            let made_by: u16 = if mode.is_some() { 3 << 8 | 20 } else { 20 };
+            central.extend_from_slice(&ZIP_CENTRAL_HEADER.to_le_bytes());
+            central.extend_from_slice(&made_by.to_le_bytes());
+            central.extend_from_slice(&20u16.to_le_bytes());
+            central.extend_from_slice(&0u16.to_le_bytes());
+            central.extend_from_slice(&method.to_le_bytes());
+            central.extend_from_slice(&[0; 4]);
+            central.extend_from_slice(&crc.sum().to_le_bytes());
+            central.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+            central.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            central.extend_from_slice(&[0; 8]); // extra, comment, disk, internal attrs
+            central.extend_from_slice(&(mode.unwrap_or(0) << 16).to_le_bytes());
+            central.extend_from_slice(&offset.to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let cd_offset = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(&ZIP_END_OF_CENTRAL_DIR.to_le_bytes());
+        out.extend_from_slice(&[0; 4]); // disk numbers
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&cd_offset.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        out
+    }
+
+    #[test]
+    fn extracts_zip_stored_and_deflated() {
+        let tmp = TempDir::new().unwrap();
+        let zip = create_zip(&[
+            ("pkg-1.0/", b"", Some(0o040755), false),
+            ("pkg-1.0/stored.txt", b"stored", None, false),
+            ("pkg-1.0/deflated.txt", &[b'z'; 4096], Some(0o100644), true),
+            ("pkg-1.0/configure", b"#!/bin/sh", Some(0o100755), true),
+            ("pkg-1.0/link", b"stored.txt", Some(0o120777), false),
+        ]);
+        let path = write_file(tmp.path(), "src.zip", &zip);
+        let dest = tmp.path().join("out");
+
+        extract_archive(&path, &dest, ExtractOptions::default()).unwrap();
+
+        let root = dest.join("pkg-1.0");
+        assert_eq!(
+            fs::read_to_string(root.join("stored.txt")).unwrap(),
+            "stored"
+        );
+        assert_eq!(
+            fs::read(root.join("deflated.txt")).unwrap(),
+            vec![b'z'; 4096]
+        );
+        let mode = fs::metadata(root.join("configure"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(
+            fs::read_link(root.join("link")).unwrap(),
+            PathBuf::from("stored.txt")
+        );
+    }
+
+    #[test]
+    fn extracts_zip_with_strip_components() {
+        let tmp = TempDir::new().unwrap();
+        let zip = create_zip(&[
+            ("pkg-1.0/", b"", None, false),
+            ("pkg-1.0/src/lib.c", b"int x;", None, true),
+        ]);
+        let path = write_file(tmp.path(), "src.zip", &zip);
+        let dest = tmp.path().join("out");
+
+        extract_archive(
+            &path,
+            &dest,
+            ExtractOptions {
+                strip_components: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("src/lib.c")).unwrap(),
+            "int x;"
+        );
+        assert!(!dest.join("pkg-1.0").exists());
+    }
+
+    #[test]
+    fn zip_rejects_path_traversal() {
+        let tmp = TempDir::new().unwrap();
+        let zip = create_zip(&[("../evil.txt", b"evil", None, false)]);
+        let path = write_file(tmp.path(), "evil.zip", &zip);
+
+        let err = extract_tarball(&path, &tmp.path().join("out")).unwrap_err();
+        assert!(err.to_string().contains("path traversal"));
+        assert!(!tmp.path().join("evil.txt").exists());
+    }
+
+    #[test]
+    fn zip_detects_corrupt_data() {
+        let tmp = TempDir::new().unwrap();
+        let mut zip = create_zip(&[("file.txt", b"hello", None, false)]);
+        // Flip a byte of the stored contents, right after the 30-byte header and name
+        zip[30 + "file.txt".len()] ^= 0xff;
+        let path = write_file(tmp.path(), "corrupt.zip", &zip);
+
+        let err = extract_tarball(&path, &tmp.path().join("out")).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
+    }
+
+    #[test]
+    fn zip_rejects_truncated_archive() {
+        let tmp = TempDir::new().unwrap();
+        let zip = create_zip(&[("file.txt", b"hello", None, false)]);
+        let path = write_file(tmp.path(), "short.zip", &zip[..20]);
+
+        let err = extract_tarball(&path, &tmp.path().join("out")).unwrap_err();
+        assert!(err.to_string().contains("invalid zip archive"), "{err}");
+    }
+
+    #[test]
+    fn extracts_empty_zip() {
+        let tmp = TempDir::new().unwrap();
+        let path = write_file(tmp.path(), "empty.zip", &create_zip(&[]));
+        let dest = tmp.path().join("out");
+
+        assert_eq!(detect_archive_format(&path).unwrap(), ArchiveFormat::Zip);
+        extract_tarball(&path, &dest).unwrap();
+        assert_eq!(fs::read_dir(&dest).unwrap().count(), 0);
+    }
 }
@@ -0,0 +1,171 @@
+//! Open file limits for parallel installs.
+//!
+//! Every download in flight holds a socket and the blob it is writing, and
+//! every extraction the archive it reads and the file it is unpacking. At the
+//! default concurrency that outruns a soft `ulimit -n` of 256, macOS's
+//! default, and whichever open happens to hit the limit fails with "Too many
+//! open files" halfway through an install. So the soft limit is raised
+//! towards the hard limit before installing, and where even that isn't
+//! enough, fewer downloads and extractions run at once.
+
+use std::io;
+
+use zb_core::ConcurrencyLimits;
+
+/// Descriptors a download holds: its connection and the blob being written
+pub const FDS_PER_DOWNLOAD: u64 = 2;
+
+/// Descriptors an extraction holds: the archive and the file being unpacked
+pub const FDS_PER_EXTRACTION: u64 = 2;
+
+/// Descriptors kept for everything else: stdio, the database, locks, the
+/// files linking and relocation open
+pub const RESERVED_FDS: u64 = 64;
+
+/// The process's open file limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FdLimit {
+    /// Limit in force, which the process may raise up to `hard`
+    pub soft: u64,
+    /// Ceiling for the soft limit, `RLIM_INFINITY` when unlimited
+    pub hard: u64,
+}
+
+/// How the open file limit was fitted to the concurrency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FdLimitAdjustment {
+    /// The limit already allowed the concurrency
+    Sufficient,
+    /// The soft limit was raised from `from` to `to`
+    Raised { from: u64, to: u64 },
+    /// The limit couldn't be raised past `limit`, so `limits` run instead
+    Clamped {
+        limit: u64,
+        limits: ConcurrencyLimits,
+    },
+}
+
+/// The open file limits of this process
+pub fn fd_limit() -> io::Result<FdLimit> {
+    let mut rlimit: libc::rlimit = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(FdLimit {
+        soft: rlimit.rlim_cur as u64,
+        hard: rlimit.rlim_max as u64,
+    })
+}
+
+/// Raise the soft limit to `wanted`, or as far towards it as the hard limit
+/// allows, returning the new soft limit. Never lowers it.
+pub fn raise_fd_limit(wanted: u64) -> io::Result<u64> {
+    let current = fd_limit()?;
+    let target = wanted.min(current.hard);
+    if target <= current.soft {
+        return Ok(current.soft);
+    }
+
+    let rlimit = libc::rlimit {
+        rlim_cur: target as libc::rlim_t,
+        rlim_max: current.hard as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &rlimit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(target)
+}
+
+/// Open files an install with `limits` may need at once
+pub fn fds_needed(limits: ConcurrencyLimits) -> u64 {
+    limits.downloads as u64 * FDS_PER_DOWNLOAD
+        + limits.extractions as u64 * FDS_PER_EXTRACTION
+        + RESERVED_FDS
+}
+
+/// Scale `limits` down to fit within `available` open files, keeping at
+/// least one download and one extraction
+pub fn fit_concurrency(limits: ConcurrencyLimits, available: u64) -> ConcurrencyLimits {
+    let wanted = fds_needed(limits) - RESERVED_FDS;
+    let budget = available.saturating_sub(RESERVED_FDS);
+    if wanted <= budget {
+        return limits;
+    }
+
+    let scale = |n: usize| ((n as u64 * budget / wanted) as usize).max(1);
+    ConcurrencyLimits {
+        downloads: scale(limits.downloads),
+        extractions: scale(limits.extractions),
+    }
+}
+
+/// Raise the open file limit far enough for `limits`, or work out the
+/// concurrency the limit allows when it can't be raised that far
+pub fn fit_fd_limit(limits: ConcurrencyLimits) -> FdLimitAdjustment {
+    let Ok(current) = fd_limit() else {
+        return FdLimitAdjustment::Sufficient;
+    };
+    let needed = fds_needed(limits);
+    if current.soft >= needed {
+        return FdLimitAdjustment::Sufficient;
+    }
+
+    let raised = raise_fd_limit(needed).unwrap_or(current.soft);
+    if raised >= needed {
+        return FdLimitAdjustment::Raised {
+            from: current.soft,
+            to: raised,
+        };
+    }
+    FdLimitAdjustment::Clamped {
+        limit: raised,
+        limits: fit_concurrency(limits, raised),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(downloads: usize, extractions: usize) -> ConcurrencyLimits {
+        ConcurrencyLimits {
+            downloads,
+            extractions,
+        }
+    }
+
+    #[test]
+    fn fds_needed_counts_every_task() {
+        assert_eq!(fds_needed(limits(48, 8)), 48 * 2 + 8 * 2 + RESERVED_FDS);
+    }
+
+    #[test]
+    fn fit_concurrency_keeps_limits_that_fit() {
+        assert_eq!(fit_concurrency(limits(48, 8), 1024), limits(48, 8));
+    }
+
+    #[test]
+    fn fit_concurrency_scales_limits_down() {
+        // 256 - 64 reserved leaves 192 of the 224 wanted
+        assert_eq!(fit_concurrency(limits(48, 64), 256), limits(41, 54));
+        assert!(fds_needed(fit_concurrency(limits(48, 64), 256)) <= 256);
+    }
+
+    #[test]
+    fn fit_concurrency_keeps_one_of_each() {
+        assert_eq!(fit_concurrency(limits(48, 8), 32), limits(1, 1));
+    }
+
+    #[test]
+    fn raising_the_limit_never_lowers_it() {
+        let current = fd_limit().unwrap();
+        assert_eq!(raise_fd_limit(1).unwrap(), current.soft);
+        assert!(fd_limit().unwrap().soft >= current.soft);
+    }
+
+    #[test]
+    fn small_concurrency_fits_the_current_limit() {
+        // Fewer open files than any soft limit in practice
+        assert_eq!(fit_fd_limit(limits(1, 1)), FdLimitAdjustment::Sufficient);
+    }
+}
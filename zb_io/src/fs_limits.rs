@@ -0,0 +1,167 @@
+//! Filesystem limits checked before unpacking or materializing.
+//!
+//! Running out of disk space isn't the only way an install can fail halfway:
+//! a filesystem can run out of inodes while it still has free bytes, and a
+//! deep prefix can push paths inside a keg past what the OS accepts. Both
+//! surface as confusing errors ("No space left on device", "File name too
+//! long") from whichever file happened to hit the limit, so they are checked
+//! up front and reported with the filesystem or path at fault.
+
+use std::ffi::CString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+/// Longest path, in bytes, the OS accepts in a file system call
+const PATH_MAX: usize = libc::PATH_MAX as usize;
+
+/// Longest file name, in bytes, common filesystems accept
+const NAME_MAX: usize = 255;
+
+/// How many files and directories a tree holds, and its longest path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeStats {
+    /// Files, directories and symlinks below the root
+    pub entries: u64,
+    /// Longest path relative to the root, in bytes
+    pub longest: PathBuf,
+}
+
+/// Count the entries below `root` and find its longest relative path
+pub fn tree_stats(root: &Path) -> Result<TreeStats, Error> {
+    let mut stats = TreeStats::default();
+    for entry in walkdir::WalkDir::new(root).min_depth(1).follow_links(false) {
+        let entry = entry.map_err(|e| Error::StoreCorruption {
+            message: format!("failed to walk {}: {e}", root.display()),
+        })?;
+        stats.entries += 1;
+        if let Ok(relative) = entry.path().strip_prefix(root)
+            && relative.as_os_str().len() > stats.longest.as_os_str().len()
+        {
+            stats.longest = relative.to_path_buf();
+        }
+    }
+    Ok(stats)
+}
+
+/// Inodes that can still be created on the filesystem holding `path`, or
+/// `None` if the filesystem has no fixed inode count (e.g. btrfs)
+pub fn free_inodes(path: &Path) -> io::Result<Option<u64>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if stat.f_files == 0 {
+        return Ok(None);
+    }
+    // `fsfilcnt_t` is 32 bits on macOS
+    #[allow(clippy::useless_conversion)]
+    Ok(Some(u64::from(stat.f_favail)))
+}
+
+/// Fail unless the filesystem holding `dir` can create `needed` more inodes.
+/// Filesystems that don't report inode counts pass.
+pub fn check_free_inodes(dir: &Path, needed: u64) -> Result<(), Error> {
+    match free_inodes(dir) {
+        Ok(Some(free)) if free < needed => Err(Error::StoreCorruption {
+            message: format!(
+                "the filesystem holding {} is out of inodes: {needed} files needed, {free} available \
+                 (remove files there to free inodes, even though it may have free space)",
+                dir.display()
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Fail if `path` is longer than the OS accepts, or has a component longer
+/// than a file name may be
+pub fn check_path_length(path: &Path) -> Result<(), Error> {
+    let too_long = |limit: usize, what: &str| Error::StoreCorruption {
+        message: format!(
+            "path is too long ({} bytes, the {what} limit is {limit}): {}; \
+             use a shorter prefix or root",
+            path.as_os_str().len(),
+            path.display()
+        ),
+    };
+
+    if path.as_os_str().len() >= PATH_MAX {
+        return Err(too_long(PATH_MAX, "path"));
+    }
+    if path
+        .components()
+        .any(|component| component.as_os_str().len() > NAME_MAX)
+    {
+        return Err(too_long(NAME_MAX, "file name"));
+    }
+    Ok(())
+}
+
+/// Fail unless a copy of the tree described by `stats` fits at each of
+/// `destinations`: its deepest path must not be too long there, and the
+/// filesystem must have an inode for every entry
+pub fn check_tree_fits(stats: &TreeStats, destinations: &[&Path]) -> Result<(), Error> {
+    for dest in destinations {
+        check_path_length(&dest.join(&stats.longest))?;
+    }
+    if let Some(dest) = destinations.first() {
+        let existing = dest.ancestors().find(|dir| dir.exists()).unwrap_or(dest);
+        check_free_inodes(existing, stats.entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn tree_stats_count_entries_and_find_the_longest_path() {
+        let tmp = TempDir::new().unwrap();
+        fs::create_dir_all(tmp.path().join("bin")).unwrap();
+        fs::create_dir_all(tmp.path().join("share/doc/pkg")).unwrap();
+        fs::write(tmp.path().join("bin/pkg"), "").unwrap();
+        fs::write(tmp.path().join("share/doc/pkg/README"), "").unwrap();
+
+        let stats = tree_stats(tmp.path()).unwrap();
+        assert_eq!(stats.entries, 6);
+        assert_eq!(stats.longest, PathBuf::from("share/doc/pkg/README"));
+    }
+
+    #[test]
+    fn overly_long_paths_are_named() {
+        assert!(check_path_length(Path::new("/opt/zerobrew/prefix/bin/jq")).is_ok());
+
+        let deep = Path::new("/deep").join(vec!["x".repeat(99); PATH_MAX / 100 + 1].join("/"));
+        let err = check_path_length(&deep).unwrap_err().to_string();
+        assert!(err.contains("the path limit"), "{err}");
+        assert!(err.contains("/deep/xxx"), "{err}");
+
+        let long_name = Path::new("/opt").join("n".repeat(NAME_MAX + 1));
+        let err = check_path_length(&long_name).unwrap_err().to_string();
+        assert!(err.contains("the file name limit is 255"), "{err}");
+    }
+
+    #[test]
+    fn inode_checks_compare_against_the_filesystem() {
+        let tmp = TempDir::new().unwrap();
+        check_free_inodes(tmp.path(), 1).unwrap();
+
+        if let Some(free) = free_inodes(tmp.path()).unwrap() {
+            let err = check_free_inodes(tmp.path(), free + 1)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("out of inodes"), "{err}");
+            assert!(err.contains(&tmp.path().display().to_string()), "{err}");
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! HTTP record/replay fixtures.
+//!
+//! In record mode every successful API response and bottle download is written to a
+//! fixture directory, keyed by URL. In replay mode those fixtures are served instead of
+//! touching the network, so integration tests can run hermetically.
+//!
+//! Modes are selected with `ZB_HTTP_RECORD=<dir>` or `ZB_HTTP_REPLAY=<dir>`
+//! (replay wins if both are set), or with `zb --record-http <dir>`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use zb_core::Error;
+
+use crate::traits::HttpClient;
+
+/// Environment variable that enables record mode
+pub const RECORD_ENV: &str = "ZB_HTTP_RECORD";
+
+/// Environment variable that enables replay mode
+pub const REPLAY_ENV: &str = "ZB_HTTP_REPLAY";
+
+/// A directory of recorded HTTP response bodies
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpFixtures {
+    dir: PathBuf,
+}
+
+impl HttpFixtures {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the recorded body for a URL
+    pub fn body_path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{}.body", fixture_key(url)))
+    }
+
+    /// Record a response body for a URL
+    pub fn save(&self, url: &str, body: &[u8]) -> Result<(), Error> {
+        self.prepare(url)?;
+        fs::write(self.body_path(url), body).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write HTTP fixture for {url}: {e}"),
+        })
+    }
+
+    /// Record a response body that is already on disk
+    pub fn save_file(&self, url: &str, path: &Path) -> Result<(), Error> {
+        self.prepare(url)?;
+        fs::copy(path, self.body_path(url)).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write HTTP fixture for {url}: {e}"),
+        })?;
+        Ok(())
+    }
+
+    /// Load the recorded body for a URL, if there is one
+    pub fn load(&self, url: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.body_path(url)) {
+            Ok(body) => Ok(Some(body)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::StoreCorruption {
+                message: format!("failed to read HTTP fixture for {url}: {e}"),
+            }),
+        }
+    }
+
+    /// Error returned when replaying a URL that was never recorded
+    pub fn missing(&self, url: &str) -> Error {
+        Error::NetworkFailure {
+            message: format!("no recorded response for {url} in {}", self.dir.display()),
+        }
+    }
+
+    /// Create the fixture directory and write the URL next to the body so
+    /// recordings stay readable.
+    fn prepare(&self, url: &str) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir).map_err(|e| Error::StoreCorruption {
+            message: format!(
+                "failed to create fixture directory {}: {e}",
+                self.dir.display()
+            ),
+        })?;
+        fs::write(self.dir.join(format!("{}.url", fixture_key(url))), url).map_err(|e| {
+            Error::StoreCorruption {
+                message: format!("failed to write HTTP fixture for {url}: {e}"),
+            }
+        })
+    }
+}
+
+/// File name stem for a URL's fixture
+fn fixture_key(url: &str) -> String {
+    format!("{:x}", Sha256::digest(url.as_bytes()))
+}
+
+/// How HTTP traffic is handled
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HttpMode {
+    /// Talk to the network
+    #[default]
+    Live,
+    /// Talk to the network and record every successful response
+    Record(HttpFixtures),
+    /// Serve recorded responses only
+    Replay(HttpFixtures),
+}
+
+impl HttpMode {
+    /// Select the mode from `ZB_HTTP_RECORD` / `ZB_HTTP_REPLAY`
+    pub fn from_env() -> Self {
+        Self::from_vars(
+            std::env::var_os(RECORD_ENV).map(PathBuf::from),
+            std::env::var_os(REPLAY_ENV).map(PathBuf::from),
+        )
+    }
+
+    fn from_vars(record: Option<PathBuf>, replay: Option<PathBuf>) -> Self {
+        let non_empty = |p: Option<PathBuf>| p.filter(|p| !p.as_os_str().is_empty());
+        if let Some(dir) = non_empty(replay) {
+            HttpMode::Replay(HttpFixtures::new(dir))
+        } else if let Some(dir) = non_empty(record) {
+            HttpMode::Record(HttpFixtures::new(dir))
+        } else {
+            HttpMode::Live
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        matches!(self, HttpMode::Live)
+    }
+}
+
+/// [`HttpClient`] wrapper that records every successful response of the inner client
+pub struct RecordingHttpClient<C> {
+    inner: C,
+    fixtures: HttpFixtures,
+}
+
+impl<C: HttpClient> RecordingHttpClient<C> {
+    pub fn new(inner: C, fixtures: HttpFixtures) -> Self {
+        Self { inner, fixtures }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient> HttpClient for RecordingHttpClient<C> {
+    async fn get(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let body = self.inner.get(url).await?;
+        self.fixtures.save(url, &body)?;
+        Ok(body)
+    }
+
+    async fn get_with_timeout(&self, url: &str, timeout: Duration) -> Result<Vec<u8>, Error> {
+        let body = self.inner.get_with_timeout(url, timeout).await?;
+        self.fixtures.save(url, &body)?;
+        Ok(body)
+    }
+}
+
+/// [`HttpClient`] that serves recorded responses and never touches the network
+pub struct ReplayHttpClient {
+    fixtures: HttpFixtures,
+}
+
+impl ReplayHttpClient {
+    pub fn new(fixtures: HttpFixtures) -> Self {
+        Self { fixtures }
+    }
+}
+
+#[async_trait]
+impl HttpClient for ReplayHttpClient {
+    async fn get(&self, url: &str) -> Result<Vec<u8>, Error> {
+        self.fixtures
+            .load(url)?
+            .ok_or_else(|| self.fixtures.missing(url))
+    }
+
+    async fn get_with_timeout(&self, url: &str, _timeout: Duration) -> Result<Vec<u8>, Error> {
+        self.get(url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::traits::MockHttpClient;
+    use tempfile::TempDir;
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path().join("fixtures"));
+
+        fixtures.save("https://example.com/a.json", b"{}").unwrap();
+
+        assert_eq!(
+            fixtures.load("https://example.com/a.json").unwrap(),
+            Some(b"{}".to_vec())
+        );
+        assert_eq!(fixtures.load("https://example.com/b.json").unwrap(), None);
+    }
+
+    #[test]
+    fn save_writes_url_alongside_body() {
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path());
+        let url = "https://example.com/a.json";
+
+        fixtures.save(url, b"{}").unwrap();
+
+        let url_path = fixtures.body_path(url).with_extension("url");
+        assert_eq!(fs::read_to_string(url_path).unwrap(), url);
+    }
+
+    #[test]
+    fn mode_prefers_replay_over_record() {
+        let mode = HttpMode::from_vars(Some("/rec".into()), Some("/rep".into()));
+        assert_eq!(mode, HttpMode::Replay(HttpFixtures::new("/rep")));
+    }
+
+    #[test]
+    fn mode_ignores_empty_values() {
+        assert!(HttpMode::from_vars(Some("".into()), Some("".into())).is_live());
+        assert_eq!(
+            HttpMode::from_vars(Some("/rec".into()), None),
+            HttpMode::Record(HttpFixtures::new("/rec"))
+        );
+    }
+
+    #[tokio::test]
+    async fn recording_client_saves_responses() {
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path());
+
+        let mut inner = MockHttpClient::new();
+        inner.expect_get().returning(|_| Ok(b"body".to_vec()));
+
+        let client = RecordingHttpClient::new(inner, fixtures.clone());
+        assert_eq!(client.get("https://example.com/x").await.unwrap(), b"body");
+        assert_eq!(
+            fixtures.load("https://example.com/x").unwrap(),
+            Some(b"body".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn recording_client_skips_failures() {
+        let tmp = TempDir::new().unwrap();
+        let fixtures = HttpFixtures::new(tmp.path());
+
+        let mut inner = MockHttpClient::new();
+        inner.expect_get().returning(|_| {
+            Err(Error::NetworkFailure {
+                message: "HTTP 500".to_string(),
+            })
+        });
+
+        let client = RecordingHttpClient::new(inner, fixtures.clone());
+        assert!(client.get("https://example.com/x").await.is_err());
+        assert_eq!(fixtures.load("https://example.com/x").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn replay_client_errors_on_unrecorded_url() {
+        let tmp = TempDir::new().unwrap();
+        let client = ReplayHttpClient::new(HttpFixtures::new(tmp.path()));
+
+        let err = client.get("https://example.com/x").await.unwrap_err();
+        assert!(
+            matches!(err, Error::NetworkFailure { message } if message.contains("no recorded response"))
+        );
+    }
+}
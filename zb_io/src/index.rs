@@ -0,0 +1,344 @@
+//! Formula index providers.
+//!
+//! Formulas come from the formulae.brew.sh API by default. Setting
+//! `ZB_FORMULA_INDEX` points zerobrew at a curated catalog instead:
+//!
+//! - an `http(s)://` base URL laid out like the Homebrew API
+//!   (`<base>.json` and `<base>/<name>.json`), e.g. an S3 or Artifactory mirror
+//! - an `http(s)://` URL of a single `.json` index file
+//! - a `file://` URL or absolute path of a local index file
+//! - a `file://` URL or absolute path of a directory written by
+//!   `zb mirror create` (see `crate::mirror`)
+//!
+//! A single-file index is a JSON array of formula objects, the same shape as
+//! Homebrew's `formula.json`. It is validated as a whole when first loaded.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use zb_core::{Error, Formula};
+
+use crate::api::FormulaInfo;
+
+/// Environment variable that selects the formula index
+pub const INDEX_ENV: &str = "ZB_FORMULA_INDEX";
+
+/// Base URL of the public Homebrew formula API
+pub const DEFAULT_API_URL: &str = "https://formulae.brew.sh/api/formula";
+
+/// Where formula metadata is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexProvider {
+    /// A Homebrew-compatible API base URL
+    Api(String),
+    /// A single-file index fetched over HTTP(S)
+    Remote(String),
+    /// A single-file index on the local filesystem
+    File(PathBuf),
+    /// A mirror directory on the local filesystem (see `crate::mirror`)
+    Mirror(PathBuf),
+}
+
+impl Default for IndexProvider {
+    fn default() -> Self {
+        IndexProvider::Api(DEFAULT_API_URL.to_string())
+    }
+}
+
+impl fmt::Display for IndexProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndexProvider::Api(url) | IndexProvider::Remote(url) => write!(f, "{url}"),
+            IndexProvider::File(path) | IndexProvider::Mirror(path) => {
+                write!(f, "file://{}", path.display())
+            }
+        }
+    }
+}
+
+impl IndexProvider {
+    /// Parse an index location as accepted by `ZB_FORMULA_INDEX`
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let spec = spec.trim();
+
+        if let Some(path) = spec.strip_prefix("file://") {
+            return Self::file(path, spec);
+        }
+
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let url = spec.trim_end_matches('/');
+            return Ok(if url.ends_with(".json") {
+                IndexProvider::Remote(url.to_string())
+            } else {
+                IndexProvider::Api(url.to_string())
+            });
+        }
+
+        if spec.starts_with('/') {
+            return Self::file(spec, spec);
+        }
+
+        Err(invalid_index(
+            spec,
+            "expected an http(s):// URL, a file:// URL or an absolute path",
+        ))
+    }
+
+    /// Read the provider from `ZB_FORMULA_INDEX`, defaulting to the Homebrew API
+    pub fn from_env() -> Result<Self, Error> {
+        match std::env::var(INDEX_ENV) {
+            Ok(spec) if !spec.trim().is_empty() => Self::parse(&spec),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    fn file(path: &str, spec: &str) -> Result<Self, Error> {
+        if !path.starts_with('/') {
+            return Err(invalid_index(spec, "file index paths must be absolute"));
+        }
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            return Ok(IndexProvider::Mirror(path));
+        }
+        Ok(IndexProvider::File(path))
+    }
+}
+
+/// A validated single-file formula index
+#[derive(Debug, Clone)]
+pub struct FormulaIndex {
+    entries: Vec<Value>,
+    /// Lookup by name, full name and alias
+    lookup: HashMap<String, usize>,
+}
+
+impl FormulaIndex {
+    /// Parse and validate an index body. `source` is used in error messages.
+    ///
+    /// Every entry must be an object with a unique, non-empty `name` and a
+    /// `versions` object; `full_name` defaults to `name` when absent.
+    pub fn parse(body: &[u8], source: &str) -> Result<Self, Error> {
+        let root: Value = serde_json::from_slice(body)
+            .map_err(|e| invalid_index(source, &format!("not valid JSON: {e}")))?;
+        let Value::Array(mut entries) = root else {
+            return Err(invalid_index(source, "expected a JSON array of formulas"));
+        };
+
+        let mut names = HashMap::new();
+        let mut others = HashMap::new();
+
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let Value::Object(obj) = entry else {
+                return Err(invalid_index(
+                    source,
+                    &format!("entry {i} is not an object"),
+                ));
+            };
+
+            let name = match obj.get("name") {
+                Some(Value::String(name)) if !name.is_empty() => name.clone(),
+                _ => {
+                    return Err(invalid_index(source, &format!("entry {i} has no \"name\"")));
+                }
+            };
+            if !obj.get("versions").is_some_and(Value::is_object) {
+                return Err(invalid_index(
+                    source,
+                    &format!("formula '{name}' has no \"versions\" object"),
+                ));
+            }
+            obj.entry("full_name")
+                .or_insert_with(|| Value::String(name.clone()));
+
+            let info: FormulaInfo = serde_json::from_value(Value::Object(obj.clone()))
+                .map_err(|e| invalid_index(source, &format!("formula '{name}': {e}")))?;
+
+            if names.insert(info.name.clone(), i).is_some() {
+                return Err(invalid_index(
+                    source,
+                    &format!("formula '{name}' appears more than once"),
+                ));
+            }
+            others.entry(info.full_name).or_insert(i);
+            for alias in info.aliases {
+                others.entry(alias).or_insert(i);
+            }
+        }
+
+        // Real names take precedence over full names and aliases
+        for (other, i) in others {
+            names.entry(other).or_insert(i);
+        }
+
+        Ok(Self {
+            entries,
+            lookup: names,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Search metadata for every formula in the index
+    pub fn infos(&self) -> Vec<FormulaInfo> {
+        self.entries
+            .iter()
+            .filter_map(|e| serde_json::from_value(e.clone()).ok())
+            .collect()
+    }
+
+    /// Look up a formula by name, full name or alias
+    pub fn formula(&self, name: &str) -> Result<Formula, Error> {
+        let entry = self
+            .lookup
+            .get(name)
+            .map(|&i| &self.entries[i])
+            .ok_or_else(|| Error::MissingFormula {
+                name: name.to_string(),
+            })?;
+
+        serde_json::from_value(entry.clone()).map_err(|e| Error::NetworkFailure {
+            message: format!("failed to parse formula '{name}' from index: {e}"),
+        })
+    }
+}
+
+fn invalid_index(source: &str, reason: &str) -> Error {
+    Error::NetworkFailure {
+        message: format!("invalid formula index {source}: {reason}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"[
+        {
+            "name": "jq",
+            "full_name": "jq",
+            "desc": "JSON processor",
+            "versions": { "stable": "1.7.1" },
+            "aliases": ["jq-cli"]
+        },
+        {
+            "name": "tool",
+            "full_name": "acme/internal/tool",
+            "versions": { "stable": "2.0" },
+            "dependencies": ["jq"]
+        }
+    ]"#;
+
+    #[test]
+    fn parse_selects_provider_by_scheme() {
+        assert_eq!(
+            IndexProvider::parse("https://mirror.example.com/api/formula/").unwrap(),
+            IndexProvider::Api("https://mirror.example.com/api/formula".to_string())
+        );
+        assert_eq!(
+            IndexProvider::parse("https://s3.example.com/catalog/formula.json").unwrap(),
+            IndexProvider::Remote("https://s3.example.com/catalog/formula.json".to_string())
+        );
+        assert_eq!(
+            IndexProvider::parse("file:///srv/index.json").unwrap(),
+            IndexProvider::File(PathBuf::from("/srv/index.json"))
+        );
+        assert_eq!(
+            IndexProvider::parse("/srv/index.json").unwrap(),
+            IndexProvider::File(PathBuf::from("/srv/index.json"))
+        );
+    }
+
+    #[test]
+    fn parse_selects_mirror_for_directories() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let dir = tmp.path().to_str().unwrap();
+
+        assert_eq!(
+            IndexProvider::parse(dir).unwrap(),
+            IndexProvider::Mirror(tmp.path().to_path_buf())
+        );
+        assert_eq!(
+            IndexProvider::parse(&format!("file://{dir}")).unwrap(),
+            IndexProvider::Mirror(tmp.path().to_path_buf())
+        );
+    }
+
+    #[test]
+    fn parse_rejects_unknown_locations() {
+        assert!(IndexProvider::parse("ftp://example.com/index.json").is_err());
+        assert!(IndexProvider::parse("index.json").is_err());
+        assert!(IndexProvider::parse("file://relative/index.json").is_err());
+    }
+
+    #[test]
+    fn display_roundtrips_through_parse() {
+        for spec in [
+            DEFAULT_API_URL,
+            "https://s3.example.com/formula.json",
+            "file:///srv/index.json",
+        ] {
+            let provider = IndexProvider::parse(spec).unwrap();
+            assert_eq!(provider.to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn index_looks_up_by_name_full_name_and_alias() {
+        let index = FormulaIndex::parse(INDEX.as_bytes(), "test").unwrap();
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.formula("jq").unwrap().versions.stable, "1.7.1");
+        assert_eq!(index.formula("jq-cli").unwrap().name, "jq");
+        assert_eq!(index.formula("acme/internal/tool").unwrap().name, "tool");
+        assert!(matches!(
+            index.formula("missing"),
+            Err(Error::MissingFormula { .. })
+        ));
+    }
+
+    #[test]
+    fn index_infos_default_full_name() {
+        let body = br#"[{ "name": "foo", "versions": { "stable": "1.0" } }]"#;
+        let index = FormulaIndex::parse(body, "test").unwrap();
+
+        let infos = index.infos();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].full_name, "foo");
+    }
+
+    #[test]
+    fn index_validation_rejects_malformed_entries() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"not json", "not valid JSON"),
+            (br#"{"name": "foo"}"#, "expected a JSON array"),
+            (b"[1]", "entry 0 is not an object"),
+            (br#"[{"versions": {}}]"#, "entry 0 has no \"name\""),
+            (br#"[{"name": "foo"}]"#, "no \"versions\" object"),
+            (
+                br#"[{"name": "foo", "versions": {}}, {"name": "foo", "versions": {}}]"#,
+                "appears more than once",
+            ),
+            (
+                br#"[{"name": "foo", "versions": {}, "aliases": "bar"}]"#,
+                "formula 'foo'",
+            ),
+        ];
+
+        for (body, expected) in cases {
+            let err = FormulaIndex::parse(body, "test").unwrap_err();
+            assert!(
+                err.to_string().contains(expected),
+                "expected {expected:?} in {err}"
+            );
+        }
+    }
+}
@@ -3,7 +3,28 @@
 //! This module provides the `doctor` command functionality for checking
 //! the health and integrity of a zerobrew installation.
 
-use super::Installer;
+use zb_core::{Error, check_requirements};
+
+use std::time::Duration;
+
+use super::{DriftedKeg, Installer};
+use crate::connectivity::{
+    CAPTIVE_PORTAL_URL, Connectivity, DEFAULT_BOTTLE_URL, SAMPLE_BYTES, Timing, configured_proxies,
+};
+use crate::db::PourEvent;
+use crate::download::configured_mirrors;
+use crate::fd_limit::{fd_limit, fds_needed};
+use crate::index::IndexProvider;
+use crate::shellenv::{EnvDirState, check_shellenv};
+
+/// How far back `zb doctor` looks for bottles that failed integrity checks
+const INTEGRITY_WINDOW_DAYS: i64 = 7;
+
+/// Hosts slower than this to answer make installs feel slow
+const SLOW_LATENCY: Duration = Duration::from_secs(1);
+
+/// Formula API downloads slower than this, in bytes per second, are slow
+const SLOW_THROUGHPUT: f64 = 1024.0 * 1024.0;
 
 /// Status level for a doctor check
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,6 +66,18 @@ impl DoctorResult {
     pub fn is_healthy(&self) -> bool {
         self.errors == 0 && self.warnings == 0
     }
+
+    /// Add more checks, counting their errors and warnings
+    pub fn extend(&mut self, checks: impl IntoIterator<Item = DoctorCheck>) {
+        for check in checks {
+            match check.status {
+                DoctorStatus::Error => self.errors += 1,
+                DoctorStatus::Warning => self.warnings += 1,
+                DoctorStatus::Ok => {}
+            }
+            self.checks.push(check);
+        }
+    }
 }
 
 impl Installer {
@@ -76,6 +109,33 @@ impl Installer {
         // Check 7: Permissions on key directories
         result.checks.extend(self.check_directory_permissions());
 
+        // Check 8: Platform and tool requirements of installed formulas
+        result
+            .checks
+            .extend(self.check_formula_requirements().await);
+
+        // Check 9: Linked executables hidden by earlier PATH entries
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        result.checks.push(self.check_path_shadowing(&path_var));
+
+        // Check 10: The formula API answers
+        result.checks.extend(self.check_formula_api().await);
+
+        // Check 11: Enough open files for the configured concurrency
+        result.checks.extend(self.check_open_files());
+
+        // Check 12: The shell environment is set up
+        let manpath_var = std::env::var("MANPATH").ok();
+        result
+            .checks
+            .push(self.check_shell_environment(&path_var, manpath_var.as_deref()));
+
+        // Check 13: No bottle failed an integrity check recently
+        result.checks.push(self.check_pour_log());
+
+        // Check 14: The Cellar and the database agree on what is installed
+        result.checks.push(self.check_cellar_drift());
+
         // Count errors and warnings
         for check in &result.checks {
             match check.status {
@@ -89,7 +149,7 @@ impl Installer {
     }
 
     pub(crate) fn check_prefix_writable(&self) -> DoctorCheck {
-        let prefix = &self.prefix;
+        let prefix = &self.context.paths.prefix;
         if !prefix.exists() {
             return DoctorCheck {
                 name: "prefix_exists".to_string(),
@@ -124,7 +184,7 @@ impl Installer {
     }
 
     pub(crate) fn check_cellar_structure(&self) -> DoctorCheck {
-        let cellar = &self.cellar_path;
+        let cellar = &self.context.paths.cellar;
         if !cellar.exists() {
             return DoctorCheck {
                 name: "cellar_exists".to_string(),
@@ -202,8 +262,55 @@ impl Installer {
         }
     }
 
+    pub(crate) fn check_cellar_drift(&self) -> DoctorCheck {
+        let drift = match self.cellar_drift() {
+            Ok(drift) => drift,
+            Err(e) => {
+                return DoctorCheck {
+                    name: "cellar_drift".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Couldn't compare the Cellar with the database: {}", e),
+                    fix: None,
+                };
+            }
+        };
+        if drift.is_empty() {
+            return DoctorCheck {
+                name: "cellar_drift".to_string(),
+                status: DoctorStatus::Ok,
+                message: "Cellar and database agree".to_string(),
+                fix: None,
+            };
+        }
+
+        let mut problems = Vec::new();
+        if !drift.untracked.is_empty() {
+            problems.push(format!(
+                "{} keg(s) in the Cellar with no database record ({})",
+                drift.untracked.len(),
+                format_kegs(&drift.untracked)
+            ));
+        }
+        if !drift.missing.is_empty() {
+            problems.push(format!(
+                "{} recorded keg(s) missing from the Cellar ({})",
+                drift.missing.len(),
+                format_kegs(&drift.missing)
+            ));
+        }
+        DoctorCheck {
+            name: "cellar_drift".to_string(),
+            status: DoctorStatus::Warning,
+            message: problems.join("; "),
+            fix: Some(
+                "Run: zb cleanup --untracked adopt to record untracked kegs, or --untracked remove to delete them"
+                    .to_string(),
+            ),
+        }
+    }
+
     pub(crate) fn check_broken_symlinks(&self) -> DoctorCheck {
-        let bin_dir = self.prefix.join("bin");
+        let bin_dir = self.context.paths.prefix.join("bin");
         if !bin_dir.exists() {
             return DoctorCheck {
                 name: "broken_symlinks".to_string(),
@@ -302,6 +409,251 @@ impl Installer {
         checks
     }
 
+    pub(crate) async fn check_formula_requirements(&self) -> Vec<DoctorCheck> {
+        let mut checks = Vec::new();
+        let installed = match self.db.list_installed() {
+            Ok(i) => i,
+            Err(_) => return checks,
+        };
+
+        for keg in &installed {
+            let Ok(formula) = self.api_client.get_formula(&keg.name).await else {
+                continue;
+            };
+
+            let (message, fix) = match check_requirements(&formula, &self.host) {
+                Ok(()) => continue,
+                Err(Error::MissingRequirement {
+                    requirement, hint, ..
+                }) => (format!("'{}' requires {}", keg.name, requirement), hint),
+                Err(Error::UnsupportedPlatform {
+                    requirement, host, ..
+                }) => (
+                    format!(
+                        "'{}' requires {} but this system runs {}",
+                        keg.name, requirement, host
+                    ),
+                    format!("Run: zb uninstall {}", keg.name),
+                ),
+                Err(_) => continue,
+            };
+
+            checks.push(DoctorCheck {
+                name: "formula_requirements".to_string(),
+                status: DoctorStatus::Warning,
+                message,
+                fix: Some(fix),
+            });
+        }
+
+        if checks.is_empty() {
+            checks.push(DoctorCheck {
+                name: "formula_requirements".to_string(),
+                status: DoctorStatus::Ok,
+                message: "Installed formulas' requirements are met".to_string(),
+                fix: None,
+            });
+        }
+
+        checks
+    }
+
+    pub(crate) fn check_path_shadowing(&self, path_var: &str) -> DoctorCheck {
+        let shadowed = match self.shadowed_commands(None, path_var) {
+            Ok(s) => s,
+            Err(e) => {
+                return DoctorCheck {
+                    name: "path_shadowing".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Could not check PATH shadowing: {}", e),
+                    fix: None,
+                };
+            }
+        };
+
+        if shadowed.is_empty() {
+            return DoctorCheck {
+                name: "path_shadowing".to_string(),
+                status: DoctorStatus::Ok,
+                message: "No linked executables are shadowed in PATH".to_string(),
+                fix: None,
+            };
+        }
+
+        DoctorCheck {
+            name: "path_shadowing".to_string(),
+            status: DoctorStatus::Warning,
+            message: format!(
+                "{} linked executables are shadowed in PATH: {}",
+                shadowed.len(),
+                shadowed
+                    .iter()
+                    .take(3)
+                    .map(|s| format!("{} ({})", s.name, s.shadowed_by.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            fix: Some(format!(
+                "Put {} before other directories in PATH; see: zb which <command>",
+                self.context.paths.prefix.join("bin").display()
+            )),
+        }
+    }
+
+    /// Check that the formula API answers where `--api-domain` and
+    /// `--api-layout` point. Skipped offline and for local indexes.
+    pub(crate) async fn check_formula_api(&self) -> Option<DoctorCheck> {
+        let (url, status) = self.api_client.probe().await?;
+        let layout_fix = "Check --api-domain and --api-layout: the homebrew layout serves \
+                          <domain>/formula/<name>.json, the flat layout <domain>/<name>.json"
+            .to_string();
+
+        Some(match status {
+            Ok(status) if status.is_success() => DoctorCheck {
+                name: "formula_api".to_string(),
+                status: DoctorStatus::Ok,
+                message: format!("Formula API answered at {}", url),
+                fix: None,
+            },
+            Ok(status) => DoctorCheck {
+                name: "formula_api".to_string(),
+                status: DoctorStatus::Warning,
+                message: format!("Formula API answered {} for {}", status, url),
+                fix: Some(layout_fix),
+            },
+            Err(e) => DoctorCheck {
+                name: "formula_api".to_string(),
+                status: DoctorStatus::Warning,
+                message: format!("Formula API at {} is unreachable: {}", url, e),
+                fix: Some("Check your network connection and --api-domain".to_string()),
+            },
+        })
+    }
+
+    /// Check that PATH and MANPATH hold the prefix's directories the way
+    /// `zb shellenv` sets them up
+    pub(crate) fn check_shell_environment(
+        &self,
+        path_var: &str,
+        manpath_var: Option<&str>,
+    ) -> DoctorCheck {
+        let problems: Vec<String> =
+            check_shellenv(&self.context.paths.prefix, path_var, manpath_var)
+                .into_iter()
+                .filter_map(|check| match check.state {
+                    EnvDirState::Ok => None,
+                    EnvDirState::Missing => {
+                        Some(format!("{} is not in {}", check.dir.display(), check.var))
+                    }
+                    EnvDirState::Late { after } => Some(format!(
+                        "{} comes after {} in {}",
+                        check.dir.display(),
+                        after.display(),
+                        check.var
+                    )),
+                })
+                .collect();
+
+        if problems.is_empty() {
+            DoctorCheck {
+                name: "shellenv".to_string(),
+                status: DoctorStatus::Ok,
+                message: "Shell environment is set up".to_string(),
+                fix: None,
+            }
+        } else {
+            DoctorCheck {
+                name: "shellenv".to_string(),
+                status: DoctorStatus::Warning,
+                message: format!("Shell environment is not set up: {}", problems.join("; ")),
+                fix: Some(
+                    "Add `eval \"$(zb shellenv)\"` to the end of your shell profile".to_string(),
+                ),
+            }
+        }
+    }
+
+    /// Check the pour log for bottles that failed an integrity check in the
+    /// last week, such as a bad bottle served by a mirror
+    pub(crate) fn check_pour_log(&self) -> DoctorCheck {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let since = now - INTEGRITY_WINDOW_DAYS * 24 * 60 * 60;
+
+        let failures = match self.db.integrity_failures_since(since) {
+            Ok(failures) => failures,
+            Err(e) => {
+                return DoctorCheck {
+                    name: "pour_log".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Cannot read the pour log: {}", e),
+                    fix: None,
+                };
+            }
+        };
+
+        if failures.is_empty() {
+            return DoctorCheck {
+                name: "pour_log".to_string(),
+                status: DoctorStatus::Ok,
+                message: format!(
+                    "No bottle failed an integrity check in the last {} days",
+                    INTEGRITY_WINDOW_DAYS
+                ),
+                fix: None,
+            };
+        }
+
+        let latest: Vec<String> = failures.iter().take(3).map(describe_failure).collect();
+        DoctorCheck {
+            name: "pour_log".to_string(),
+            status: DoctorStatus::Warning,
+            message: format!(
+                "{} bottle integrity failures in the last {} days, latest: {}",
+                failures.len(),
+                INTEGRITY_WINDOW_DAYS,
+                latest.join("; ")
+            ),
+            fix: Some(
+                "Check the bottle source, e.g. your mirror; `zb stats --pours` lists every pour"
+                    .to_string(),
+            ),
+        }
+    }
+
+    /// Check that the open file limit allows the configured number of
+    /// downloads and extractions at once
+    pub(crate) fn check_open_files(&self) -> Option<DoctorCheck> {
+        let limit = fd_limit().ok()?;
+        let concurrency = self.context.concurrency;
+        let needed = fds_needed(concurrency);
+        let usage = format!(
+            "{} downloads and {} extractions need up to {} open files",
+            concurrency.downloads, concurrency.extractions, needed
+        );
+
+        Some(if limit.soft >= needed {
+            DoctorCheck {
+                name: "open_files".to_string(),
+                status: DoctorStatus::Ok,
+                message: format!("Open file limit is {}; {}", limit.soft, usage),
+                fix: None,
+            }
+        } else {
+            DoctorCheck {
+                name: "open_files".to_string(),
+                status: DoctorStatus::Warning,
+                message: format!("Open file limit is only {}; {}", limit.soft, usage),
+                fix: Some(format!(
+                    "Run `ulimit -n {}`, or lower --concurrency and --extract-concurrency",
+                    needed
+                )),
+            }
+        })
+    }
+
     #[cfg(target_os = "linux")]
     pub(crate) fn check_patchelf(&self) -> DoctorCheck {
         // Check if patchelf is available
@@ -329,7 +681,7 @@ impl Installer {
 
     pub(crate) fn check_directory_permissions(&self) -> Vec<DoctorCheck> {
         let mut checks = Vec::new();
-        let prefix = &self.prefix;
+        let prefix = &self.context.paths.prefix;
 
         let dirs_to_check = [
             prefix.to_path_buf(),
@@ -370,6 +722,224 @@ impl Installer {
     }
 }
 
+impl Installer {
+    /// Time the network paths installs depend on, for `zb doctor --network`:
+    /// the formula API, the bottle registry and configured mirrors. Empty
+    /// offline.
+    pub async fn network_doctor(&self) -> Vec<DoctorCheck> {
+        // The bottle source in use comes first
+        let mut bottle_sources = Vec::new();
+        if let Some(domain) = self.api_client.bottle_domain()
+            && domain.starts_with("http")
+        {
+            bottle_sources.push(format!("{}/", domain.trim_end_matches('/')));
+        }
+        bottle_sources.push(DEFAULT_BOTTLE_URL.to_string());
+        for mirror in configured_mirrors() {
+            if mirror.contains("://") {
+                bottle_sources.push(mirror);
+            } else {
+                bottle_sources.push(format!("https://{}/v2/", mirror));
+            }
+        }
+
+        let connectivity =
+            Connectivity::new().with_credentials(self.api_client.credentials().clone());
+        self.check_network(&connectivity, CAPTIVE_PORTAL_URL, &bottle_sources)
+            .await
+    }
+
+    /// Check for a captive portal, time the formula API and rank
+    /// `bottle_sources`, the first of which is the one in use
+    pub(crate) async fn check_network(
+        &self,
+        connectivity: &Connectivity,
+        captive_portal_url: &str,
+        bottle_sources: &[String],
+    ) -> Vec<DoctorCheck> {
+        if !self.api_client.is_live() {
+            return Vec::new();
+        }
+        let mut checks = Vec::new();
+        let mut proxied = Vec::new();
+
+        checks.push(
+            match connectivity.captive_portal(captive_portal_url).await {
+                Ok(None) => DoctorCheck {
+                    name: "captive_portal".to_string(),
+                    status: DoctorStatus::Ok,
+                    message: "No captive portal is intercepting requests".to_string(),
+                    fix: None,
+                },
+                Ok(Some(reason)) => DoctorCheck {
+                    name: "captive_portal".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Requests seem intercepted: {}", reason),
+                    fix: Some(
+                        "Sign in to the network's captive portal in a browser, or check your proxy"
+                            .to_string(),
+                    ),
+                },
+                Err(e) => DoctorCheck {
+                    name: "captive_portal".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Cannot reach {}: {}", captive_portal_url, e),
+                    fix: Some("Check your network connection".to_string()),
+                },
+            },
+        );
+
+        if let IndexProvider::Api(base_url) = self.api_client.provider() {
+            let url = format!("{}.json", base_url);
+            checks.push(match connectivity.time(&url, SAMPLE_BYTES).await {
+                Ok(timing) => {
+                    let slow = timing.latency > SLOW_LATENCY
+                        || timing.throughput().is_some_and(|t| t < SLOW_THROUGHPUT);
+                    if let Some(via) = &timing.via {
+                        proxied.push(format!("{} (Via: {})", url, via));
+                    }
+                    DoctorCheck {
+                        name: "api_speed".to_string(),
+                        status: if slow {
+                            DoctorStatus::Warning
+                        } else {
+                            DoctorStatus::Ok
+                        },
+                        message: format!("Formula API: {} from {}", format_timing(&timing), url),
+                        fix: slow.then(|| {
+                            "Try a closer API mirror with --api-domain or ZB_MIRROR".to_string()
+                        }),
+                    }
+                }
+                Err(e) => DoctorCheck {
+                    name: "api_speed".to_string(),
+                    status: DoctorStatus::Warning,
+                    message: format!("Cannot time the formula API at {}: {}", url, e),
+                    fix: Some("Check your network connection and --api-domain".to_string()),
+                },
+            });
+        }
+
+        if !bottle_sources.is_empty() {
+            let ranked = connectivity.rank(bottle_sources).await;
+            for (url, timing) in &ranked {
+                if let Ok(Timing { via: Some(via), .. }) = timing {
+                    proxied.push(format!("{} (Via: {})", url, via));
+                }
+            }
+            checks.push(bottle_sources_check(&bottle_sources[0], &ranked));
+        }
+
+        let proxies = configured_proxies();
+        if !proxies.is_empty() || !proxied.is_empty() {
+            let mut routes: Vec<String> = proxies
+                .iter()
+                .map(|(var, proxy)| format!("{}={}", var, proxy))
+                .collect();
+            routes.extend(proxied);
+            checks.push(DoctorCheck {
+                name: "proxy".to_string(),
+                status: DoctorStatus::Ok,
+                message: format!("Requests go through a proxy: {}", routes.join(", ")),
+                fix: None,
+            });
+        }
+
+        checks
+    }
+}
+
+/// Rank the bottle sources, warning when the one in use is unreachable or
+/// slow and another answers
+fn bottle_sources_check(active: &str, ranked: &[(String, Result<Timing, Error>)]) -> DoctorCheck {
+    let describe = |(url, timing): &(String, Result<Timing, Error>)| match timing {
+        Ok(timing) => format!("{} {} ms", host_of(url), timing.latency.as_millis()),
+        Err(_) => format!("{} unreachable", host_of(url)),
+    };
+    let active_timing = ranked
+        .iter()
+        .find(|(url, _)| url == active)
+        .and_then(|(_, timing)| timing.as_ref().ok());
+    let fastest = ranked
+        .first()
+        .filter(|(_, timing)| timing.is_ok())
+        .map(|(url, _)| url.as_str());
+
+    let message = match ranked {
+        [only] => format!("Bottle source: {}", describe(only)),
+        _ => format!(
+            "Bottle sources, fastest first: {}",
+            ranked.iter().map(describe).collect::<Vec<_>>().join(", ")
+        ),
+    };
+    let healthy = active_timing.is_some_and(|timing| timing.latency <= SLOW_LATENCY);
+    let fix = match fastest {
+        _ if healthy => None,
+        Some(fastest) if fastest != active => Some(format!(
+            "{} answers faster than {}; point --bottle-domain or HOMEBREW_BOTTLE_MIRRORS at it",
+            host_of(fastest),
+            host_of(active)
+        )),
+        _ => Some("Check your network connection and bottle mirrors".to_string()),
+    };
+
+    DoctorCheck {
+        name: "bottle_sources".to_string(),
+        status: if healthy {
+            DoctorStatus::Ok
+        } else {
+            DoctorStatus::Warning
+        },
+        message,
+        fix,
+    }
+}
+
+/// "85 ms, 12.3 MB/s", or just the latency if too little was read
+fn format_timing(timing: &Timing) -> String {
+    let latency = format!("{} ms", timing.latency.as_millis());
+    match timing.throughput() {
+        Some(throughput) => format!("{}, {:.1} MB/s", latency, throughput / (1024.0 * 1024.0)),
+        None => latency,
+    }
+}
+
+/// The host and port of `url`, or `url` itself if it has none
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|parsed| {
+            let host = parsed.host_str()?.to_string();
+            Some(match parsed.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host,
+            })
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// One integrity failure, e.g. "jq 1.7.1 (checksum mismatch) from <url>"
+fn describe_failure(event: &PourEvent) -> String {
+    format!(
+        "{} {} ({}) from {}",
+        event.name, event.version, event.kind, event.url
+    )
+}
+
+/// `jq 1.7.1, wget 1.24.5`, naming at most a few kegs
+fn format_kegs(kegs: &[DriftedKeg]) -> String {
+    const MAX_NAMED: usize = 3;
+    let mut named: Vec<String> = kegs
+        .iter()
+        .take(MAX_NAMED)
+        .map(|keg| format!("{} {}", keg.name, keg.version))
+        .collect();
+    if kegs.len() > MAX_NAMED {
+        named.push(format!("and {} more", kegs.len() - MAX_NAMED));
+    }
+    named.join(", ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -410,9 +980,7 @@ mod tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         )
     }
 
@@ -422,7 +990,7 @@ mod tests {
         let installer = create_test_installer_for_doctor(&tmp);
 
         // Make the prefix read-only
-        let prefix = &installer.prefix;
+        let prefix = &installer.context.paths.prefix;
         let permissions = fs::Permissions::from_mode(0o555);
         fs::set_permissions(prefix, permissions).unwrap();
 
@@ -454,7 +1022,7 @@ mod tests {
         let installer = create_test_installer_for_doctor(&tmp);
 
         // Create bin directory with a broken symlink
-        let bin_dir = installer.prefix.join("bin");
+        let bin_dir = installer.context.paths.prefix.join("bin");
         fs::create_dir_all(&bin_dir).unwrap();
 
         // Create a symlink to a non-existent target
@@ -476,7 +1044,7 @@ mod tests {
         let installer = create_test_installer_for_doctor(&tmp);
 
         // Create bin directory with a valid symlink
-        let bin_dir = installer.prefix.join("bin");
+        let bin_dir = installer.context.paths.prefix.join("bin");
         fs::create_dir_all(&bin_dir).unwrap();
 
         // Create a valid target and symlink to it
@@ -539,9 +1107,7 @@ mod tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         );
 
         // Record testpkg as installed in the database
@@ -577,5 +1143,341 @@ mod tests {
         assert!(checks[0].message.contains("All dependencies are installed"));
     }
 
+    #[tokio::test]
+    async fn check_formula_requirements_reports_unmet() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::create_dir_all(&prefix).unwrap();
+
+        let formula_json = r#"{
+            "name": "intelonly",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+            "requirements": [
+                { "name": "arch", "version": "x86_64", "contexts": [] }
+            ]
+        }"#;
+
+        Mock::given(method("GET"))
+            .and(path("/intelonly.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let taps_dir = root.join("taps");
+        fs::create_dir_all(&taps_dir).unwrap();
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(mock_server.uri()),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            TapManager::new(&taps_dir),
+            zb_core::Context::new(&root, &prefix),
+        );
+        installer.host = zb_core::Host::new(
+            zb_core::HostPlatform::MacOS(zb_core::MacOSVersion::parse("14.0")),
+            "arm64",
+        );
+
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("intelonly", "1.0.0", "abc123", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let checks = installer.check_formula_requirements().await;
+
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, DoctorStatus::Warning);
+        assert!(
+            checks[0]
+                .message
+                .contains("'intelonly' requires an x86_64 CPU")
+        );
+        assert!(checks[0].fix.as_deref().unwrap().contains("arch -x86_64"));
+    }
+
+    #[tokio::test]
+    async fn check_formula_requirements_ok_when_nothing_installed() {
+        let tmp = TempDir::new().unwrap();
+        let installer = create_test_installer_for_doctor(&tmp);
+
+        let checks = installer.check_formula_requirements().await;
+
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, DoctorStatus::Ok);
+    }
+
+    #[test]
+    fn check_path_shadowing_warns_when_earlier_dir_wins() {
+        let tmp = TempDir::new().unwrap();
+        let installer = create_test_installer_for_doctor(&tmp);
+        let prefix_bin = tmp.path().join("homebrew/bin");
+        let system_bin = tmp.path().join("usr/bin");
+        for dir in [&prefix_bin, &system_bin] {
+            fs::create_dir_all(dir).unwrap();
+            let tool = dir.join("python3");
+            fs::write(&tool, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path_of = |dirs: &[&std::path::PathBuf]| {
+            std::env::join_paths(dirs)
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let check = installer.check_path_shadowing(&path_of(&[&system_bin, &prefix_bin]));
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert!(check.message.contains("python3"));
+        assert!(check.message.contains(&system_bin.display().to_string()));
+
+        let check = installer.check_path_shadowing(&path_of(&[&prefix_bin, &system_bin]));
+        assert_eq!(check.status, DoctorStatus::Ok);
+
+        // prefix/bin missing from PATH entirely is not shadowing
+        let check = installer.check_path_shadowing(&path_of(&[&system_bin]));
+        assert_eq!(check.status, DoctorStatus::Ok);
+    }
+
+    #[tokio::test]
+    async fn check_formula_api_reports_what_the_api_answers() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer_for_doctor(&tmp);
+        let api_domain = format!("{}/api", mock_server.uri());
+
+        installer
+            .set_api_domain(&api_domain, zb_core::ApiLayout::Homebrew)
+            .unwrap();
+        let check = installer.check_formula_api().await.unwrap();
+        assert_eq!(check.status, DoctorStatus::Ok, "{}", check.message);
+
+        // The flat layout looks for <domain>.json, which this server lacks
+        installer
+            .set_api_domain(&api_domain, zb_core::ApiLayout::Flat)
+            .unwrap();
+        let check = installer.check_formula_api().await.unwrap();
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert!(check.message.contains("404"), "{}", check.message);
+        assert!(check.fix.unwrap().contains("--api-layout"));
+
+        installer.api_client.set_offline(true);
+        assert!(installer.check_formula_api().await.is_none());
+    }
+
+    #[test]
+    fn extend_counts_errors_and_warnings() {
+        let check = |status| DoctorCheck {
+            name: "test".to_string(),
+            status,
+            message: String::new(),
+            fix: None,
+        };
+        let mut result = DoctorResult::default();
+        result.extend([
+            check(DoctorStatus::Ok),
+            check(DoctorStatus::Warning),
+            check(DoctorStatus::Warning),
+            check(DoctorStatus::Error),
+        ]);
+        assert_eq!(result.checks.len(), 4);
+        assert_eq!((result.errors, result.warnings), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn check_network_times_the_api_and_ranks_bottle_sources() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/generate_204"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![b'x'; 4096]))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer_for_doctor(&tmp);
+        installer
+            .set_api_domain(
+                &format!("{}/api", mock_server.uri()),
+                zb_core::ApiLayout::Homebrew,
+            )
+            .unwrap();
+        let connectivity = Connectivity::new();
+        let captive_portal_url = format!("{}/generate_204", mock_server.uri());
+        let mirror = format!("{}/v2/", mock_server.uri());
+
+        // The bottle source in use doesn't answer, but a mirror does
+        let sources = vec!["http://127.0.0.1:1/v2/".to_string(), mirror.clone()];
+        let checks = installer
+            .check_network(&connectivity, &captive_portal_url, &sources)
+            .await;
+        let names: Vec<&str> = checks.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(
+            &names[..3],
+            ["captive_portal", "api_speed", "bottle_sources"]
+        );
+        assert_eq!(checks[0].status, DoctorStatus::Ok);
+        assert_eq!(checks[1].status, DoctorStatus::Ok, "{}", checks[1].message);
+        assert!(checks[1].message.starts_with("Formula API: "));
+        assert_eq!(checks[2].status, DoctorStatus::Warning);
+        assert!(
+            checks[2].message.ends_with("127.0.0.1:1 unreachable"),
+            "{}",
+            checks[2].message
+        );
+        assert!(
+            checks[2]
+                .fix
+                .as_ref()
+                .unwrap()
+                .contains("answers faster than")
+        );
+
+        let checks = installer
+            .check_network(&connectivity, &captive_portal_url, &[mirror])
+            .await;
+        assert_eq!(checks[2].status, DoctorStatus::Ok, "{}", checks[2].message);
+
+        installer.api_client.set_offline(true);
+        assert!(
+            installer
+                .check_network(&connectivity, &captive_portal_url, &sources)
+                .await
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn check_open_files_compares_the_limit_with_the_concurrency() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer_for_doctor(&tmp);
+
+        installer.context.concurrency = zb_core::ConcurrencyLimits {
+            downloads: 1,
+            extractions: 1,
+        };
+        let check = installer.check_open_files().unwrap();
+        assert_eq!(check.status, DoctorStatus::Ok, "{}", check.message);
+
+        // More open files than any soft limit allows
+        installer.context.concurrency = zb_core::ConcurrencyLimits {
+            downloads: 1 << 40,
+            extractions: 1,
+        };
+        let check = installer.check_open_files().unwrap();
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert!(check.fix.unwrap().contains("ulimit -n"));
+    }
+
+    #[test]
+    fn check_shell_environment_reports_missing_directories() {
+        let tmp = TempDir::new().unwrap();
+        let installer = create_test_installer_for_doctor(&tmp);
+        let prefix = tmp.path().join("homebrew");
+
+        let path_var = format!("{0}/bin:{0}/sbin:/usr/bin", prefix.display());
+        let manpath_var = format!("{}/share/man:", prefix.display());
+        let check = installer.check_shell_environment(&path_var, Some(&manpath_var));
+        assert_eq!(check.status, DoctorStatus::Ok, "{}", check.message);
+
+        let check = installer.check_shell_environment("/usr/bin:/bin", None);
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert!(check.message.contains("sbin is not in PATH"));
+        assert!(check.message.contains("share/man is not in MANPATH"));
+        assert!(check.fix.unwrap().contains("zb shellenv"));
+    }
+
+    #[test]
+    fn check_cellar_drift_reports_untracked_and_missing_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer_for_doctor(&tmp);
+        let check = installer.check_cellar_drift();
+        assert_eq!(check.status, DoctorStatus::Ok, "{}", check.message);
+
+        let cellar = tmp.path().join("zerobrew/cellar");
+        fs::create_dir_all(cellar.join("stray/1.0/bin")).unwrap();
+        fs::create_dir_all(cellar.join(".stray-1.1.tmp.1/keg")).unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("ghost", "2.0", "abc123", true).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let check = installer.check_cellar_drift();
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert_eq!(
+            check.message,
+            "1 keg(s) in the Cellar with no database record (stray 1.0); \
+             1 recorded keg(s) missing from the Cellar (ghost 2.0)"
+        );
+        assert!(check.fix.unwrap().contains("zb cleanup --untracked adopt"));
+    }
+
+    #[test]
+    fn check_pour_log_reports_integrity_failures() {
+        let tmp = TempDir::new().unwrap();
+        let installer = create_test_installer_for_doctor(&tmp);
+        let check = installer.check_pour_log();
+        assert_eq!(check.status, DoctorStatus::Ok, "{}", check.message);
+
+        let event = PourEvent {
+            at: 0,
+            kind: crate::db::PourEventKind::ChecksumMismatch,
+            name: "jq".to_string(),
+            version: "1.7.1".to_string(),
+            url: "https://mirror.example.com/jq.tar.gz".to_string(),
+            sha256: "abc".to_string(),
+            bytes: 0,
+            duration_ms: 0,
+            relocated: false,
+            detail: Some("got sha256 def".to_string()),
+        };
+        installer.db.record_pour_event(&event).unwrap();
+
+        let check = installer.check_pour_log();
+        assert_eq!(check.status, DoctorStatus::Warning);
+        assert!(
+            check.message.contains(
+                "1 bottle integrity failures in the last 7 days, latest: \
+                 jq 1.7.1 (checksum mismatch) from https://mirror.example.com/jq.tar.gz"
+            ),
+            "{}",
+            check.message
+        );
+        assert!(check.fix.unwrap().contains("zb stats --pours"));
+    }
+
     use std::os::unix::fs::PermissionsExt;
 }
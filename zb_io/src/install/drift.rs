@@ -0,0 +1,314 @@
+//! Drift between the Cellar and the database
+//!
+//! This module handles:
+//! - Finding keg directories the database has no record of, e.g. left
+//!   behind by an install that crashed before recording them
+//! - Finding recorded kegs whose directories were deleted by hand
+//! - Removing or re-adopting the former and forgetting the latter
+//! - Adopting a keg placed in the Cellar by hand as a managed install
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use zb_core::Error;
+
+use super::{Installer, dependency_names};
+
+/// A keg the Cellar and the database disagree about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriftedKeg {
+    pub name: String,
+    pub version: String,
+    /// Where the keg is, or should be, in the Cellar
+    pub path: PathBuf,
+}
+
+/// Kegs the Cellar and the database disagree about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellarDrift {
+    /// Keg directories in the Cellar with no database record
+    pub untracked: Vec<DriftedKeg>,
+    /// Kegs the database records but the Cellar doesn't have
+    pub missing: Vec<DriftedKeg>,
+}
+
+impl CellarDrift {
+    pub fn is_empty(&self) -> bool {
+        self.untracked.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// What to do with untracked keg directories
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UntrackedAction {
+    /// Delete them
+    Remove,
+    /// Record them as installed, unlinked, so zb manages them again
+    Adopt,
+}
+
+/// A keg placed in the Cellar by hand that zb now manages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdoptResult {
+    pub name: String,
+    pub version: String,
+    pub keg_path: PathBuf,
+    /// Store entry the keg was copied into
+    pub store_key: String,
+    /// Whether the formula's metadata was found, so its dependencies and
+    /// keg-only status could be recorded
+    pub known_formula: bool,
+    pub keg_only: bool,
+    pub linked: bool,
+    pub files_linked: usize,
+}
+
+/// Store key for the copy of a keg zb didn't pour itself
+fn adopted_store_key(name: &str, version: &str) -> String {
+    format!("adopted-{name}-{version}")
+}
+
+impl Installer {
+    /// Take over a keg placed in the Cellar by hand.
+    ///
+    /// The keg is copied into the store with its manifest, so `zb verify`
+    /// and `zb gc` treat it like a poured bottle, recorded as the formula's
+    /// active version and linked. Dependencies and keg-only status come from
+    /// the formula's metadata when it can be fetched. A keg-only formula, or
+    /// any when `link` is false, only gets its opt symlink.
+    pub async fn adopt(
+        &mut self,
+        name: &str,
+        version: &str,
+        link: bool,
+    ) -> Result<AdoptResult, Error> {
+        let keg_path = self.cellar.keg_path(name, version);
+        if !keg_path.is_dir() {
+            return Err(Error::StoreCorruption {
+                message: format!("no keg at {}", keg_path.display()),
+            });
+        }
+        let formula = self.api_client.get_formula(name).await.ok();
+
+        let _lock = self.store.lock_formula(name)?;
+        if self.is_recorded(name, version)? {
+            return Err(Error::StoreCorruption {
+                message: format!("{name} {version} is already managed by zb"),
+            });
+        }
+
+        let store_key = adopted_store_key(name, version);
+        self.store
+            .ingest_keg(&store_key, name, version, &keg_path)?;
+
+        let keg_only = formula.as_ref().is_some_and(|f| f.keg_only);
+        let link = link && !keg_only;
+        let linked_files = if link {
+            self.link_replacing(name, &keg_path)?
+        } else {
+            self.linker.link_opt(&keg_path)?;
+            Vec::new()
+        };
+
+        let tx = self.db.transaction()?;
+        tx.record_install(name, version, &store_key, true)?;
+        tx.record_link_state(name, link, keg_only)?;
+        tx.record_provenance(name, &self.provenance)?;
+        if let Some(formula) = &formula {
+            tx.record_dependencies(name, &dependency_names(formula))?;
+        }
+        for linked in &linked_files {
+            tx.record_linked_file(
+                name,
+                version,
+                &linked.link_path.to_string_lossy(),
+                &linked.target_path.to_string_lossy(),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(AdoptResult {
+            name: name.to_string(),
+            version: version.to_string(),
+            keg_path,
+            store_key,
+            known_formula: formula.is_some(),
+            keg_only,
+            linked: link,
+            files_linked: linked_files.len(),
+        })
+    }
+
+    /// Whether the database records `name` `version`, active or not
+    fn is_recorded(&self, name: &str, version: &str) -> Result<bool, Error> {
+        Ok(self
+            .db
+            .get_installed(name)
+            .is_some_and(|installed| installed.version == version)
+            || self
+                .db
+                .list_versions(name)?
+                .iter()
+                .any(|v| v.version == version))
+    }
+
+    /// Compare the kegs in the Cellar with the ones the database records
+    pub fn cellar_drift(&self) -> Result<CellarDrift, Error> {
+        let mut recorded: HashSet<(String, String)> = self
+            .db
+            .list_all_versions()?
+            .into_iter()
+            .map(|keg| (keg.name, keg.version))
+            .collect();
+        recorded.extend(
+            self.db
+                .list_installed()?
+                .into_iter()
+                .map(|keg| (keg.name, keg.version)),
+        );
+
+        let present = self
+            .cellar
+            .list_kegs()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to list Cellar: {e}"),
+            })?;
+
+        let drifted = |(name, version): &(String, String)| DriftedKeg {
+            path: self.cellar.keg_path(name, version),
+            name: name.clone(),
+            version: version.clone(),
+        };
+        let untracked = present
+            .iter()
+            .filter(|keg| !recorded.contains(*keg))
+            .map(drifted)
+            .collect();
+        let present: HashSet<(String, String)> = present.into_iter().collect();
+        let mut missing: Vec<DriftedKeg> = recorded
+            .iter()
+            .filter(|keg| !present.contains(*keg))
+            .map(drifted)
+            .collect();
+        missing.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+        Ok(CellarDrift { untracked, missing })
+    }
+
+    /// Bring the database and the Cellar back in line: forget recorded kegs
+    /// whose directories are gone, then remove or adopt untracked ones.
+    /// Returns the drift that was reconciled.
+    pub fn reconcile_cellar(&mut self, action: UntrackedAction) -> Result<CellarDrift, Error> {
+        let missing = self.cellar_drift()?.missing;
+        let missing = self.forget_missing_kegs(missing)?;
+
+        // Forgetting a missing active keg drops the formula's other
+        // versions too, so look again for what became untracked
+        let untracked = self.cellar_drift()?.untracked;
+        let untracked = self.reconcile_untracked(untracked, action)?;
+
+        Ok(CellarDrift { untracked, missing })
+    }
+
+    /// Forget each of `missing` that is still recorded and still gone once
+    /// its formula is locked; another zb may have reinstalled it since the
+    /// scan. Returns the kegs forgotten. Extracted for testability.
+    pub(crate) fn forget_missing_kegs(
+        &mut self,
+        missing: Vec<DriftedKeg>,
+    ) -> Result<Vec<DriftedKeg>, Error> {
+        let mut forgotten = Vec::new();
+        for keg in missing {
+            let _lock = self.store.lock_formula(&keg.name)?;
+            if keg.path.exists() || !self.is_recorded(&keg.name, &keg.version)? {
+                continue;
+            }
+            self.forget_missing(&keg)?;
+            forgotten.push(keg);
+        }
+        Ok(forgotten)
+    }
+
+    /// Remove or adopt each of `untracked` that is still there and still
+    /// unrecorded once its formula is locked; an install in progress when
+    /// the Cellar was scanned may have recorded it since. Returns the kegs
+    /// handled. Extracted for testability.
+    pub(crate) fn reconcile_untracked(
+        &mut self,
+        untracked: Vec<DriftedKeg>,
+        action: UntrackedAction,
+    ) -> Result<Vec<DriftedKeg>, Error> {
+        let mut reconciled = Vec::new();
+        for keg in untracked {
+            let _lock = self.store.lock_formula(&keg.name)?;
+            if !keg.path.is_dir() || self.is_recorded(&keg.name, &keg.version)? {
+                continue;
+            }
+            match action {
+                UntrackedAction::Remove => self.cellar.remove_keg(&keg.name, &keg.version)?,
+                UntrackedAction::Adopt => self.adopt_keg(&keg)?,
+            }
+            reconciled.push(keg);
+        }
+        Ok(reconciled)
+    }
+
+    /// Drop the record of a keg whose directory is gone, with the links
+    /// left dangling into it. The caller holds the formula's lock.
+    fn forget_missing(&mut self, keg: &DriftedKeg) -> Result<(), Error> {
+        let active = self
+            .db
+            .get_installed(&keg.name)
+            .is_some_and(|installed| installed.version == keg.version);
+
+        if !active {
+            let tx = self.db.transaction()?;
+            tx.record_uninstall_version(&keg.name, &keg.version)?;
+            return tx.commit();
+        }
+
+        let opt_link = self.context.paths.prefix.join("opt").join(&keg.name);
+        let mut links: Vec<PathBuf> = self
+            .db
+            .get_linked_files(&keg.name)?
+            .into_iter()
+            .map(|(link_path, _)| PathBuf::from(link_path))
+            .collect();
+        links.push(opt_link);
+        for link in links {
+            let dangling = link.symlink_metadata().is_ok() && !link.exists();
+            if dangling {
+                let _ = std::fs::remove_file(&link);
+            }
+        }
+        self.restore_backups(&keg.name)?;
+
+        let tx = self.db.transaction()?;
+        tx.record_uninstall(&keg.name)?;
+        tx.commit()
+    }
+
+    /// Record an untracked keg as installed. It becomes the formula's active
+    /// version if no other is installed, and is left unlinked apart from
+    /// its opt symlink. The caller holds the formula's lock.
+    fn adopt_keg(&mut self, keg: &DriftedKeg) -> Result<(), Error> {
+        let store_key = adopted_store_key(&keg.name, &keg.version);
+        self.store
+            .ingest_keg(&store_key, &keg.name, &keg.version, &keg.path)?;
+        let has_active = self.db.get_installed(&keg.name).is_some();
+
+        let tx = self.db.transaction()?;
+        if has_active {
+            tx.record_keg_version(&keg.name, &keg.version, &store_key)?;
+        } else {
+            tx.record_install(&keg.name, &keg.version, &store_key, true)?;
+            tx.record_link_state(&keg.name, false, false)?;
+        }
+        tx.commit()?;
+
+        if !has_active {
+            self.linker.link_opt(&keg.path)?;
+        }
+        Ok(())
+    }
+}
@@ -6,19 +6,63 @@
 //! - Linking executables
 //! - Garbage collection and cleanup
 
+use std::path::Path;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::attestation::{Attestation, AttestationStatus, CORE_ATTESTATION_REPO};
+use crate::config::ConfigAction;
+use crate::db::{KegVersion, PourEvent, PourEventKind, Provenance};
 use crate::download::{DownloadProgressCallback, DownloadRequest, DownloadResult};
+use crate::manifest::ManifestMismatch;
+use crate::materialize::find_bottle_content;
 use crate::progress::{InstallProgress, ProgressCallback};
+use crate::receipt::InstallReceipt;
+use crate::timing::{InstallTimings, PackageTiming, Phase, SpanRecorder, duration_to_ms};
 
 use zb_core::{Error, Formula, SelectedBottle};
 
-use super::{CleanupResult, InstallPlan, Installer, MAX_CORRUPTION_RETRIES, ProcessedPackage};
+use super::journal::InstallJournal;
+use super::planner::{CORE_TAP, source_ref};
+use super::{
+    CleanupResult, InstallPlan, Installer, MAX_CORRUPTION_RETRIES, ProcessedPackage,
+    SourceBuildResult, dependency_names,
+};
 
 /// Result of executing an install plan
 #[derive(Debug)]
 pub struct ExecuteResult {
     pub installed: usize,
+    /// The plan's source builds, in the order they were built
+    pub built: Vec<SourceBuildResult>,
+    /// Per-phase timings for this run
+    pub timings: InstallTimings,
+}
+
+/// An installed keg re-checked by [`Installer::verify_installed`]
+#[derive(Debug, Clone)]
+pub struct KegVerification {
+    pub name: String,
+    pub version: String,
+    pub store_key: String,
+    /// Files that differ from the store entry's manifest
+    pub mismatches: Vec<ManifestMismatch>,
+    /// Recorded when the bottle was installed with `--verify`
+    pub attestation: Option<Attestation>,
+}
+
+impl KegVerification {
+    /// The keg matches its store entry, and its bottle's attestation, if
+    /// checked, didn't fail
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+            && self
+                .attestation
+                .as_ref()
+                .is_none_or(|a| a.status != AttestationStatus::Failed)
+    }
 }
 
 impl Installer {
@@ -29,7 +73,37 @@ impl Installer {
 
     /// Execute the install plan with progress callback
     /// Uses streaming extraction - starts extracting each package as soon as its download completes
+    ///
+    /// Pouring the bottles is all or nothing: if any package fails,
+    /// everything this call extracted, materialized or linked is rolled back
+    /// and no database records are written. The plan's source builds follow,
+    /// in dependency order; the first that fails is returned as the error,
+    /// with the bottles poured before it left installed.
     pub async fn execute_with_progress(
+        &mut self,
+        mut plan: InstallPlan,
+        link: bool,
+        progress: Option<Arc<ProgressCallback>>,
+    ) -> Result<ExecuteResult, Error> {
+        let source_builds = std::mem::take(&mut plan.source_builds);
+        let roots = plan.roots.clone();
+        let mut result = self.pour_bottles(plan, link, progress).await?;
+
+        for formula in &source_builds {
+            let explicit = roots.contains(&formula.name);
+            // Boxed: a build installs its missing dependencies through here
+            let built =
+                Box::pin(self.build_from_source(&source_ref(formula), link, false, &[], explicit))
+                    .await?;
+            result.installed += 1;
+            result.built.push(built);
+        }
+        Ok(result)
+    }
+
+    /// Pour the bottles of `plan`, streaming each package's extraction as
+    /// soon as its download completes
+    async fn pour_bottles(
         &mut self,
         plan: InstallPlan,
         link: bool,
@@ -41,10 +115,21 @@ impl Installer {
             }
         };
 
-        // Track which package was explicitly requested
-        let root_name = plan.root_name.clone();
+        let mut spans = SpanRecorder::new();
+
+        // Fail on executables that can't be linked before downloading anything
+        if link && let Some(conflict) = self.link_conflicts(&plan).first() {
+            return Err(conflict.to_error());
+        }
+
+        // Track which packages were explicitly requested
+        let root_name = plan.roots.join(" ");
+        let requested_by = plan.requested_by();
+        let first_root = plan.root_name.clone();
+        let resolve_time = plan.resolve_time;
 
         // Pair formulas with bottles
+        #[allow(clippy::useless_conversion)]
         let to_install: Vec<(Formula, SelectedBottle)> = plan
             .formulas
             .into_iter()
@@ -52,9 +137,43 @@ impl Installer {
             .collect();
 
         if to_install.is_empty() {
-            return Ok(ExecuteResult { installed: 0 });
+            return Ok(ExecuteResult {
+                installed: 0,
+                built: Vec::new(),
+                timings: InstallTimings {
+                    resolve: resolve_time,
+                    ..Default::default()
+                },
+            });
+        }
+
+        // Serialize with other zb processes touching the same formulas
+        let _locks = self
+            .store
+            .lock_formulas(to_install.iter().map(|(f, _)| f.name.as_str()))?;
+
+        if self.refetch_bottles {
+            for (_, bottle) in &to_install {
+                let _ = self.blob_cache.remove_blob(&bottle.sha256);
+            }
         }
 
+        // Note which bottles are already cached so we can report cache effectiveness
+        let blob_cached: Vec<bool> = to_install
+            .iter()
+            .map(|(_, b)| self.blob_cache.has_blob(&b.sha256))
+            .collect();
+
+        // Note which packages replace an older installed version, for upgrade history
+        let upgraded: Vec<bool> = to_install
+            .iter()
+            .map(|(f, _)| {
+                self.db
+                    .get_installed(&f.name)
+                    .is_some_and(|k| k.version != f.effective_version())
+            })
+            .collect();
+
         // Download all bottles
         let requests: Vec<DownloadRequest> = to_install
             .iter()
@@ -72,7 +191,8 @@ impl Installer {
             }) as DownloadProgressCallback
         });
 
-        // Use streaming downloads - process each as it completes
+        // Use streaming downloads - process each as it completes. Cached
+        // bottles come through first, so they extract while the rest download.
         let mut rx = self
             .downloader
             .download_streaming(requests, download_progress.clone());
@@ -80,56 +200,185 @@ impl Installer {
         // Track results by index to maintain install order for database records
         let total = to_install.len();
         let mut completed: Vec<Option<ProcessedPackage>> = vec![None; total];
+        let mut package_timings: Vec<Option<PackageTiming>> = vec![None; total];
+        let mut pours: Vec<PourEvent> = Vec::new();
         let mut error: Option<Error> = None;
+        let mut journal = InstallJournal::new();
+
+        // Bottles the downloader didn't already unpack into the store as
+        // they arrived are handed to a pool of extraction workers bounded by
+        // `ConcurrencyLimits::extractions`, whose slots the downloader's
+        // unpacking holds too. Extracted bottles are then materialized and
+        // linked here, one at a time.
+        let this = &*self;
+        let mut extractions = FuturesUnordered::new();
+        let mut downloads_open = true;
+
+        loop {
+            tokio::select! {
+                result = rx.recv(), if downloads_open => match result {
+                    Some(Ok(download)) => {
+                        let idx = download.index;
+                        let (formula, bottle) = &to_install[idx];
+                        let download_time =
+                            spans.record(Phase::Download, &formula.name, Duration::ZERO);
+                        let bytes = std::fs::metadata(&download.blob_path)
+                            .map(|m| m.len())
+                            .unwrap_or(0);
+                        // An entry unpacked during the download is new
+                        let store_reused =
+                            download.entry.is_none() && this.store.has_entry(&bottle.sha256);
+
+                        report(InstallProgress::UnpackStarted {
+                            name: formula.name.clone(),
+                        });
 
-        // Process downloads as they complete
-        while let Some(result) = rx.recv().await {
-            match result {
-                Ok(download) => {
-                    let idx = download.index;
+                        let download_progress = download_progress.clone();
+                        extractions.push(async move {
+                            let started = Instant::now();
+                            if let Some(entry) = download.entry.clone() {
+                                return (idx, download_time, bytes, store_reused, started, Ok(entry));
+                            }
+                            let _slot = this.extract_slots.acquire().await;
+                            // Retries corrupted downloads
+                            let entry = this
+                                .extract_with_retry(&download, formula, bottle, download_progress)
+                                .await;
+                            (idx, download_time, bytes, store_reused, started, entry)
+                        });
+                    }
+                    Some(Err(e)) => {
+                        if let Error::ChecksumMismatch { expected, actual, .. } = &e
+                            && let Some((formula, bottle)) =
+                                to_install.iter().find(|(_, b)| b.sha256 == *expected)
+                        {
+                            this.log_integrity_failure(
+                                PourEventKind::ChecksumMismatch,
+                                formula,
+                                bottle,
+                                0,
+                                format!("got sha256 {actual}"),
+                            );
+                        }
+                        error = Some(e);
+                    }
+                    None => downloads_open = false,
+                },
+                Some((idx, download_time, bytes, store_reused, started, entry)) =
+                    extractions.next() =>
+                {
                     let (formula, bottle) = &to_install[idx];
-
-                    report(InstallProgress::UnpackStarted {
-                        name: formula.name.clone(),
-                    });
-
-                    // Try extraction with retry logic for corrupted downloads
-                    let store_entry = match self
-                        .extract_with_retry(&download, formula, bottle, download_progress.clone())
-                        .await
-                    {
+                    let extract_start = spans.now().saturating_sub(started.elapsed());
+                    let store_entry = match entry {
                         Ok(entry) => entry,
                         Err(e) => {
                             error = Some(e);
                             continue;
                         }
                     };
+                    if !store_reused {
+                        journal.ingested(&bottle.sha256);
+                    }
+
+                    // Everything will be rolled back, so don't touch the
+                    // Cellar or prefix for the remaining packages
+                    if error.is_some() {
+                        continue;
+                    }
+
+                    if this.verify
+                        && let Err(e) = this.check_attestation(formula, bottle).await
+                    {
+                        this.log_integrity_failure(
+                            PourEventKind::AttestationFailed,
+                            formula,
+                            bottle,
+                            bytes,
+                            e.to_string(),
+                        );
+                        error = Some(e);
+                        continue;
+                    }
 
                     // Materialize to cellar
                     // Use effective_version() which includes rebuild suffix if applicable
-                    let keg_path = match self.cellar.materialize(
-                        &formula.name,
-                        &formula.effective_version(),
-                        &store_entry,
-                    ) {
-                        Ok(path) => path,
-                        Err(e) => {
-                            error = Some(e);
-                            continue;
-                        }
-                    };
+                    let version = formula.effective_version();
+                    let keg_existed = this.cellar.has_keg(&formula.name, &version);
+                    let keg_path =
+                        match this.cellar.materialize(&formula.name, &version, &store_entry) {
+                            Ok(path) => path,
+                            Err(e) => {
+                                error = Some(e);
+                                continue;
+                            }
+                        };
+                    if !keg_existed {
+                        journal.materialized(&formula.name, &version);
+                    }
+
+                    if this.verify
+                        && let Err(e) = this.verify_keg(
+                            &formula.name,
+                            &formula.effective_version(),
+                            &bottle.sha256,
+                            &keg_path,
+                        )
+                    {
+                        this.log_integrity_failure(
+                            PourEventKind::KegMismatch,
+                            formula,
+                            bottle,
+                            bytes,
+                            e.to_string(),
+                        );
+                        error = Some(e);
+                        continue;
+                    }
+
+                    let receipt = InstallReceipt::bottle(formula);
+                    if let Err(e) = receipt.write(&keg_path) {
+                        error = Some(e);
+                        continue;
+                    }
+
+                    let config_files =
+                        match this.install_config_files(&formula.name, &keg_path) {
+                            Ok((files, changes)) => {
+                                for change in changes {
+                                    if change.action == ConfigAction::Installed {
+                                        journal.configured(change.path);
+                                    }
+                                }
+                                files
+                            }
+                            Err(e) => {
+                                error = Some(e);
+                                continue;
+                            }
+                        };
 
                     report(InstallProgress::UnpackCompleted {
                         name: formula.name.clone(),
                     });
+                    let extract_time = spans.record(Phase::Extract, &formula.name, extract_start);
 
                     // Link executables if requested
+                    let link_start = spans.now();
                     let linked_files = if link {
                         report(InstallProgress::LinkStarted {
                             name: formula.name.clone(),
                         });
-                        match self.linker.link_keg(&keg_path) {
+                        let previous = this
+                            .db
+                            .get_installed(&formula.name)
+                            .filter(|k| k.linked)
+                            .map(|k| this.cellar.keg_path(&formula.name, &k.version));
+                        let already_linked = previous.as_ref() == Some(&keg_path);
+                        match this.link_replacing(&formula.name, &keg_path) {
                             Ok(files) => {
+                                if !already_linked {
+                                    journal.linked(keg_path.clone(), previous);
+                                }
                                 report(InstallProgress::LinkCompleted {
                                     name: formula.name.clone(),
                                 });
@@ -141,29 +390,111 @@ impl Installer {
                             }
                         }
                     } else {
+                        // Unlinked formulas still get opt/, which build
+                        // scripts use to find keg-only ones
+                        let previous = this
+                            .db
+                            .get_installed(&formula.name)
+                            .map(|k| this.cellar.keg_path(&formula.name, &k.version));
+                        let already_linked = previous.as_ref() == Some(&keg_path);
+                        if let Err(e) = this.linker.link_opt(&keg_path) {
+                            error = Some(e);
+                            continue;
+                        }
+                        if !already_linked {
+                            journal.opt_linked(keg_path.clone(), previous);
+                        }
                         Vec::new()
                     };
+                    let link_time = spans.record(Phase::Link, &formula.name, link_start);
+
+                    pours.push(PourEvent {
+                        at: 0, // Set when recorded
+                        kind: PourEventKind::Poured,
+                        name: formula.name.clone(),
+                        version: formula.effective_version(),
+                        url: bottle.url.clone(),
+                        sha256: bottle.sha256.clone(),
+                        bytes,
+                        duration_ms: duration_to_ms(extract_time + link_time),
+                        relocated: !keg_existed,
+                        detail: None,
+                    });
+
+                    package_timings[idx] = Some(PackageTiming {
+                        name: formula.name.clone(),
+                        version: formula.effective_version(),
+                        download: download_time,
+                        extract: extract_time,
+                        link: link_time,
+                        bytes,
+                        blob_cached: blob_cached[idx],
+                        store_reused,
+                        upgraded: upgraded[idx],
+                    });
 
                     completed[idx] = Some(ProcessedPackage {
                         name: formula.name.clone(),
                         version: formula.effective_version(),
                         store_key: bottle.sha256.clone(),
                         linked_files,
-                        explicit: formula.name == root_name,
+                        explicit: requested_by.get(&formula.name) == Some(&formula.name),
+                        linked: link,
+                        keg_only: formula.keg_only,
+                        dependencies: dependency_names(formula),
+                        receipt,
+                        config_files,
+                        provenance: match requested_by.get(&formula.name) {
+                            Some(root) if *root == formula.name => this.provenance.clone(),
+                            root => Provenance::DependencyOf {
+                                name: root.unwrap_or(&first_root).clone(),
+                            },
+                        },
                     });
                 }
-                Err(e) => {
-                    error = Some(e);
-                }
+                else => break,
             }
         }
+        drop(extractions);
 
         // Return error if any download failed
         if let Some(e) = error {
+            self.undo_install(journal);
             return Err(e);
         }
 
-        // Record all successful installs in database (in a single transaction for efficiency)
+        let timings = InstallTimings {
+            resolve: resolve_time,
+            execute: spans.now(),
+            packages: package_timings.into_iter().flatten().collect(),
+            spans: spans.into_spans(),
+        };
+        if let Err(e) = self.record_processed(completed, &root_name, &timings, &pours) {
+            self.undo_install(journal);
+            return Err(e);
+        }
+        self.post_install_all(to_install.iter().map(|(formula, _)| {
+            let keg_path = self
+                .cellar
+                .keg_path(&formula.name, &formula.effective_version());
+            (formula, keg_path)
+        }));
+
+        Ok(ExecuteResult {
+            installed: to_install.len(),
+            built: Vec::new(),
+            timings,
+        })
+    }
+
+    /// Record all successful installs in database (in a single transaction for efficiency)
+    fn record_processed(
+        &mut self,
+        completed: Vec<Option<ProcessedPackage>>,
+        root_name: &str,
+        timings: &InstallTimings,
+        pours: &[PourEvent],
+    ) -> Result<(), Error> {
         let tx = self.db.transaction()?;
         for processed in completed.into_iter().flatten() {
             tx.record_install(
@@ -172,6 +503,11 @@ impl Installer {
                 &processed.store_key,
                 processed.explicit,
             )?;
+            tx.record_link_state(&processed.name, processed.linked, processed.keg_only)?;
+            tx.record_dependencies(&processed.name, &processed.dependencies)?;
+            tx.record_provenance(&processed.name, &processed.provenance)?;
+            tx.record_receipt(&processed.name, &processed.receipt)?;
+            tx.record_config_files(&processed.name, &processed.config_files)?;
 
             for linked in &processed.linked_files {
                 tx.record_linked_file(
@@ -182,11 +518,10 @@ impl Installer {
                 )?;
             }
         }
-        tx.commit()?;
 
-        Ok(ExecuteResult {
-            installed: to_install.len(),
-        })
+        tx.record_install_timings(root_name, timings)?;
+        tx.record_pour_events(pours)?;
+        tx.commit()
     }
 
     /// Convenience method to plan and execute in one call
@@ -195,16 +530,24 @@ impl Installer {
         self.execute(plan, link).await
     }
 
-    /// Uninstall a formula
+    /// Uninstall a formula, removing every installed version
     pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
+        let _lock = self.store.lock_formula(name)?;
+        self.uninstall_locked(name)
+    }
+
+    /// Uninstall with the formula lock already held
+    fn uninstall_locked(&mut self, name: &str) -> Result<(), Error> {
         // Check if installed
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
+        let versions = self.db.list_versions(name)?;
 
-        // Unlink executables
+        // Unlink executables and put back anything `link --overwrite` displaced
         let keg_path = self.cellar.keg_path(name, &installed.version);
         self.linker.unlink_keg(&keg_path)?;
+        self.restore_backups(name)?;
 
         // Remove from database (decrements store ref)
         {
@@ -213,13 +556,195 @@ impl Installer {
             tx.commit()?;
         }
 
-        // Remove cellar entry
+        // Remove cellar entries
         self.cellar.remove_keg(name, &installed.version)?;
+        for version in versions {
+            self.cellar.remove_keg(name, &version.version)?;
+        }
 
         Ok(())
     }
 
+    /// Uninstall a single version of a formula.
+    /// The active version can only be removed if it is the last one left.
+    pub fn uninstall_version(&mut self, name: &str, version: &str) -> Result<(), Error> {
+        let _lock = self.store.lock_formula(name)?;
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let versions = self.db.list_versions(name)?;
+
+        if !versions.iter().any(|v| v.version == version) {
+            return Err(Error::NotInstalled {
+                name: format!("{name} {version}"),
+            });
+        }
+
+        if installed.version == version {
+            if versions.len() == 1 {
+                return self.uninstall_locked(name);
+            }
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "{name} {version} is the active version; run `zb switch {name} <version>` first"
+                ),
+            });
+        }
+
+        {
+            let tx = self.db.transaction()?;
+            tx.record_uninstall_version(name, version)?;
+            tx.commit()?;
+        }
+
+        self.cellar.remove_keg(name, version)
+    }
+
+    /// Remove kegs for versions that are installed but not active.
+    /// Their store entries become eligible for garbage collection.
+    pub fn prune_inactive_versions(&mut self) -> Result<Vec<KegVersion>, Error> {
+        let inactive = self.db.list_inactive_versions()?;
+
+        for keg in &inactive {
+            let tx = self.db.transaction()?;
+            tx.record_uninstall_version(&keg.name, &keg.version)?;
+            tx.commit()?;
+
+            self.cellar.remove_keg(&keg.name, &keg.version)?;
+        }
+
+        Ok(inactive)
+    }
+
     /// Try to extract a download, with automatic retry on corruption
+    /// Compare a materialized keg against the manifest recorded when its store
+    /// entry was extracted. A keg that doesn't match is removed so it can't be
+    /// linked by a later run.
+    pub(crate) fn verify_keg(
+        &self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+        keg_path: &Path,
+    ) -> Result<(), Error> {
+        let mismatches = self.keg_mismatches(name, version, store_key, keg_path)?;
+        let Some(first) = mismatches.first() else {
+            return Ok(());
+        };
+
+        let _ = self.cellar.remove_keg(name, version);
+        Err(Error::StoreCorruption {
+            message: format!(
+                "verification of {name} {version} failed: {} file(s) differ from the store manifest (first: {})",
+                mismatches.len(),
+                first.path()
+            ),
+        })
+    }
+
+    /// Files of a materialized keg that differ from the manifest recorded
+    /// when its store entry was extracted
+    fn keg_mismatches(
+        &self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+        keg_path: &Path,
+    ) -> Result<Vec<ManifestMismatch>, Error> {
+        let store_entry = self.store.entry_path(store_key);
+        let content = find_bottle_content(&store_entry, name, version)?;
+        let prefix = content.strip_prefix(&store_entry).unwrap_or(Path::new(""));
+
+        Ok(self
+            .store
+            .manifest(store_key)?
+            .subtree(prefix)
+            .verify(keg_path))
+    }
+
+    /// Re-check an installed keg against the hashes recorded for its store
+    /// entry, along with the attestation recorded for its bottle. Unlike
+    /// `--verify` installs, a keg that doesn't match is left in place.
+    pub fn verify_installed(&self, name: &str) -> Result<KegVerification, Error> {
+        let keg = self
+            .get_installed(name)
+            .ok_or_else(|| Error::NotInstalled {
+                name: name.to_string(),
+            })?;
+        let keg_path = self.cellar.keg_path(&keg.name, &keg.version);
+        let mismatches = self.keg_mismatches(&keg.name, &keg.version, &keg.store_key, &keg_path)?;
+
+        Ok(KegVerification {
+            attestation: self.db.attestation(&keg.store_key),
+            name: keg.name,
+            version: keg.version,
+            store_key: keg.store_key,
+            mismatches,
+        })
+    }
+
+    /// Check the build provenance attestation of the bottle a store entry
+    /// was extracted from, and record the result for the entry. Only a failed
+    /// check is an error: bottles nothing attests, or that can't be checked
+    /// because `gh` is missing, install with a warning.
+    pub(crate) async fn check_attestation(
+        &self,
+        formula: &Formula,
+        bottle: &SelectedBottle,
+    ) -> Result<(), Error> {
+        if self
+            .db
+            .attestation(&bottle.sha256)
+            .is_some_and(|a| a.status == AttestationStatus::Verified)
+        {
+            return Ok(());
+        }
+
+        let is_core = formula.tap.as_deref().is_none_or(|tap| tap == CORE_TAP);
+        let blob = self.blob_cache.blob_path(&bottle.sha256);
+        let attestation = match &self.attestation_verifier {
+            _ if !is_core => {
+                Attestation::unavailable(None, "only homebrew/core attests its bottles")
+            }
+            None => Attestation::unavailable(Some(CORE_ATTESTATION_REPO), "gh is not installed"),
+            Some(_) if !blob.exists() => Attestation::unavailable(
+                Some(CORE_ATTESTATION_REPO),
+                "the bottle is no longer in the download cache",
+            ),
+            Some(verifier) => {
+                let verifier = verifier.clone();
+                tokio::task::spawn_blocking(move || verifier.verify(&blob, CORE_ATTESTATION_REPO))
+                    .await
+                    .map_err(|e| Error::StoreCorruption {
+                        message: format!("attestation task failed: {e}"),
+                    })?
+            }
+        };
+        let _ = self.db.record_attestation(&bottle.sha256, &attestation);
+
+        let detail = attestation.detail.as_deref().unwrap_or("unknown error");
+        match attestation.status {
+            AttestationStatus::Verified => Ok(()),
+            AttestationStatus::Unavailable => {
+                self.context.logger.warn(format!(
+                    "could not check the attestation of {} {}: {}",
+                    formula.name,
+                    formula.effective_version(),
+                    detail
+                ));
+                Ok(())
+            }
+            AttestationStatus::Failed => Err(Error::StoreCorruption {
+                message: format!(
+                    "attestation of {} {} failed: {}",
+                    formula.name,
+                    formula.effective_version(),
+                    detail
+                ),
+            }),
+        }
+    }
+
     pub(crate) async fn extract_with_retry(
         &self,
         download: &DownloadResult,
@@ -231,9 +756,28 @@ impl Installer {
         let mut last_error = None;
 
         for attempt in 0..MAX_CORRUPTION_RETRIES {
-            match self.store.ensure_entry(&bottle.sha256, &blob_path) {
+            let store = self.store.clone();
+            let store_key = bottle.sha256.clone();
+            let path = blob_path.clone();
+            let ingested =
+                tokio::task::spawn_blocking(move || store.ensure_entry(&store_key, &path))
+                    .await
+                    .map_err(|e| Error::StoreCorruption {
+                        message: format!("extraction task failed: {e}"),
+                    })
+                    .and_then(|result| result);
+            match ingested {
                 Ok(entry) => return Ok(entry),
                 Err(Error::StoreCorruption { message }) => {
+                    let bytes = std::fs::metadata(&blob_path).map(|m| m.len()).unwrap_or(0);
+                    self.log_integrity_failure(
+                        PourEventKind::CorruptArchive,
+                        formula,
+                        bottle,
+                        bytes,
+                        message.clone(),
+                    );
+
                     // Remove the corrupted blob
                     self.downloader.remove_blob(&bottle.sha256);
 
@@ -287,8 +831,35 @@ impl Installer {
         }))
     }
 
-    /// Garbage collect unreferenced store entries
+    /// Record a bottle that failed an integrity check in the pour log.
+    /// Logging is best effort and never fails the install.
+    fn log_integrity_failure(
+        &self,
+        kind: PourEventKind,
+        formula: &Formula,
+        bottle: &SelectedBottle,
+        bytes: u64,
+        detail: String,
+    ) {
+        let _ = self.db.record_pour_event(&PourEvent {
+            at: 0, // Set when recorded
+            kind,
+            name: formula.name.clone(),
+            version: formula.effective_version(),
+            url: bottle.url.clone(),
+            sha256: bottle.sha256.clone(),
+            bytes,
+            duration_ms: 0,
+            relocated: false,
+            detail: Some(detail),
+        });
+    }
+
+    /// Garbage collect inactive kegs, unreferenced store entries and the
+    /// pooled files only they used
     pub fn gc(&mut self) -> Result<Vec<String>, Error> {
+        self.prune_inactive_versions()?;
+
         let unreferenced = self.db.get_unreferenced_store_keys()?;
         let mut removed = Vec::new();
 
@@ -297,6 +868,14 @@ impl Installer {
             removed.push(store_key);
         }
 
+        // Files the removed entries shared through the pool are freed once
+        // nothing else links to them
+        self.store
+            .prune_files()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to prune shared files: {e}"),
+            })?;
+
         Ok(removed)
     }
 
@@ -372,6 +951,15 @@ impl Installer {
         result.temp_files_removed += temp_dirs;
         result.bytes_freed += temp_dir_bytes;
 
+        let (temp_kegs, temp_keg_bytes) =
+            self.cellar
+                .cleanup_temp_dirs()
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to cleanup temp kegs: {e}"),
+                })?;
+        result.temp_files_removed += temp_kegs;
+        result.bytes_freed += temp_keg_bytes;
+
         // 6. Clean up stale lock files
         let locks_removed =
             self.store
@@ -397,7 +985,11 @@ impl Installer {
 
     /// Execute an empty install plan (no-op convenience method for testing)
     pub async fn execute_empty(&mut self) -> Result<ExecuteResult, Error> {
-        Ok(ExecuteResult { installed: 0 })
+        Ok(ExecuteResult {
+            installed: 0,
+            built: Vec::new(),
+            timings: InstallTimings::default(),
+        })
     }
 
     /// Preview what would be cleaned up (dry run)
@@ -470,6 +1062,7 @@ mod tests {
     use crate::api::ApiClient;
     use crate::blob::BlobCache;
     use crate::db::Database;
+    use crate::install::ConcurrencyLimits;
     use crate::link::Linker;
     use crate::materialize::Cellar;
     use crate::store::Store;
@@ -562,9 +1155,7 @@ mod tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         )
     }
 
@@ -580,6 +1171,9 @@ mod tests {
             formulas: vec![],
             bottles: vec![],
             root_name: "empty".to_string(),
+            roots: vec!["empty".to_string()],
+            resolve_time: std::time::Duration::ZERO,
+            source_builds: Vec::new(),
         };
 
         let result = installer.execute(plan, true).await;
@@ -690,6 +1284,60 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn execute_extracts_on_bounded_worker_pool() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        let deps: Vec<String> = (1..=5).map(|i| format!("dep{i}")).collect();
+        for name in deps.iter().map(String::as_str).chain(["root"]) {
+            let bottle = create_bottle_tarball(name);
+            let formula_deps = if name == "root" { deps.clone() } else { vec![] };
+            let json = serde_json::json!({
+                "name": name,
+                "versions": { "stable": "1.0.0" },
+                "dependencies": formula_deps,
+                "bottle": { "stable": { "files": { tag: {
+                    "url": format!("{}/bottles/{name}.tar.gz", mock_server.uri()),
+                    "sha256": sha256_hex(&bottle),
+                }}}},
+            });
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut installer = create_test_installer_for_executor(&mock_server, &tmp);
+        installer.set_concurrency_limits(ConcurrencyLimits {
+            downloads: 4,
+            extractions: 2,
+        });
+        assert_eq!(installer.concurrency_limits().extractions, 2);
+
+        let result = installer.install("root", true).await.unwrap();
+
+        assert_eq!(result.installed, 6);
+        let extracts = result
+            .timings
+            .spans
+            .iter()
+            .filter(|s| s.phase == Phase::Extract)
+            .count();
+        assert_eq!(extracts, 6);
+        for name in deps.iter().map(String::as_str).chain(["root"]) {
+            assert!(installer.is_installed(name), "{name} not installed");
+            assert!(tmp.path().join("homebrew/bin").join(name).exists());
+        }
+    }
+
     #[tokio::test]
     async fn uninstall_not_installed_returns_error() {
         let mock_server = MockServer::start().await;
@@ -0,0 +1,131 @@
+//! Rollback of partially executed install plans
+//!
+//! While `execute_with_progress` works through a plan it records every change
+//! it makes outside the database: store entries it extracted, kegs it
+//! materialized, configs it copied into `etc/` and kegs it linked, fully or
+//! only into `opt/`. Database records are only written once
+//! every package has been processed, in a single transaction, so when any
+//! step fails the journal is replayed backwards and the prefix, Cellar and
+//! store end up as they were before the install started.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use zb_core::Error;
+
+use super::Installer;
+
+#[derive(Debug)]
+enum Action {
+    /// A store entry that did not exist before the install
+    StoreIngest { store_key: String },
+    /// A keg that did not exist before the install
+    Materialize { name: String, version: String },
+    /// A config copied into `etc/` where there was none
+    Config { path: PathBuf },
+    /// `keg` was linked, replacing the links of `previous` if set
+    Link {
+        keg: PathBuf,
+        previous: Option<PathBuf>,
+    },
+    /// Only `opt/` was pointed at `keg`, away from `previous` if set
+    Opt {
+        keg: PathBuf,
+        previous: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::StoreIngest { store_key } => write!(f, "store entry {store_key}"),
+            Action::Materialize { name, version } => write!(f, "keg {name} {version}"),
+            Action::Config { path } => write!(f, "config {}", path.display()),
+            Action::Link { keg, .. } => write!(f, "links to {}", keg.display()),
+            Action::Opt { keg, .. } => write!(f, "opt link to {}", keg.display()),
+        }
+    }
+}
+
+/// Actions taken by one install, in the order they happened
+#[derive(Debug, Default)]
+pub(crate) struct InstallJournal {
+    actions: Vec<Action>,
+}
+
+impl InstallJournal {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn ingested(&mut self, store_key: &str) {
+        self.actions.push(Action::StoreIngest {
+            store_key: store_key.to_string(),
+        });
+    }
+
+    pub(crate) fn materialized(&mut self, name: &str, version: &str) {
+        self.actions.push(Action::Materialize {
+            name: name.to_string(),
+            version: version.to_string(),
+        });
+    }
+
+    pub(crate) fn configured(&mut self, path: PathBuf) {
+        self.actions.push(Action::Config { path });
+    }
+
+    pub(crate) fn linked(&mut self, keg: PathBuf, previous: Option<PathBuf>) {
+        self.actions.push(Action::Link { keg, previous });
+    }
+
+    pub(crate) fn opt_linked(&mut self, keg: PathBuf, previous: Option<PathBuf>) {
+        self.actions.push(Action::Opt { keg, previous });
+    }
+}
+
+impl Installer {
+    /// Undo the actions in `journal`, newest first.
+    ///
+    /// Every action is attempted even if an earlier one could not be undone;
+    /// failures are reported as warnings since the install error that caused
+    /// the rollback is the one returned to the caller.
+    pub(crate) fn undo_install(&self, journal: InstallJournal) {
+        for action in journal.actions.into_iter().rev() {
+            let result = match &action {
+                Action::Link {
+                    keg,
+                    previous: Some(previous),
+                } => self.linker.replace_keg(keg, previous).map(|_| ()),
+                Action::Link {
+                    keg,
+                    previous: None,
+                } => self.linker.unlink_keg(keg).map(|_| ()),
+                Action::Opt {
+                    previous: Some(previous),
+                    ..
+                } => self.linker.link_opt(previous),
+                Action::Opt {
+                    keg,
+                    previous: None,
+                } => self.linker.unlink_opt(keg),
+                Action::Materialize { name, version } => self.cellar.remove_keg(name, version),
+                Action::Config { path } => {
+                    std::fs::remove_file(path).map_err(|e| Error::StoreCorruption {
+                        message: format!("failed to remove {}: {e}", path.display()),
+                    })
+                }
+                // Another process may have installed something from the same
+                // bottle in the meantime
+                Action::StoreIngest { store_key } if self.db.get_store_refcount(store_key) > 0 => {
+                    Ok(())
+                }
+                Action::StoreIngest { store_key } => self.store.remove_entry(store_key),
+            };
+
+            if let Err(e) = result {
+                eprintln!("    Warning: failed to roll back {action}: {e}");
+            }
+        }
+    }
+}
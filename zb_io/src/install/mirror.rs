@@ -0,0 +1,312 @@
+//! Mirror creation for air-gapped installs
+//!
+//! Fetches formula metadata and bottles for a set of formulas and their
+//! dependencies, and writes them in the layout described in `crate::mirror`.
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::api::{FormulaInfo, FormulaVersions};
+use crate::download::DownloadRequest;
+use crate::mirror;
+use crate::tap::TapFormula;
+
+use zb_core::{Error, Formula, select_bottle_with_tag};
+
+use super::Installer;
+
+/// Result of creating a mirror
+#[derive(Debug, Default)]
+pub struct MirrorResult {
+    /// Formulas written to the mirror, including dependencies
+    pub formulas: Vec<String>,
+    /// Bottles written to the mirror (paths inside the mirror directory)
+    pub bottles: Vec<PathBuf>,
+    /// Formula/platform pairs with no bottle available
+    pub missing_bottles: Vec<(String, String)>,
+    /// Requested names that could not be mirrored, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+impl Installer {
+    /// Write metadata and bottles for `names` and their dependencies to `dir`.
+    ///
+    /// `platforms` are bottle tags (e.g. `arm64_sonoma`, `x86_64_linux`). When
+    /// empty, the bottle this machine would install is mirrored.
+    pub async fn create_mirror(
+        &self,
+        dir: &Path,
+        names: &[String],
+        platforms: &[String],
+    ) -> Result<MirrorResult, Error> {
+        let mut result = MirrorResult::default();
+        let include_linux_deps = platforms.is_empty() && cfg!(target_os = "linux")
+            || platforms.iter().any(|p| p.ends_with("_linux"));
+
+        let formulas = self
+            .fetch_mirror_closure(names, include_linux_deps, &mut result)
+            .await?;
+
+        for (name, formula) in &formulas {
+            write_json(&mirror::formula_path(dir, name), formula)?;
+            // Aliases requested by name get their own metadata file but are
+            // otherwise mirrored under the formula's real name
+            if *name != formula.name {
+                continue;
+            }
+            result.formulas.push(formula.name.clone());
+
+            for (tag, file) in
+                mirror_bottles(formula, platforms, self.bottle_tag.as_deref(), &mut result)
+            {
+                let target = mirror::bottles_dir(dir).join(mirror::bottle_path(&file.url));
+                if !file_matches(&target, &file.sha256) {
+                    let blob = self
+                        .downloader
+                        .download_single(
+                            DownloadRequest {
+                                url: file.url.clone(),
+                                sha256: file.sha256.clone(),
+                                name: format!("{} ({})", formula.name, tag),
+                            },
+                            None,
+                        )
+                        .await?;
+                    copy_file(&blob, &target)?;
+                }
+                if !result.bottles.contains(&target) {
+                    result.bottles.push(target);
+                }
+            }
+        }
+
+        // Merge with any formulas already in the mirror so it can be built up
+        // over several runs
+        let list_path = mirror::formula_list_path(dir);
+        let mut list: BTreeMap<String, FormulaInfo> = fs::read(&list_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Vec<FormulaInfo>>(&data).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|info| (info.name.clone(), info))
+            .collect();
+        for (name, formula) in &formulas {
+            if *name == formula.name {
+                list.insert(formula.name.clone(), formula_info(formula));
+            }
+        }
+        write_json(&list_path, &list.into_values().collect::<Vec<_>>())?;
+
+        if !platforms.is_empty() {
+            let mut recorded = read_mirror_platforms(dir);
+            for platform in platforms {
+                if !recorded.contains(platform) {
+                    recorded.push(platform.clone());
+                }
+            }
+            write_json(&mirror::platforms_path(dir), &recorded)?;
+        }
+
+        Ok(result)
+    }
+
+    /// Refresh a mirror from upstream.
+    ///
+    /// Every formula already in the mirror is fetched again, along with
+    /// `names`, so new versions and their bottles are added. Without
+    /// `platforms`, the tags the mirror was created with are used.
+    pub async fn sync_mirror(
+        &self,
+        dir: &Path,
+        names: &[String],
+        platforms: &[String],
+    ) -> Result<MirrorResult, Error> {
+        let list_path = mirror::formula_list_path(dir);
+        let existing: Vec<FormulaInfo> = match fs::read(&list_path) {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to parse {}: {e}", list_path.display()),
+            })?,
+            Err(_) if !names.is_empty() => Vec::new(),
+            Err(_) => {
+                return Err(Error::StoreCorruption {
+                    message: format!(
+                        "{} is not a zerobrew mirror (create one with `zb mirror create`)",
+                        dir.display()
+                    ),
+                });
+            }
+        };
+
+        let mut all_names: Vec<String> = existing.into_iter().map(|info| info.name).collect();
+        for name in names {
+            if !all_names.contains(name) {
+                all_names.push(name.clone());
+            }
+        }
+
+        let platforms = if platforms.is_empty() {
+            read_mirror_platforms(dir)
+        } else {
+            platforms.to_vec()
+        };
+
+        self.create_mirror(dir, &all_names, &platforms).await
+    }
+
+    /// Fetch the requested formulas and everything they depend on, keyed by
+    /// the name they were requested under
+    async fn fetch_mirror_closure(
+        &self,
+        names: &[String],
+        include_linux_deps: bool,
+        result: &mut MirrorResult,
+    ) -> Result<BTreeMap<String, Formula>, Error> {
+        let mut formulas = BTreeMap::new();
+        let mut queued: HashSet<String> = HashSet::new();
+        let mut pending: VecDeque<(String, bool)> = VecDeque::new();
+
+        for name in names {
+            if TapFormula::parse(name).is_some() {
+                result
+                    .skipped
+                    .push((name.clone(), "tap formulas cannot be mirrored".to_string()));
+            } else if queued.insert(name.clone()) {
+                pending.push_back((name.clone(), true));
+            }
+        }
+
+        while let Some((name, requested)) = pending.pop_front() {
+            let formula = match self.api_client.get_formula(&name).await {
+                Ok(formula) => formula,
+                Err(Error::MissingFormula { .. }) if !requested => continue,
+                Err(Error::MissingFormula { .. }) => {
+                    result.skipped.push((name, "formula not found".to_string()));
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let mut deps = formula.dependencies.clone();
+            if include_linux_deps {
+                deps.extend(formula.uses_from_macos.iter().cloned());
+            }
+            for dep in deps {
+                if queued.insert(dep.clone()) {
+                    pending.push_back((dep, false));
+                }
+            }
+
+            if name != formula.name {
+                formulas.insert(name, formula.clone());
+                if !queued.insert(formula.name.clone()) {
+                    continue;
+                }
+            }
+            formulas.insert(formula.name.clone(), formula);
+        }
+
+        Ok(formulas)
+    }
+}
+
+/// Pick the bottle files to mirror for each requested platform
+fn mirror_bottles(
+    formula: &Formula,
+    platforms: &[String],
+    bottle_tag: Option<&str>,
+    result: &mut MirrorResult,
+) -> Vec<(String, zb_core::formula::BottleFile)> {
+    if platforms.is_empty() {
+        return match select_bottle_with_tag(formula, bottle_tag) {
+            Ok(bottle) => vec![(
+                bottle.tag,
+                zb_core::formula::BottleFile {
+                    url: bottle.url,
+                    sha256: bottle.sha256,
+                },
+            )],
+            Err(_) => {
+                result
+                    .missing_bottles
+                    .push((formula.name.clone(), "this platform".to_string()));
+                Vec::new()
+            }
+        };
+    }
+
+    let files = &formula.bottle.stable.files;
+    platforms
+        .iter()
+        .filter_map(|tag| {
+            let file = files.get(tag).or_else(|| files.get("all"));
+            if file.is_none() {
+                result
+                    .missing_bottles
+                    .push((formula.name.clone(), tag.clone()));
+            }
+            file.map(|f| (tag.clone(), f.clone()))
+        })
+        .collect()
+}
+
+/// Bottle tags recorded for a mirror, empty when it mirrors this platform
+fn read_mirror_platforms(dir: &Path) -> Vec<String> {
+    fs::read(mirror::platforms_path(dir))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn formula_info(formula: &Formula) -> FormulaInfo {
+    FormulaInfo {
+        name: formula.name.clone(),
+        full_name: formula.name.clone(),
+        desc: formula.desc.clone(),
+        homepage: formula.homepage.clone(),
+        versions: FormulaVersions {
+            stable: Some(formula.versions.stable.clone()),
+        },
+        aliases: Vec::new(),
+        deprecated: false,
+        disabled: false,
+        dependencies: formula.dependencies.clone(),
+    }
+}
+
+/// True if `path` exists and has the expected SHA-256
+fn file_matches(path: &Path, sha256: &str) -> bool {
+    fs::read(path).is_ok_and(|data| format!("{:x}", Sha256::digest(&data)) == sha256)
+}
+
+fn write_json<T: serde::Serialize + ?Sized>(path: &Path, value: &T) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create directory '{}': {e}", parent.display()),
+        })?;
+    }
+    let data = serde_json::to_vec_pretty(value).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to serialize {}: {e}", path.display()),
+    })?;
+    fs::write(path, data).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to write '{}': {e}", path.display()),
+    })
+}
+
+fn copy_file(from: &Path, to: &Path) -> Result<(), Error> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create directory '{}': {e}", parent.display()),
+        })?;
+    }
+    fs::copy(from, to).map_err(|e| Error::StoreCorruption {
+        message: format!(
+            "failed to copy '{}' to '{}': {e}",
+            from.display(),
+            to.display()
+        ),
+    })?;
+    Ok(())
+}
@@ -5,39 +5,84 @@
 //!
 //! - `planner` - Install planning and dependency resolution
 //! - `executor` - Download, extraction, and linking orchestration
+//! - `journal` - Rollback of failed installs
 //! - `doctor` - Health check diagnostics
+//! - `drift` - Cellar kegs the database has lost track of, and adopting them
+//! - `mirror` - Static mirror creation for air-gapped installs
 //! - `orphan` - Orphan detection and autoremove logic
+//! - `pack` - Offline pack creation and import
+//! - `post_install` - Running formula `post_install` steps
+//! - `update` - Refreshing formula and tap metadata
 //! - `upgrade` - Upgrade-specific functionality
+//! - `versions` - Available and locally present versions of a formula
 
 mod doctor;
+mod drift;
 mod executor;
+mod journal;
+mod mirror;
 mod orphan;
+mod pack;
 mod planner;
+mod post_install;
+mod shadow;
+mod update;
 mod upgrade;
+mod versions;
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use crate::api::ApiClient;
+use crate::attestation::AttestationVerifier;
 use crate::blob::BlobCache;
-use crate::bundle::{self, BrewfileEntry, BundleCheckResult, BundleInstallResult};
-use crate::db::{Database, InstalledTap};
+use crate::bundle::{self, BrewfileEntry, BundleCheckResult, BundleEnv, BundleInstallResult};
+use crate::bundle_lock::BundleLock;
+use crate::cache::ApiCache;
+use crate::config::{self, ConfigChange, ConfigDiff, ConfigFile};
+use crate::credentials::Credentials;
+use crate::db::{Database, InstalledTap, Provenance};
 use crate::download::ParallelDownloader;
-use crate::link::{LinkedFile, Linker};
+use crate::http_fixtures::HttpMode;
+use crate::index::IndexProvider;
+use crate::link::{DisplacedFile, LinkedFile, Linker, is_env_name};
 use crate::materialize::Cellar;
+use crate::ratelimit::RateLimits;
+use crate::receipt::InstallReceipt;
+use crate::search::{self, SearchOptions, SearchResult};
+use crate::services::ServiceSettings;
 use crate::store::Store;
-use crate::tap::TapManager;
+use crate::tap::{TapFormula, TapManager};
+use crate::tempdir::{self, TempDirGuard};
 
 use std::collections::HashSet;
 
-use zb_core::{Error, Formula};
+use futures::stream::{self, StreamExt};
+use tokio::sync::Semaphore;
+use zb_core::{ApiLayout, Context, Error, Formula, Host};
 
 // Re-export public types
 pub use doctor::{DoctorCheck, DoctorResult, DoctorStatus};
-pub use executor::ExecuteResult;
-pub use orphan::SourceBuildResult;
-pub use planner::InstallPlan;
-pub use upgrade::UpgradeResult;
+pub use drift::{AdoptResult, CellarDrift, DriftedKeg, UntrackedAction};
+pub use executor::{ExecuteResult, KegVerification};
+pub use mirror::MirrorResult;
+pub use orphan::{SourceBuildResult, WhyResult};
+pub use pack::UnpackResult;
+pub use planner::{
+    AvailableBottle, BottleAvailability, DownloadEstimate, InstallPlan, PackageEstimate,
+    PlannedLinkConflict,
+};
+use planner::{CORE_TAP, MAX_CONCURRENT_FETCHES, dependency_names};
+pub use post_install::PostInstallResult;
+pub use shadow::{CommandResolution, ShadowedCommand};
+pub use update::{FormulaChange, UpdateSummary};
+pub use upgrade::{
+    OutdatedGroup, RollbackResult, RollbackSource, UpgradePlan, UpgradeReason, UpgradeResult,
+    UpgradeStep,
+};
+pub use versions::FormulaVersions;
+pub use zb_core::ConcurrencyLimits;
 
 /// Maximum number of retries for corrupted downloads
 const MAX_CORRUPTION_RETRIES: usize = 3;
@@ -60,16 +105,67 @@ pub struct CleanupResult {
 }
 
 /// Dependency tree node for displaying hierarchical dependencies
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DepsTree {
     /// The formula name
     pub name: String,
     /// Whether this formula is installed
     pub installed: bool,
+    /// Versions and keg state, for `zb deps --annotate`
+    pub annotation: DepAnnotation,
     /// Child dependencies
     pub children: Vec<DepsTree>,
 }
 
+/// Versions and keg state of a node in a dependency tree, taken from the
+/// database and the formulas already fetched for the tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepAnnotation {
+    /// Version of the installed keg
+    pub installed_version: Option<String>,
+    /// Version an install would get
+    pub available_version: Option<String>,
+    /// Whether the installed keg is pinned
+    pub pinned: bool,
+    /// Whether the formula is keg-only
+    pub keg_only: bool,
+}
+
+/// Dependency graph with one node per formula, however many packages
+/// depend on it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepsGraph {
+    /// Direct dependencies of every formula in the graph, keyed by name
+    pub edges: BTreeMap<String, Vec<String>>,
+    /// Formulas in the graph that are installed
+    pub installed: BTreeSet<String>,
+}
+
+impl DepsTree {
+    /// Merge the tree into a graph, so a formula reached along several
+    /// paths appears once
+    pub fn to_graph(&self) -> DepsGraph {
+        fn add(tree: &DepsTree, graph: &mut DepsGraph) {
+            if tree.installed {
+                graph.installed.insert(tree.name.clone());
+            }
+            let deps = graph.edges.entry(tree.name.clone()).or_default();
+            for child in &tree.children {
+                if !deps.contains(&child.name) {
+                    deps.push(child.name.clone());
+                }
+            }
+            for child in &tree.children {
+                add(child, graph);
+            }
+        }
+
+        let mut graph = DepsGraph::default();
+        add(self, &mut graph);
+        graph
+    }
+}
+
 /// Result of a link operation
 #[derive(Debug, Clone)]
 pub struct LinkResult {
@@ -79,6 +175,39 @@ pub struct LinkResult {
     pub already_linked: bool,
     /// True if --force was used to link a keg-only formula
     pub keg_only_forced: bool,
+    /// Files moved into the backups directory to make room for links
+    pub backed_up: Vec<DisplacedFile>,
+}
+
+/// An installed keg as `zb list --full` describes it
+#[derive(Debug, Clone)]
+pub struct InventoryEntry {
+    pub keg: crate::db::InstalledKeg,
+    /// Disk used by the keg's files; `None` if they couldn't be measured
+    pub size_bytes: Option<u64>,
+    /// Tap the keg was installed from, e.g. `homebrew/core`
+    pub source: String,
+}
+
+/// Disk used by the content-addressed store compared with giving every
+/// installed keg its own copy
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupeStats {
+    /// Installed kegs (every version) backed by a store entry
+    pub kegs: usize,
+    /// Distinct store entries behind those kegs
+    pub store_entries: usize,
+    /// Size of those store entries
+    pub store_bytes: u64,
+    /// Size if each keg held a private copy of its files
+    pub naive_bytes: u64,
+}
+
+impl DedupeStats {
+    /// Bytes the store saves over per-keg copies
+    pub fn bytes_saved(&self) -> u64 {
+        self.naive_bytes.saturating_sub(self.store_bytes)
+    }
 }
 
 /// Internal struct for tracking processed packages during streaming install
@@ -90,6 +219,13 @@ pub(crate) struct ProcessedPackage {
     pub linked_files: Vec<LinkedFile>,
     /// Whether this package was explicitly requested (true) or a dependency (false)
     pub explicit: bool,
+    pub linked: bool,
+    pub keg_only: bool,
+    pub dependencies: Vec<String>,
+    pub provenance: Provenance,
+    pub receipt: InstallReceipt,
+    /// Default configs copied from the keg's `etc/`
+    pub config_files: Vec<ConfigFile>,
 }
 
 pub struct Installer {
@@ -101,8 +237,31 @@ pub struct Installer {
     pub(crate) linker: Linker,
     pub(crate) db: Database,
     pub(crate) tap_manager: TapManager,
-    pub(crate) prefix: PathBuf,
-    pub(crate) cellar_path: PathBuf,
+    /// Paths, concurrency limits, logger and offline mode
+    pub(crate) context: Context,
+    pub(crate) verify: bool,
+    /// Checks bottles' attestations in verify mode; `None` when `gh` isn't
+    /// installed
+    pub(crate) attestation_verifier: Option<AttestationVerifier>,
+    /// Install formulas upstream has disabled
+    pub(crate) allow_disabled: bool,
+    /// Don't run `post_install` steps of installed formulas
+    pub(crate) skip_post_install: bool,
+    /// Recorded as the provenance of explicitly requested formulas
+    pub(crate) provenance: Provenance,
+    /// Checked against formula platform requirements when planning
+    pub(crate) host: Host,
+    /// Where source builds unpack and compile; the store when unset
+    pub(crate) temp_dir: Option<PathBuf>,
+    /// Bottle tag to select instead of the host's (see `select_bottle_with_tag`)
+    pub(crate) bottle_tag: Option<String>,
+    /// Download bottles again even when they are in the blob cache
+    pub(crate) refetch_bottles: bool,
+    /// Server publishing patches between bottles, for upgrades (see `crate::delta`)
+    pub(crate) delta_url: Option<String>,
+    /// Bounds the extraction worker pool and the downloader's unpacking
+    /// (see `ConcurrencyLimits::extractions`)
+    pub(crate) extract_slots: Arc<Semaphore>,
 }
 
 impl Installer {
@@ -115,34 +274,211 @@ impl Installer {
         linker: Linker,
         db: Database,
         tap_manager: TapManager,
-        prefix: PathBuf,
-        cellar_path: PathBuf,
-        download_concurrency: usize,
+        context: Context,
     ) -> Self {
+        let mut api_client = api_client;
+        api_client.set_offline(context.offline);
+        let mut downloader =
+            ParallelDownloader::new(blob_cache.clone(), context.concurrency.downloads);
+        downloader.set_offline(context.offline);
+        let extract_slots = Arc::new(Semaphore::new(context.concurrency.extractions.max(1)));
+        downloader.set_ingest_store(store.clone(), extract_slots.clone());
+        let mut linker = linker;
+        for (name, env) in db.list_link_env().unwrap_or_default() {
+            linker.set_wrapper_env(&name, env);
+        }
         Self {
             api_client,
-            downloader: ParallelDownloader::new(blob_cache.clone(), download_concurrency),
+            downloader,
             blob_cache,
             store,
             cellar,
             linker,
             db,
             tap_manager,
-            prefix,
-            cellar_path,
+            extract_slots,
+            context,
+            verify: false,
+            attestation_verifier: None,
+            allow_disabled: false,
+            skip_post_install: false,
+            provenance: Provenance::Cli,
+            host: Host::detect(),
+            temp_dir: None,
+            bottle_tag: None,
+            refetch_bottles: false,
+            delta_url: None,
         }
     }
 
+    /// Paths, limits and modes the installer was configured with
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn concurrency_limits(&self) -> ConcurrencyLimits {
+        self.context.concurrency
+    }
+
+    /// Change how many bottles are downloaded and extracted at once
+    pub fn set_concurrency_limits(&mut self, limits: ConcurrencyLimits) {
+        self.downloader.set_concurrency(limits.downloads);
+        self.extract_slots = Arc::new(Semaphore::new(limits.extractions.max(1)));
+        self.downloader
+            .set_ingest_store(self.store.clone(), self.extract_slots.clone());
+        self.context.concurrency = limits;
+    }
+
+    /// Answer from caches only: formulas from the API cache and bottles from
+    /// the blob cache, failing anything that would need the network
+    pub fn set_offline(&mut self, offline: bool) {
+        self.api_client.set_offline(offline);
+        self.downloader.set_offline(offline);
+        self.context.offline = offline;
+    }
+
+    /// Record or replay API responses and bottle downloads
+    pub fn set_http_mode(&mut self, mode: HttpMode) {
+        self.api_client.set_http_mode(mode.clone());
+        self.downloader.set_http_mode(mode);
+    }
+
+    /// Authenticate metadata and bottle fetches from private mirrors
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.api_client.set_credentials(credentials.clone());
+        self.downloader.set_credentials(credentials);
+    }
+
+    /// Share rate-limit cool-downs between metadata and bottle fetches
+    pub fn set_rate_limits(&mut self, rate_limits: RateLimits) {
+        self.api_client.set_rate_limits(rate_limits.clone());
+        self.downloader.set_rate_limits(rate_limits);
+    }
+
+    /// Unpack and compile source builds under `dir` instead of the store,
+    /// e.g. to keep large builds off a small root filesystem. Kegs are still
+    /// assembled inside the Cellar so they are moved into place atomically.
+    pub fn set_temp_dir(&mut self, dir: &Path) -> Result<(), Error> {
+        std::fs::create_dir_all(dir).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create temp directory '{}': {e}", dir.display()),
+        })?;
+        let _ = tempdir::reap_stale(dir);
+        self.temp_dir = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// A scratch directory for builds, removed on drop
+    pub(crate) fn scratch_dir(&self, label: &str) -> Result<TempDirGuard, Error> {
+        match &self.temp_dir {
+            Some(dir) => TempDirGuard::new(dir, label),
+            None => self.store.temp_dir(label),
+        }
+    }
+
+    /// Fetch formula metadata from `api_domain`, laid out as `layout` (see
+    /// `crate::mirror`)
+    pub fn set_api_domain(&mut self, api_domain: &str, layout: ApiLayout) -> Result<(), Error> {
+        crate::mirror::validate_api_domain(api_domain)?;
+        self.api_client
+            .set_provider(IndexProvider::Api(crate::mirror::api_base_url(
+                api_domain, layout,
+            )));
+        Ok(())
+    }
+
+    /// Upgrade from patches published at `delta_url` when the bottle being
+    /// replaced is still cached (see [`crate::delta`])
+    pub fn set_delta_url(&mut self, delta_url: &str) {
+        self.delta_url = Some(delta_url.to_string());
+    }
+
+    /// Download bottles from `bottle_domain` instead of their upstream hosts
+    pub fn set_bottle_domain(&mut self, bottle_domain: &str) {
+        self.api_client
+            .set_bottle_domain(Some(bottle_domain.to_string()));
+    }
+
+    /// Resolve formulas and bottles only from a mirror written by
+    /// `zb mirror create`, either an `http(s)://` URL or a local directory.
+    /// A local mirror needs no network access at all.
+    pub fn set_mirror(&mut self, location: &str) -> Result<(), Error> {
+        let location = location.trim().trim_end_matches('/');
+        if location.starts_with("http://") || location.starts_with("https://") {
+            self.set_api_domain(&format!("{location}/api"), ApiLayout::Homebrew)?;
+            self.set_bottle_domain(&format!("{location}/bottles"));
+            return Ok(());
+        }
+
+        let dir = location.strip_prefix("file://").unwrap_or(location);
+        let dir = std::path::absolute(dir).map_err(|e| Error::StoreCorruption {
+            message: format!("invalid mirror path '{dir}': {e}"),
+        })?;
+        if !crate::mirror::formula_list_path(&dir).exists() {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "{} is not a zerobrew mirror (create one with `zb mirror create`)",
+                    dir.display()
+                ),
+            });
+        }
+        self.set_bottle_domain(&format!(
+            "file://{}",
+            crate::mirror::bottles_dir(&dir).display()
+        ));
+        self.api_client.set_provider(IndexProvider::Mirror(dir));
+        Ok(())
+    }
+
+    /// Select bottles for `tag` (falling back to older macOS releases)
+    /// instead of the host's platform tags
+    pub fn set_bottle_tag(&mut self, tag: &str) {
+        self.bottle_tag = Some(tag.to_string());
+    }
+
+    /// Ignore cached formula metadata and cached bottles, fetching both again.
+    /// What gets fetched replaces the cached copies.
+    pub fn set_no_cache(&mut self) {
+        self.api_client.set_bypass_cache(true);
+        self.refetch_bottles = true;
+    }
+
+    /// Forget the cached metadata for `name` so it is fetched in full.
+    /// Returns whether anything was cached.
+    pub fn refresh_formula(&self, name: &str) -> bool {
+        self.api_client.invalidate_formula(name)
+    }
+
+    /// Re-hash each keg against its store manifest before linking it, and
+    /// check its bottle's attestation
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Verify bottles' attestations with `verifier` in verify mode
+    pub fn set_attestation_verifier(&mut self, verifier: Option<AttestationVerifier>) {
+        self.attestation_verifier = verifier;
+    }
+
+    /// Install formulas upstream has disabled instead of refusing them
+    pub fn set_allow_disabled(&mut self, allow: bool) {
+        self.allow_disabled = allow;
+    }
+
+    /// Set what is requesting the following installs (defaults to the command line)
+    pub fn set_provenance(&mut self, provenance: Provenance) {
+        self.provenance = provenance;
+    }
+
     // ========== Query Methods ==========
 
     /// Check if a formula is installed
     pub fn is_installed(&self, name: &str) -> bool {
-        self.db.get_installed(name).is_some()
+        self.get_installed(name).is_some()
     }
 
-    /// Get info about an installed formula
+    /// Get info about an installed formula, also by its `user/repo/formula` name
     pub fn get_installed(&self, name: &str) -> Option<crate::db::InstalledKeg> {
-        self.db.get_installed(name)
+        self.db.get_installed(TapFormula::formula_name(name))
     }
 
     /// List all installed formulas
@@ -150,19 +486,172 @@ impl Installer {
         self.db.list_installed()
     }
 
+    /// List every installed version of a formula, oldest first
+    pub fn list_versions(&self, name: &str) -> Result<Vec<crate::db::KegVersion>, Error> {
+        self.db.list_versions(name)
+    }
+
+    /// List installed versions that are not active
+    pub fn list_inactive_versions(&self) -> Result<Vec<crate::db::KegVersion>, Error> {
+        self.db.list_inactive_versions()
+    }
+
     /// Get API client reference for external use (e.g., outdated checks)
     pub fn api_client(&self) -> &ApiClient {
         &self.api_client
     }
 
+    /// Search the formula list for `query`, best match first
+    ///
+    /// Plain text or a `/regex/`; see [`crate::search::search`] for how
+    /// matches are scored and `options` applied.
+    pub async fn search(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>, Error> {
+        // The formula list cache isn't keyed by source, so only use it for the default index
+        let provider = self.api_client.provider().clone();
+        let cache = ApiCache::open(&self.context.paths.cache).ok();
+        let api_client = match cache.filter(|_| provider == IndexProvider::default()) {
+            Some(cache) => ApiClient::with_provider(provider).with_cache(cache),
+            None => ApiClient::with_provider(provider),
+        }
+        .with_credentials(self.api_client.credentials().clone());
+
+        let formulas = api_client.get_all_formulas().await?;
+        let installed: HashSet<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect();
+        Ok(search::search(&formulas, query, options, &installed))
+    }
+
+    /// Summarize the recorded install timing history
+    pub fn install_stats(&self) -> Result<crate::db::InstallStats, Error> {
+        self.db.install_stats()
+    }
+
+    /// Delete the recorded install timing history and API cache hit counts
+    pub fn clear_install_stats(&self) -> Result<usize, Error> {
+        self.api_client.clear_lookup_stats();
+        self.db.clear_install_stats()
+    }
+
+    /// Installed and upgraded package counts per month
+    pub fn install_activity_by_month(&self) -> Result<Vec<crate::db::MonthlyActivity>, Error> {
+        self.db.install_activity_by_month()
+    }
+
+    /// The most recent `limit` entries of the bottle pour log, newest first
+    pub fn pour_events(&self, limit: usize) -> Result<Vec<crate::db::PourEvent>, Error> {
+        self.db.pour_events(limit)
+    }
+
+    /// Totals over the bottle pour log
+    pub fn pour_stats(&self) -> Result<crate::db::PourStats, Error> {
+        self.db.pour_stats()
+    }
+
+    /// Formula API lookups served from the HTTP cache, as (hits, misses).
+    /// None when the API cache is disabled.
+    pub fn api_lookup_stats(&self) -> Option<(u64, u64)> {
+        self.api_client.lookup_stats()
+    }
+
+    /// Installed kegs with their sizes and sources, in `sort` order. Sizes
+    /// are measured once per store entry and then read from the database.
+    pub fn inventory(&self, sort: crate::db::InventorySort) -> Result<Vec<InventoryEntry>, Error> {
+        for keg in self.db.list_unsized_kegs()? {
+            // Source builds have no store entry, so measure their keg
+            let path = if self.store.has_entry(&keg.store_key) {
+                self.store.entry_path(&keg.store_key)
+            } else {
+                self.cellar.keg_path(&keg.name, &keg.version)
+            };
+            if !path.is_dir() {
+                continue;
+            }
+            let bytes =
+                crate::dedup::disk_usage([path.as_path()]).map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to measure {}: {e}", path.display()),
+                })?;
+            self.db.record_store_size(&keg.store_key, bytes)?;
+        }
+
+        Ok(self
+            .db
+            .list_inventory(sort)?
+            .into_iter()
+            .map(|(keg, size_bytes)| InventoryEntry {
+                source: upgrade::keg_source(&keg).to_string(),
+                keg,
+                size_bytes,
+            })
+            .collect())
+    }
+
+    /// Measure how much disk the store's deduplication saves
+    pub fn dedupe_stats(&self) -> Result<DedupeStats, Error> {
+        let mut stats = DedupeStats::default();
+        let mut sizes: BTreeMap<String, u64> = BTreeMap::new();
+
+        for keg in self.db.list_all_versions()? {
+            // Source builds have no store entry to share
+            if !self.store.has_entry(&keg.store_key) {
+                continue;
+            }
+            let size = match sizes.get(&keg.store_key) {
+                Some(size) => *size,
+                None => {
+                    let size = self.store.entry_size(&keg.store_key).map_err(|e| {
+                        Error::StoreCorruption {
+                            message: format!("failed to measure store entry: {e}"),
+                        }
+                    })?;
+                    sizes.insert(keg.store_key.clone(), size);
+                    size
+                }
+            };
+            stats.kegs += 1;
+            stats.naive_bytes += size;
+        }
+
+        stats.store_entries = sizes.len();
+        // Files shared between entries through the file pool count once
+        stats.store_bytes = self
+            .store
+            .entries_disk_usage(sizes.keys().map(String::as_str))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to measure store entries: {e}"),
+            })?;
+        Ok(stats)
+    }
+
+    /// Record the result of a benchmark run
+    pub fn record_benchmark(&self, record: &crate::db::BenchmarkRecord) -> Result<(), Error> {
+        self.db.record_benchmark(record)
+    }
+
+    /// List previous benchmark runs for a formula, most recent first
+    pub fn benchmark_history(
+        &self,
+        formula: &str,
+        limit: usize,
+    ) -> Result<Vec<crate::db::BenchmarkRecord>, Error> {
+        self.db.list_benchmarks(formula, limit)
+    }
+
     /// Get linked files for a package
     pub fn get_linked_files(&self, name: &str) -> Result<Vec<(String, String)>, Error> {
-        self.db.get_linked_files(name)
+        self.db.get_linked_files(TapFormula::formula_name(name))
     }
 
-    /// Get formula info from API
+    /// Get formula info, resolved the way `install` would resolve it
     pub async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
-        self.api_client.get_formula(name).await
+        self.fetch_formula(name).await
     }
 
     /// Get installed packages that depend on a given package (reverse dependencies)
@@ -172,15 +661,16 @@ impl Installer {
         let mut dependents = Vec::new();
 
         // For each installed package, check if it depends on the target
+        let name = TapFormula::formula_name(name);
         for keg in &installed {
             if keg.name == name {
                 continue;
             }
 
             // Fetch the formula to get its dependencies
-            if let Ok(formula) = self.api_client.get_formula(&keg.name).await {
+            if let Ok(formula) = self.installed_formula(keg).await {
                 let deps = formula.effective_dependencies();
-                if deps.iter().any(|d| d == name) {
+                if deps.iter().any(|d| TapFormula::formula_name(d) == name) {
                     dependents.push(keg.name.clone());
                 }
             }
@@ -208,8 +698,8 @@ impl Installer {
             // Get all transitive dependencies
             let formulas = self.fetch_all_formulas(name).await?;
             let mut deps = zb_core::resolve_closure(name, &formulas)?;
-            // Remove the package itself
-            deps.retain(|n| n != name);
+            // Remove the package itself, named as the formula if `name` is an alias
+            deps.pop();
 
             if installed_only {
                 deps.retain(|n| self.is_installed(n));
@@ -239,32 +729,41 @@ impl Installer {
             name: &str,
             formulas: &BTreeMap<String, Formula>,
             installed_only: bool,
-            is_installed: &dyn Fn(&str) -> bool,
+            get_installed: &dyn Fn(&str) -> Option<crate::db::InstalledKeg>,
             visited: &mut std::collections::HashSet<String>,
         ) -> DepsTree {
-            let installed = is_installed(name);
+            let keg = get_installed(name);
+            let formula = formulas.get(name);
+            let annotation = DepAnnotation {
+                installed_version: keg.as_ref().map(|k| k.version.clone()),
+                available_version: formula.map(|f| f.effective_version()),
+                pinned: keg.as_ref().is_some_and(|k| k.pinned),
+                keg_only: formula.map_or(keg.as_ref().is_some_and(|k| k.keg_only), |f| f.keg_only),
+            };
+            let installed = keg.is_some();
 
             // Check for cycles
             if visited.contains(name) {
                 return DepsTree {
                     name: name.to_string(),
                     installed,
+                    annotation,
                     children: Vec::new(),
                 };
             }
             visited.insert(name.to_string());
 
             // Get dependencies
-            let children = if let Some(formula) = formulas.get(name) {
+            let children = if let Some(formula) = formula {
                 let deps = formula.effective_dependencies();
                 deps.into_iter()
-                    .filter(|dep| !installed_only || is_installed(dep))
+                    .filter(|dep| !installed_only || get_installed(dep).is_some())
                     .map(|dep| {
                         build_tree_from_formula(
                             &dep,
                             formulas,
                             installed_only,
-                            is_installed,
+                            get_installed,
                             visited,
                         )
                     })
@@ -279,24 +778,58 @@ impl Installer {
             DepsTree {
                 name: name.to_string(),
                 installed,
+                annotation,
                 children,
             }
         }
 
-        let is_installed = |n: &str| self.is_installed(n);
+        let get_installed = |n: &str| self.get_installed(n);
         let mut visited = std::collections::HashSet::new();
         Ok(build_tree_from_formula(
             name,
             &formulas,
             installed_only,
-            &is_installed,
+            &get_installed,
             &mut visited,
         ))
     }
 
+    /// Get a dependency graph for a formula's closure, or for every
+    /// installed package when `name` is `None`.
+    ///
+    /// The installed graph uses the edges recorded at install time, like
+    /// [`Installer::dependency_graph`].
+    pub async fn get_deps_graph(
+        &self,
+        name: Option<&str>,
+        installed_only: bool,
+    ) -> Result<DepsGraph, Error> {
+        let Some(name) = name else {
+            let mut edges = self.dependency_graph().await?;
+            let deps: BTreeSet<String> = edges.values().flatten().cloned().collect();
+            for dep in deps {
+                edges.entry(dep).or_default();
+            }
+            let installed: BTreeSet<String> = edges
+                .keys()
+                .filter(|n| self.is_installed(n))
+                .cloned()
+                .collect();
+            if installed_only {
+                edges.retain(|n, _| installed.contains(n));
+                for deps in edges.values_mut() {
+                    deps.retain(|d| installed.contains(d));
+                }
+            }
+            return Ok(DepsGraph { edges, installed });
+        };
+
+        Ok(self.get_deps_tree(name, installed_only).await?.to_graph())
+    }
+
     /// Get packages that use (depend on) a given formula.
     /// For installed packages, this checks which installed packages depend on this formula.
-    /// This is a wrapper around get_dependents with the same logic.
+    /// Otherwise the whole catalog is searched through its reverse-dependency index.
     ///
     /// # Arguments
     /// * `name` - The formula name to check
@@ -308,11 +841,11 @@ impl Installer {
         installed_only: bool,
         recursive: bool,
     ) -> Result<Vec<String>, Error> {
-        // For uses, we only support checking installed packages
-        // (checking all formulas would require fetching the entire formula index)
         if !installed_only {
-            // For now, installed_only=false behaves the same as installed_only=true
-            // A full implementation would need to scan all formulas in the API
+            return self
+                .api_client
+                .get_catalog_dependents(name, recursive)
+                .await;
         }
 
         let direct_dependents = self.get_dependents(name).await?;
@@ -357,10 +890,10 @@ impl Installer {
 
         for keg in &installed {
             // Fetch formula to get its dependencies
-            if let Ok(formula) = self.api_client.get_formula(&keg.name).await {
+            if let Ok(formula) = self.installed_formula(keg).await {
                 let deps = formula.effective_dependencies();
                 for dep in deps {
-                    all_deps.insert(dep);
+                    all_deps.insert(TapFormula::formula_name(&dep).to_string());
                 }
             }
         }
@@ -376,11 +909,59 @@ impl Installer {
         Ok(leaves)
     }
 
+    /// Installed packages upstream has deprecated or disabled, with the
+    /// reason, in install order
+    pub async fn list_deprecated(&self) -> Result<Vec<(crate::db::InstalledKeg, String)>, Error> {
+        let installed = self.db.list_installed()?;
+
+        let mut fetches = stream::iter(installed.into_iter().enumerate())
+            .map(|(idx, keg)| async move {
+                let message = self
+                    .installed_formula(&keg)
+                    .await
+                    .ok()
+                    .and_then(|formula| formula.deprecation_message());
+                (idx, keg, message)
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES);
+
+        let mut deprecated = Vec::new();
+        while let Some((idx, keg, message)) = fetches.next().await {
+            if let Some(message) = message {
+                deprecated.push((idx, keg, message));
+            }
+        }
+        deprecated.sort_by_key(|(idx, _, _)| *idx);
+
+        Ok(deprecated
+            .into_iter()
+            .map(|(_, keg, message)| (keg, message))
+            .collect())
+    }
+
     /// Get the keg path for an installed package
     pub fn keg_path(&self, name: &str) -> Option<PathBuf> {
-        self.db
-            .get_installed(name)
-            .map(|keg| self.cellar.keg_path(name, &keg.version))
+        self.get_installed(name)
+            .map(|keg| self.cellar.keg_path(&keg.name, &keg.version))
+    }
+
+    /// List every file and symlink materialized in an installed keg, sorted
+    pub fn list_keg_files(&self, name: &str) -> Result<Vec<PathBuf>, Error> {
+        let keg_path = self.keg_path(name).ok_or_else(|| Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+
+        let mut files = Vec::new();
+        for entry in walkdir::WalkDir::new(&keg_path).sort_by_file_name() {
+            let entry = entry.map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read keg '{}': {e}", keg_path.display()),
+            })?;
+            if !entry.file_type().is_dir() {
+                files.push(entry.into_path());
+            }
+        }
+
+        Ok(files)
     }
 
     // ========== Tap Operations ==========
@@ -430,6 +1011,29 @@ impl Installer {
         Ok(())
     }
 
+    /// Pin a tap so its formulas take precedence over homebrew/core
+    pub fn pin_tap(&self, user: &str, repo: &str) -> Result<(), Error> {
+        self.set_tap_pinned(user, repo, true)
+    }
+
+    /// Unpin a tap, so homebrew/core is preferred again
+    pub fn unpin_tap(&self, user: &str, repo: &str) -> Result<(), Error> {
+        self.set_tap_pinned(user, repo, false)
+    }
+
+    fn set_tap_pinned(&self, user: &str, repo: &str, pinned: bool) -> Result<(), Error> {
+        let repo = repo.strip_prefix("homebrew-").unwrap_or(repo);
+        let tap_name = format!("{}/{}", user, repo);
+
+        if !self.db.set_tap_pinned(&tap_name, pinned)? {
+            return Err(Error::MissingFormula {
+                name: format!("{} (tap not installed)", tap_name),
+            });
+        }
+
+        Ok(())
+    }
+
     /// List all installed taps
     pub fn list_taps(&self) -> Result<Vec<InstalledTap>, Error> {
         self.db.list_taps()
@@ -456,11 +1060,14 @@ impl Installer {
     ///
     /// # Arguments
     /// * `name` - The package name to link
-    /// * `overwrite` - If true, overwrite any existing symlinks that conflict
+    /// * `overwrite` - If true, move conflicting files and symlinks into
+    ///   `root/backups/<timestamp>/` so `zb unlink` can restore them
     /// * `force` - If true, link even if the formula is keg-only
     ///
     /// Returns the number of files linked
     pub fn link(&mut self, name: &str, overwrite: bool, force: bool) -> Result<LinkResult, Error> {
+        let _lock = self.store.lock_formula(name)?;
+
         // Check if installed
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
@@ -474,6 +1081,7 @@ impl Installer {
                 files_linked: 0,
                 already_linked: true,
                 keg_only_forced: false,
+                backed_up: Vec::new(),
             });
         }
 
@@ -485,7 +1093,24 @@ impl Installer {
         }
 
         // Perform the link
-        let linked_files = self.linker.link_keg(&keg_path)?;
+        let (linked_files, backed_up) = if overwrite {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let backup_dir = self.backups_dir().join(now.to_string());
+            self.linker.link_keg_overwrite(&keg_path, &backup_dir)?
+        } else {
+            (self.linker.link_keg(&keg_path)?, Vec::new())
+        };
+
+        for displaced in &backed_up {
+            self.db.record_link_backup(
+                name,
+                &displaced.link_path.to_string_lossy(),
+                &displaced.backup_path.to_string_lossy(),
+            )?;
+        }
 
         // Record the links in the database
         for linked in &linked_files {
@@ -496,21 +1121,148 @@ impl Installer {
                 &linked.target_path.to_string_lossy(),
             )?;
         }
+        self.db.set_linked(name, true)?;
 
         Ok(LinkResult {
             files_linked: linked_files.len(),
             already_linked: false,
             keg_only_forced: force,
+            backed_up,
         })
     }
 
+    /// Make another installed version of a formula the active one.
+    ///
+    /// A linked formula has its links moved straight from the current keg to
+    /// the chosen one; an unlinked or keg-only formula stays out of the
+    /// prefix and only its `opt/` link follows. The chosen version is
+    /// recorded as active. Returns the number of files linked.
+    pub fn switch(&mut self, name: &str, version: &str) -> Result<LinkResult, Error> {
+        let _lock = self.store.lock_formula(name)?;
+        self.switch_locked(name, version)
+    }
+
+    /// Switch versions while the caller holds the formula lock
+    pub(crate) fn switch_locked(&mut self, name: &str, version: &str) -> Result<LinkResult, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+
+        let keg_path = self.cellar.keg_path(name, version);
+        let known = self
+            .db
+            .list_versions(name)?
+            .iter()
+            .any(|v| v.version == version);
+        if !known || !keg_path.exists() {
+            return Err(Error::NotInstalled {
+                name: format!("{name} {version}"),
+            });
+        }
+
+        if installed.version == version && (!installed.linked || self.linker.is_linked(&keg_path)) {
+            return Ok(LinkResult {
+                files_linked: 0,
+                already_linked: true,
+                keg_only_forced: false,
+                backed_up: Vec::new(),
+            });
+        }
+
+        let old_keg_path = self.cellar.keg_path(name, &installed.version);
+        let linked_files = if installed.linked {
+            // Conflicts are found before anything moves, so a failed switch
+            // leaves the previous version linked
+            self.linker.replace_keg(&old_keg_path, &keg_path)?
+        } else {
+            self.linker.link_opt(&keg_path)?;
+            Vec::new()
+        };
+
+        let tx = self.db.transaction()?;
+        tx.record_switch(name, version)?;
+        for linked in &linked_files {
+            tx.record_linked_file(
+                name,
+                version,
+                &linked.link_path.to_string_lossy(),
+                &linked.target_path.to_string_lossy(),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(LinkResult {
+            files_linked: linked_files.len(),
+            already_linked: false,
+            keg_only_forced: false,
+            backed_up: Vec::new(),
+        })
+    }
+
+    /// Link a freshly installed keg, taking over the links of the version it
+    /// replaces without unlinking that version first
+    pub(crate) fn link_replacing(
+        &self,
+        name: &str,
+        keg_path: &Path,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        match self.db.get_installed(name) {
+            Some(previous) if self.cellar.keg_path(name, &previous.version) != keg_path => {
+                let old_keg_path = self.cellar.keg_path(name, &previous.version);
+                self.linker.replace_keg(&old_keg_path, keg_path)
+            }
+            _ => self.linker.link_keg(keg_path),
+        }
+    }
+
+    /// Copy the default configs of a freshly installed keg into `prefix/etc`,
+    /// keeping any the user has edited since the previous install
+    pub(crate) fn install_config_files(
+        &self,
+        name: &str,
+        keg_path: &Path,
+    ) -> Result<(Vec<ConfigFile>, Vec<ConfigChange>), Error> {
+        let previous = self
+            .db
+            .config_files(name)?
+            .into_iter()
+            .map(|file| (file.path, file.sha256))
+            .collect();
+        let (files, changes) =
+            config::install_configs(keg_path, &self.context.paths.prefix.join("etc"), &previous)?;
+
+        for change in &changes {
+            if let Some(default) = change.default_path() {
+                self.context.logger.note(format!(
+                    "keeping modified {}; new default saved as {}",
+                    change.path.display(),
+                    default.display()
+                ));
+            }
+        }
+        Ok((files, changes))
+    }
+
+    /// Configs in `prefix/etc` that differ from the defaults shipped with the
+    /// installed version of `name`
+    pub fn config_diffs(&self, name: &str) -> Result<Vec<ConfigDiff>, Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let keg_path = self.cellar.keg_path(name, &installed.version);
+        config::modified_configs(&keg_path, &self.context.paths.prefix.join("etc"))
+    }
+
     /// Unlink an installed keg's executables from the prefix.
     ///
-    /// This removes symlinks in `prefix/bin` and `prefix/opt` for the installed package
-    /// but keeps the package installed in the Cellar.
+    /// This removes symlinks in `prefix/bin` for the installed package but
+    /// keeps the package installed in the Cellar, and its `prefix/opt` link
+    /// in place like a keg-only formula's.
     ///
     /// Returns the number of files unlinked
     pub fn unlink(&mut self, name: &str) -> Result<usize, Error> {
+        let _lock = self.store.lock_formula(name)?;
+
         // Check if installed
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
@@ -519,14 +1271,101 @@ impl Installer {
         let keg_path = self.cellar.keg_path(name, &installed.version);
 
         // Unlink the keg
-        let unlinked = self.linker.unlink_keg(&keg_path)?;
+        let unlinked = self.linker.unlink_prefix(&keg_path)?;
 
         // Clear linked files from database
         self.db.clear_linked_files(name)?;
+        self.db.set_linked(name, false)?;
 
         Ok(unlinked.len())
     }
 
+    /// Environment exported by the wrappers `name`'s executables are linked
+    /// through
+    pub fn link_env(&self, name: &str) -> Result<BTreeMap<String, String>, Error> {
+        self.db.link_env(name)
+    }
+
+    /// Environment exported by wrappers, by formula
+    pub fn list_link_env(&self) -> Result<BTreeMap<String, BTreeMap<String, String>>, Error> {
+        self.db.list_link_env()
+    }
+
+    /// Set and unset environment variables for `name`'s executables.
+    ///
+    /// A formula with any variables set has its executables linked as
+    /// wrapper scripts that export them before running the real binary;
+    /// `{prefix}` and `{opt}` in values expand to the prefix and the
+    /// formula's `opt/` directory. A linked formula is relinked right away.
+    /// Returns the resulting environment.
+    pub fn set_link_env(
+        &mut self,
+        name: &str,
+        set: &[(String, String)],
+        unset: &[String],
+    ) -> Result<BTreeMap<String, String>, Error> {
+        let _lock = self.store.lock_formula(name)?;
+
+        if let Some((key, _)) = set.iter().find(|(key, _)| !is_env_name(key)) {
+            return Err(Error::StoreCorruption {
+                message: format!("invalid environment variable name: {key}"),
+            });
+        }
+        for (key, value) in set {
+            self.db.set_link_env(name, key, value)?;
+        }
+        for key in unset {
+            self.db.unset_link_env(name, key)?;
+        }
+
+        let env = self.db.link_env(name)?;
+        self.linker.set_wrapper_env(name, env.clone());
+
+        if let Some(installed) = self.db.get_installed(name).filter(|i| i.linked) {
+            let keg_path = self.cellar.keg_path(name, &installed.version);
+            // Links to this keg are rewritten in place as wrappers or symlinks
+            self.linker.link_keg(&keg_path)?;
+        }
+
+        Ok(env)
+    }
+
+    /// Directory that holds files displaced by `zb link --overwrite`
+    pub fn backups_dir(&self) -> PathBuf {
+        self.store.root().join("backups")
+    }
+
+    /// Put back files that were displaced when `name` was linked with `--overwrite`.
+    /// Files whose original location is occupied again are left in the backups
+    /// directory. Returns the restored paths.
+    pub fn restore_backups(&mut self, name: &str) -> Result<Vec<PathBuf>, Error> {
+        let mut restored = Vec::new();
+
+        for backup in self.db.list_link_backups(name)? {
+            let displaced = DisplacedFile {
+                link_path: PathBuf::from(&backup.link_path),
+                backup_path: PathBuf::from(&backup.backup_path),
+            };
+
+            if displaced.backup_path.symlink_metadata().is_err() {
+                // Backup was removed by hand; nothing to restore
+                self.db.remove_link_backup(backup.id)?;
+                continue;
+            }
+
+            if self.linker.restore(&displaced)? {
+                self.db.remove_link_backup(backup.id)?;
+                // Drop the timestamp directory once it is empty
+                if let Some(dir) = displaced.backup_path.parent() {
+                    let _ = std::fs::remove_dir(dir);
+                }
+                restored.push(displaced.link_path);
+            }
+        }
+
+        Ok(restored)
+    }
+
     /// Check if a keg is currently linked
     pub fn is_linked(&self, name: &str) -> bool {
         if let Some(installed) = self.db.get_installed(name) {
@@ -590,10 +1429,83 @@ impl Installer {
     pub async fn bundle_install(
         &mut self,
         brewfile_path: &Path,
+    ) -> Result<BundleInstallResult, Error> {
+        self.bundle_install_impl(brewfile_path, None).await
+    }
+
+    /// Install packages from a Brewfile, pouring exactly the bottles recorded
+    /// in `lock`.
+    ///
+    /// Everything still to be installed is resolved first; if any formula no
+    /// longer matches the lockfile nothing is installed and the differences
+    /// are returned as the error.
+    pub async fn bundle_install_locked(
+        &mut self,
+        brewfile_path: &Path,
+        lock: &BundleLock,
+    ) -> Result<BundleInstallResult, Error> {
+        self.bundle_install_impl(brewfile_path, Some(lock)).await
+    }
+
+    /// Resolve a Brewfile to the exact versions and bottles it installs on
+    /// this platform, dependencies included.
+    ///
+    /// Bottles that `previous` locked for other platforms are kept for
+    /// formulas whose version is unchanged. HEAD entries follow a branch and
+    /// are left out.
+    pub async fn bundle_lock(
+        &self,
+        brewfile_path: &Path,
+        previous: Option<BundleLock>,
+    ) -> Result<BundleLock, Error> {
+        let entries = bundle::read_brewfile(brewfile_path)?;
+        let mut lock = BundleLock::default();
+        lock.entries.brew = previous.map(|p| p.entries.brew).unwrap_or_default();
+        let mut locked = HashSet::new();
+
+        for entry in &entries {
+            match entry {
+                BrewfileEntry::Tap { name } => {
+                    lock.entries.tap.insert(name.clone(), Default::default());
+                }
+                BrewfileEntry::Brew { name, args } => match bundle::build_flags(args) {
+                    (true, _) => {
+                        self.context.logger.note(format!(
+                            "not locking {name}, HEAD installs have no fixed version"
+                        ));
+                    }
+                    (false, true) => {
+                        let formula = self.fetch_formula(name).await?;
+                        lock.lock_formula(&formula, None);
+                        locked.insert(formula.name);
+                    }
+                    (false, false) => {
+                        let plan = self.plan(name).await?;
+                        for (formula, bottle) in plan.formulas.iter().zip(&plan.bottles) {
+                            lock.lock_formula(formula, Some(bottle));
+                            locked.insert(formula.name.clone());
+                        }
+                    }
+                },
+                BrewfileEntry::Comment(_) => {}
+            }
+        }
+
+        lock.entries.brew.retain(|name, _| locked.contains(name));
+        Ok(lock)
+    }
+
+    async fn bundle_install_impl(
+        &mut self,
+        brewfile_path: &Path,
+        lock: Option<&BundleLock>,
     ) -> Result<BundleInstallResult, Error> {
         let entries = bundle::read_brewfile(brewfile_path)?;
 
-        let mut result = BundleInstallResult::default();
+        let mut result = BundleInstallResult {
+            run_id: chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+            ..Default::default()
+        };
 
         // Get currently installed formulas and taps
         let installed_kegs = self.db.list_installed()?;
@@ -632,18 +1544,88 @@ impl Installer {
             }
         }
 
-        // Process formulas
+        // `user/repo/formula` entries need their tap even without a `tap` line
+        for entry in &entries {
+            if let BrewfileEntry::Brew { name, .. } = entry
+                && let Some(tap_ref) = TapFormula::parse(name)
+            {
+                let repo = tap_ref
+                    .repo
+                    .strip_prefix("homebrew-")
+                    .unwrap_or(&tap_ref.repo);
+                let tap = format!("{}/{}", tap_ref.user, repo);
+                if tap == "homebrew/core" || self.db.is_tapped(&tap) {
+                    continue;
+                }
+                match self.add_tap(&tap_ref.user, repo).await {
+                    Ok(_) => result.taps_added.push(tap),
+                    Err(e) => result.failed.push((name.clone(), e.to_string())),
+                }
+            }
+        }
+
+        // With a lockfile, resolve everything still to be installed up front
+        // and refuse to install anything that drifted from it
+        let mut locked_plans = HashMap::new();
+        if let Some(lock) = lock {
+            let mut drift = BTreeSet::new();
+            for entry in &entries {
+                let BrewfileEntry::Brew { name, args } = entry else {
+                    continue;
+                };
+                if installed_formulas.contains(&bundle::extract_formula_name(name)) {
+                    continue;
+                }
+                match bundle::build_flags(args) {
+                    (true, _) => {}
+                    (false, true) => match self.fetch_formula(name).await {
+                        Ok(formula) => drift.extend(lock.drift(&formula, None)),
+                        Err(e) => {
+                            drift.insert(format!("{name}: {e}"));
+                        }
+                    },
+                    (false, false) => match self.plan(name).await {
+                        Ok(plan) => {
+                            drift.extend(
+                                plan.formulas
+                                    .iter()
+                                    .zip(&plan.bottles)
+                                    .filter_map(|(f, b)| lock.drift(f, Some(b))),
+                            );
+                            locked_plans.insert(name.clone(), plan);
+                        }
+                        Err(e) => {
+                            drift.insert(format!("{name}: {e}"));
+                        }
+                    },
+                }
+            }
+
+            if !drift.is_empty() {
+                return Err(Error::StoreCorruption {
+                    message: format!(
+                        "lockfile is out of date (run `zb bundle lock` to update it):\n  {}",
+                        drift.into_iter().collect::<Vec<_>>().join("\n  ")
+                    ),
+                });
+            }
+        }
+
+        // Process formulas, recording the Brewfile and run as their provenance
+        let brewfile =
+            std::fs::canonicalize(brewfile_path).unwrap_or_else(|_| brewfile_path.to_path_buf());
+        let previous = std::mem::replace(
+            &mut self.provenance,
+            Provenance::Brewfile {
+                path: brewfile.to_string_lossy().into_owned(),
+                run_id: result.run_id.clone(),
+            },
+        );
+
         for entry in &entries {
             if let BrewfileEntry::Brew { name, args } = entry {
                 // Extract formula name (handle user/repo/formula format)
-                let formula_name = {
-                    let parts: Vec<_> = name.split('/').collect();
-                    if parts.len() == 3 {
-                        parts[2].to_string()
-                    } else {
-                        name.clone()
-                    }
-                };
+                let formula_name = bundle::extract_formula_name(name);
 
                 // Check if already installed
                 if installed_formulas.contains(&formula_name) {
@@ -652,14 +1634,16 @@ impl Installer {
                 }
 
                 // Check for HEAD flag in args
-                let is_head = args.iter().any(|a| a == "--HEAD" || a == "-H");
-                let is_source = args.iter().any(|a| a == "--build-from-source" || a == "-s");
+                let (is_head, is_source) = bundle::build_flags(args);
 
-                // Install the formula
+                // Install the formula, from the plan checked against the
+                // lockfile if there is one
                 let install_result = if is_head || is_source {
-                    self.install_from_source(name, true, is_head)
+                    self.install_from_source(name, true, is_head, &[])
                         .await
                         .map(|r| r.name)
+                } else if let Some(plan) = locked_plans.remove(name) {
+                    self.execute(plan, true).await.map(|_| name.clone())
                 } else {
                     self.install(name, true).await.map(|_| name.clone())
                 };
@@ -675,6 +1659,7 @@ impl Installer {
             }
         }
 
+        self.provenance = previous;
         Ok(result)
     }
 
@@ -687,53 +1672,156 @@ impl Installer {
     pub fn find_brewfile(&self, start_dir: &Path) -> Option<PathBuf> {
         bundle::find_brewfile(start_dir)
     }
-}
 
-/// Recursively copy a directory
-pub(crate) fn copy_dir_recursive(
-    src: &std::path::Path,
-    dst: &std::path::Path,
-) -> Result<(), Error> {
-    if !dst.exists() {
-        std::fs::create_dir_all(dst).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create directory '{}': {}", dst.display(), e),
-        })?;
-    }
+    /// Keg paths that activate a Brewfile's formulas without linking them
+    pub fn bundle_env(&self, entries: &[BrewfileEntry]) -> BundleEnv {
+        let mut env = BundleEnv::default();
 
-    for entry in std::fs::read_dir(src).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to read directory '{}': {}", src.display(), e),
-    })? {
-        let entry = entry.map_err(|e| Error::StoreCorruption {
-            message: format!("failed to read entry: {}", e),
-        })?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+        for entry in entries {
+            if let BrewfileEntry::Brew { name, .. } = entry {
+                match self.keg_path(&bundle::extract_formula_name(name)) {
+                    Some(keg) if !env.kegs.contains(&keg) => env.kegs.push(keg),
+                    Some(_) => {}
+                    None => env.missing.push(name.clone()),
+                }
+            }
+        }
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            std::fs::copy(&src_path, &dst_path).map_err(|e| Error::StoreCorruption {
-                message: format!(
-                    "failed to copy '{}' to '{}': {}",
-                    src_path.display(),
-                    dst_path.display(),
-                    e
-                ),
+        env
+    }
+
+    /// Keg paths for running a command against one formula without linking it.
+    ///
+    /// `spec` is an installed formula (`node@18`) or an installed but
+    /// inactive version of one (`node@20.1.0`). Its keg comes first, followed
+    /// by the active kegs of its installed dependencies.
+    pub async fn exec_env(&self, spec: &str) -> Result<BundleEnv, Error> {
+        let (name, keg) = self
+            .resolve_exec_keg(spec)
+            .ok_or_else(|| Error::NotInstalled {
+                name: spec.to_string(),
             })?;
+        let mut env = BundleEnv {
+            kegs: vec![keg],
+            missing: Vec::new(),
+        };
+
+        let graph = self.dependency_graph().await?;
+        let mut seen = HashSet::from([name.clone()]);
+        let mut pending = std::collections::VecDeque::from([name]);
+        while let Some(pkg) = pending.pop_front() {
+            for dep in graph.get(&pkg).into_iter().flatten() {
+                if !seen.insert(dep.clone()) {
+                    continue;
+                }
+                match self.keg_path(dep) {
+                    Some(keg) => env.kegs.push(keg),
+                    None => env.missing.push(dep.clone()),
+                }
+                pending.push_back(dep.clone());
+            }
+        }
+
+        Ok(env)
+    }
+
+    /// Formula name and keg for an exec spec, see [`Installer::exec_env`]
+    fn resolve_exec_keg(&self, spec: &str) -> Option<(String, PathBuf)> {
+        if let Some(keg) = self.keg_path(spec) {
+            return Some((spec.to_string(), keg));
+        }
+
+        let (name, version) = spec.rsplit_once('@')?;
+        let kept = self.db.list_versions(name).ok()?;
+        kept.iter()
+            .any(|k| k.version == version)
+            .then(|| self.cellar.keg_path(name, version))
+            .filter(|keg| keg.is_dir())
+            .map(|keg| (name.to_string(), keg))
+    }
+
+    // ==================== Service Settings ====================
+
+    /// Stored process settings for a formula's service
+    pub fn service_settings(&self, formula: &str) -> Result<ServiceSettings, Error> {
+        let Some(config) = self
+            .db
+            .get_service_for_formula(formula)
+            .and_then(|record| record.config)
+        else {
+            return Ok(ServiceSettings::default());
+        };
+
+        serde_json::from_str(&config).map_err(|e| Error::StoreCorruption {
+            message: format!("invalid service settings for '{formula}': {e}"),
+        })
+    }
+
+    /// Merge `settings` into a formula's stored service settings.
+    ///
+    /// Returns the combined settings.
+    pub fn update_service_settings(
+        &self,
+        formula: &str,
+        settings: ServiceSettings,
+    ) -> Result<ServiceSettings, Error> {
+        if !self.is_installed(formula) {
+            return Err(Error::NotInstalled {
+                name: formula.to_string(),
+            });
         }
+        settings.validate()?;
+
+        let mut merged = self.service_settings(formula)?;
+        merged.merge(settings);
+        self.store_service_settings(formula, &merged)?;
+        Ok(merged)
+    }
+
+    /// Forget a formula's stored service settings
+    pub fn reset_service_settings(&self, formula: &str) -> Result<(), Error> {
+        self.store_service_settings(formula, &ServiceSettings::default())
     }
 
-    Ok(())
+    fn store_service_settings(
+        &self,
+        formula: &str,
+        settings: &ServiceSettings,
+    ) -> Result<(), Error> {
+        let config = serde_json::to_string(settings).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize service settings: {e}"),
+        })?;
+        self.db.record_service(formula, formula, Some(&config))
+    }
 }
 
+/// Overrides where source builds unpack and compile (see [`Installer::set_temp_dir`])
+pub const TEMP_DIR_ENV: &str = "ZB_TEMP";
+
+/// Resolves formulas and bottles from a mirror (see [`Installer::set_mirror`])
+pub use zb_core::config::MIRROR_ENV;
+
+/// Server publishing patches between bottles (see [`Installer::set_delta_url`])
+pub const DELTA_URL_ENV: &str = "ZB_DELTA_URL";
+
 /// Create an Installer with standard paths
 pub fn create_installer(
     root: &Path,
     prefix: &Path,
     download_concurrency: usize,
 ) -> Result<Installer, Error> {
+    let mut context = Context::new(root, prefix);
+    context.concurrency = ConcurrencyLimits::new(download_concurrency);
+    create_installer_from_context(context)
+}
+
+/// Create an Installer for `context`, creating its directories as needed
+pub fn create_installer_from_context(context: Context) -> Result<Installer, Error> {
     use std::fs;
 
+    let paths = &context.paths;
+    let root = paths.root.as_path();
+
     // First ensure the root directory exists
     if !root.exists() {
         fs::create_dir_all(root).map_err(|e| {
@@ -756,36 +1844,40 @@ pub fn create_installer(
     }
 
     // Ensure all subdirectories exist
-    fs::create_dir_all(root.join("db")).map_err(|e| Error::StoreCorruption {
-        message: format!("failed to create db directory: {e}"),
-    })?;
+    if let Some(db_dir) = paths.db.parent() {
+        fs::create_dir_all(db_dir).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create db directory: {e}"),
+        })?;
+    }
 
     // Create taps directory
-    let taps_dir = root.join("taps");
-    fs::create_dir_all(&taps_dir).map_err(|e| Error::StoreCorruption {
+    fs::create_dir_all(&paths.taps).map_err(|e| Error::StoreCorruption {
         message: format!("failed to create taps directory: {e}"),
     })?;
 
-    let api_client = ApiClient::new();
-    let blob_cache = BlobCache::new(&root.join("cache")).map_err(|e| Error::StoreCorruption {
+    let api_client = ApiClient::from_env()?;
+    let blob_cache = BlobCache::new(&paths.cache).map_err(|e| Error::StoreCorruption {
         message: format!("failed to create blob cache: {e}"),
     })?;
     let store = Store::new(root).map_err(|e| Error::StoreCorruption {
         message: format!("failed to create store: {e}"),
     })?;
     // Use prefix/Cellar so bottles' hardcoded rpaths work
-    let cellar = Cellar::new_at(prefix.join("Cellar")).map_err(|e| Error::StoreCorruption {
+    let cellar = Cellar::new_at(paths.cellar.clone()).map_err(|e| Error::StoreCorruption {
         message: format!("failed to create cellar: {e}"),
     })?;
-    let linker = Linker::new(prefix).map_err(|e| Error::StoreCorruption {
+    let linker = Linker::new(&paths.prefix).map_err(|e| Error::StoreCorruption {
         message: format!("failed to create linker: {e}"),
     })?;
-    let db = Database::open(&root.join("db/zb.sqlite3"))?;
-    let tap_manager = TapManager::new(&taps_dir);
+    // Reap temp directories left behind by runs that crashed or were killed
+    let _ = store.cleanup_temp_dirs();
+    let _ = cellar.cleanup_temp_dirs();
 
-    let cellar_path = prefix.join("Cellar");
+    let db = Database::open(&paths.db)?;
+    let tap_manager = TapManager::new(&paths.taps);
+    let cooldowns = paths.cache.join("cooldowns.json");
 
-    Ok(Installer::new(
+    let mut installer = Installer::new(
         api_client,
         blob_cache,
         store,
@@ -793,10 +1885,32 @@ pub fn create_installer(
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        cellar_path,
-        download_concurrency,
-    ))
+        context,
+    );
+    installer.set_http_mode(HttpMode::from_env());
+    installer.set_credentials(Credentials::from_env());
+    installer.set_attestation_verifier(AttestationVerifier::from_env());
+    installer.set_rate_limits(RateLimits::persistent(&cooldowns));
+    if let Some(dir) = std::env::var_os(TEMP_DIR_ENV).filter(|d| !d.is_empty()) {
+        installer.set_temp_dir(Path::new(&dir))?;
+    }
+    if let Some(domain) = installer.context.api_domain.clone() {
+        installer.set_api_domain(&domain, installer.context.api_layout)?;
+    }
+    let mirror = std::env::var(MIRROR_ENV)
+        .ok()
+        .filter(|location| !location.trim().is_empty())
+        .or_else(|| installer.context.mirror.clone());
+    if let Some(location) = mirror {
+        installer.set_mirror(&location)?;
+    }
+    if let Ok(url) = std::env::var(DELTA_URL_ENV)
+        && !url.trim().is_empty()
+    {
+        installer.set_delta_url(&url);
+    }
+
+    Ok(installer)
 }
 
 #[cfg(test)]
@@ -2,15 +2,19 @@
 //!
 //! This module handles:
 //! - Finding orphaned packages (dependencies no longer needed)
-//! - Autoremove functionality
+//! - Autoremove functionality and the keep-list
 //! - Marking packages as explicit/dependency
+//! - Explaining why a package is installed
+//! - Uninstalling a package together with its dependents
 //! - Source builds
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
-use zb_core::{Error, resolve_closure};
+use crate::receipt::InstallReceipt;
 
-use super::{Installer, copy_dir_recursive};
+use zb_core::Error;
+
+use super::{CORE_TAP, Installer, dependency_names};
 
 /// Result of a source build operation
 #[derive(Debug, Clone)]
@@ -27,13 +31,118 @@ pub struct SourceBuildResult {
     pub head: bool,
 }
 
+/// Maximum number of dependency chains reported by [`Installer::why`]
+const MAX_WHY_CHAINS: usize = 10;
+
+/// Why an installed package is present
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WhyResult {
+    /// Whether the package was installed explicitly
+    pub explicit: bool,
+    /// Chains from explicitly installed packages down to the package, shortest first
+    pub chains: Vec<Vec<String>>,
+}
+
+impl WhyResult {
+    /// True if nothing explicitly installed needs the package
+    pub fn is_orphan(&self) -> bool {
+        !self.explicit && self.chains.is_empty()
+    }
+}
+
 impl Installer {
+    /// Direct dependencies of installed packages, keyed by package name.
+    ///
+    /// Uses the edges recorded at install time. Packages installed before
+    /// edges were recorded fall back to their formula's current dependencies;
+    /// if the formula can't be fetched either, the package is left out.
+    pub async fn dependency_graph(&self) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        let mut graph = self.db.recorded_dependencies()?;
+
+        for keg in self.db.list_installed()? {
+            if graph.contains_key(&keg.name) {
+                continue;
+            }
+            if let Ok(formula) = self.installed_formula(&keg).await {
+                graph.insert(keg.name, dependency_names(&formula));
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Explain why a package is installed.
+    ///
+    /// Walks the recorded dependency edges from the package up to the
+    /// explicitly installed packages that need it.
+    pub async fn why(&self, name: &str) -> Result<WhyResult, Error> {
+        let keg = self
+            .db
+            .get_installed(name)
+            .ok_or_else(|| Error::NotInstalled {
+                name: name.to_string(),
+            })?;
+
+        let explicit: HashSet<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|k| k.explicit)
+            .map(|k| k.name)
+            .collect();
+        let graph = self.dependency_graph().await?;
+
+        Ok(WhyResult {
+            explicit: keg.explicit,
+            chains: dependency_chains(&keg.name, &graph, &explicit),
+        })
+    }
+
+    /// The package plus every installed package that depends on it, directly
+    /// or transitively, in the order they can be uninstalled: each package
+    /// comes before anything it depends on, so `name` is always last.
+    pub async fn dependents_closure(&self, name: &str) -> Result<Vec<String>, Error> {
+        if self.db.get_installed(name).is_none() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+
+        let graph = self.dependency_graph().await?;
+        Ok(reverse_dependency_closure(name, &graph))
+    }
+
+    /// Uninstall a package along with every installed package that depends
+    /// on it, dependents first.
+    ///
+    /// Stops at the first failure; returns the packages removed so far.
+    pub async fn uninstall_with_dependents(&mut self, name: &str) -> Result<Vec<String>, Error> {
+        let closure = self.dependents_closure(name).await?;
+
+        let mut removed = Vec::new();
+        for pkg in closure {
+            self.uninstall(&pkg)?;
+            removed.push(pkg);
+        }
+
+        Ok(removed)
+    }
+
     /// Find orphaned packages - dependencies that are no longer needed by any explicit package.
     ///
     /// A package is considered an orphan if:
     /// 1. It was installed as a dependency (explicit = false)
     /// 2. No explicitly installed package depends on it (directly or transitively)
+    /// 3. It is not on the keep-list, and no kept package depends on it
+    ///
+    /// Dependencies come from [`Installer::dependency_graph`], so this only
+    /// needs the network for packages installed before edges were recorded.
     pub async fn find_orphans(&self) -> Result<Vec<String>, Error> {
+        self.find_orphans_except(&[]).await
+    }
+
+    /// Find orphaned packages, treating `except` like kept packages.
+    pub async fn find_orphans_except(&self, except: &[String]) -> Result<Vec<String>, Error> {
         let installed = self.db.list_installed()?;
 
         if installed.is_empty() {
@@ -47,48 +156,37 @@ impl Installer {
             return Ok(Vec::new());
         }
 
-        // Get all explicit packages
-        let explicit_pkgs: Vec<_> = installed.iter().filter(|k| k.explicit).collect();
+        // Explicit packages, kept packages and exceptions are all roots
+        let kept = self.db.list_kept()?;
+        let mut pending: Vec<&str> = installed
+            .iter()
+            .filter(|k| k.explicit)
+            .map(|k| k.name.as_str())
+            .chain(kept.iter().map(String::as_str))
+            .chain(except.iter().map(String::as_str))
+            .collect();
 
-        if explicit_pkgs.is_empty() {
-            // If no explicit packages, all dependencies are orphans
+        if pending.is_empty() {
+            // If no roots, all dependencies are orphans
             return Ok(dependency_pkgs.iter().map(|k| k.name.clone()).collect());
         }
 
-        // Find all packages that are required by explicit packages
-        let mut required: HashSet<String> = HashSet::new();
-
-        for keg in &explicit_pkgs {
-            // The explicit package itself is required
-            required.insert(keg.name.clone());
-
-            // Fetch its formula to get dependencies
-            match self.api_client.get_formula(&keg.name).await {
-                Ok(formula) => {
-                    // Get all transitive dependencies
-                    match self.fetch_all_formulas(&keg.name).await {
-                        Ok(formulas) => {
-                            if let Ok(deps) = resolve_closure(&keg.name, &formulas) {
-                                required.extend(deps);
-                            }
-                        }
-                        Err(_) => {
-                            // If we can't fetch formulas, just use direct dependencies
-                            required.extend(formula.effective_dependencies());
-                        }
-                    }
-                }
-                Err(_) => {
-                    // Formula no longer in API - can't determine deps, keep it safe
-                    continue;
-                }
+        // Find all packages that are required by the roots
+        let graph = self.dependency_graph().await?;
+        let mut required: HashSet<&str> = HashSet::new();
+
+        while let Some(name) = pending.pop() {
+            if required.insert(name)
+                && let Some(deps) = graph.get(name)
+            {
+                pending.extend(deps.iter().map(String::as_str));
             }
         }
 
         // Find orphans: packages that are dependencies but not required
         let orphans: Vec<String> = dependency_pkgs
             .iter()
-            .filter(|k| !required.contains(&k.name))
+            .filter(|k| !required.contains(k.name.as_str()))
             .map(|k| k.name.clone())
             .collect();
 
@@ -99,7 +197,14 @@ impl Installer {
     ///
     /// Returns the list of packages that were removed.
     pub async fn autoremove(&mut self) -> Result<Vec<String>, Error> {
-        let orphans = self.find_orphans().await?;
+        self.autoremove_except(&[]).await
+    }
+
+    /// Remove orphaned packages, sparing `except` and their dependencies.
+    ///
+    /// Returns the list of packages that were removed.
+    pub async fn autoremove_except(&mut self, except: &[String]) -> Result<Vec<String>, Error> {
+        let orphans = self.find_orphans_except(except).await?;
 
         if orphans.is_empty() {
             return Ok(Vec::new());
@@ -114,7 +219,9 @@ impl Installer {
                 }
                 Err(e) => {
                     // Log warning but continue with other packages
-                    eprintln!("    Warning: failed to remove {}: {}", name, e);
+                    self.context
+                        .logger
+                        .warn(format!("failed to remove {}: {}", name, e));
                 }
             }
         }
@@ -122,6 +229,29 @@ impl Installer {
         Ok(removed)
     }
 
+    /// Add an installed package to the keep-list so autoremove never removes it.
+    ///
+    /// Returns false if it was already kept. The keep-list outlives the
+    /// package, so a kept package stays kept across reinstalls.
+    pub fn keep(&self, name: &str) -> Result<bool, Error> {
+        if self.db.get_installed(name).is_none() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+        self.db.add_keep(name)
+    }
+
+    /// Remove a package from the keep-list. Returns false if it wasn't kept.
+    pub fn unkeep(&self, name: &str) -> Result<bool, Error> {
+        self.db.remove_keep(name)
+    }
+
+    /// List packages on the keep-list
+    pub fn list_kept(&self) -> Result<Vec<String>, Error> {
+        self.db.list_kept()
+    }
+
     /// Mark a package as explicitly installed.
     ///
     /// Use this when a user explicitly installs a package that was previously
@@ -164,19 +294,39 @@ impl Installer {
     /// 4. Builds using the detected build system
     /// 5. Installs the built files to the cellar
     /// 6. Links executables
+    ///
+    /// `build_args` are passed to the build system (e.g. configure flags) and
+    /// recorded in the install receipt so upgrades can rebuild the same way.
     pub async fn install_from_source(
         &mut self,
         name: &str,
         link: bool,
         head: bool,
+        build_args: &[String],
+    ) -> Result<SourceBuildResult, Error> {
+        self.build_from_source(name, link, head, build_args, true)
+            .await
+    }
+
+    /// Build and install `name` from source, recording it as explicitly
+    /// installed or as a dependency
+    pub(crate) async fn build_from_source(
+        &mut self,
+        name: &str,
+        link: bool,
+        head: bool,
+        build_args: &[String],
+        explicit: bool,
     ) -> Result<SourceBuildResult, Error> {
         use crate::build::{
-            BuildEnvironment, Builder, clone_git_repo, download_source, extract_tarball,
+            BuildEnvironment, Builder, clone_git_repo, download_source, extract_source,
+            git_head_commit, head_version, source_archive_name,
         };
-        use tempfile::TempDir;
+        use crate::extract::ExtractOptions;
 
         // Fetch formula
         let formula = self.fetch_formula(name).await?;
+        self.check_not_disabled(&formula)?;
 
         // Check source availability
         let source_url = if head {
@@ -218,63 +368,86 @@ impl Installer {
             .cloned()
             .collect();
 
+        let tap = formula.tap.as_deref().filter(|tap| *tap != CORE_TAP);
         for dep in &all_deps {
             if !self.is_installed(dep) {
-                // Try to install the dependency as a bottle
-                match self.install(dep, true).await {
-                    Ok(_) => {}
+                // Try to install the dependency as a bottle, from the
+                // formula's own tap if it has one
+                let dep = &self.dependency_ref(dep, tap).await;
+                let installed = if self.needs_source_build(dep).await.unwrap_or(false) {
+                    // A tap dependency without a bottle is built as well
+                    Box::pin(self.build_from_source(dep, true, false, &[], false))
+                        .await
+                        .map(|_| ())
+                } else {
+                    self.install(dep, true).await.map(|_| ())
+                };
+                match installed {
+                    Ok(()) => {}
                     Err(e) => {
-                        eprintln!(
-                            "    Warning: failed to install build dependency '{}': {}",
+                        self.context.logger.warn(format!(
+                            "failed to install build dependency '{}': {}",
                             dep, e
-                        );
+                        ));
                     }
                 }
             }
         }
 
-        // Create temporary directories for build
-        let build_tmp = TempDir::new().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create temp directory: {}", e),
-        })?;
-        let staging_tmp = TempDir::new().map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create staging directory: {}", e),
-        })?;
+        // Hold the formula lock for the rest of the build and install
+        let _lock = self.store.lock_formula(&formula.name)?;
+
+        // Create temporary directories for build (removed on drop, reaped after a crash)
+        let build_tmp = self.scratch_dir(&format!("build-{}", formula.name))?;
+        let staging_tmp = self.scratch_dir(&format!("staging-{}", formula.name))?;
 
         // Download or clone source
+        let mut head_commit = None;
         let source_dir = if head {
             let clone_dir = build_tmp.path().join("source");
             let branch = formula.urls.head.as_ref().and_then(|h| h.branch.as_deref());
             clone_git_repo(&source_url, branch, &clone_dir)?;
+            head_commit = Some(git_head_commit(&clone_dir)?);
             clone_dir
         } else {
-            let tarball_path = build_tmp.path().join("source.tar.gz");
-            download_source(&source_url, &tarball_path, checksum.as_deref())?;
-            extract_tarball(&tarball_path, build_tmp.path())?
+            let archive_path = build_tmp.path().join(source_archive_name(&source_url));
+            download_source(&source_url, &archive_path, checksum.as_deref())?;
+            // Extract next to the archive so it isn't mistaken for source
+            extract_source(
+                &archive_path,
+                &build_tmp.path().join("source"),
+                ExtractOptions::default(),
+            )?
         };
 
-        // Determine version
-        let version = if head {
-            // For HEAD builds, use current timestamp or commit
-            let now = chrono::Utc::now();
-            format!("HEAD-{}", now.format("%Y%m%d%H%M%S"))
-        } else {
-            formula.versions.stable.clone()
+        // Determine version; HEAD builds are named after their commit
+        let version = match head_commit {
+            Some(ref commit) => head_version(commit),
+            None => formula.versions.stable.clone(),
         };
 
-        // Create build environment
-        let opt_dir = self.prefix.join("opt");
-        let build_env = BuildEnvironment::new(
+        // Create build environment; compilers' scratch files go next to the
+        // build rather than to the system temp directory
+        let opt_dir = self.context.paths.prefix.join("opt");
+        let mut build_env = BuildEnvironment::new(
             &formula,
             source_dir.clone(),
-            &self.prefix,
+            &self.context.paths.prefix,
             &opt_dir,
             staging_tmp.path().to_path_buf(),
         );
+        let build_scratch = build_tmp.path().join("tmp");
+        std::fs::create_dir_all(&build_scratch).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create build temp directory: {e}"),
+        })?;
+        build_env.env.insert(
+            "TMPDIR".to_string(),
+            build_scratch.to_string_lossy().to_string(),
+        );
 
         // Build
         let builder = Builder::new(build_env);
-        let build_result = builder.build_auto(&[])?;
+        let build_result = builder.build_auto(build_args)?;
 
         if build_result.installed_files.is_empty() {
             return Err(Error::StoreCorruption {
@@ -283,33 +456,37 @@ impl Installer {
         }
 
         // Create keg in cellar from staging directory
-        let keg_path = self.cellar_path.join(&formula.name).join(&version);
-        if keg_path.exists() {
-            std::fs::remove_dir_all(&keg_path).map_err(|e| Error::StoreCorruption {
-                message: format!("failed to remove existing keg: {}", e),
-            })?;
-        }
-        std::fs::create_dir_all(&keg_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create keg directory: {}", e),
-        })?;
-
-        // Copy files from staging to keg
-        copy_dir_recursive(staging_tmp.path(), &keg_path)?;
+        let keg_path = self
+            .cellar
+            .install_dir(&formula.name, &version, staging_tmp.path())?;
 
         // Generate a unique store key for source builds
         let store_key = format!("source-{}-{}", formula.name, version);
 
+        let receipt = InstallReceipt::source(&formula, head, build_args);
+        receipt.write(&keg_path)?;
+        let (config_files, _) = self.install_config_files(&formula.name, &keg_path)?;
+
         // Link executables if requested
         let linked_files = if link {
-            self.linker.link_keg(&keg_path)?
+            self.link_replacing(&formula.name, &keg_path)?
         } else {
+            self.linker.link_opt(&keg_path)?;
             Vec::new()
         };
 
         // Record in database
         {
             let tx = self.db.transaction()?;
-            tx.record_install(&formula.name, &version, &store_key, true)?;
+            tx.record_install(&formula.name, &version, &store_key, explicit)?;
+            tx.record_link_state(&formula.name, link, formula.keg_only)?;
+            tx.record_dependencies(&formula.name, &dependency_names(&formula))?;
+            tx.record_provenance(&formula.name, &self.provenance)?;
+            tx.record_receipt(&formula.name, &receipt)?;
+            tx.record_config_files(&formula.name, &config_files)?;
+            if let Some(ref commit) = head_commit {
+                tx.record_head_commit(&formula.name, commit)?;
+            }
 
             for linked in &linked_files {
                 tx.record_linked_file(
@@ -322,6 +499,7 @@ impl Installer {
 
             tx.commit()?;
         }
+        self.post_install_all([(&formula, keg_path)]);
 
         Ok(SourceBuildResult {
             name: formula.name.clone(),
@@ -332,3 +510,114 @@ impl Installer {
         })
     }
 }
+
+/// Chains from explicitly installed packages down to `name`, shortest first.
+///
+/// Each chain starts at an explicit package and ends at `name`. Walking stops
+/// at the first explicit package, so a chain never passes through another
+/// explicit package. At most [`MAX_WHY_CHAINS`] chains are returned.
+fn dependency_chains(
+    name: &str,
+    graph: &BTreeMap<String, Vec<String>>,
+    explicit: &HashSet<String>,
+) -> Vec<Vec<String>> {
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (pkg, deps) in graph {
+        for dep in deps {
+            dependents
+                .entry(dep.as_str())
+                .or_default()
+                .push(pkg.as_str());
+        }
+    }
+
+    let mut chains = Vec::new();
+    let mut pending: VecDeque<Vec<&str>> = VecDeque::from([vec![name]]);
+
+    while let Some(path) = pending.pop_front() {
+        let last = path[path.len() - 1];
+        for &parent in dependents.get(last).into_iter().flatten() {
+            if path.contains(&parent) {
+                continue;
+            }
+
+            let mut next = path.clone();
+            next.push(parent);
+            if explicit.contains(parent) {
+                chains.push(next.iter().rev().map(|s| s.to_string()).collect());
+                if chains.len() == MAX_WHY_CHAINS {
+                    return chains;
+                }
+            } else {
+                pending.push_back(next);
+            }
+        }
+    }
+
+    chains
+}
+
+/// Collect `name` and everything in `graph` that reaches it through
+/// dependency edges, ordered so dependents come before their dependencies.
+///
+/// Ties are broken by name. Packages caught in a dependency cycle can't be
+/// ordered and are appended by name before `name` itself.
+fn reverse_dependency_closure(name: &str, graph: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (pkg, deps) in graph {
+        for dep in deps {
+            dependents
+                .entry(dep.as_str())
+                .or_default()
+                .push(pkg.as_str());
+        }
+    }
+
+    let mut closure: HashSet<&str> = HashSet::new();
+    let mut pending = vec![name];
+    while let Some(pkg) = pending.pop() {
+        if closure.insert(pkg) {
+            pending.extend(dependents.get(pkg).into_iter().flatten());
+        }
+    }
+
+    // Count, for each package, how many packages in the closure still need it
+    let mut needed_by: BTreeMap<&str, usize> = closure.iter().map(|&pkg| (pkg, 0)).collect();
+    for &pkg in &closure {
+        for dep in graph.get(pkg).into_iter().flatten() {
+            if let Some(count) = needed_by.get_mut(dep.as_str()) {
+                *count += 1;
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(closure.len());
+    loop {
+        let ready: Vec<&str> = needed_by
+            .iter()
+            .filter(|&(&pkg, &count)| count == 0 && pkg != name)
+            .map(|(&pkg, _)| pkg)
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for pkg in ready {
+            needed_by.remove(pkg);
+            for dep in graph.get(pkg).into_iter().flatten() {
+                if let Some(count) = needed_by.get_mut(dep.as_str()) {
+                    *count -= 1;
+                }
+            }
+            order.push(pkg.to_string());
+        }
+    }
+
+    order.extend(
+        needed_by
+            .keys()
+            .filter(|&&pkg| pkg != name)
+            .map(|pkg| pkg.to_string()),
+    );
+    order.push(name.to_string());
+    order
+}
@@ -0,0 +1,220 @@
+//! Offline packs
+//!
+//! A pack (`.zbpack`) is a gzipped tarball of a mirror directory (see
+//! `crate::mirror`). Unpacking imports its bottles into the blob cache and
+//! merges its formulas into `<root>/packs/index.json`, a single-file formula
+//! index that `zb install --from-pack` reads instead of the API.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use sha2::{Digest, Sha256};
+
+use crate::extract::extract_tarball;
+use crate::index::IndexProvider;
+use crate::install::MirrorResult;
+use crate::mirror;
+use crate::tempdir::TempDirGuard;
+
+use zb_core::{Error, Formula};
+
+use super::Installer;
+
+/// Result of unpacking a pack
+#[derive(Debug, Default)]
+pub struct UnpackResult {
+    /// Formulas added to the pack index
+    pub formulas: Vec<String>,
+    /// Bottles imported into the blob cache
+    pub bottles: usize,
+}
+
+impl Installer {
+    /// Mirror `names` and their dependencies into a single pack file
+    pub async fn create_pack(
+        &self,
+        output: &Path,
+        names: &[String],
+        platforms: &[String],
+    ) -> Result<MirrorResult, Error> {
+        let parent = match output.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let staging = TempDirGuard::new(parent, "zbpack")?;
+
+        let result = self.create_mirror(staging.path(), names, platforms).await?;
+        if result.formulas.is_empty() {
+            return Err(Error::MissingFormula {
+                name: names.join(", "),
+            });
+        }
+
+        write_pack(staging.path(), output)?;
+        Ok(result)
+    }
+
+    /// Import a pack's bottles and formulas so they can be installed offline
+    pub fn unpack(&self, pack: &Path) -> Result<UnpackResult, Error> {
+        let packs_dir = self.packs_dir();
+        fs::create_dir_all(&packs_dir).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create packs directory: {e}"),
+        })?;
+
+        let staging = TempDirGuard::new(&packs_dir, "unpack")?;
+        extract_tarball(pack, staging.path())?;
+        let dir = staging.path();
+
+        if !mirror::formula_list_path(dir).exists() {
+            return Err(Error::StoreCorruption {
+                message: format!("{} is not a zerobrew pack", pack.display()),
+            });
+        }
+
+        let mut result = UnpackResult::default();
+        let mut formulas = BTreeMap::new();
+
+        for formula in read_pack_formulas(dir)? {
+            for file in formula.bottle.stable.files.values() {
+                let bottle = mirror::bottles_dir(dir).join(mirror::bottle_path(&file.url));
+                if bottle.exists() {
+                    self.import_blob(&bottle, &file.sha256)?;
+                    result.bottles += 1;
+                }
+            }
+            formulas.insert(formula.name.clone(), formula);
+        }
+
+        let index_path = self.pack_index_path();
+        let mut index: BTreeMap<String, serde_json::Value> = fs::read(&index_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Vec<serde_json::Value>>(&data).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|v| Some((v.get("name")?.as_str()?.to_string(), v)))
+            .collect();
+
+        for (name, formula) in formulas {
+            let value = serde_json::to_value(&formula).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to serialize formula '{name}': {e}"),
+            })?;
+            index.insert(name.clone(), value);
+            result.formulas.push(name);
+        }
+
+        let data =
+            serde_json::to_vec_pretty(&index.into_values().collect::<Vec<_>>()).map_err(|e| {
+                Error::StoreCorruption {
+                    message: format!("failed to serialize pack index: {e}"),
+                }
+            })?;
+        fs::write(&index_path, data).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write {}: {e}", index_path.display()),
+        })?;
+
+        Ok(result)
+    }
+
+    /// Resolve formulas from unpacked packs instead of the API
+    pub fn use_packs(&mut self) -> Result<(), Error> {
+        let index_path = self.pack_index_path();
+        if !index_path.exists() {
+            return Err(Error::StoreCorruption {
+                message: "no packs have been unpacked (run `zb unpack <file>` first)".to_string(),
+            });
+        }
+        self.api_client
+            .set_provider(IndexProvider::File(index_path));
+        Ok(())
+    }
+
+    pub fn packs_dir(&self) -> PathBuf {
+        self.store.root().join("packs")
+    }
+
+    pub fn pack_index_path(&self) -> PathBuf {
+        self.packs_dir().join("index.json")
+    }
+
+    /// Copy a bottle into the blob cache after checking its hash
+    fn import_blob(&self, path: &Path, sha256: &str) -> Result<(), Error> {
+        if self.blob_cache.has_blob(sha256) {
+            return Ok(());
+        }
+
+        let data = fs::read(path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let actual = format!("{:x}", Sha256::digest(&data));
+        if actual != sha256 {
+            return Err(Error::ChecksumMismatch {
+                expected: sha256.to_string(),
+                actual,
+                file_name: path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            });
+        }
+
+        let mut writer =
+            self.blob_cache
+                .start_write(sha256)
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to write blob: {e}"),
+                })?;
+        io::Write::write_all(&mut writer, &data).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write blob: {e}"),
+        })?;
+        writer.commit()?;
+        Ok(())
+    }
+}
+
+/// Formulas in an extracted pack, skipping alias copies
+fn read_pack_formulas(dir: &Path) -> Result<Vec<Formula>, Error> {
+    let formula_dir = mirror::api_dir(dir).join("formula");
+    let entries = fs::read_dir(&formula_dir).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to read {}: {e}", formula_dir.display()),
+    })?;
+
+    let mut formulas = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let data = fs::read(&path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read {}: {e}", path.display()),
+        })?;
+        let formula: Formula =
+            serde_json::from_slice(&data).map_err(|e| Error::StoreCorruption {
+                message: format!("invalid formula in pack {}: {e}", path.display()),
+            })?;
+
+        if path
+            .file_stem()
+            .is_some_and(|stem| stem == formula.name.as_str())
+        {
+            formulas.push(formula);
+        }
+    }
+
+    formulas.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(formulas)
+}
+
+/// Write `dir` as a gzipped tarball
+fn write_pack(dir: &Path, output: &Path) -> Result<(), Error> {
+    let file = fs::File::create(output).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create {}: {e}", output.display()),
+    })?;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    builder
+        .append_dir_all(".", dir)
+        .and_then(|_| builder.into_inner())
+        .and_then(|encoder| encoder.finish())
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write {}: {e}", output.display()),
+        })?;
+    Ok(())
+}
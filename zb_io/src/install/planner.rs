@@ -5,18 +5,34 @@
 //! - Fetching formulas from API or taps
 //! - Resolving dependency trees
 
-use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use futures::stream::{FuturesUnordered, StreamExt};
 
+use crate::db::InstalledKeg;
+use crate::materialize::find_bottle_content;
+use crate::plan_file::PlanFile;
 use crate::tap::TapFormula;
 
-use zb_core::{Error, Formula, SelectedBottle, resolve_closure, select_bottle};
+use zb_core::{
+    Error, Formula, LinkConflictType, SelectedBottle, check_requirements, resolve_closure,
+    select_bottle_with_tag,
+};
 
 use super::Installer;
 
+/// Tap of the formulas served by the main API
+pub(crate) const CORE_TAP: &str = "homebrew/core";
+
 /// Maximum concurrent formula fetches to avoid overwhelming the API
-const MAX_CONCURRENT_FETCHES: usize = 12;
+pub(crate) const MAX_CONCURRENT_FETCHES: usize = 12;
+
+/// Dependency trees with more formulas than this are resolved from the API's
+/// full formula index, one cached download, instead of a request per formula
+pub(crate) const INDEX_PREFETCH_THRESHOLD: usize = 24;
 
 /// An installation plan containing formulas and their selected bottles
 #[derive(Debug)]
@@ -25,36 +41,188 @@ pub struct InstallPlan {
     pub bottles: Vec<SelectedBottle>,
     /// The name of the root package (the one explicitly requested by the user)
     pub root_name: String,
+    /// Every package explicitly requested, by canonical name; `root_name`
+    /// is the first of them
+    pub roots: Vec<String>,
+    /// Time spent fetching formulas and resolving the dependency closure
+    pub resolve_time: Duration,
+    /// Third-party tap formulas with no bottle for this platform, built
+    /// from source once the bottles are poured, in dependency order
+    pub source_builds: Vec<Formula>,
+}
+
+impl InstallPlan {
+    /// The requested package each package of the plan is installed for:
+    /// itself when it was requested, otherwise the first requested package
+    /// depending on it
+    pub fn requested_by(&self) -> HashMap<String, String> {
+        let dependencies: HashMap<&str, Vec<String>> = self
+            .formulas
+            .iter()
+            .map(|f| (f.name.as_str(), dependency_names(f)))
+            .collect();
+
+        let mut requested_by: HashMap<String, String> = self
+            .roots
+            .iter()
+            .map(|root| (root.clone(), root.clone()))
+            .collect();
+        for root in &self.roots {
+            let mut visited = HashSet::new();
+            let mut pending = vec![root.as_str()];
+            while let Some(name) = pending.pop() {
+                for dep in dependencies.get(name).into_iter().flatten() {
+                    if visited.insert(dep.as_str()) {
+                        requested_by
+                            .entry(dep.clone())
+                            .or_insert_with(|| root.clone());
+                        pending.push(dep);
+                    }
+                }
+            }
+        }
+        requested_by
+    }
+}
+
+/// Bottles are gzipped tarballs, which typically unpack to about this many
+/// times their size
+const UNPACKED_SIZE_RATIO: u64 = 3;
+
+/// Expected download volume and disk usage of an install plan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadEstimate {
+    /// Bytes still to be downloaded, for bottles whose size is known
+    pub bytes: u64,
+    /// Bottles already in the blob cache
+    pub cached: usize,
+    /// Bottles whose size the server didn't report
+    pub unknown: usize,
+    /// Disk space the packages take once poured, for those whose size is
+    /// known
+    pub installed_bytes: u64,
+    /// Each package of the plan, in plan order
+    pub packages: Vec<PackageEstimate>,
+}
+
+/// Expected download and disk usage of one package of an install plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageEstimate {
+    pub name: String,
+    pub version: String,
+    /// Bytes to download: 0 for a cached bottle, `None` if the server
+    /// didn't report the size
+    pub download_bytes: Option<u64>,
+    /// Disk space once poured: measured if the bottle is already in the
+    /// store, otherwise estimated from the bottle's size
+    pub installed_bytes: Option<u64>,
+    /// Whether the bottle is already in the blob cache
+    pub cached: bool,
+}
+
+/// Bottles published for a formula and whether one can be poured here
+#[derive(Debug)]
+pub struct BottleAvailability {
+    /// Bottles by platform tag, in tag order
+    pub bottles: Vec<AvailableBottle>,
+    /// Tag of the bottle an install on this host would pour
+    pub selected: Option<String>,
+    /// Why installing on this host would fail, if it would
+    pub unsupported: Option<Error>,
+}
+
+/// A bottle built for one platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvailableBottle {
+    /// Platform tag, e.g. `arm64_sonoma` or `x86_64_linux`
+    pub tag: String,
+    /// Download size, when the server reports it
+    pub size: Option<u64>,
+}
+
+/// An executable a plan would link where something else already is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedLinkConflict {
+    /// Package of the plan that would link it
+    pub formula: String,
+    /// Where it would be linked
+    pub path: PathBuf,
+    pub existing_type: LinkConflictType,
+    /// Formula whose keg owns what is there, installed or in the same plan
+    pub owner: Option<String>,
+}
+
+impl PlannedLinkConflict {
+    /// The error linking would have failed with
+    pub fn to_error(&self) -> Error {
+        Error::LinkConflict {
+            path: self.path.clone(),
+            existing_type: self.existing_type.clone(),
+        }
+    }
 }
 
 impl Installer {
     /// Resolve dependencies and plan the install
     pub async fn plan(&self, name: &str) -> Result<InstallPlan, Error> {
-        // Recursively fetch all formulas we need
-        let formulas = self.fetch_all_formulas(name).await?;
+        self.plan_many(&[name]).await
+    }
 
-        // Resolve in topological order
-        let ordered = resolve_closure(name, &formulas)?;
+    /// Plan installing several formulas at once. Their dependency closures
+    /// are merged, so dependencies they share are installed once.
+    pub async fn plan_many(&self, names: &[&str]) -> Result<InstallPlan, Error> {
+        let start = Instant::now();
+
+        // Recursively fetch all formulas we need
+        let formulas = self.fetch_closures(names).await?;
+
+        // Resolve each closure in topological order, under the root's own
+        // name if it was asked for by an alias. Appending the packages not
+        // seen yet keeps every dependency ahead of its dependents.
+        let mut ordered = Vec::new();
+        let mut seen = HashSet::new();
+        let mut roots: Vec<String> = Vec::new();
+        for name in names {
+            let closure = resolve_closure(name, &formulas)?;
+            let root = closure.last().cloned().unwrap_or_else(|| name.to_string());
+            for formula_name in closure {
+                if seen.insert(formula_name.clone()) {
+                    ordered.push(formula_name);
+                }
+            }
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
 
         // Build list of formulas in order, selecting bottles
         // Skip dependencies that don't have compatible bottles (e.g., macOS-only packages)
         let mut result_formulas = Vec::new();
         let mut bottles = Vec::new();
+        let mut source_builds = Vec::new();
 
         for formula_name in &ordered {
             let formula = formulas.get(formula_name).cloned().unwrap();
-            match select_bottle(&formula) {
+
+            // Fail before downloading anything that can't run on this OS
+            check_requirements(&formula, &self.host)?;
+            self.check_not_disabled(&formula)?;
+
+            match select_bottle_with_tag(&formula, self.bottle_tag.as_deref()) {
                 Ok(bottle) => {
                     result_formulas.push(formula);
                     bottles.push(bottle);
                 }
-                Err(Error::UnsupportedBottle { .. }) if formula_name != name => {
+                Err(Error::UnsupportedBottle { .. }) if builds_from_source(&formula) => {
+                    source_builds.push(formula);
+                }
+                Err(Error::UnsupportedBottle { .. }) if !roots.contains(formula_name) => {
                     // Skip dependencies without compatible bottles (e.g., libiconv on Linux)
-                    // But fail if the root package doesn't have a compatible bottle
-                    eprintln!(
-                        "    Note: skipping dependency '{}' (no compatible bottle for this platform)",
+                    // But fail if a requested package doesn't have a compatible bottle
+                    self.context.logger.note(format!(
+                        "skipping dependency '{}' (no compatible bottle for this platform)",
                         formula_name
-                    );
+                    ));
                     continue;
                 }
                 Err(e) => return Err(e),
@@ -64,11 +232,252 @@ impl Installer {
         Ok(InstallPlan {
             formulas: result_formulas,
             bottles,
-            root_name: name.to_string(),
+            root_name: roots.first().cloned().unwrap_or_default(),
+            roots,
+            resolve_time: start.elapsed(),
+            source_builds,
         })
     }
 
-    /// Fetch a single formula, checking taps if it's a tap reference
+    /// Turn an exported plan back into an install plan for this machine.
+    ///
+    /// Nothing is resolved again: the plan's own metadata and bottles are
+    /// used as written. Fails if the plan was edited, if a package can't run
+    /// here, or if this machine would pour a different bottle than the one
+    /// planned.
+    pub fn plan_from_file(&self, file: PlanFile) -> Result<InstallPlan, Error> {
+        let start = Instant::now();
+        file.verify()?;
+
+        for package in &file.packages {
+            check_requirements(&package.formula, &self.host)?;
+            self.check_not_disabled(&package.formula)?;
+            let selected = select_bottle_with_tag(&package.formula, self.bottle_tag.as_deref())?;
+            if selected.tag != package.bottle.tag {
+                return Err(Error::StoreCorruption {
+                    message: format!(
+                        "plan installs the {} bottle of {}, but this machine pours {} (use --bottle-tag {} to force it)",
+                        package.bottle.tag, package.name, selected.tag, package.bottle.tag
+                    ),
+                });
+            }
+        }
+
+        let (root_name, formulas, bottles) = file.into_parts();
+        Ok(InstallPlan {
+            formulas,
+            bottles,
+            roots: vec![root_name.clone()],
+            root_name,
+            resolve_time: start.elapsed(),
+            source_builds: Vec::new(),
+        })
+    }
+
+    /// Refuse formulas upstream has disabled, unless allowed
+    pub(crate) fn check_not_disabled(&self, formula: &Formula) -> Result<(), Error> {
+        match formula.deprecation_message() {
+            Some(message) if formula.disabled && !self.allow_disabled => {
+                Err(Error::DisabledFormula {
+                    name: formula.name.clone(),
+                    message,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Which platforms `formula` has bottles for, with their sizes, and
+    /// whether it can be installed on this host
+    pub async fn bottle_availability(&self, formula: &Formula) -> BottleAvailability {
+        let files = &formula.bottle.stable.files;
+        let urls = files.values().map(|file| file.url.clone()).collect();
+        let bottles = files
+            .keys()
+            .zip(self.downloader.remote_sizes(urls).await)
+            .map(|(tag, size)| AvailableBottle {
+                tag: tag.clone(),
+                size,
+            })
+            .collect();
+
+        let selected = check_requirements(formula, &self.host)
+            .and_then(|()| select_bottle_with_tag(formula, self.bottle_tag.as_deref()));
+        let (selected, unsupported) = match selected {
+            Ok(bottle) => (Some(bottle.tag), None),
+            Err(e) => (None, Some(e)),
+        };
+
+        BottleAvailability {
+            bottles,
+            selected,
+            unsupported,
+        }
+    }
+
+    /// Whether `name` has to be built from source: a formula from a
+    /// third-party tap with no bottle for this platform, but a source
+    /// tarball to build from. Such taps often bottle only some platforms,
+    /// if any.
+    pub async fn needs_source_build(&self, name: &str) -> Result<bool, Error> {
+        let formula = self.fetch_formula(name).await?;
+        match select_bottle_with_tag(&formula, self.bottle_tag.as_deref()) {
+            Ok(_) => Ok(false),
+            Err(Error::UnsupportedBottle { .. }) => Ok(builds_from_source(&formula)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Every executable `plan` would link onto something already there,
+    /// found before anything is downloaded.
+    ///
+    /// A package's executables are known up front when its bottle is in the
+    /// store already, or from the links of a version installed before.
+    /// Packages with neither are left for linking to check.
+    pub fn link_conflicts(&self, plan: &InstallPlan) -> Vec<PlannedLinkConflict> {
+        let mut conflicts = Vec::new();
+        // Executables claimed by earlier packages of the plan
+        let mut claimed: HashMap<OsString, (String, PathBuf)> = HashMap::new();
+
+        for (formula, bottle) in plan.formulas.iter().zip(&plan.bottles) {
+            let keg_path = self
+                .cellar
+                .keg_path(&formula.name, &formula.effective_version());
+            for file_name in self.planned_executables(formula, bottle) {
+                let Some((path, existing_type)) = self.linker.bin_conflict(&file_name) else {
+                    if let Some((owner, target)) = claimed.get(&file_name) {
+                        conflicts.push(PlannedLinkConflict {
+                            formula: formula.name.clone(),
+                            path: self.context.paths.prefix.join("bin").join(&file_name),
+                            existing_type: LinkConflictType::SymlinkToOther {
+                                target: target.clone(),
+                            },
+                            owner: Some(owner.clone()),
+                        });
+                    } else {
+                        let target = keg_path.join("bin").join(&file_name);
+                        claimed.insert(file_name, (formula.name.clone(), target));
+                    }
+                    continue;
+                };
+
+                let owner = match &existing_type {
+                    LinkConflictType::SymlinkToOther { target } => self.cellar.keg_owner(target),
+                    _ => None,
+                };
+                // Another version of the same formula hands its links over
+                if owner.as_deref() != Some(formula.name.as_str()) {
+                    conflicts.push(PlannedLinkConflict {
+                        formula: formula.name.clone(),
+                        path,
+                        existing_type,
+                        owner,
+                    });
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    /// Names of the executables `formula` would link, as far as they are
+    /// known before downloading its bottle
+    fn planned_executables(&self, formula: &Formula, bottle: &SelectedBottle) -> Vec<OsString> {
+        if self.store.has_entry(&bottle.sha256) {
+            let entry = self.store.entry_path(&bottle.sha256);
+            let Ok(content) =
+                find_bottle_content(&entry, &formula.name, &formula.effective_version())
+            else {
+                return Vec::new();
+            };
+            let Ok(entries) = std::fs::read_dir(content.join("bin")) else {
+                return Vec::new();
+            };
+            let mut names: Vec<OsString> = entries.flatten().map(|e| e.file_name()).collect();
+            names.sort();
+            return names;
+        }
+
+        let bin_dir = self.context.paths.prefix.join("bin");
+        self.db
+            .get_linked_files(&formula.name)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(link_path, _)| {
+                let link_path = PathBuf::from(link_path);
+                (link_path.parent() == Some(bin_dir.as_path()))
+                    .then(|| link_path.file_name().map(OsStr::to_os_string))
+                    .flatten()
+            })
+            .collect()
+    }
+
+    /// Estimate how much an install plan will download and how much disk
+    /// its packages will take, asking the server for the size of each
+    /// bottle that isn't cached yet
+    pub async fn estimate_download(&self, plan: &InstallPlan) -> DownloadEstimate {
+        let mut urls = Vec::new();
+        for bottle in &plan.bottles {
+            if !self.blob_cache.has_blob(&bottle.sha256) {
+                urls.push(bottle.url.clone());
+            }
+        }
+        let mut remote_sizes = self.downloader.remote_sizes(urls).await.into_iter();
+
+        let mut estimate = DownloadEstimate::default();
+        for (formula, bottle) in plan.formulas.iter().zip(&plan.bottles) {
+            let cached = self.blob_cache.has_blob(&bottle.sha256);
+            let (download_bytes, bottle_bytes) = if cached {
+                let blob_bytes = std::fs::metadata(self.blob_cache.blob_path(&bottle.sha256))
+                    .ok()
+                    .map(|meta| meta.len());
+                (Some(0), blob_bytes)
+            } else {
+                let size = remote_sizes.next().flatten();
+                (size, size)
+            };
+            let installed_bytes = self
+                .poured_size(&bottle.sha256)
+                .or(bottle_bytes.map(|bytes| bytes * UNPACKED_SIZE_RATIO));
+
+            if cached {
+                estimate.cached += 1;
+            }
+            match download_bytes {
+                Some(bytes) => estimate.bytes += bytes,
+                None => estimate.unknown += 1,
+            }
+            estimate.installed_bytes += installed_bytes.unwrap_or(0);
+            estimate.packages.push(PackageEstimate {
+                name: formula.name.clone(),
+                version: formula.effective_version(),
+                download_bytes,
+                installed_bytes,
+                cached,
+            });
+        }
+
+        estimate
+    }
+
+    /// Disk size of the store entry a bottle was poured into, if it has
+    /// been, recording it the first time it is measured
+    fn poured_size(&self, store_key: &str) -> Option<u64> {
+        if let Some(bytes) = self.db.store_size(store_key) {
+            return Some(bytes);
+        }
+        if !self.store.has_entry(store_key) {
+            return None;
+        }
+        let bytes = crate::dedup::disk_usage([self.store.entry_path(store_key).as_path()]).ok()?;
+        let _ = self.db.record_store_size(store_key, bytes);
+        Some(bytes)
+    }
+
+    /// Fetch a single formula, checking taps if it's a tap reference.
+    ///
+    /// Pinned taps are searched before homebrew/core, the remaining taps
+    /// only when core has no such formula.
     pub(crate) async fn fetch_formula(&self, name: &str) -> Result<Formula, Error> {
         // Check if this is a tap formula reference (user/repo/formula)
         if let Some(tap_ref) = TapFormula::parse(name) {
@@ -78,13 +487,26 @@ impl Installer {
                 .await;
         }
 
-        // Try the main API first
+        let (pinned, unpinned): (Vec<_>, Vec<_>) = self
+            .db
+            .list_taps()
+            .unwrap_or_default()
+            .into_iter()
+            .partition(|tap| tap.pinned);
+        for tap in &pinned {
+            if let Some((user, repo)) = tap.name.split_once('/')
+                && let Ok(formula) = self.tap_manager.get_formula(user, repo, name).await
+            {
+                return Ok(formula);
+            }
+        }
+
+        // Then the main API
         match self.api_client.get_formula(name).await {
             Ok(formula) => Ok(formula),
             Err(Error::MissingFormula { .. }) => {
-                // Try installed taps in order
-                let taps = self.db.list_taps().unwrap_or_default();
-                for tap in &taps {
+                // Try the other installed taps in order
+                for tap in &unpinned {
                     let parts: Vec<&str> = tap.name.split('/').collect();
                     if parts.len() == 2
                         && let Ok(formula) =
@@ -102,6 +524,42 @@ impl Installer {
         }
     }
 
+    /// Fetch a dependency of a formula from `tap`.
+    ///
+    /// Like Homebrew, an unqualified dependency of a tap formula is looked up
+    /// in that tap before homebrew/core.
+    async fn fetch_dependency(&self, name: &str, tap: Option<&str>) -> Result<Formula, Error> {
+        if TapFormula::parse(name).is_none()
+            && let Some((user, repo)) = tap.and_then(|tap| tap.split_once('/'))
+            && let Ok(formula) = self.tap_manager.get_formula(user, repo, name).await
+        {
+            return Ok(formula);
+        }
+        self.fetch_formula(name).await
+    }
+
+    /// Name to install a dependency of a formula from `tap` by, qualified
+    /// with the tap it resolves to unless that is homebrew/core
+    pub(crate) async fn dependency_ref(&self, name: &str, tap: Option<&str>) -> String {
+        match self.fetch_dependency(name, tap).await {
+            Ok(Formula {
+                tap: Some(tap),
+                name: formula,
+                ..
+            }) if tap != CORE_TAP => format!("{tap}/{formula}"),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Fetch the formula an installed keg was installed from, from its tap
+    /// when that isn't homebrew/core
+    pub(crate) async fn installed_formula(&self, keg: &InstalledKeg) -> Result<Formula, Error> {
+        match tap_ref(keg) {
+            Some(name) => self.fetch_formula(&name).await,
+            None => self.api_client.get_formula(&keg.name).await,
+        }
+    }
+
     /// Recursively fetch a formula and all its dependencies using streaming parallelism.
     ///
     /// Unlike batch processing which waits for all formulas in a batch to complete,
@@ -110,16 +568,28 @@ impl Installer {
     pub(crate) async fn fetch_all_formulas(
         &self,
         name: &str,
+    ) -> Result<BTreeMap<String, Formula>, Error> {
+        self.fetch_closures(&[name]).await
+    }
+
+    /// Fetch several formulas and all their dependencies, each only once
+    pub(crate) async fn fetch_closures(
+        &self,
+        names: &[&str],
     ) -> Result<BTreeMap<String, Formula>, Error> {
         let mut formulas = BTreeMap::new();
         let mut queued: HashSet<String> = HashSet::new();
         let mut skipped: HashSet<String> = HashSet::new();
-        let mut pending: VecDeque<String> = VecDeque::new();
-        let root_name = name.to_string();
-
-        // Start with the root package
-        pending.push_back(name.to_string());
-        queued.insert(name.to_string());
+        let mut prefetched = false;
+        // Packages still to fetch, with the tap of the formula that needs them
+        let mut pending: VecDeque<(String, Option<String>)> = VecDeque::new();
+
+        // Start with the root packages
+        for name in names {
+            if queued.insert(name.to_string()) {
+                pending.push_back((name.to_string(), None));
+            }
+        }
 
         // Use FuturesUnordered for streaming - process results as they complete
         let mut in_flight: FuturesUnordered<_> = FuturesUnordered::new();
@@ -127,9 +597,9 @@ impl Installer {
         loop {
             // Fill up to MAX_CONCURRENT_FETCHES
             while in_flight.len() < MAX_CONCURRENT_FETCHES && !pending.is_empty() {
-                let pkg_name = pending.pop_front().unwrap();
+                let (pkg_name, tap) = pending.pop_front().unwrap();
                 let future = async move {
-                    let result = self.fetch_formula(&pkg_name).await;
+                    let result = self.fetch_dependency(&pkg_name, tap.as_deref()).await;
                     (pkg_name, result)
                 };
                 in_flight.push(future);
@@ -146,20 +616,32 @@ impl Installer {
             match result {
                 Ok(formula) => {
                     // Immediately queue dependencies (streaming benefit!)
+                    let tap = formula.tap.clone().filter(|tap| tap != CORE_TAP);
                     for dep in formula.effective_dependencies() {
                         if !queued.contains(&dep) && !skipped.contains(&dep) {
                             queued.insert(dep.clone());
-                            pending.push_back(dep);
+                            pending.push_back((dep, tap.clone()));
                         }
                     }
+                    if !prefetched && queued.len() > INDEX_PREFETCH_THRESHOLD {
+                        prefetched = true;
+                        self.api_client.prefetch_index().await;
+                    }
+                    // An alias such as `postgresql` fetches `postgresql@17`, and
+                    // `user/repo/foo` fetches `foo`; keep the formula under its
+                    // own name too so the resolver installs it once, under that
+                    // name
+                    if formula.name != pkg_name && queued.insert(formula.name.clone()) {
+                        formulas.insert(formula.name.clone(), formula.clone());
+                    }
                     formulas.insert(pkg_name, formula);
                 }
-                Err(Error::MissingFormula { .. }) if pkg_name != root_name => {
+                Err(Error::MissingFormula { .. }) if !names.contains(&pkg_name.as_str()) => {
                     // Skip missing dependencies (e.g., uses_from_macos like "python")
-                    eprintln!(
-                        "    Note: skipping dependency '{}' (formula not found)",
+                    self.context.logger.note(format!(
+                        "skipping dependency '{}' (formula not found)",
                         pkg_name
-                    );
+                    ));
                     skipped.insert(pkg_name);
                 }
                 Err(e) => return Err(e),
@@ -170,6 +652,39 @@ impl Installer {
     }
 }
 
+/// `user/repo/formula` for a keg installed from a tap other than homebrew/core
+pub(crate) fn tap_ref(keg: &InstalledKeg) -> Option<String> {
+    keg.receipt
+        .as_ref()
+        .and_then(|receipt| receipt.tap.as_deref())
+        .filter(|tap| *tap != CORE_TAP)
+        .map(|tap| format!("{tap}/{}", keg.name))
+}
+
+/// Whether `formula`, lacking a bottle for this platform, can be built from
+/// source instead: a third-party tap formula with a source tarball
+pub(crate) fn builds_from_source(formula: &Formula) -> bool {
+    formula.tap.as_deref().is_some_and(|tap| tap != CORE_TAP) && formula.urls.stable.is_some()
+}
+
+/// `user/repo/formula` to build a source build of a plan by
+pub(crate) fn source_ref(formula: &Formula) -> String {
+    match formula.tap.as_deref() {
+        Some(tap) if tap != CORE_TAP => format!("{tap}/{}", formula.name),
+        _ => formula.name.clone(),
+    }
+}
+
+/// Dependencies of `formula` by the names they are installed under, without
+/// any `user/repo/` qualifier
+pub(crate) fn dependency_names(formula: &Formula) -> Vec<String> {
+    formula
+        .effective_dependencies()
+        .iter()
+        .map(|dep| TapFormula::formula_name(dep).to_string())
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -211,9 +726,7 @@ mod tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         )
     }
 
@@ -379,6 +892,115 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn plan_rejects_dependency_requiring_newer_macos() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let root_json = r#"{
+            "name": "newapp",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": ["newlib"],
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        let dep_json = r#"{
+            "name": "newlib",
+            "versions": { "stable": "2.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+            "requirements": [
+                { "name": "macos", "version": "14", "contexts": [], "specs": ["stable"] }
+            ]
+        }"#;
+
+        Mock::given(method("GET"))
+            .and(path("/newapp.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(root_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/newlib.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(dep_json))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer_for_planner(&mock_server, &tmp);
+        installer.host = zb_core::Host::new(
+            zb_core::HostPlatform::MacOS(zb_core::MacOSVersion::parse("13.6")),
+            "arm64",
+        );
+
+        // Rejected by its requirements before bottle selection is attempted
+        match installer.plan("newapp").await.unwrap_err() {
+            Error::UnsupportedPlatform {
+                name, requirement, ..
+            } => {
+                assert_eq!(name, "newlib");
+                assert_eq!(requirement, "macOS 14+");
+            }
+            e => panic!("Expected UnsupportedPlatform error, got: {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn plan_uses_forced_bottle_tag_fallback() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let json = r#"{
+            "name": "tagged",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {
+                "arm64_ventura": { "url": "http://x/v.tar.gz", "sha256": "vvv" },
+                "x86_64_linux": { "url": "http://x/l.tar.gz", "sha256": "lll" }
+            }}}
+        }"#;
+
+        Mock::given(method("GET"))
+            .and(path("/tagged.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(json))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer_for_planner(&mock_server, &tmp);
+        installer.set_bottle_tag("arm64_sequoia");
+
+        let plan = installer.plan("tagged").await.unwrap();
+        assert_eq!(plan.bottles[0].tag, "arm64_ventura");
+        assert_eq!(plan.bottles[0].sha256, "vvv");
+    }
+
+    #[tokio::test]
+    async fn plan_suggests_installing_command_line_tools() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let json = r#"{
+            "name": "needsclt",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } },
+            "requirements": [
+                { "name": "xcode", "version": null, "contexts": [] }
+            ]
+        }"#;
+
+        Mock::given(method("GET"))
+            .and(path("/needsclt.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(json))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer_for_planner(&mock_server, &tmp);
+        installer.host = zb_core::Host::new(zb_core::HostPlatform::MacOS(None), "arm64")
+            .with_developer_tools(None);
+
+        let err = installer.plan("needsclt").await.unwrap_err();
+        assert!(matches!(err, Error::MissingRequirement { .. }));
+        assert!(err.to_string().contains("xcode-select --install"));
+    }
+
     #[tokio::test]
     async fn fetch_all_formulas_deduplicates_shared_deps() {
         let mock_server = MockServer::start().await;
@@ -447,4 +1069,50 @@ mod tests {
         assert!(formulas.contains_key("b"));
         assert!(formulas.contains_key("shared"));
     }
+
+    #[tokio::test]
+    async fn pinned_tap_takes_precedence_over_core() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/jq.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name":"jq","tap":"homebrew/core","versions":{"stable":"1.7.1"}}"#,
+            ))
+            .mount(&mock_server)
+            .await;
+
+        // A tap carrying its own jq, already cached locally
+        let formula_dir = tmp.path().join("zerobrew/taps/user/tools/Formula");
+        fs::create_dir_all(&formula_dir).unwrap();
+        fs::write(
+            formula_dir.join("jq.json"),
+            r#"{"name":"jq","versions":{"stable":"1.8.0"}}"#,
+        )
+        .unwrap();
+
+        let installer = create_test_installer_for_planner(&mock_server, &tmp);
+        installer
+            .db
+            .add_tap("user/tools", "https://github.com/user/homebrew-tools")
+            .unwrap();
+
+        let formula = installer.fetch_formula("jq").await.unwrap();
+        assert_eq!(formula.tap.as_deref(), Some("homebrew/core"));
+
+        installer.pin_tap("user", "homebrew-tools").unwrap();
+        let formula = installer.fetch_formula("jq").await.unwrap();
+        assert_eq!(formula.tap.as_deref(), Some("user/tools"));
+        assert_eq!(formula.effective_version(), "1.8.0");
+
+        installer.unpin_tap("user", "tools").unwrap();
+        let formula = installer.fetch_formula("jq").await.unwrap();
+        assert_eq!(formula.tap.as_deref(), Some("homebrew/core"));
+
+        assert!(matches!(
+            installer.pin_tap("nobody", "none"),
+            Err(Error::MissingFormula { .. })
+        ));
+    }
 }
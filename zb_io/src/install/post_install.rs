@@ -0,0 +1,302 @@
+//! Formula `post_install` steps
+//!
+//! This module handles:
+//! - Resolving step paths against the keg and the prefix
+//! - Running the steps of freshly installed formulas, unless opted out
+//! - Logging what ran to `<root>/logs/<name>/post_install.log`
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::formula::PostInstallStep;
+use zb_core::{Error, Formula};
+
+use super::Installer;
+use crate::tap::TapFormula;
+
+/// What running a formula's post-install steps did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostInstallResult {
+    /// Steps that ran successfully
+    pub ran: usize,
+    /// Statements zerobrew can't run, as written in the formula
+    pub unsupported: Vec<String>,
+    /// Why a step failed; the steps after it did not run
+    pub error: Option<String>,
+    /// Where the steps and their output were logged
+    pub log_path: PathBuf,
+}
+
+/// Directory a `post_install` path helper such as `var` or `bin` stands for
+fn helper_path(helper: &str, name: &str, keg_path: &Path, prefix: &Path) -> Option<PathBuf> {
+    let opt = prefix.join("opt").join(name);
+    Some(match helper {
+        "prefix" => keg_path.to_path_buf(),
+        "bin" | "sbin" | "lib" | "libexec" | "include" | "share" => keg_path.join(helper),
+        "pkgshare" => keg_path.join("share").join(name),
+        "etc" | "var" => prefix.join(helper),
+        "HOMEBREW_PREFIX" => prefix.to_path_buf(),
+        "opt_prefix" => opt,
+        "opt_pkgshare" => opt.join("share").join(name),
+        _ => opt.join(helper.strip_prefix("opt_")?),
+    })
+}
+
+/// Replace the `{helper}` placeholders of a step path
+pub(crate) fn resolve_step_path(
+    template: &str,
+    name: &str,
+    keg_path: &Path,
+    prefix: &Path,
+) -> String {
+    let mut resolved = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        resolved.push_str(&rest[..start]);
+        let helper = &rest[start + 1..start + len];
+        match helper_path(helper, name, keg_path, prefix) {
+            Some(path) => resolved.push_str(&path.to_string_lossy()),
+            None => resolved.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    resolved.push_str(rest);
+    resolved
+}
+
+impl Installer {
+    /// Skip post-install steps for every formula installed from now on
+    pub fn set_skip_post_install(&mut self, skip: bool) {
+        self.skip_post_install = skip;
+    }
+
+    /// Where the last post-install run of `name` was logged
+    pub fn post_install_log_path(&self, name: &str) -> PathBuf {
+        self.store
+            .root()
+            .join("logs")
+            .join(name)
+            .join("post_install.log")
+    }
+
+    /// Stop running `name`'s post-install steps after installs.
+    /// Returns false if they were already skipped.
+    pub fn disable_post_install(&self, name: &str) -> Result<bool, Error> {
+        self.db
+            .add_post_install_skip(TapFormula::formula_name(name))
+    }
+
+    /// Run `name`'s post-install steps after installs again.
+    /// Returns false if they weren't skipped.
+    pub fn enable_post_install(&self, name: &str) -> Result<bool, Error> {
+        self.db
+            .remove_post_install_skip(TapFormula::formula_name(name))
+    }
+
+    /// Formulas whose post-install steps are skipped
+    pub fn list_post_install_disabled(&self) -> Result<Vec<String>, Error> {
+        self.db.list_post_install_skips()
+    }
+
+    /// Run the post-install steps of an installed formula now, even if they
+    /// are skipped after installs
+    pub async fn post_install(&mut self, name: &str) -> Result<PostInstallResult, Error> {
+        let formula = self.get_formula(name).await?;
+        let installed = self
+            .db
+            .get_installed(&formula.name)
+            .ok_or(Error::NotInstalled {
+                name: name.to_string(),
+            })?;
+
+        let _lock = self.store.lock_formula(&formula.name)?;
+        let keg_path = self.cellar.keg_path(&formula.name, &installed.version);
+        self.run_post_install(&formula, &keg_path)
+    }
+
+    /// Run the post-install steps of formulas an install just recorded, with
+    /// their kegs, in install order. Failures are reported but don't undo
+    /// the install.
+    pub(crate) fn post_install_all<'a>(
+        &self,
+        installed: impl IntoIterator<Item = (&'a Formula, PathBuf)>,
+    ) {
+        if self.skip_post_install {
+            return;
+        }
+        let skipped = self.db.list_post_install_skips().unwrap_or_default();
+
+        for (formula, keg_path) in installed {
+            if !formula.post_install_defined || skipped.contains(&formula.name) {
+                continue;
+            }
+            match self.run_post_install(formula, &keg_path) {
+                Ok(result) => {
+                    if let Some(error) = &result.error {
+                        self.context.logger.warn(format!(
+                            "post-install of {} failed: {}; see {}",
+                            formula.name,
+                            error,
+                            result.log_path.display()
+                        ));
+                    } else if !result.unsupported.is_empty() {
+                        self.context.logger.note(format!(
+                            "skipped {} post-install step(s) of {} zerobrew can't run; see {}",
+                            result.unsupported.len(),
+                            formula.name,
+                            result.log_path.display()
+                        ));
+                    }
+                }
+                Err(e) => self
+                    .context
+                    .logger
+                    .warn(format!("post-install of {} failed: {}", formula.name, e)),
+            }
+        }
+    }
+
+    /// Run `formula`'s post-install steps against `keg_path`, logging each
+    /// step and its output. Stops at the first failing step.
+    pub(crate) fn run_post_install(
+        &self,
+        formula: &Formula,
+        keg_path: &Path,
+    ) -> Result<PostInstallResult, Error> {
+        let log_path = self.post_install_log_path(&formula.name);
+        let mut log = open_log(&log_path)?;
+        let mut result = PostInstallResult {
+            log_path,
+            ..Default::default()
+        };
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let _ = writeln!(
+            log,
+            "==> post_install for {} {} at {}",
+            formula.name,
+            formula.effective_version(),
+            now
+        );
+        if formula.post_install.is_empty() {
+            let _ = writeln!(
+                log,
+                "post_install is defined upstream, but its steps are only available from the formula's Ruby source"
+            );
+        }
+
+        let resolve = |template: &str| {
+            resolve_step_path(
+                template,
+                &formula.name,
+                keg_path,
+                &self.context.paths.prefix,
+            )
+        };
+        for step in &formula.post_install {
+            match step {
+                PostInstallStep::Mkpath { path } => {
+                    let path = resolve(path);
+                    let _ = writeln!(log, "mkdir -p {path}");
+                    if let Err(e) = fs::create_dir_all(&path) {
+                        result.error = Some(format!("failed to create {path}: {e}"));
+                        break;
+                    }
+                }
+                PostInstallStep::System { args } => {
+                    let args: Vec<String> = args.iter().map(|arg| resolve(arg)).collect();
+                    let _ = writeln!(log, "{}", args.join(" "));
+                    if let Err(e) = self.run_step_command(&args, keg_path, &mut log) {
+                        result.error = Some(e);
+                        break;
+                    }
+                }
+                PostInstallStep::Unsupported { source } => {
+                    let _ = writeln!(log, "skipped (unsupported): {source}");
+                    result.unsupported.push(source.clone());
+                    continue;
+                }
+            }
+            result.ran += 1;
+        }
+
+        if let Some(error) = &result.error {
+            let _ = writeln!(log, "error: {error}");
+        }
+        Ok(result)
+    }
+
+    /// Run a `system` step from the keg with `prefix/bin` first on PATH,
+    /// appending its output to `log`
+    fn run_step_command(
+        &self,
+        args: &[String],
+        keg_path: &Path,
+        log: &mut fs::File,
+    ) -> Result<(), String> {
+        let (program, args) = args.split_first().ok_or("empty command")?;
+        let mut path = vec![self.context.paths.prefix.join("bin")];
+        if let Some(existing) = std::env::var_os("PATH") {
+            path.extend(std::env::split_paths(&existing));
+        }
+
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(keg_path)
+            .env(
+                "PATH",
+                std::env::join_paths(path).map_err(|e| e.to_string())?,
+            )
+            .output()
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        let _ = log.write_all(&output.stdout);
+        let _ = log.write_all(&output.stderr);
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!("{program} exited with {}", output.status))
+        }
+    }
+}
+
+/// Open the post-install log at `path`, replacing the previous run's
+fn open_log(path: &Path) -> Result<fs::File, Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create log directory {}: {e}", dir.display()),
+        })?;
+    }
+    fs::File::create(path).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create post-install log {}: {e}", path.display()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_paths_resolve_like_homebrew_helpers() {
+        let keg = Path::new("/zb/Cellar/pgtool/14.0");
+        let prefix = Path::new("/zb");
+        let resolve = |template| resolve_step_path(template, "pgtool", keg, prefix);
+
+        assert_eq!(resolve("{var}/pgtool"), "/zb/var/pgtool");
+        assert_eq!(resolve("{bin}/initdb"), "/zb/Cellar/pgtool/14.0/bin/initdb");
+        assert_eq!(resolve("{pkgshare}"), "/zb/Cellar/pgtool/14.0/share/pgtool");
+        assert_eq!(resolve("{opt_bin}/pg"), "/zb/opt/pgtool/bin/pg");
+        assert_eq!(resolve("{HOMEBREW_PREFIX}/etc"), "/zb/etc");
+        assert_eq!(resolve("--data={var}/x"), "--data=/zb/var/x");
+        // Unknown placeholders and stray braces are left alone
+        assert_eq!(resolve("{nope}/x{"), "{nope}/x{");
+    }
+}
@@ -0,0 +1,198 @@
+//! PATH shadowing detection
+//!
+//! This module handles:
+//! - Resolving a command through PATH the way a shell would
+//! - Finding linked executables that an earlier PATH directory hides
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+use super::Installer;
+
+/// A linked executable hidden by another one earlier in PATH
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedCommand {
+    /// Executable name
+    pub name: String,
+    /// The executable zerobrew linked into `prefix/bin`
+    pub linked: PathBuf,
+    /// The executable the shell runs instead
+    pub shadowed_by: PathBuf,
+}
+
+/// Where a command resolves to, in PATH order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandResolution {
+    /// Command name
+    pub name: String,
+    /// Every executable with that name in PATH, first one wins
+    pub candidates: Vec<PathBuf>,
+    /// The executable in `prefix/bin`, if zerobrew provides one
+    pub linked: Option<PathBuf>,
+    /// The formula the linked executable belongs to
+    pub formula: Option<String>,
+    /// Whether `prefix/bin` is in PATH at all
+    pub prefix_bin_in_path: bool,
+}
+
+impl CommandResolution {
+    /// The executable the shell runs, if any
+    pub fn resolved(&self) -> Option<&Path> {
+        self.candidates.first().map(PathBuf::as_path)
+    }
+
+    /// True if zerobrew links the command but something earlier in PATH wins
+    pub fn is_shadowed(&self) -> bool {
+        self.linked.is_some()
+            && self.prefix_bin_in_path
+            && self
+                .resolved()
+                .and_then(Path::parent)
+                .is_some_and(|dir| !self.is_prefix_bin(dir))
+    }
+
+    fn is_prefix_bin(&self, dir: &Path) -> bool {
+        self.linked
+            .as_deref()
+            .and_then(Path::parent)
+            .is_some_and(|bin| same_dir(bin, dir))
+    }
+}
+
+impl Installer {
+    /// Resolve `name` through `path_var` and note what zerobrew links for it
+    pub fn which(&self, name: &str, path_var: &str) -> CommandResolution {
+        let bin_dir = self.context.paths.prefix.join("bin");
+        let linked = bin_dir.join(name);
+        let linked = is_executable(&linked).then_some(linked);
+        let formula = linked.as_deref().and_then(|path| self.owning_formula(path));
+
+        CommandResolution {
+            name: name.to_string(),
+            candidates: path_candidates(name, path_var),
+            linked,
+            formula,
+            prefix_bin_in_path: std::env::split_paths(path_var).any(|dir| same_dir(&dir, &bin_dir)),
+        }
+    }
+
+    /// Linked executables that resolve to something else through `path_var`.
+    ///
+    /// Looks at the executables linked for `formula`, or at everything in
+    /// `prefix/bin` when no formula is given. Nothing is reported when
+    /// `prefix/bin` isn't in PATH at all.
+    pub fn shadowed_commands(
+        &self,
+        formula: Option<&str>,
+        path_var: &str,
+    ) -> Result<Vec<ShadowedCommand>, Error> {
+        let bin_dir = self.context.paths.prefix.join("bin");
+        let mut names: Vec<String> = match formula {
+            Some(name) => self
+                .db
+                .get_linked_files(name)?
+                .into_iter()
+                .map(|(link, _)| PathBuf::from(link))
+                .filter(|link| link.parent().is_some_and(|dir| dir == bin_dir))
+                .filter_map(|link| Some(link.file_name()?.to_string_lossy().to_string()))
+                .collect(),
+            None => match std::fs::read_dir(&bin_dir) {
+                Ok(entries) => entries
+                    .flatten()
+                    .map(|entry| entry.file_name().to_string_lossy().to_string())
+                    .collect(),
+                Err(_) => Vec::new(),
+            },
+        };
+        names.sort();
+
+        Ok(names
+            .iter()
+            .map(|name| self.which(name, path_var))
+            .filter(CommandResolution::is_shadowed)
+            .filter_map(|resolution| {
+                Some(ShadowedCommand {
+                    shadowed_by: resolution.resolved()?.to_path_buf(),
+                    linked: resolution.linked?,
+                    name: resolution.name,
+                })
+            })
+            .collect())
+    }
+
+    /// The installed formula whose keg a linked path points into
+    fn owning_formula(&self, path: &Path) -> Option<String> {
+        let target = path.canonicalize().ok()?;
+        self.db.list_installed().ok()?.into_iter().find_map(|keg| {
+            let keg_path = self.cellar.keg_path(&keg.name, &keg.version);
+            let keg_path = keg_path.canonicalize().ok()?;
+            target.starts_with(keg_path).then_some(keg.name)
+        })
+    }
+}
+
+/// Every executable named `name` in `path_var`, in PATH order
+fn path_candidates(name: &str, path_var: &str) -> Vec<PathBuf> {
+    let mut seen: Vec<PathBuf> = Vec::new();
+    let mut candidates = Vec::new();
+    for dir in std::env::split_paths(path_var) {
+        if dir.as_os_str().is_empty() || seen.iter().any(|d| same_dir(d, &dir)) {
+            continue;
+        }
+        let path = dir.join(name);
+        if is_executable(&path) {
+            candidates.push(path);
+        }
+        seen.push(dir);
+    }
+    candidates
+}
+
+/// Compare directories, looking through symlinks where possible
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_executable(dir: &Path, name: &str) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn path_candidates_follow_path_order() {
+        let tmp = TempDir::new().unwrap();
+        let first = tmp.path().join("first");
+        let second = tmp.path().join("second");
+        let tool_second = write_executable(&second, "tool");
+        let tool_first = write_executable(&first, "tool");
+        std::fs::write(first.join("data"), "not executable").unwrap();
+
+        let path_var = std::env::join_paths([&first, &second, &first]).unwrap();
+        let path_var = path_var.to_str().unwrap();
+
+        assert_eq!(
+            path_candidates("tool", path_var),
+            vec![tool_first, tool_second]
+        );
+        assert!(path_candidates("data", path_var).is_empty());
+        assert!(path_candidates("missing", path_var).is_empty());
+    }
+}
@@ -104,9 +104,7 @@ fn create_test_installer(mock_server: &MockServer, tmp: &TempDir) -> Installer {
         linker,
         db,
         tap_manager,
-        prefix.clone(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     )
 }
 
@@ -384,9 +382,7 @@ async fn gc_does_not_remove_referenced_store_entries() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install but don't uninstall
@@ -509,9 +505,7 @@ async fn install_with_dependencies() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install main package (should also install dependency)
@@ -626,9 +620,7 @@ async fn parallel_api_fetching_with_deep_deps() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install root (should install all 5 packages)
@@ -727,9 +719,7 @@ async fn streaming_extraction_processes_as_downloads_complete() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install slow package (which depends on fast)
@@ -842,9 +832,7 @@ async fn retries_on_corrupted_download() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install - should succeed (first download is valid in this test)
@@ -975,9 +963,7 @@ async fn install_skips_macos_only_uses_from_macos_deps() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install main package - should succeed despite macos-only-dep not having Linux bottles
@@ -995,7 +981,7 @@ async fn install_skips_macos_only_uses_from_macos_deps() {
 }
 
 #[tokio::test]
-async fn upgrade_installs_new_version_and_removes_old() {
+async fn upgrade_keeps_old_version_until_gc() {
     let mock_server = MockServer::start().await;
     let tmp = TempDir::new().unwrap();
     let tag = platform_bottle_tag();
@@ -1112,9 +1098,7 @@ async fn upgrade_installs_new_version_and_removes_old() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install old version
@@ -1146,8 +1130,42 @@ async fn upgrade_installs_new_version_and_removes_old() {
     assert!(root.join("cellar/upgrademe/2.0.0").exists());
     assert!(prefix.join("bin/upgrademe").exists());
 
-    // Verify old version removed
+    // Old version is kept and can be switched back to
+    assert!(root.join("cellar/upgrademe/1.0.0").exists());
+    let versions: Vec<_> = installer
+        .list_versions("upgrademe")
+        .unwrap()
+        .into_iter()
+        .map(|v| v.version)
+        .collect();
+    assert_eq!(versions, vec!["1.0.0", "2.0.0"]);
+
+    let switched = installer.switch("upgrademe", "1.0.0").unwrap();
+    assert_eq!(switched.files_linked, 1);
+    assert_eq!(
+        installer.get_installed("upgrademe").unwrap().store_key,
+        old_sha
+    );
+    assert_eq!(
+        fs::read_link(prefix.join("bin/upgrademe")).unwrap(),
+        root.join("cellar/upgrademe/1.0.0/bin/upgrademe")
+    );
+    assert!(
+        installer
+            .switch("upgrademe", "1.0.0")
+            .unwrap()
+            .already_linked
+    );
+
+    installer.switch("upgrademe", "2.0.0").unwrap();
+
+    // GC removes the inactive keg and its store entry
+    let removed = installer.gc().unwrap();
+    assert_eq!(removed, vec![old_sha]);
     assert!(!root.join("cellar/upgrademe/1.0.0").exists());
+    assert!(root.join("cellar/upgrademe/2.0.0").exists());
+    assert_eq!(installer.list_versions("upgrademe").unwrap().len(), 1);
+    assert!(prefix.join("bin/upgrademe").exists());
 }
 
 #[tokio::test]
@@ -1220,9 +1238,7 @@ async fn upgrade_returns_none_when_up_to_date() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install
@@ -1266,9 +1282,7 @@ async fn upgrade_not_installed_returns_error() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Try to upgrade a package that's not installed
@@ -1401,9 +1415,7 @@ async fn upgrade_all_upgrades_multiple_packages() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install both packages at v1
@@ -1425,7 +1437,11 @@ async fn upgrade_all_upgrades_multiple_packages() {
     assert_eq!(installer.get_installed("pkg1").unwrap().version, "2.0.0");
     assert_eq!(installer.get_installed("pkg2").unwrap().version, "2.0.0");
 
-    // Verify old kegs removed
+    // Old kegs are kept until GC
+    assert!(root.join("cellar/pkg1/1.0.0").exists());
+    assert!(root.join("cellar/pkg2/1.0.0").exists());
+
+    installer.gc().unwrap();
     assert!(!root.join("cellar/pkg1/1.0.0").exists());
     assert!(!root.join("cellar/pkg2/1.0.0").exists());
 }
@@ -1480,9 +1496,7 @@ async fn upgrade_all_empty_when_all_current() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install
@@ -1576,9 +1590,7 @@ async fn upgrade_preserves_links() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install with linking
@@ -1653,9 +1665,7 @@ async fn pin_and_unpin_package() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install
@@ -1717,9 +1727,7 @@ async fn pin_not_installed_returns_error() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Try to pin a package that's not installed
@@ -1836,9 +1844,7 @@ async fn pinned_packages_excluded_from_get_outdated() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install both packages at v1
@@ -1861,6 +1867,20 @@ async fn pinned_packages_excluded_from_get_outdated() {
     assert_eq!(outdated_with_pinned.len(), 2);
     assert!(outdated_with_pinned.iter().any(|p| p.name == "pkg1"));
     assert!(outdated_with_pinned.iter().any(|p| p.name == "pkg2"));
+
+    // Progress counts every checked formula, pinned ones only when included
+    let mut reported = Vec::new();
+    installer
+        .get_outdated_with_progress(false, false, |done, total| reported.push((done, total)))
+        .await
+        .unwrap();
+    assert_eq!(reported, vec![(0, 1), (1, 1)]);
+    reported.clear();
+    installer
+        .get_outdated_with_progress(true, false, |done, total| reported.push((done, total)))
+        .await
+        .unwrap();
+    assert_eq!(reported, vec![(0, 2), (1, 2), (2, 2)]);
 }
 
 #[tokio::test]
@@ -1934,9 +1954,7 @@ async fn install_marks_root_as_explicit_and_deps_as_dependency() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install root package (should also install deppkg as dependency)
@@ -2029,9 +2047,7 @@ async fn find_orphans_returns_unused_dependencies() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install mypkg (which installs mydep as dependency)
@@ -2121,9 +2137,7 @@ async fn autoremove_removes_orphaned_dependencies() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install parent (which installs child as dependency)
@@ -2217,9 +2231,7 @@ async fn mark_explicit_prevents_autoremove() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install app (which installs lib as dependency)
@@ -2271,9 +2283,7 @@ async fn mark_explicit_not_installed_returns_error() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Marking a non-installed package as explicit should fail
@@ -2345,9 +2355,7 @@ async fn cleanup_removes_unused_blobs_and_store_entries() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install and then uninstall
@@ -2450,9 +2458,7 @@ async fn cleanup_dry_run_does_not_remove_files() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install and then uninstall
@@ -2539,9 +2545,7 @@ async fn cleanup_keeps_installed_package_blobs() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install but DON'T uninstall
@@ -2633,9 +2637,7 @@ async fn link_creates_symlinks() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install without linking
@@ -2728,9 +2730,7 @@ async fn unlink_removes_symlinks_but_keeps_installed() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install with linking
@@ -2826,9 +2826,7 @@ async fn link_already_linked_returns_already_linked() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install with linking
@@ -2865,9 +2863,7 @@ async fn link_not_installed_returns_error() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Try to link non-existent package
@@ -2900,9 +2896,7 @@ async fn unlink_not_installed_returns_error() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Try to unlink non-existent package
@@ -2979,9 +2973,7 @@ async fn unlink_then_relink_works() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Install with linking
@@ -3030,9 +3022,7 @@ async fn is_linked_returns_false_for_uninstalled_package() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // is_linked should return false for uninstalled package
@@ -3111,9 +3101,7 @@ async fn get_deps_returns_direct_dependencies() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Get direct deps (non-recursive)
@@ -3234,9 +3222,7 @@ async fn get_leaves_returns_packages_not_depended_on() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Get leaves - should be independent and dependent (not deplib which is depended on)
@@ -3272,9 +3258,7 @@ async fn doctor_checks_run_without_panic() {
         linker,
         db,
         tap_manager,
-        prefix.to_path_buf(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     );
 
     // Doctor should run without panicking
@@ -3288,33 +3272,6 @@ async fn doctor_checks_run_without_panic() {
     assert_eq!(result.errors, 0);
 }
 
-#[test]
-fn copy_dir_recursive_copies_all_files() {
-    let src = TempDir::new().unwrap();
-    let dst = TempDir::new().unwrap();
-
-    // Create source structure
-    fs::create_dir_all(src.path().join("bin")).unwrap();
-    fs::create_dir_all(src.path().join("lib/pkgconfig")).unwrap();
-    fs::write(src.path().join("bin/foo"), "binary").unwrap();
-    fs::write(src.path().join("lib/libfoo.so"), "library").unwrap();
-    fs::write(src.path().join("lib/pkgconfig/foo.pc"), "pkgconfig").unwrap();
-
-    // Copy
-    super::copy_dir_recursive(src.path(), dst.path()).unwrap();
-
-    // Verify
-    assert!(dst.path().join("bin/foo").exists());
-    assert!(dst.path().join("lib/libfoo.so").exists());
-    assert!(dst.path().join("lib/pkgconfig/foo.pc").exists());
-
-    // Verify content
-    assert_eq!(
-        fs::read_to_string(dst.path().join("bin/foo")).unwrap(),
-        "binary"
-    );
-}
-
 #[test]
 fn source_build_result_fields() {
     let result = super::SourceBuildResult {
@@ -3619,9 +3576,7 @@ mod error_path_tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         );
 
         // Attempt install - should fail due to permission denied
@@ -4139,6 +4094,58 @@ mod error_path_tests {
         assert!(!ctx.installer().is_installed("faildb"));
     }
 
+    /// Test that a plan failing partway rolls back the packages that had
+    /// already been extracted, materialized and linked.
+    #[tokio::test]
+    async fn test_failed_plan_rolls_back_completed_packages() {
+        let mut ctx = TestContext::new().await;
+
+        let dep_sha = ctx.mount_formula("rbdep", "1.0.0", &[]).await;
+        // The root's bottle fails only after the dependency has been processed
+        let failing = mock_500_error(None).set_delay(Duration::from_millis(500));
+        let root_sha = "b".repeat(64);
+        ctx.mount_formula_with_bottle_response("rbapp", "1.0.0", &["rbdep"], failing, &root_sha)
+            .await;
+
+        let result = ctx.installer_mut().install("rbapp", true).await;
+        assert!(result.is_err());
+
+        assert!(!ctx.cellar().join("rbdep/1.0.0").exists());
+        assert!(ctx.prefix().join("bin/rbdep").symlink_metadata().is_err());
+        assert!(ctx.prefix().join("opt/rbdep").symlink_metadata().is_err());
+        assert!(!ctx.store().join(&dep_sha).exists());
+        assert!(!ctx.installer().is_installed("rbdep"));
+        assert!(!ctx.installer().is_installed("rbapp"));
+
+        // The downloaded bottle is kept, so a retry doesn't fetch it again
+        assert!(ctx.installer().blob_cache.has_blob(&dep_sha));
+    }
+
+    /// Rolling back an unlinked reinstall of the same version keeps the
+    /// `opt/` link it found.
+    #[tokio::test]
+    async fn test_failed_reinstall_keeps_existing_opt_link() {
+        let mut ctx = TestContext::new().await;
+
+        ctx.mount_formula("rbkeep", "1.0.0", &[]).await;
+        let failing = mock_500_error(None).set_delay(Duration::from_millis(500));
+        ctx.mount_formula_with_bottle_response("rbbroken", "1.0.0", &[], failing, &"c".repeat(64))
+            .await;
+
+        ctx.installer_mut().install("rbkeep", false).await.unwrap();
+        let keg = ctx.installer().keg_path("rbkeep").unwrap();
+
+        let plan = ctx
+            .installer()
+            .plan_many(&["rbkeep", "rbbroken"])
+            .await
+            .unwrap();
+        assert!(ctx.installer_mut().execute(plan, false).await.is_err());
+
+        assert_eq!(fs::read_link(ctx.prefix().join("opt/rbkeep")).unwrap(), keg);
+        assert!(ctx.installer().is_installed("rbkeep"));
+    }
+
     /// Test retry behavior with server that returns 500 on first attempt.
     /// The download mechanism should handle transient server errors gracefully.
     #[tokio::test]
@@ -4299,6 +4306,328 @@ mod orphan_tests {
         assert!(orphans.contains(&"dep_d".to_string()));
     }
 
+    /// `why` walks the recorded dependency edges of the diamond back to A.
+    #[tokio::test]
+    async fn test_why_reports_chains_to_explicit_packages() {
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_d", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "dep_c", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b", "dep_c"]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+
+        let why = ctx.installer().why("dep_d").await.unwrap();
+        assert!(!why.explicit);
+        assert_eq!(
+            why.chains,
+            vec![
+                vec!["pkg_a", "dep_b", "dep_d"],
+                vec!["pkg_a", "dep_c", "dep_d"],
+            ]
+        );
+
+        let why = ctx.installer().why("pkg_a").await.unwrap();
+        assert!(why.explicit);
+        assert!(why.chains.is_empty());
+
+        ctx.installer_mut().uninstall("pkg_a").unwrap();
+        assert!(ctx.installer().why("dep_d").await.unwrap().is_orphan());
+        assert!(matches!(
+            ctx.installer().why("pkg_a").await,
+            Err(zb_core::Error::NotInstalled { .. })
+        ));
+    }
+
+    /// The installed graph and a formula's closure both hold the diamond
+    /// once, with the shared dependency as a single node.
+    #[tokio::test]
+    async fn test_deps_graph_merges_shared_dependencies() {
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_d", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "dep_c", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b", "dep_c"]).await;
+        mount_formula_with_deps(&ctx, "pkg_e", "1.0.0", &["dep_c"]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+
+        let graph = ctx.installer().get_deps_graph(None, false).await.unwrap();
+        assert_eq!(graph.edges.len(), 4);
+        assert_eq!(graph.edges["pkg_a"], vec!["dep_b", "dep_c"]);
+        assert_eq!(graph.edges["dep_c"], vec!["dep_d"]);
+        assert!(graph.edges["dep_d"].is_empty());
+        assert_eq!(graph.installed.len(), 4);
+
+        let graph = ctx
+            .installer()
+            .get_deps_graph(Some("pkg_e"), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            graph.edges.keys().collect::<Vec<_>>(),
+            vec!["dep_c", "dep_d", "pkg_e"]
+        );
+        assert!(!graph.installed.contains("pkg_e"));
+        assert!(graph.installed.contains("dep_c"));
+
+        let graph = ctx
+            .installer()
+            .get_deps_graph(Some("pkg_a"), true)
+            .await
+            .unwrap();
+        assert_eq!(graph.edges["dep_b"], vec!["dep_d"]);
+        assert_eq!(graph.edges.len(), 4);
+    }
+
+    /// Several formulas install in one plan, their shared dependency once.
+    #[tokio::test]
+    async fn test_plan_many_installs_shared_dependencies_once() {
+        use crate::db::Provenance;
+
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_d", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b"]).await;
+        mount_formula_with_deps(&ctx, "pkg_e", "1.0.0", &["dep_d"]).await;
+
+        let plan = ctx
+            .installer()
+            .plan_many(&["pkg_a", "pkg_e", "dep_b"])
+            .await
+            .unwrap();
+        let names: Vec<&str> = plan.formulas.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["dep_d", "dep_b", "pkg_a", "pkg_e"]);
+        assert_eq!(plan.root_name, "pkg_a");
+        assert_eq!(plan.roots, vec!["pkg_a", "pkg_e", "dep_b"]);
+
+        let result = ctx.installer_mut().execute(plan, true).await.unwrap();
+        assert_eq!(result.installed, 4);
+
+        let installer = ctx.installer();
+        assert!(installer.is_installed("pkg_a"));
+        assert!(installer.is_installed("pkg_e"));
+        let keg = |name: &str| installer.get_installed(name).unwrap();
+        assert!(keg("pkg_a").explicit);
+        assert!(keg("pkg_e").explicit);
+        assert!(keg("dep_b").explicit);
+        assert!(!keg("dep_d").explicit);
+        assert_eq!(keg("dep_b").provenance, Some(Provenance::Cli));
+        assert_eq!(
+            keg("dep_d").provenance,
+            Some(Provenance::DependencyOf {
+                name: "pkg_a".to_string()
+            })
+        );
+    }
+
+    /// Orphan detection reads the recorded dependency edges, not the API.
+    #[tokio::test]
+    async fn test_find_orphans_works_offline() {
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_d", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b"]).await;
+        mount_formula_with_deps(&ctx, "pkg_e", "1.0.0", &["dep_d"]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+        ctx.installer_mut().install("pkg_e", true).await.unwrap();
+
+        // Every API request now fails
+        ctx.mock_server.reset().await;
+
+        assert!(ctx.installer().find_orphans().await.unwrap().is_empty());
+
+        ctx.installer_mut().uninstall("pkg_a").unwrap();
+        assert_eq!(ctx.installer().find_orphans().await.unwrap(), vec!["dep_b"]);
+
+        ctx.installer_mut().uninstall("pkg_e").unwrap();
+        let mut orphans = ctx.installer().find_orphans().await.unwrap();
+        orphans.sort();
+        assert_eq!(orphans, vec!["dep_b", "dep_d"]);
+    }
+
+    /// Kept packages and `--except` names are roots, so they and their
+    /// dependencies survive autoremove.
+    #[tokio::test]
+    async fn test_autoremove_spares_kept_and_excepted_packages() {
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_d", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &["dep_d"]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b"]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+        ctx.installer_mut().uninstall("pkg_a").unwrap();
+
+        assert!(ctx.installer().keep("dep_b").unwrap());
+        assert!(!ctx.installer().keep("dep_b").unwrap());
+        assert_eq!(ctx.installer().list_kept().unwrap(), vec!["dep_b"]);
+        assert!(ctx.installer().find_orphans().await.unwrap().is_empty());
+
+        assert!(ctx.installer().unkeep("dep_b").unwrap());
+        let except = vec!["dep_d".to_string()];
+        let removed = ctx
+            .installer_mut()
+            .autoremove_except(&except)
+            .await
+            .unwrap();
+        assert_eq!(removed, vec!["dep_b"]);
+        assert!(ctx.installer().is_installed("dep_d"));
+
+        assert!(matches!(
+            ctx.installer().keep("pkg_a"),
+            Err(zb_core::Error::NotInstalled { .. })
+        ));
+    }
+
+    /// The requested formula records the installer's provenance and its
+    /// dependencies record what pulled them in.
+    #[tokio::test]
+    async fn test_install_records_provenance() {
+        use crate::db::Provenance;
+
+        let mut ctx = TestContext::new().await;
+
+        mount_formula_with_deps(&ctx, "dep_b", "1.0.0", &[]).await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &["dep_b"]).await;
+        mount_formula_with_deps(&ctx, "pkg_c", "1.0.0", &[]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+        let brewfile = Provenance::Brewfile {
+            path: "/work/Brewfile".to_string(),
+            run_id: "run1".to_string(),
+        };
+        ctx.installer_mut().set_provenance(brewfile.clone());
+        ctx.installer_mut().install("pkg_c", true).await.unwrap();
+
+        let provenance = |name: &str| ctx.installer().get_installed(name).unwrap().provenance;
+        assert_eq!(provenance("pkg_a"), Some(Provenance::Cli));
+        assert_eq!(
+            provenance("dep_b"),
+            Some(Provenance::DependencyOf {
+                name: "pkg_a".to_string()
+            })
+        );
+        assert_eq!(provenance("pkg_c"), Some(brewfile));
+    }
+
+    /// Bottle installs record a receipt in the database and the keg.
+    #[tokio::test]
+    async fn test_install_records_bottle_receipt() {
+        use crate::receipt::{BuiltFrom, RECEIPT_FILE};
+
+        let mut ctx = TestContext::new().await;
+        mount_formula_with_deps(&ctx, "pkg_a", "1.0.0", &[]).await;
+
+        ctx.installer_mut().install("pkg_a", true).await.unwrap();
+
+        let receipt = ctx
+            .installer()
+            .get_installed("pkg_a")
+            .unwrap()
+            .receipt
+            .unwrap();
+        assert_eq!(receipt.built_from, BuiltFrom::Bottle);
+        assert!(receipt.build_args.is_empty());
+
+        let file = ctx.cellar().join("pkg_a/1.0.0").join(RECEIPT_FILE);
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+        assert_eq!(written["poured_from_bottle"], true);
+        assert_eq!(written["source"]["spec"], "stable");
+    }
+
+    fn git_commit(repo: &std::path::Path, message: &str) {
+        std::fs::write(repo.join("CHANGELOG"), message).unwrap();
+        for args in [
+            &["add", "CHANGELOG"][..],
+            &["commit", "-q", "-m", message][..],
+        ] {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(args)
+                .env("GIT_AUTHOR_NAME", "test")
+                .env("GIT_AUTHOR_EMAIL", "test@example.com")
+                .env("GIT_COMMITTER_NAME", "test")
+                .env("GIT_COMMITTER_EMAIL", "test@example.com")
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    /// Test that `--fetch-HEAD` compares HEAD installs against the upstream branch tip.
+    #[tokio::test]
+    async fn test_outdated_fetch_head_detects_new_commits() {
+        use crate::build::{git_head_commit, head_version};
+
+        let mut ctx = TestContext::new().await;
+
+        let repo = ctx.tmp.path().join("neovim-src");
+        std::fs::create_dir(&repo).unwrap();
+        let status = std::process::Command::new("git")
+            .args(["init", "-q", "-b", "main"])
+            .arg(&repo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        git_commit(&repo, "first");
+        let built = git_head_commit(&repo).unwrap();
+
+        let formula_json = format!(
+            r#"{{
+                "name": "neovim",
+                "versions": {{ "stable": "0.10.0" }},
+                "dependencies": [],
+                "bottle": {{ "stable": {{ "files": {{}} }} }},
+                "urls": {{ "head": {{ "url": "file://{}", "branch": "main" }} }}
+            }}"#,
+            repo.display()
+        );
+        Mock::given(method("GET"))
+            .and(path("/neovim.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&ctx.mock_server)
+            .await;
+
+        {
+            let db = &mut ctx.installer_mut().db;
+            let tx = db.transaction().unwrap();
+            tx.record_install("neovim", &head_version(&built), "source-neovim", true)
+                .unwrap();
+            tx.record_head_commit("neovim", &built).unwrap();
+            tx.commit().unwrap();
+        }
+
+        // Up to date with upstream, and never compared against stable
+        assert!(ctx.installer().get_outdated().await.unwrap().is_empty());
+        assert!(
+            ctx.installer()
+                .get_outdated_fetch_head()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        git_commit(&repo, "second");
+        let tip = git_head_commit(&repo).unwrap();
+
+        let outdated = ctx.installer().get_outdated_fetch_head().await.unwrap();
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].name, "neovim");
+        assert_eq!(outdated[0].installed_version, head_version(&built));
+        assert_eq!(outdated[0].available_version, head_version(&tip));
+
+        // Without --fetch-HEAD the network is never asked
+        assert!(ctx.installer().get_outdated().await.unwrap().is_empty());
+    }
+
     /// Test cascade autoremove: after removing some orphans, check if others become orphans.
     /// This tests the scenario where removing an orphan might make its dependencies orphans too.
     ///
@@ -5433,6 +5762,7 @@ mod orphan_tests {
 // Tap resolution and dependency handling tests
 // ============================================================================
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tap_and_dependency_tests {
     use super::*;
     use crate::tap::{TapFormula, TapInfo, TapManager};
@@ -5593,9 +5923,7 @@ mod tap_and_dependency_tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         );
 
         // Fetch formula - should fall back to tap
@@ -5676,9 +6004,7 @@ mod tap_and_dependency_tests {
             linker,
             db,
             tap_manager,
-            prefix.clone(),
-            prefix.join("Cellar"),
-            4,
+            zb_core::Context::new(&root, &prefix),
         );
 
         // Fetch with explicit tap reference (user/repo/formula format)
@@ -5693,64 +6019,288 @@ mod tap_and_dependency_tests {
         assert_eq!(formula.versions.stable, "2.5.0");
     }
 
-    // ========================================================================
-    // Dependency cycle detection tests
-    // ========================================================================
+    #[tokio::test]
+    async fn install_by_tap_name_takes_dependencies_from_the_tap() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
 
-    fn test_formula(name: &str, deps: &[&str]) -> Formula {
-        let mut files = BTreeMap::new();
-        files.insert(
-            "arm64_linux".to_string(),
-            BottleFile {
-                url: format!("https://example.com/{name}.tar.gz"),
-                sha256: "deadbeef".repeat(8),
-            },
-        );
+        // homebrew/core has an older `bar` than the tap
+        let core_bar = mock_formula_json("bar", "1.0.0", &[], &mock_server.uri(), "0000");
+        Mock::given(method("GET"))
+            .and(path("/bar.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&core_bar))
+            .mount(&mock_server)
+            .await;
 
-        Formula {
-            name: name.to_string(),
-            versions: Versions {
-                stable: "1.0.0".to_string(),
-            },
-            dependencies: deps.iter().map(|d| d.to_string()).collect(),
-            uses_from_macos: vec![],
-            bottle: Bottle {
-                stable: BottleStable { files, rebuild: 0 },
-            },
-            ..Default::default()
+        let tap_formula_dir = tmp.path().join("zerobrew/taps/myuser/myrepo/Formula");
+        fs::create_dir_all(&tap_formula_dir).unwrap();
+        for (name, deps) in [("foo", &["bar"][..]), ("bar", &[][..])] {
+            let bottle = mock_bottle_tarball_with_version(name, "2.0.0");
+            let sha = sha256_hex(&bottle);
+            fs::write(
+                tap_formula_dir.join(format!("{name}.json")),
+                mock_formula_json(name, "2.0.0", deps, &mock_server.uri(), &sha),
+            )
+            .unwrap();
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-2.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
         }
-    }
-
-    #[test]
-    fn dependency_cycle_detection_simple_cycle() {
-        // A -> B -> A (simple 2-node cycle)
-        let mut formulas = BTreeMap::new();
-        formulas.insert("pkga".to_string(), test_formula("pkga", &["pkgb"]));
-        formulas.insert("pkgb".to_string(), test_formula("pkgb", &["pkga"]));
-
-        let result = resolve_closure("pkga", &formulas);
-        assert!(result.is_err(), "Expected cycle detection error");
 
-        match result.unwrap_err() {
-            zb_core::Error::DependencyCycle { cycle } => {
-                assert!(!cycle.is_empty(), "Cycle should contain package names");
-                // Both packages should be in the cycle
-                assert!(cycle.contains(&"pkga".to_string()) || cycle.contains(&"pkgb".to_string()));
-            }
-            other => panic!("Expected DependencyCycle error, got: {:?}", other),
-        }
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("myuser/myrepo/foo", true).await.unwrap();
+
+        // Installed and looked up under the formula's own name
+        let foo = installer.get_installed("myuser/myrepo/foo").unwrap();
+        assert_eq!(foo.name, "foo");
+        assert!(foo.explicit);
+        assert_eq!(foo.receipt.unwrap().tap.as_deref(), Some("myuser/myrepo"));
+        assert!(installer.is_installed("foo"));
+
+        let bar = installer.get_installed("bar").unwrap();
+        assert_eq!(bar.version, "2.0.0");
+        assert!(!bar.explicit);
+        assert_eq!(installer.get_dependents("bar").await.unwrap(), ["foo"]);
     }
 
-    #[test]
-    fn dependency_cycle_detection_three_node_cycle() {
-        // A -> B -> C -> A (3-node cycle)
-        let mut formulas = BTreeMap::new();
-        formulas.insert("alpha".to_string(), test_formula("alpha", &["beta"]));
-        formulas.insert("beta".to_string(), test_formula("beta", &["gamma"]));
-        formulas.insert("gamma".to_string(), test_formula("gamma", &["alpha"]));
-
-        let result = resolve_closure("alpha", &formulas);
-        assert!(result.is_err());
+    #[tokio::test]
+    async fn outdated_report_checks_tap_formulas_against_tap_head() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        let core_bottle = mock_bottle_tarball_with_version("corepkg", "1.0.0");
+        let core_json = mock_formula_json(
+            "corepkg",
+            "1.0.0",
+            &[],
+            &mock_server.uri(),
+            &sha256_hex(&core_bottle),
+        );
+        Mock::given(method("GET"))
+            .and(path("/corepkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&core_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/corepkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(core_bottle))
+            .mount(&mock_server)
+            .await;
+
+        let tap_dir = tmp.path().join("zerobrew/taps/myuser/myrepo");
+        fs::create_dir_all(tap_dir.join("Formula")).unwrap();
+        let tap_bottle = mock_bottle_tarball_with_version("tappkg", "1.0.0");
+        fs::write(
+            tap_dir.join("Formula/tappkg.json"),
+            mock_formula_json(
+                "tappkg",
+                "1.0.0",
+                &[],
+                &mock_server.uri(),
+                &sha256_hex(&tap_bottle),
+            ),
+        )
+        .unwrap();
+        fs::write(
+            tap_dir.join(".tap_info"),
+            r#"{"name":"myuser/myrepo","url":"https://github.com/myuser/homebrew-myrepo","added_at":0}"#,
+        )
+        .unwrap();
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/tappkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(tap_bottle))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.tap_manager =
+            TapManager::new(&tmp.path().join("zerobrew/taps")).with_raw_url(&mock_server.uri());
+        installer.install("corepkg", true).await.unwrap();
+        installer
+            .install("myuser/myrepo/tappkg", true)
+            .await
+            .unwrap();
+
+        // The tap's HEAD has moved on since tappkg was installed
+        Mock::given(method("GET"))
+            .and(path("/myuser/homebrew-myrepo/HEAD/Formula/tappkg.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(mock_formula_json(
+                    "tappkg",
+                    "2.0.0",
+                    &[],
+                    &mock_server.uri(),
+                    "0000",
+                )),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let groups = installer
+            .get_outdated_report(false, false, |_, _| {})
+            .await
+            .unwrap();
+        let sources: Vec<&str> = groups.iter().map(|g| g.source.as_str()).collect();
+        assert_eq!(sources, ["homebrew/core", "myuser/myrepo"]);
+
+        assert!(groups[0].packages.is_empty());
+        assert_eq!(groups[1].packages.len(), 1);
+        assert_eq!(groups[1].packages[0].name, "tappkg");
+        assert_eq!(groups[1].packages[0].available_version, "2.0.0");
+        assert!(groups[1].updated_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn needs_source_build_only_for_unbottled_tap_formulas() {
+        let ctx = crate::test_utils::TestContext::new().await;
+        ctx.mount_formula("corepkg", "1.0.0", &[]).await;
+
+        let tap_formula_dir = ctx.root().join("taps/myuser/myrepo/Formula");
+        fs::create_dir_all(&tap_formula_dir).unwrap();
+        // A Ruby formula with a source tarball but no bottle block
+        let tool = zb_core::parse_ruby_formula(
+            r#"
+class Tool < Formula
+  url "https://example.com/tool-2.0.tar.gz"
+  sha256 "feedface"
+end
+"#,
+            "tool",
+        )
+        .unwrap();
+        fs::write(
+            tap_formula_dir.join("tool.json"),
+            serde_json::to_string(&tool).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            tap_formula_dir.join("bottled.json"),
+            mock_formula_json("bottled", "1.0.0", &[], &ctx.mock_server.uri(), "abc"),
+        )
+        .unwrap();
+
+        let installer = ctx.installer();
+        assert!(
+            installer
+                .needs_source_build("myuser/myrepo/tool")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !installer
+                .needs_source_build("myuser/myrepo/bottled")
+                .await
+                .unwrap()
+        );
+        assert!(!installer.needs_source_build("corepkg").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn plan_many_plans_unbottled_tap_formulas_as_source_builds() {
+        let ctx = crate::test_utils::TestContext::new().await;
+        ctx.mount_formula("corepkg", "1.0.0", &[]).await;
+
+        let tap_formula_dir = ctx.root().join("taps/myuser/myrepo/Formula");
+        fs::create_dir_all(&tap_formula_dir).unwrap();
+        let tool = zb_core::parse_ruby_formula(
+            r#"
+class Tool < Formula
+  url "https://example.com/tool-2.0.tar.gz"
+  sha256 "feedface"
+end
+"#,
+            "tool",
+        )
+        .unwrap();
+        fs::write(
+            tap_formula_dir.join("tool.json"),
+            serde_json::to_string(&tool).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            tap_formula_dir.join("app.json"),
+            mock_formula_json("app", "1.0.0", &["tool"], &ctx.mock_server.uri(), "abc"),
+        )
+        .unwrap();
+
+        let plan = ctx
+            .installer()
+            .plan_many(&["corepkg", "myuser/myrepo/app"])
+            .await
+            .unwrap();
+
+        let bottled: Vec<&str> = plan.formulas.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(bottled, ["corepkg", "app"]);
+        let built: Vec<&str> = plan.source_builds.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(built, ["tool"]);
+        assert_eq!(
+            super::planner::source_ref(&plan.source_builds[0]),
+            "myuser/myrepo/tool"
+        );
+    }
+
+    // ========================================================================
+    // Dependency cycle detection tests
+    // ========================================================================
+
+    fn test_formula(name: &str, deps: &[&str]) -> Formula {
+        let mut files = BTreeMap::new();
+        files.insert(
+            "arm64_linux".to_string(),
+            BottleFile {
+                url: format!("https://example.com/{name}.tar.gz"),
+                sha256: "deadbeef".repeat(8),
+            },
+        );
+
+        Formula {
+            name: name.to_string(),
+            versions: Versions {
+                stable: "1.0.0".to_string(),
+            },
+            dependencies: deps.iter().map(|d| d.to_string()).collect(),
+            uses_from_macos: vec![],
+            bottle: Bottle {
+                stable: BottleStable { files, rebuild: 0 },
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dependency_cycle_detection_simple_cycle() {
+        // A -> B -> A (simple 2-node cycle)
+        let mut formulas = BTreeMap::new();
+        formulas.insert("pkga".to_string(), test_formula("pkga", &["pkgb"]));
+        formulas.insert("pkgb".to_string(), test_formula("pkgb", &["pkga"]));
+
+        let result = resolve_closure("pkga", &formulas);
+        assert!(result.is_err(), "Expected cycle detection error");
+
+        match result.unwrap_err() {
+            zb_core::Error::DependencyCycle { cycle } => {
+                assert!(!cycle.is_empty(), "Cycle should contain package names");
+                // Both packages should be in the cycle
+                assert!(cycle.contains(&"pkga".to_string()) || cycle.contains(&"pkgb".to_string()));
+            }
+            other => panic!("Expected DependencyCycle error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dependency_cycle_detection_three_node_cycle() {
+        // A -> B -> C -> A (3-node cycle)
+        let mut formulas = BTreeMap::new();
+        formulas.insert("alpha".to_string(), test_formula("alpha", &["beta"]));
+        formulas.insert("beta".to_string(), test_formula("beta", &["gamma"]));
+        formulas.insert("gamma".to_string(), test_formula("gamma", &["alpha"]));
+
+        let result = resolve_closure("alpha", &formulas);
+        assert!(result.is_err());
 
         match result.unwrap_err() {
             zb_core::Error::DependencyCycle { cycle } => {
@@ -6162,6 +6712,62 @@ mod tap_and_dependency_tests {
             other => panic!("Expected MissingFormula, got: {:?}", other),
         }
     }
+
+    #[tokio::test]
+    async fn versioned_formulas_install_side_by_side() {
+        // `pgsql@16.4` isn't a formula; it resolves through `pgsql`'s
+        // versioned formulas to `pgsql@16`, which is at 16.4
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/pgsql.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name": "pgsql", "versions": {"stable": "17.0"},
+                    "versioned_formulas": ["pgsql@16", "pgsql@15"]}"#,
+            ))
+            .mount(&mock_server)
+            .await;
+        for (name, version) in [("pgsql@16", "16.4"), ("pgsql@15", "15.8")] {
+            let bottle = mock_bottle_tarball_with_version(name, version);
+            let sha = sha256_hex(&bottle);
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_string(mock_formula_json(
+                        name,
+                        version,
+                        &[],
+                        &mock_server.uri(),
+                        &sha,
+                    )),
+                )
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/bottles/{name}-{version}.{}.bottle.tar.gz",
+                    platform_bottle_tag()
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let plan = installer.plan("pgsql@16.4").await.unwrap();
+        assert_eq!(plan.root_name, "pgsql@16");
+        installer.execute(plan, true).await.unwrap();
+        installer.install("pgsql@15", true).await.unwrap();
+
+        for (name, version) in [("pgsql@16", "16.4"), ("pgsql@15", "15.8")] {
+            let keg = installer.get_installed(name).unwrap();
+            assert_eq!(keg.version, version);
+            assert!(installer.is_explicit(name));
+            assert!(installer.keg_path(name).unwrap().is_dir());
+        }
+        assert!(installer.get_installed("pgsql").is_none());
+    }
 }
 
 // ============================================================================
@@ -6180,21 +6786,42 @@ mod orchestration_tests {
     use wiremock::{Mock, ResponseTemplate};
 
     /// Helper to mount a formula with its bottle for tests
-    async fn mount_formula(
+    pub(super) async fn mount_formula(
+        mock_server: &MockServer,
+        name: &str,
+        version: &str,
+        deps: &[&str],
+    ) -> String {
+        mount_formula_with(mock_server, name, version, deps, serde_json::json!({})).await
+    }
+
+    /// Like [`mount_formula`], with `fields` merged into the formula JSON
+    pub(super) async fn mount_formula_with(
         mock_server: &MockServer,
         name: &str,
         version: &str,
         deps: &[&str],
+        fields: serde_json::Value,
     ) -> String {
         let bottle = mock_bottle_tarball_with_version(name, version);
         let sha = sha256_hex(&bottle);
         let tag = platform_bottle_tag();
 
-        let formula_json = mock_formula_json(name, version, deps, &mock_server.uri(), &sha);
+        let mut formula: serde_json::Value = serde_json::from_str(&mock_formula_json(
+            name,
+            version,
+            deps,
+            &mock_server.uri(),
+            &sha,
+        ))
+        .unwrap();
+        if let (Some(formula), Some(fields)) = (formula.as_object_mut(), fields.as_object()) {
+            formula.extend(fields.clone());
+        }
 
         Mock::given(method("GET"))
             .and(path(format!("/{}.json", name)))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .respond_with(ResponseTemplate::new(200).set_body_json(formula))
             .mount(mock_server)
             .await;
 
@@ -6421,522 +7048,2125 @@ mod orchestration_tests {
         );
     }
 
-    /// Test force reinstall by uninstalling and reinstalling.
-    /// This is the pattern users would use to force a clean reinstall.
+    /// Installing one package must not wait on a lock held for an unrelated
+    /// one, and the installed formula's lock is released afterwards.
     #[tokio::test]
-    async fn force_reinstall_via_uninstall_install() {
+    async fn install_proceeds_while_unrelated_formula_is_locked() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
 
-        mount_formula(&mock_server, "forcepkg", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "lockfree", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
 
-        // Install
-        installer.install("forcepkg", true).await.unwrap();
-        assert!(installer.is_installed("forcepkg"));
-        assert!(root.join("cellar/forcepkg/1.0.0").exists());
-        assert!(prefix.join("bin/forcepkg").exists());
-
-        // Uninstall
-        installer.uninstall("forcepkg").unwrap();
-        assert!(!installer.is_installed("forcepkg"));
-        assert!(!root.join("cellar/forcepkg/1.0.0").exists());
-        assert!(!prefix.join("bin/forcepkg").exists());
+        // Stands in for another zb process working on a different formula
+        let _busy = installer.store.lock_formula("busypkg").unwrap();
 
-        // Reinstall
-        installer.install("forcepkg", true).await.unwrap();
-        assert!(installer.is_installed("forcepkg"));
-        assert!(root.join("cellar/forcepkg/1.0.0").exists());
-        assert!(prefix.join("bin/forcepkg").exists());
+        installer.install("lockfree", true).await.unwrap();
+        assert!(installer.is_installed("lockfree"));
+        assert!(
+            installer
+                .store
+                .try_lock_formula("lockfree")
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            installer
+                .store
+                .try_lock_formula("busypkg")
+                .unwrap()
+                .is_none()
+        );
     }
 
-    // ========================================================================
-    // Install without linking tests
-    // ========================================================================
-
-    /// Test install with link=false doesn't create symlinks.
     #[tokio::test]
-    async fn install_without_link_flag() {
+    async fn list_keg_files_returns_materialized_files() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-        let prefix = tmp.path().join("homebrew");
 
-        mount_formula(&mock_server, "nolinkpkg", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "filespkg", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        assert!(matches!(
+            installer.list_keg_files("filespkg"),
+            Err(Error::NotInstalled { .. })
+        ));
 
-        // Install without linking
-        let result = installer.install("nolinkpkg", false).await;
-        assert!(result.is_ok());
-
-        // Keg should exist
-        assert!(root.join("cellar/nolinkpkg/1.0.0").exists());
-
-        // But no symlinks
-        assert!(!prefix.join("bin/nolinkpkg").exists());
-        assert!(!installer.is_linked("nolinkpkg"));
+        installer.install("filespkg", true).await.unwrap();
 
-        // Package is installed
-        assert!(installer.is_installed("nolinkpkg"));
+        let keg = installer.keg_path("filespkg").unwrap();
+        let files = installer.list_keg_files("filespkg").unwrap();
+        assert!(files.contains(&keg.join("bin/filespkg")));
+        assert!(files.iter().all(|f| f.starts_with(&keg) && !f.is_dir()));
 
-        // Linked files should be empty
-        let linked = installer.get_linked_files("nolinkpkg").unwrap();
-        assert!(linked.is_empty());
+        let linked = installer.get_linked_files("filespkg").unwrap();
+        assert_eq!(linked.len(), 1);
     }
 
-    /// Test that dependencies are linked even when main package link=false.
     #[tokio::test]
-    async fn install_deps_linked_when_main_not_linked() {
+    async fn bundle_env_uses_keg_paths() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let prefix = tmp.path().join("homebrew");
 
-        mount_formula(&mock_server, "linkeddep", "1.0.0", &[]).await;
-        mount_formula(&mock_server, "unlinkpkg", "1.0.0", &["linkeddep"]).await;
+        mount_formula(&mock_server, "envpkg", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        // Installed but not linked: bundle env should still activate it
+        installer.install("envpkg", false).await.unwrap();
 
-        // Install with link=false - deps should still be linked (they follow the flag)
-        let result = installer.install("unlinkpkg", false).await;
-        assert!(result.is_ok());
+        let entries = crate::bundle::parse_brewfile(
+            "brew \"envpkg\"\nbrew \"user/tap/envpkg\"\nbrew \"absentpkg\"",
+        )
+        .unwrap();
+        let env = installer.bundle_env(&entries);
 
-        // Both packages installed
-        assert!(installer.is_installed("unlinkpkg"));
-        assert!(installer.is_installed("linkeddep"));
+        let keg = installer.keg_path("envpkg").unwrap();
+        assert_eq!(env.kegs, vec![keg.clone()]);
+        assert_eq!(env.missing, vec!["absentpkg".to_string()]);
 
-        // Neither is linked when link=false is passed
-        assert!(!prefix.join("bin/unlinkpkg").exists());
-        assert!(!prefix.join("bin/linkeddep").exists());
+        let vars = env.path_vars();
+        assert_eq!(vars[0], ("PATH", vec![keg.join("bin")]));
+        assert!(
+            vars.iter()
+                .any(|(var, dirs)| *var == "CMAKE_PREFIX_PATH" && dirs == &vec![keg.clone()])
+        );
+        assert!(!vars.iter().any(|(var, _)| *var == "MANPATH"));
     }
 
-    // ========================================================================
-    // Rollback and failure handling tests
-    // ========================================================================
-
-    /// Test that a download failure doesn't leave partial state.
     #[tokio::test]
-    async fn download_failure_no_partial_state() {
+    async fn formula_versions_lists_installed_and_store_versions() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
 
-        let tag = platform_bottle_tag();
-        let bottle = mock_bottle_tarball_with_version("faildownload", "1.0.0");
-        let sha = sha256_hex(&bottle);
+        mount_formula(&mock_server, "verpkg", "1.0.0", &[]).await;
 
-        let formula_json =
-            mock_formula_json("faildownload", "1.0.0", &[], &mock_server.uri(), &sha);
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("verpkg", true).await.unwrap();
+
+        let versions = installer.formula_versions("verpkg").await.unwrap();
+        assert_eq!(versions.available.as_deref(), Some("1.0.0"));
+        assert_eq!(versions.installed, vec!["1.0.0".to_string()]);
+        assert_eq!(versions.active.as_deref(), Some("1.0.0"));
+        assert!(versions.in_store.is_empty());
+
+        // The bottle stays in the store after uninstall until gc
+        installer.uninstall("verpkg").unwrap();
+        let versions = installer.formula_versions("verpkg").await.unwrap();
+        assert!(versions.installed.is_empty());
+        assert_eq!(versions.active, None);
+        assert_eq!(versions.in_store, vec!["1.0.0".to_string()]);
+
+        assert!(matches!(
+            installer.formula_versions("nosuchpkg").await,
+            Err(Error::MissingFormula { .. })
+        ));
+    }
 
-        Mock::given(method("GET"))
-            .and(path("/faildownload.json"))
+    #[tokio::test]
+    async fn formula_versions_includes_versioned_formulas() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        for (name, version, versioned) in [
+            ("pyish", "3.13.1", r#"["pyish@3.12", "pyish@3.11"]"#),
+            ("pyish@3.12", "3.12.8", "[]"),
+        ] {
+            Mock::given(method("GET"))
+                .and(path(format!("/{}.json", name)))
+                .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"name": "{name}", "versions": {{"stable": "{version}"}}, "versioned_formulas": {versioned}}}"#
+                )))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let installer = create_test_installer(&mock_server, &tmp);
+        let versions = installer.formula_versions("pyish").await.unwrap();
+
+        assert_eq!(versions.available.as_deref(), Some("3.13.1"));
+        assert_eq!(
+            versions.versioned_formulas,
+            vec![
+                ("pyish@3.12".to_string(), Some("3.12.8".to_string())),
+                ("pyish@3.11".to_string(), None),
+            ]
+        );
+    }
+
+    /// Install `name` 1.0.0, then upgrade it to 2.0.0
+    async fn install_then_upgrade(mock_server: &MockServer, installer: &mut Installer, name: &str) {
+        mount_formula(mock_server, name, "1.0.0", &[]).await;
+        installer.install(name, true).await.unwrap();
+
+        mock_server.reset().await;
+        mount_formula(mock_server, name, "2.0.0", &[]).await;
+        installer.upgrade_one(name, true, None).await.unwrap();
+        assert_eq!(installer.get_installed(name).unwrap().version, "2.0.0");
+    }
+
+    #[tokio::test]
+    async fn rollback_switches_to_previous_keg_and_pins() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        install_then_upgrade(&mock_server, &mut installer, "rbpkg").await;
+
+        let result = installer.rollback("rbpkg", true).unwrap();
+        assert_eq!(result.from_version, "2.0.0");
+        assert_eq!(result.to_version, "1.0.0");
+        assert_eq!(result.source, RollbackSource::Keg);
+        assert_eq!(result.files_linked, 1);
+        assert!(result.pinned);
+
+        assert_eq!(installer.get_installed("rbpkg").unwrap().version, "1.0.0");
+        assert!(installer.is_pinned("rbpkg"));
+        assert_eq!(
+            fs::read_link(installer.context.paths.prefix.join("bin/rbpkg")).unwrap(),
+            installer.keg_path("rbpkg").unwrap().join("bin/rbpkg")
+        );
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_keg_from_store() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        install_then_upgrade(&mock_server, &mut installer, "storepkg").await;
+
+        // The old keg is gone but its bottle is still in the store
+        installer.cellar.remove_keg("storepkg", "1.0.0").unwrap();
+        installer.unlink("storepkg").unwrap();
+
+        let result = installer.rollback("storepkg", false).unwrap();
+        assert_eq!(result.to_version, "1.0.0");
+        assert_eq!(result.source, RollbackSource::Store);
+        assert!(!result.pinned);
+
+        // An unlinked formula stays unlinked
+        assert_eq!(result.files_linked, 0);
+        let keg = installer.get_installed("storepkg").unwrap();
+        assert_eq!(keg.version, "1.0.0");
+        assert!(!keg.linked);
+        assert!(installer.cellar.has_keg("storepkg", "1.0.0"));
+        assert!(!installer.is_pinned("storepkg"));
+    }
+
+    #[tokio::test]
+    async fn rollback_without_previous_version_fails() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "onlyone", "1.0.0", &[]).await;
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("onlyone", true).await.unwrap();
+
+        assert!(matches!(
+            installer.rollback("onlyone", true),
+            Err(Error::NoPreviousVersion { .. })
+        ));
+        assert!(!installer.is_pinned("onlyone"));
+        assert!(matches!(
+            installer.rollback("absent", true),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn uninstall_with_dependents_removes_dependents_first() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // libbase <- libmid <- app, and libbase <- tool; other is unrelated
+        mount_formula(&mock_server, "libbase", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "libmid", "1.0.0", &["libbase"]).await;
+        mount_formula(&mock_server, "app", "1.0.0", &["libmid"]).await;
+        mount_formula(&mock_server, "tool", "1.0.0", &["libbase"]).await;
+        mount_formula(&mock_server, "other", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        for name in ["app", "tool", "other"] {
+            installer.install(name, true).await.unwrap();
+        }
+
+        let closure = installer.dependents_closure("libbase").await.unwrap();
+        assert_eq!(closure, vec!["app", "tool", "libmid", "libbase"]);
+
+        let leaf = installer.dependents_closure("app").await.unwrap();
+        assert_eq!(leaf, vec!["app"]);
+
+        let removed = installer
+            .uninstall_with_dependents("libbase")
+            .await
+            .unwrap();
+        assert_eq!(removed, closure);
+        for name in ["libbase", "libmid", "app", "tool"] {
+            assert!(!installer.is_installed(name), "{name} should be removed");
+        }
+        assert!(installer.is_installed("other"));
+
+        assert!(matches!(
+            installer.dependents_closure("libbase").await,
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn shadowed_commands_reports_earlier_path_entries() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "shadowme", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("shadowme", true).await.unwrap();
+
+        let prefix_bin = installer.context.paths.prefix.join("bin");
+        let system_bin = tmp.path().join("usr/bin");
+        std::fs::create_dir_all(&system_bin).unwrap();
+        let system_tool = system_bin.join("shadowme");
+        std::fs::write(&system_tool, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&system_tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let path_var = std::env::join_paths([&system_bin, &prefix_bin]).unwrap();
+        let path_var = path_var.to_str().unwrap();
+
+        let shadowed = installer
+            .shadowed_commands(Some("shadowme"), path_var)
+            .unwrap();
+        assert_eq!(shadowed.len(), 1);
+        assert_eq!(shadowed[0].name, "shadowme");
+        assert_eq!(shadowed[0].shadowed_by, system_tool);
+        assert_eq!(shadowed[0].linked, prefix_bin.join("shadowme"));
+
+        let resolution = installer.which("shadowme", path_var);
+        assert_eq!(resolution.formula.as_deref(), Some("shadowme"));
+        assert_eq!(resolution.resolved(), Some(system_tool.as_path()));
+        assert_eq!(resolution.candidates.len(), 2);
+        assert!(resolution.is_shadowed());
+
+        let path_var = std::env::join_paths([&prefix_bin, &system_bin]).unwrap();
+        let path_var = path_var.to_str().unwrap();
+        assert!(
+            installer
+                .shadowed_commands(Some("shadowme"), path_var)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn exec_env_puts_formula_keg_before_dependencies() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "execdep", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "execmain", "1.0.0", &["execdep"]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("execmain", false).await.unwrap();
+
+        let env = installer.exec_env("execmain").await.unwrap();
+        let main_keg = installer.keg_path("execmain").unwrap();
+        let dep_keg = installer.keg_path("execdep").unwrap();
+        assert_eq!(env.kegs, vec![main_keg.clone(), dep_keg.clone()]);
+        assert!(env.missing.is_empty());
+        assert_eq!(
+            env.path_vars()[0],
+            ("PATH", vec![main_keg.join("bin"), dep_keg.join("bin")])
+        );
+
+        assert!(matches!(
+            installer.exec_env("absent").await,
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn exec_env_resolves_inactive_version() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        install_then_upgrade(&mock_server, &mut installer, "execver").await;
+
+        let env = installer.exec_env("execver@1.0.0").await.unwrap();
+        assert_eq!(
+            env.kegs,
+            vec![installer.cellar.keg_path("execver", "1.0.0")]
+        );
+
+        let env = installer.exec_env("execver").await.unwrap();
+        assert_eq!(
+            env.kegs,
+            vec![installer.cellar.keg_path("execver", "2.0.0")]
+        );
+
+        assert!(installer.exec_env("execver@3.0.0").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scratch_dir_uses_configured_temp_dir() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let default = installer.scratch_dir("build-foo").unwrap();
+        assert!(default.path().starts_with(tmp.path().join("zerobrew")));
+        drop(default);
+
+        let custom = tmp.path().join("scratch");
+        let abandoned = custom.join(".build-old.tmp.999999999");
+        fs::create_dir_all(&abandoned).unwrap();
+        installer.set_temp_dir(&custom).unwrap();
+        assert!(!abandoned.exists());
+
+        let scratch = installer.scratch_dir("build-foo").unwrap();
+        assert_eq!(scratch.path().parent(), Some(custom.as_path()));
+        let path = scratch.path().to_path_buf();
+        drop(scratch);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn upgrade_swaps_links_to_new_keg() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        install_then_upgrade(&mock_server, &mut installer, "swappkg").await;
+
+        let new_keg = installer.keg_path("swappkg").unwrap();
+        assert!(new_keg.ends_with("swappkg/2.0.0"));
+        assert_eq!(
+            fs::read_link(installer.context.paths.prefix.join("bin/swappkg")).unwrap(),
+            new_keg.join("bin/swappkg")
+        );
+        assert_eq!(
+            fs::read_link(installer.context.paths.prefix.join("opt/swappkg")).unwrap(),
+            new_keg
+        );
+        assert!(installer.cellar.keg_path("swappkg", "1.0.0").exists());
+    }
+
+    #[tokio::test]
+    async fn no_cache_downloads_cached_bottle_again() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let sha = mount_formula(&mock_server, "freshpkg", "1.0.0", &[]).await;
+
+        installer.install("freshpkg", true).await.unwrap();
+        installer.uninstall("freshpkg").unwrap();
+        assert!(installer.blob_cache.has_blob(&sha));
+
+        let bottle_requests = || async {
+            mock_server
+                .received_requests()
+                .await
+                .unwrap()
+                .iter()
+                .filter(|r| r.url.path().starts_with("/bottles/"))
+                .count()
+        };
+        assert_eq!(bottle_requests().await, 1);
+
+        installer.set_no_cache();
+        installer.install("freshpkg", true).await.unwrap();
+        assert_eq!(bottle_requests().await, 2);
+        assert!(installer.blob_cache.has_blob(&sha));
+    }
+
+    #[tokio::test]
+    async fn local_mirror_installs_without_network() {
+        let upstream = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&upstream, "localdep", "1.0.0", &[]).await;
+        mount_formula(&upstream, "localroot", "1.0.0", &["localdep"]).await;
+
+        let installer = create_test_installer(&upstream, &tmp);
+        let mirror_dir = tmp.path().join("mirror");
+        installer
+            .create_mirror(&mirror_dir, &["localroot".to_string()], &[])
+            .await
+            .unwrap();
+        drop(upstream);
+
+        // Nothing is mounted: every request would fail
+        let unreachable = MockServer::start().await;
+        let offline_tmp = TempDir::new().unwrap();
+        let mut offline = create_test_installer(&unreachable, &offline_tmp);
+        assert!(offline.set_mirror(tmp.path().to_str().unwrap()).is_err());
+        offline.set_mirror(mirror_dir.to_str().unwrap()).unwrap();
+
+        offline.install("localroot", true).await.unwrap();
+
+        assert!(offline.is_installed("localroot"));
+        assert!(offline.is_installed("localdep"));
+        assert!(unreachable.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_mirror_refreshes_existing_formulas() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "syncpkg", "1.0.0", &[]).await;
+
+        let installer = create_test_installer(&mock_server, &tmp);
+        let mirror_dir = tmp.path().join("mirror");
+        assert!(installer.sync_mirror(&mirror_dir, &[], &[]).await.is_err());
+        installer
+            .create_mirror(&mirror_dir, &["syncpkg".to_string()], &[])
+            .await
+            .unwrap();
+
+        mock_server.reset().await;
+        mount_formula(&mock_server, "syncpkg", "2.0.0", &[]).await;
+        mount_formula(&mock_server, "syncnew", "1.0.0", &[]).await;
+
+        let result = installer
+            .sync_mirror(&mirror_dir, &["syncnew".to_string()], &[])
+            .await
+            .unwrap();
+
+        assert_eq!(result.formulas, vec!["syncnew", "syncpkg"]);
+        let metadata: serde_json::Value =
+            serde_json::from_slice(&fs::read(mirror_dir.join("api/formula/syncpkg.json")).unwrap())
+                .unwrap();
+        assert_eq!(metadata["versions"]["stable"], "2.0.0");
+        // Old bottles stay for machines that haven't upgraded yet
+        let syncpkg_bottles = result
+            .bottles
+            .iter()
+            .filter(|b| b.to_string_lossy().contains("syncpkg-"))
+            .count();
+        assert_eq!(syncpkg_bottles, 1);
+        let bottle_dir = result.bottles[0].parent().unwrap();
+        let on_disk = fs::read_dir(bottle_dir)
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with("syncpkg-"))
+            .count();
+        assert_eq!(on_disk, 2);
+    }
+
+    /// Test force reinstall by uninstalling and reinstalling.
+    /// This is the pattern users would use to force a clean reinstall.
+    #[tokio::test]
+    async fn force_reinstall_via_uninstall_install() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+
+        mount_formula(&mock_server, "forcepkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Install
+        installer.install("forcepkg", true).await.unwrap();
+        assert!(installer.is_installed("forcepkg"));
+        assert!(root.join("cellar/forcepkg/1.0.0").exists());
+        assert!(prefix.join("bin/forcepkg").exists());
+
+        // Uninstall
+        installer.uninstall("forcepkg").unwrap();
+        assert!(!installer.is_installed("forcepkg"));
+        assert!(!root.join("cellar/forcepkg/1.0.0").exists());
+        assert!(!prefix.join("bin/forcepkg").exists());
+
+        // Reinstall
+        installer.install("forcepkg", true).await.unwrap();
+        assert!(installer.is_installed("forcepkg"));
+        assert!(root.join("cellar/forcepkg/1.0.0").exists());
+        assert!(prefix.join("bin/forcepkg").exists());
+    }
+
+    // ========================================================================
+    // Install without linking tests
+    // ========================================================================
+
+    /// Test install with link=false doesn't create symlinks.
+    #[tokio::test]
+    async fn install_without_link_flag() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+
+        mount_formula(&mock_server, "nolinkpkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Install without linking
+        let result = installer.install("nolinkpkg", false).await;
+        assert!(result.is_ok());
+
+        // Keg should exist
+        assert!(root.join("cellar/nolinkpkg/1.0.0").exists());
+
+        // But no symlinks
+        assert!(!prefix.join("bin/nolinkpkg").exists());
+        assert!(!installer.is_linked("nolinkpkg"));
+
+        // Package is installed
+        assert!(installer.is_installed("nolinkpkg"));
+
+        // Linked files should be empty
+        let linked = installer.get_linked_files("nolinkpkg").unwrap();
+        assert!(linked.is_empty());
+    }
+
+    /// Test that dependencies are linked even when main package link=false.
+    #[tokio::test]
+    async fn install_deps_linked_when_main_not_linked() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("homebrew");
+
+        mount_formula(&mock_server, "linkeddep", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "unlinkpkg", "1.0.0", &["linkeddep"]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Install with link=false - deps should still be linked (they follow the flag)
+        let result = installer.install("unlinkpkg", false).await;
+        assert!(result.is_ok());
+
+        // Both packages installed
+        assert!(installer.is_installed("unlinkpkg"));
+        assert!(installer.is_installed("linkeddep"));
+
+        // Neither is linked when link=false is passed
+        assert!(!prefix.join("bin/unlinkpkg").exists());
+        assert!(!prefix.join("bin/linkeddep").exists());
+    }
+
+    // ========================================================================
+    // Rollback and failure handling tests
+    // ========================================================================
+
+    /// Test that a download failure doesn't leave partial state.
+    #[tokio::test]
+    async fn download_failure_no_partial_state() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+
+        let tag = platform_bottle_tag();
+        let bottle = mock_bottle_tarball_with_version("faildownload", "1.0.0");
+        let sha = sha256_hex(&bottle);
+
+        let formula_json =
+            mock_formula_json("faildownload", "1.0.0", &[], &mock_server.uri(), &sha);
+
+        Mock::given(method("GET"))
+            .and(path("/faildownload.json"))
             .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
             .mount(&mock_server)
             .await;
-
-        // Bottle download fails
-        let bottle_path = format!("/bottles/faildownload-1.0.0.{}.bottle.tar.gz", tag);
+
+        // Bottle download fails
+        let bottle_path = format!("/bottles/faildownload-1.0.0.{}.bottle.tar.gz", tag);
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let result = installer.install("faildownload", true).await;
+        assert!(result.is_err());
+
+        // No partial state
+        assert!(!installer.is_installed("faildownload"));
+        assert!(!root.join("cellar/faildownload").exists());
+
+        // Database should not have any record
+        assert!(installer.db.get_installed("faildownload").is_none());
+    }
+
+    /// Test that extraction failure cleans up properly.
+    #[tokio::test]
+    async fn extraction_failure_cleans_up() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let tag = platform_bottle_tag();
+
+        // Create corrupted tarball (valid gzip, invalid tar)
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"not a valid tar").unwrap();
+        let corrupt_bottle = encoder.finish().unwrap();
+        let sha = sha256_hex(&corrupt_bottle);
+
+        let formula_json = mock_formula_json("corrupttar", "1.0.0", &[], &mock_server.uri(), &sha);
+
+        Mock::given(method("GET"))
+            .and(path("/corrupttar.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let bottle_path = format!("/bottles/corrupttar-1.0.0.{}.bottle.tar.gz", tag);
+        Mock::given(method("GET"))
+            .and(path(bottle_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(corrupt_bottle))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let result = installer.install("corrupttar", true).await;
+        assert!(result.is_err());
+
+        // No partial cellar entry
+        assert!(!root.join("cellar/corrupttar").exists());
+
+        // No temp directories in store
+        let store_path = root.join("store");
+        if store_path.exists() {
+            for entry in fs::read_dir(&store_path).unwrap().flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                assert!(!name.contains(".tmp."), "Temp dir left behind: {}", name);
+            }
+        }
+
+        // Not in database
+        assert!(installer.db.get_installed("corrupttar").is_none());
+    }
+
+    /// Test multi-package install where one package fails.
+    /// Successfully installed packages should remain.
+    #[tokio::test]
+    async fn partial_install_failure_keeps_successful() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        // Good package
+        mount_formula(&mock_server, "goodpkg", "1.0.0", &[]).await;
+
+        // Bad package (formula exists, bottle fails)
+        let bad_bottle = mock_bottle_tarball_with_version("badpkg", "1.0.0");
+        let bad_sha = sha256_hex(&bad_bottle);
+
+        let bad_formula = mock_formula_json("badpkg", "1.0.0", &[], &mock_server.uri(), &bad_sha);
+        Mock::given(method("GET"))
+            .and(path("/badpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&bad_formula))
+            .mount(&mock_server)
+            .await;
+
+        let bad_bottle_path = format!("/bottles/badpkg-1.0.0.{}.bottle.tar.gz", tag);
+        Mock::given(method("GET"))
+            .and(path(bad_bottle_path))
+            .respond_with(ResponseTemplate::new(500)) // Bottle fails
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Install good package first
+        let good_result = installer.install("goodpkg", true).await;
+        assert!(good_result.is_ok());
+        assert!(installer.is_installed("goodpkg"));
+
+        // Install bad package (should fail)
+        let bad_result = installer.install("badpkg", true).await;
+        assert!(bad_result.is_err());
+        assert!(!installer.is_installed("badpkg"));
+
+        // Good package should still be there
+        assert!(installer.is_installed("goodpkg"));
+    }
+
+    /// Test that a dependency chain failure doesn't leave partial installs.
+    #[tokio::test]
+    async fn dependency_chain_failure_rollback() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        // Create chain: main -> dep1 -> dep2 (dep2 fails)
+        // Good: main and dep1
+        mount_formula(&mock_server, "chainmain", "1.0.0", &["chaindep1"]).await;
+        mount_formula(&mock_server, "chaindep1", "1.0.0", &["chaindep2"]).await;
+
+        // dep2 has formula but bottle fails
+        let dep2_bottle = mock_bottle_tarball_with_version("chaindep2", "1.0.0");
+        let dep2_sha = sha256_hex(&dep2_bottle);
+
+        let dep2_formula =
+            mock_formula_json("chaindep2", "1.0.0", &[], &mock_server.uri(), &dep2_sha);
+        Mock::given(method("GET"))
+            .and(path("/chaindep2.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&dep2_formula))
+            .mount(&mock_server)
+            .await;
+
+        let dep2_bottle_path = format!("/bottles/chaindep2-1.0.0.{}.bottle.tar.gz", tag);
+        Mock::given(method("GET"))
+            .and(path(dep2_bottle_path))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Try to install main (should fail because dep2 fails)
+        let result = installer.install("chainmain", true).await;
+        assert!(result.is_err());
+
+        // None of the chain should be installed
+        assert!(!installer.is_installed("chainmain"));
+        assert!(!installer.is_installed("chaindep1"));
+        assert!(!installer.is_installed("chaindep2"));
+    }
+
+    // ========================================================================
+    // Database consistency tests
+    // ========================================================================
+
+    /// Test that database records are consistent after successful install.
+    #[tokio::test]
+    async fn database_consistency_after_install() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("homebrew");
+
+        mount_formula(&mock_server, "dbpkg", "2.5.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        installer.install("dbpkg", true).await.unwrap();
+
+        // Check database record
+        let keg = installer.db.get_installed("dbpkg").unwrap();
+        assert_eq!(keg.name, "dbpkg");
+        assert_eq!(keg.version, "2.5.0");
+        assert!(keg.explicit);
+
+        // Check linked files record
+        let linked = installer.db.get_linked_files("dbpkg").unwrap();
+        assert!(!linked.is_empty());
+
+        // Verify actual symlink exists
+        assert!(prefix.join("bin/dbpkg").exists());
+    }
+
+    /// Test database has no records after failed install.
+    #[tokio::test]
+    async fn database_clean_after_failed_install() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let formula_json = r#"{
+            "name": "dbfailpkg",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": {
+                "stable": {
+                    "files": {}
+                }
+            }
+        }"#;
+
+        Mock::given(method("GET"))
+            .and(path("/dbfailpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        // Should fail due to no bottle for platform
+        let result = installer.install("dbfailpkg", true).await;
+        assert!(result.is_err());
+
+        // No database records
+        assert!(installer.db.get_installed("dbfailpkg").is_none());
+        assert!(
+            installer
+                .db
+                .get_linked_files("dbfailpkg")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    // ========================================================================
+    // Streaming extraction order tests
+    // ========================================================================
+
+    /// Test that dependencies are installed before dependents.
+    /// With streaming extraction, deps should complete before their dependents.
+    #[tokio::test]
+    async fn dependency_installation_order() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Chain: main -> mid -> leaf
+        mount_formula(&mock_server, "orderleaf", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "ordermid", "1.0.0", &["orderleaf"]).await;
+        mount_formula(&mock_server, "ordermain", "1.0.0", &["ordermid"]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let result = installer.install("ordermain", true).await;
+        assert!(result.is_ok());
+
+        // All packages should be installed
+        assert!(installer.is_installed("orderleaf"));
+        assert!(installer.is_installed("ordermid"));
+        assert!(installer.is_installed("ordermain"));
+
+        // Leaf should be dependency, mid should be dependency, main should be explicit
+        assert!(!installer.is_explicit("orderleaf"));
+        assert!(!installer.is_explicit("ordermid"));
+        assert!(installer.is_explicit("ordermain"));
+    }
+
+    /// Test that all packages in a diamond dependency are installed correctly.
+    #[tokio::test]
+    async fn diamond_dependency_all_installed() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Diamond: main -> [left, right], left -> shared, right -> shared
+        mount_formula(&mock_server, "diamondshared", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "diamondleft", "1.0.0", &["diamondshared"]).await;
+        mount_formula(&mock_server, "diamondright", "1.0.0", &["diamondshared"]).await;
+        mount_formula(
+            &mock_server,
+            "diamondmain",
+            "1.0.0",
+            &["diamondleft", "diamondright"],
+        )
+        .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let result = installer.install("diamondmain", true).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().installed, 4);
+
+        // All four packages installed
+        assert!(installer.is_installed("diamondshared"));
+        assert!(installer.is_installed("diamondleft"));
+        assert!(installer.is_installed("diamondright"));
+        assert!(installer.is_installed("diamondmain"));
+    }
+
+    // ========================================================================
+    // Execute plan edge cases
+    // ========================================================================
+
+    /// Test execute with an empty plan returns zero installed.
+    #[tokio::test]
+    async fn execute_empty_plan() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let empty_plan = super::super::InstallPlan {
+            formulas: vec![],
+            bottles: vec![],
+            root_name: "empty".to_string(),
+            roots: vec!["empty".to_string()],
+            resolve_time: std::time::Duration::ZERO,
+            source_builds: Vec::new(),
+        };
+
+        let result = installer.execute(empty_plan, true).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().installed, 0);
+    }
+
+    /// Test that install result contains correct count.
+    #[tokio::test]
+    async fn install_result_count_correct() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Three packages: main -> dep1 -> dep2
+        mount_formula(&mock_server, "countdep2", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "countdep1", "1.0.0", &["countdep2"]).await;
+        mount_formula(&mock_server, "countmain", "1.0.0", &["countdep1"]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let result = installer.install("countmain", true).await;
+        assert!(result.is_ok());
+
+        let execute_result = result.unwrap();
+        assert_eq!(
+            execute_result.installed, 3,
+            "Should report 3 packages installed"
+        );
+    }
+
+    // ========================================================================
+    // Keg materialization tests
+    // ========================================================================
+
+    /// Test that keg directory structure is correct after install.
+    #[tokio::test]
+    async fn keg_structure_correct_after_install() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+
+        mount_formula(&mock_server, "kegpkg", "3.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        installer.install("kegpkg", true).await.unwrap();
+
+        // Check keg structure
+        let keg_path = root.join("cellar/kegpkg/3.0.0");
+        assert!(keg_path.exists());
+        assert!(keg_path.join("bin").exists());
+        assert!(keg_path.join("bin/kegpkg").exists());
+
+        // Verify it's executable
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let perms = fs::metadata(keg_path.join("bin/kegpkg"))
+                .unwrap()
+                .permissions();
+            assert!(perms.mode() & 0o111 != 0, "Should be executable");
+        }
+    }
+
+    /// Test that store entry is created correctly.
+    #[tokio::test]
+    async fn store_entry_created() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+
+        let bottle = mock_bottle_tarball_with_version("storepkg", "1.0.0");
+        let sha = sha256_hex(&bottle);
+
+        mount_formula(&mock_server, "storepkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        installer.install("storepkg", true).await.unwrap();
+
+        // Store entry should exist
+        let store_entry = root.join("store").join(&sha);
+        assert!(
+            store_entry.exists(),
+            "Store entry should exist at {}",
+            store_entry.display()
+        );
+    }
+
+    // ========================================================================
+    // Install timing history tests
+    // ========================================================================
+
+    /// Test that an install records per-package timings in the database.
+    #[tokio::test]
+    async fn install_records_timing_history() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "timedroot", "1.0.0", &["timeddep"]).await;
+        mount_formula(&mock_server, "timeddep", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let result = installer.install("timedroot", true).await.unwrap();
+
+        let names: Vec<&str> = result
+            .timings
+            .packages
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["timeddep", "timedroot"]);
+        assert!(result.timings.packages.iter().all(|p| p.bytes > 0));
+        assert!(result.timings.packages.iter().all(|p| !p.blob_cached));
+
+        let stats = installer.db.install_stats().unwrap();
+        assert_eq!(stats.runs, 1);
+        assert_eq!(stats.packages, 2);
+        assert_eq!(stats.blob_cache_hits, 0);
+    }
+
+    /// Test that a reinstall from the blob cache is recorded as a cache hit.
+    #[tokio::test]
+    async fn reinstall_records_cache_hit() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "cachedpkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("cachedpkg", true).await.unwrap();
+        installer.uninstall("cachedpkg").unwrap();
+        let result = installer.install("cachedpkg", true).await.unwrap();
+
+        assert!(result.timings.packages[0].blob_cached);
+        assert!(result.timings.packages[0].store_reused);
+
+        let stats = installer.db.install_stats().unwrap();
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.blob_cache_hits, 1);
+        assert!(stats.bytes_saved > 0);
+    }
+
+    /// Test that upgrades are counted apart from installs in the monthly history.
+    #[tokio::test]
+    async fn upgrade_is_recorded_in_monthly_activity() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "monthly", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("monthly", true).await.unwrap();
+
+        mock_server.reset().await;
+        mount_formula(&mock_server, "monthly", "2.0.0", &[]).await;
+        installer.upgrade_one("monthly", true, None).await.unwrap();
+
+        let activity = installer.install_activity_by_month().unwrap();
+        assert_eq!(activity.len(), 1);
+        assert_eq!(activity[0].installs, 1);
+        assert_eq!(activity[0].upgrades, 1);
+    }
+
+    /// Test that kegs sharing a store entry are counted once on disk.
+    #[tokio::test]
+    async fn dedupe_stats_count_shared_store_entries_once() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let sha = mount_formula(&mock_server, "sharedpkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        assert_eq!(installer.dedupe_stats().unwrap(), DedupeStats::default());
+
+        installer.install("sharedpkg", true).await.unwrap();
+
+        // A second keg backed by the same store entry
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("sharedtwin", "1.0.0", &sha, true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let stats = installer.dedupe_stats().unwrap();
+        assert_eq!(stats.kegs, 2);
+        assert_eq!(stats.store_entries, 1);
+        assert!(stats.store_bytes > 0);
+        assert_eq!(stats.naive_bytes, stats.store_bytes * 2);
+        assert_eq!(stats.bytes_saved(), stats.store_bytes);
+    }
+
+    /// Test that a keg placed in the Cellar by hand is ingested, recorded
+    /// and linked.
+    #[tokio::test]
+    async fn adopt_takes_over_a_manually_placed_keg() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("homebrew");
+        mount_formula(&mock_server, "handmade", "2.0.0", &[]).await;
+        let mut installer = create_test_installer(&mock_server, &tmp);
+
+        let keg = tmp.path().join("zerobrew/cellar/handmade/1.5.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/handmade"), "#!/bin/sh\necho handmade\n").unwrap();
+        assert_eq!(installer.cellar_drift().unwrap().untracked.len(), 1);
+
+        let result = installer.adopt("handmade", "1.5.0", true).await.unwrap();
+
+        assert_eq!(result.store_key, "adopted-handmade-1.5.0");
+        assert!(result.known_formula);
+        assert!(result.linked);
+        assert_eq!(result.files_linked, 1);
+        assert!(
+            installer
+                .store
+                .entry_path(&result.store_key)
+                .join("handmade/1.5.0/bin/handmade")
+                .exists()
+        );
+        assert!(installer.store.manifest_path(&result.store_key).exists());
+        let installed = installer.get_installed("handmade").unwrap();
+        assert_eq!(installed.version, "1.5.0");
+        assert!(installed.linked);
+        assert_eq!(
+            fs::read_link(prefix.join("bin/handmade")).unwrap(),
+            keg.join("bin/handmade")
+        );
+        assert_eq!(fs::read_link(prefix.join("opt/handmade")).unwrap(), keg);
+        assert!(installer.cellar_drift().unwrap().is_empty());
+
+        // Adopting twice, or a keg that isn't there, fails
+        assert!(installer.adopt("handmade", "1.5.0", true).await.is_err());
+        assert!(installer.adopt("handmade", "9.9.9", true).await.is_err());
+    }
+
+    /// Test that update compares the refreshed formula list with the cached
+    /// one and refreshes tapped formulas.
+    #[tokio::test]
+    async fn update_reports_formula_list_and_tap_changes() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let list = |entries: &[(&str, &str)]| {
+            let formulas: Vec<String> = entries
+                .iter()
+                .map(|(name, version)| {
+                    format!(
+                        r#"{{"name": "{name}", "full_name": "{name}", "desc": null, "homepage": null, "versions": {{"stable": "{version}"}}}}"#
+                    )
+                })
+                .collect();
+            format!("[{}]", formulas.join(","))
+        };
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(list(&[
+                ("jq", "1.7"),
+                ("old", "1.0"),
+                ("wget", "1.24"),
+            ])))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
         Mock::given(method("GET"))
-            .and(path(bottle_path))
-            .respond_with(ResponseTemplate::new(500))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(list(&[
+                ("fresh", "0.1"),
+                ("jq", "1.7"),
+                ("wget", "1.25"),
+            ])))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user/homebrew-repo/HEAD/Formula/tapped.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name": "tapped", "versions": {"stable": "2.0"}, "dependencies": []}"#,
+            ))
             .mount(&mock_server)
             .await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.api_client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+            .with_cache(ApiCache::in_memory().unwrap());
+        installer.tap_manager =
+            TapManager::new(&tmp.path().join("zerobrew/taps")).with_raw_url(&mock_server.uri());
+        let tap_dir = tmp.path().join("zerobrew/taps/user/repo");
+        let formula_dir = tap_dir.join("Formula");
+        fs::create_dir_all(&formula_dir).unwrap();
+        fs::write(
+            tap_dir.join(".tap_info"),
+            r#"{"name": "user/repo", "url": "https://github.com/user/homebrew-repo", "added_at": 0, "updated_at": null}"#,
+        )
+        .unwrap();
+        fs::write(
+            formula_dir.join("tapped.json"),
+            r#"{"name": "tapped", "versions": {"stable": "1.0"}, "dependencies": []}"#,
+        )
+        .unwrap();
 
-        let result = installer.install("faildownload", true).await;
-        assert!(result.is_err());
+        // Nothing cached yet, so there is nothing to compare with
+        let summary = installer.update().await.unwrap();
+        assert!(!summary.had_previous);
+        assert_eq!(summary.total, 3);
+        assert!(summary.added.is_empty());
+        assert_eq!(summary.taps.len(), 1);
+        assert_eq!(summary.taps[0].tap, "user/repo");
+        assert_eq!(
+            summary.taps[0].updated,
+            [("tapped".to_string(), "1.0".to_string(), "2.0".to_string())]
+        );
 
-        // No partial state
-        assert!(!installer.is_installed("faildownload"));
-        assert!(!root.join("cellar/faildownload").exists());
+        let summary = installer.update().await.unwrap();
+        assert!(summary.had_previous);
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.added, ["fresh"]);
+        assert_eq!(summary.removed, ["old"]);
+        assert_eq!(
+            summary.updated,
+            [FormulaChange {
+                name: "wget".to_string(),
+                from: "1.24".to_string(),
+                to: "1.25".to_string(),
+            }]
+        );
+        assert_eq!(summary.taps[0].refreshed, 1);
+        assert!(summary.taps[0].updated.is_empty());
+    }
 
-        // Database should not have any record
-        assert!(installer.db.get_installed("faildownload").is_none());
+    /// Test that reconciling forgets kegs deleted by hand and adopts or
+    /// removes keg directories the database doesn't know.
+    #[tokio::test]
+    async fn reconcile_cellar_forgets_missing_and_adopts_or_removes_untracked() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "driftpkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("driftpkg", true).await.unwrap();
+        let bin_link = tmp.path().join("homebrew/bin/driftpkg");
+        assert!(bin_link.symlink_metadata().is_ok());
+
+        let cellar = tmp.path().join("zerobrew/cellar");
+        fs::remove_dir_all(cellar.join("driftpkg/1.0.0")).unwrap();
+        fs::create_dir_all(cellar.join("stray/2.0/bin")).unwrap();
+
+        let drift = installer.cellar_drift().unwrap();
+        let names = |kegs: &[DriftedKeg]| -> Vec<String> {
+            kegs.iter()
+                .map(|k| format!("{} {}", k.name, k.version))
+                .collect()
+        };
+        assert_eq!(names(&drift.untracked), ["stray 2.0"]);
+        assert_eq!(names(&drift.missing), ["driftpkg 1.0.0"]);
+
+        let reconciled = installer.reconcile_cellar(UntrackedAction::Adopt).unwrap();
+        assert_eq!(reconciled, drift);
+        assert!(installer.db.get_installed("driftpkg").is_none());
+        assert!(bin_link.symlink_metadata().is_err());
+        let adopted = installer.db.get_installed("stray").unwrap();
+        assert_eq!(adopted.version, "2.0");
+        assert!(!adopted.linked);
+        assert!(installer.cellar_drift().unwrap().is_empty());
+
+        fs::create_dir_all(cellar.join("leftover/0.1")).unwrap();
+        installer.reconcile_cellar(UntrackedAction::Remove).unwrap();
+        assert!(!cellar.join("leftover").exists());
+        assert!(cellar.join("stray/2.0").exists());
+        assert!(installer.cellar_drift().unwrap().is_empty());
     }
 
-    /// Test that extraction failure cleans up properly.
+    /// Test that reconciling leaves alone kegs another zb recorded or put
+    /// back after the Cellar was scanned.
     #[tokio::test]
-    async fn extraction_failure_cleans_up() {
+    async fn reconcile_skips_kegs_that_changed_after_the_scan() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-        let tag = platform_bottle_tag();
 
-        // Create corrupted tarball (valid gzip, invalid tar)
-        use flate2::Compression;
-        use flate2::write::GzEncoder;
-        use std::io::Write;
+        mount_formula(&mock_server, "racepkg", "1.0.0", &[]).await;
 
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        encoder.write_all(b"not a valid tar").unwrap();
-        let corrupt_bottle = encoder.finish().unwrap();
-        let sha = sha256_hex(&corrupt_bottle);
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("racepkg", true).await.unwrap();
+        let cellar = tmp.path().join("zerobrew/cellar");
+
+        // An install materialized its keg but hadn't recorded it yet...
+        fs::create_dir_all(cellar.join("inflight/1.0/bin")).unwrap();
+        let untracked = installer.cellar_drift().unwrap().untracked;
+        assert_eq!(untracked.len(), 1);
+        // ...and records it before cleanup gets the formula's lock
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("inflight", "1.0", "inflight-key", true)
+            .unwrap();
+        tx.commit().unwrap();
 
-        let formula_json = mock_formula_json("corrupttar", "1.0.0", &[], &mock_server.uri(), &sha);
+        for action in [UntrackedAction::Remove, UntrackedAction::Adopt] {
+            let reconciled = installer
+                .reconcile_untracked(untracked.clone(), action)
+                .unwrap();
+            assert!(reconciled.is_empty());
+        }
+        assert!(cellar.join("inflight/1.0").exists());
+        assert_eq!(
+            installer.db.get_installed("inflight").unwrap().store_key,
+            "inflight-key"
+        );
+
+        // A keg that was briefly gone is put back before it is forgotten
+        let keg = cellar.join("racepkg/1.0.0");
+        let moved = tmp.path().join("racepkg-moved");
+        fs::rename(&keg, &moved).unwrap();
+        let missing = installer.cellar_drift().unwrap().missing;
+        assert_eq!(missing.len(), 1);
+        fs::rename(&moved, &keg).unwrap();
+
+        assert!(installer.forget_missing_kegs(missing).unwrap().is_empty());
+        assert!(installer.db.get_installed("racepkg").is_some());
+        assert!(
+            tmp.path()
+                .join("homebrew/bin/racepkg")
+                .symlink_metadata()
+                .is_ok()
+        );
+    }
+
+    /// Test that the inventory measures each store entry once and reports
+    /// the keg's source.
+    #[tokio::test]
+    async fn inventory_measures_kegs_and_reports_their_source() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let sha = mount_formula(&mock_server, "inventorypkg", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("inventorypkg", true).await.unwrap();
+        assert_eq!(installer.db.list_unsized_kegs().unwrap().len(), 1);
+
+        let inventory = installer.inventory(crate::db::InventorySort::Size).unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert_eq!(inventory[0].keg.name, "inventorypkg");
+        assert_eq!(inventory[0].source, "homebrew/core");
+        assert_eq!(
+            inventory[0].size_bytes,
+            Some(installer.store.entry_size(&sha).unwrap())
+        );
+        assert!(installer.db.list_unsized_kegs().unwrap().is_empty());
+    }
+
+    /// Test that the download estimate covers uncached bottles only.
+    #[tokio::test]
+    async fn estimate_download_skips_cached_bottles() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        mount_formula(&mock_server, "sizedroot", "1.0.0", &["sizeddep"]).await;
+        mount_formula(&mock_server, "sizeddep", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let plan = installer.plan("sizedroot").await.unwrap();
+        let estimate = installer.estimate_download(&plan).await;
+        assert_eq!(estimate.cached, 0);
+        assert_eq!(estimate.unknown, 0);
+        assert!(estimate.bytes > 0);
+        assert_eq!(estimate.installed_bytes, estimate.bytes * 3);
+        let names: Vec<&str> = estimate.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["sizeddep", "sizedroot"]);
+        assert!(estimate.packages.iter().all(|p| !p.cached));
+
+        let result = installer.install("sizedroot", true).await.unwrap();
+        let downloaded: u64 = result.timings.packages.iter().map(|p| p.bytes).sum();
+        assert_eq!(estimate.bytes, downloaded);
+
+        let plan = installer.plan("sizedroot").await.unwrap();
+        let estimate = installer.estimate_download(&plan).await;
+        assert_eq!(estimate.cached, 2);
+        assert_eq!(estimate.bytes, 0);
+        // Poured packages report the size of their store entries
+        let sizes: Vec<Option<u64>> = estimate
+            .packages
+            .iter()
+            .map(|p| p.installed_bytes)
+            .collect();
+        let poured: Vec<Option<u64>> = plan
+            .bottles
+            .iter()
+            .map(|b| installer.db.store_size(&b.sha256))
+            .collect();
+        assert!(poured.iter().all(Option::is_some));
+        assert_eq!(sizes, poured);
+    }
+
+    /// Test that bottle availability lists every platform and picks this host's.
+    #[tokio::test]
+    async fn bottle_availability_reports_sizes_and_host_support() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
 
+        mount_formula(&mock_server, "portable", "1.0.0", &[]).await;
+        let foreign = crate::test_utils::mock_formula_json_with_bottles(
+            "foreign",
+            "1.0.0",
+            &[],
+            &[(
+                "sometag_nowhere",
+                &format!("{}/bottles/foreign.tar.gz", mock_server.uri()),
+                "aaaa",
+            )],
+        );
         Mock::given(method("GET"))
-            .and(path("/corrupttar.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .and(path("/foreign.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(foreign))
             .mount(&mock_server)
             .await;
-
-        let bottle_path = format!("/bottles/corrupttar-1.0.0.{}.bottle.tar.gz", tag);
         Mock::given(method("GET"))
-            .and(path(bottle_path))
-            .respond_with(ResponseTemplate::new(200).set_body_bytes(corrupt_bottle))
+            .and(path("/bottles/foreign.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 100]))
             .mount(&mock_server)
             .await;
 
-        let mut installer = create_test_installer(&mock_server, &tmp);
+        let installer = create_test_installer(&mock_server, &tmp);
 
-        let result = installer.install("corrupttar", true).await;
-        assert!(result.is_err());
+        let formula = installer.get_formula("portable").await.unwrap();
+        let availability = installer.bottle_availability(&formula).await;
+        assert_eq!(availability.bottles.len(), 1);
+        assert_eq!(availability.bottles[0].tag, platform_bottle_tag());
+        assert!(availability.bottles[0].size.is_some_and(|size| size > 0));
+        assert_eq!(
+            availability.selected.as_deref(),
+            Some(platform_bottle_tag())
+        );
+        assert!(availability.unsupported.is_none());
+
+        let formula = installer.get_formula("foreign").await.unwrap();
+        let availability = installer.bottle_availability(&formula).await;
+        assert_eq!(availability.bottles[0].size, Some(100));
+        assert_eq!(availability.selected, None);
+        assert!(matches!(
+            availability.unsupported,
+            Some(zb_core::Error::UnsupportedBottle { .. })
+        ));
+    }
 
-        // No partial cellar entry
-        assert!(!root.join("cellar/corrupttar").exists());
+    // ========================================================================
+    // Multiple version tests
+    // ========================================================================
 
-        // No temp directories in store
-        let store_path = root.join("store");
-        if store_path.exists() {
-            for entry in fs::read_dir(&store_path).unwrap().flatten() {
-                let name = entry.file_name().to_string_lossy().to_string();
-                assert!(!name.contains(".tmp."), "Temp dir left behind: {}", name);
-            }
-        }
+    /// Install `name` 1.0.0 and fake an older 0.9.0 keg next to it
+    async fn install_with_old_version(
+        mock_server: &MockServer,
+        tmp: &TempDir,
+        name: &str,
+    ) -> Installer {
+        let sha = mount_formula(mock_server, name, "1.0.0", &[]).await;
+        let mut installer = create_test_installer(mock_server, tmp);
+        installer.install(name, true).await.unwrap();
+
+        let cellar = tmp.path().join("zerobrew/cellar").join(name);
+        fs::create_dir_all(cellar.join("0.9.0/bin")).unwrap();
+        fs::write(cellar.join("0.9.0/bin").join(name), b"#!/bin/sh\necho old").unwrap();
+
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install(name, "0.9.0", "oldkey", true).unwrap();
+        tx.record_install(name, "1.0.0", &sha, true).unwrap();
+        tx.commit().unwrap();
 
-        // Not in database
-        assert!(installer.db.get_installed("corrupttar").is_none());
+        installer
+    }
+
+    #[tokio::test]
+    async fn uninstall_version_removes_only_that_keg() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = install_with_old_version(&mock_server, &tmp, "multi").await;
+        let cellar = tmp.path().join("zerobrew/cellar/multi");
+
+        installer.uninstall_version("multi", "0.9.0").unwrap();
+
+        assert!(!cellar.join("0.9.0").exists());
+        assert!(cellar.join("1.0.0").exists());
+        assert_eq!(installer.get_installed("multi").unwrap().version, "1.0.0");
+        assert_eq!(installer.db.get_store_refcount("oldkey"), 0);
+        assert!(tmp.path().join("homebrew/bin/multi").exists());
+    }
+
+    #[tokio::test]
+    async fn uninstall_version_refuses_active_version() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = install_with_old_version(&mock_server, &tmp, "activev").await;
+
+        let err = installer.uninstall_version("activev", "1.0.0").unwrap_err();
+        assert!(
+            matches!(err, zb_core::Error::StoreCorruption { message } if message.contains("zb switch"))
+        );
+
+        let err = installer.uninstall_version("activev", "3.0.0").unwrap_err();
+        assert!(matches!(err, zb_core::Error::NotInstalled { .. }));
+    }
+
+    #[tokio::test]
+    async fn uninstall_removes_all_versions() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let mut installer = install_with_old_version(&mock_server, &tmp, "allv").await;
+
+        installer.uninstall("allv").unwrap();
+
+        assert!(!installer.is_installed("allv"));
+        assert!(installer.list_versions("allv").unwrap().is_empty());
+        assert!(!tmp.path().join("zerobrew/cellar/allv/0.9.0").exists());
+        assert!(!tmp.path().join("zerobrew/cellar/allv/1.0.0").exists());
+        assert_eq!(installer.db.get_store_refcount("oldkey"), 0);
+    }
+
+    #[tokio::test]
+    async fn switch_to_unknown_version_fails_without_unlinking() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "stay", "1.0.0", &[]).await;
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("stay", true).await.unwrap();
+
+        let err = installer.switch("stay", "0.1.0").unwrap_err();
+
+        assert!(matches!(err, zb_core::Error::NotInstalled { .. }));
+        assert!(installer.is_linked("stay"));
     }
 
-    /// Test multi-package install where one package fails.
-    /// Successfully installed packages should remain.
     #[tokio::test]
-    async fn partial_install_failure_keeps_successful() {
+    async fn switch_keeps_unlinked_formula_out_of_prefix() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let tag = platform_bottle_tag();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        mount_formula(&mock_server, "quiet", "1.0.0", &[]).await;
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("quiet", true).await.unwrap();
+        installer.unlink("quiet").unwrap();
+        {
+            // A second version, as an upgrade leaves behind
+            let tx = installer.db.transaction().unwrap();
+            tx.record_keg_version("quiet", "0.9.0", "oldkey").unwrap();
+            tx.commit().unwrap();
+        }
+        let old_keg = root.join("cellar/quiet/0.9.0");
+        fs::create_dir_all(old_keg.join("bin")).unwrap();
+        fs::write(old_keg.join("bin/quiet"), "#!/bin/sh\n").unwrap();
+
+        let result = installer.switch("quiet", "0.9.0").unwrap();
+
+        assert_eq!(result.files_linked, 0);
+        let keg = installer.get_installed("quiet").unwrap();
+        assert_eq!(keg.version, "0.9.0");
+        assert!(!keg.linked);
+        assert!(prefix.join("bin/quiet").symlink_metadata().is_err());
+        // opt/ follows the active version even while unlinked
+        assert_eq!(fs::read_link(prefix.join("opt/quiet")).unwrap(), old_keg);
+    }
 
-        // Good package
-        mount_formula(&mock_server, "goodpkg", "1.0.0", &[]).await;
+    #[tokio::test]
+    async fn unlinked_formulas_keep_an_opt_link_to_the_active_keg() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("homebrew");
+        mount_formula(&mock_server, "kegonly", "1.0.0", &[]).await;
+        mount_formula(&mock_server, "plainpkg", "1.0.0", &[]).await;
+        let mut installer = create_test_installer(&mock_server, &tmp);
 
-        // Bad package (formula exists, bottle fails)
-        let bad_bottle = mock_bottle_tarball_with_version("badpkg", "1.0.0");
-        let bad_sha = sha256_hex(&bad_bottle);
+        // Installed without linking, as keg-only formulas are
+        installer.install("kegonly", false).await.unwrap();
+        let keg = installer.keg_path("kegonly").unwrap();
+        assert!(prefix.join("bin/kegonly").symlink_metadata().is_err());
+        assert_eq!(fs::read_link(prefix.join("opt/kegonly")).unwrap(), keg);
+
+        // Unlinking leaves opt/ in place; uninstalling removes it
+        installer.install("plainpkg", true).await.unwrap();
+        let keg = installer.keg_path("plainpkg").unwrap();
+        installer.unlink("plainpkg").unwrap();
+        assert!(prefix.join("bin/plainpkg").symlink_metadata().is_err());
+        assert_eq!(fs::read_link(prefix.join("opt/plainpkg")).unwrap(), keg);
+
+        installer.uninstall("plainpkg").unwrap();
+        assert!(prefix.join("opt/plainpkg").symlink_metadata().is_err());
+    }
 
-        let bad_formula = mock_formula_json("badpkg", "1.0.0", &[], &mock_server.uri(), &bad_sha);
-        Mock::given(method("GET"))
-            .and(path("/badpkg.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&bad_formula))
-            .mount(&mock_server)
-            .await;
+    // ========================================================================
+    // Link state tests
+    // ========================================================================
 
-        let bad_bottle_path = format!("/bottles/badpkg-1.0.0.{}.bottle.tar.gz", tag);
-        Mock::given(method("GET"))
-            .and(path(bad_bottle_path))
-            .respond_with(ResponseTemplate::new(500)) // Bottle fails
-            .mount(&mock_server)
-            .await;
+    #[tokio::test]
+    async fn link_state_is_tracked_in_database() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "lstate", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("lstate", false).await.unwrap();
+        assert_eq!(
+            installer.get_installed("lstate").unwrap().link_status(),
+            crate::db::LinkStatus::Unlinked
+        );
 
-        // Install good package first
-        let good_result = installer.install("goodpkg", true).await;
-        assert!(good_result.is_ok());
-        assert!(installer.is_installed("goodpkg"));
-
-        // Install bad package (should fail)
-        let bad_result = installer.install("badpkg", true).await;
-        assert!(bad_result.is_err());
-        assert!(!installer.is_installed("badpkg"));
+        installer.link("lstate", false, false).unwrap();
+        assert_eq!(
+            installer.get_installed("lstate").unwrap().link_status(),
+            crate::db::LinkStatus::Linked
+        );
 
-        // Good package should still be there
-        assert!(installer.is_installed("goodpkg"));
+        installer.unlink("lstate").unwrap();
+        assert!(!installer.get_installed("lstate").unwrap().linked);
     }
 
-    /// Test that a dependency chain failure doesn't leave partial installs.
+    // ========================================================================
+    // Verify mode tests
+    // ========================================================================
+
     #[tokio::test]
-    async fn dependency_chain_failure_rollback() {
+    async fn verify_accepts_intact_keg() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let tag = platform_bottle_tag();
-
-        // Create chain: main -> dep1 -> dep2 (dep2 fails)
-        // Good: main and dep1
-        mount_formula(&mock_server, "chainmain", "1.0.0", &["chaindep1"]).await;
-        mount_formula(&mock_server, "chaindep1", "1.0.0", &["chaindep2"]).await;
+        mount_formula(&mock_server, "vgood", "1.0.0", &[]).await;
 
-        // dep2 has formula but bottle fails
-        let dep2_bottle = mock_bottle_tarball_with_version("chaindep2", "1.0.0");
-        let dep2_sha = sha256_hex(&dep2_bottle);
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.set_verify(true);
+        installer.install("vgood", true).await.unwrap();
 
-        let dep2_formula =
-            mock_formula_json("chaindep2", "1.0.0", &[], &mock_server.uri(), &dep2_sha);
-        Mock::given(method("GET"))
-            .and(path("/chaindep2.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&dep2_formula))
-            .mount(&mock_server)
-            .await;
+        assert!(installer.is_installed("vgood"));
+        assert!(tmp.path().join("homebrew/bin/vgood").exists());
+    }
 
-        let dep2_bottle_path = format!("/bottles/chaindep2-1.0.0.{}.bottle.tar.gz", tag);
-        Mock::given(method("GET"))
-            .and(path(dep2_bottle_path))
-            .respond_with(ResponseTemplate::new(500))
-            .mount(&mock_server)
-            .await;
+    #[tokio::test]
+    async fn verify_rejects_corrupted_store_entry() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let sha = mount_formula(&mock_server, "vcorr", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("vcorr", true).await.unwrap();
+        installer.uninstall("vcorr").unwrap();
+
+        // Simulate on-disk corruption of the store entry the reinstall reuses
+        // (its files are read-only, so replace rather than overwrite)
+        let stored = tmp
+            .path()
+            .join("zerobrew/store")
+            .join(&sha)
+            .join("vcorr/1.0.0/bin/vcorr");
+        fs::remove_file(&stored).unwrap();
+        fs::write(
+            &stored,
+            b"#!/bin/sh
+echo XXXXX",
+        )
+        .unwrap();
 
-        // Try to install main (should fail because dep2 fails)
-        let result = installer.install("chainmain", true).await;
-        assert!(result.is_err());
+        installer.set_verify(true);
+        let err = installer.install("vcorr", true).await.unwrap_err();
 
-        // None of the chain should be installed
-        assert!(!installer.is_installed("chainmain"));
-        assert!(!installer.is_installed("chaindep1"));
-        assert!(!installer.is_installed("chaindep2"));
+        assert!(
+            matches!(&err, zb_core::Error::StoreCorruption { message } if message.contains("bin/vcorr")),
+            "unexpected error: {err:?}"
+        );
+        assert!(!installer.is_installed("vcorr"));
+        assert!(!tmp.path().join("homebrew/bin/vcorr").exists());
+        assert!(!tmp.path().join("homebrew/Cellar/vcorr/1.0.0").exists());
     }
 
-    // ========================================================================
-    // Database consistency tests
-    // ========================================================================
+    /// A stand-in for `gh` that runs `script`
+    fn fake_gh(tmp: &TempDir, script: &str) -> crate::AttestationVerifier {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = tmp.path().join("gh");
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        crate::AttestationVerifier::new(path)
+    }
 
-    /// Test that database records are consistent after successful install.
     #[tokio::test]
-    async fn database_consistency_after_install() {
+    async fn verify_records_attestations_per_store_entry() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let prefix = tmp.path().join("homebrew");
-
-        mount_formula(&mock_server, "dbpkg", "2.5.0", &[]).await;
+        let sha = mount_formula(&mock_server, "vattest", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.set_verify(true);
+        installer.set_attestation_verifier(Some(fake_gh(&tmp, "exit 0")));
+        installer.install("vattest", true).await.unwrap();
 
-        installer.install("dbpkg", true).await.unwrap();
+        let attestation = installer.db.attestation(&sha).unwrap();
+        assert_eq!(attestation.status, crate::AttestationStatus::Verified);
+        assert_eq!(
+            attestation.repo.as_deref(),
+            Some(crate::attestation::CORE_ATTESTATION_REPO)
+        );
 
-        // Check database record
-        let keg = installer.db.get_installed("dbpkg").unwrap();
-        assert_eq!(keg.name, "dbpkg");
-        assert_eq!(keg.version, "2.5.0");
-        assert!(keg.explicit);
+        let verification = installer.verify_installed("vattest").unwrap();
+        assert!(verification.is_ok());
+        assert_eq!(verification.attestation, Some(attestation));
+    }
 
-        // Check linked files record
-        let linked = installer.db.get_linked_files("dbpkg").unwrap();
-        assert!(!linked.is_empty());
+    #[tokio::test]
+    async fn verify_rejects_bottle_whose_attestation_fails() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let sha = mount_formula(&mock_server, "vforged", "1.0.0", &[]).await;
 
-        // Verify actual symlink exists
-        assert!(prefix.join("bin/dbpkg").exists());
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.set_verify(true);
+        installer.set_attestation_verifier(Some(fake_gh(
+            &tmp,
+            "echo 'Error: no attestations found' >&2; exit 1",
+        )));
+        let err = installer.install("vforged", true).await.unwrap_err();
+
+        assert!(
+            matches!(&err, zb_core::Error::StoreCorruption { message } if message.contains("no attestations found")),
+            "unexpected error: {err:?}"
+        );
+        assert!(!installer.is_installed("vforged"));
+        assert!(!installer.cellar.has_keg("vforged", "1.0.0"));
+        assert_eq!(
+            installer.db.attestation(&sha).unwrap().status,
+            crate::AttestationStatus::Failed
+        );
+        let events = installer.pour_events(10).unwrap();
+        assert_eq!(events[0].kind, crate::PourEventKind::AttestationFailed);
     }
 
-    /// Test database has no records after failed install.
     #[tokio::test]
-    async fn database_clean_after_failed_install() {
+    async fn verify_installed_reports_modified_keg_files() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "vedit", "1.0.0", &[]).await;
 
-        let formula_json = r#"{
-            "name": "dbfailpkg",
-            "versions": { "stable": "1.0.0" },
-            "dependencies": [],
-            "bottle": {
-                "stable": {
-                    "files": {}
-                }
-            }
-        }"#;
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("vedit", true).await.unwrap();
+        let verification = installer.verify_installed("vedit").unwrap();
+        assert!(verification.is_ok());
+        assert!(verification.attestation.is_none());
+
+        // Replace rather than overwrite, as the keg may share the store's files
+        let keg_file = installer
+            .cellar
+            .keg_path("vedit", "1.0.0")
+            .join("bin/vedit");
+        fs::remove_file(&keg_file).unwrap();
+        fs::write(&keg_file, b"#!/bin/sh\necho tampered").unwrap();
+
+        let verification = installer.verify_installed("vedit").unwrap();
+        assert!(!verification.is_ok());
+        assert_eq!(verification.mismatches.len(), 1);
+        assert!(verification.mismatches[0].path().ends_with("bin/vedit"));
+        // Left in place, unlike a failed --verify install
+        assert!(keg_file.exists());
+
+        assert!(matches!(
+            installer.verify_installed("missing"),
+            Err(zb_core::Error::NotInstalled { .. })
+        ));
+    }
 
-        Mock::given(method("GET"))
-            .and(path("/dbfailpkg.json"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
-            .mount(&mock_server)
-            .await;
+    /// Test that link --overwrite backs up a conflicting file and unlink restores it.
+    #[tokio::test]
+    async fn link_overwrite_backs_up_and_restores_file() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("homebrew");
+        mount_formula(&mock_server, "backuppkg", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("backuppkg", false).await.unwrap();
 
-        // Should fail due to no bottle for platform
-        let result = installer.install("dbfailpkg", true).await;
-        assert!(result.is_err());
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("bin/backuppkg"), "user file").unwrap();
 
-        // No database records
-        assert!(installer.db.get_installed("dbfailpkg").is_none());
+        let result = installer.link("backuppkg", true, false).unwrap();
+        assert_eq!(result.backed_up.len(), 1);
         assert!(
-            installer
-                .db
-                .get_linked_files("dbfailpkg")
-                .unwrap()
-                .is_empty()
+            result.backed_up[0]
+                .backup_path
+                .starts_with(tmp.path().join("zerobrew/backups"))
         );
-    }
+        assert!(installer.is_linked("backuppkg"));
 
-    // ========================================================================
-    // Streaming extraction order tests
-    // ========================================================================
+        installer.unlink("backuppkg").unwrap();
+        let restored = installer.restore_backups("backuppkg").unwrap();
 
-    /// Test that dependencies are installed before dependents.
-    /// With streaming extraction, deps should complete before their dependents.
+        assert_eq!(restored, vec![prefix.join("bin/backuppkg")]);
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/backuppkg")).unwrap(),
+            "user file"
+        );
+        assert!(installer.restore_backups("backuppkg").unwrap().is_empty());
+    }
+
+    /// Test that uninstalling restores files displaced by link --overwrite.
     #[tokio::test]
-    async fn dependency_installation_order() {
+    async fn uninstall_restores_backed_up_files() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-
-        // Chain: main -> mid -> leaf
-        mount_formula(&mock_server, "orderleaf", "1.0.0", &[]).await;
-        mount_formula(&mock_server, "ordermid", "1.0.0", &["orderleaf"]).await;
-        mount_formula(&mock_server, "ordermain", "1.0.0", &["ordermid"]).await;
+        let prefix = tmp.path().join("homebrew");
+        mount_formula(&mock_server, "restorepkg", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("restorepkg", false).await.unwrap();
 
-        let result = installer.install("ordermain", true).await;
-        assert!(result.is_ok());
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("bin/restorepkg"), "user file").unwrap();
+        installer.link("restorepkg", true, false).unwrap();
 
-        // All packages should be installed
-        assert!(installer.is_installed("orderleaf"));
-        assert!(installer.is_installed("ordermid"));
-        assert!(installer.is_installed("ordermain"));
+        installer.uninstall("restorepkg").unwrap();
 
-        // Leaf should be dependency, mid should be dependency, main should be explicit
-        assert!(!installer.is_explicit("orderleaf"));
-        assert!(!installer.is_explicit("ordermid"));
-        assert!(installer.is_explicit("ordermain"));
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/restorepkg")).unwrap(),
+            "user file"
+        );
+    }
+
+    /// Serve every file under `dir` from `server` at its relative path
+    async fn serve_dir(server: &MockServer, dir: &std::path::Path) {
+        for entry in walkdir::WalkDir::new(dir) {
+            let entry = entry.unwrap();
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(dir).unwrap();
+            Mock::given(method("GET"))
+                .and(path(format!("/{}", relative.display())))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_bytes(fs::read(entry.path()).unwrap()),
+                )
+                .mount(server)
+                .await;
+        }
     }
 
-    /// Test that all packages in a diamond dependency are installed correctly.
     #[tokio::test]
-    async fn diamond_dependency_all_installed() {
-        let mock_server = MockServer::start().await;
+    async fn mirror_includes_dependencies_and_installs_offline() {
+        let upstream = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
+        mount_formula(&upstream, "mirrordep", "1.0.0", &[]).await;
+        mount_formula(&upstream, "mirrorroot", "2.0.0", &["mirrordep"]).await;
 
-        // Diamond: main -> [left, right], left -> shared, right -> shared
-        mount_formula(&mock_server, "diamondshared", "1.0.0", &[]).await;
-        mount_formula(&mock_server, "diamondleft", "1.0.0", &["diamondshared"]).await;
-        mount_formula(&mock_server, "diamondright", "1.0.0", &["diamondshared"]).await;
-        mount_formula(
-            &mock_server,
-            "diamondmain",
-            "1.0.0",
-            &["diamondleft", "diamondright"],
-        )
-        .await;
+        let installer = create_test_installer(&upstream, &tmp);
+        let mirror_dir = tmp.path().join("mirror");
+        let result = installer
+            .create_mirror(&mirror_dir, &["mirrorroot".to_string()], &[])
+            .await
+            .unwrap();
 
-        let mut installer = create_test_installer(&mock_server, &tmp);
+        assert_eq!(result.formulas, vec!["mirrordep", "mirrorroot"]);
+        assert_eq!(result.bottles.len(), 2);
+        assert!(mirror_dir.join("api/formula/mirrorroot.json").exists());
+        assert!(mirror_dir.join("api/formula/mirrordep.json").exists());
+        let list: Vec<serde_json::Value> =
+            serde_json::from_slice(&fs::read(mirror_dir.join("api/formula.json")).unwrap())
+                .unwrap();
+        assert_eq!(list.len(), 2);
+        drop(upstream);
+
+        // Install on a fresh machine that can only reach the mirror
+        let mirror_server = MockServer::start().await;
+        serve_dir(&mirror_server, &mirror_dir).await;
+        let offline_tmp = TempDir::new().unwrap();
+        let mut offline = create_test_installer(&mirror_server, &offline_tmp);
+        offline
+            .set_api_domain(&format!("{}/api", mirror_server.uri()), ApiLayout::Homebrew)
+            .unwrap();
+        offline.set_bottle_domain(&format!("{}/bottles", mirror_server.uri()));
 
-        let result = installer.install("diamondmain", true).await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().installed, 4);
+        offline.install("mirrorroot", true).await.unwrap();
 
-        // All four packages installed
-        assert!(installer.is_installed("diamondshared"));
-        assert!(installer.is_installed("diamondleft"));
-        assert!(installer.is_installed("diamondright"));
-        assert!(installer.is_installed("diamondmain"));
+        assert!(offline.is_installed("mirrorroot"));
+        assert!(offline.is_installed("mirrordep"));
     }
 
-    // ========================================================================
-    // Execute plan edge cases
-    // ========================================================================
-
-    /// Test execute with an empty plan returns zero installed.
     #[tokio::test]
-    async fn execute_empty_plan() {
+    async fn mirror_reports_missing_platform_bottles() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "mirrorplat", "1.0.0", &[]).await;
 
-        let mut installer = create_test_installer(&mock_server, &tmp);
+        let installer = create_test_installer(&mock_server, &tmp);
+        let result = installer
+            .create_mirror(
+                &tmp.path().join("mirror"),
+                &["mirrorplat".to_string(), "user/tap/thing".to_string()],
+                &["no_such_platform".to_string()],
+            )
+            .await
+            .unwrap();
 
-        let empty_plan = super::super::InstallPlan {
-            formulas: vec![],
-            bottles: vec![],
-            root_name: "empty".to_string(),
-        };
+        assert_eq!(result.formulas, vec!["mirrorplat"]);
+        assert!(result.bottles.is_empty());
+        assert_eq!(
+            result.missing_bottles,
+            vec![("mirrorplat".to_string(), "no_such_platform".to_string())]
+        );
+        assert_eq!(result.skipped.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pack_unpack_installs_without_network() {
+        let upstream = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&upstream, "packdep", "1.0.0", &[]).await;
+        mount_formula(&upstream, "packroot", "1.2.0", &["packdep"]).await;
+
+        let installer = create_test_installer(&upstream, &tmp);
+        let pack = tmp.path().join("tools.zbpack");
+        let result = installer
+            .create_pack(&pack, &["packroot".to_string()], &[])
+            .await
+            .unwrap();
+        assert_eq!(result.formulas, vec!["packdep", "packroot"]);
+        assert!(pack.exists());
+        drop(upstream);
+
+        // The target machine's API server has nothing mounted
+        let offline_server = MockServer::start().await;
+        let offline_tmp = TempDir::new().unwrap();
+        let mut offline = create_test_installer(&offline_server, &offline_tmp);
 
-        let result = installer.execute(empty_plan, true).await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().installed, 0);
+        assert!(offline.use_packs().is_err());
+
+        let unpacked = offline.unpack(&pack).unwrap();
+        assert_eq!(unpacked.formulas, vec!["packdep", "packroot"]);
+        assert_eq!(unpacked.bottles, 2);
+
+        offline.use_packs().unwrap();
+        offline.install("packroot", true).await.unwrap();
+
+        assert!(offline.is_installed("packroot"));
+        assert!(offline.is_installed("packdep"));
     }
 
-    /// Test that install result contains correct count.
     #[tokio::test]
-    async fn install_result_count_correct() {
+    async fn unpack_rejects_non_pack_archive() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
+        let installer = create_test_installer(&mock_server, &tmp);
 
-        // Three packages: main -> dep1 -> dep2
-        mount_formula(&mock_server, "countdep2", "1.0.0", &[]).await;
-        mount_formula(&mock_server, "countdep1", "1.0.0", &["countdep2"]).await;
-        mount_formula(&mock_server, "countmain", "1.0.0", &["countdep1"]).await;
-
-        let mut installer = create_test_installer(&mock_server, &tmp);
-
-        let result = installer.install("countmain", true).await;
-        assert!(result.is_ok());
+        let archive = tmp.path().join("bottle.tar.gz");
+        fs::write(&archive, mock_bottle_tarball_with_version("other", "1.0.0")).unwrap();
 
-        let execute_result = result.unwrap();
-        assert_eq!(
-            execute_result.installed, 3,
-            "Should report 3 packages installed"
-        );
+        let err = installer.unpack(&archive).unwrap_err();
+        assert!(err.to_string().contains("not a zerobrew pack"), "{err}");
     }
 
-    // ========================================================================
-    // Keg materialization tests
-    // ========================================================================
-
-    /// Test that keg directory structure is correct after install.
     #[tokio::test]
-    async fn keg_structure_correct_after_install() {
+    async fn link_conflicts_are_found_before_downloading() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
-
-        mount_formula(&mock_server, "kegpkg", "3.0.0", &[]).await;
+        mount_formula(&mock_server, "lcfoo", "1.0.0", &[]).await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("lcfoo", true).await.unwrap();
+
+        // Reinstalling takes over the keg's own links
+        let plan = installer.plan("lcfoo").await.unwrap();
+        assert!(installer.link_conflicts(&plan).is_empty());
+
+        // Another keg's link, known from the bottle already in the store
+        installer.unlink("lcfoo").unwrap();
+        let bin_link = tmp.path().join("homebrew/bin/lcfoo");
+        let other = tmp.path().join("zerobrew/cellar/lcother/1.0.0/bin/lcfoo");
+        fs::create_dir_all(other.parent().unwrap()).unwrap();
+        fs::write(&other, "#!/bin/sh\n").unwrap();
+        std::os::unix::fs::symlink(&other, &bin_link).unwrap();
+
+        let conflicts = installer.link_conflicts(&plan);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].formula, "lcfoo");
+        assert_eq!(conflicts[0].path, bin_link);
+        assert_eq!(conflicts[0].owner.as_deref(), Some("lcother"));
+
+        // A file zerobrew doesn't manage fails the install up front
+        fs::remove_file(&bin_link).unwrap();
+        fs::write(&bin_link, "mine").unwrap();
+        let err = installer.execute(plan, true).await.unwrap_err();
+        assert!(matches!(
+            err,
+            zb_core::Error::LinkConflict {
+                existing_type: zb_core::LinkConflictType::RegularFile,
+                ..
+            }
+        ));
+        assert_eq!(fs::read_to_string(&bin_link).unwrap(), "mine");
+    }
 
-        installer.install("kegpkg", true).await.unwrap();
+    #[tokio::test]
+    async fn pours_and_integrity_failures_are_logged() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let sha = mount_formula(&mock_server, "plfoo", "1.0.0", &[]).await;
 
-        // Check keg structure
-        let keg_path = root.join("cellar/kegpkg/3.0.0");
-        assert!(keg_path.exists());
-        assert!(keg_path.join("bin").exists());
-        assert!(keg_path.join("bin/kegpkg").exists());
+        // A bottle that doesn't match its checksum
+        let uri = mock_server.uri();
+        let wrong_sha = "a".repeat(64);
+        Mock::given(method("GET"))
+            .and(path("/plbad.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(mock_formula_json(
+                    "plbad",
+                    "1.0.0",
+                    &[],
+                    &uri,
+                    &wrong_sha,
+                )),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/plbad-1.0.0.{}.bottle.tar.gz",
+                platform_bottle_tag()
+            )))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(mock_bottle_tarball_with_version("plbad", "1.0.0")),
+            )
+            .mount(&mock_server)
+            .await;
 
-        // Verify it's executable
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let perms = fs::metadata(keg_path.join("bin/kegpkg"))
-                .unwrap()
-                .permissions();
-            assert!(perms.mode() & 0o111 != 0, "Should be executable");
-        }
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("plfoo", true).await.unwrap();
+        assert!(installer.install("plbad", true).await.is_err());
+
+        let events = installer.pour_events(10).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].name, "plbad");
+        assert_eq!(events[0].kind, crate::PourEventKind::ChecksumMismatch);
+        assert_eq!(events[1].name, "plfoo");
+        assert_eq!(events[1].kind, crate::PourEventKind::Poured);
+        assert_eq!(events[1].sha256, sha);
+        assert!(events[1].url.ends_with(".bottle.tar.gz"));
+        assert!(events[1].bytes > 0);
+        assert!(events[1].relocated);
+
+        // Reinstalling the same keg doesn't relocate it again
+        installer.install("plfoo", true).await.unwrap();
+        assert!(!installer.pour_events(1).unwrap()[0].relocated);
     }
 
-    /// Test that store entry is created correctly.
     #[tokio::test]
-    async fn store_entry_created() {
+    async fn large_dependency_trees_resolve_from_the_full_index() {
         let mock_server = MockServer::start().await;
         let tmp = TempDir::new().unwrap();
-        let root = tmp.path().join("zerobrew");
+        let uri = mock_server.uri();
 
-        let bottle = mock_bottle_tarball_with_version("storepkg", "1.0.0");
-        let sha = sha256_hex(&bottle);
+        let deps: Vec<String> = (0..crate::install::planner::INDEX_PREFETCH_THRESHOLD)
+            .map(|i| format!("ixdep{i}"))
+            .collect();
+        let dep_refs: Vec<&str> = deps.iter().map(String::as_str).collect();
+        let mut entries = vec![mock_formula_json("ixroot", "1.0.0", &dep_refs, &uri, "00")];
+        entries.extend(
+            dep_refs
+                .iter()
+                .map(|dep| mock_formula_json(dep, "1.0.0", &[], &uri, "00")),
+        );
 
-        mount_formula(&mock_server, "storepkg", "1.0.0", &[]).await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula/ixroot.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&entries[0]))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/formula.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(format!("[{}]", entries.join(","))),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
 
         let mut installer = create_test_installer(&mock_server, &tmp);
-
-        installer.install("storepkg", true).await.unwrap();
-
-        // Store entry should exist
-        let store_entry = root.join("store").join(&sha);
-        assert!(
-            store_entry.exists(),
-            "Store entry should exist at {}",
-            store_entry.display()
-        );
+        installer
+            .api_client
+            .set_provider(crate::IndexProvider::Api(format!("{uri}/api/formula")));
+
+        // One request for the root, one for the index, none per dependency
+        let formulas = installer.fetch_all_formulas("ixroot").await.unwrap();
+        assert_eq!(formulas.len(), deps.len() + 1);
+        assert!(formulas.contains_key("ixdep0"));
     }
 }
 
@@ -6944,10 +9174,13 @@ mod orchestration_tests {
 // Additional mod.rs Coverage Tests
 // ============================================================================
 
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod mod_rs_coverage_tests {
+    use super::orchestration_tests::{mount_formula, mount_formula_with};
+    use crate::plan_file::PlanFile;
     use crate::test_utils::{
-        create_test_installer, mock_bottle_tarball_with_version, mock_formula_json,
-        platform_bottle_tag, sha256_hex,
+        create_test_installer, make_delta_patch, mock_bottle_tarball_with_version,
+        mock_formula_json, platform_bottle_tag, sha256_hex,
     };
     use std::fs;
     use tempfile::TempDir;
@@ -7198,6 +9431,38 @@ mod mod_rs_coverage_tests {
         }
     }
 
+    /// Test get_deps_tree annotates nodes from the database and fetched formulas.
+    #[tokio::test]
+    async fn get_deps_tree_annotates_versions_and_pins() {
+        let mut ctx = crate::test_utils::TestContext::new().await;
+
+        ctx.mount_formula("dep_d", "1.0.0", &[]).await;
+        ctx.mount_formula("pkg_a", "2.0.0", &["dep_d"]).await;
+
+        ctx.installer_mut().install("dep_d", true).await.unwrap();
+        assert!(ctx.installer().pin("dep_d").unwrap());
+
+        let tree = ctx.installer().get_deps_tree("pkg_a", false).await.unwrap();
+        assert_eq!(
+            tree.annotation,
+            super::super::DepAnnotation {
+                installed_version: None,
+                available_version: Some("2.0.0".to_string()),
+                pinned: false,
+                keg_only: false,
+            }
+        );
+        assert_eq!(
+            tree.children[0].annotation,
+            super::super::DepAnnotation {
+                installed_version: Some("1.0.0".to_string()),
+                available_version: Some("1.0.0".to_string()),
+                pinned: true,
+                keg_only: false,
+            }
+        );
+    }
+
     // ========================================================================
     // get_uses() and get_dependents() tests
     // ========================================================================
@@ -7755,6 +10020,181 @@ brew "missingpkg"
         assert!(!result.missing_formulas.contains(&"checkpkg".to_string()));
     }
 
+    /// Test bundle_install_locked refuses metadata that drifted from the lockfile.
+    #[tokio::test]
+    async fn bundle_install_locked_fails_on_drift() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        mount_formula(&mock_server, "lockpkg", "1.0.0", &["lockdep"]).await;
+        mount_formula(&mock_server, "lockdep", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let brewfile_path = tmp.path().join("Brewfile");
+        fs::write(&brewfile_path, "brew \"lockpkg\"\n").unwrap();
+
+        let lock = installer.bundle_lock(&brewfile_path, None).await.unwrap();
+        assert_eq!(
+            lock.entries.brew.keys().collect::<Vec<_>>(),
+            vec!["lockdep", "lockpkg"]
+        );
+        assert!(
+            lock.entries.brew["lockdep"].bottle.as_ref().unwrap().files[tag]
+                .url
+                .contains("lockdep-1.0.0")
+        );
+
+        // The dependency moves on after the lockfile was written
+        mock_server.reset().await;
+        mount_formula(&mock_server, "lockpkg", "1.0.0", &["lockdep"]).await;
+        mount_formula(&mock_server, "lockdep", "1.1.0", &[]).await;
+
+        let err = installer
+            .bundle_install_locked(&brewfile_path, &lock)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("lockdep is locked at 1.0.0 but resolves to 1.1.0"),
+            "{err}"
+        );
+        assert!(installer.get_installed("lockpkg").is_none());
+        assert!(installer.get_installed("lockdep").is_none());
+
+        // Relocking picks up the new version and installs exactly it
+        let lock = installer
+            .bundle_lock(&brewfile_path, Some(lock))
+            .await
+            .unwrap();
+        let result = installer
+            .bundle_install_locked(&brewfile_path, &lock)
+            .await
+            .unwrap();
+        assert_eq!(result.formulas_installed, vec!["lockpkg".to_string()]);
+        assert_eq!(installer.get_installed("lockdep").unwrap().version, "1.1.0");
+    }
+
+    /// Test upgrades rebuild bottles from a published delta, and download
+    /// the whole bottle when there is none.
+    #[tokio::test]
+    async fn upgrade_uses_bottle_deltas() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        async fn serve_formula(server: &MockServer, name: &str, version: &str) -> Vec<u8> {
+            let bottle = mock_bottle_tarball_with_version(name, version);
+            Mock::given(method("GET"))
+                .and(path(format!("/{name}.json")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_string(mock_formula_json(
+                        name,
+                        version,
+                        &[],
+                        &server.uri(),
+                        &sha256_hex(&bottle),
+                    )),
+                )
+                .mount(server)
+                .await;
+            bottle
+        }
+
+        async fn serve_bottle(server: &MockServer, name: &str, version: &str, bottle: Vec<u8>) {
+            let tag = platform_bottle_tag();
+            Mock::given(method("GET"))
+                .and(path(format!(
+                    "/bottles/{name}-{version}.{tag}.bottle.tar.gz"
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(server)
+                .await;
+        }
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let mut old_bottles = Vec::new();
+        for name in ["deltapkg", "fullpkg"] {
+            let bottle = serve_formula(&mock_server, name, "1.0.0").await;
+            serve_bottle(&mock_server, name, "1.0.0", bottle.clone()).await;
+            installer.install(name, true).await.unwrap();
+            old_bottles.push(bottle);
+        }
+
+        // 2.0.0 of deltapkg is only available as a patch
+        mock_server.reset().await;
+        let new_bottle = serve_formula(&mock_server, "deltapkg", "2.0.0").await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/deltas/{}/{}.zst",
+                sha256_hex(&old_bottles[0]),
+                sha256_hex(&new_bottle)
+            )))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(make_delta_patch(&old_bottles[0], &new_bottle)),
+            )
+            .mount(&mock_server)
+            .await;
+        let full_bottle = serve_formula(&mock_server, "fullpkg", "2.0.0").await;
+        serve_bottle(&mock_server, "fullpkg", "2.0.0", full_bottle).await;
+
+        installer.set_delta_url(&format!("{}/deltas", mock_server.uri()));
+        for name in ["deltapkg", "fullpkg"] {
+            installer.upgrade_one(name, true, None).await.unwrap();
+            assert_eq!(installer.get_installed(name).unwrap().version, "2.0.0");
+        }
+        let requests = mock_server.received_requests().await.unwrap();
+        assert!(
+            !requests
+                .iter()
+                .any(|r| r.url.path() == format!("/bottles/deltapkg-2.0.0.{tag}.bottle.tar.gz"))
+        );
+    }
+
+    /// Test an exported plan installs the planned bottles after the API has
+    /// moved on, and refuses bottles that were edited.
+    #[tokio::test]
+    async fn plan_file_replays_planned_versions() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = platform_bottle_tag();
+
+        mount_formula(&mock_server, "planpkg", "1.0.0", &["plandep"]).await;
+        mount_formula(&mock_server, "plandep", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let plan_path = tmp.path().join("plan.json");
+        let plan = installer.plan("planpkg").await.unwrap();
+        PlanFile::from_plan(&plan).write(&plan_path).unwrap();
+
+        // The API moves on after the plan was reviewed; only the planned
+        // bottles stay available
+        mock_server.reset().await;
+        for name in ["planpkg", "plandep"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_bytes(mock_bottle_tarball_with_version(name, "1.0.0")),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+
+        let mut edited = PlanFile::read(&plan_path).unwrap();
+        edited.packages[0].bottle.url = format!("{}/bottles/evil.tar.gz", mock_server.uri());
+        let err = installer.plan_from_file(edited).unwrap_err().to_string();
+        assert!(err.contains("the bottle of plandep"), "{err}");
+
+        let file = PlanFile::read(&plan_path).unwrap();
+        assert_eq!(file.packages[0].bottle.tag, tag);
+        let plan = installer.plan_from_file(file).unwrap();
+        installer.execute(plan, true).await.unwrap();
+        assert_eq!(installer.get_installed("planpkg").unwrap().version, "1.0.0");
+        assert_eq!(installer.get_installed("plandep").unwrap().version, "1.0.0");
+    }
+
     /// Test parse_brewfile parses entries correctly.
     #[tokio::test]
     async fn parse_brewfile_entries() {
@@ -7854,51 +10294,6 @@ brew "curl", args: ["--HEAD"]
         assert!(!installer.is_tapped("user", "repo"));
     }
 
-    // ========================================================================
-    // copy_dir_recursive() tests
-    // ========================================================================
-
-    /// Test copy_dir_recursive copies nested directories.
-    #[test]
-    fn copy_dir_recursive_nested() {
-        let src = TempDir::new().unwrap();
-        let dst = TempDir::new().unwrap();
-
-        // Create nested structure
-        fs::create_dir_all(src.path().join("a/b/c")).unwrap();
-        fs::write(src.path().join("a/b/c/file.txt"), "content").unwrap();
-        fs::write(src.path().join("a/b/file2.txt"), "content2").unwrap();
-        fs::write(src.path().join("a/file3.txt"), "content3").unwrap();
-
-        super::super::copy_dir_recursive(src.path(), dst.path()).unwrap();
-
-        // Verify all files copied
-        assert!(dst.path().join("a/b/c/file.txt").exists());
-        assert!(dst.path().join("a/b/file2.txt").exists());
-        assert!(dst.path().join("a/file3.txt").exists());
-
-        // Verify content
-        assert_eq!(
-            fs::read_to_string(dst.path().join("a/b/c/file.txt")).unwrap(),
-            "content"
-        );
-    }
-
-    /// Test copy_dir_recursive handles empty directories.
-    #[test]
-    fn copy_dir_recursive_empty_dirs() {
-        let src = TempDir::new().unwrap();
-        let dst = TempDir::new().unwrap();
-
-        // Create empty nested dirs
-        fs::create_dir_all(src.path().join("empty/nested/deep")).unwrap();
-
-        super::super::copy_dir_recursive(src.path(), dst.path()).unwrap();
-
-        // Empty dirs should be created
-        assert!(dst.path().join("empty/nested/deep").exists());
-    }
-
     // ========================================================================
     // get_leaves() additional tests
     // ========================================================================
@@ -7976,6 +10371,182 @@ brew "curl", args: ["--HEAD"]
         assert!(leaves.contains(&"leaf2".to_string()));
     }
 
+    /// Deprecated formulas install and show up in the audit
+    #[tokio::test]
+    async fn list_deprecated_reports_abandoned_packages() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "current", "1.0.0", &[]).await;
+        mount_formula_with(
+            &mock_server,
+            "oldtool",
+            "1.0.0",
+            &[],
+            serde_json::json!({"deprecated": true, "deprecation_reason": "unmaintained"}),
+        )
+        .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("current", true).await.unwrap();
+        installer.install("oldtool", true).await.unwrap();
+
+        let deprecated = installer.list_deprecated().await.unwrap();
+        assert_eq!(deprecated.len(), 1);
+        assert_eq!(deprecated[0].0.name, "oldtool");
+        assert_eq!(
+            deprecated[0].1,
+            "has been deprecated because it is not maintained upstream"
+        );
+    }
+
+    /// Disabled formulas need an explicit override
+    #[tokio::test]
+    async fn disabled_formula_requires_force() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula_with(
+            &mock_server,
+            "deadtool",
+            "1.0.0",
+            &[],
+            serde_json::json!({"disabled": true, "disable_date": "2024-01-01"}),
+        )
+        .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        let err = installer.install("deadtool", true).await.unwrap_err();
+        assert!(
+            matches!(err, zb_core::Error::DisabledFormula { ref name, .. } if name == "deadtool"),
+            "{err}"
+        );
+        assert!(!installer.is_installed("deadtool"));
+
+        installer.set_allow_disabled(true);
+        installer.install("deadtool", true).await.unwrap();
+        assert!(installer.is_installed("deadtool"));
+    }
+
+    /// Post-install steps run after the install is recorded, are logged, and
+    /// can be turned off per formula or per run
+    #[tokio::test]
+    async fn post_install_steps_run_after_install() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula_with(
+            &mock_server,
+            "dbtool",
+            "1.0.0",
+            &[],
+            serde_json::json!({
+                "post_install_defined": true,
+                "post_install": [
+                    {"type": "mkpath", "path": "{var}/dbtool"},
+                    {"type": "system", "args": ["{bin}/dbtool"]},
+                    {"type": "unsupported", "source": "ohai \"Done\""}
+                ]
+            }),
+        )
+        .await;
+        mount_formula_with(
+            &mock_server,
+            "brokentool",
+            "1.0.0",
+            &[],
+            serde_json::json!({
+                "post_install_defined": true,
+                "post_install": [
+                    {"type": "system", "args": ["{bin}/missing-binary"]},
+                    {"type": "mkpath", "path": "{var}/brokentool"}
+                ]
+            }),
+        )
+        .await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("dbtool", true).await.unwrap();
+        let var = tmp.path().join("homebrew/var");
+        assert!(var.join("dbtool").is_dir());
+        let log = fs::read_to_string(installer.post_install_log_path("dbtool")).unwrap();
+        assert!(log.contains("mkdir -p"), "{log}");
+        assert!(log.contains("bin/dbtool"), "{log}");
+        assert!(log.contains("skipped (unsupported): ohai"), "{log}");
+
+        // A failing step is reported, stops later steps and keeps the install
+        installer.install("brokentool", true).await.unwrap();
+        assert!(installer.is_installed("brokentool"));
+        assert!(!var.join("brokentool").exists());
+        let result = installer.post_install("brokentool").await.unwrap();
+        assert_eq!(result.ran, 0);
+        assert!(result.error.unwrap().contains("missing-binary"));
+
+        // Opted-out formulas only run when asked to
+        fs::remove_dir(var.join("dbtool")).unwrap();
+        assert!(installer.disable_post_install("dbtool").unwrap());
+        assert_eq!(installer.list_post_install_disabled().unwrap(), ["dbtool"]);
+        installer.uninstall("dbtool").unwrap();
+        installer.install("dbtool", true).await.unwrap();
+        assert!(!var.join("dbtool").exists());
+
+        let result = installer.post_install("dbtool").await.unwrap();
+        assert_eq!(result.ran, 2);
+        assert_eq!(result.unsupported, ["ohai \"Done\""]);
+        assert!(var.join("dbtool").is_dir());
+
+        assert!(installer.enable_post_install("dbtool").unwrap());
+        fs::remove_dir(var.join("dbtool")).unwrap();
+        installer.set_skip_post_install(true);
+        installer.uninstall("dbtool").unwrap();
+        installer.install("dbtool", true).await.unwrap();
+        assert!(!var.join("dbtool").exists());
+    }
+
+    /// Setting link environment relinks executables through wrappers, and
+    /// the setting survives a new installer
+    #[tokio::test]
+    async fn link_env_relinks_through_wrappers() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        mount_formula(&mock_server, "gittool", "1.0.0", &[]).await;
+
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        installer.install("gittool", true).await.unwrap();
+        let link = tmp.path().join("homebrew/bin/gittool");
+        assert!(fs::read_link(&link).is_ok());
+
+        let env = installer
+            .set_link_env(
+                "gittool",
+                &[("GIT_EXEC_PATH".to_string(), "{opt}/libexec".to_string())],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(env.len(), 1);
+        assert!(fs::read_link(&link).is_err());
+        assert!(
+            fs::read_to_string(&link)
+                .unwrap()
+                .contains("export GIT_EXEC_PATH=")
+        );
+
+        // A fresh installer loads the environment and keeps the wrapper
+        drop(installer);
+        let mut installer = create_test_installer(&mock_server, &tmp);
+        assert_eq!(installer.link_env("gittool").unwrap(), env);
+        installer.unlink("gittool").unwrap();
+        installer.link("gittool", false, false).unwrap();
+        assert!(fs::read_link(&link).is_err());
+
+        let err = installer
+            .set_link_env("gittool", &[("BAD-NAME".to_string(), "x".to_string())], &[])
+            .unwrap_err();
+        assert!(err.to_string().contains("BAD-NAME"), "{err}");
+
+        installer
+            .set_link_env("gittool", &[], &["GIT_EXEC_PATH".to_string()])
+            .unwrap();
+        assert!(fs::read_link(&link).is_ok());
+    }
+
     // ========================================================================
     // CleanupResult and struct coverage
     // ========================================================================
@@ -7998,12 +10569,14 @@ brew "curl", args: ["--HEAD"]
         let child = super::super::DepsTree {
             name: "child".to_string(),
             installed: true,
+            annotation: Default::default(),
             children: vec![],
         };
 
         let parent = super::super::DepsTree {
             name: "parent".to_string(),
             installed: false,
+            annotation: Default::default(),
             children: vec![child],
         };
 
@@ -8018,6 +10591,7 @@ brew "curl", args: ["--HEAD"]
     #[test]
     fn link_result_fields() {
         let result = super::super::LinkResult {
+            backed_up: Vec::new(),
             files_linked: 10,
             already_linked: false,
             keg_only_forced: true,
@@ -8037,6 +10611,12 @@ brew "curl", args: ["--HEAD"]
             store_key: "abc123".to_string(),
             linked_files: vec![],
             explicit: true,
+            linked: true,
+            keg_only: false,
+            dependencies: vec![],
+            provenance: crate::db::Provenance::Cli,
+            receipt: crate::receipt::InstallReceipt::bottle(&zb_core::Formula::default()),
+            config_files: vec![],
         };
 
         assert_eq!(pkg.name, "testpkg");
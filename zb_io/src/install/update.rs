@@ -0,0 +1,165 @@
+//! Metadata refresh
+//!
+//! This module handles:
+//! - Fetching the formula list again and comparing it with the cached one
+//! - Dropping cached metadata of formulas that changed
+//! - Refreshing the cached formulas of every tap
+
+use std::collections::HashMap;
+
+use zb_core::Error;
+
+use super::Installer;
+use crate::api::FormulaInfo;
+use crate::tap::TapRefresh;
+
+/// A formula whose version changed since the last update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormulaChange {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// What `zb update` changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateSummary {
+    /// Formulas in the catalog now
+    pub total: usize,
+    /// False if there was no cached formula list to compare against
+    pub had_previous: bool,
+    /// Formulas new to the catalog
+    pub added: Vec<String>,
+    /// Formulas with a new version
+    pub updated: Vec<FormulaChange>,
+    /// Formulas dropped from the catalog
+    pub removed: Vec<String>,
+    /// What refreshing each tap changed
+    pub taps: Vec<TapRefresh>,
+}
+
+impl Installer {
+    /// Fetch the formula list and every tap's formulas again, and report
+    /// what changed since they were last fetched
+    pub async fn update(&self) -> Result<UpdateSummary, Error> {
+        let previous = self.api_client.cached_formula_list();
+        let current = self.api_client.refresh_formula_list().await?;
+
+        let mut summary = UpdateSummary {
+            total: current.len(),
+            had_previous: previous.is_some(),
+            ..UpdateSummary::default()
+        };
+        if let Some(previous) = previous {
+            let (added, updated, removed) = diff_formula_lists(&previous, &current);
+            summary.added = added;
+            summary.updated = updated;
+            summary.removed = removed;
+        }
+
+        // A formula cached before its version changed would be served until
+        // its TTL ran out
+        for change in &summary.updated {
+            self.api_client.invalidate_formula(&change.name);
+        }
+        for name in &summary.removed {
+            self.api_client.invalidate_formula(name);
+        }
+
+        for tap in self.tap_manager.list_taps()? {
+            let Some((user, repo)) = tap.name.split_once('/') else {
+                continue;
+            };
+            summary
+                .taps
+                .push(self.tap_manager.refresh_tap(user, repo).await?);
+        }
+
+        Ok(summary)
+    }
+}
+
+/// Formulas added, updated and removed between two formula lists, each
+/// sorted by name
+pub(crate) fn diff_formula_lists(
+    previous: &[FormulaInfo],
+    current: &[FormulaInfo],
+) -> (Vec<String>, Vec<FormulaChange>, Vec<String>) {
+    let previous: HashMap<&str, Option<&str>> = previous
+        .iter()
+        .map(|f| (f.name.as_str(), f.versions.stable.as_deref()))
+        .collect();
+    let current_names: HashMap<&str, Option<&str>> = current
+        .iter()
+        .map(|f| (f.name.as_str(), f.versions.stable.as_deref()))
+        .collect();
+
+    let mut added = Vec::new();
+    let mut updated = Vec::new();
+    for (name, version) in &current_names {
+        match previous.get(name) {
+            None => added.push(name.to_string()),
+            Some(old) if old != version => updated.push(FormulaChange {
+                name: name.to_string(),
+                from: old.unwrap_or_default().to_string(),
+                to: version.unwrap_or_default().to_string(),
+            }),
+            Some(_) => {}
+        }
+    }
+    let mut removed: Vec<String> = previous
+        .keys()
+        .filter(|name| !current_names.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+
+    added.sort();
+    updated.sort_by(|a, b| a.name.cmp(&b.name));
+    removed.sort();
+    (added, updated, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::FormulaVersions;
+
+    fn info(name: &str, version: &str) -> FormulaInfo {
+        FormulaInfo {
+            name: name.to_string(),
+            full_name: name.to_string(),
+            desc: None,
+            homepage: None,
+            versions: FormulaVersions {
+                stable: Some(version.to_string()),
+            },
+            aliases: Vec::new(),
+            deprecated: false,
+            disabled: false,
+            dependencies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn diff_formula_lists_finds_added_updated_and_removed() {
+        let previous = [info("jq", "1.7"), info("old", "1.0"), info("wget", "1.24")];
+        let current = [
+            info("wget", "1.25"),
+            info("jq", "1.7"),
+            info("fresh", "0.1"),
+        ];
+
+        let (added, updated, removed) = diff_formula_lists(&previous, &current);
+
+        assert_eq!(added, ["fresh"]);
+        assert_eq!(
+            updated,
+            [FormulaChange {
+                name: "wget".to_string(),
+                from: "1.24".to_string(),
+                to: "1.25".to_string(),
+            }]
+        );
+        assert_eq!(removed, ["old"]);
+    }
+}
@@ -1,17 +1,29 @@
 //! Upgrade-specific logic
 //!
 //! This module handles:
-//! - Upgrading packages
-//! - Detecting outdated packages
+//! - Upgrading packages, from bottle deltas where available
+//! - Planning upgrades across packages, dependencies first
+//! - Detecting outdated packages, including HEAD installs behind upstream
+//!   and tap formulas behind their tap's HEAD
 //! - Pin/unpin functionality
+//! - Maintenance windows for scheduled upgrades
 
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
+use crate::build::{head_version, remote_head_commit};
+use crate::db::InstalledKeg;
+use crate::delta;
 use crate::progress::ProgressCallback;
+use crate::receipt::BuiltFrom;
+use crate::schedule::{UpgradeWindow, UpgradeWindowStatus, window_status};
+use crate::tap::TapFormula;
 
-use zb_core::{Error, OutdatedPackage, Version};
+use futures::stream::{self, StreamExt};
+use zb_core::{Error, Formula, OutdatedPackage, Version};
 
 use super::Installer;
+use super::planner::{CORE_TAP, InstallPlan, MAX_CONCURRENT_FETCHES, tap_ref};
 
 /// Result of an upgrade operation
 pub struct UpgradeResult {
@@ -21,11 +33,75 @@ pub struct UpgradeResult {
     pub packages: Vec<(String, String, String)>,
 }
 
+/// Why a package is part of an upgrade plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpgradeReason {
+    /// Asked for by name, or outdated when upgrading everything
+    Requested,
+    /// An outdated dependency of a package being upgraded
+    Dependency { of: String },
+    /// An outdated package that depends on one being upgraded (greedy)
+    Dependent { of: String },
+    /// Built from source against a package being upgraded (greedy)
+    Rebuild { of: String },
+}
+
+/// One package in an upgrade plan
+#[derive(Debug, Clone)]
+pub struct UpgradeStep {
+    /// For rebuilds, both versions are the installed one
+    pub package: OutdatedPackage,
+    pub reason: UpgradeReason,
+}
+
+/// Upgrades to run in order, each package after the dependencies it
+/// is being upgraded with
+#[derive(Debug, Clone, Default)]
+pub struct UpgradePlan {
+    pub steps: Vec<UpgradeStep>,
+}
+
+/// Outdated packages from one formula source
+#[derive(Debug, Clone)]
+pub struct OutdatedGroup {
+    /// `homebrew/core`, or the `user/repo` of a tap
+    pub source: String,
+    /// When the source's formulas were last fetched (Unix timestamp), if known.
+    /// For homebrew/core this is the oldest metadata the check relied on.
+    pub updated_at: Option<i64>,
+    /// Sorted by name; empty when everything from the source is up to date
+    pub packages: Vec<OutdatedPackage>,
+}
+
+/// Where a rollback found the previous version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackSource {
+    /// An old keg still in the Cellar
+    Keg,
+    /// A bottle left in the store, materialized into a keg again
+    Store,
+}
+
+/// Result of a rollback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollbackResult {
+    /// Version that was active before the rollback
+    pub from_version: String,
+    /// Version that is active now
+    pub to_version: String,
+    pub source: RollbackSource,
+    /// Files linked into the prefix; zero if the formula was not linked
+    pub files_linked: usize,
+    /// Whether the formula is pinned, so upgrades won't undo the rollback
+    pub pinned: bool,
+}
+
 impl Installer {
     /// Check for outdated packages by comparing installed versions against API.
     /// By default, excludes pinned packages.
     pub async fn get_outdated(&self) -> Result<Vec<OutdatedPackage>, Error> {
-        self.get_outdated_impl(false).await
+        self.get_outdated_with_progress(false, false, |_, _| {})
+            .await
     }
 
     /// Check for outdated packages, optionally including pinned packages
@@ -33,10 +109,39 @@ impl Installer {
         &self,
         include_pinned: bool,
     ) -> Result<Vec<OutdatedPackage>, Error> {
-        self.get_outdated_impl(include_pinned).await
+        self.get_outdated_with_progress(include_pinned, false, |_, _| {})
+            .await
     }
 
-    async fn get_outdated_impl(&self, include_pinned: bool) -> Result<Vec<OutdatedPackage>, Error> {
+    /// Check for outdated packages, also asking the upstream git repository
+    /// of each HEAD install whether its branch has moved on.
+    /// Excludes pinned packages.
+    pub async fn get_outdated_fetch_head(&self) -> Result<Vec<OutdatedPackage>, Error> {
+        self.get_outdated_with_progress(false, true, |_, _| {})
+            .await
+    }
+
+    /// Check for outdated packages with HEAD upstream checks, optionally
+    /// including pinned packages.
+    pub async fn get_outdated_fetch_head_with_pinned(
+        &self,
+        include_pinned: bool,
+    ) -> Result<Vec<OutdatedPackage>, Error> {
+        self.get_outdated_with_progress(include_pinned, true, |_, _| {})
+            .await
+    }
+
+    /// Check for outdated packages, calling `progress` with the number of
+    /// formulas checked so far and the total as each one comes back.
+    ///
+    /// Formulas are fetched concurrently, and any fetched within the last
+    /// few minutes are answered from the API cache.
+    pub async fn get_outdated_with_progress(
+        &self,
+        include_pinned: bool,
+        fetch_head: bool,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<OutdatedPackage>, Error> {
         let installed = self.db.list_installed()?;
 
         if installed.is_empty() {
@@ -54,18 +159,52 @@ impl Installer {
             return Ok(Vec::new());
         }
 
-        // Fetch all formulas from API in parallel
-        let futures: Vec<_> = to_check
-            .iter()
-            .map(|keg| self.api_client.get_formula(&keg.name))
-            .collect();
-
-        let results = futures::future::join_all(futures).await;
+        // Fetch formulas with bounded concurrency, keeping results in keg order
+        let total = to_check.len();
+        let mut results: Vec<Option<Result<Formula, Error>>> =
+            std::iter::repeat_with(|| None).take(total).collect();
+        let mut fetches = stream::iter(to_check.iter().enumerate())
+            .map(|(idx, keg)| async move {
+                let result = if tap_ref(keg).is_some() {
+                    self.current_tap_formula(keg).await
+                } else {
+                    self.api_client.get_recent_formula(&keg.name).await
+                };
+                (idx, result)
+            })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES);
+
+        let mut done = 0;
+        progress(done, total);
+        while let Some((idx, result)) = fetches.next().await {
+            results[idx] = Some(result);
+            done += 1;
+            progress(done, total);
+        }
+        drop(fetches);
 
         let mut outdated = Vec::new();
 
-        for (keg, result) in to_check.iter().zip(results.into_iter()) {
+        for (keg, result) in to_check.iter().zip(results.into_iter().flatten()) {
             match result {
+                Ok(formula) if is_head_install(keg) => {
+                    if !fetch_head {
+                        continue;
+                    }
+                    match newer_head_commit(keg, &formula) {
+                        Ok(Some(commit)) => outdated.push(OutdatedPackage {
+                            name: keg.name.clone(),
+                            installed_version: keg.version.clone(),
+                            available_version: head_version(&commit),
+                        }),
+                        Ok(None) => {}
+                        Err(e) => {
+                            self.context
+                                .logger
+                                .warn(format!("failed to check {}: {}", keg.name, e));
+                        }
+                    }
+                }
                 Ok(formula) => {
                     let installed_ver = Version::parse(&keg.version);
                     let available_ver = Version::parse(&formula.effective_version());
@@ -84,7 +223,9 @@ impl Installer {
                 }
                 Err(e) => {
                     // Log warning but continue checking other packages
-                    eprintln!("    Warning: failed to check {}: {}", keg.name, e);
+                    self.context
+                        .logger
+                        .warn(format!("failed to check {}: {}", keg.name, e));
                 }
             }
         }
@@ -95,6 +236,86 @@ impl Installer {
         Ok(outdated)
     }
 
+    /// Check for outdated packages like
+    /// [`get_outdated_with_progress`](Self::get_outdated_with_progress),
+    /// grouped by the source each package was installed from: homebrew/core
+    /// first, then taps by name. Every source packages were checked from has
+    /// a group, even when nothing from it is outdated.
+    pub async fn get_outdated_report(
+        &self,
+        include_pinned: bool,
+        fetch_head: bool,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<Vec<OutdatedGroup>, Error> {
+        let outdated = self
+            .get_outdated_with_progress(include_pinned, fetch_head, progress)
+            .await?;
+        let kegs: Vec<InstalledKeg> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| include_pinned || !keg.pinned)
+            .collect();
+
+        let mut sources: BTreeMap<&str, Vec<&InstalledKeg>> = BTreeMap::new();
+        for keg in &kegs {
+            sources.entry(keg_source(keg)).or_default().push(keg);
+        }
+
+        let mut groups: Vec<OutdatedGroup> = sources
+            .into_iter()
+            .map(|(source, kegs)| {
+                let updated_at = if source == CORE_TAP {
+                    kegs.iter()
+                        .filter_map(|keg| self.api_client.formula_fetched_at(&keg.name))
+                        .min()
+                } else {
+                    source
+                        .split_once('/')
+                        .and_then(|(user, repo)| self.tap_manager.tap_info(user, repo))
+                        .and_then(|info| info.updated_at)
+                };
+                let packages = outdated
+                    .iter()
+                    .filter(|pkg| kegs.iter().any(|keg| keg.name == pkg.name))
+                    .cloned()
+                    .collect();
+                OutdatedGroup {
+                    source: source.to_string(),
+                    updated_at,
+                    packages,
+                }
+            })
+            .collect();
+        groups.sort_by_key(|group| group.source != CORE_TAP);
+
+        Ok(groups)
+    }
+
+    /// The formula a tap keg was installed from, as of the tap's current
+    /// HEAD. Falls back to the cached copy when offline or when the tap
+    /// can't be reached.
+    async fn current_tap_formula(&self, keg: &InstalledKeg) -> Result<Formula, Error> {
+        if !self.context.offline
+            && let Some(tap) = tap_ref(keg).as_deref().and_then(TapFormula::parse)
+        {
+            match self
+                .tap_manager
+                .refresh_formula(&tap.user, &tap.repo, &tap.formula)
+                .await
+            {
+                Ok(formula) => return Ok(formula),
+                Err(e) => self.context.logger.warn(format!(
+                    "failed to refresh {} from {}: {}",
+                    keg.name,
+                    tap.tap_name(),
+                    e
+                )),
+            }
+        }
+        self.installed_formula(keg).await
+    }
+
     /// Upgrade a single package to its latest version
     /// Returns the old and new version if upgraded, None if already up to date
     pub async fn upgrade_one(
@@ -104,12 +325,21 @@ impl Installer {
         progress: Option<Arc<ProgressCallback>>,
     ) -> Result<Option<(String, String)>, Error> {
         // Check if installed
-        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+        let installed = self.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
 
+        // Kegs from a tap other than homebrew/core upgrade from that tap
+        let qualified = tap_ref(&installed).unwrap_or_else(|| name.to_string());
+        let name = installed.name.as_str();
+
         // Fetch new formula to check version
-        let new_formula = self.api_client.get_formula(name).await?;
+        let new_formula = self.fetch_formula(&qualified).await?;
+
+        if is_head_install(&installed) {
+            return self.upgrade_head(&installed, &new_formula, link).await;
+        }
+
         let new_version = new_formula.effective_version();
 
         // Check if already up to date using version comparison
@@ -122,24 +352,183 @@ impl Installer {
 
         let old_version = installed.version.clone();
 
+        // Kegs built with custom arguments are rebuilt the same way; plain
+        // source builds move over to bottles
+        if let Some(receipt) = &installed.receipt {
+            if receipt.has_build_args() {
+                let new_version = self.rebuild_from_source(&installed, link, false).await?;
+                return Ok(Some((old_version, new_version)));
+            }
+            if receipt.built_from == BuiltFrom::Source {
+                self.context.logger.warn(format!(
+                    "{} was built from source; upgrading to a bottle",
+                    name
+                ));
+            }
+        }
+
         // Plan the new installation (handles dependencies)
-        let plan = self.plan(name).await?;
+        let plan = self.plan(&qualified).await?;
 
-        // Unlink the old version
-        let old_keg_path = self.cellar.keg_path(name, &old_version);
-        self.linker.unlink_keg(&old_keg_path)?;
+        // Without linking there is nothing to swap, so just drop the old
+        // links; opt/ moves over once the new keg is in the Cellar
+        if !link {
+            let old_keg_path = self.cellar.keg_path(name, &old_version);
+            self.linker.unlink_prefix(&old_keg_path)?;
+        }
 
-        // Install new version
+        // Install new version. The old version stays linked until the new
+        // keg is in the Cellar, then its links are swapped over in place.
         // Note: execute_with_progress uses INSERT OR REPLACE for database,
         // so it will automatically update the record for this package
+        self.fetch_deltas(&plan).await;
         self.execute_with_progress(plan, link, progress).await?;
 
-        // Remove old keg (only for the upgraded package, not dependencies)
-        self.cellar.remove_keg(name, &old_version)?;
+        // The old keg stays in the Cellar so `zb switch` can go back to it
+        // until the next `zb gc`
 
         Ok(Some((old_version, new_version)))
     }
 
+    /// Rebuild the bottles `plan` upgrades to from the cached bottles of the
+    /// installed versions, where the delta server has a patch between them.
+    /// Bottles that can't be rebuilt are downloaded in full as usual.
+    pub(crate) async fn fetch_deltas(&self, plan: &InstallPlan) {
+        let Some(base) = &self.delta_url else {
+            return;
+        };
+        if self.refetch_bottles {
+            return;
+        }
+
+        let deltas = plan
+            .formulas
+            .iter()
+            .zip(&plan.bottles)
+            .filter_map(|(formula, bottle)| {
+                let from = self.db.get_installed(&formula.name)?.store_key;
+                let cached = self.blob_cache.has_blob(&from);
+                (cached && from != bottle.sha256 && !self.blob_cache.has_blob(&bottle.sha256))
+                    .then_some((from, bottle))
+            })
+            .map(|(from, bottle)| async move {
+                let url = delta::delta_url(base, &from, &bottle.sha256);
+                let _ = self
+                    .downloader
+                    .download_delta(&url, &from, &bottle.sha256)
+                    .await;
+            });
+        futures::future::join_all(deltas).await;
+    }
+
+    /// Rebuild a HEAD install if its upstream branch has new commits
+    async fn upgrade_head(
+        &mut self,
+        installed: &InstalledKeg,
+        formula: &Formula,
+        link: bool,
+    ) -> Result<Option<(String, String)>, Error> {
+        if newer_head_commit(installed, formula)?.is_none() {
+            return Ok(None);
+        }
+
+        let new_version = self.rebuild_from_source(installed, link, true).await?;
+        Ok(Some((installed.version.clone(), new_version)))
+    }
+
+    /// Build the latest version of an installed formula from source with
+    /// the build arguments in its receipt. Returns the new version.
+    async fn rebuild_from_source(
+        &mut self,
+        installed: &InstalledKeg,
+        link: bool,
+        head: bool,
+    ) -> Result<String, Error> {
+        if !link {
+            let old_keg_path = self.cellar.keg_path(&installed.name, &installed.version);
+            self.linker.unlink_prefix(&old_keg_path)?;
+        }
+
+        let build_args = installed
+            .receipt
+            .as_ref()
+            .map(|r| r.build_args.clone())
+            .unwrap_or_default();
+        let name = tap_ref(installed).unwrap_or_else(|| installed.name.clone());
+        let result = self
+            .install_from_source(&name, link, head, &build_args)
+            .await?;
+        if !installed.explicit {
+            self.db.mark_dependency(&installed.name)?;
+        }
+
+        Ok(result.version)
+    }
+
+    /// Plan upgrades for `outdated` packages as one dependency-ordered list.
+    ///
+    /// With `requested`, only that package and its outdated dependencies are
+    /// planned; otherwise every package in `outdated`. `greedy` also plans
+    /// outdated packages that depend on anything being upgraded, and
+    /// rebuilds unpinned source builds that do.
+    pub async fn plan_upgrade(
+        &self,
+        outdated: Vec<OutdatedPackage>,
+        requested: Option<&str>,
+        greedy: bool,
+    ) -> Result<UpgradePlan, Error> {
+        let graph = self.dependency_graph().await?;
+        let source_built: BTreeMap<String, String> = if greedy {
+            self.db
+                .list_installed()?
+                .into_iter()
+                .filter(|keg| !keg.pinned)
+                .filter(|keg| {
+                    keg.receipt
+                        .as_ref()
+                        .is_some_and(|r| r.built_from != BuiltFrom::Bottle)
+                })
+                .map(|keg| (keg.name, keg.version))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(UpgradePlan {
+            steps: order_upgrades(&graph, outdated, requested, greedy, &source_built),
+        })
+    }
+
+    /// Run one step of an upgrade plan.
+    /// Returns the old and new version, or None if it was already up to date.
+    pub async fn upgrade_step(
+        &mut self,
+        step: &UpgradeStep,
+        link: bool,
+        progress: Option<Arc<ProgressCallback>>,
+    ) -> Result<Option<(String, String)>, Error> {
+        match step.reason {
+            UpgradeReason::Rebuild { .. } => self.rebuild(&step.package.name, link).await.map(Some),
+            _ => self.upgrade_one(&step.package.name, link, progress).await,
+        }
+    }
+
+    /// Rebuild an installed source build with the arguments in its receipt,
+    /// e.g. after one of its dependencies was upgraded.
+    /// Returns the old and new version.
+    pub async fn rebuild(&mut self, name: &str, link: bool) -> Result<(String, String), Error> {
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+        let head = installed
+            .receipt
+            .as_ref()
+            .is_some_and(|r| r.built_from == BuiltFrom::Head);
+
+        let new_version = self.rebuild_from_source(&installed, link, head).await?;
+        Ok((installed.version, new_version))
+    }
+
     /// Upgrade all outdated packages
     pub async fn upgrade_all(
         &mut self,
@@ -155,13 +544,14 @@ impl Installer {
             });
         }
 
+        let plan = self.plan_upgrade(outdated, None, false).await?;
         let mut packages = Vec::new();
 
-        for pkg in outdated {
+        for step in &plan.steps {
             if let Some((old_ver, new_ver)) =
-                self.upgrade_one(&pkg.name, link, progress.clone()).await?
+                self.upgrade_step(step, link, progress.clone()).await?
             {
-                packages.push((pkg.name, old_ver, new_ver));
+                packages.push((step.package.name.clone(), old_ver, new_ver));
             }
         }
 
@@ -171,6 +561,84 @@ impl Installer {
         })
     }
 
+    // ========== Rollback ==========
+
+    /// Return a formula to the version it had before.
+    ///
+    /// Prefers an old keg still in the Cellar, then the newest older version
+    /// whose bottle is still in the store. The formula is relinked only if it
+    /// was linked, and pinned when `pin` is set.
+    pub fn rollback(&mut self, name: &str, pin: bool) -> Result<RollbackResult, Error> {
+        let _lock = self.store.lock_formula(name)?;
+        let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
+            name: name.to_string(),
+        })?;
+
+        let (version, store_key) = self
+            .previous_version(name, &installed.version)?
+            .ok_or_else(|| Error::NoPreviousVersion {
+                name: name.to_string(),
+            })?;
+
+        let source = match store_key {
+            Some(store_key) => {
+                self.cellar
+                    .materialize(name, &version, &self.store.entry_path(&store_key))?;
+                let tx = self.db.transaction()?;
+                tx.record_keg_version(name, &version, &store_key)?;
+                tx.commit()?;
+                RollbackSource::Store
+            }
+            None => RollbackSource::Keg,
+        };
+
+        let files_linked = self.switch_locked(name, &version)?.files_linked;
+
+        if pin {
+            self.db.pin(name)?;
+        }
+
+        Ok(RollbackResult {
+            from_version: installed.version,
+            to_version: version,
+            source,
+            files_linked,
+            pinned: pin || installed.pinned,
+        })
+    }
+
+    /// The version to roll back to, with the store key to restore it from
+    /// when no keg of it is left
+    fn previous_version(
+        &self,
+        name: &str,
+        current: &str,
+    ) -> Result<Option<(String, Option<String>)>, Error> {
+        // Versions are listed oldest install first
+        if let Some(keg) = self
+            .db
+            .list_versions(name)?
+            .into_iter()
+            .rev()
+            .find(|v| v.version != current && self.cellar.has_keg(name, &v.version))
+        {
+            return Ok(Some((keg.version, None)));
+        }
+
+        let current = Version::parse(current);
+        let from_store = self
+            .store
+            .formula_versions(name)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to list store entries: {e}"),
+            })?
+            .into_iter()
+            .filter(|(version, _)| Version::parse(version) < current)
+            .max_by_key(|(version, _)| Version::parse(version));
+
+        Ok(from_store.map(|(version, store_key)| (version, Some(store_key))))
+    }
+
     // ========== Pin Operations ==========
 
     /// List only pinned formulas
@@ -204,4 +672,300 @@ impl Installer {
     pub fn is_pinned(&self, name: &str) -> bool {
         self.db.is_pinned(name)
     }
+
+    // ========== Maintenance Windows ==========
+
+    /// Allow scheduled upgrades during `window`.
+    /// Returns false if it was already allowed.
+    pub fn add_upgrade_window(&self, window: &UpgradeWindow) -> Result<bool, Error> {
+        self.db.add_upgrade_window(&window.to_string())
+    }
+
+    /// Remove an upgrade window. Returns false if it wasn't configured.
+    pub fn remove_upgrade_window(&self, window: &UpgradeWindow) -> Result<bool, Error> {
+        self.db.remove_upgrade_window(&window.to_string())
+    }
+
+    /// Windows scheduled upgrades may run in; empty if they may run any time
+    pub fn list_upgrade_windows(&self) -> Result<Vec<UpgradeWindow>, Error> {
+        Ok(self
+            .db
+            .list_upgrade_windows()?
+            .iter()
+            .filter_map(|spec| spec.parse().ok())
+            .collect())
+    }
+
+    /// Whether a scheduled upgrade may run now, in local time
+    pub fn upgrade_window_status(&self) -> Result<UpgradeWindowStatus, Error> {
+        Ok(window_status(
+            &self.list_upgrade_windows()?,
+            chrono::Local::now().naive_local(),
+        ))
+    }
+}
+
+/// Select and order upgrade steps over the installed dependency graph.
+///
+/// `source_built` maps source-built packages that may be rebuilt to their
+/// installed version.
+fn order_upgrades(
+    graph: &BTreeMap<String, Vec<String>>,
+    outdated: Vec<OutdatedPackage>,
+    requested: Option<&str>,
+    greedy: bool,
+    source_built: &BTreeMap<String, String>,
+) -> Vec<UpgradeStep> {
+    let outdated: BTreeMap<String, OutdatedPackage> = outdated
+        .into_iter()
+        .map(|pkg| (pkg.name.clone(), pkg))
+        .collect();
+
+    let roots: Vec<String> = match requested {
+        Some(name) => vec![name.to_string()],
+        None => outdated.keys().cloned().collect(),
+    };
+    let mut selected: BTreeMap<String, UpgradeReason> = roots
+        .iter()
+        .filter(|name| outdated.contains_key(*name))
+        .map(|name| (name.clone(), UpgradeReason::Requested))
+        .collect();
+
+    // Packages depending on anything upgraded, transitively
+    if greedy {
+        let mut dependents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (name, deps) in graph {
+            for dep in deps {
+                dependents.entry(dep).or_default().push(name);
+            }
+        }
+
+        let mut queue: Vec<String> = selected.keys().cloned().collect();
+        while let Some(name) = queue.pop() {
+            for &dependent in dependents.get(name.as_str()).into_iter().flatten() {
+                if selected.contains_key(dependent) {
+                    continue;
+                }
+                let reason = if outdated.contains_key(dependent) {
+                    UpgradeReason::Dependent { of: name.clone() }
+                } else if source_built.contains_key(dependent) {
+                    UpgradeReason::Rebuild { of: name.clone() }
+                } else {
+                    continue;
+                };
+                selected.insert(dependent.to_string(), reason);
+                queue.push(dependent.to_string());
+            }
+        }
+    }
+
+    // Outdated dependencies of everything planned so far
+    let planned: Vec<String> = roots.into_iter().chain(selected.keys().cloned()).collect();
+    for root in planned {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![root.clone()];
+        while let Some(name) = stack.pop() {
+            for dep in graph.get(&name).into_iter().flatten() {
+                if !seen.insert(dep.clone()) {
+                    continue;
+                }
+                if outdated.contains_key(dep) && !selected.contains_key(dep) {
+                    selected.insert(dep.clone(), UpgradeReason::Dependency { of: root.clone() });
+                }
+                stack.push(dep.clone());
+            }
+        }
+    }
+
+    // Dependencies first: a depth-first post-order over the graph
+    fn visit(
+        name: &str,
+        graph: &BTreeMap<String, Vec<String>>,
+        visited: &mut BTreeSet<String>,
+        order: &mut Vec<String>,
+    ) {
+        if !visited.insert(name.to_string()) {
+            return;
+        }
+        for dep in graph.get(name).into_iter().flatten() {
+            visit(dep, graph, visited, order);
+        }
+        order.push(name.to_string());
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    for name in selected.keys() {
+        visit(name, graph, &mut visited, &mut order);
+    }
+
+    order
+        .into_iter()
+        .filter_map(|name| {
+            let reason = selected.remove(&name)?;
+            let package = match outdated.get(&name) {
+                Some(pkg) => pkg.clone(),
+                None => {
+                    let version = source_built.get(&name)?.clone();
+                    OutdatedPackage {
+                        name,
+                        installed_version: version.clone(),
+                        available_version: version,
+                    }
+                }
+            };
+            Some(UpgradeStep { package, reason })
+        })
+        .collect()
+}
+
+/// The formula source a keg was installed from: its tap, or homebrew/core
+pub(super) fn keg_source(keg: &InstalledKeg) -> &str {
+    keg.receipt
+        .as_ref()
+        .and_then(|receipt| receipt.tap.as_deref())
+        .unwrap_or(CORE_TAP)
+}
+
+/// True if the keg was built from HEAD and knows which commit
+fn is_head_install(keg: &InstalledKeg) -> bool {
+    keg.version.starts_with("HEAD") && keg.head_commit.is_some()
+}
+
+/// The upstream branch tip of a HEAD install, if it differs from the
+/// commit the keg was built from
+fn newer_head_commit(keg: &InstalledKeg, formula: &Formula) -> Result<Option<String>, Error> {
+    let head = formula
+        .urls
+        .head
+        .as_ref()
+        .ok_or_else(|| Error::StoreCorruption {
+            message: format!("formula '{}' no longer has a HEAD source", keg.name),
+        })?;
+
+    let tip = remote_head_commit(&head.url, head.branch.as_deref())?;
+    Ok((keg.head_commit.as_deref() != Some(tip.as_str())).then_some(tip))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        edges
+            .iter()
+            .map(|(name, deps)| {
+                (
+                    name.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn outdated(names: &[&str]) -> Vec<OutdatedPackage> {
+        names
+            .iter()
+            .map(|name| OutdatedPackage {
+                name: name.to_string(),
+                installed_version: "1.0".to_string(),
+                available_version: "2.0".to_string(),
+            })
+            .collect()
+    }
+
+    fn names(steps: &[UpgradeStep]) -> Vec<&str> {
+        steps.iter().map(|s| s.package.name.as_str()).collect()
+    }
+
+    #[test]
+    fn upgrades_shared_dependencies_first() {
+        let graph = graph(&[
+            ("git", &["curl", "pcre2"]),
+            ("curl", &["openssl@3"]),
+            ("wget", &["openssl@3"]),
+            ("openssl@3", &[]),
+            ("pcre2", &[]),
+        ]);
+        let steps = order_upgrades(
+            &graph,
+            outdated(&["wget", "git", "openssl@3"]),
+            None,
+            false,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(names(&steps), vec!["openssl@3", "git", "wget"]);
+        assert!(steps.iter().all(|s| s.reason == UpgradeReason::Requested));
+    }
+
+    #[test]
+    fn named_upgrade_pulls_in_outdated_dependencies() {
+        let graph = graph(&[
+            ("git", &["curl"]),
+            ("curl", &["openssl@3"]),
+            ("wget", &["openssl@3"]),
+        ]);
+        let steps = order_upgrades(
+            &graph,
+            outdated(&["git", "openssl@3", "wget"]),
+            Some("git"),
+            false,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(names(&steps), vec!["openssl@3", "git"]);
+        assert_eq!(
+            steps[0].reason,
+            UpgradeReason::Dependency {
+                of: "git".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn greedy_adds_dependents_and_rebuilds_source_builds() {
+        let graph = graph(&[
+            ("curl", &["openssl@3"]),
+            ("wget", &["openssl@3"]),
+            ("git", &["curl"]),
+            ("jq", &[]),
+        ]);
+        let source_built = BTreeMap::from([
+            ("curl".to_string(), "8.9".to_string()),
+            ("jq".to_string(), "1.7".to_string()),
+        ]);
+        let steps = order_upgrades(
+            &graph,
+            outdated(&["openssl@3", "wget"]),
+            Some("openssl@3"),
+            true,
+            &source_built,
+        );
+
+        assert_eq!(names(&steps), vec!["openssl@3", "curl", "wget"]);
+        assert_eq!(
+            steps[1].reason,
+            UpgradeReason::Rebuild {
+                of: "openssl@3".to_string()
+            }
+        );
+        assert_eq!(steps[1].package.available_version, "8.9");
+        assert_eq!(
+            steps[2].reason,
+            UpgradeReason::Dependent {
+                of: "openssl@3".to_string()
+            }
+        );
+
+        // Not greedy: only the named package
+        let steps = order_upgrades(
+            &graph,
+            outdated(&["openssl@3", "wget"]),
+            Some("openssl@3"),
+            false,
+            &source_built,
+        );
+        assert_eq!(names(&steps), vec!["openssl@3"]);
+    }
 }
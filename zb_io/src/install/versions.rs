@@ -0,0 +1,96 @@
+//! Version discovery
+//!
+//! This module handles:
+//! - Collecting the versions of a formula zb can install or roll back to
+
+use futures::future::join_all;
+
+use zb_core::{Error, Version};
+
+use super::Installer;
+
+/// Versions of a formula available from the API and present locally
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormulaVersions {
+    /// The formula name
+    pub name: String,
+    /// Current version from the API, if the formula is known there
+    pub available: Option<String>,
+    /// Versioned formulas (`foo@x`) with their current versions
+    pub versioned_formulas: Vec<(String, Option<String>)>,
+    /// Versions with a keg in the Cellar, oldest first
+    pub installed: Vec<String>,
+    /// The active installed version
+    pub active: Option<String>,
+    /// Versions without a keg whose bottle is still in the store, oldest first
+    pub in_store: Vec<String>,
+}
+
+impl FormulaVersions {
+    /// True if neither the API nor the local install knows the formula
+    pub fn is_empty(&self) -> bool {
+        self.available.is_none() && self.installed.is_empty() && self.in_store.is_empty()
+    }
+}
+
+impl Installer {
+    /// Collect the API, Cellar and store versions of a formula
+    pub async fn formula_versions(&self, name: &str) -> Result<FormulaVersions, Error> {
+        let api_result = self.api_client.get_formula(name).await;
+
+        let mut installed: Vec<String> = self
+            .db
+            .list_versions(name)?
+            .into_iter()
+            .map(|v| v.version)
+            .collect();
+        installed.sort_by_key(|v| Version::parse(v));
+
+        let mut in_store: Vec<String> = self
+            .store
+            .formula_versions(name)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to list store entries: {e}"),
+            })?
+            .into_iter()
+            .map(|(version, _)| version)
+            .filter(|v| !installed.contains(v))
+            .collect();
+        in_store.sort_by_key(|v| Version::parse(v));
+        in_store.dedup();
+
+        let mut versions = FormulaVersions {
+            name: name.to_string(),
+            installed,
+            active: self.db.get_installed(name).map(|keg| keg.version),
+            in_store,
+            ..Default::default()
+        };
+
+        match api_result {
+            Ok(formula) => {
+                versions.available = Some(formula.effective_version());
+
+                let lookups = formula
+                    .versioned_formulas
+                    .iter()
+                    .map(|versioned| self.api_client.get_formula(versioned));
+                versions.versioned_formulas = formula
+                    .versioned_formulas
+                    .iter()
+                    .cloned()
+                    .zip(join_all(lookups).await)
+                    .map(|(versioned, result)| {
+                        (versioned, result.ok().map(|f| f.effective_version()))
+                    })
+                    .collect();
+            }
+            // Versions left on disk are still worth showing when the API
+            // no longer has the formula or is unreachable
+            Err(e) if versions.is_empty() => return Err(e),
+            Err(_) => {}
+        }
+
+        Ok(versions)
+    }
+}
@@ -18,6 +18,8 @@
 //! - [`ApiClient`] - Homebrew API access with caching
 //! - [`Database`] - Local SQLite state storage for installed packages
 //! - [`Store`] - Content-addressable blob store for package data
+//! - [`BlobCache::fetch`] / [`Store::ingest_reader`] - Verified downloads and
+//!   store ingestion for external `zb-*` commands
 //! - [`Downloader`] / [`ParallelDownloader`] - HTTP download handling
 //! - [`Linker`] - Symlink management for installed formulas
 //! - [`Cellar`] - Package materialization from the store
@@ -25,46 +27,95 @@
 //! - [`TapManager`] - Third-party tap repository management
 //! - [`Builder`] - Source compilation support
 //! - [`traits`] - Trait abstractions for mockable I/O operations
+//! - [`http_fixtures`] - HTTP record/replay for hermetic tests
 
 pub mod api;
+pub mod attestation;
 pub mod blob;
 pub mod build;
 pub mod bundle;
+pub mod bundle_lock;
 pub mod cache;
+pub mod config;
+pub mod connectivity;
+pub mod credentials;
 pub mod db;
+pub mod dedup;
+pub mod delta;
 pub mod download;
 pub mod extract;
+pub mod fd_limit;
+pub mod fs_limits;
+pub mod http_fixtures;
+pub mod index;
 pub mod install;
 pub mod link;
+pub mod lock;
+pub mod manifest;
 pub mod materialize;
+pub mod mirror;
 #[cfg(target_os = "linux")]
 pub mod patchelf;
+pub mod pipe;
+pub mod plan_file;
 pub mod progress;
+pub mod ratelimit;
+pub mod receipt;
+pub mod schedule;
 pub mod search;
 pub mod services;
+pub mod shellenv;
 pub mod store;
 pub mod tap;
+pub mod tempdir;
+pub mod timing;
 pub mod traits;
 
 #[cfg(any(test, feature = "test-utils"))]
 pub mod test_utils;
 
 pub use api::{ApiClient, FormulaInfo};
+pub use attestation::{Attestation, AttestationStatus, AttestationVerifier};
 pub use blob::BlobCache;
 pub use build::{BuildEnvironment, BuildResult, BuildSystem, Builder, detect_build_system};
-pub use bundle::{BrewfileEntry, BundleCheckResult, BundleInstallResult};
+pub use bundle::{BrewfileEntry, BundleCheckResult, BundleEnv, BundleInstallResult};
+pub use bundle_lock::BundleLock;
 pub use cache::ApiCache;
-pub use db::{Database, InstalledKeg, InstalledTap};
+pub use config::{ConfigAction, ConfigChange, ConfigDiff, ConfigFile};
+pub use connectivity::{Connectivity, Timing};
+pub use credentials::{Credential, Credentials, Keychain};
+pub use db::{
+    BenchmarkRecord, Database, InstallStats, InstalledKeg, InstalledTap, InventorySort, KegVersion,
+    LinkStatus, MonthlyActivity, PourEvent, PourEventKind, PourStats, Provenance,
+};
 pub use download::{DownloadProgressCallback, DownloadRequest, Downloader, ParallelDownloader};
-pub use extract::extract_tarball;
+pub use extract::{ArchiveFormat, ExtractOptions, extract_archive, extract_tarball};
+pub use http_fixtures::{HttpFixtures, HttpMode, RecordingHttpClient, ReplayHttpClient};
+pub use index::{FormulaIndex, IndexProvider};
 pub use install::{
-    CleanupResult, DepsTree, DoctorCheck, DoctorResult, DoctorStatus, Installer, LinkResult,
-    SourceBuildResult, UpgradeResult,
+    AdoptResult, AvailableBottle, BottleAvailability, CellarDrift, CleanupResult,
+    CommandResolution, ConcurrencyLimits, DedupeStats, DepAnnotation, DepsGraph, DepsTree,
+    DoctorCheck, DoctorResult, DoctorStatus, DownloadEstimate, DriftedKeg, FormulaChange,
+    FormulaVersions, Installer, InventoryEntry, KegVerification, LinkResult, MirrorResult,
+    OutdatedGroup, PackageEstimate, PlannedLinkConflict, PostInstallResult, RollbackResult,
+    RollbackSource, ShadowedCommand, SourceBuildResult, UnpackResult, UntrackedAction,
+    UpdateSummary, UpgradePlan, UpgradeReason, UpgradeResult, UpgradeStep, WhyResult,
 };
-pub use link::Linker;
+pub use link::{DisplacedFile, Linker};
+pub use manifest::{Manifest, ManifestEntry, ManifestMismatch};
 pub use materialize::Cellar;
+pub use plan_file::PlanFile;
 pub use progress::{InstallProgress, ProgressCallback};
-pub use services::{ServiceConfig, ServiceInfo, ServiceManager, ServiceStatus};
+pub use ratelimit::RateLimits;
+pub use receipt::{BuiltFrom, InstallReceipt};
+pub use schedule::{UpgradeWindow, UpgradeWindowStatus};
+pub use services::{
+    ForeignService, Launchd, RestartHistory, ServiceBackend, ServiceConfig, ServiceExit,
+    ServiceInfo, ServiceManager, ServiceSettings, ServiceSpec, ServiceStatus, Systemd,
+    detect_backend,
+};
+pub use shellenv::{EnvDirCheck, EnvDirState, check_shellenv};
 pub use store::Store;
-pub use tap::{TapFormula, TapInfo, TapManager};
+pub use tap::{TapFormula, TapInfo, TapManager, TapRefresh};
+pub use timing::{InstallTimings, PackageTiming, Phase, Span};
 pub use traits::{FileSystem, HttpClient, ReqwestHttpClient, StdFileSystem};
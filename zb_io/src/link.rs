@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
@@ -23,9 +25,202 @@ fn resolve_symlink_target(link_path: &Path, target: &Path) -> Option<PathBuf> {
     }
 }
 
+/// Move `link_path` into `backup_dir`, keeping its file name
+fn back_up(link_path: &Path, backup_dir: &Path) -> Result<DisplacedFile, Error> {
+    let file_name = link_path
+        .file_name()
+        .ok_or_else(|| Error::StoreCorruption {
+            message: format!("invalid link path: {}", link_path.display()),
+        })?;
+
+    fs::create_dir_all(backup_dir).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create backup directory: {e}"),
+    })?;
+
+    let mut backup_path = backup_dir.join(file_name);
+    let mut n = 1;
+    while backup_path.symlink_metadata().is_ok() {
+        backup_path = backup_dir.join(format!("{}.{n}", file_name.to_string_lossy()));
+        n += 1;
+    }
+
+    move_path(link_path, &backup_path)?;
+    Ok(DisplacedFile {
+        link_path: link_path.to_path_buf(),
+        backup_path,
+    })
+}
+
+/// Rename a file, symlink, or directory, copying regular files and symlinks
+/// when the destination is on another filesystem.
+fn move_path(from: &Path, to: &Path) -> Result<(), Error> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    let copied = match fs::read_link(from) {
+        #[cfg(unix)]
+        Ok(target) => std::os::unix::fs::symlink(target, to),
+        #[cfg(not(unix))]
+        Ok(_) => Err(io::Error::other("symlinks not supported on this platform")),
+        Err(_) => fs::copy(from, to).map(|_| ()),
+    };
+
+    copied
+        .and_then(|()| fs::remove_file(from))
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to move {} to {}: {e}", from.display(), to.display()),
+        })
+}
+
+/// Point `link_path` at `target` by creating the symlink beside it and
+/// renaming it into place, so the path never goes missing.
+fn swap_symlink(target: &Path, link_path: &Path) -> Result<(), Error> {
+    let file_name = link_path
+        .file_name()
+        .ok_or_else(|| Error::StoreCorruption {
+            message: format!("invalid link path: {}", link_path.display()),
+        })?;
+    let staged = link_path.with_file_name(format!(".{}.zb-swap", file_name.to_string_lossy()));
+    let _ = fs::remove_file(&staged);
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, &staged).map_err(|e| Error::StoreCorruption {
+        message: format!("failed to create symlink: {e}"),
+    })?;
+
+    #[cfg(not(unix))]
+    return Err(Error::StoreCorruption {
+        message: "symlinks not supported on this platform".to_string(),
+    });
+
+    fs::rename(&staged, link_path).map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        Error::StoreCorruption {
+            message: format!("failed to replace {}: {e}", link_path.display()),
+        }
+    })
+}
+
+/// Directories, relative to a keg and to the prefix, that hold shell
+/// completion files. Their contents are linked file by file so several kegs
+/// can share them.
+pub const COMPLETION_DIRS: &[&str] = &[
+    "etc/bash_completion.d",
+    "share/bash-completion/completions",
+    "share/zsh/site-functions",
+    "share/fish/vendor_completions.d",
+];
+
+/// First line after the shebang of a wrapper script, followed by the path
+/// of the executable it runs
+const WRAPPER_MARKER: &str = "# zerobrew wrapper for ";
+
+/// Where `link_path` leads: a symlink's resolved target, or the executable a
+/// zerobrew wrapper script runs
+fn bin_link_target(link_path: &Path) -> Option<PathBuf> {
+    if let Ok(target) = fs::read_link(link_path) {
+        return resolve_symlink_target(link_path, &target).or(Some(target));
+    }
+    wrapper_target(link_path)
+}
+
+/// The executable a zerobrew wrapper script at `path` runs
+fn wrapper_target(path: &Path) -> Option<PathBuf> {
+    use std::io::{BufRead, BufReader};
+
+    let file = fs::File::open(path).ok()?;
+    let mut lines = BufReader::new(file).lines();
+    let shebang = lines.next()?.ok()?;
+    if !shebang.starts_with("#!") {
+        return None;
+    }
+    let marker = lines.next()?.ok()?;
+    marker.strip_prefix(WRAPPER_MARKER).map(PathBuf::from)
+}
+
+/// Whether `key` can be exported from a wrapper script
+pub fn is_env_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quote `value` for a double-quoted shell string, leaving `$VAR`
+/// references to expand when the wrapper runs
+fn shell_double_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Script that exports `env` and then runs `target` with its arguments.
+///
+/// `{prefix}` and `{opt}` in values are replaced by the prefix and the
+/// formula's `opt/` directory.
+pub fn render_wrapper(
+    target: &Path,
+    env: &BTreeMap<String, String>,
+    prefix: &Path,
+    opt: &Path,
+) -> String {
+    let mut script = format!("#!/bin/sh\n{WRAPPER_MARKER}{}\n", target.display());
+    for (key, value) in env {
+        let value = value
+            .replace("{prefix}", &prefix.to_string_lossy())
+            .replace("{opt}", &opt.to_string_lossy());
+        script.push_str(&format!("export {key}={}\n", shell_double_quote(&value)));
+    }
+    script.push_str(&format!(
+        "exec {} \"$@\"\n",
+        shell_double_quote(&target.to_string_lossy())
+    ));
+    script
+}
+
+/// Write a wrapper script to `link_path`, replacing whatever is there in a
+/// single rename
+fn write_wrapper(script: &str, link_path: &Path) -> Result<(), Error> {
+    let file_name = link_path
+        .file_name()
+        .ok_or_else(|| Error::StoreCorruption {
+            message: format!("invalid link path: {}", link_path.display()),
+        })?;
+    let staged = link_path.with_file_name(format!(".{}.zb-swap", file_name.to_string_lossy()));
+    let _ = fs::remove_file(&staged);
+
+    let written = fs::write(&staged, script).and_then(|()| {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+        }
+        fs::rename(&staged, link_path)
+    });
+    written.map_err(|e| {
+        let _ = fs::remove_file(&staged);
+        Error::StoreCorruption {
+            message: format!("failed to write wrapper {}: {e}", link_path.display()),
+        }
+    })
+}
+
 pub struct Linker {
+    prefix: PathBuf,
     bin_dir: PathBuf,
     opt_dir: PathBuf,
+    /// Environment exported by wrapper scripts, by formula. Executables of
+    /// formulas listed here are linked as wrappers instead of symlinks.
+    wrapper_env: HashMap<String, BTreeMap<String, String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -34,29 +229,112 @@ pub struct LinkedFile {
     pub target_path: PathBuf,
 }
 
+/// A file that was moved aside so a keg could be linked over it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplacedFile {
+    pub link_path: PathBuf,
+    pub backup_path: PathBuf,
+}
+
 impl Linker {
     pub fn new(prefix: &Path) -> io::Result<Self> {
         let bin_dir = prefix.join("bin");
         let opt_dir = prefix.join("opt");
         fs::create_dir_all(&bin_dir)?;
         fs::create_dir_all(&opt_dir)?;
-        Ok(Self { bin_dir, opt_dir })
+        Ok(Self {
+            prefix: prefix.to_path_buf(),
+            bin_dir,
+            opt_dir,
+            wrapper_env: HashMap::new(),
+        })
+    }
+
+    /// Link `formula`'s executables as wrapper scripts exporting `env`, or
+    /// as plain symlinks again when `env` is empty. Applies to links made
+    /// from now on.
+    pub fn set_wrapper_env(&mut self, formula: &str, env: BTreeMap<String, String>) {
+        if env.is_empty() {
+            self.wrapper_env.remove(formula);
+        } else {
+            self.wrapper_env.insert(formula.to_string(), env);
+        }
+    }
+
+    /// Point `link_path` at the executable `target_path` of `keg_path`,
+    /// through a wrapper if the formula has an environment configured
+    fn place_bin(
+        &self,
+        keg_path: &Path,
+        target_path: &Path,
+        link_path: &Path,
+    ) -> Result<(), Error> {
+        let name = keg_path.parent().and_then(|p| p.file_name());
+        match name.and_then(|name| self.wrapper_env.get(&*name.to_string_lossy())) {
+            Some(env) => {
+                let opt = self.opt_dir.join(name.unwrap_or_default());
+                write_wrapper(
+                    &render_wrapper(target_path, env, &self.prefix, &opt),
+                    link_path,
+                )
+            }
+            None => swap_symlink(target_path, link_path),
+        }
+    }
+
+    /// Whether `link_path` already has the form `place_bin` would give it
+    fn is_placed(&self, keg_path: &Path, link_path: &Path) -> bool {
+        let wrapped = keg_path
+            .parent()
+            .and_then(|p| p.file_name())
+            .is_some_and(|name| self.wrapper_env.contains_key(&*name.to_string_lossy()));
+        // Wrappers are always rewritten, in case the environment changed
+        !wrapped && fs::read_link(link_path).is_ok()
     }
 
     /// Link all executables from a keg's bin directory and create opt symlink.
     /// Returns the list of created links.
     /// Errors on conflict (existing file/link that doesn't point to our keg).
     pub fn link_keg(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
+        self.link_keg_impl(keg_path, None).map(|(linked, _)| linked)
+    }
+
+    /// Link a keg, moving conflicting files and other kegs' symlinks into `backup_dir`
+    /// instead of failing. Returns the created links and the displaced files.
+    pub fn link_keg_overwrite(
+        &self,
+        keg_path: &Path,
+        backup_dir: &Path,
+    ) -> Result<(Vec<LinkedFile>, Vec<DisplacedFile>), Error> {
+        self.link_keg_impl(keg_path, Some(backup_dir))
+    }
+
+    fn link_keg_impl(
+        &self,
+        keg_path: &Path,
+        backup_dir: Option<&Path>,
+    ) -> Result<(Vec<LinkedFile>, Vec<DisplacedFile>), Error> {
         // Create opt symlink: /opt/homebrew/opt/<name> -> /opt/homebrew/Cellar/<name>/<version>
         self.link_opt(keg_path)?;
 
+        let (mut linked, displaced) = self.link_bins(keg_path, backup_dir)?;
+        linked.extend(self.link_completions(keg_path)?);
+        Ok((linked, displaced))
+    }
+
+    fn link_bins(
+        &self,
+        keg_path: &Path,
+        backup_dir: Option<&Path>,
+    ) -> Result<(Vec<LinkedFile>, Vec<DisplacedFile>), Error> {
         let keg_bin = keg_path.join("bin");
 
         if !keg_bin.exists() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), Vec::new()));
         }
 
         let mut linked = Vec::new();
+        let mut displaced = Vec::new();
 
         for entry in fs::read_dir(&keg_bin).map_err(|e| Error::StoreCorruption {
             message: format!("failed to read keg bin directory: {e}"),
@@ -72,17 +350,16 @@ impl Linker {
             // Check for conflicts
             if link_path.exists() || link_path.symlink_metadata().is_ok() {
                 // Check if it's our own link (compare canonical paths to handle relative symlinks)
-                if let Ok(existing_target) = fs::read_link(&link_path) {
-                    // Resolve relative symlinks
-                    let resolved_existing = resolve_symlink_target(&link_path, &existing_target)
-                        .unwrap_or_else(|| existing_target.clone());
-
+                if let Some(resolved_existing) = bin_link_target(&link_path) {
                     // Canonicalize both to compare actual filesystem locations
                     let existing_canonical = fs::canonicalize(&resolved_existing).ok();
                     let target_canonical = fs::canonicalize(&target_path).ok();
 
                     if existing_canonical.is_some() && existing_canonical == target_canonical {
-                        // Already linked to us, skip
+                        // Already linked to us; only the symlink/wrapper form may change
+                        if !self.is_placed(keg_path, &link_path) {
+                            self.place_bin(keg_path, &target_path, &link_path)?;
+                        }
                         linked.push(LinkedFile {
                             link_path,
                             target_path,
@@ -96,6 +373,8 @@ impl Linker {
                             message: format!("failed to remove broken symlink: {e}"),
                         })?;
                         // Fall through to create new symlink below
+                    } else if let Some(backup_dir) = backup_dir {
+                        displaced.push(back_up(&link_path, backup_dir)?);
                     } else {
                         return Err(Error::LinkConflict {
                             path: link_path,
@@ -104,6 +383,8 @@ impl Linker {
                             },
                         });
                     }
+                } else if let Some(backup_dir) = backup_dir {
+                    displaced.push(back_up(&link_path, backup_dir)?);
                 } else {
                     // Not a symlink - check if it's a file or directory
                     let existing_type = if link_path.is_dir() {
@@ -120,18 +401,7 @@ impl Linker {
                 }
             }
 
-            // Create symlink
-            #[cfg(unix)]
-            std::os::unix::fs::symlink(&target_path, &link_path).map_err(|e| {
-                Error::StoreCorruption {
-                    message: format!("failed to create symlink: {e}"),
-                }
-            })?;
-
-            #[cfg(not(unix))]
-            return Err(Error::StoreCorruption {
-                message: "symlinks not supported on this platform".to_string(),
-            });
+            self.place_bin(keg_path, &target_path, &link_path)?;
 
             linked.push(LinkedFile {
                 link_path,
@@ -139,14 +409,129 @@ impl Linker {
             });
         }
 
-        Ok(linked)
+        Ok((linked, displaced))
     }
 
-    /// Unlink all executables that point to the given keg and remove opt symlink.
+    /// Unlink all executables and completions that point to the given keg and remove opt symlink.
     pub fn unlink_keg(&self, keg_path: &Path) -> Result<Vec<PathBuf>, Error> {
         // Remove opt symlink
         self.unlink_opt(keg_path)?;
+        self.unlink_prefix(keg_path)
+    }
+
+    /// Unlink all executables and completions that point to the given keg,
+    /// leaving its opt symlink in place.
+    pub fn unlink_prefix(&self, keg_path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut unlinked = self.unlink_bins(keg_path)?;
+        unlinked.extend(self.unlink_completions(keg_path)?);
+        Ok(unlinked)
+    }
+
+    /// Link the keg's shell completion files into the prefix's completion
+    /// directories. A completion another keg already provides is left alone
+    /// rather than failing the link.
+    fn link_completions(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
+        let mut linked = Vec::new();
+
+        for dir in COMPLETION_DIRS {
+            let Ok(entries) = fs::read_dir(keg_path.join(dir)) else {
+                continue;
+            };
+            let prefix_dir = self.prefix.join(dir);
+
+            for entry in entries.flatten() {
+                let target_path = entry.path();
+                let link_path = prefix_dir.join(entry.file_name());
+
+                if link_path.symlink_metadata().is_ok() {
+                    let existing = fs::read_link(&link_path).ok().map(|target| {
+                        let resolved =
+                            resolve_symlink_target(&link_path, &target).unwrap_or(target);
+                        fs::canonicalize(resolved).ok()
+                    });
+                    match existing {
+                        // Ours already
+                        Some(Some(existing))
+                            if Some(&existing) == fs::canonicalize(&target_path).ok().as_ref() =>
+                        {
+                            linked.push(LinkedFile {
+                                link_path,
+                                target_path,
+                            });
+                            continue;
+                        }
+                        // Broken symlink
+                        Some(None) => {
+                            fs::remove_file(&link_path).map_err(|e| Error::StoreCorruption {
+                                message: format!("failed to remove broken symlink: {e}"),
+                            })?;
+                        }
+                        _ => continue,
+                    }
+                }
+
+                fs::create_dir_all(&prefix_dir).map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to create {}: {e}", prefix_dir.display()),
+                })?;
+
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target_path, &link_path).map_err(|e| {
+                    Error::StoreCorruption {
+                        message: format!("failed to create symlink: {e}"),
+                    }
+                })?;
+
+                #[cfg(not(unix))]
+                return Err(Error::StoreCorruption {
+                    message: "symlinks not supported on this platform".to_string(),
+                });
+
+                linked.push(LinkedFile {
+                    link_path,
+                    target_path,
+                });
+            }
+        }
+
+        Ok(linked)
+    }
 
+    /// Remove the completion symlinks that point into the given keg
+    fn unlink_completions(&self, keg_path: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut unlinked = Vec::new();
+        let keg_canonical = fs::canonicalize(keg_path).ok();
+
+        for dir in COMPLETION_DIRS {
+            let Ok(entries) = fs::read_dir(self.prefix.join(dir)) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let link_path = entry.path();
+                let Ok(target) = fs::read_link(&link_path) else {
+                    continue;
+                };
+                let resolved = resolve_symlink_target(&link_path, &target).unwrap_or(target);
+                let points_into_keg = match (&keg_canonical, fs::canonicalize(&resolved)) {
+                    (Some(keg), Ok(existing)) => existing.starts_with(keg),
+                    // The keg may already be gone; compare the paths as written
+                    _ => resolved.starts_with(keg_path),
+                };
+
+                if points_into_keg {
+                    fs::remove_file(&link_path).map_err(|e| Error::StoreCorruption {
+                        message: format!("failed to remove symlink: {e}"),
+                    })?;
+                    unlinked.push(link_path);
+                }
+            }
+        }
+
+        Ok(unlinked)
+    }
+
+    /// Remove the `bin/` symlinks that point into the given keg
+    fn unlink_bins(&self, keg_path: &Path) -> Result<Vec<PathBuf>, Error> {
         let keg_bin = keg_path.join("bin");
 
         if !keg_bin.exists() {
@@ -166,12 +551,8 @@ impl Linker {
             let target_path = entry.path();
             let link_path = self.bin_dir.join(&file_name);
 
-            // Only remove if it's a symlink pointing to our keg
-            if let Ok(existing_target) = fs::read_link(&link_path) {
-                // Resolve relative symlinks
-                let resolved_existing = resolve_symlink_target(&link_path, &existing_target)
-                    .unwrap_or_else(|| existing_target.clone());
-
+            // Only remove if it's a symlink or wrapper pointing to our keg
+            if let Some(resolved_existing) = bin_link_target(&link_path) {
                 // Canonicalize both to compare actual filesystem locations
                 let existing_canonical = fs::canonicalize(&resolved_existing).ok();
                 let target_canonical = fs::canonicalize(&target_path).ok();
@@ -188,8 +569,101 @@ impl Linker {
         Ok(unlinked)
     }
 
+    /// Link `new_keg` in place of `old_keg`, another version of the same formula.
+    ///
+    /// Every conflict is checked before anything changes. Links are then
+    /// renamed over the old ones, so `opt/` and `bin/` go straight from the
+    /// old version to the new one and running shells never see a missing
+    /// command. Links only the old version provided are removed last.
+    pub fn replace_keg(&self, old_keg: &Path, new_keg: &Path) -> Result<Vec<LinkedFile>, Error> {
+        let name = new_keg
+            .parent()
+            .and_then(|p| p.file_name())
+            .ok_or_else(|| Error::StoreCorruption {
+                message: "could not determine formula name from keg path".to_string(),
+            })?;
+        let old_canonical = fs::canonicalize(old_keg).ok();
+
+        // Plan every link first so a conflict leaves the old version linked
+        let mut planned = Vec::new();
+        let keg_bin = new_keg.join("bin");
+        if keg_bin.exists() {
+            for entry in fs::read_dir(&keg_bin).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read keg bin directory: {e}"),
+            })? {
+                let entry = entry.map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to read directory entry: {e}"),
+                })?;
+                let target_path = entry.path();
+                let link_path = self.bin_dir.join(entry.file_name());
+
+                let needs_link = if link_path.symlink_metadata().is_err() {
+                    true
+                } else if let Some(resolved_existing) = bin_link_target(&link_path) {
+                    match fs::canonicalize(&resolved_existing).ok() {
+                        // Broken links are replaced, as link_keg does
+                        None => true,
+                        Some(existing)
+                            if Some(&existing) == fs::canonicalize(&target_path).ok().as_ref() =>
+                        {
+                            !self.is_placed(new_keg, &link_path)
+                        }
+                        Some(existing)
+                            if old_canonical
+                                .as_ref()
+                                .is_some_and(|old| existing.starts_with(old)) =>
+                        {
+                            true
+                        }
+                        Some(_) => {
+                            return Err(Error::LinkConflict {
+                                path: link_path,
+                                existing_type: LinkConflictType::SymlinkToOther {
+                                    target: resolved_existing,
+                                },
+                            });
+                        }
+                    }
+                } else {
+                    let existing_type = if link_path.is_dir() {
+                        LinkConflictType::Directory
+                    } else if link_path.is_file() {
+                        LinkConflictType::RegularFile
+                    } else {
+                        LinkConflictType::Unknown
+                    };
+                    return Err(Error::LinkConflict {
+                        path: link_path,
+                        existing_type,
+                    });
+                };
+                planned.push((needs_link, link_path, target_path));
+            }
+        }
+
+        swap_symlink(new_keg, &self.opt_dir.join(name))?;
+
+        let mut linked = Vec::new();
+        for (needs_link, link_path, target_path) in planned {
+            if needs_link {
+                self.place_bin(new_keg, &target_path, &link_path)?;
+            }
+            linked.push(LinkedFile {
+                link_path,
+                target_path,
+            });
+        }
+
+        // Whatever still points into the old keg isn't provided any more
+        self.unlink_bins(old_keg)?;
+        self.unlink_completions(old_keg)?;
+        linked.extend(self.link_completions(new_keg)?);
+
+        Ok(linked)
+    }
+
     /// Remove opt symlink if it points to the given keg
-    fn unlink_opt(&self, keg_path: &Path) -> Result<(), Error> {
+    pub fn unlink_opt(&self, keg_path: &Path) -> Result<(), Error> {
         let name = keg_path
             .parent()
             .and_then(|p| p.file_name())
@@ -214,7 +688,11 @@ impl Linker {
     }
 
     /// Create opt symlink: /opt/homebrew/opt/<name> -> keg_path
-    fn link_opt(&self, keg_path: &Path) -> Result<(), Error> {
+    ///
+    /// Every installed formula has one pointing at its active keg, linked or
+    /// not, so keg-only formulas can be found at a path that doesn't change
+    /// between versions.
+    pub fn link_opt(&self, keg_path: &Path) -> Result<(), Error> {
         // Extract formula name from keg_path (e.g., /opt/homebrew/Cellar/libtool/2.5.4 -> libtool)
         let name = keg_path
             .parent() // Cellar/<name>
@@ -254,6 +732,39 @@ impl Linker {
         Ok(())
     }
 
+    /// Move a displaced file back to where it was.
+    /// Returns false if something else now occupies that path.
+    pub fn restore(&self, displaced: &DisplacedFile) -> Result<bool, Error> {
+        if displaced.link_path.symlink_metadata().is_ok() {
+            return Ok(false);
+        }
+        move_path(&displaced.backup_path, &displaced.link_path)?;
+        Ok(true)
+    }
+
+    /// What linking an executable named `file_name` would run into, with
+    /// the path it would be linked at. Symlinks and wrappers report the
+    /// executable they run; broken ones don't count, as linking replaces
+    /// them.
+    pub fn bin_conflict(&self, file_name: &OsStr) -> Option<(PathBuf, LinkConflictType)> {
+        let link_path = self.bin_dir.join(file_name);
+        link_path.symlink_metadata().ok()?;
+
+        let existing_type = if let Some(target) = bin_link_target(&link_path) {
+            if !target.exists() {
+                return None;
+            }
+            LinkConflictType::SymlinkToOther { target }
+        } else if link_path.is_dir() {
+            LinkConflictType::Directory
+        } else if link_path.is_file() {
+            LinkConflictType::RegularFile
+        } else {
+            LinkConflictType::Unknown
+        };
+        Some((link_path, existing_type))
+    }
+
     /// Check if a keg is currently linked.
     pub fn is_linked(&self, keg_path: &Path) -> bool {
         let keg_bin = keg_path.join("bin");
@@ -267,11 +778,7 @@ impl Linker {
                 let target_path = entry.path();
                 let link_path = self.bin_dir.join(entry.file_name());
 
-                if let Ok(existing_target) = fs::read_link(&link_path) {
-                    // Resolve relative symlinks
-                    let resolved_existing = resolve_symlink_target(&link_path, &existing_target)
-                        .unwrap_or_else(|| existing_target.clone());
-
+                if let Some(resolved_existing) = bin_link_target(&link_path) {
                     // Canonicalize both to compare actual filesystem locations
                     let existing_canonical = fs::canonicalize(&resolved_existing).ok();
                     let target_canonical = fs::canonicalize(&target_path).ok();
@@ -350,6 +857,64 @@ mod tests {
         assert!(matches!(err, Error::LinkConflict { .. }));
     }
 
+    #[test]
+    fn overwrite_backs_up_conflicts() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        fs::write(prefix.join("bin/foo"), b"user file").unwrap();
+
+        let backup_dir = tmp.path().join("backups/1");
+        let (linked, displaced) = linker.link_keg_overwrite(&keg, &backup_dir).unwrap();
+
+        assert_eq!(linked.len(), 1);
+        assert_eq!(
+            displaced,
+            vec![DisplacedFile {
+                link_path: prefix.join("bin/foo"),
+                backup_path: backup_dir.join("foo"),
+            }]
+        );
+        assert_eq!(fs::read(backup_dir.join("foo")).unwrap(), b"user file");
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo")).unwrap(),
+            keg.join("bin/foo")
+        );
+    }
+
+    #[test]
+    fn overwrite_backs_up_other_keg_symlink() {
+        let tmp = TempDir::new().unwrap();
+        let keg1 = setup_keg(&tmp, "foo");
+        let keg2 = tmp.path().join("cellar/bar/1.0.0");
+        fs::create_dir_all(keg2.join("bin")).unwrap();
+        fs::write(keg2.join("bin/foo"), b"#!/bin/sh\necho bar").unwrap();
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&keg1).unwrap();
+
+        let backup_dir = tmp.path().join("backups/1");
+        let (_, displaced) = linker.link_keg_overwrite(&keg2, &backup_dir).unwrap();
+
+        assert_eq!(displaced.len(), 1);
+        assert_eq!(
+            fs::read_link(&displaced[0].backup_path).unwrap(),
+            keg1.join("bin/foo")
+        );
+
+        // Restoring puts the original symlink back once the path is free
+        assert!(!linker.restore(&displaced[0]).unwrap());
+        linker.unlink_keg(&keg2).unwrap();
+        assert!(linker.restore(&displaced[0]).unwrap());
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo")).unwrap(),
+            keg1.join("bin/foo")
+        );
+    }
+
     #[test]
     fn unlink_removes_symlinks() {
         let tmp = TempDir::new().unwrap();
@@ -701,4 +1266,254 @@ mod tests {
         let link_target = fs::read_link(prefix.join("opt/jq")).unwrap();
         assert_eq!(link_target, homebrew_keg);
     }
+
+    fn setup_version(tmp: &TempDir, name: &str, version: &str, bins: &[&str]) -> PathBuf {
+        let keg_path = tmp.path().join("cellar").join(name).join(version);
+        fs::create_dir_all(keg_path.join("bin")).unwrap();
+        for bin in bins {
+            fs::write(keg_path.join("bin").join(bin), b"#!/bin/sh\n").unwrap();
+        }
+        keg_path
+    }
+
+    #[test]
+    fn replace_keg_retargets_links_and_drops_stale_ones() {
+        let tmp = TempDir::new().unwrap();
+        let old_keg = setup_version(&tmp, "foo", "1.0.0", &["foo", "foo-legacy"]);
+        let new_keg = setup_version(&tmp, "foo", "2.0.0", &["foo", "foo-new"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&old_keg).unwrap();
+
+        let linked = linker.replace_keg(&old_keg, &new_keg).unwrap();
+
+        assert_eq!(linked.len(), 2);
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo")).unwrap(),
+            new_keg.join("bin/foo")
+        );
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo-new")).unwrap(),
+            new_keg.join("bin/foo-new")
+        );
+        assert!(prefix.join("bin/foo-legacy").symlink_metadata().is_err());
+        assert_eq!(fs::read_link(prefix.join("opt/foo")).unwrap(), new_keg);
+
+        // No staged links are left behind
+        let leftovers: Vec<_> = fs::read_dir(prefix.join("bin"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".zb-swap"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn replace_keg_conflict_keeps_old_version_linked() {
+        let tmp = TempDir::new().unwrap();
+        let old_keg = setup_version(&tmp, "foo", "1.0.0", &["foo"]);
+        let new_keg = setup_version(&tmp, "foo", "2.0.0", &["foo", "bar"]);
+        let other_keg = setup_version(&tmp, "bar", "1.0.0", &["bar"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&old_keg).unwrap();
+        linker.link_keg(&other_keg).unwrap();
+
+        let err = linker.replace_keg(&old_keg, &new_keg).unwrap_err();
+
+        assert!(matches!(err, Error::LinkConflict { .. }));
+        assert_eq!(
+            fs::read_link(prefix.join("bin/foo")).unwrap(),
+            old_keg.join("bin/foo")
+        );
+        assert_eq!(fs::read_link(prefix.join("opt/foo")).unwrap(), old_keg);
+    }
+
+    #[test]
+    fn unlink_prefix_keeps_opt_link() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_version(&tmp, "foo", "1.0.0", &["foo"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        let unlinked = linker.unlink_prefix(&keg).unwrap();
+
+        assert_eq!(unlinked, [prefix.join("bin/foo")]);
+        assert!(prefix.join("bin/foo").symlink_metadata().is_err());
+        assert_eq!(fs::read_link(prefix.join("opt/foo")).unwrap(), keg);
+
+        linker.unlink_keg(&keg).unwrap();
+        assert!(prefix.join("opt/foo").symlink_metadata().is_err());
+    }
+
+    fn add_completions(keg: &Path, files: &[&str]) {
+        for file in files {
+            let path = keg.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"# completion\n").unwrap();
+        }
+    }
+
+    #[test]
+    fn links_and_unlinks_shell_completions() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_version(&tmp, "gh", "2.0.0", &["gh"]);
+        add_completions(
+            &keg,
+            &[
+                "etc/bash_completion.d/gh",
+                "share/zsh/site-functions/_gh",
+                "share/fish/vendor_completions.d/gh.fish",
+            ],
+        );
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        let linked = linker.link_keg(&keg).unwrap();
+
+        assert_eq!(linked.len(), 4);
+        assert_eq!(
+            fs::read_link(prefix.join("share/zsh/site-functions/_gh")).unwrap(),
+            keg.join("share/zsh/site-functions/_gh")
+        );
+        assert!(prefix.join("etc/bash_completion.d/gh").exists());
+        assert!(
+            prefix
+                .join("share/fish/vendor_completions.d/gh.fish")
+                .exists()
+        );
+
+        let unlinked = linker.unlink_keg(&keg).unwrap();
+        assert_eq!(unlinked.len(), 4);
+        assert!(
+            prefix
+                .join("share/zsh/site-functions/_gh")
+                .symlink_metadata()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn completion_owned_by_another_keg_is_left_alone() {
+        let tmp = TempDir::new().unwrap();
+        let git = setup_version(&tmp, "git", "2.46.0", &["git"]);
+        let other = setup_version(&tmp, "git-extras", "7.0.0", &["git-extras"]);
+        add_completions(&git, &["share/zsh/site-functions/_git"]);
+        add_completions(&other, &["share/zsh/site-functions/_git"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&git).unwrap();
+
+        let linked = linker.link_keg(&other).unwrap();
+        assert_eq!(linked.len(), 1);
+        assert_eq!(
+            fs::read_link(prefix.join("share/zsh/site-functions/_git")).unwrap(),
+            git.join("share/zsh/site-functions/_git")
+        );
+    }
+
+    #[test]
+    fn replace_keg_moves_completions_to_new_version() {
+        let tmp = TempDir::new().unwrap();
+        let old_keg = setup_version(&tmp, "foo", "1.0.0", &["foo"]);
+        let new_keg = setup_version(&tmp, "foo", "2.0.0", &["foo"]);
+        add_completions(
+            &old_keg,
+            &["share/zsh/site-functions/_foo", "etc/bash_completion.d/foo"],
+        );
+        add_completions(&new_keg, &["share/zsh/site-functions/_foo"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&old_keg).unwrap();
+        linker.replace_keg(&old_keg, &new_keg).unwrap();
+
+        assert_eq!(
+            fs::read_link(prefix.join("share/zsh/site-functions/_foo")).unwrap(),
+            new_keg.join("share/zsh/site-functions/_foo")
+        );
+        assert!(
+            prefix
+                .join("etc/bash_completion.d/foo")
+                .symlink_metadata()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn wrapper_exports_env_before_running_binary() {
+        let tmp = TempDir::new().unwrap();
+        let keg_path = setup_keg(&tmp, "git");
+        let binary = keg_path.join("bin/git");
+        fs::write(&binary, "#!/bin/sh\necho \"$GIT_EXEC_PATH|$GREETING|$1\"\n").unwrap();
+
+        let prefix = tmp.path().join("homebrew");
+        let mut linker = Linker::new(&prefix).unwrap();
+        linker.set_wrapper_env(
+            "git",
+            BTreeMap::from([
+                (
+                    "GIT_EXEC_PATH".to_string(),
+                    "{opt}/libexec/git-core".to_string(),
+                ),
+                ("GREETING".to_string(), "say \"hi\" to $MARK".to_string()),
+            ]),
+        );
+
+        let linked = linker.link_keg(&keg_path).unwrap();
+        assert_eq!(linked.len(), 1);
+        let wrapper = prefix.join("bin/git");
+        assert!(fs::read_link(&wrapper).is_err());
+        assert_eq!(wrapper_target(&wrapper), Some(binary.clone()));
+        assert!(linker.is_linked(&keg_path));
+
+        let output = std::process::Command::new(&wrapper)
+            .arg("status")
+            .env("MARK", "you")
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            format!(
+                "{}|say \"hi\" to you|status",
+                prefix.join("opt/git/libexec/git-core").display()
+            )
+        );
+
+        // Relinking keeps the wrapper; unlinking removes it
+        assert_eq!(linker.link_keg(&keg_path).unwrap().len(), 1);
+        let unlinked = linker.unlink_keg(&keg_path).unwrap();
+        assert_eq!(unlinked, vec![wrapper.clone()]);
+        assert!(wrapper.symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn wrappers_follow_env_changes_and_new_versions() {
+        let tmp = TempDir::new().unwrap();
+        let old_keg = setup_version(&tmp, "foo", "1.0.0", &["foo"]);
+        let new_keg = setup_version(&tmp, "foo", "2.0.0", &["foo"]);
+
+        let prefix = tmp.path().join("homebrew");
+        let mut linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&old_keg).unwrap();
+        let link = prefix.join("bin/foo");
+
+        let env = BTreeMap::from([("FOO_HOME".to_string(), "{prefix}".to_string())]);
+        linker.set_wrapper_env("foo", env);
+        linker.link_keg(&old_keg).unwrap();
+        assert_eq!(wrapper_target(&link), Some(old_keg.join("bin/foo")));
+
+        linker.replace_keg(&old_keg, &new_keg).unwrap();
+        assert_eq!(wrapper_target(&link), Some(new_keg.join("bin/foo")));
+        assert!(linker.is_linked(&new_keg));
+
+        linker.set_wrapper_env("foo", BTreeMap::new());
+        linker.link_keg(&new_keg).unwrap();
+        assert_eq!(fs::read_link(&link).unwrap(), new_keg.join("bin/foo"));
+    }
 }
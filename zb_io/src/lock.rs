@@ -0,0 +1,136 @@
+//! Advisory file locks in the `locks` directory.
+//!
+//! Each store key and each formula gets its own lock file, so zb processes
+//! working on unrelated packages run in parallel while work on the same
+//! formula or store entry is serialized.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use fs4::fs_std::FileExt;
+
+use zb_core::Error;
+
+/// Subdirectory of the locks directory holding per-formula locks.
+/// Kept apart from store-key locks, which `zb cleanup` prunes.
+const FORMULA_LOCKS_DIR: &str = "formulas";
+
+/// An exclusive lock, released when dropped
+#[derive(Debug)]
+pub struct LockGuard {
+    _file: File,
+    path: PathBuf,
+}
+
+impl LockGuard {
+    /// Path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Block until the lock at `path` is held exclusively
+pub fn acquire(path: &Path) -> Result<LockGuard, Error> {
+    let file = open_lock_file(path)?;
+    file.lock_exclusive().map_err(|e| Error::StoreCorruption {
+        message: format!("failed to acquire lock: {e}"),
+    })?;
+
+    Ok(LockGuard {
+        _file: file,
+        path: path.to_path_buf(),
+    })
+}
+
+/// Take the lock at `path` if nobody else holds it
+pub fn try_acquire(path: &Path) -> Result<Option<LockGuard>, Error> {
+    let file = open_lock_file(path)?;
+    match file.try_lock_exclusive() {
+        Ok(true) => Ok(Some(LockGuard {
+            _file: file,
+            path: path.to_path_buf(),
+        })),
+        Ok(false) => Ok(None),
+        Err(e) => Err(Error::StoreCorruption {
+            message: format!("failed to acquire lock: {e}"),
+        }),
+    }
+}
+
+fn open_lock_file(path: &Path) -> Result<File, Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create lock directory: {e}"),
+        })?;
+    }
+
+    // Don't truncate: the file may be locked by another process
+    File::options()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create lock file: {e}"),
+        })
+}
+
+/// Lock file for a store key
+pub fn store_key_lock_path(locks_dir: &Path, store_key: &str) -> PathBuf {
+    locks_dir.join(format!("{store_key}.lock"))
+}
+
+/// Lock file for a formula. Tap formulas (`user/repo/name`) are flattened
+/// so every formula gets a single file.
+pub fn formula_lock_path(locks_dir: &Path, name: &str) -> PathBuf {
+    locks_dir
+        .join(FORMULA_LOCKS_DIR)
+        .join(format!("{}.lock", name.replace('/', "--")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn lock_is_exclusive_until_dropped() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("jq.lock");
+
+        let guard = acquire(&path).unwrap();
+        assert_eq!(guard.path(), path);
+        assert!(try_acquire(&path).unwrap().is_none());
+
+        drop(guard);
+        assert!(try_acquire(&path).unwrap().is_some());
+    }
+
+    #[test]
+    fn unrelated_locks_do_not_conflict() {
+        let tmp = TempDir::new().unwrap();
+        let _jq = acquire(&formula_lock_path(tmp.path(), "jq")).unwrap();
+        assert!(
+            try_acquire(&formula_lock_path(tmp.path(), "wget"))
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn formula_lock_paths() {
+        let locks = Path::new("/zb/locks");
+        assert_eq!(
+            formula_lock_path(locks, "python@3.12"),
+            PathBuf::from("/zb/locks/formulas/python@3.12.lock")
+        );
+        assert_eq!(
+            formula_lock_path(locks, "user/repo/tool"),
+            PathBuf::from("/zb/locks/formulas/user--repo--tool.lock")
+        );
+        assert_eq!(
+            store_key_lock_path(locks, "abc123"),
+            PathBuf::from("/zb/locks/abc123.lock")
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! File manifests for store entries.
+//!
+//! When a bottle is extracted into the store, the hash of every regular file is
+//! recorded. `zb install --verify` re-hashes the materialized keg against this
+//! manifest before linking. Files that materialization is allowed to rewrite
+//! (binaries and files containing Homebrew placeholders) are only checked for
+//! presence.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use zb_core::Error;
+
+/// Markers that cause a file to be patched during materialization
+const PATCH_MARKERS: &[&[u8]] = &[b"@@HOMEBREW", b"/home/linuxbrew"];
+
+/// Magic numbers of binaries that may be patched or re-signed
+const BINARY_MAGICS: &[[u8; 4]] = &[
+    [0x7f, b'E', b'L', b'F'],
+    [0xfe, 0xed, 0xfa, 0xce],
+    [0xfe, 0xed, 0xfa, 0xcf],
+    [0xce, 0xfa, 0xed, 0xfe],
+    [0xcf, 0xfa, 0xed, 0xfe],
+    [0xca, 0xfe, 0xba, 0xbe],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest root, always `/`-separated
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+    /// True if materialization may legitimately change this file
+    #[serde(default)]
+    pub patchable: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A difference between a manifest and a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestMismatch {
+    Missing(String),
+    Modified(String),
+}
+
+impl ManifestMismatch {
+    pub fn path(&self) -> &str {
+        match self {
+            ManifestMismatch::Missing(p) | ManifestMismatch::Modified(p) => p,
+        }
+    }
+}
+
+impl Manifest {
+    /// Hash every regular file under `root`
+    pub fn build(root: &Path) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+
+        for entry in walkdir::WalkDir::new(root).follow_links(false) {
+            let entry = entry.map_err(|e| Error::StoreCorruption {
+                message: format!("failed to walk {}: {e}", root.display()),
+            })?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let data = fs::read(entry.path()).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to read {}: {e}", entry.path().display()),
+            })?;
+            let Ok(relative) = entry.path().strip_prefix(root) else {
+                continue;
+            };
+
+            entries.push(ManifestEntry {
+                path: relative_path_string(relative),
+                sha256: format!("{:x}", Sha256::digest(&data)),
+                size: data.len() as u64,
+                patchable: is_patchable(&data),
+            });
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Self { entries })
+    }
+
+    /// Load a manifest, returning None if it does not exist
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::StoreCorruption {
+                    message: format!("failed to read manifest {}: {e}", path.display()),
+                });
+            }
+        };
+
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to parse manifest {}: {e}", path.display()),
+            })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to create manifest directory: {e}"),
+            })?;
+        }
+
+        let data = serde_json::to_vec(self).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize manifest: {e}"),
+        })?;
+        fs::write(path, data).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to write manifest {}: {e}", path.display()),
+        })
+    }
+
+    /// Entries under `prefix`, with the prefix stripped from their paths
+    pub fn subtree(&self, prefix: &Path) -> Self {
+        let prefix = relative_path_string(prefix);
+        if prefix.is_empty() {
+            return self.clone();
+        }
+
+        let prefix = format!("{prefix}/");
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter_map(|e| {
+                    e.path.strip_prefix(&prefix).map(|rest| ManifestEntry {
+                        path: rest.to_string(),
+                        ..e.clone()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Compare the files under `dir` against this manifest
+    pub fn verify(&self, dir: &Path) -> Vec<ManifestMismatch> {
+        let mut mismatches = Vec::new();
+
+        for entry in &self.entries {
+            let path = dir.join(&entry.path);
+            let Ok(data) = fs::read(&path) else {
+                mismatches.push(ManifestMismatch::Missing(entry.path.clone()));
+                continue;
+            };
+
+            if entry.patchable {
+                continue;
+            }
+
+            if data.len() as u64 != entry.size
+                || format!("{:x}", Sha256::digest(&data)) != entry.sha256
+            {
+                mismatches.push(ManifestMismatch::Modified(entry.path.clone()));
+            }
+        }
+
+        mismatches
+    }
+}
+
+fn relative_path_string(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_patchable(data: &[u8]) -> bool {
+    if data.len() >= 4 && BINARY_MAGICS.iter().any(|m| data[..4] == m[..]) {
+        return true;
+    }
+    PATCH_MARKERS
+        .iter()
+        .any(|marker| data.windows(marker.len()).any(|w| w == *marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample_tree(tmp: &TempDir) -> std::path::PathBuf {
+        let root = tmp.path().join("entry");
+        fs::create_dir_all(root.join("foo/1.0/bin")).unwrap();
+        fs::write(root.join("foo/1.0/bin/foo"), b"#!/bin/sh\necho foo").unwrap();
+        fs::write(
+            root.join("foo/1.0/bin/foo-config"),
+            b"prefix=@@HOMEBREW_PREFIX@@",
+        )
+        .unwrap();
+        root
+    }
+
+    #[test]
+    fn build_records_files_and_patchable_flag() {
+        let tmp = TempDir::new().unwrap();
+        let manifest = Manifest::build(&sample_tree(&tmp)).unwrap();
+
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["foo/1.0/bin/foo", "foo/1.0/bin/foo-config"]);
+        assert!(!manifest.entries[0].patchable);
+        assert!(manifest.entries[1].patchable);
+    }
+
+    #[test]
+    fn save_and_load_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let manifest = Manifest::build(&sample_tree(&tmp)).unwrap();
+        let path = tmp.path().join("manifests/abc.json");
+
+        manifest.save(&path).unwrap();
+
+        assert_eq!(Manifest::load(&path).unwrap(), Some(manifest));
+        assert_eq!(Manifest::load(&tmp.path().join("missing")).unwrap(), None);
+    }
+
+    #[test]
+    fn subtree_strips_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let manifest = Manifest::build(&sample_tree(&tmp)).unwrap();
+
+        let keg = manifest.subtree(Path::new("foo/1.0"));
+        assert_eq!(keg.entries[0].path, "bin/foo");
+        assert_eq!(manifest.subtree(Path::new("")), manifest);
+    }
+
+    #[test]
+    fn verify_detects_modified_and_missing_files() {
+        let tmp = TempDir::new().unwrap();
+        let root = sample_tree(&tmp);
+        let manifest = Manifest::build(&root).unwrap();
+        assert!(manifest.verify(&root).is_empty());
+
+        fs::write(root.join("foo/1.0/bin/foo"), b"#!/bin/sh\necho bar").unwrap();
+        fs::write(root.join("foo/1.0/bin/foo-config"), b"prefix=/opt/zerobrew").unwrap();
+        assert_eq!(
+            manifest.verify(&root),
+            vec![ManifestMismatch::Modified("foo/1.0/bin/foo".to_string())]
+        );
+
+        fs::remove_file(root.join("foo/1.0/bin/foo-config")).unwrap();
+        assert!(manifest.verify(&root).contains(&ManifestMismatch::Missing(
+            "foo/1.0/bin/foo-config".to_string()
+        )));
+    }
+
+    #[test]
+    fn binaries_are_patchable() {
+        assert!(is_patchable(&[0x7f, b'E', b'L', b'F', 0, 0]));
+        assert!(is_patchable(&[0xcf, 0xfa, 0xed, 0xfe]));
+        assert!(!is_patchable(b"plain text"));
+    }
+}
@@ -7,9 +7,13 @@
 //!
 //! Materialization uses a fallback chain for efficiency:
 //! 1. **Clonefile** (macOS APFS): Copy-on-write clone, instant and uses no extra disk space
-//! 2. **Hardlink**: Zero-copy on same filesystem, shares disk blocks
+//! 2. **Hardlink**: Zero-copy when the store and Cellar share a filesystem, shares disk blocks
 //! 3. **Regular copy**: Standard file copy, used as final fallback
 //!
+//! Hardlinked kegs share inodes with the store, so copy-on-write is done by
+//! hand: store files are read-only, and a file is replaced by a private copy
+//! before patching or re-signing writes to it (see [`WriteGuard`]).
+//!
 //! # ELF Patching (Linux)
 //!
 //! Homebrew bottles contain binaries built for `/home/linuxbrew/.linuxbrew`.
@@ -29,11 +33,22 @@
 //!
 //! Homebrew bottles use their own dynamic linker to avoid glibc version mismatches.
 //! We patch both to point to zerobrew's prefix.
+//!
+//! # Relocation
+//!
+//! Bottles are built for Homebrew's prefix, and bottling replaces that prefix
+//! with placeholders such as `@@HOMEBREW_PREFIX@@` in scripts, pkg-config
+//! files and other text. Each materialized keg is relocated to the Cellar's
+//! prefix: placeholders in text files are rewritten in place (see
+//! [`Relocation`]), and binaries are patched as above, or on macOS with
+//! `install_name_tool` (install names, linked libraries and `LC_RPATH`s).
 
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
+use crate::fs_limits;
+use crate::tempdir::{self, TempDirGuard};
 use zb_core::Error;
 
 /// Helper to convert io::Result to Error::StoreCorruption with context
@@ -47,9 +62,10 @@ fn store_err<T>(result: std::io::Result<T>, context: &str) -> Result<T, Error> {
 ///
 /// This is useful when modifying read-only files (common with Homebrew bottles).
 /// The guard will:
-/// 1. Check if the file is read-only (missing owner write permission)
-/// 2. If so, make it writable
-/// 3. On drop, restore the original permissions
+/// 1. Replace the file with a private copy if it is hardlinked (e.g. to the store)
+/// 2. Check if the file is read-only (missing owner write permission)
+/// 3. If so, make it writable
+/// 4. On drop, restore the original permissions
 #[cfg(unix)]
 struct WriteGuard {
     path: PathBuf,
@@ -64,6 +80,7 @@ impl WriteGuard {
     /// Returns an error if the file metadata cannot be read or permissions cannot be changed.
     fn new(path: &Path) -> std::io::Result<Self> {
         use std::os::unix::fs::PermissionsExt;
+        break_hardlink(path)?;
         let metadata = fs::metadata(path)?;
         let mode = metadata.permissions().mode();
 
@@ -99,11 +116,41 @@ impl Drop for WriteGuard {
     }
 }
 
+/// Replace `path` with a copy of itself if other links share its inode, so
+/// writing to it can't change the store entry (or another keg) it came from
+#[cfg(unix)]
+fn break_hardlink(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    if fs::symlink_metadata(path)?.nlink() <= 1 {
+        return Ok(());
+    }
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{file_name}.unshared"));
+    fs::copy(path, &tmp)
+        .and_then(|_| fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+}
+
+/// Whether `a` and `b` are on the same filesystem, so files can be
+/// hardlinked between them
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
 /// Strategy used to copy files from store to Cellar.
 ///
 /// Zerobrew tries strategies in order of efficiency:
 /// 1. `Clonefile` - Instant copy-on-write (macOS APFS/Linux btrfs+xfs)
-/// 2. `Hardlink` - Zero disk usage, shares blocks (same filesystem only)
+/// 2. `Hardlink` - Zero disk usage, shares blocks (same filesystem only;
+///    store files are read-only so the shared blocks can't be modified)
 /// 3. `Copy` - Standard copy, always works
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CopyStrategy {
@@ -151,6 +198,47 @@ impl Cellar {
         self.cellar_dir.join(name).join(version)
     }
 
+    /// Formula whose keg `path` lies in, if it is inside the Cellar
+    pub fn keg_owner(&self, path: &Path) -> Option<String> {
+        let relative = path
+            .strip_prefix(&self.cellar_dir)
+            .map(Path::to_path_buf)
+            .ok()
+            .or_else(|| {
+                // Either side may be reached through a symlink (/tmp on macOS)
+                let cellar = fs::canonicalize(&self.cellar_dir).ok()?;
+                let path = fs::canonicalize(path).ok()?;
+                path.strip_prefix(cellar).ok().map(Path::to_path_buf)
+            })?;
+        relative
+            .components()
+            .next()
+            .map(|name| name.as_os_str().to_string_lossy().to_string())
+    }
+
+    /// Every keg directory in the Cellar, as `(name, version)` sorted by
+    /// name and version. Temp kegs being built are skipped.
+    pub fn list_kegs(&self) -> io::Result<Vec<(String, String)>> {
+        let mut kegs = Vec::new();
+        for formula in fs::read_dir(&self.cellar_dir)? {
+            let formula = formula?;
+            let name = formula.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || !formula.file_type()?.is_dir() {
+                continue;
+            }
+            for version in fs::read_dir(formula.path())? {
+                let version = version?;
+                let version_name = version.file_name().to_string_lossy().to_string();
+                if version_name.starts_with('.') || !version.file_type()?.is_dir() {
+                    continue;
+                }
+                kegs.push((name.clone(), version_name));
+            }
+        }
+        kegs.sort();
+        Ok(kegs)
+    }
+
     pub fn has_keg(&self, name: &str, version: &str) -> bool {
         self.keg_path(name, version).exists()
     }
@@ -178,30 +266,84 @@ impl Cellar {
         // Find the source directory to copy from
         let src_path = find_bottle_content(store_entry, name, version)?;
 
+        // Build the keg in a temp directory and move it into place once it is complete,
+        // so a crash never leaves a half-copied or half-patched keg behind
+        let tmp_keg = TempDirGuard::new(&self.cellar_dir, &format!("{name}-{version}"))?;
+        let staged = tmp_keg.path().join("keg");
+
+        // Fail before copying anything if the Cellar's filesystem is out of
+        // inodes or the keg's deepest path would be too long
+        let tree = fs_limits::tree_stats(&src_path)?;
+        fs_limits::check_tree_fits(&tree, &[&staged, &keg_path])?;
+
         // Copy the content to the cellar using best available strategy
-        copy_dir_with_fallback(&src_path, &keg_path)?;
+        copy_dir_with_fallback(&src_path, &staged)?;
+
+        // Point scripts and config files at this prefix
+        relocate_text_files(&staged, &Relocation::new(&self.cellar_dir))?;
 
         // Patch Homebrew placeholders in Mach-O binaries
         #[cfg(target_os = "macos")]
-        patch_homebrew_placeholders(&keg_path, &self.cellar_dir, name, version)?;
+        patch_homebrew_placeholders(&staged, &self.cellar_dir, name, version)?;
 
         // Strip quarantine xattrs and ad-hoc sign Mach-O binaries
         #[cfg(target_os = "macos")]
-        codesign_and_strip_xattrs(&keg_path)?;
+        codesign_and_strip_xattrs(&staged)?;
 
         // Patch Homebrew placeholders in ELF binaries (Linux)
         #[cfg(target_os = "linux")]
         patch_homebrew_placeholders_linux(
-            &keg_path,
+            &staged,
             &self.cellar_dir,
             &self.zerobrew_root,
             name,
             version,
         )?;
 
+        fs::rename(&staged, &keg_path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to move keg into place: {e}"),
+        })?;
+
         Ok(keg_path)
     }
 
+    /// Install the contents of `src` (e.g. a source build's staging
+    /// directory) as the keg for `name` `version`, replacing any existing keg.
+    ///
+    /// The copy is made in a temp directory inside the Cellar and renamed into
+    /// place, so `src` may live on another filesystem without the keg ever
+    /// being visible half-copied.
+    pub fn install_dir(&self, name: &str, version: &str, src: &Path) -> Result<PathBuf, Error> {
+        let keg_path = self.keg_path(name, version);
+        if let Some(parent) = keg_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to create keg parent directory: {e}"),
+            })?;
+        }
+
+        let tmp_keg = TempDirGuard::new(&self.cellar_dir, &format!("{name}-{version}"))?;
+        let staged = tmp_keg.path().join("keg");
+        let tree = fs_limits::tree_stats(src)?;
+        fs_limits::check_tree_fits(&tree, &[&staged, &keg_path])?;
+        copy_dir_with_fallback(src, &staged)?;
+
+        if keg_path.exists() {
+            fs::remove_dir_all(&keg_path).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove existing keg: {e}"),
+            })?;
+        }
+        fs::rename(&staged, &keg_path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to move keg into place: {e}"),
+        })?;
+
+        Ok(keg_path)
+    }
+
+    /// Remove temp kegs left behind by processes that are no longer running
+    pub fn cleanup_temp_dirs(&self) -> io::Result<(usize, u64)> {
+        tempdir::reap_stale(&self.cellar_dir)
+    }
+
     pub fn remove_keg(&self, name: &str, version: &str) -> Result<(), Error> {
         let keg_path = self.keg_path(name, version);
 
@@ -286,7 +428,11 @@ fn patch_homebrew_path(
 /// Homebrew bottles have structure {name}/{version}/ inside the tarball.
 /// This function finds that directory, falling back to the store_entry root
 /// if the expected structure isn't found.
-fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<PathBuf, Error> {
+pub(crate) fn find_bottle_content(
+    store_entry: &Path,
+    name: &str,
+    version: &str,
+) -> Result<PathBuf, Error> {
     // Try the expected Homebrew structure: {name}/{version}/
     let expected_path = store_entry.join(name).join(version);
     if expected_path.exists() && expected_path.is_dir() {
@@ -313,6 +459,127 @@ fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<
     Ok(store_entry.to_path_buf())
 }
 
+/// The paths Homebrew's placeholders stand for in a Cellar's prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Relocation {
+    cellar: String,
+    prefix: String,
+}
+
+impl Relocation {
+    /// Relocation into `cellar_dir`, whose parent is the prefix
+    pub(crate) fn new(cellar_dir: &Path) -> Self {
+        // Derive prefix from cellar (cellar_dir is typically prefix/Cellar)
+        let prefix = cellar_dir.parent().unwrap_or(Path::new("/opt/homebrew"));
+        Self {
+            cellar: cellar_dir.to_string_lossy().to_string(),
+            prefix: prefix.to_string_lossy().to_string(),
+        }
+    }
+
+    /// Each placeholder with the path it is replaced by
+    fn replacements(&self) -> [(&'static str, String); 5] {
+        [
+            ("@@HOMEBREW_CELLAR@@", self.cellar.clone()),
+            ("@@HOMEBREW_PREFIX@@", self.prefix.clone()),
+            // zerobrew has no separate repository; Homebrew's defaults to the prefix
+            ("@@HOMEBREW_REPOSITORY@@", self.prefix.clone()),
+            ("@@HOMEBREW_LIBRARY@@", format!("{}/Library", self.prefix)),
+            ("@@HOMEBREW_PERL@@", "/usr/bin/perl".to_string()),
+        ]
+    }
+
+    /// `text` with every placeholder replaced, or `None` if it has none
+    pub(crate) fn rewrite(&self, text: &str) -> Option<String> {
+        if !text.contains("@@HOMEBREW_") {
+            return None;
+        }
+        let mut rewritten = text.to_string();
+        for (placeholder, path) in self.replacements() {
+            rewritten = rewritten.replace(placeholder, &path);
+        }
+        (rewritten != text).then_some(rewritten)
+    }
+}
+
+/// Whether `data` looks like text rather than a binary. Binaries can't be
+/// rewritten in place, as replacing a placeholder changes their length.
+fn is_text(data: &[u8]) -> bool {
+    const SNIFF_LEN: usize = 8192;
+    !data[..data.len().min(SNIFF_LEN)].contains(&0)
+}
+
+/// Rewrite Homebrew placeholders in a keg's text files, returning how many
+/// files changed. Symlinks are left alone, and hardlinked files get a
+/// private copy first, so the store entry is never modified.
+fn relocate_text_files(keg_path: &Path, relocation: &Relocation) -> Result<usize, Error> {
+    use rayon::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let files: Vec<PathBuf> = walkdir::WalkDir::new(keg_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect();
+
+    let relocated = AtomicUsize::new(0);
+    let failures = AtomicUsize::new(0);
+    files.par_iter().for_each(|path| {
+        let Ok(data) = fs::read(path) else {
+            return;
+        };
+        if !is_text(&data) {
+            return;
+        }
+        let Some(text) = std::str::from_utf8(&data)
+            .ok()
+            .and_then(|text| relocation.rewrite(text))
+        else {
+            return;
+        };
+
+        // Make file writable if needed (permissions restored automatically on drop)
+        let written = WriteGuard::new(path).and_then(|_guard| fs::write(path, text));
+        match written {
+            Ok(()) => relocated.fetch_add(1, Ordering::Relaxed),
+            Err(_) => failures.fetch_add(1, Ordering::Relaxed),
+        };
+    });
+
+    let failures = failures.load(Ordering::Relaxed);
+    if failures > 0 {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "failed to relocate {} text files in {}",
+                failures,
+                keg_path.display()
+            ),
+        });
+    }
+
+    Ok(relocated.load(Ordering::Relaxed))
+}
+
+/// The `LC_RPATH` entries in `otool -l` output
+#[cfg(any(target_os = "macos", test))]
+fn parse_otool_rpaths(output: &str) -> Vec<String> {
+    let mut rpaths = Vec::new();
+    let mut in_rpath = false;
+    for line in output.lines().map(str::trim) {
+        if let Some(cmd) = line.strip_prefix("cmd ") {
+            in_rpath = cmd.trim() == "LC_RPATH";
+        } else if in_rpath && let Some(rest) = line.strip_prefix("path ") {
+            // e.g. "path @@HOMEBREW_PREFIX@@/lib (offset 12)"
+            let path = rest.rsplit_once(" (offset").map_or(rest, |(path, _)| path);
+            rpaths.push(path.to_string());
+            in_rpath = false;
+        }
+    }
+    rpaths
+}
+
 /// Patch @@HOMEBREW_CELLAR@@ and @@HOMEBREW_PREFIX@@ placeholders in Mach-O binaries.
 /// Also fixes version mismatches where a bottle references a different version of itself.
 /// Uses rayon for parallel processing.
@@ -444,6 +711,34 @@ fn patch_homebrew_placeholders(
             }
         }
 
+        // Get and patch run paths (LC_RPATH)
+        if let Ok(output) = Command::new("otool")
+            .args(["-l", &path.to_string_lossy()])
+            .output()
+            && output.status.success()
+        {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            for old_rpath in parse_otool_rpaths(&stdout) {
+                if let Some(new_rpath) = patch_homebrew_path(
+                    &old_rpath,
+                    &cellar_str,
+                    &prefix_str,
+                    version_regex.as_ref(),
+                    pkg_name,
+                    pkg_version,
+                ) {
+                    let result = Command::new("install_name_tool")
+                        .args(["-rpath", &old_rpath, &new_rpath, &path.to_string_lossy()])
+                        .output();
+                    if result.is_ok() {
+                        patched_any = true;
+                    } else {
+                        patch_failures.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
         // Re-sign if we patched anything (patching invalidates code signature)
         if patched_any {
             let _ = Command::new("codesign")
@@ -763,7 +1058,7 @@ fn patch_homebrew_placeholders_linux(
     Ok(())
 }
 
-fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
+pub(crate) fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
     // Try clonefile first (APFS on macOS), then hardlink, then copy
     #[cfg(target_os = "macos")]
     {
@@ -780,8 +1075,12 @@ fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
         }
     }
 
-    // Fall back to recursive copy with hardlink/copy per file
-    copy_dir_recursive(src, dst, true)
+    // Fall back to recursive copy, hardlinking files when the source and
+    // destination share a filesystem
+    let try_hardlink = dst
+        .parent()
+        .is_some_and(|parent| same_filesystem(src, parent));
+    copy_dir_recursive(src, dst, try_hardlink)
 }
 
 #[cfg(target_os = "macos")]
@@ -1005,6 +1304,92 @@ mod tests {
         store_entry
     }
 
+    #[test]
+    fn materialize_leaves_no_temp_dirs() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        let leftovers: Vec<_> = fs::read_dir(tmp.path().join("cellar"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp."))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn materialize_refuses_paths_too_long_for_the_cellar() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+        let cellar = Cellar::new(tmp.path()).unwrap();
+
+        // A file whose path fits in the store, but not below the staged keg
+        let staged = tmp
+            .path()
+            .join("cellar")
+            .join(tempdir::temp_dir_name("foo-1.2.3", std::process::id()))
+            .join("keg");
+        let mut relative = PathBuf::from("share");
+        let target = libc::PATH_MAX as usize - staged.as_os_str().len() - 1;
+        while relative.as_os_str().len() + 201 < target {
+            relative.push("d".repeat(200));
+        }
+        let file_len = target - relative.as_os_str().len() - 1;
+        relative.push("f".repeat(file_len));
+        fs::create_dir_all(store_entry.join(relative.parent().unwrap())).unwrap();
+        fs::write(store_entry.join(&relative), "").unwrap();
+
+        let err = cellar
+            .materialize("foo", "1.2.3", &store_entry)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("path is too long"), "{err}");
+        assert!(err.contains("share/ddd"), "{err}");
+        assert!(!cellar.has_keg("foo", "1.2.3"));
+    }
+
+    #[test]
+    fn install_dir_replaces_keg_without_leftovers() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let staging = tmp.path().join("staging");
+        fs::create_dir_all(staging.join("bin")).unwrap();
+        fs::write(staging.join("bin/foo"), "new").unwrap();
+        fs::create_dir_all(staging.join("share/empty/nested")).unwrap();
+
+        let old_keg = cellar.keg_path("foo", "1.2.3");
+        fs::create_dir_all(old_keg.join("share")).unwrap();
+
+        let keg_path = cellar.install_dir("foo", "1.2.3", &staging).unwrap();
+
+        assert_eq!(keg_path, old_keg);
+        assert_eq!(fs::read_to_string(keg_path.join("bin/foo")).unwrap(), "new");
+        assert!(keg_path.join("share/empty/nested").is_dir());
+        assert!(staging.join("bin/foo").exists());
+        let leftovers: Vec<_> = fs::read_dir(tmp.path().join("cellar"))
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp."))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn cleanup_temp_dirs_reaps_abandoned_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let abandoned = tmp.path().join("cellar/.foo-1.2.3.tmp.999999999");
+        fs::create_dir_all(abandoned.join("keg/bin")).unwrap();
+
+        let (count, _) = cellar.cleanup_temp_dirs().unwrap();
+
+        assert_eq!(count, 1);
+        assert!(!abandoned.exists());
+    }
+
     #[test]
     fn tree_reproduced_exactly() {
         let tmp = TempDir::new().unwrap();
@@ -1116,6 +1501,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn write_guard_unshares_hardlinked_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let stored = tmp.path().join("stored");
+        fs::write(&stored, b"original").unwrap();
+        fs::set_permissions(&stored, fs::Permissions::from_mode(0o555)).unwrap();
+        let linked = tmp.path().join("linked");
+        fs::hard_link(&stored, &linked).unwrap();
+
+        {
+            let _guard = WriteGuard::new(&linked).unwrap();
+            fs::write(&linked, b"patched").unwrap();
+        }
+
+        assert_eq!(fs::read(&stored).unwrap(), b"original");
+        assert_eq!(fs::read(&linked).unwrap(), b"patched");
+        let meta = fs::metadata(&linked).unwrap();
+        assert_eq!(meta.nlink(), 1);
+        assert_eq!(meta.mode() & 0o777, 0o555);
+        assert_eq!(fs::metadata(&stored).unwrap().nlink(), 1);
+    }
+
+    #[test]
+    fn relocation_rewrites_every_placeholder() {
+        let relocation = Relocation::new(Path::new("/opt/zerobrew/prefix/Cellar"));
+        assert_eq!(
+            relocation
+                .rewrite(
+                    "prefix=@@HOMEBREW_PREFIX@@\n\
+                     libdir=@@HOMEBREW_CELLAR@@/foo/1.0/lib\n\
+                     repo=@@HOMEBREW_REPOSITORY@@ lib=@@HOMEBREW_LIBRARY@@\n\
+                     #!@@HOMEBREW_PERL@@\n"
+                )
+                .unwrap(),
+            "prefix=/opt/zerobrew/prefix\n\
+             libdir=/opt/zerobrew/prefix/Cellar/foo/1.0/lib\n\
+             repo=/opt/zerobrew/prefix lib=/opt/zerobrew/prefix/Library\n\
+             #!/usr/bin/perl\n"
+        );
+        assert_eq!(relocation.rewrite("prefix=/usr/local\n"), None);
+        assert_eq!(relocation.rewrite("@@HOMEBREW_UNKNOWN@@"), None);
+    }
+
+    #[test]
+    fn materialize_relocates_text_files_without_touching_the_store() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+        let config = store_entry.join("bin/foo-config");
+        fs::write(&config, "#!/bin/sh\necho @@HOMEBREW_PREFIX@@/include\n").unwrap();
+        fs::set_permissions(&config, fs::Permissions::from_mode(0o555)).unwrap();
+        let pc = store_entry.join("lib/foo.pc");
+        fs::write(&pc, "libdir=@@HOMEBREW_CELLAR@@/foo/1.2.3/lib\n").unwrap();
+        // Binaries can't be resized, so they're left to the binary patchers
+        let blob = store_entry.join("lib/libfoo.a");
+        fs::write(&blob, b"\0\0@@HOMEBREW_PREFIX@@\0").unwrap();
+
+        let cellar_dir = tmp.path().join("prefix/Cellar");
+        let cellar = Cellar::new_at(cellar_dir.clone()).unwrap();
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        assert_eq!(
+            fs::read_to_string(keg_path.join("bin/foo-config")).unwrap(),
+            format!("#!/bin/sh\necho {}/include\n", prefix.display())
+        );
+        assert_eq!(
+            fs::metadata(keg_path.join("bin/foo-config"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o555
+        );
+        assert_eq!(
+            fs::read_to_string(keg_path.join("lib/foo.pc")).unwrap(),
+            format!("libdir={}/foo/1.2.3/lib\n", cellar_dir.display())
+        );
+        assert_eq!(
+            fs::read(keg_path.join("lib/libfoo.a")).unwrap(),
+            b"\0\0@@HOMEBREW_PREFIX@@\0"
+        );
+        assert!(
+            fs::read_to_string(&config)
+                .unwrap()
+                .contains("@@HOMEBREW_PREFIX@@")
+        );
+    }
+
+    #[test]
+    fn otool_rpaths_are_parsed() {
+        let output = "/opt/zerobrew/prefix/bin/foo:
+Load command 12
+          cmd LC_LOAD_DYLIB
+      cmdsize 56
+         name @@HOMEBREW_PREFIX@@/lib/libbar.dylib (offset 24)
+Load command 13
+          cmd LC_RPATH
+      cmdsize 48
+         path @@HOMEBREW_PREFIX@@/lib (offset 12)
+Load command 14
+          cmd LC_RPATH
+      cmdsize 32
+         path @loader_path/../lib (offset 12)
+";
+        assert_eq!(
+            parse_otool_rpaths(output),
+            ["@@HOMEBREW_PREFIX@@/lib", "@loader_path/../lib"]
+        );
+    }
+
+    #[test]
+    fn materialize_on_same_filesystem_shares_store_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        // Shared through a hardlink, or cloned when the filesystem supports it
+        let stored = fs::metadata(store_entry.join("bin/foo")).unwrap();
+        let keg = fs::metadata(keg_path.join("bin/foo")).unwrap();
+        assert!(stored.ino() == keg.ino() || stored.nlink() == 1);
+        assert_eq!(
+            fs::read_to_string(keg_path.join("bin/foo")).unwrap(),
+            "#!/bin/sh\necho foo"
+        );
+    }
+
     #[test]
     #[cfg(target_os = "macos")]
     fn clonefile_fallback_works() {
@@ -0,0 +1,176 @@
+//! Static mirror layout for air-gapped installs.
+//!
+//! `zb mirror create <dir>` writes formula metadata and bottles into a
+//! directory that any static web server can serve:
+//!
+//! ```text
+//! <dir>/api/formula.json                      # formula list (search)
+//! <dir>/api/formula/<name>.json               # per-formula metadata
+//! <dir>/bottles/<name>/blobs/sha256:<sha>     # bottles, laid out like ghcr.io
+//! <dir>/platforms.json                        # bottle tags `zb mirror sync` keeps
+//! ```
+//!
+//! Clients then run `zb --mirror <url-or-dir>`, which is shorthand for
+//! `zb --api-domain <url>/api --bottle-domain <url>/bottles`. A local
+//! directory is read directly, without any network access.
+//! Formula metadata keeps the upstream bottle URLs; `--bottle-domain` rewrites
+//! them with [`rewrite_bottle_url`], the same mapping used to lay out bottles.
+
+use std::path::{Path, PathBuf};
+
+use zb_core::{ApiLayout, Error};
+
+/// Prefix of Homebrew core bottle URLs
+const GHCR_CORE_PREFIX: &str = "https://ghcr.io/v2/homebrew/core/";
+
+/// Directory holding the formula API inside a mirror
+pub fn api_dir(mirror: &Path) -> PathBuf {
+    mirror.join("api")
+}
+
+/// Directory holding bottles inside a mirror
+pub fn bottles_dir(mirror: &Path) -> PathBuf {
+    mirror.join("bottles")
+}
+
+/// Path of a formula's metadata inside a mirror
+pub fn formula_path(mirror: &Path, name: &str) -> PathBuf {
+    api_dir(mirror).join("formula").join(format!("{name}.json"))
+}
+
+/// Path of the formula list inside a mirror
+pub fn formula_list_path(mirror: &Path) -> PathBuf {
+    api_dir(mirror).join("formula.json")
+}
+
+/// Path of the list of mirrored bottle tags
+pub fn platforms_path(mirror: &Path) -> PathBuf {
+    mirror.join("platforms.json")
+}
+
+/// Formula API base URL for an `--api-domain` laid out as `layout`
+pub fn api_base_url(api_domain: &str, layout: ApiLayout) -> String {
+    let api_domain = api_domain.trim().trim_end_matches('/');
+    match layout {
+        ApiLayout::Homebrew => format!("{api_domain}/formula"),
+        ApiLayout::Flat => api_domain.to_string(),
+    }
+}
+
+/// Check that an `--api-domain` is an `http(s)://` URL formula paths can be
+/// appended to
+pub fn validate_api_domain(api_domain: &str) -> Result<(), Error> {
+    let invalid = |reason: &str| Error::StoreCorruption {
+        message: format!("invalid API domain '{api_domain}': {reason}"),
+    };
+    let url = reqwest::Url::parse(api_domain.trim()).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid("expected an http:// or https:// URL"));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("missing host"));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(invalid("formula paths can't follow a query or fragment"));
+    }
+    if url.path().ends_with(".json") {
+        return Err(invalid(
+            "this is a single index file; point ZB_FORMULA_INDEX at it instead",
+        ));
+    }
+    Ok(())
+}
+
+/// Path of a bottle relative to the mirror's bottle directory.
+///
+/// Homebrew core bottles keep their path below `ghcr.io/v2/homebrew/core`;
+/// bottles from anywhere else keep their path below the host.
+pub fn bottle_path(url: &str) -> String {
+    if let Some(rest) = url.strip_prefix(GHCR_CORE_PREFIX) {
+        return rest.to_string();
+    }
+
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme
+        .split_once('/')
+        .map_or(without_scheme, |(_, path)| path)
+        .to_string()
+}
+
+/// Point a bottle URL at a `--bottle-domain`
+pub fn rewrite_bottle_url(url: &str, bottle_domain: &str) -> String {
+    format!(
+        "{}/{}",
+        bottle_domain.trim_end_matches('/'),
+        bottle_path(url)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bottle_path_strips_ghcr_core_prefix() {
+        assert_eq!(
+            bottle_path("https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc"),
+            "jq/blobs/sha256:abc"
+        );
+    }
+
+    #[test]
+    fn bottle_path_keeps_path_for_other_hosts() {
+        assert_eq!(
+            bottle_path("https://example.com/bottles/foo-1.0.x86_64_linux.bottle.tar.gz"),
+            "bottles/foo-1.0.x86_64_linux.bottle.tar.gz"
+        );
+    }
+
+    #[test]
+    fn rewrite_bottle_url_uses_domain() {
+        assert_eq!(
+            rewrite_bottle_url(
+                "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc",
+                "http://mirror.local/bottles/"
+            ),
+            "http://mirror.local/bottles/jq/blobs/sha256:abc"
+        );
+    }
+
+    #[test]
+    fn layout_paths() {
+        let mirror = Path::new("/srv/mirror");
+        assert_eq!(
+            formula_path(mirror, "jq"),
+            Path::new("/srv/mirror/api/formula/jq.json")
+        );
+        assert_eq!(
+            formula_list_path(mirror),
+            Path::new("/srv/mirror/api/formula.json")
+        );
+        assert_eq!(
+            api_base_url("http://mirror.local/api/", ApiLayout::Homebrew),
+            "http://mirror.local/api/formula"
+        );
+        assert_eq!(
+            api_base_url("http://127.0.0.1:8080/", ApiLayout::Flat),
+            "http://127.0.0.1:8080"
+        );
+    }
+
+    #[test]
+    fn api_domains_are_validated() {
+        validate_api_domain("https://formulae.brew.sh/api").unwrap();
+        validate_api_domain("http://127.0.0.1:8080").unwrap();
+
+        for (domain, reason) in [
+            ("formulae.brew.sh/api", "relative URL"),
+            ("ftp://mirror.local/api", "http:// or https://"),
+            ("https://mirror.local/api?token=1", "query or fragment"),
+            ("https://mirror.local/formula.json", "ZB_FORMULA_INDEX"),
+        ] {
+            let err = validate_api_domain(domain).unwrap_err().to_string();
+            assert!(err.contains(reason), "{domain}: {err}");
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! A pipe from a download to a blocking reader, for unpacking bottles while
+//! they download.
+//!
+//! A download sends each chunk of the body down the pipe as it writes it to
+//! the blob cache, while a blocking task unpacks the other end into the
+//! store. The reader only sees the end of the body once the sender has
+//! checked its checksum: if the download fails, is cancelled or doesn't
+//! match, the sender is dropped without finishing and the reader fails, so
+//! an unverified store entry is never kept.
+
+use std::io::{self, Read};
+
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+/// Chunks buffered between the download and the reader. Once full, the
+/// download waits for the reader to catch up.
+const PIPE_CAPACITY: usize = 64;
+
+/// Download end of a pipe
+pub struct BodySender {
+    tx: mpsc::Sender<Bytes>,
+}
+
+/// Blocking end of a pipe, yielding the body once it has been verified
+pub struct BodyReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+    finished: bool,
+}
+
+/// Create a pipe
+pub fn body_pipe() -> (BodySender, BodyReader) {
+    let (tx, rx) = mpsc::channel(PIPE_CAPACITY);
+    let reader = BodyReader {
+        rx,
+        chunk: Bytes::new(),
+        finished: false,
+    };
+    (BodySender { tx }, reader)
+}
+
+impl BodySender {
+    /// Pass a chunk of the body on. Returns false once the reader has gone
+    /// away, after which nothing more needs to be sent.
+    pub async fn send(&self, chunk: Bytes) -> bool {
+        chunk.is_empty() || self.tx.send(chunk).await.is_ok()
+    }
+
+    /// Tell the reader the body is complete and verified
+    pub async fn finish(self) {
+        // An empty chunk marks the end; a dropped sender is a failure
+        let _ = self.tx.send(Bytes::new()).await;
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            if self.finished {
+                return Ok(0);
+            }
+            match self.rx.blocking_recv() {
+                Some(chunk) if chunk.is_empty() => self.finished = true,
+                Some(chunk) => self.chunk = chunk,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "download ended before it was verified",
+                    ));
+                }
+            }
+        }
+
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reader_sees_the_body_once_finished() {
+        let (tx, mut reader) = body_pipe();
+        let read = tokio::task::spawn_blocking(move || {
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body).map(|_| body)
+        });
+
+        assert!(tx.send(Bytes::from_static(b"hello ")).await);
+        assert!(tx.send(Bytes::new()).await);
+        assert!(tx.send(Bytes::from_static(b"world")).await);
+        tx.finish().await;
+
+        assert_eq!(read.await.unwrap().unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn dropped_senders_fail_the_reader() {
+        let (tx, mut reader) = body_pipe();
+        let read = tokio::task::spawn_blocking(move || {
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body)
+        });
+
+        assert!(tx.send(Bytes::from_static(b"partial")).await);
+        drop(tx);
+
+        let err = read.await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn sends_stop_once_the_reader_is_gone() {
+        let (tx, reader) = body_pipe();
+        drop(reader);
+        assert!(!tx.send(Bytes::from_static(b"unread")).await);
+    }
+}
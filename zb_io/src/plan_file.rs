@@ -0,0 +1,261 @@
+//! Install plans exported for review and replay.
+//!
+//! `zb install <formula> --emit-plan plan.json` resolves an install without
+//! running it and writes every package it would pour: the exact version,
+//! bottle URL and checksum, and the formula metadata they were resolved
+//! from. `zb execute-plan plan.json` installs exactly those bottles later or
+//! on another machine, without resolving anything against the API again.
+//!
+//! ```text
+//! {
+//!   "format_version": 1,
+//!   "root": "jq",
+//!   "created_at": "2024-06-01T12:00:00Z",
+//!   "packages": [
+//!     {
+//!       "name": "oniguruma",
+//!       "version": "6.9.9",
+//!       "bottle": { "tag": "arm64_sonoma", "url": "https://...", "sha256": "..." },
+//!       "formula": { ... }
+//!     },
+//!     ...
+//!   ]
+//! }
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use zb_core::{Error, Formula, SelectedBottle};
+
+use crate::install::InstallPlan;
+
+/// Version of the plan file layout written by this zerobrew
+pub const PLAN_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub format_version: u32,
+    /// Formula that was asked for, by its canonical name
+    pub root: String,
+    /// When the plan was resolved, in UTC
+    pub created_at: String,
+    /// Packages in install order, dependencies first
+    pub packages: Vec<PlannedPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedPackage {
+    pub name: String,
+    /// Version including the bottle rebuild, e.g. `1.7.1_1`
+    pub version: String,
+    pub bottle: PlannedBottle,
+    /// Metadata the package was resolved from, so the install doesn't
+    /// depend on what the API serves when the plan runs
+    pub formula: Formula,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedBottle {
+    pub tag: String,
+    pub url: String,
+    pub sha256: String,
+}
+
+impl PlanFile {
+    /// Record `plan` as a plan file
+    pub fn from_plan(plan: &InstallPlan) -> Self {
+        let packages = plan
+            .formulas
+            .iter()
+            .zip(&plan.bottles)
+            .map(|(formula, bottle)| PlannedPackage {
+                name: formula.name.clone(),
+                version: formula.effective_version(),
+                bottle: PlannedBottle {
+                    tag: bottle.tag.clone(),
+                    url: bottle.url.clone(),
+                    sha256: bottle.sha256.clone(),
+                },
+                formula: formula.clone(),
+            })
+            .collect();
+
+        PlanFile {
+            format_version: PLAN_FORMAT_VERSION,
+            root: plan.root_name.clone(),
+            created_at: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            packages,
+        }
+    }
+
+    /// Read a plan file
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to read plan at {}: {e}", path.display()),
+        })?;
+        let plan: PlanFile = serde_json::from_slice(&data).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to parse plan at {}: {e}", path.display()),
+        })?;
+        if plan.format_version != PLAN_FORMAT_VERSION {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "plan at {} has format version {}, but this zerobrew reads version {}",
+                    path.display(),
+                    plan.format_version,
+                    PLAN_FORMAT_VERSION
+                ),
+            });
+        }
+        Ok(plan)
+    }
+
+    /// Write the plan, replacing any existing file in a single rename
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut body = serde_json::to_vec_pretty(self).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize plan: {e}"),
+        })?;
+        body.push(b'\n');
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp = path.with_file_name(format!(".{file_name}.tmp"));
+        fs::write(&tmp, body)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&tmp);
+                Error::StoreCorruption {
+                    message: format!("failed to write {}: {e}", path.display()),
+                }
+            })
+    }
+
+    /// Check that every package's name, version and bottle agree with the
+    /// metadata recorded for it, so an edited plan can't install something
+    /// other than what it shows
+    pub fn verify(&self) -> Result<(), Error> {
+        let inconsistent = |name: &str, what: &str| Error::StoreCorruption {
+            message: format!("plan is inconsistent: {what} of {name} doesn't match its metadata"),
+        };
+
+        if !self.packages.iter().any(|p| p.name == self.root) {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "plan is inconsistent: {} is not among its packages",
+                    self.root
+                ),
+            });
+        }
+        for package in &self.packages {
+            let formula = &package.formula;
+            if formula.name != package.name {
+                return Err(inconsistent(&package.name, "the name"));
+            }
+            if formula.effective_version() != package.version {
+                return Err(inconsistent(&package.name, "the version"));
+            }
+            match formula.bottle.stable.files.get(&package.bottle.tag) {
+                Some(file)
+                    if file.url == package.bottle.url && file.sha256 == package.bottle.sha256 => {}
+                _ => return Err(inconsistent(&package.name, "the bottle")),
+            }
+        }
+        Ok(())
+    }
+
+    /// The formulas and bottles to install, in order
+    pub fn into_parts(self) -> (String, Vec<Formula>, Vec<SelectedBottle>) {
+        let (formulas, bottles) = self
+            .packages
+            .into_iter()
+            .map(|package| {
+                let bottle = SelectedBottle {
+                    tag: package.bottle.tag,
+                    url: package.bottle.url,
+                    sha256: package.bottle.sha256,
+                };
+                (package.formula, bottle)
+            })
+            .unzip();
+        (self.root, formulas, bottles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn formula(name: &str, sha256: &str) -> Formula {
+        let json = format!(
+            r#"{{"name":"{name}","versions":{{"stable":"1.0"}},"bottle":{{"stable":{{"rebuild":1,"files":{{"x86_64_linux":{{"url":"https://x/{name}","sha256":"{sha256}"}}}}}}}}}}"#
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn plan() -> InstallPlan {
+        let formulas = vec![formula("oniguruma", "aaa"), formula("jq", "bbb")];
+        let bottles = formulas
+            .iter()
+            .map(|f| zb_core::select_bottle_with_tag(f, Some("x86_64_linux")).unwrap())
+            .collect();
+        InstallPlan {
+            formulas,
+            bottles,
+            root_name: "jq".to_string(),
+            roots: vec!["jq".to_string()],
+            resolve_time: Duration::ZERO,
+            source_builds: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn plans_roundtrip_through_a_file() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("plan.json");
+        let file = PlanFile::from_plan(&plan());
+        assert_eq!(file.packages[1].version, "1.0_1");
+        assert_eq!(file.packages[1].bottle.sha256, "bbb");
+
+        file.write(&path).unwrap();
+        let read = PlanFile::read(&path).unwrap();
+        assert_eq!(read, file);
+        read.verify().unwrap();
+
+        let (root, formulas, bottles) = read.into_parts();
+        assert_eq!(root, "jq");
+        assert_eq!(formulas, plan().formulas);
+        assert_eq!(bottles, plan().bottles);
+    }
+
+    #[test]
+    fn edited_plans_fail_verification() {
+        let mut file = PlanFile::from_plan(&plan());
+        file.packages[0].bottle.sha256 = "ccc".to_string();
+        let err = file.verify().unwrap_err().to_string();
+        assert!(err.contains("the bottle of oniguruma"), "{err}");
+
+        let mut file = PlanFile::from_plan(&plan());
+        file.packages[1].version = "2.0".to_string();
+        let err = file.verify().unwrap_err().to_string();
+        assert!(err.contains("the version of jq"), "{err}");
+
+        let mut file = PlanFile::from_plan(&plan());
+        file.root = "curl".to_string();
+        assert!(file.verify().is_err());
+    }
+
+    #[test]
+    fn newer_format_versions_are_refused() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("plan.json");
+        let mut file = PlanFile::from_plan(&plan());
+        file.format_version = PLAN_FORMAT_VERSION + 1;
+        file.write(&path).unwrap();
+
+        let err = PlanFile::read(&path).unwrap_err().to_string();
+        assert!(err.contains("format version 2"), "{err}");
+    }
+}
@@ -0,0 +1,214 @@
+//! Rate-limit cool-downs shared by the API client and downloaders.
+//!
+//! When a host answers `429 Too Many Requests`, every request to that host
+//! fails fast with [`Error::RateLimited`] until its `Retry-After` time has
+//! passed, instead of each parallel fetch hitting it again. Cool-downs are
+//! saved to a file so the next `zb` invocation honours them too.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use zb_core::Error;
+
+/// Cool-down used when a 429 response has no usable `Retry-After`
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Longest cool-down honoured, so a bogus header can't lock zb out for days
+pub const MAX_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// Per-host cool-downs, cheap to clone and shared between clients
+#[derive(Clone, Default)]
+pub struct RateLimits {
+    /// Host → unix time (seconds) until which it must not be contacted
+    until: Arc<Mutex<HashMap<String, u64>>>,
+    /// Where cool-downs are persisted, if anywhere
+    path: Option<PathBuf>,
+}
+
+impl RateLimits {
+    /// Cool-downs kept in memory only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cool-downs loaded from and saved to `path`. A missing or unreadable
+    /// file starts empty.
+    pub fn persistent(path: &Path) -> Self {
+        let until = std::fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self {
+            until: Arc::new(Mutex::new(until)),
+            path: Some(path.to_path_buf()),
+        }
+    }
+
+    /// Fail with [`Error::RateLimited`] if `url`'s host is cooling down
+    pub fn check(&self, url: &str) -> Result<(), Error> {
+        let Some(host) = host_of(url) else {
+            return Ok(());
+        };
+        let now = unix_now();
+        let until = self.until.lock().unwrap().get(host).copied();
+        match until {
+            Some(until) if until > now => Err(Error::RateLimited {
+                host: host.to_string(),
+                retry_after: Some(until - now),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Turn a `429 Too Many Requests` response into [`Error::RateLimited`],
+    /// starting the host's cool-down
+    pub fn check_response(&self, url: &str, response: &reqwest::Response) -> Result<(), Error> {
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(());
+        }
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok());
+        Err(self.record(url, retry_after))
+    }
+
+    /// Record a 429 from `url`'s host and return the error to report.
+    /// `retry_after` is the raw `Retry-After` header value, if any.
+    pub fn record(&self, url: &str, retry_after: Option<&str>) -> Error {
+        let host = host_of(url).unwrap_or(url).to_string();
+        let wait = retry_after
+            .and_then(|value| parse_retry_after(value, SystemTime::now()))
+            .unwrap_or(DEFAULT_COOLDOWN)
+            .min(MAX_COOLDOWN);
+        let now = unix_now();
+
+        {
+            let mut until = self.until.lock().unwrap();
+            until.retain(|_, until| *until > now);
+            let entry = until.entry(host.clone()).or_default();
+            *entry = (*entry).max(now + wait.as_secs());
+            if let Some(path) = &self.path {
+                let _ = save(path, &until);
+            }
+        }
+
+        Error::RateLimited {
+            host,
+            retry_after: Some(wait.as_secs()),
+        }
+    }
+}
+
+/// Parse a `Retry-After` value: delay seconds or an HTTP date
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let at = UNIX_EPOCH + Duration::from_secs(u64::try_from(date.timestamp()).ok()?);
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// The host part of an `http(s)://` URL
+fn host_of(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    (!host.is_empty()).then_some(host)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn save(path: &Path, until: &HashMap<String, u64>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(until)?)?;
+    std::fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parses_seconds_and_http_dates() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_445_412_400);
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        // 2015-10-21T07:28:00Z is 80 seconds after `now`
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(80))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn host_of_strips_scheme_path_and_credentials() {
+        assert_eq!(
+            host_of("https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc"),
+            Some("ghcr.io")
+        );
+        assert_eq!(
+            host_of("http://user:pw@127.0.0.1:8080/api?x=1"),
+            Some("127.0.0.1:8080")
+        );
+        assert_eq!(host_of("file:///srv/mirror"), None);
+    }
+
+    #[test]
+    fn cooldown_blocks_host_and_persists() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("cooldowns.json");
+        let limits = RateLimits::persistent(&path);
+
+        let err = limits.record("https://ghcr.io/v2/a", Some("30"));
+        assert_eq!(
+            err,
+            Error::RateLimited {
+                host: "ghcr.io".to_string(),
+                retry_after: Some(30),
+            }
+        );
+        assert!(matches!(
+            limits.check("https://ghcr.io/v2/b"),
+            Err(Error::RateLimited { .. })
+        ));
+        assert!(limits.check("https://formulae.brew.sh/api").is_ok());
+
+        // A later invocation picks the cool-down up from disk
+        let reloaded = RateLimits::persistent(&path);
+        assert!(reloaded.check("https://ghcr.io/v2/c").is_err());
+    }
+
+    #[test]
+    fn cooldown_is_capped() {
+        let limits = RateLimits::new();
+        let err = limits.record("https://ghcr.io/v2/a", Some("999999"));
+        assert_eq!(
+            err,
+            Error::RateLimited {
+                host: "ghcr.io".to_string(),
+                retry_after: Some(MAX_COOLDOWN.as_secs()),
+            }
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Install receipts: how a keg was produced.
+//!
+//! Each install records whether the keg was poured from a bottle or built
+//! from source (stable or HEAD), the build arguments used, and the tap and
+//! tap revision the formula came from. The receipt is kept in the database
+//! and written to the keg's `INSTALL_RECEIPT.json`, using the keys Homebrew
+//! uses so other tools reading the file keep working.
+
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use zb_core::{Error, Formula};
+
+/// File written into every keg
+pub const RECEIPT_FILE: &str = "INSTALL_RECEIPT.json";
+
+/// What a keg was produced from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltFrom {
+    Bottle,
+    Source,
+    Head,
+}
+
+impl BuiltFrom {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BuiltFrom::Bottle => "bottle",
+            BuiltFrom::Source => "source",
+            BuiltFrom::Head => "HEAD",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallReceipt {
+    pub built_from: BuiltFrom,
+    /// Extra arguments passed to the build system
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build_args: Vec<String>,
+    /// Tap the formula came from, e.g. `homebrew/core`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap: Option<String>,
+    /// Commit of the tap the formula metadata was generated from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap_revision: Option<String>,
+}
+
+impl InstallReceipt {
+    /// Receipt for a keg of `formula` poured from a bottle
+    pub fn bottle(formula: &Formula) -> Self {
+        Self {
+            built_from: BuiltFrom::Bottle,
+            build_args: Vec::new(),
+            tap: formula.tap.clone(),
+            tap_revision: formula.tap_git_head.clone(),
+        }
+    }
+
+    /// Receipt for a keg of `formula` built from source
+    pub fn source(formula: &Formula, head: bool, build_args: &[String]) -> Self {
+        Self {
+            built_from: if head {
+                BuiltFrom::Head
+            } else {
+                BuiltFrom::Source
+            },
+            build_args: build_args.to_vec(),
+            tap: formula.tap.clone(),
+            tap_revision: formula.tap_git_head.clone(),
+        }
+    }
+
+    /// Whether upgrading to a bottle would lose how this keg was built
+    pub fn has_build_args(&self) -> bool {
+        self.built_from != BuiltFrom::Bottle && !self.build_args.is_empty()
+    }
+
+    pub(crate) fn to_db(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub(crate) fn from_db(value: &str) -> Option<Self> {
+        serde_json::from_str(value).ok()
+    }
+
+    /// Write the receipt into `keg_path`, keeping any other keys of an
+    /// existing receipt (bottles ship one from Homebrew).
+    ///
+    /// The file is replaced rather than rewritten in place, since keg files
+    /// may be hard links into the store.
+    pub fn write(&self, keg_path: &Path) -> Result<(), Error> {
+        let path = keg_path.join(RECEIPT_FILE);
+        let mut receipt = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Map<String, Value>>(&data).ok())
+            .unwrap_or_default();
+
+        let poured = self.built_from == BuiltFrom::Bottle;
+        receipt.insert("poured_from_bottle".to_string(), json!(poured));
+        receipt.insert("used_options".to_string(), json!(self.build_args));
+        let source = receipt
+            .entry("source")
+            .or_insert_with(|| json!({}))
+            .as_object_mut();
+        if let Some(source) = source {
+            source.insert("tap".to_string(), json!(self.tap));
+            source.insert("tap_git_head".to_string(), json!(self.tap_revision));
+            let spec = if self.built_from == BuiltFrom::Head {
+                "head"
+            } else {
+                "stable"
+            };
+            source.insert("spec".to_string(), json!(spec));
+        }
+
+        let tmp = keg_path.join(format!(".{RECEIPT_FILE}.tmp"));
+        let body = serde_json::to_vec_pretty(&receipt).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to serialize install receipt: {e}"),
+        })?;
+        std::fs::write(&tmp, body)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to write {}: {e}", path.display()),
+            })
+    }
+}
+
+impl fmt::Display for InstallReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.built_from.as_str())?;
+        if !self.build_args.is_empty() {
+            write!(f, " with {}", self.build_args.join(" "))?;
+        }
+        if let Some(tap) = &self.tap {
+            write!(f, " from {tap}")?;
+            if let Some(rev) = &self.tap_revision {
+                write!(f, " @ {}", &rev[..rev.len().min(10)])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn source_receipt() -> InstallReceipt {
+        InstallReceipt {
+            built_from: BuiltFrom::Source,
+            build_args: vec!["--with-oniguruma".to_string()],
+            tap: Some("homebrew/core".to_string()),
+            tap_revision: Some("0123456789abcdef".to_string()),
+        }
+    }
+
+    #[test]
+    fn display_summarizes_origin() {
+        assert_eq!(
+            source_receipt().to_string(),
+            "source with --with-oniguruma from homebrew/core @ 0123456789"
+        );
+        let bottle = InstallReceipt {
+            built_from: BuiltFrom::Bottle,
+            build_args: Vec::new(),
+            tap: None,
+            tap_revision: None,
+        };
+        assert_eq!(bottle.to_string(), "bottle");
+    }
+
+    #[test]
+    fn db_round_trip() {
+        let receipt = source_receipt();
+        assert_eq!(InstallReceipt::from_db(&receipt.to_db()), Some(receipt));
+        assert_eq!(InstallReceipt::from_db("garbage"), None);
+    }
+
+    #[test]
+    fn write_merges_into_existing_receipt_without_touching_links() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("keg");
+        std::fs::create_dir(&keg).unwrap();
+        let original = tmp.path().join("store-receipt.json");
+        std::fs::write(
+            &original,
+            r#"{"homebrew_version": "4.4.0", "source": {"path": "jq.rb"}}"#,
+        )
+        .unwrap();
+        std::fs::hard_link(&original, keg.join(RECEIPT_FILE)).unwrap();
+
+        source_receipt().write(&keg).unwrap();
+
+        let written: Value =
+            serde_json::from_slice(&std::fs::read(keg.join(RECEIPT_FILE)).unwrap()).unwrap();
+        assert_eq!(written["homebrew_version"], "4.4.0");
+        assert_eq!(written["poured_from_bottle"], false);
+        assert_eq!(written["used_options"], json!(["--with-oniguruma"]));
+        assert_eq!(written["source"]["path"], "jq.rb");
+        assert_eq!(written["source"]["tap"], "homebrew/core");
+        assert_eq!(written["source"]["spec"], "stable");
+
+        // The store's copy is left alone
+        let original: Value = serde_json::from_slice(&std::fs::read(&original).unwrap()).unwrap();
+        assert!(original.get("poured_from_bottle").is_none());
+    }
+}
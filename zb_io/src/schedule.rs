@@ -0,0 +1,290 @@
+//! Maintenance windows for scheduled upgrades.
+//!
+//! A window is a set of weekdays and a time of day, in local time:
+//!
+//! ```text
+//! Sat,Sun 02:00-05:00     weekends, early morning
+//! Mon-Fri 22:00-04:00     weeknights, past midnight into the next day
+//! Sun                     all day Sunday
+//! 01:00-03:00             every day
+//! ```
+//!
+//! `zb upgrade --scheduled` only upgrades inside a configured window. With no
+//! windows configured, scheduled upgrades may run at any time.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, Duration, NaiveDateTime, Timelike, Weekday};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Weekdays and a time range during which scheduled upgrades may run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpgradeWindow {
+    /// Bit `n` is set for the nth day of the week, counting from Monday
+    days: u8,
+    /// Minutes after midnight the window opens
+    start: u16,
+    /// Minutes after midnight the window closes; before `start` if it runs
+    /// past midnight
+    end: u16,
+}
+
+/// Whether scheduled upgrades may run now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeWindowStatus {
+    /// No windows are configured
+    Unrestricted,
+    /// Inside a configured window
+    Open,
+    /// Outside every window, with when the next one opens
+    Closed { next: Option<NaiveDateTime> },
+}
+
+impl UpgradeWindowStatus {
+    /// Whether scheduled upgrades have to wait
+    pub fn is_closed(&self) -> bool {
+        matches!(self, UpgradeWindowStatus::Closed { .. })
+    }
+
+    /// When the next window opens, e.g. `Sat 02:00`, while closed
+    pub fn next_opening(&self) -> Option<String> {
+        match self {
+            UpgradeWindowStatus::Closed { next: Some(next) } => {
+                Some(next.format("%a %H:%M").to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl UpgradeWindow {
+    fn has_day(&self, day: Weekday) -> bool {
+        self.days & (1 << day.num_days_from_monday()) != 0
+    }
+
+    /// Whether the window covers local time `now`. A window running past
+    /// midnight belongs to the day it opens on.
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let minute = (now.hour() * 60 + now.minute()) as u16;
+        let day = now.weekday();
+        if self.start < self.end {
+            self.has_day(day) && (self.start..self.end).contains(&minute)
+        } else {
+            (self.has_day(day) && minute >= self.start)
+                || (self.has_day(day.pred()) && minute < self.end)
+        }
+    }
+
+    /// When the window next opens after `now`
+    pub fn next_start(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = now.date().and_hms_opt(0, 0, 0)? + Duration::minutes(self.start.into());
+        (0..=7)
+            .map(|offset| start + Duration::days(offset))
+            .find(|candidate| *candidate > now && self.has_day(candidate.weekday()))
+    }
+}
+
+/// Whether scheduled upgrades may run at local time `now`
+pub fn window_status(windows: &[UpgradeWindow], now: NaiveDateTime) -> UpgradeWindowStatus {
+    if windows.is_empty() {
+        UpgradeWindowStatus::Unrestricted
+    } else if windows.iter().any(|w| w.contains(now)) {
+        UpgradeWindowStatus::Open
+    } else {
+        UpgradeWindowStatus::Closed {
+            next: windows.iter().filter_map(|w| w.next_start(now)).min(),
+        }
+    }
+}
+
+fn parse_day(name: &str) -> Result<u32, String> {
+    name.parse::<Weekday>()
+        .map(|day| day.num_days_from_monday())
+        .map_err(|_| format!("unknown day '{}'", name))
+}
+
+fn parse_days(spec: &str) -> Result<u8, String> {
+    let mut days = 0u8;
+    for item in spec.split(',') {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_day(first)?, parse_day(last)?);
+                let mut day = first;
+                loop {
+                    days |= 1 << day;
+                    if day == last {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days |= 1 << parse_day(item)?,
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time(time: &str) -> Result<u16, String> {
+    let invalid = || format!("invalid time '{}': expected HH:MM", time);
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+    if minutes >= 60 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+impl FromStr for UpgradeWindow {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut window = UpgradeWindow {
+            days: 0b111_1111,
+            start: 0,
+            end: MINUTES_PER_DAY,
+        };
+
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let (days, times) = match parts[..] {
+            [days, times] => (Some(days), Some(times)),
+            [part] if part.contains(':') => (None, Some(part)),
+            [part] => (Some(part), None),
+            _ => {
+                return Err(format!(
+                    "invalid window '{}': expected e.g. 'Sat,Sun 02:00-05:00'",
+                    spec
+                ));
+            }
+        };
+
+        if let Some(days) = days {
+            window.days = parse_days(days)?;
+        }
+        if let Some(times) = times {
+            let (start, end) = times
+                .split_once('-')
+                .ok_or_else(|| format!("invalid time range '{}': expected HH:MM-HH:MM", times))?;
+            window.start = parse_time(start)?;
+            window.end = parse_time(end)?;
+            if window.start == MINUTES_PER_DAY {
+                return Err(format!("invalid time range '{}'", times));
+            }
+            if window.start == window.end {
+                return Err(format!("time range '{}' is empty", times));
+            }
+        }
+
+        Ok(window)
+    }
+}
+
+impl fmt::Display for UpgradeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all_day = self.start == 0 && self.end == MINUTES_PER_DAY;
+        if self.days != 0b111_1111 || all_day {
+            let days: Vec<&str> = (0..7)
+                .filter(|day| self.days & (1 << day) != 0)
+                .map(|day| DAY_NAMES[day])
+                .collect();
+            f.write_str(&days.join(","))?;
+            if all_day {
+                return Ok(());
+            }
+            f.write_str(" ")?;
+        }
+        let time = |minutes: u16| format!("{:02}:{:02}", minutes / 60, minutes % 60);
+        write!(f, "{}-{}", time(self.start), time(self.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// 2024-06-01 was a Saturday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn window(spec: &str) -> UpgradeWindow {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_normalizes_windows() {
+        assert_eq!(
+            window("sat,SUNDAY 2:00-05:00").to_string(),
+            "Sat,Sun 02:00-05:00"
+        );
+        assert_eq!(
+            window("Mon-Fri 22:00-04:00").to_string(),
+            "Mon,Tue,Wed,Thu,Fri 22:00-04:00"
+        );
+        assert_eq!(window("Fri-Mon").to_string(), "Mon,Fri,Sat,Sun");
+        assert_eq!(window("01:00-03:00").to_string(), "01:00-03:00");
+        assert_eq!(window("Sun 00:00-24:00").to_string(), "Sun");
+
+        for bad in [
+            "",
+            "Caturday",
+            "Sat 02:00",
+            "25:00-26:00",
+            "Sat 3:00-3:00",
+            "a b c",
+        ] {
+            assert!(bad.parse::<UpgradeWindow>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn windows_contain_their_days_and_times() {
+        let weekend = window("Sat,Sun 02:00-05:00");
+        assert!(weekend.contains(at(1, 2, 0)));
+        assert!(weekend.contains(at(2, 4, 59)));
+        assert!(!weekend.contains(at(1, 5, 0)));
+        assert!(!weekend.contains(at(3, 3, 0)));
+
+        // Friday night runs into Saturday morning, but Saturday night does not
+        let overnight = window("Fri 22:00-04:00");
+        assert!(overnight.contains(at(1, 3, 0)));
+        assert!(!overnight.contains(at(1, 23, 0)));
+        assert!(!overnight.contains(at(2, 3, 0)));
+    }
+
+    #[test]
+    fn status_reports_the_next_window() {
+        let windows = [window("Sat,Sun 02:00-05:00"), window("Wed 12:00-13:00")];
+
+        assert_eq!(
+            window_status(&[], at(3, 9, 0)),
+            UpgradeWindowStatus::Unrestricted
+        );
+        assert_eq!(
+            window_status(&windows, at(2, 3, 0)),
+            UpgradeWindowStatus::Open
+        );
+        assert_eq!(
+            window_status(&windows, at(3, 9, 0)),
+            UpgradeWindowStatus::Closed {
+                next: Some(at(5, 12, 0))
+            }
+        );
+        let status = window_status(&windows, at(1, 6, 0));
+        assert_eq!(
+            status,
+            UpgradeWindowStatus::Closed {
+                next: Some(at(2, 2, 0))
+            }
+        );
+        assert_eq!(status.next_opening().as_deref(), Some("Sun 02:00"));
+    }
+}
@@ -1,8 +1,13 @@
 //! Search functionality for finding formulas
+//!
+//! [`search`] is the entry point shared by everything that searches: it
+//! scores formulas against a query and applies [`SearchOptions`]. The CLI
+//! goes through [`Installer::search`](crate::install::Installer::search),
+//! which supplies the formula list and installed kegs.
 
 use crate::api::FormulaInfo;
 use rayon::prelude::*;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use std::collections::HashSet;
 
 /// Search result with relevance scoring
@@ -12,16 +17,115 @@ pub struct SearchResult {
     pub full_name: String,
     pub version: String,
     pub description: String,
+    /// Higher is a better match: 100 for an exact name, 50 for a name
+    /// prefix or regex name match, down to 10 for a description match
     pub score: u32,
+    /// Whether the formula is installed
+    pub installed: bool,
+}
+
+/// Which formula fields a search matches against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchScope {
+    /// Name, full name, aliases and description
+    #[default]
+    All,
+    /// Only the tap-qualified full name
+    FullName,
+    /// Only aliases
+    Alias,
+}
+
+/// Filters applied to a search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Fields the query is matched against
+    pub scope: SearchScope,
+    /// Also match descriptions, when `scope` is [`SearchScope::All`]
+    pub descriptions: bool,
+    /// Only return installed formulas
+    pub installed_only: bool,
+    /// Only return formulas from this tap, e.g. `user/repo` or `homebrew/core`
+    pub tap: Option<String>,
+    /// Return at most this many of the best results
+    pub limit: Option<usize>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            scope: SearchScope::All,
+            descriptions: true,
+            installed_only: false,
+            tap: None,
+            limit: None,
+        }
+    }
+}
+
+/// Search `formulas` for `query`, best match first
+///
+/// Accepts plain text or a `/regex/`, like [`search_formulas`]. Results
+/// are marked installed when their name is in `installed`, and filtered
+/// and truncated according to `options`.
+pub fn search(
+    formulas: &[FormulaInfo],
+    query: &str,
+    options: &SearchOptions,
+    installed: &HashSet<String>,
+) -> Vec<SearchResult> {
+    let tap = options.tap.as_deref().map(|tap| {
+        let (user, repo) = tap.split_once('/').unwrap_or((tap, ""));
+        format!(
+            "{}/{}",
+            user,
+            repo.strip_prefix("homebrew-").unwrap_or(repo)
+        )
+    });
+    let formulas: Vec<FormulaInfo> = formulas
+        .iter()
+        .filter(|f| tap.as_deref().is_none_or(|tap| formula_tap(f) == tap))
+        .filter(|f| !options.installed_only || installed.contains(&f.name))
+        .cloned()
+        .collect();
+
+    let mut results = search_impl(&formulas, query, options.scope, options.descriptions, None);
+    for result in &mut results {
+        result.installed = installed.contains(&result.name);
+    }
+    if let Some(limit) = options.limit {
+        results.truncate(limit);
+    }
+    results
+}
+
+/// The tap a formula comes from, `homebrew/core` unless its full name is
+/// qualified with another
+fn formula_tap(f: &FormulaInfo) -> &str {
+    match f.full_name.rsplit_once('/') {
+        Some((tap, _)) if tap.contains('/') => tap,
+        _ => "homebrew/core",
+    }
 }
 
 /// Search formulas by query string
 ///
 /// Supports:
-/// - Plain text search (matches name and description)
+/// - Plain text search (matches name, full name, aliases and description)
 /// - Regex search when query is wrapped in /slashes/
+///
+/// Both forms are case-insensitive and Unicode-aware.
 pub fn search_formulas(formulas: &[FormulaInfo], query: &str) -> Vec<SearchResult> {
-    search_formulas_with_candidates(formulas, query, None)
+    search_formulas_scoped(formulas, query, SearchScope::All)
+}
+
+/// Search formulas, restricting matches to the fields selected by `scope`
+pub fn search_formulas_scoped(
+    formulas: &[FormulaInfo],
+    query: &str,
+    scope: SearchScope,
+) -> Vec<SearchResult> {
+    search_impl(formulas, query, scope, true, None)
 }
 
 /// Search formulas with optional FTS candidate filtering
@@ -33,6 +137,16 @@ pub fn search_formulas_with_candidates(
     formulas: &[FormulaInfo],
     query: &str,
     fts_candidates: Option<&HashSet<String>>,
+) -> Vec<SearchResult> {
+    search_impl(formulas, query, SearchScope::All, true, fts_candidates)
+}
+
+fn search_impl(
+    formulas: &[FormulaInfo],
+    query: &str,
+    scope: SearchScope,
+    descriptions: bool,
+    fts_candidates: Option<&HashSet<String>>,
 ) -> Vec<SearchResult> {
     let query = query.trim();
 
@@ -41,15 +155,19 @@ pub fn search_formulas_with_candidates(
 
     let results: Vec<SearchResult> = if is_regex {
         let pattern = &query[1..query.len() - 1];
-        match Regex::new(pattern) {
-            Ok(re) => search_by_regex(formulas, &re),
+        match RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .unicode(true)
+            .build()
+        {
+            Ok(re) => search_by_regex(formulas, &re, scope, descriptions),
             Err(_) => {
                 // Invalid regex, fall back to literal search
-                search_by_text_with_candidates(formulas, query, fts_candidates)
+                search_by_text_with_candidates(formulas, query, scope, descriptions, fts_candidates)
             }
         }
     } else {
-        search_by_text_with_candidates(formulas, query, fts_candidates)
+        search_by_text_with_candidates(formulas, query, scope, descriptions, fts_candidates)
     };
 
     // Sort by score (descending), then by name (ascending)
@@ -59,96 +177,146 @@ pub fn search_formulas_with_candidates(
     sorted
 }
 
+/// Case-fold a string for comparison.
+///
+/// Lowercases per character rather than with `str::to_lowercase`, which
+/// applies context-sensitive rules (e.g. Greek final sigma) that would make
+/// a query and a field fold differently.
+fn fold_case(s: &str) -> String {
+    s.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c == 'ς' { 'σ' } else { c })
+        .collect()
+}
+
+/// Score a single field: exact match, prefix, or substring
+fn field_score(field: &str, query: &str) -> u32 {
+    let field = fold_case(field);
+    if field == query {
+        100
+    } else if field.starts_with(query) {
+        50
+    } else if field.contains(query) {
+        25
+    } else {
+        0
+    }
+}
+
 fn search_by_text_with_candidates(
     formulas: &[FormulaInfo],
     query: &str,
+    scope: SearchScope,
+    descriptions: bool,
     fts_candidates: Option<&HashSet<String>>,
 ) -> Vec<SearchResult> {
-    let query_lower = query.to_lowercase();
+    let query = fold_case(query);
 
     formulas
         .par_iter()
         .filter(|f| !f.deprecated && !f.disabled)
         // If FTS candidates provided, filter to only those names
-        .filter(|f| fts_candidates.map_or(true, |c| c.contains(&f.name)))
+        .filter(|f| fts_candidates.is_none_or(|c| c.contains(&f.name)))
         .filter_map(|f| {
-            let name_lower = f.name.to_lowercase();
-            let desc_lower = f.desc.as_deref().unwrap_or("").to_lowercase();
-
-            let mut score = 0u32;
-
-            // Exact name match
-            if name_lower == query_lower {
-                score += 100;
-            }
-            // Name starts with query
-            else if name_lower.starts_with(&query_lower) {
-                score += 50;
-            }
-            // Name contains query
-            else if name_lower.contains(&query_lower) {
-                score += 25;
-            }
-            // Description contains query
-            else if desc_lower.contains(&query_lower) {
-                score += 10;
-            }
-            // Check aliases
-            else if f
-                .aliases
-                .iter()
-                .any(|a| a.to_lowercase().contains(&query_lower))
-            {
-                score += 15;
-            }
-
-            if score > 0 {
-                Some(SearchResult {
-                    name: f.name.clone(),
-                    full_name: f.full_name.clone(),
-                    version: f
-                        .versions
-                        .stable
-                        .clone()
-                        .unwrap_or_else(|| "HEAD".to_string()),
-                    description: f.desc.clone().unwrap_or_default(),
-                    score,
-                })
-            } else {
-                None
-            }
+            let score = match scope {
+                SearchScope::FullName => field_score(&f.full_name, &query),
+                SearchScope::Alias => f
+                    .aliases
+                    .iter()
+                    .map(|a| field_score(a, &query))
+                    .max()
+                    .unwrap_or(0),
+                SearchScope::All => {
+                    let name_score = field_score(&f.name, &query);
+                    if name_score > 0 {
+                        name_score
+                    }
+                    // Tap-qualified name contains query
+                    else if fold_case(&f.full_name).contains(&query) {
+                        20
+                    }
+                    // Check aliases
+                    else if f.aliases.iter().any(|a| fold_case(a).contains(&query)) {
+                        15
+                    }
+                    // Description contains query
+                    else if descriptions
+                        && fold_case(f.desc.as_deref().unwrap_or("")).contains(&query)
+                    {
+                        10
+                    } else {
+                        0
+                    }
+                }
+            };
+
+            (score > 0).then(|| search_result(f, score))
         })
         .collect()
 }
 
-fn search_by_regex(formulas: &[FormulaInfo], re: &Regex) -> Vec<SearchResult> {
+fn search_by_regex(
+    formulas: &[FormulaInfo],
+    re: &Regex,
+    scope: SearchScope,
+    descriptions: bool,
+) -> Vec<SearchResult> {
     formulas
         .par_iter()
         .filter(|f| !f.deprecated && !f.disabled)
         .filter_map(|f| {
-            let name_matches = re.is_match(&f.name);
-            let desc_matches = f.desc.as_ref().map(|d| re.is_match(d)).unwrap_or(false);
-
-            if name_matches || desc_matches {
-                let score = if name_matches { 50 } else { 10 };
-                Some(SearchResult {
-                    name: f.name.clone(),
-                    full_name: f.full_name.clone(),
-                    version: f
-                        .versions
-                        .stable
-                        .clone()
-                        .unwrap_or_else(|| "HEAD".to_string()),
-                    description: f.desc.clone().unwrap_or_default(),
-                    score,
-                })
-            } else {
-                None
-            }
+            let alias_matches = || f.aliases.iter().any(|a| re.is_match(a));
+
+            let score = match scope {
+                SearchScope::FullName => {
+                    if re.is_match(&f.full_name) {
+                        50
+                    } else {
+                        0
+                    }
+                }
+                SearchScope::Alias => {
+                    if alias_matches() {
+                        50
+                    } else {
+                        0
+                    }
+                }
+                SearchScope::All => {
+                    if re.is_match(&f.name) {
+                        50
+                    } else if re.is_match(&f.full_name) {
+                        20
+                    } else if alias_matches() {
+                        15
+                    } else if descriptions && f.desc.as_ref().is_some_and(|d| re.is_match(d)) {
+                        10
+                    } else {
+                        0
+                    }
+                }
+            };
+
+            (score > 0).then(|| search_result(f, score))
         })
         .collect()
 }
 
+fn search_result(f: &FormulaInfo, score: u32) -> SearchResult {
+    SearchResult {
+        name: f.name.clone(),
+        full_name: f.full_name.clone(),
+        version: f
+            .versions
+            .stable
+            .clone()
+            .unwrap_or_else(|| "HEAD".to_string()),
+        description: f.desc.clone().unwrap_or_default(),
+        score,
+        installed: false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -166,6 +334,7 @@ mod tests {
             aliases: vec![],
             deprecated: false,
             disabled: false,
+            dependencies: vec![],
         }
     }
 
@@ -246,4 +415,121 @@ mod tests {
         // Should not panic, just return empty or fall back
         assert!(results.is_empty() || !results.is_empty());
     }
+
+    #[test]
+    fn text_search_is_case_insensitive_across_fields() {
+        let mut formula = make_formula("ripgrep", "Search tool like grep");
+        formula.full_name = "BurntSushi/tap/ripgrep".to_string();
+        formula.aliases = vec!["RG".to_string()];
+        let formulas = vec![formula];
+
+        assert_eq!(search_formulas(&formulas, "RIPGREP")[0].score, 100);
+        assert_eq!(search_formulas(&formulas, "burntsushi")[0].score, 20);
+        assert_eq!(search_formulas(&formulas, "rg")[0].score, 15);
+        assert_eq!(search_formulas(&formulas, "GREP")[0].score, 25);
+        assert_eq!(search_formulas(&formulas, "TOOL")[0].score, 10);
+    }
+
+    #[test]
+    fn text_search_folds_unicode() {
+        let formulas = vec![make_formula("odos", "ΟΔΟΣ path utility")];
+
+        assert_eq!(search_formulas(&formulas, "οδοσ").len(), 1);
+        assert_eq!(search_formulas(&formulas, "ΟΔΟΣ").len(), 1);
+        assert_eq!(search_formulas(&formulas, "/οδοσ/").len(), 1);
+    }
+
+    #[test]
+    fn regex_search_matches_full_name_and_aliases() {
+        let mut tapped = make_formula("foo", "Something");
+        tapped.full_name = "acme/tools/foo".to_string();
+        let mut aliased = make_formula("bar", "Something else");
+        aliased.aliases = vec!["baz-cli".to_string()];
+        let formulas = vec![tapped, aliased];
+
+        let results = search_formulas(&formulas, "/^acme//");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "foo");
+
+        let results = search_formulas(&formulas, "/BAZ-.*/");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "bar");
+    }
+
+    #[test]
+    fn search_applies_options() {
+        let mut tapped = make_formula("jq-extra", "More jq filters");
+        tapped.full_name = "acme/tools/jq-extra".to_string();
+        let formulas = vec![
+            make_formula("jq", "Lightweight JSON processor"),
+            make_formula("gojq", "Pure Go jq"),
+            make_formula("yq", "Process YAML like jq"),
+            tapped,
+        ];
+        let installed = HashSet::from(["yq".to_string()]);
+        let names = |options: &SearchOptions| -> Vec<String> {
+            search(&formulas, "jq", options, &installed)
+                .into_iter()
+                .map(|r| r.name)
+                .collect()
+        };
+
+        assert_eq!(
+            names(&SearchOptions::default()),
+            ["jq", "jq-extra", "gojq", "yq"]
+        );
+        assert_eq!(
+            names(&SearchOptions {
+                descriptions: false,
+                ..Default::default()
+            }),
+            ["jq", "jq-extra", "gojq"]
+        );
+        assert_eq!(
+            names(&SearchOptions {
+                installed_only: true,
+                ..Default::default()
+            }),
+            ["yq"]
+        );
+        assert_eq!(
+            names(&SearchOptions {
+                tap: Some("acme/homebrew-tools".to_string()),
+                ..Default::default()
+            }),
+            ["jq-extra"]
+        );
+        assert_eq!(
+            names(&SearchOptions {
+                tap: Some("homebrew/core".to_string()),
+                limit: Some(2),
+                ..Default::default()
+            }),
+            ["jq", "gojq"]
+        );
+
+        let results = search(&formulas, "yq", &SearchOptions::default(), &installed);
+        assert!(results[0].installed);
+    }
+
+    #[test]
+    fn scoped_search_only_checks_selected_field() {
+        let mut tapped = make_formula("foo", "foo utility");
+        tapped.full_name = "acme/tools/foo".to_string();
+        let mut aliased = make_formula("bar", "acme helper");
+        aliased.aliases = vec!["foo-compat".to_string()];
+        let formulas = vec![tapped, aliased];
+
+        let results = search_formulas_scoped(&formulas, "acme", SearchScope::FullName);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "foo");
+
+        let results = search_formulas_scoped(&formulas, "foo", SearchScope::Alias);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "bar");
+
+        let results = search_formulas_scoped(&formulas, "/^ACME/", SearchScope::FullName);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "foo");
+    }
 }
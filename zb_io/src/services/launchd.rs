@@ -0,0 +1,372 @@
+//! launchd agents, managed with `launchctl`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::Error;
+
+use super::{
+    RestartHistory, ServiceBackend, ServiceConfig, ServiceSpec, ServiceStatus,
+    parse_launchctl_print, parse_launchd_plist,
+};
+
+/// Services run as launchd agents in the user's GUI domain
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Launchd;
+
+impl Launchd {
+    /// Run `launchctl` with `args`, failing with `action` in the message
+    /// unless it succeeds or its error output contains one of `ignored`
+    fn launchctl(&self, args: &[&str], action: &str, ignored: &[&str]) -> Result<(), Error> {
+        let output =
+            Command::new("launchctl")
+                .args(args)
+                .output()
+                .map_err(|e| Error::StoreCorruption {
+                    message: format!("failed to {}: {}", action, e),
+                })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !ignored.iter().any(|msg| stderr.contains(msg)) {
+                return Err(Error::StoreCorruption {
+                    message: format!("failed to {}: {}", action, stderr),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `gui/<uid>/<label>`, the target `launchctl` addresses the agent by
+    fn target(&self, formula: &str) -> String {
+        format!("gui/{}/{}", unsafe { libc::getuid() }, self.label(formula))
+    }
+}
+
+/// Errors from `launchctl load` for agents that are already loaded
+const ALREADY_LOADED: &[&str] = &["already loaded"];
+
+/// Errors from `launchctl unload` for agents that are not loaded
+const NOT_LOADED: &[&str] = &["Could not find", "not loaded"];
+
+impl ServiceBackend for Launchd {
+    fn name(&self) -> &'static str {
+        "launchd"
+    }
+
+    fn default_paths(&self, home: &Path) -> (PathBuf, PathBuf) {
+        (
+            home.join("Library/LaunchAgents"),
+            home.join("Library/Logs/zerobrew"),
+        )
+    }
+
+    fn file_name(&self, formula: &str) -> String {
+        format!("com.zerobrew.{}.plist", formula)
+    }
+
+    fn formula_from_file_name<'a>(&self, file_name: &'a str) -> Option<&'a str> {
+        // com.zerobrew.formula.plist -> formula
+        file_name
+            .strip_prefix("com.zerobrew.")
+            .and_then(|s| s.strip_suffix(".plist"))
+    }
+
+    fn label(&self, formula: &str) -> String {
+        format!("com.zerobrew.{}", formula)
+    }
+
+    fn foreign_file_name(&self, formula: &str) -> String {
+        format!("homebrew.mxcl.{}.plist", formula)
+    }
+
+    fn render(
+        &self,
+        formula: &str,
+        config: &ServiceConfig,
+        (stdout_log, stderr_log): (&Path, &Path),
+    ) -> String {
+        let label = self.label(formula);
+
+        let mut plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{program}</string>"#,
+            label = label,
+            program = config.program.display(),
+        );
+
+        // Add arguments
+        for arg in &config.args {
+            plist.push_str(&format!("\n        <string>{}</string>", arg));
+        }
+        plist.push_str("\n    </array>\n");
+
+        // Working directory
+        if let Some(ref wd) = config.working_directory {
+            plist.push_str(&format!(
+                "    <key>WorkingDirectory</key>\n    <string>{}</string>\n",
+                wd.display()
+            ));
+        }
+
+        // Environment variables
+        if !config.environment.is_empty() {
+            plist.push_str("    <key>EnvironmentVariables</key>\n    <dict>\n");
+            for (key, value) in &config.environment {
+                plist.push_str(&format!(
+                    "        <key>{}</key>\n        <string>{}</string>\n",
+                    key, value
+                ));
+            }
+            plist.push_str("    </dict>\n");
+        }
+
+        // Run at load
+        plist.push_str(&format!(
+            "    <key>RunAtLoad</key>\n    <{}/>\\n",
+            if config.run_at_load { "true" } else { "false" }
+        ));
+
+        // Keep alive
+        if config.keep_alive {
+            plist.push_str("    <key>KeepAlive</key>\n    <true/>\n");
+        }
+
+        // Minimum seconds between launches; launchd has no exponential backoff
+        if let Some(delay) = config.settings.restart_delay {
+            plist.push_str(&format!(
+                "    <key>ThrottleInterval</key>\n    <integer>{}</integer>\n",
+                delay
+            ));
+        }
+
+        // Run-as user, priorities and limits
+        plist.push_str(&config.settings.launchd_keys());
+
+        // Logging
+        plist.push_str(&format!(
+            "    <key>StandardOutPath</key>\n    <string>{}</string>\n",
+            stdout_log.display()
+        ));
+        plist.push_str(&format!(
+            "    <key>StandardErrorPath</key>\n    <string>{}</string>\n",
+            stderr_log.display()
+        ));
+
+        plist.push_str("</dict>\n</plist>\n");
+
+        plist
+    }
+
+    fn read_spec(&self, file: &Path) -> Option<ServiceSpec> {
+        let content = std::fs::read_to_string(file).ok()?;
+        Some(parse_launchd_plist(&content))
+    }
+
+    fn keg_service_file(&self, keg_path: &Path, formula: &str) -> PathBuf {
+        keg_path
+            .join("homebrew.mxcl.")
+            .join(format!("{}.plist", formula))
+    }
+
+    fn parse_keg_service(&self, path: &Path) -> Option<ServiceConfig> {
+        let content = std::fs::read_to_string(path).ok()?;
+
+        // Simple XML parsing to extract program arguments
+        let mut config = ServiceConfig::default();
+
+        // Find ProgramArguments
+        if let Some(start) = content.find("<key>ProgramArguments</key>")
+            && let Some(array_start) = content[start..].find("<array>")
+        {
+            let array_content = &content[start + array_start..];
+            if let Some(array_end) = array_content.find("</array>") {
+                let array = &array_content[7..array_end];
+                let args: Vec<String> = array
+                    .lines()
+                    .filter_map(|line| line.trim().strip_prefix("<string>"))
+                    .filter_map(|s| s.strip_suffix("</string>"))
+                    .map(str::to_string)
+                    .collect();
+                if !args.is_empty() {
+                    config.program = PathBuf::from(&args[0]);
+                    config.args = args[1..].to_vec();
+                }
+            }
+        }
+
+        if config.program.as_os_str().is_empty() {
+            return None;
+        }
+
+        // Check for RunAtLoad
+        config.run_at_load =
+            content.contains("<key>RunAtLoad</key>") && content.contains("<true/>");
+
+        // Check for KeepAlive
+        config.keep_alive = content.contains("<key>KeepAlive</key>") && content.contains("<true/>");
+
+        Some(config)
+    }
+
+    fn status(&self, formula: &str) -> ServiceStatus {
+        let label = self.label(formula);
+
+        let Ok(out) = Command::new("launchctl").args(["list"]).output() else {
+            return ServiceStatus::Unknown;
+        };
+
+        // Parse launchctl list output to find our service
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        for line in stdout.lines().filter(|line| line.contains(&label)) {
+            // First column is PID (- if not running), second is exit status
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if let [pid, exit_status, ..] = parts[..] {
+                return match (pid, exit_status) {
+                    ("-", "0") => ServiceStatus::Stopped,
+                    ("-", status) => ServiceStatus::Error(format!("exited with status {}", status)),
+                    _ => ServiceStatus::Running,
+                };
+            }
+        }
+        ServiceStatus::Stopped
+    }
+
+    fn pid(&self, formula: &str) -> Option<u32> {
+        let label = self.label(formula);
+        let out = Command::new("launchctl")
+            .args(["list", &label])
+            .output()
+            .ok()
+            .filter(|out| out.status.success())?;
+
+        // First line has: PID Status Label
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let first_line = stdout.lines().nth(1)?;
+        first_line
+            .split_whitespace()
+            .next()
+            .filter(|pid| *pid != "-")
+            .and_then(|pid| pid.parse::<u32>().ok())
+    }
+
+    fn restart_history(&self, formula: &str) -> RestartHistory {
+        Command::new("launchctl")
+            .args(["print", &self.target(formula)])
+            .output()
+            .map(|out| parse_launchctl_print(&String::from_utf8_lossy(&out.stdout)))
+            .unwrap_or_default()
+    }
+
+    fn is_enabled(&self, _formula: &str, file: &Path) -> bool {
+        // If the plist exists and is loaded, it's auto-start
+        file.exists()
+    }
+
+    fn start(&self, formula: &str, file: &Path) -> Result<(), Error> {
+        // Load the service if not already loaded
+        self.launchctl(
+            &["load", "-w", &file.to_string_lossy()],
+            "start service",
+            ALREADY_LOADED,
+        )?;
+
+        // Start the service
+        let _ = Command::new("launchctl")
+            .args(["kickstart", "-k", &self.target(formula)])
+            .output();
+
+        Ok(())
+    }
+
+    fn stop(&self, _formula: &str, file: &Path) -> Result<(), Error> {
+        self.launchctl(
+            &["unload", &file.to_string_lossy()],
+            "stop service",
+            NOT_LOADED,
+        )
+    }
+
+    fn enable(&self, _formula: &str, file: &Path) -> Result<(), Error> {
+        // Loading with -w enables auto-start
+        self.launchctl(
+            &["load", "-w", &file.to_string_lossy()],
+            "enable service",
+            ALREADY_LOADED,
+        )
+    }
+
+    fn disable(&self, _formula: &str, file: &Path) -> Result<(), Error> {
+        self.launchctl(
+            &["unload", "-w", &file.to_string_lossy()],
+            "disable service",
+            NOT_LOADED,
+        )
+    }
+
+    fn reload(&self) -> Result<(), Error> {
+        // launchd reads plists when they are loaded
+        Ok(())
+    }
+
+    fn stop_foreign(&self, _formula: &str, file: &Path) {
+        let _ = self.launchctl(
+            &["unload", "-w", &file.to_string_lossy()],
+            "stop Homebrew service",
+            NOT_LOADED,
+        );
+    }
+
+    fn journal(&self, _formula: &str, _lines: usize, _follow: bool) -> Option<Command> {
+        // launchd only writes to the StandardOutPath/StandardErrorPath files
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_plist_readable_on_any_platform() {
+        let config = ServiceConfig {
+            program: PathBuf::from("/opt/zerobrew/opt/redis/bin/redis-server"),
+            args: vec!["/opt/zerobrew/etc/redis.conf".to_string()],
+            ..Default::default()
+        };
+        let plist = Launchd.render(
+            "redis",
+            &config,
+            (
+                Path::new("/logs/redis.log"),
+                Path::new("/logs/redis.error.log"),
+            ),
+        );
+
+        assert!(plist.contains("<string>com.zerobrew.redis</string>"));
+        let spec = parse_launchd_plist(&plist);
+        assert_eq!(
+            spec.command,
+            [
+                "/opt/zerobrew/opt/redis/bin/redis-server",
+                "/opt/zerobrew/etc/redis.conf"
+            ]
+        );
+        assert_eq!(
+            spec.stderr_log,
+            Some(PathBuf::from("/logs/redis.error.log"))
+        );
+        assert_eq!(
+            Launchd.formula_from_file_name("com.zerobrew.redis.plist"),
+            Some("redis")
+        );
+    }
+}
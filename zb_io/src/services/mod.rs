@@ -6,13 +6,346 @@
 //! - macOS: launchd LaunchAgents
 //!
 //! Services are managed using the native service management system on each platform.
+//! Each init system is a [`ServiceBackend`] ([`Systemd`] or [`Launchd`]),
+//! picked at runtime by [`detect_backend`].
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
 use zb_core::Error;
 
+mod launchd;
+mod systemd;
+
+pub use launchd::Launchd;
+pub use systemd::Systemd;
+
+/// Default seconds between restarts of a failed service
+pub const DEFAULT_RESTART_DELAY_SECS: u64 = 3;
+
+/// Number of steps systemd takes to grow the restart delay to its maximum
+const RESTART_BACKOFF_STEPS: u32 = 5;
+
+/// Failed exits within [`CRASH_LOOP_WINDOW_SECS`] that count as a crash loop
+pub const CRASH_LOOP_THRESHOLD: usize = 3;
+
+/// Window in seconds in which repeated failures count as a crash loop
+pub const CRASH_LOOP_WINDOW_SECS: i64 = 600;
+
+/// Maximum number of exits kept in a [`RestartHistory`]
+const MAX_TRACKED_EXITS: usize = 20;
+
+/// A single exit of a service's main process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceExit {
+    /// Exit code, or 128 + signal number if the process was killed
+    pub code: i32,
+    /// When the process exited, if the service manager logged it
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// Restart count and recent exits of a service, as reported by the
+/// platform's service manager
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestartHistory {
+    /// Automatic restarts since the service was loaded
+    pub restarts: u32,
+    /// Recent exits, oldest first
+    pub exits: Vec<ServiceExit>,
+}
+
+impl RestartHistory {
+    /// Exit codes of the last `limit` exits, oldest first
+    pub fn recent_exit_codes(&self, limit: usize) -> Vec<i32> {
+        let skip = self.exits.len().saturating_sub(limit);
+        self.exits[skip..].iter().map(|exit| exit.code).collect()
+    }
+
+    /// [`Self::is_crash_looping`] as of now
+    pub fn is_crash_looping_now(&self) -> bool {
+        self.is_crash_looping(Utc::now())
+    }
+
+    /// True if the service keeps failing and being restarted.
+    ///
+    /// With timestamps, this needs [`CRASH_LOOP_THRESHOLD`] failed exits
+    /// within [`CRASH_LOOP_WINDOW_SECS`] of `now`. launchd doesn't log exit
+    /// times, so there it needs that many restarts and a failed last exit.
+    pub fn is_crash_looping(&self, now: DateTime<Utc>) -> bool {
+        if self.restarts < CRASH_LOOP_THRESHOLD as u32 {
+            return false;
+        }
+        let Some(last) = self.exits.last() else {
+            return false;
+        };
+        if last.code == 0 {
+            return false;
+        }
+        if self.exits.iter().all(|exit| exit.at.is_none()) {
+            return true;
+        }
+
+        let window_start = now - chrono::Duration::seconds(CRASH_LOOP_WINDOW_SECS);
+        self.exits
+            .iter()
+            .filter(|exit| exit.code != 0 && exit.at.is_some_and(|at| at >= window_start))
+            .count()
+            >= CRASH_LOOP_THRESHOLD
+    }
+}
+
+/// Parse `systemctl show --property=NRestarts` output.
+pub fn parse_systemd_restarts(output: &str) -> u32 {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("NRestarts="))
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Parse main-process exits from `journalctl -o short-iso` output.
+///
+/// Lines look like
+/// `2024-05-01T12:00:00+0000 host systemd[1]: zerobrew.redis.service: Main
+/// process exited, code=exited, status=1/FAILURE`.
+pub fn parse_systemd_exits(journal: &str) -> Vec<ServiceExit> {
+    let mut exits: Vec<ServiceExit> = journal
+        .lines()
+        .filter(|line| line.contains("Main process exited"))
+        .filter_map(|line| {
+            let status = line.split("status=").nth(1)?;
+            let number: i32 = status
+                .split(|c: char| !c.is_ascii_digit())
+                .next()?
+                .parse()
+                .ok()?;
+            let code = if line.contains("code=exited") {
+                number
+            } else {
+                128 + number
+            };
+            let at = line
+                .split_whitespace()
+                .next()
+                .and_then(|ts| DateTime::parse_from_str(ts, "%Y-%m-%dT%H:%M:%S%z").ok())
+                .map(|ts| ts.with_timezone(&Utc));
+            Some(ServiceExit { code, at })
+        })
+        .collect();
+
+    let skip = exits.len().saturating_sub(MAX_TRACKED_EXITS);
+    exits.drain(..skip);
+    exits
+}
+
+/// Parse `launchctl print` output for a job.
+///
+/// launchd reports `runs = N`, which counts the first launch, and only the
+/// last exit (`last exit code = N` or `last terminating signal = Name: N`).
+pub fn parse_launchctl_print(output: &str) -> RestartHistory {
+    let mut history = RestartHistory::default();
+    for line in output.lines().map(str::trim) {
+        if let Some(runs) = line.strip_prefix("runs = ")
+            && let Ok(runs) = runs.parse::<u32>()
+        {
+            history.restarts = runs.saturating_sub(1);
+        } else if let Some(code) = line.strip_prefix("last exit code = ")
+            && let Ok(code) = code.parse::<i32>()
+        {
+            history.exits = vec![ServiceExit { code, at: None }];
+        } else if let Some(signal) = line.strip_prefix("last terminating signal = ")
+            && let Some(number) = signal.rsplit(": ").next()
+            && let Ok(number) = number.parse::<i32>()
+        {
+            history.exits = vec![ServiceExit {
+                code: 128 + number,
+                at: None,
+            }];
+        }
+    }
+    history
+}
+
+/// What an installed service file runs, read back from the file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceSpec {
+    /// Program followed by its arguments
+    pub command: Vec<String>,
+    /// Working directory
+    pub working_directory: Option<PathBuf>,
+    /// Environment variables set for the service
+    pub environment: BTreeMap<String, String>,
+    /// Log file for stdout
+    pub stdout_log: Option<PathBuf>,
+    /// Log file for stderr
+    pub stderr_log: Option<PathBuf>,
+    /// When the service is run, for services started on a schedule rather
+    /// than kept running, e.g. `daily` or `every 3600s`
+    pub schedule: Option<String>,
+}
+
+/// Parse a systemd unit written by [`ServiceManager::create_service`],
+/// and the matching `.timer` unit if the service runs on a schedule.
+pub fn parse_systemd_unit(unit: &str, timer: Option<&str>) -> ServiceSpec {
+    let mut spec = ServiceSpec::default();
+
+    for line in unit.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("ExecStart=") {
+            spec.command = value.split_whitespace().map(str::to_string).collect();
+        } else if let Some(value) = line.strip_prefix("WorkingDirectory=") {
+            spec.working_directory = Some(PathBuf::from(value));
+        } else if let Some(value) = line.strip_prefix("Environment=") {
+            let value = value.trim_matches('"');
+            if let Some((key, value)) = value.split_once('=') {
+                spec.environment.insert(key.to_string(), value.to_string());
+            }
+        } else if let Some(value) = line.strip_prefix("StandardOutput=append:") {
+            spec.stdout_log = Some(PathBuf::from(value));
+        } else if let Some(value) = line.strip_prefix("StandardError=append:") {
+            spec.stderr_log = Some(PathBuf::from(value));
+        }
+    }
+
+    spec.schedule = timer.and_then(|timer| {
+        timer.lines().map(str::trim).find_map(|line| {
+            if let Some(calendar) = line.strip_prefix("OnCalendar=") {
+                Some(calendar.to_string())
+            } else {
+                line.strip_prefix("OnUnitActiveSec=")
+                    .map(|interval| format!("every {interval}"))
+            }
+        })
+    });
+
+    spec
+}
+
+/// Parse a launchd plist written by [`ServiceManager::create_service`].
+pub fn parse_launchd_plist(plist: &str) -> ServiceSpec {
+    let pair =
+        regex::Regex::new(r"<key>([^<]*)</key>\s*<(?:string|integer)>([^<]*)</(?:string|integer)>")
+            .unwrap();
+    let pairs = |section: &str| -> BTreeMap<String, String> {
+        pair.captures_iter(section)
+            .map(|c| (c[1].to_string(), c[2].to_string()))
+            .collect()
+    };
+
+    let mut spec = ServiceSpec::default();
+    if let Some(args) = plist_section(plist, "ProgramArguments", "<array>", "</array>") {
+        spec.command = args
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("<string>"))
+            .filter_map(|line| line.strip_suffix("</string>"))
+            .map(str::to_string)
+            .collect();
+    }
+    if let Some(env) = plist_section(plist, "EnvironmentVariables", "<dict>", "</dict>") {
+        spec.environment = pairs(env);
+    }
+
+    // Top-level values; nested dicts are parsed above
+    let top = pairs(plist);
+    spec.working_directory = top.get("WorkingDirectory").map(PathBuf::from);
+    spec.stdout_log = top.get("StandardOutPath").map(PathBuf::from);
+    spec.stderr_log = top.get("StandardErrorPath").map(PathBuf::from);
+    spec.schedule = if let Some(interval) = top.get("StartInterval") {
+        Some(format!("every {interval}s"))
+    } else {
+        plist_section(plist, "StartCalendarInterval", "<dict>", "</dict>").map(|calendar| {
+            pairs(calendar)
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    };
+
+    spec
+}
+
+/// The contents of the `open`..`close` element following `<key>{key}</key>`
+fn plist_section<'a>(plist: &'a str, key: &str, open: &str, close: &str) -> Option<&'a str> {
+    let key = format!("<key>{key}</key>");
+    let rest = plist[plist.find(&key)? + key.len()..].trim_start();
+    let inner = rest.strip_prefix(open)?;
+    Some(&inner[..inner.find(close)?])
+}
+
+/// Prefixes Homebrew installs into, in the order they are checked
+const HOMEBREW_PREFIXES: &[&str] = &["/opt/homebrew", "/home/linuxbrew/.linuxbrew", "/usr/local"];
+
+/// A service set up by `brew services` rather than by zerobrew
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignService {
+    /// Formula name
+    pub name: String,
+    /// Path to the service file
+    pub file_path: PathBuf,
+    /// Whether zerobrew also has a service for the same formula
+    pub duplicate: bool,
+}
+
+/// Formula of a service file written by `brew services`:
+/// `homebrew.mxcl.<formula>.plist` on macOS, `homebrew.<formula>.service`
+/// on Linux.
+pub fn homebrew_service_formula(file_name: &str) -> Option<&str> {
+    file_name
+        .strip_prefix("homebrew.mxcl.")
+        .and_then(|rest| rest.strip_suffix(".plist"))
+        .or_else(|| {
+            file_name
+                .strip_prefix("homebrew.")
+                .and_then(|rest| rest.strip_suffix(".service"))
+        })
+        .filter(|formula| !formula.is_empty())
+}
+
+/// Rewrite paths under the Homebrew prefix the service runs from to the same
+/// paths under `prefix`, e.g. `/opt/homebrew/opt/postgresql@14/bin/postgres`
+/// to `<prefix>/opt/postgresql@14/bin/postgres`.
+///
+/// The Homebrew prefix is taken from the program being run, so other paths
+/// that merely share a prefix such as `/usr/local` are only rewritten when
+/// the service actually came from there.
+pub fn rewrite_homebrew_paths(spec: &ServiceSpec, prefix: &Path) -> ServiceSpec {
+    let Some(homebrew) = spec.command.first().and_then(|program| {
+        HOMEBREW_PREFIXES
+            .iter()
+            .find(|hb| Path::new(program).starts_with(hb))
+    }) else {
+        return spec.clone();
+    };
+
+    let from = format!("{homebrew}/");
+    let to = format!("{}/", prefix.display());
+    let rewrite = |value: &str| {
+        if value == *homebrew {
+            prefix.display().to_string()
+        } else {
+            value.replace(&from, &to)
+        }
+    };
+    let rewrite_path = |path: &PathBuf| PathBuf::from(rewrite(&path.to_string_lossy()));
+
+    ServiceSpec {
+        command: spec.command.iter().map(|arg| rewrite(arg)).collect(),
+        working_directory: spec.working_directory.as_ref().map(rewrite_path),
+        environment: spec
+            .environment
+            .iter()
+            .map(|(key, value)| (key.clone(), rewrite(value)))
+            .collect(),
+        stdout_log: spec.stdout_log.as_ref().map(rewrite_path),
+        stderr_log: spec.stderr_log.as_ref().map(rewrite_path),
+        schedule: spec.schedule.clone(),
+    }
+}
+
 /// Status of a service
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ServiceStatus {
@@ -73,6 +406,174 @@ pub struct ServiceConfig {
     pub stdout_log: Option<PathBuf>,
     /// Log file for stderr
     pub stderr_log: Option<PathBuf>,
+    /// Run-as user, umask, priorities and resource limits
+    pub settings: ServiceSettings,
+}
+
+/// Process settings for a service, set with `zb services config` or
+/// `zb services start` and stored in the database
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceSettings {
+    /// User to run as (only honored for system services)
+    pub user: Option<String>,
+    /// Group to run as (only honored for system services)
+    pub group: Option<String>,
+    /// File mode creation mask, e.g. `0o022`
+    pub umask: Option<u32>,
+    /// Scheduling priority, from -20 (highest) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// Best-effort I/O priority, from 0 (highest) to 7 (lowest).
+    /// launchd only has a low-priority switch, used for levels 4 and up.
+    pub io_nice: Option<u8>,
+    /// Maximum number of open files
+    pub open_files: Option<u64>,
+    /// Maximum number of processes
+    pub processes: Option<u64>,
+    /// Seconds to wait before restarting a crashed service
+    pub restart_delay: Option<u64>,
+    /// Upper bound in seconds for the restart delay as consecutive restarts
+    /// back off. systemd only; launchd has a fixed throttle interval.
+    pub restart_max_delay: Option<u64>,
+}
+
+impl ServiceSettings {
+    /// True if no setting is set
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Overwrite these settings with the ones set in `other`
+    pub fn merge(&mut self, other: ServiceSettings) {
+        if other.user.is_some() {
+            self.user = other.user;
+        }
+        if other.group.is_some() {
+            self.group = other.group;
+        }
+        self.umask = other.umask.or(self.umask);
+        self.nice = other.nice.or(self.nice);
+        self.io_nice = other.io_nice.or(self.io_nice);
+        self.open_files = other.open_files.or(self.open_files);
+        self.processes = other.processes.or(self.processes);
+        self.restart_delay = other.restart_delay.or(self.restart_delay);
+        self.restart_max_delay = other.restart_max_delay.or(self.restart_max_delay);
+    }
+
+    /// Check that every setting is in range
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |message: String| Err(Error::StoreCorruption { message });
+
+        if let Some(umask) = self.umask
+            && umask > 0o777
+        {
+            return invalid(format!("invalid umask {umask:o}: must be at most 777"));
+        }
+        if let Some(nice) = self.nice
+            && !(-20..=19).contains(&nice)
+        {
+            return invalid(format!("invalid nice level {nice}: must be -20 to 19"));
+        }
+        if let Some(io_nice) = self.io_nice
+            && io_nice > 7
+        {
+            return invalid(format!("invalid I/O priority {io_nice}: must be 0 to 7"));
+        }
+        if let Some(max_delay) = self.restart_max_delay
+            && max_delay < self.restart_delay.unwrap_or(DEFAULT_RESTART_DELAY_SECS)
+        {
+            return invalid(format!(
+                "invalid restart backoff: max delay {max_delay}s is below the restart delay"
+            ));
+        }
+        Ok(())
+    }
+
+    /// `[Service]` directives for a systemd unit
+    pub fn systemd_directives(&self) -> String {
+        let mut out = String::new();
+        if let Some(ref user) = self.user {
+            out.push_str(&format!("User={}\n", user));
+        }
+        if let Some(ref group) = self.group {
+            out.push_str(&format!("Group={}\n", group));
+        }
+        if let Some(umask) = self.umask {
+            out.push_str(&format!("UMask={:04o}\n", umask));
+        }
+        if let Some(nice) = self.nice {
+            out.push_str(&format!("Nice={}\n", nice));
+        }
+        if let Some(io_nice) = self.io_nice {
+            out.push_str("IOSchedulingClass=best-effort\n");
+            out.push_str(&format!("IOSchedulingPriority={}\n", io_nice));
+        }
+        if let Some(open_files) = self.open_files {
+            out.push_str(&format!("LimitNOFILE={}\n", open_files));
+        }
+        if let Some(processes) = self.processes {
+            out.push_str(&format!("LimitNPROC={}\n", processes));
+        }
+        out
+    }
+
+    /// Keys for a launchd plist's top-level dict
+    pub fn launchd_keys(&self) -> String {
+        let mut out = String::new();
+        if let Some(ref user) = self.user {
+            out.push_str(&format!(
+                "    <key>UserName</key>\n    <string>{}</string>\n",
+                user
+            ));
+        }
+        if let Some(ref group) = self.group {
+            out.push_str(&format!(
+                "    <key>GroupName</key>\n    <string>{}</string>\n",
+                group
+            ));
+        }
+        if let Some(umask) = self.umask {
+            out.push_str(&format!(
+                "    <key>Umask</key>\n    <integer>{}</integer>\n",
+                umask
+            ));
+        }
+        if let Some(nice) = self.nice {
+            out.push_str(&format!(
+                "    <key>Nice</key>\n    <integer>{}</integer>\n",
+                nice
+            ));
+        }
+        if let Some(io_nice) = self.io_nice {
+            out.push_str(&format!(
+                "    <key>LowPriorityIO</key>\n    <{}/>\n",
+                io_nice >= 4
+            ));
+        }
+
+        let mut limits = String::new();
+        if let Some(open_files) = self.open_files {
+            limits.push_str(&format!(
+                "        <key>NumberOfFiles</key>\n        <integer>{}</integer>\n",
+                open_files
+            ));
+        }
+        if let Some(processes) = self.processes {
+            limits.push_str(&format!(
+                "        <key>NumberOfProcesses</key>\n        <integer>{}</integer>\n",
+                processes
+            ));
+        }
+        if !limits.is_empty() {
+            for key in ["SoftResourceLimits", "HardResourceLimits"] {
+                out.push_str(&format!(
+                    "    <key>{}</key>\n    <dict>\n{}    </dict>\n",
+                    key, limits
+                ));
+            }
+        }
+        out
+    }
 }
 
 impl Default for ServiceConfig {
@@ -87,10 +588,85 @@ impl Default for ServiceConfig {
             keep_alive: false,
             stdout_log: None,
             stderr_log: None,
+            settings: ServiceSettings::default(),
         }
     }
 }
 
+/// The init system that runs services.
+///
+/// A backend knows how its service files are named and written and how to
+/// drive the init system; [`ServiceManager`] decides where files go and
+/// what they run.
+pub trait ServiceBackend: Send + Sync {
+    /// Name of the init system, e.g. `systemd`
+    fn name(&self) -> &'static str;
+
+    /// Directories for service files and logs under `home`
+    fn default_paths(&self, home: &Path) -> (PathBuf, PathBuf);
+
+    /// File name of the service file of a formula
+    fn file_name(&self, formula: &str) -> String;
+
+    /// Formula of a zerobrew service file, `None` for other files
+    fn formula_from_file_name<'a>(&self, file_name: &'a str) -> Option<&'a str>;
+
+    /// Name the init system knows the service of a formula by
+    fn label(&self, formula: &str) -> String;
+
+    /// File name `brew services` uses for the service of a formula
+    fn foreign_file_name(&self, formula: &str) -> String;
+
+    /// Service file running `config`, logging to `(stdout, stderr)`
+    fn render(&self, formula: &str, config: &ServiceConfig, logs: (&Path, &Path)) -> String;
+
+    /// Read back what a service file runs
+    fn read_spec(&self, file: &Path) -> Option<ServiceSpec>;
+
+    /// Where a keg ships the service definition Homebrew generated for it
+    fn keg_service_file(&self, keg_path: &Path, formula: &str) -> PathBuf;
+
+    /// Parse the service definition shipped in a keg
+    fn parse_keg_service(&self, path: &Path) -> Option<ServiceConfig>;
+
+    fn status(&self, formula: &str) -> ServiceStatus;
+
+    fn pid(&self, formula: &str) -> Option<u32>;
+
+    fn restart_history(&self, formula: &str) -> RestartHistory;
+
+    /// Whether the service starts at login
+    fn is_enabled(&self, formula: &str, file: &Path) -> bool;
+
+    fn start(&self, formula: &str, file: &Path) -> Result<(), Error>;
+
+    fn stop(&self, formula: &str, file: &Path) -> Result<(), Error>;
+
+    fn enable(&self, formula: &str, file: &Path) -> Result<(), Error>;
+
+    fn disable(&self, formula: &str, file: &Path) -> Result<(), Error>;
+
+    /// Pick up service files that were written or removed
+    fn reload(&self) -> Result<(), Error>;
+
+    /// Stop and disable a `brew services` service, ignoring errors from
+    /// services that are not loaded
+    fn stop_foreign(&self, formula: &str, file: &Path);
+
+    /// Command printing the service's output from the system journal, for
+    /// init systems that keep one
+    fn journal(&self, formula: &str, lines: usize, follow: bool) -> Option<Command>;
+}
+
+/// The backend for the running system: launchd on macOS, systemd elsewhere
+pub fn detect_backend() -> Box<dyn ServiceBackend> {
+    if cfg!(target_os = "macos") {
+        Box::new(Launchd)
+    } else {
+        Box::new(Systemd)
+    }
+}
+
 /// Service manager that handles platform-specific service operations
 pub struct ServiceManager {
     /// Path to the Zerobrew prefix
@@ -99,16 +675,28 @@ pub struct ServiceManager {
     service_dir: PathBuf,
     /// Path for log files
     log_dir: PathBuf,
+    /// Init system the services run under
+    backend: Box<dyn ServiceBackend>,
 }
 
 impl ServiceManager {
-    /// Create a new service manager
+    /// Create a new service manager for the init system of this machine
     pub fn new(prefix: &Path) -> Self {
-        let (service_dir, log_dir) = Self::get_service_paths();
+        Self::with_backend(prefix, detect_backend())
+    }
+
+    /// Create a new service manager driving `backend`, with service files
+    /// and logs in its default locations under `$HOME`
+    pub fn with_backend(prefix: &Path, backend: Box<dyn ServiceBackend>) -> Self {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+        let (service_dir, log_dir) = backend.default_paths(&home);
         Self {
             prefix: prefix.to_path_buf(),
             service_dir,
             log_dir,
+            backend,
         }
     }
 
@@ -122,68 +710,18 @@ impl ServiceManager {
             prefix: prefix.to_path_buf(),
             service_dir: service_dir.to_path_buf(),
             log_dir: log_dir.to_path_buf(),
+            backend: detect_backend(),
         }
     }
 
-    /// Get platform-specific paths for service files and logs
-    #[cfg(target_os = "linux")]
-    fn get_service_paths() -> (PathBuf, PathBuf) {
-        let home = std::env::var("HOME").unwrap_or_else(|_| "/home".to_string());
-        let config_dir = PathBuf::from(&home).join(".config/systemd/user");
-        let log_dir = PathBuf::from(&home).join(".local/share/zerobrew/logs");
-        (config_dir, log_dir)
-    }
-
-    #[cfg(target_os = "macos")]
-    fn get_service_paths() -> (PathBuf, PathBuf) {
-        let home = std::env::var("HOME").unwrap_or_else(|_| "/Users".to_string());
-        let service_dir = PathBuf::from(&home).join("Library/LaunchAgents");
-        let log_dir = PathBuf::from(&home).join("Library/Logs/zerobrew");
-        (service_dir, log_dir)
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    fn get_service_paths() -> (PathBuf, PathBuf) {
-        let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
-        (
-            PathBuf::from(&home).join(".zerobrew/services"),
-            PathBuf::from(&home).join(".zerobrew/logs"),
-        )
+    /// The init system services run under
+    pub fn backend(&self) -> &dyn ServiceBackend {
+        self.backend.as_ref()
     }
 
     /// Get the service file path for a formula
-    #[cfg(target_os = "linux")]
     fn service_file_path(&self, formula: &str) -> PathBuf {
-        self.service_dir
-            .join(format!("zerobrew.{}.service", formula))
-    }
-
-    #[cfg(target_os = "macos")]
-    fn service_file_path(&self, formula: &str) -> PathBuf {
-        self.service_dir
-            .join(format!("com.zerobrew.{}.plist", formula))
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    fn service_file_path(&self, formula: &str) -> PathBuf {
-        self.service_dir
-            .join(format!("zerobrew.{}.service", formula))
-    }
-
-    /// Get the service label/name for a formula
-    #[cfg(target_os = "linux")]
-    fn service_label(&self, formula: &str) -> String {
-        format!("zerobrew.{}.service", formula)
-    }
-
-    #[cfg(target_os = "macos")]
-    fn service_label(&self, formula: &str) -> String {
-        format!("com.zerobrew.{}", formula)
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    fn service_label(&self, formula: &str) -> String {
-        format!("zerobrew.{}", formula)
+        self.service_dir.join(self.backend.file_name(formula))
     }
 
     /// List all managed services
@@ -209,22 +747,9 @@ impl ServiceManager {
             let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
 
             // Filter to zerobrew services only
-            #[cfg(target_os = "linux")]
-            let is_zerobrew_service =
-                file_name.starts_with("zerobrew.") && file_name.ends_with(".service");
-            #[cfg(target_os = "macos")]
-            let is_zerobrew_service =
-                file_name.starts_with("com.zerobrew.") && file_name.ends_with(".plist");
-            #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-            let is_zerobrew_service = file_name.starts_with("zerobrew.");
-
-            if is_zerobrew_service {
-                // Extract formula name from service file name
-                let formula = self.extract_formula_name(file_name);
-                if let Some(name) = formula {
-                    let info = self.get_service_info(&name)?;
-                    services.push(info);
-                }
+            if let Some(name) = self.extract_formula_name(file_name) {
+                let info = self.get_service_info(&name)?;
+                services.push(info);
             }
         }
 
@@ -236,29 +761,9 @@ impl ServiceManager {
 
     /// Extract formula name from service file name
     fn extract_formula_name(&self, file_name: &str) -> Option<String> {
-        #[cfg(target_os = "linux")]
-        {
-            // zerobrew.formula.service -> formula
-            file_name
-                .strip_prefix("zerobrew.")
-                .and_then(|s| s.strip_suffix(".service"))
-                .map(String::from)
-        }
-        #[cfg(target_os = "macos")]
-        {
-            // com.zerobrew.formula.plist -> formula
-            file_name
-                .strip_prefix("com.zerobrew.")
-                .and_then(|s| s.strip_suffix(".plist"))
-                .map(String::from)
-        }
-        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-        {
-            file_name
-                .strip_prefix("zerobrew.")
-                .and_then(|s| s.strip_suffix(".service"))
-                .map(String::from)
-        }
+        self.backend
+            .formula_from_file_name(file_name)
+            .map(String::from)
     }
 
     /// Get information about a specific service
@@ -266,7 +771,7 @@ impl ServiceManager {
         let file_path = self.service_file_path(formula);
         let status = self.get_status(formula)?;
         let pid = self.get_pid(formula).ok().flatten();
-        let auto_start = self.is_auto_start_enabled(formula);
+        let auto_start = self.backend.is_enabled(formula, &file_path);
 
         Ok(ServiceInfo {
             name: formula.to_string(),
@@ -278,176 +783,18 @@ impl ServiceManager {
     }
 
     /// Get the status of a service
-    #[cfg(target_os = "linux")]
-    pub fn get_status(&self, formula: &str) -> Result<ServiceStatus, Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "is-active", &label])
-            .output();
-
-        match output {
-            Ok(out) => {
-                let status = String::from_utf8_lossy(&out.stdout).trim().to_string();
-                match status.as_str() {
-                    "active" => Ok(ServiceStatus::Running),
-                    "inactive" | "dead" => Ok(ServiceStatus::Stopped),
-                    "failed" => {
-                        // Get more details about the failure
-                        let detail = self.get_status_detail(formula);
-                        Ok(ServiceStatus::Error(detail))
-                    }
-                    _ => Ok(ServiceStatus::Unknown),
-                }
-            }
-            Err(_) => Ok(ServiceStatus::Unknown),
-        }
-    }
-
-    #[cfg(target_os = "macos")]
     pub fn get_status(&self, formula: &str) -> Result<ServiceStatus, Error> {
-        let label = self.service_label(formula);
-
-        // First check if service is loaded
-        let output = Command::new("launchctl").args(["list"]).output();
-
-        match output {
-            Ok(out) => {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                // Parse launchctl list output to find our service
-                for line in stdout.lines() {
-                    if line.contains(&label) {
-                        let parts: Vec<&str> = line.split_whitespace().collect();
-                        if parts.len() >= 2 {
-                            // First column is PID (- if not running), second is exit status
-                            let pid = parts[0];
-                            let exit_status = parts[1];
-
-                            if pid == "-" {
-                                // Not running - check exit status
-                                if exit_status == "0" {
-                                    return Ok(ServiceStatus::Stopped);
-                                } else {
-                                    return Ok(ServiceStatus::Error(format!(
-                                        "exited with status {}",
-                                        exit_status
-                                    )));
-                                }
-                            } else {
-                                return Ok(ServiceStatus::Running);
-                            }
-                        }
-                    }
-                }
-                Ok(ServiceStatus::Stopped)
-            }
-            Err(_) => Ok(ServiceStatus::Unknown),
-        }
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn get_status(&self, _formula: &str) -> Result<ServiceStatus, Error> {
-        Ok(ServiceStatus::Unknown)
-    }
-
-    /// Get more details about a service status (systemd only)
-    #[cfg(target_os = "linux")]
-    fn get_status_detail(&self, formula: &str) -> String {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "status", &label, "--no-pager"])
-            .output();
-
-        if let Ok(out) = output {
-            let stderr = String::from_utf8_lossy(&out.stderr);
-            let stdout = String::from_utf8_lossy(&out.stdout);
-            // Look for relevant status lines
-            for line in stdout.lines().chain(stderr.lines()) {
-                if line.contains("Active:") && line.contains("failed") {
-                    return line.trim().to_string();
-                }
-            }
-        }
-        "unknown error".to_string()
+        Ok(self.backend.status(formula))
     }
 
     /// Get PID of a running service
-    #[cfg(target_os = "linux")]
-    pub fn get_pid(&self, formula: &str) -> Result<Option<u32>, Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "show", &label, "--property=MainPID"])
-            .output();
-
-        match output {
-            Ok(out) => {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                // Output is like: MainPID=12345
-                for line in stdout.lines() {
-                    if let Some(pid_str) = line.strip_prefix("MainPID=")
-                        && let Ok(pid) = pid_str.trim().parse::<u32>()
-                        && pid > 0
-                    {
-                        return Ok(Some(pid));
-                    }
-                }
-                Ok(None)
-            }
-            Err(_) => Ok(None),
-        }
-    }
-
-    #[cfg(target_os = "macos")]
     pub fn get_pid(&self, formula: &str) -> Result<Option<u32>, Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("launchctl").args(["list", &label]).output();
-
-        match output {
-            Ok(out) if out.status.success() => {
-                let stdout = String::from_utf8_lossy(&out.stdout);
-                // First line has: PID Status Label
-                if let Some(first_line) = stdout.lines().skip(1).next() {
-                    let parts: Vec<&str> = first_line.split_whitespace().collect();
-                    if !parts.is_empty() && parts[0] != "-" {
-                        if let Ok(pid) = parts[0].parse::<u32>() {
-                            return Ok(Some(pid));
-                        }
-                    }
-                }
-                Ok(None)
-            }
-            _ => Ok(None),
-        }
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn get_pid(&self, _formula: &str) -> Result<Option<u32>, Error> {
-        Ok(None)
+        Ok(self.backend.pid(formula))
     }
 
-    /// Check if auto-start is enabled
-    #[cfg(target_os = "linux")]
-    fn is_auto_start_enabled(&self, formula: &str) -> bool {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "is-enabled", &label])
-            .output();
-
-        match output {
-            Ok(out) => String::from_utf8_lossy(&out.stdout).trim() == "enabled",
-            Err(_) => false,
-        }
-    }
-
-    #[cfg(target_os = "macos")]
-    fn is_auto_start_enabled(&self, formula: &str) -> bool {
-        // On macOS, if the plist exists and is loaded, it's auto-start
-        let file_path = self.service_file_path(formula);
-        file_path.exists()
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    fn is_auto_start_enabled(&self, _formula: &str) -> bool {
-        false
+    /// Get the restart count and recent exits of a service
+    pub fn restart_history(&self, formula: &str) -> RestartHistory {
+        self.backend.restart_history(formula)
     }
 
     /// Create a service file for a formula
@@ -482,157 +829,19 @@ impl ServiceManager {
         })?;
 
         // Reload daemon
-        self.daemon_reload()?;
+        self.backend.reload()?;
 
         Ok(())
     }
 
-    /// Generate service file content
-    #[cfg(target_os = "linux")]
+    /// Generate service file content, logging to the log directory unless
+    /// `config` names log files
     fn generate_service_file(&self, formula: &str, config: &ServiceConfig) -> String {
-        let mut unit = format!(
-            r#"[Unit]
-Description=Zerobrew: {formula}
-After=network.target
-
-[Service]
-Type=simple
-ExecStart={program}"#,
-            formula = formula,
-            program = config.program.display(),
-        );
-
-        // Add arguments if any
-        if !config.args.is_empty() {
-            for arg in &config.args {
-                unit.push_str(&format!(" {}", arg));
-            }
-        }
-        unit.push('\n');
-
-        // Working directory
-        if let Some(ref wd) = config.working_directory {
-            unit.push_str(&format!("WorkingDirectory={}\n", wd.display()));
-        }
-
-        // Environment variables
-        for (key, value) in &config.environment {
-            unit.push_str(&format!("Environment=\"{}={}\"\n", key, value));
-        }
-
-        // Restart policy
-        if config.restart_on_failure {
-            unit.push_str("Restart=on-failure\n");
-            unit.push_str("RestartSec=3\n");
-        }
-
-        // Logging
-        let stdout_log = config
-            .stdout_log
-            .clone()
-            .unwrap_or_else(|| self.log_dir.join(format!("{}.log", formula)));
-        let stderr_log = config
-            .stderr_log
-            .clone()
-            .unwrap_or_else(|| self.log_dir.join(format!("{}.error.log", formula)));
-        unit.push_str(&format!("StandardOutput=append:{}\n", stdout_log.display()));
-        unit.push_str(&format!("StandardError=append:{}\n", stderr_log.display()));
-
-        // Install section
-        unit.push_str("\n[Install]\n");
-        if config.run_at_load {
-            unit.push_str("WantedBy=default.target\n");
-        }
-
-        unit
-    }
-
-    #[cfg(target_os = "macos")]
-    fn generate_service_file(&self, formula: &str, config: &ServiceConfig) -> String {
-        let label = self.service_label(formula);
-        let stdout_log = config
-            .stdout_log
-            .clone()
-            .unwrap_or_else(|| self.log_dir.join(format!("{}.log", formula)));
-        let stderr_log = config
-            .stderr_log
-            .clone()
-            .unwrap_or_else(|| self.log_dir.join(format!("{}.error.log", formula)));
-
-        let mut plist = format!(
-            r#"<?xml version="1.0" encoding="UTF-8"?>
-<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
-<plist version="1.0">
-<dict>
-    <key>Label</key>
-    <string>{label}</string>
-    <key>ProgramArguments</key>
-    <array>
-        <string>{program}</string>"#,
-            label = label,
-            program = config.program.display(),
-        );
-
-        // Add arguments
-        for arg in &config.args {
-            plist.push_str(&format!("\n        <string>{}</string>", arg));
-        }
-        plist.push_str("\n    </array>\n");
-
-        // Working directory
-        if let Some(ref wd) = config.working_directory {
-            plist.push_str(&format!(
-                "    <key>WorkingDirectory</key>\n    <string>{}</string>\n",
-                wd.display()
-            ));
-        }
-
-        // Environment variables
-        if !config.environment.is_empty() {
-            plist.push_str("    <key>EnvironmentVariables</key>\n    <dict>\n");
-            for (key, value) in &config.environment {
-                plist.push_str(&format!(
-                    "        <key>{}</key>\n        <string>{}</string>\n",
-                    key, value
-                ));
-            }
-            plist.push_str("    </dict>\n");
-        }
-
-        // Run at load
-        plist.push_str(&format!(
-            "    <key>RunAtLoad</key>\n    <{}/>\\n",
-            if config.run_at_load { "true" } else { "false" }
-        ));
-
-        // Keep alive
-        if config.keep_alive {
-            plist.push_str("    <key>KeepAlive</key>\n    <true/>\n");
-        }
-
-        // Logging
-        plist.push_str(&format!(
-            "    <key>StandardOutPath</key>\n    <string>{}</string>\n",
-            stdout_log.display()
-        ));
-        plist.push_str(&format!(
-            "    <key>StandardErrorPath</key>\n    <string>{}</string>\n",
-            stderr_log.display()
-        ));
-
-        plist.push_str("</dict>\n</plist>\n");
-
-        plist
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    fn generate_service_file(&self, formula: &str, config: &ServiceConfig) -> String {
-        // Generic format for unsupported platforms
-        format!(
-            "# Service: {}\n# Program: {}\n",
-            formula,
-            config.program.display()
-        )
+        let (default_stdout, default_stderr) = self.get_log_paths(formula);
+        let stdout_log = config.stdout_log.clone().unwrap_or(default_stdout);
+        let stderr_log = config.stderr_log.clone().unwrap_or(default_stderr);
+        self.backend
+            .render(formula, config, (&stdout_log, &stderr_log))
     }
 
     /// Remove a service
@@ -656,124 +865,20 @@ ExecStart={program}"#,
         }
 
         // Reload daemon
-        self.daemon_reload()?;
+        self.backend.reload()?;
 
         Ok(())
     }
 
     /// Start a service
-    #[cfg(target_os = "linux")]
     pub fn start(&self, formula: &str) -> Result<(), Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "start", &label])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to start service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to start service: {}", stderr),
-            });
-        }
-
-        Ok(())
-    }
-
-    #[cfg(target_os = "macos")]
-    pub fn start(&self, formula: &str) -> Result<(), Error> {
-        let file_path = self.service_file_path(formula);
-
-        // Load the service if not already loaded
-        let output = Command::new("launchctl")
-            .args(["load", "-w", &file_path.to_string_lossy()])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to start service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            // Ignore "already loaded" error
-            if !stderr.contains("already loaded") {
-                return Err(Error::StoreCorruption {
-                    message: format!("failed to start service: {}", stderr),
-                });
-            }
-        }
-
-        // Start the service
-        let label = self.service_label(formula);
-        let _ = Command::new("launchctl")
-            .args([
-                "kickstart",
-                "-k",
-                &format!("gui/{}/{}", self.get_uid(), label),
-            ])
-            .output();
-
-        Ok(())
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn start(&self, _formula: &str) -> Result<(), Error> {
-        Err(Error::StoreCorruption {
-            message: "service management not supported on this platform".to_string(),
-        })
+        self.backend
+            .start(formula, &self.service_file_path(formula))
     }
 
     /// Stop a service
-    #[cfg(target_os = "linux")]
     pub fn stop(&self, formula: &str) -> Result<(), Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "stop", &label])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to stop service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to stop service: {}", stderr),
-            });
-        }
-
-        Ok(())
-    }
-
-    #[cfg(target_os = "macos")]
-    pub fn stop(&self, formula: &str) -> Result<(), Error> {
-        let file_path = self.service_file_path(formula);
-
-        let output = Command::new("launchctl")
-            .args(["unload", &file_path.to_string_lossy()])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to stop service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            // Ignore "not loaded" error
-            if !stderr.contains("Could not find") && !stderr.contains("not loaded") {
-                return Err(Error::StoreCorruption {
-                    message: format!("failed to stop service: {}", stderr),
-                });
-            }
-        }
-
-        Ok(())
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn stop(&self, _formula: &str) -> Result<(), Error> {
-        Err(Error::StoreCorruption {
-            message: "service management not supported on this platform".to_string(),
-        })
+        self.backend.stop(formula, &self.service_file_path(formula))
     }
 
     /// Restart a service
@@ -783,136 +888,21 @@ ExecStart={program}"#,
     }
 
     /// Enable auto-start for a service
-    #[cfg(target_os = "linux")]
     pub fn enable_auto_start(&self, formula: &str) -> Result<(), Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "enable", &label])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to enable service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to enable service: {}", stderr),
-            });
-        }
-
-        Ok(())
-    }
-
-    #[cfg(target_os = "macos")]
-    pub fn enable_auto_start(&self, formula: &str) -> Result<(), Error> {
-        // On macOS, loading with -w enables auto-start
-        let file_path = self.service_file_path(formula);
-        let output = Command::new("launchctl")
-            .args(["load", "-w", &file_path.to_string_lossy()])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to enable service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if !stderr.contains("already loaded") {
-                return Err(Error::StoreCorruption {
-                    message: format!("failed to enable service: {}", stderr),
-                });
-            }
-        }
-
-        Ok(())
-    }
-
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn enable_auto_start(&self, _formula: &str) -> Result<(), Error> {
-        Err(Error::StoreCorruption {
-            message: "service management not supported on this platform".to_string(),
-        })
+        self.backend
+            .enable(formula, &self.service_file_path(formula))
     }
 
     /// Disable auto-start for a service
-    #[cfg(target_os = "linux")]
-    pub fn disable_auto_start(&self, formula: &str) -> Result<(), Error> {
-        let label = self.service_label(formula);
-        let output = Command::new("systemctl")
-            .args(["--user", "disable", &label])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to disable service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to disable service: {}", stderr),
-            });
-        }
-
-        Ok(())
-    }
-
-    #[cfg(target_os = "macos")]
     pub fn disable_auto_start(&self, formula: &str) -> Result<(), Error> {
-        let file_path = self.service_file_path(formula);
-        let output = Command::new("launchctl")
-            .args(["unload", "-w", &file_path.to_string_lossy()])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to disable service: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if !stderr.contains("Could not find") && !stderr.contains("not loaded") {
-                return Err(Error::StoreCorruption {
-                    message: format!("failed to disable service: {}", stderr),
-                });
-            }
-        }
-
-        Ok(())
+        self.backend
+            .disable(formula, &self.service_file_path(formula))
     }
 
-    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
-    pub fn disable_auto_start(&self, _formula: &str) -> Result<(), Error> {
-        Err(Error::StoreCorruption {
-            message: "service management not supported on this platform".to_string(),
-        })
-    }
-
-    /// Reload systemd daemon
-    #[cfg(target_os = "linux")]
-    fn daemon_reload(&self) -> Result<(), Error> {
-        let output = Command::new("systemctl")
-            .args(["--user", "daemon-reload"])
-            .output()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to reload daemon: {}", e),
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to reload daemon: {}", stderr),
-            });
-        }
-
-        Ok(())
-    }
-
-    #[cfg(not(target_os = "linux"))]
-    fn daemon_reload(&self) -> Result<(), Error> {
-        // No-op on non-Linux platforms
-        Ok(())
-    }
-
-    /// Get current user ID (macOS only)
-    #[cfg(target_os = "macos")]
-    fn get_uid(&self) -> u32 {
-        unsafe { libc::getuid() }
+    /// Command showing a service's output from the system journal, when the
+    /// init system keeps one
+    pub fn journal_command(&self, formula: &str, lines: usize, follow: bool) -> Option<Command> {
+        self.backend.journal(formula, lines, follow)
     }
 
     /// Try to detect service configuration from installed formula files
@@ -939,107 +929,117 @@ ExecStart={program}"#,
         }
 
         // Check for existing homebrew service files in the keg
-        #[cfg(target_os = "macos")]
-        {
-            let plist_path = keg_path
-                .join("homebrew.mxcl.")
-                .join(format!("{}.plist", formula));
-            if plist_path.exists() {
-                return self.parse_homebrew_plist(&plist_path);
-            }
+        let keg_service = self.backend.keg_service_file(keg_path, formula);
+        if keg_service.exists() {
+            return self.backend.parse_keg_service(&keg_service);
         }
 
-        #[cfg(target_os = "linux")]
-        {
-            let service_path = keg_path
-                .join("systemd")
-                .join(format!("{}.service", formula));
-            if service_path.exists() {
-                return self.parse_homebrew_systemd(&service_path);
-            }
+        None
+    }
+
+    /// Get the log file paths for a formula's service
+    pub fn get_log_paths(&self, formula: &str) -> (PathBuf, PathBuf) {
+        let stdout_log = self.log_dir.join(format!("{}.log", formula));
+        let stderr_log = self.log_dir.join(format!("{}.error.log", formula));
+        (stdout_log, stderr_log)
+    }
+
+    /// Read back what the installed service file of a formula runs.
+    ///
+    /// Returns `None` if no service file has been written for it.
+    pub fn service_spec(&self, formula: &str) -> Option<ServiceSpec> {
+        self.backend.read_spec(&self.service_file_path(formula))
+    }
+
+    /// Path of the service file `brew services` writes for a formula
+    fn foreign_file_path(&self, formula: &str) -> PathBuf {
+        self.service_dir
+            .join(self.backend.foreign_file_name(formula))
+    }
+
+    /// List services set up by `brew services` in the service directory
+    pub fn foreign_services(&self) -> Result<Vec<ForeignService>, Error> {
+        if !self.service_dir.exists() {
+            return Ok(Vec::new());
         }
 
-        None
+        let entries = std::fs::read_dir(&self.service_dir).map_err(|e| Error::StoreCorruption {
+            message: format!(
+                "failed to read service directory {}: {}",
+                self.service_dir.display(),
+                e
+            ),
+        })?;
+
+        let mut services: Vec<ForeignService> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let name = homebrew_service_formula(file_name.to_str()?)?.to_string();
+                Some(ForeignService {
+                    duplicate: self.service_file_path(&name).exists(),
+                    file_path: entry.path(),
+                    name,
+                })
+            })
+            .collect();
+        services.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(services)
     }
 
-    /// Parse a Homebrew plist file to extract service config
-    #[cfg(target_os = "macos")]
-    fn parse_homebrew_plist(&self, path: &Path) -> Option<ServiceConfig> {
-        let content = std::fs::read_to_string(path).ok()?;
-
-        // Simple XML parsing to extract program arguments
-        let mut config = ServiceConfig::default();
-
-        // Find ProgramArguments
-        if let Some(start) = content.find("<key>ProgramArguments</key>") {
-            if let Some(array_start) = content[start..].find("<array>") {
-                let array_content = &content[start + array_start..];
-                if let Some(array_end) = array_content.find("</array>") {
-                    let array = &array_content[7..array_end];
-                    let mut args = Vec::new();
-                    for line in array.lines() {
-                        if let Some(s) = line.trim().strip_prefix("<string>") {
-                            if let Some(value) = s.strip_suffix("</string>") {
-                                args.push(value.to_string());
-                            }
-                        }
-                    }
-                    if !args.is_empty() {
-                        config.program = PathBuf::from(&args[0]);
-                        config.args = args[1..].to_vec();
-                    }
-                }
-            }
-        }
+    /// Read the `brew services` service of a formula as a zerobrew service
+    /// config, with Homebrew paths rewritten to this prefix.
+    pub fn foreign_service_config(&self, formula: &str) -> Result<ServiceConfig, Error> {
+        let path = self.foreign_file_path(formula);
+        let spec = self
+            .backend
+            .read_spec(&path)
+            .ok_or_else(|| Error::StoreCorruption {
+                message: format!("failed to read Homebrew service file {}", path.display()),
+            })?;
 
-        if config.program.as_os_str().is_empty() {
-            return None;
+        if let Some(schedule) = &spec.schedule {
+            return Err(Error::StoreCorruption {
+                message: format!(
+                    "the Homebrew service for {} runs on a schedule ({}), which cannot be adopted",
+                    formula, schedule
+                ),
+            });
         }
 
-        // Check for RunAtLoad
-        config.run_at_load =
-            content.contains("<key>RunAtLoad</key>") && content.contains("<true/>");
-
-        // Check for KeepAlive
-        config.keep_alive = content.contains("<key>KeepAlive</key>") && content.contains("<true/>");
+        let spec = rewrite_homebrew_paths(&spec, &self.prefix);
+        let Some((program, args)) = spec.command.split_first() else {
+            return Err(Error::StoreCorruption {
+                message: format!("no command found in {}", path.display()),
+            });
+        };
 
-        Some(config)
+        Ok(ServiceConfig {
+            program: PathBuf::from(program),
+            args: args.to_vec(),
+            working_directory: spec.working_directory,
+            environment: spec.environment.into_iter().collect(),
+            ..Default::default()
+        })
     }
 
-    /// Parse a Homebrew systemd service file
-    #[cfg(target_os = "linux")]
-    fn parse_homebrew_systemd(&self, path: &Path) -> Option<ServiceConfig> {
-        let content = std::fs::read_to_string(path).ok()?;
-
-        let mut config = ServiceConfig::default();
-
-        for line in content.lines() {
-            let line = line.trim();
-            if let Some(value) = line.strip_prefix("ExecStart=") {
-                let parts: Vec<&str> = value.split_whitespace().collect();
-                if !parts.is_empty() {
-                    config.program = PathBuf::from(parts[0]);
-                    config.args = parts[1..].iter().map(|s| s.to_string()).collect();
-                }
-            } else if let Some(value) = line.strip_prefix("WorkingDirectory=") {
-                config.working_directory = Some(PathBuf::from(value));
-            } else if line.starts_with("Restart=") && line != "Restart=no" {
-                config.restart_on_failure = true;
-            }
-        }
-
-        if config.program.as_os_str().is_empty() {
-            return None;
+    /// Replace the `brew services` service of a formula with a zerobrew
+    /// service running `config`.
+    ///
+    /// The Homebrew service is stopped and its file removed before the new
+    /// one is written, so the two never run at once.
+    pub fn adopt(&self, formula: &str, config: &ServiceConfig) -> Result<(), Error> {
+        let path = self.foreign_file_path(formula);
+        self.backend.stop_foreign(formula, &path);
+
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to remove service file {}: {}", path.display(), e),
+            })?;
         }
 
-        Some(config)
-    }
-
-    /// Get the log file paths for a formula's service
-    pub fn get_log_paths(&self, formula: &str) -> (PathBuf, PathBuf) {
-        let stdout_log = self.log_dir.join(format!("{}.log", formula));
-        let stderr_log = self.log_dir.join(format!("{}.error.log", formula));
-        (stdout_log, stderr_log)
+        self.create_service(formula, config)
     }
 
     /// Get the log directory path
@@ -1162,6 +1162,10 @@ mod tests {
             keep_alive: true,
             stdout_log: Some(PathBuf::from("/var/log/my.log")),
             stderr_log: Some(PathBuf::from("/var/log/my.err")),
+            settings: ServiceSettings {
+                nice: Some(10),
+                ..Default::default()
+            },
         };
 
         assert_eq!(config.program, PathBuf::from("/usr/bin/myservice"));
@@ -1195,6 +1199,422 @@ mod tests {
         assert_eq!(config.environment, cloned.environment);
     }
 
+    // ==================== ServiceSettings Tests ====================
+
+    fn full_settings() -> ServiceSettings {
+        ServiceSettings {
+            user: Some("redis".to_string()),
+            group: Some("daemon".to_string()),
+            umask: Some(0o027),
+            nice: Some(-5),
+            io_nice: Some(6),
+            open_files: Some(65536),
+            processes: Some(512),
+            restart_delay: Some(10),
+            restart_max_delay: Some(300),
+        }
+    }
+
+    #[test]
+    fn test_service_settings_systemd_directives() {
+        assert_eq!(ServiceSettings::default().systemd_directives(), "");
+        assert_eq!(
+            full_settings().systemd_directives(),
+            "User=redis\nGroup=daemon\nUMask=0027\nNice=-5\n\
+             IOSchedulingClass=best-effort\nIOSchedulingPriority=6\n\
+             LimitNOFILE=65536\nLimitNPROC=512\n"
+        );
+    }
+
+    #[test]
+    fn test_service_settings_launchd_keys() {
+        assert_eq!(ServiceSettings::default().launchd_keys(), "");
+
+        let keys = full_settings().launchd_keys();
+        assert!(keys.contains("<key>UserName</key>\n    <string>redis</string>"));
+        assert!(keys.contains("<key>GroupName</key>\n    <string>daemon</string>"));
+        // 0o027 as a decimal plist integer
+        assert!(keys.contains("<key>Umask</key>\n    <integer>23</integer>"));
+        assert!(keys.contains("<key>Nice</key>\n    <integer>-5</integer>"));
+        assert!(keys.contains("<key>LowPriorityIO</key>\n    <true/>"));
+        assert!(keys.contains("<key>SoftResourceLimits</key>"));
+        assert!(keys.contains("<key>HardResourceLimits</key>"));
+        assert_eq!(keys.matches("<integer>65536</integer>").count(), 2);
+    }
+
+    #[test]
+    fn test_service_settings_merge_keeps_unset_values() {
+        let mut settings = full_settings();
+        settings.merge(ServiceSettings {
+            nice: Some(10),
+            ..Default::default()
+        });
+
+        assert_eq!(settings.nice, Some(10));
+        assert_eq!(settings.user.as_deref(), Some("redis"));
+        assert_eq!(settings.open_files, Some(65536));
+    }
+
+    #[test]
+    fn test_service_settings_validate() {
+        assert!(full_settings().validate().is_ok());
+
+        for settings in [
+            ServiceSettings {
+                umask: Some(0o1000),
+                ..Default::default()
+            },
+            ServiceSettings {
+                nice: Some(20),
+                ..Default::default()
+            },
+            ServiceSettings {
+                io_nice: Some(8),
+                ..Default::default()
+            },
+            ServiceSettings {
+                restart_delay: Some(60),
+                restart_max_delay: Some(30),
+                ..Default::default()
+            },
+            ServiceSettings {
+                restart_max_delay: Some(1),
+                ..Default::default()
+            },
+        ] {
+            assert!(settings.validate().is_err(), "{settings:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_generate_service_file_linux_with_settings() {
+        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
+        let config = ServiceConfig {
+            program: PathBuf::from("/usr/bin/redis-server"),
+            settings: full_settings(),
+            ..Default::default()
+        };
+
+        let content = manager.generate_service_file("redis", &config);
+        assert!(content.contains("[Service]"));
+        assert!(content.contains("\nUser=redis\n"));
+        assert!(content.contains("\nLimitNOFILE=65536\n"));
+        assert!(content.contains("\nRestartSec=10\n"));
+        assert!(content.contains("\nRestartSteps=5\n"));
+        assert!(content.contains("\nRestartMaxDelaySec=300\n"));
+    }
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn test_generate_service_file_macos_restart_delay() {
+        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
+        let config = ServiceConfig {
+            program: PathBuf::from("/usr/bin/redis-server"),
+            settings: full_settings(),
+            ..Default::default()
+        };
+
+        let content = manager.generate_service_file("redis", &config);
+        assert!(content.contains("<key>ThrottleInterval</key>\n    <integer>10</integer>"));
+    }
+
+    // ==================== RestartHistory Tests ====================
+
+    fn exit_at(code: i32, at: &str) -> ServiceExit {
+        ServiceExit {
+            code,
+            at: Some(at.parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_parse_systemd_restarts() {
+        assert_eq!(parse_systemd_restarts("NRestarts=7\n"), 7);
+        assert_eq!(parse_systemd_restarts(""), 0);
+        assert_eq!(parse_systemd_restarts("NRestarts=[not set]\n"), 0);
+    }
+
+    #[test]
+    fn test_parse_systemd_exits() {
+        let journal = "\
+2024-05-01T12:00:00+0000 host systemd[900]: Started zerobrew.redis.service - Zerobrew: redis.
+2024-05-01T12:00:01+0000 host systemd[900]: zerobrew.redis.service: Main process exited, code=exited, status=1/FAILURE
+2024-05-01T12:00:04+0000 host systemd[900]: zerobrew.redis.service: Scheduled restart job, restart counter is at 1.
+2024-05-01T12:00:05+0200 host systemd[900]: zerobrew.redis.service: Main process exited, code=killed, status=9/KILL
+";
+        let exits = parse_systemd_exits(journal);
+        assert_eq!(
+            exits,
+            vec![
+                exit_at(1, "2024-05-01T12:00:01Z"),
+                exit_at(137, "2024-05-01T10:00:05Z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_systemd_exits_keeps_most_recent() {
+        let journal: String = (0..30)
+            .map(|i| {
+                format!(
+                    "2024-05-01T12:00:{i:02}+0000 host systemd[1]: x.service: \
+                     Main process exited, code=exited, status={i}/FAILURE\n"
+                )
+            })
+            .collect();
+        let exits = parse_systemd_exits(&journal);
+        assert_eq!(exits.len(), MAX_TRACKED_EXITS);
+        assert_eq!(exits.last().unwrap().code, 29);
+    }
+
+    #[test]
+    fn test_parse_launchctl_print() {
+        let output = "\
+com.zerobrew.redis = {
+\tactive count = 0
+\truns = 5
+\tlast exit code = 78
+}
+";
+        let history = parse_launchctl_print(output);
+        assert_eq!(history.restarts, 4);
+        assert_eq!(history.recent_exit_codes(5), vec![78]);
+
+        let signalled =
+            parse_launchctl_print("\truns = 2\n\tlast terminating signal = Killed: 9\n");
+        assert_eq!(signalled.recent_exit_codes(5), vec![137]);
+
+        let never = parse_launchctl_print("\truns = 1\n\tlast exit code = (never exited)\n");
+        assert_eq!(never, RestartHistory::default());
+    }
+
+    #[test]
+    fn test_parse_systemd_unit() {
+        let unit = "\
+[Service]
+Type=simple
+ExecStart=/opt/zerobrew/opt/redis/bin/redis-server /opt/zerobrew/etc/redis.conf
+WorkingDirectory=/opt/zerobrew/var
+Environment=\"LANG=C\"
+Environment=\"REDIS_PORT=6380\"
+StandardOutput=append:/logs/redis.log
+StandardError=append:/logs/redis.error.log
+";
+        let spec = parse_systemd_unit(unit, None);
+        assert_eq!(
+            spec.command,
+            vec![
+                "/opt/zerobrew/opt/redis/bin/redis-server",
+                "/opt/zerobrew/etc/redis.conf"
+            ]
+        );
+        assert_eq!(
+            spec.working_directory,
+            Some(PathBuf::from("/opt/zerobrew/var"))
+        );
+        assert_eq!(
+            spec.environment.get("REDIS_PORT").map(String::as_str),
+            Some("6380")
+        );
+        assert_eq!(spec.environment.len(), 2);
+        assert_eq!(spec.stdout_log, Some(PathBuf::from("/logs/redis.log")));
+        assert_eq!(
+            spec.stderr_log,
+            Some(PathBuf::from("/logs/redis.error.log"))
+        );
+        assert_eq!(spec.schedule, None);
+
+        let timer = "[Timer]\nOnUnitActiveSec=3600\n";
+        let spec = parse_systemd_unit(unit, Some(timer));
+        assert_eq!(spec.schedule.as_deref(), Some("every 3600"));
+    }
+
+    #[test]
+    fn test_parse_launchd_plist() {
+        let plist = r#"<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.zerobrew.redis</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/opt/zerobrew/opt/redis/bin/redis-server</string>
+        <string>/opt/zerobrew/etc/redis.conf</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>/opt/zerobrew/var</string>
+    <key>EnvironmentVariables</key>
+    <dict>
+        <key>REDIS_PORT</key>
+        <string>6380</string>
+    </dict>
+    <key>StartCalendarInterval</key>
+    <dict>
+        <key>Hour</key>
+        <integer>3</integer>
+        <key>Minute</key>
+        <integer>0</integer>
+    </dict>
+    <key>StandardOutPath</key>
+    <string>/logs/redis.log</string>
+    <key>StandardErrorPath</key>
+    <string>/logs/redis.error.log</string>
+</dict>
+</plist>
+"#;
+        let spec = parse_launchd_plist(plist);
+        assert_eq!(spec.command.len(), 2);
+        assert_eq!(
+            spec.working_directory,
+            Some(PathBuf::from("/opt/zerobrew/var"))
+        );
+        assert_eq!(
+            spec.environment,
+            BTreeMap::from([("REDIS_PORT".to_string(), "6380".to_string())])
+        );
+        assert_eq!(spec.stdout_log, Some(PathBuf::from("/logs/redis.log")));
+        assert_eq!(
+            spec.stderr_log,
+            Some(PathBuf::from("/logs/redis.error.log"))
+        );
+        assert_eq!(spec.schedule.as_deref(), Some("Hour=3 Minute=0"));
+
+        let interval = "<key>StartInterval</key>\n<integer>300</integer>\n";
+        assert_eq!(
+            parse_launchd_plist(interval).schedule.as_deref(),
+            Some("every 300s")
+        );
+    }
+
+    #[test]
+    fn test_homebrew_service_formula() {
+        assert_eq!(
+            homebrew_service_formula("homebrew.mxcl.postgresql@14.plist"),
+            Some("postgresql@14")
+        );
+        assert_eq!(
+            homebrew_service_formula("homebrew.redis.service"),
+            Some("redis")
+        );
+        assert_eq!(homebrew_service_formula("homebrew.redis.timer"), None);
+        assert_eq!(homebrew_service_formula("zerobrew.redis.service"), None);
+        assert_eq!(homebrew_service_formula("com.zerobrew.redis.plist"), None);
+        assert_eq!(homebrew_service_formula("homebrew.mxcl..plist"), None);
+    }
+
+    #[test]
+    fn test_rewrite_homebrew_paths() {
+        let spec = ServiceSpec {
+            command: vec![
+                "/opt/homebrew/opt/postgresql@14/bin/postgres".to_string(),
+                "-D".to_string(),
+                "/opt/homebrew/var/postgresql@14".to_string(),
+            ],
+            working_directory: Some(PathBuf::from("/opt/homebrew")),
+            environment: BTreeMap::from([(
+                "PATH".to_string(),
+                "/opt/homebrew/bin:/usr/local/bin:/usr/bin".to_string(),
+            )]),
+            stdout_log: Some(PathBuf::from("/opt/homebrew/var/log/postgresql@14.log")),
+            ..Default::default()
+        };
+
+        let rewritten = rewrite_homebrew_paths(&spec, Path::new("/opt/zerobrew"));
+        assert_eq!(
+            rewritten.command,
+            vec![
+                "/opt/zerobrew/opt/postgresql@14/bin/postgres",
+                "-D",
+                "/opt/zerobrew/var/postgresql@14",
+            ]
+        );
+        // Only the prefix the program came from is rewritten
+        assert_eq!(
+            rewritten.environment["PATH"],
+            "/opt/zerobrew/bin:/usr/local/bin:/usr/bin"
+        );
+        assert_eq!(
+            rewritten.stdout_log,
+            Some(PathBuf::from("/opt/zerobrew/var/log/postgresql@14.log"))
+        );
+        assert_eq!(
+            rewritten.working_directory,
+            Some(PathBuf::from("/opt/zerobrew"))
+        );
+
+        let system = ServiceSpec {
+            command: vec!["/usr/bin/true".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            rewrite_homebrew_paths(&system, Path::new("/opt/zerobrew")),
+            system
+        );
+    }
+
+    #[test]
+    fn test_recent_exit_codes_limit() {
+        let history = RestartHistory {
+            restarts: 3,
+            exits: [1, 2, 3]
+                .map(|code| ServiceExit { code, at: None })
+                .to_vec(),
+        };
+        assert_eq!(history.recent_exit_codes(2), vec![2, 3]);
+        assert_eq!(history.recent_exit_codes(10), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_is_crash_looping_with_timestamps() {
+        let now: DateTime<Utc> = "2024-05-01T12:10:00Z".parse().unwrap();
+        let history = RestartHistory {
+            restarts: 3,
+            exits: vec![
+                exit_at(1, "2024-05-01T12:05:00Z"),
+                exit_at(1, "2024-05-01T12:06:00Z"),
+                exit_at(1, "2024-05-01T12:07:00Z"),
+            ],
+        };
+        assert!(history.is_crash_looping(now));
+
+        // The same failures an hour later are old news
+        let later: DateTime<Utc> = "2024-05-01T13:10:00Z".parse().unwrap();
+        assert!(!history.is_crash_looping(later));
+
+        // Few restarts means the service isn't being restarted in a loop
+        let few = RestartHistory {
+            restarts: 1,
+            ..history.clone()
+        };
+        assert!(!few.is_crash_looping(now));
+    }
+
+    #[test]
+    fn test_is_crash_looping_clean_last_exit() {
+        let now: DateTime<Utc> = "2024-05-01T12:10:00Z".parse().unwrap();
+        let history = RestartHistory {
+            restarts: 5,
+            exits: vec![
+                exit_at(1, "2024-05-01T12:05:00Z"),
+                exit_at(1, "2024-05-01T12:06:00Z"),
+                exit_at(1, "2024-05-01T12:07:00Z"),
+                exit_at(0, "2024-05-01T12:08:00Z"),
+            ],
+        };
+        assert!(!history.is_crash_looping(now));
+    }
+
+    #[test]
+    fn test_is_crash_looping_without_timestamps() {
+        let now = Utc::now();
+        let history = RestartHistory {
+            restarts: 4,
+            exits: vec![ServiceExit { code: 78, at: None }],
+        };
+        assert!(history.is_crash_looping(now));
+        assert!(!RestartHistory::default().is_crash_looping(now));
+    }
+
     // ==================== ServiceInfo Tests ====================
 
     #[test]
@@ -1292,9 +1712,9 @@ mod tests {
     #[cfg(target_os = "linux")]
     fn test_service_label_linux() {
         let manager = ServiceManager::new(Path::new("/opt/zerobrew/prefix"));
-        assert_eq!(manager.service_label("redis"), "zerobrew.redis.service");
+        assert_eq!(manager.backend().label("redis"), "zerobrew.redis.service");
         assert_eq!(
-            manager.service_label("mysql@8.0"),
+            manager.backend().label("mysql@8.0"),
             "zerobrew.mysql@8.0.service"
         );
     }
@@ -1457,7 +1877,6 @@ mod tests {
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_basic() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -1476,8 +1895,7 @@ WantedBy=default.target
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path);
+        let config = Systemd.parse_keg_service(&service_path);
 
         assert!(config.is_some());
         let config = config.unwrap();
@@ -1491,7 +1909,6 @@ WantedBy=default.target
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_no_restart() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -1502,8 +1919,7 @@ Restart=no
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         // Restart=no should NOT set restart_on_failure (Default::default is true, but parsed should be based on file)
         // Actually looking at the code, it only sets true if Restart= exists and != "no"
@@ -1517,24 +1933,20 @@ Restart=no
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_empty_file() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
 
         std::fs::write(&service_path, "").unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path);
+        let config = Systemd.parse_keg_service(&service_path);
 
         assert!(config.is_none()); // No ExecStart means no valid config
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_missing_file() {
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(Path::new("/nonexistent/path.service"));
+        let config = Systemd.parse_keg_service(Path::new("/nonexistent/path.service"));
 
         assert!(config.is_none());
     }
@@ -1564,8 +1976,11 @@ Restart=no
     #[cfg(target_os = "macos")]
     fn test_service_label_macos() {
         let manager = ServiceManager::new(Path::new("/opt/zerobrew/prefix"));
-        assert_eq!(manager.service_label("redis"), "com.zerobrew.redis");
-        assert_eq!(manager.service_label("mysql@8.0"), "com.zerobrew.mysql@8.0");
+        assert_eq!(manager.backend().label("redis"), "com.zerobrew.redis");
+        assert_eq!(
+            manager.backend().label("mysql@8.0"),
+            "com.zerobrew.mysql@8.0"
+        );
     }
 
     #[test]
@@ -1664,7 +2079,6 @@ Restart=no
     }
 
     #[test]
-    #[cfg(target_os = "macos")]
     fn test_parse_homebrew_plist_basic() {
         let temp_dir = TempDir::new().unwrap();
         let plist_path = temp_dir.path().join("test.plist");
@@ -1689,8 +2103,7 @@ Restart=no
 "#;
         std::fs::write(&plist_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_plist(&plist_path);
+        let config = Launchd.parse_keg_service(&plist_path);
 
         assert!(config.is_some());
         let config = config.unwrap();
@@ -1704,7 +2117,6 @@ Restart=no
     }
 
     #[test]
-    #[cfg(target_os = "macos")]
     fn test_parse_homebrew_plist_no_run_at_load() {
         let temp_dir = TempDir::new().unwrap();
         let plist_path = temp_dir.path().join("test.plist");
@@ -1723,15 +2135,13 @@ Restart=no
 "#;
         std::fs::write(&plist_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_plist(&plist_path).unwrap();
+        let config = Launchd.parse_keg_service(&plist_path).unwrap();
 
         assert!(!config.run_at_load);
         assert!(!config.keep_alive);
     }
 
     #[test]
-    #[cfg(target_os = "macos")]
     fn test_parse_homebrew_plist_empty() {
         let temp_dir = TempDir::new().unwrap();
         let plist_path = temp_dir.path().join("test.plist");
@@ -1739,17 +2149,14 @@ Restart=no
         let content = r#"<?xml version="1.0"?><plist><dict></dict></plist>"#;
         std::fs::write(&plist_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_plist(&plist_path);
+        let config = Launchd.parse_keg_service(&plist_path);
 
         assert!(config.is_none());
     }
 
     #[test]
-    #[cfg(target_os = "macos")]
     fn test_parse_homebrew_plist_missing_file() {
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_plist(Path::new("/nonexistent/path.plist"));
+        let config = Launchd.parse_keg_service(Path::new("/nonexistent/path.plist"));
 
         assert!(config.is_none());
     }
@@ -1798,6 +2205,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: temp_dir.path().join("nonexistent"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services = manager.list().unwrap();
@@ -1825,6 +2233,7 @@ Restart=no
     // ==================== Service File Creation Tests (with temp dir) ====================
 
     #[test]
+    #[allow(clippy::overly_complex_bool_expr)]
     fn test_create_service_creates_directories() {
         let temp_dir = TempDir::new().unwrap();
         let service_dir = temp_dir.path().join("services");
@@ -1834,6 +2243,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: log_dir.clone(),
+            backend: detect_backend(),
         };
 
         let config = ServiceConfig {
@@ -1868,6 +2278,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/myservice/1.0");
@@ -1894,6 +2305,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/nginx/1.0");
@@ -1919,6 +2331,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/redis/1.0");
@@ -1937,6 +2350,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/unknown/1.0");
@@ -1954,6 +2368,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services: Vec<ServiceInfo> = vec![];
@@ -2041,6 +2456,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services = manager.list().unwrap();
@@ -2068,6 +2484,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services = manager.list().unwrap();
@@ -2094,6 +2511,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services = manager.list().unwrap();
@@ -2118,6 +2536,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         // Note: This will fail on stop/disable but file removal should still work
@@ -2139,6 +2558,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         // Removing a service that doesn't exist should not error on file removal
@@ -2159,6 +2579,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let info = manager.get_service_info("redis").unwrap();
@@ -2273,6 +2694,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/myapp/1.0");
@@ -2300,6 +2722,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/myapp/1.0");
@@ -2324,6 +2747,7 @@ Restart=no
             prefix: prefix.to_path_buf(),
             service_dir: temp_dir.path().join("services"),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let keg_path = temp_dir.path().join("Cellar/myapp/1.0");
@@ -2350,6 +2774,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         // Only redis is installed
@@ -2374,6 +2799,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir,
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let installed = vec!["redis".to_string(), "postgresql".to_string()];
@@ -2385,6 +2811,7 @@ Restart=no
     // ==================== Cleanup Services Tests ====================
 
     #[test]
+    #[allow(clippy::single_match)]
     fn test_cleanup_services_returns_count() {
         let temp_dir = TempDir::new().unwrap();
         let service_dir = temp_dir.path().join("services");
@@ -2403,6 +2830,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: temp_dir.path().join("logs"),
+            backend: detect_backend(),
         };
 
         let services = vec![
@@ -2444,6 +2872,7 @@ Restart=no
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: log_dir.clone(),
+            backend: detect_backend(),
         };
 
         let config = ServiceConfig {
@@ -2477,7 +2906,7 @@ Restart=no
     #[cfg(target_os = "linux")]
     fn test_service_label_empty_formula() {
         let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        assert_eq!(manager.service_label(""), "zerobrew..service");
+        assert_eq!(manager.backend().label(""), "zerobrew..service");
     }
 
     #[test]
@@ -2485,11 +2914,11 @@ Restart=no
     fn test_service_label_special_characters() {
         let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
         assert_eq!(
-            manager.service_label("node@18.x"),
+            manager.backend().label("node@18.x"),
             "zerobrew.node@18.x.service"
         );
         assert_eq!(
-            manager.service_label("my_underscore-dash"),
+            manager.backend().label("my_underscore-dash"),
             "zerobrew.my_underscore-dash.service"
         );
     }
@@ -2497,7 +2926,6 @@ Restart=no
     // ==================== Parse Homebrew Systemd Edge Cases ====================
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_with_multiple_restart_types() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -2508,14 +2936,12 @@ Restart=always
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         assert!(config.restart_on_failure);
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_restart_on_abort() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -2526,15 +2952,13 @@ Restart=on-abort
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         // on-abort is not "no", so restart_on_failure should be true
         assert!(config.restart_on_failure);
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_no_working_directory() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -2544,14 +2968,12 @@ ExecStart=/usr/bin/test
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         assert!(config.working_directory.is_none());
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_exec_start_no_args() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -2561,15 +2983,13 @@ ExecStart=/usr/bin/simple-daemon
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         assert_eq!(config.program, PathBuf::from("/usr/bin/simple-daemon"));
         assert!(config.args.is_empty());
     }
 
     #[test]
-    #[cfg(target_os = "linux")]
     fn test_parse_homebrew_systemd_multiple_args() {
         let temp_dir = TempDir::new().unwrap();
         let service_path = temp_dir.path().join("test.service");
@@ -2579,8 +2999,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
 "#;
         std::fs::write(&service_path, content).unwrap();
 
-        let manager = ServiceManager::new(Path::new("/opt/zerobrew"));
-        let config = manager.parse_homebrew_systemd(&service_path).unwrap();
+        let config = Systemd.parse_keg_service(&service_path).unwrap();
 
         assert_eq!(config.program, PathBuf::from("/usr/bin/myapp"));
         assert_eq!(
@@ -2593,7 +3012,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
 
     #[test]
     fn test_service_paths_contains_expected_directories() {
-        let (service_dir, log_dir) = ServiceManager::get_service_paths();
+        let (service_dir, log_dir) = detect_backend().default_paths(Path::new("/home/user"));
 
         // Both paths should be absolute or relative to home
         #[cfg(target_os = "linux")]
@@ -2622,6 +3041,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: log_dir.clone(),
+            backend: detect_backend(),
         };
 
         let config = ServiceConfig {
@@ -2703,6 +3123,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
                 prefix: prefix.clone(),
                 service_dir: service_dir.clone(),
                 log_dir: log_dir.clone(),
+                backend: detect_backend(),
             };
 
             Self {
@@ -2811,6 +3232,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
             stdout_log: Some(PathBuf::from("/var/log/redis/stdout.log")),
             stderr_log: Some(PathBuf::from("/var/log/redis/stderr.log")),
             keep_alive: false,
+            settings: ServiceSettings::default(),
         };
 
         let content = ctx.manager.generate_service_file("redis", &config);
@@ -3036,6 +3458,68 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
         assert_eq!(services[0].file_path, expected_path);
     }
 
+    #[test]
+    fn test_fs_foreign_services_flag_duplicates() {
+        let ctx = TestServiceManager::new();
+        ctx.create_mock_service_file("redis", None);
+        let foreign = ctx.manager.foreign_file_path("redis");
+        std::fs::write(&foreign, "homebrew").unwrap();
+        std::fs::write(ctx.manager.foreign_file_path("postgresql@14"), "homebrew").unwrap();
+
+        let services = ctx.manager.foreign_services().unwrap();
+        let summary: Vec<(&str, bool)> = services
+            .iter()
+            .map(|s| (s.name.as_str(), s.duplicate))
+            .collect();
+        assert_eq!(summary, vec![("postgresql@14", false), ("redis", true)]);
+        assert_eq!(services[1].file_path, foreign);
+
+        // Foreign services are not listed as zerobrew's own
+        let names: Vec<String> = ctx
+            .manager
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["redis"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fs_foreign_service_config_rewrites_paths() {
+        let ctx = TestServiceManager::new();
+        std::fs::write(
+            ctx.service_dir.join("homebrew.redis.service"),
+            "[Unit]\nDescription=Homebrew generated unit for redis\n\n[Service]\n\
+             ExecStart=/home/linuxbrew/.linuxbrew/opt/redis/bin/redis-server /home/linuxbrew/.linuxbrew/etc/redis.conf\n\
+             Restart=always\n\
+             WorkingDirectory=/home/linuxbrew/.linuxbrew/var\n",
+        )
+        .unwrap();
+
+        let config = ctx.manager.foreign_service_config("redis").unwrap();
+        assert_eq!(
+            config.program,
+            ctx.prefix.join("opt/redis/bin/redis-server")
+        );
+        assert_eq!(
+            config.args,
+            vec![ctx.prefix.join("etc/redis.conf").display().to_string()]
+        );
+        assert_eq!(config.working_directory, Some(ctx.prefix.join("var")));
+
+        std::fs::write(
+            ctx.service_dir.join("homebrew.redis.timer"),
+            "[Timer]\nOnCalendar=daily\n",
+        )
+        .unwrap();
+        let err = ctx.manager.foreign_service_config("redis").unwrap_err();
+        assert!(err.to_string().contains("runs on a schedule"), "{err}");
+
+        assert!(ctx.manager.foreign_service_config("postgresql").is_err());
+    }
+
     // --- Orphan Service Detection Tests ---
 
     #[test]
@@ -3159,6 +3643,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
             prefix: temp_dir.path().to_path_buf(),
             service_dir: service_dir.clone(),
             log_dir: log_dir.clone(),
+            backend: detect_backend(),
         };
 
         let config = ServiceConfig {
@@ -3452,6 +3937,7 @@ ExecStart=/usr/bin/myapp --config /etc/myapp.conf --verbose --port 8080
     // --- Cleanup Services Tests ---
 
     #[test]
+    #[allow(clippy::single_match)]
     fn test_fs_cleanup_removes_multiple_services() {
         let ctx = TestServiceManager::new();
 
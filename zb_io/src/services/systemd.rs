@@ -0,0 +1,346 @@
+//! systemd user units, managed with `systemctl --user`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use zb_core::Error;
+
+use super::{
+    DEFAULT_RESTART_DELAY_SECS, RESTART_BACKOFF_STEPS, RestartHistory, ServiceBackend,
+    ServiceConfig, ServiceSpec, ServiceStatus, parse_systemd_exits, parse_systemd_restarts,
+    parse_systemd_unit,
+};
+
+/// Services run as systemd user units
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Systemd;
+
+impl Systemd {
+    /// Run `systemctl --user` with `args`, failing with `action` in the
+    /// message when it exits unsuccessfully
+    fn systemctl(&self, args: &[&str], action: &str) -> Result<(), Error> {
+        let output = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .output()
+            .map_err(|e| Error::StoreCorruption {
+                message: format!("failed to {}: {}", action, e),
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::StoreCorruption {
+                message: format!("failed to {}: {}", action, stderr),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Get more details about a failed service
+    fn status_detail(&self, formula: &str) -> String {
+        let label = self.label(formula);
+        let output = Command::new("systemctl")
+            .args(["--user", "status", &label, "--no-pager"])
+            .output();
+
+        if let Ok(out) = output {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            // Look for relevant status lines
+            for line in stdout.lines().chain(stderr.lines()) {
+                if line.contains("Active:") && line.contains("failed") {
+                    return line.trim().to_string();
+                }
+            }
+        }
+        "unknown error".to_string()
+    }
+}
+
+impl ServiceBackend for Systemd {
+    fn name(&self) -> &'static str {
+        "systemd"
+    }
+
+    fn default_paths(&self, home: &Path) -> (PathBuf, PathBuf) {
+        (
+            home.join(".config/systemd/user"),
+            home.join(".local/share/zerobrew/logs"),
+        )
+    }
+
+    fn file_name(&self, formula: &str) -> String {
+        format!("zerobrew.{}.service", formula)
+    }
+
+    fn formula_from_file_name<'a>(&self, file_name: &'a str) -> Option<&'a str> {
+        // zerobrew.formula.service -> formula
+        file_name
+            .strip_prefix("zerobrew.")
+            .and_then(|s| s.strip_suffix(".service"))
+    }
+
+    fn label(&self, formula: &str) -> String {
+        format!("zerobrew.{}.service", formula)
+    }
+
+    fn foreign_file_name(&self, formula: &str) -> String {
+        format!("homebrew.{}.service", formula)
+    }
+
+    fn render(
+        &self,
+        formula: &str,
+        config: &ServiceConfig,
+        (stdout_log, stderr_log): (&Path, &Path),
+    ) -> String {
+        let mut unit = format!(
+            r#"[Unit]
+Description=Zerobrew: {formula}
+After=network.target
+
+[Service]
+Type=simple
+ExecStart={program}"#,
+            formula = formula,
+            program = config.program.display(),
+        );
+
+        // Add arguments if any
+        if !config.args.is_empty() {
+            for arg in &config.args {
+                unit.push_str(&format!(" {}", arg));
+            }
+        }
+        unit.push('\n');
+
+        // Working directory
+        if let Some(ref wd) = config.working_directory {
+            unit.push_str(&format!("WorkingDirectory={}\n", wd.display()));
+        }
+
+        // Environment variables
+        for (key, value) in &config.environment {
+            unit.push_str(&format!("Environment=\"{}={}\"\n", key, value));
+        }
+
+        // Run-as user, priorities and limits
+        unit.push_str(&config.settings.systemd_directives());
+
+        // Restart policy
+        if config.restart_on_failure {
+            unit.push_str("Restart=on-failure\n");
+            unit.push_str(&format!(
+                "RestartSec={}\n",
+                config
+                    .settings
+                    .restart_delay
+                    .unwrap_or(DEFAULT_RESTART_DELAY_SECS)
+            ));
+            if let Some(max_delay) = config.settings.restart_max_delay {
+                unit.push_str(&format!("RestartSteps={}\n", RESTART_BACKOFF_STEPS));
+                unit.push_str(&format!("RestartMaxDelaySec={}\n", max_delay));
+            }
+        }
+
+        // Logging
+        unit.push_str(&format!("StandardOutput=append:{}\n", stdout_log.display()));
+        unit.push_str(&format!("StandardError=append:{}\n", stderr_log.display()));
+
+        // Install section
+        unit.push_str("\n[Install]\n");
+        if config.run_at_load {
+            unit.push_str("WantedBy=default.target\n");
+        }
+
+        unit
+    }
+
+    fn read_spec(&self, file: &Path) -> Option<ServiceSpec> {
+        let content = std::fs::read_to_string(file).ok()?;
+        let timer = std::fs::read_to_string(file.with_extension("timer")).ok();
+        Some(parse_systemd_unit(&content, timer.as_deref()))
+    }
+
+    fn keg_service_file(&self, keg_path: &Path, formula: &str) -> PathBuf {
+        keg_path
+            .join("systemd")
+            .join(format!("{}.service", formula))
+    }
+
+    fn parse_keg_service(&self, path: &Path) -> Option<ServiceConfig> {
+        let content = std::fs::read_to_string(path).ok()?;
+
+        let mut config = ServiceConfig::default();
+
+        for line in content.lines() {
+            let line = line.trim();
+            if let Some(value) = line.strip_prefix("ExecStart=") {
+                let parts: Vec<&str> = value.split_whitespace().collect();
+                if !parts.is_empty() {
+                    config.program = PathBuf::from(parts[0]);
+                    config.args = parts[1..].iter().map(|s| s.to_string()).collect();
+                }
+            } else if let Some(value) = line.strip_prefix("WorkingDirectory=") {
+                config.working_directory = Some(PathBuf::from(value));
+            } else if line.starts_with("Restart=") && line != "Restart=no" {
+                config.restart_on_failure = true;
+            }
+        }
+
+        if config.program.as_os_str().is_empty() {
+            return None;
+        }
+
+        Some(config)
+    }
+
+    fn status(&self, formula: &str) -> ServiceStatus {
+        let label = self.label(formula);
+        let output = Command::new("systemctl")
+            .args(["--user", "is-active", &label])
+            .output();
+
+        match output {
+            Ok(out) => {
+                let status = String::from_utf8_lossy(&out.stdout).trim().to_string();
+                match status.as_str() {
+                    "active" => ServiceStatus::Running,
+                    "inactive" | "dead" => ServiceStatus::Stopped,
+                    // Get more details about the failure
+                    "failed" => ServiceStatus::Error(self.status_detail(formula)),
+                    _ => ServiceStatus::Unknown,
+                }
+            }
+            Err(_) => ServiceStatus::Unknown,
+        }
+    }
+
+    fn pid(&self, formula: &str) -> Option<u32> {
+        let label = self.label(formula);
+        let out = Command::new("systemctl")
+            .args(["--user", "show", &label, "--property=MainPID"])
+            .output()
+            .ok()?;
+
+        // Output is like: MainPID=12345
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|line| line.strip_prefix("MainPID="))
+            .filter_map(|pid| pid.trim().parse::<u32>().ok())
+            .find(|pid| *pid > 0)
+    }
+
+    fn restart_history(&self, formula: &str) -> RestartHistory {
+        let label = self.label(formula);
+        let restarts = Command::new("systemctl")
+            .args(["--user", "show", &label, "--property=NRestarts"])
+            .output()
+            .map(|out| parse_systemd_restarts(&String::from_utf8_lossy(&out.stdout)))
+            .unwrap_or(0);
+        let exits = Command::new("journalctl")
+            .args([
+                "--user",
+                "-u",
+                &label,
+                "-o",
+                "short-iso",
+                "-n",
+                "500",
+                "--no-pager",
+            ])
+            .output()
+            .map(|out| parse_systemd_exits(&String::from_utf8_lossy(&out.stdout)))
+            .unwrap_or_default();
+
+        RestartHistory { restarts, exits }
+    }
+
+    fn is_enabled(&self, formula: &str, _file: &Path) -> bool {
+        let label = self.label(formula);
+        Command::new("systemctl")
+            .args(["--user", "is-enabled", &label])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim() == "enabled")
+            .unwrap_or(false)
+    }
+
+    fn start(&self, formula: &str, _file: &Path) -> Result<(), Error> {
+        self.systemctl(&["start", &self.label(formula)], "start service")
+    }
+
+    fn stop(&self, formula: &str, _file: &Path) -> Result<(), Error> {
+        self.systemctl(&["stop", &self.label(formula)], "stop service")
+    }
+
+    fn enable(&self, formula: &str, _file: &Path) -> Result<(), Error> {
+        self.systemctl(&["enable", &self.label(formula)], "enable service")
+    }
+
+    fn disable(&self, formula: &str, _file: &Path) -> Result<(), Error> {
+        self.systemctl(&["disable", &self.label(formula)], "disable service")
+    }
+
+    fn reload(&self) -> Result<(), Error> {
+        self.systemctl(&["daemon-reload"], "reload daemon")
+    }
+
+    fn stop_foreign(&self, formula: &str, _file: &Path) {
+        let _ = self.systemctl(
+            &["disable", "--now", &self.foreign_file_name(formula)],
+            "stop Homebrew service",
+        );
+    }
+
+    fn journal(&self, formula: &str, lines: usize, follow: bool) -> Option<Command> {
+        let mut command = Command::new("journalctl");
+        command.args([
+            "--user",
+            "-u",
+            &self.label(formula),
+            "-n",
+            &lines.to_string(),
+            "--no-pager",
+        ]);
+        if follow {
+            command.arg("-f");
+        }
+        Some(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_round_trip() {
+        let name = Systemd.file_name("postgresql@14");
+        assert_eq!(name, "zerobrew.postgresql@14.service");
+        assert_eq!(Systemd.formula_from_file_name(&name), Some("postgresql@14"));
+        assert_eq!(
+            Systemd.formula_from_file_name("homebrew.redis.service"),
+            None
+        );
+    }
+
+    #[test]
+    fn journal_follows_the_unit() {
+        let command = Systemd.journal("redis", 50, true).unwrap();
+        let args: Vec<_> = command.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(command.get_program(), "journalctl");
+        assert_eq!(
+            args,
+            [
+                "--user",
+                "-u",
+                "zerobrew.redis.service",
+                "-n",
+                "50",
+                "--no-pager",
+                "-f"
+            ]
+        );
+    }
+}
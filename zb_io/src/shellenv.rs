@@ -0,0 +1,169 @@
+//! Whether a shell's environment is set up the way `zb shellenv` sets it.
+//!
+//! `zb shellenv --check` reports this so dotfiles only eval `zb shellenv`
+//! when they need to, and `zb doctor` warns when it isn't.
+
+use std::path::{Path, PathBuf};
+
+/// Directories zerobrew's executables must come before in `PATH`, so the
+/// system's or Homebrew's copies don't hide them
+const SYSTEM_BIN_DIRS: &[&str] = &[
+    "/usr/local/bin",
+    "/usr/local/sbin",
+    "/usr/bin",
+    "/usr/sbin",
+    "/bin",
+    "/sbin",
+    "/opt/homebrew/bin",
+    "/opt/homebrew/sbin",
+    "/home/linuxbrew/.linuxbrew/bin",
+    "/home/linuxbrew/.linuxbrew/sbin",
+];
+
+/// Directories zerobrew's manual pages must come before in `MANPATH`
+const SYSTEM_MAN_DIRS: &[&str] = &[
+    "/usr/local/share/man",
+    "/usr/share/man",
+    "/opt/homebrew/share/man",
+    "/home/linuxbrew/.linuxbrew/share/man",
+];
+
+/// Where a zerobrew directory stands in a search path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvDirState {
+    /// Present, ahead of the system's directories
+    Ok,
+    /// Not in the search path
+    Missing,
+    /// Present, but after `after`, which is searched first
+    Late { after: PathBuf },
+}
+
+/// One zerobrew directory's place in a search path variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvDirCheck {
+    /// Variable the directory belongs in, e.g. `PATH`
+    pub var: &'static str,
+    pub dir: PathBuf,
+    pub state: EnvDirState,
+}
+
+impl EnvDirCheck {
+    pub fn is_ok(&self) -> bool {
+        self.state == EnvDirState::Ok
+    }
+}
+
+/// Check that `PATH` and `MANPATH` hold the prefix's directories ahead of
+/// the system's, as `zb shellenv` puts them
+pub fn check_shellenv(
+    prefix: &Path,
+    path_var: &str,
+    manpath_var: Option<&str>,
+) -> Vec<EnvDirCheck> {
+    let path: Vec<PathBuf> = std::env::split_paths(path_var).collect();
+    let manpath: Vec<PathBuf> = std::env::split_paths(manpath_var.unwrap_or_default()).collect();
+
+    vec![
+        check_dir("PATH", prefix.join("bin"), &path, SYSTEM_BIN_DIRS),
+        check_dir("PATH", prefix.join("sbin"), &path, SYSTEM_BIN_DIRS),
+        check_dir(
+            "MANPATH",
+            prefix.join("share/man"),
+            &manpath,
+            SYSTEM_MAN_DIRS,
+        ),
+    ]
+}
+
+fn check_dir(
+    var: &'static str,
+    dir: PathBuf,
+    entries: &[PathBuf],
+    system_dirs: &[&str],
+) -> EnvDirCheck {
+    let state = match entries.iter().position(|entry| *entry == dir) {
+        None => EnvDirState::Missing,
+        Some(index) => entries[..index]
+            .iter()
+            .find(|entry| {
+                // A prefix installed over a system directory can't come before itself
+                **entry != dir
+                    && system_dirs
+                        .iter()
+                        .any(|system| entry.as_path() == Path::new(system))
+            })
+            .map_or(EnvDirState::Ok, |after| EnvDirState::Late {
+                after: after.clone(),
+            }),
+    };
+    EnvDirCheck { var, dir, state }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PREFIX: &str = "/opt/zerobrew/prefix";
+
+    fn states(path: &str, manpath: Option<&str>) -> Vec<EnvDirState> {
+        check_shellenv(Path::new(PREFIX), path, manpath)
+            .into_iter()
+            .map(|check| check.state)
+            .collect()
+    }
+
+    #[test]
+    fn configured_environment_passes() {
+        let path = format!("{PREFIX}/bin:{PREFIX}/sbin:/usr/bin:/bin");
+        let manpath = format!("{PREFIX}/share/man:");
+        assert!(
+            states(&path, Some(&manpath))
+                .iter()
+                .all(|state| *state == EnvDirState::Ok)
+        );
+    }
+
+    #[test]
+    fn missing_directories_are_reported() {
+        assert_eq!(
+            states("/usr/bin:/bin", None),
+            [
+                EnvDirState::Missing,
+                EnvDirState::Missing,
+                EnvDirState::Missing
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_after_the_system_ones_are_late() {
+        let path = format!("/opt/homebrew/bin:{PREFIX}/bin:/usr/bin:{PREFIX}/sbin");
+        let manpath = format!("/usr/share/man:{PREFIX}/share/man");
+        assert_eq!(
+            states(&path, Some(&manpath)),
+            [
+                EnvDirState::Late {
+                    after: PathBuf::from("/opt/homebrew/bin")
+                },
+                EnvDirState::Late {
+                    after: PathBuf::from("/opt/homebrew/bin")
+                },
+                EnvDirState::Late {
+                    after: PathBuf::from("/usr/share/man")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_directories_may_come_first() {
+        let path = format!("/home/me/bin:{PREFIX}/bin/:{PREFIX}/sbin");
+        let manpath = format!("{PREFIX}/share/man");
+        assert!(
+            states(&path, Some(&manpath))
+                .iter()
+                .all(|s| *s == EnvDirState::Ok)
+        );
+    }
+}
@@ -1,31 +1,48 @@
-use std::fs::{self, File};
-use std::io;
+use std::fs;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 
-use fs4::fs_std::FileExt;
-
-use crate::extract::extract_tarball;
+use crate::dedup::{self, FilePool};
+use crate::extract::{extract_tarball, extract_tarball_from_reader};
+use crate::fs_limits;
+use crate::lock::{self, LockGuard};
+use crate::manifest::Manifest;
+use crate::materialize::copy_dir_with_fallback;
+use crate::tempdir::{self, TempDirGuard};
 use zb_core::Error;
 
+#[derive(Clone)]
 pub struct Store {
     store_dir: PathBuf,
     locks_dir: PathBuf,
+    manifests_dir: PathBuf,
+    pool: FilePool,
 }
 
 impl Store {
     pub fn new(root: &Path) -> io::Result<Self> {
         let store_dir = root.join("store");
         let locks_dir = root.join("locks");
+        let manifests_dir = root.join("manifests");
 
         fs::create_dir_all(&store_dir)?;
         fs::create_dir_all(&locks_dir)?;
+        fs::create_dir_all(&manifests_dir)?;
+        let pool = FilePool::new(&root.join("files"))?;
 
         Ok(Self {
             store_dir,
             locks_dir,
+            manifests_dir,
+            pool,
         })
     }
 
+    /// The zerobrew root this store lives in
+    pub fn root(&self) -> &Path {
+        self.store_dir.parent().unwrap_or(&self.store_dir)
+    }
+
     pub fn entry_path(&self, store_key: &str) -> PathBuf {
         self.store_dir.join(store_key)
     }
@@ -35,6 +52,54 @@ impl Store {
     }
 
     pub fn ensure_entry(&self, store_key: &str, blob_path: &Path) -> Result<PathBuf, Error> {
+        self.ingest(store_key, |dir| extract_tarball(blob_path, dir))
+    }
+
+    /// Unpack a gzipped tarball from `reader` into the store under `store_key`.
+    ///
+    /// This is part of the stable API for external `zb-*` commands. The entry
+    /// is unpacked into a temp directory, its manifest recorded, and then
+    /// renamed into place, so readers never see a partial entry. If the entry
+    /// already exists the reader is not consumed. Returns the entry path.
+    pub fn ingest_reader<R: Read>(&self, store_key: &str, reader: R) -> Result<PathBuf, Error> {
+        if !is_valid_store_key(store_key) {
+            return Err(Error::StoreCorruption {
+                message: format!("invalid store key '{store_key}'"),
+            });
+        }
+        self.ingest(store_key, |dir| extract_tarball_from_reader(reader, dir))
+    }
+
+    /// Copy the keg at `keg_path` into the store under `store_key`, laid out
+    /// like a bottle as `<name>/<version>/`, and record its manifest. Used
+    /// for kegs zb didn't pour itself. Returns the entry path.
+    pub fn ingest_keg(
+        &self,
+        store_key: &str,
+        name: &str,
+        version: &str,
+        keg_path: &Path,
+    ) -> Result<PathBuf, Error> {
+        if !is_valid_store_key(store_key) {
+            return Err(Error::StoreCorruption {
+                message: format!("invalid store key '{store_key}'"),
+            });
+        }
+        self.ingest(store_key, |dir| {
+            let dst = dir.join(name).join(version);
+            fs::create_dir_all(dir.join(name)).map_err(|e| Error::StoreCorruption {
+                message: format!("failed to create store entry directory: {e}"),
+            })?;
+            copy_dir_with_fallback(keg_path, &dst)
+        })
+    }
+
+    /// Create a store entry with `unpack` unless it already exists
+    fn ingest(
+        &self,
+        store_key: &str,
+        unpack: impl FnOnce(&Path) -> Result<(), Error>,
+    ) -> Result<PathBuf, Error> {
         let entry_path = self.entry_path(store_key);
 
         // Fast path: already exists
@@ -43,55 +108,40 @@ impl Store {
         }
 
         // Acquire exclusive lock for this store_key
-        let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
-        let lock_file = File::create(&lock_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create lock file: {e}"),
-        })?;
-
-        lock_file
-            .lock_exclusive()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to acquire lock: {e}"),
-            })?;
+        let lock_path = lock::store_key_lock_path(&self.locks_dir, store_key);
+        let _lock = lock::acquire(&lock_path)?;
 
         // Double-check after acquiring lock (another process may have created it)
         if entry_path.exists() {
-            // Lock will be released when lock_file is dropped
+            // Lock will be released when _lock is dropped
             return Ok(entry_path);
         }
 
-        // Unpack to a temp directory first
-        let tmp_dir = self
-            .store_dir
-            .join(format!(".{store_key}.tmp.{}", std::process::id()));
+        // Unpack to a temp directory first (removed on drop if anything fails)
+        let tmp_dir = TempDirGuard::new(&self.store_dir, store_key)?;
 
-        // Clean up any leftover temp directory from a previous interrupted extraction
-        // (can happen if the process crashed or was killed during extraction)
-        if tmp_dir.exists() {
-            let _ = fs::remove_dir_all(&tmp_dir);
+        // Extract the tarball. A filesystem out of inodes fails with "no
+        // space left" even with free bytes, so name that cause instead.
+        if let Err(e) = unpack(tmp_dir.path()) {
+            fs_limits::check_free_inodes(&self.store_dir, 1)?;
+            return Err(e);
         }
 
-        fs::create_dir_all(&tmp_dir).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create temp directory: {e}"),
-        })?;
+        // Record file hashes before anything can touch the extracted files
+        let manifest = Manifest::build(tmp_dir.path())?;
+        manifest.save(&self.manifest_path(store_key))?;
 
-        // Extract the tarball
-        if let Err(e) = extract_tarball(blob_path, &tmp_dir) {
-            // Clean up temp directory on failure
-            let _ = fs::remove_dir_all(&tmp_dir);
-            return Err(e);
-        }
+        // Kegs may hardlink these files, so writes through a keg must fail
+        // rather than change the entry
+        make_read_only(tmp_dir.path(), &manifest);
+
+        // Share files identical to ones other entries already hold
+        self.pool.dedup(tmp_dir.path(), &manifest);
 
         // Atomically rename temp dir to final path
-        if let Err(e) = fs::rename(&tmp_dir, &entry_path) {
-            // Clean up temp directory on failure
-            let _ = fs::remove_dir_all(&tmp_dir);
-            return Err(Error::StoreCorruption {
-                message: format!("failed to rename store entry: {e}"),
-            });
-        }
+        tmp_dir.persist(&entry_path)?;
 
-        // Lock will be released when lock_file is dropped
+        // Lock will be released when _lock is dropped
         Ok(entry_path)
     }
 
@@ -104,16 +154,8 @@ impl Store {
         }
 
         // Acquire exclusive lock for this store_key
-        let lock_path = self.locks_dir.join(format!("{store_key}.lock"));
-        let lock_file = File::create(&lock_path).map_err(|e| Error::StoreCorruption {
-            message: format!("failed to create lock file: {e}"),
-        })?;
-
-        lock_file
-            .lock_exclusive()
-            .map_err(|e| Error::StoreCorruption {
-                message: format!("failed to acquire lock: {e}"),
-            })?;
+        let lock_path = lock::store_key_lock_path(&self.locks_dir, store_key);
+        let _lock = lock::acquire(&lock_path)?;
 
         // Remove the directory
         if entry_path.exists() {
@@ -122,12 +164,61 @@ impl Store {
             })?;
         }
 
-        // Clean up the lock file
+        // Clean up the lock file and manifest
         let _ = fs::remove_file(&lock_path);
+        let _ = fs::remove_file(self.manifest_path(store_key));
 
         Ok(())
     }
 
+    /// Lock a formula against concurrent installs, upgrades and removals
+    /// by other zb processes. Blocks until the lock is free.
+    pub fn lock_formula(&self, name: &str) -> Result<LockGuard, Error> {
+        lock::acquire(&lock::formula_lock_path(&self.locks_dir, name))
+    }
+
+    /// Lock several formulas at once. Locks are taken in name order so two
+    /// processes locking overlapping sets can't deadlock.
+    pub fn lock_formulas<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<LockGuard>, Error> {
+        let names: std::collections::BTreeSet<&str> = names.into_iter().collect();
+        names
+            .into_iter()
+            .map(|name| self.lock_formula(name))
+            .collect()
+    }
+
+    /// Lock a formula if no other process holds it
+    pub fn try_lock_formula(&self, name: &str) -> Result<Option<LockGuard>, Error> {
+        lock::try_acquire(&lock::formula_lock_path(&self.locks_dir, name))
+    }
+
+    pub fn manifest_path(&self, store_key: &str) -> PathBuf {
+        self.manifests_dir.join(format!("{store_key}.json"))
+    }
+
+    /// Load the file manifest of a store entry.
+    /// Entries extracted before manifests existed get one built on first use.
+    pub fn manifest(&self, store_key: &str) -> Result<Manifest, Error> {
+        let path = self.manifest_path(store_key);
+        if let Some(manifest) = Manifest::load(&path)? {
+            return Ok(manifest);
+        }
+
+        let entry_path = self.entry_path(store_key);
+        if !entry_path.exists() {
+            return Err(Error::StoreCorruption {
+                message: format!("store entry {store_key} does not exist"),
+            });
+        }
+
+        let manifest = Manifest::build(&entry_path)?;
+        manifest.save(&path)?;
+        Ok(manifest)
+    }
+
     /// List all store entries (directories in the store)
     pub fn list_entries(&self) -> io::Result<Vec<String>> {
         let mut entries = Vec::new();
@@ -148,11 +239,59 @@ impl Store {
         Ok(entries)
     }
 
+    /// Versions of a formula found in store entries, as (version, store key)
+    /// pairs. Bottles unpack to `{name}/{version}/`, so entries outlive the
+    /// kegs made from them until `zb gc` removes them.
+    pub fn formula_versions(&self, name: &str) -> io::Result<Vec<(String, String)>> {
+        let mut versions = Vec::new();
+
+        for store_key in self.list_entries()? {
+            let formula_dir = self.entry_path(&store_key).join(name);
+            let Ok(dir) = fs::read_dir(&formula_dir) else {
+                continue;
+            };
+            for entry in dir.filter_map(|e| e.ok()) {
+                if entry.path().is_dir()
+                    && let Some(version) = entry.file_name().to_str()
+                {
+                    versions.push((version.to_string(), store_key.clone()));
+                }
+            }
+        }
+
+        versions.sort();
+        Ok(versions)
+    }
+
     /// Get the total size of the store
     pub fn total_size(&self) -> io::Result<u64> {
         dir_size(&self.store_dir)
     }
 
+    /// Get the size of a single store entry
+    pub fn entry_size(&self, store_key: &str) -> io::Result<u64> {
+        dir_size(&self.entry_path(store_key))
+    }
+
+    /// Disk used by several store entries together, counting files they
+    /// share through the file pool once
+    pub fn entries_disk_usage<'a>(
+        &self,
+        store_keys: impl IntoIterator<Item = &'a str>,
+    ) -> io::Result<u64> {
+        let paths: Vec<PathBuf> = store_keys
+            .into_iter()
+            .map(|key| self.entry_path(key))
+            .collect();
+        dedup::disk_usage(paths.iter().map(PathBuf::as_path))
+    }
+
+    /// Remove pooled files no store entry or keg links to any more.
+    /// Returns the number of files removed and the bytes freed.
+    pub fn prune_files(&self) -> io::Result<(usize, u64)> {
+        self.pool.prune()
+    }
+
     /// Clean up stale lock files (locks without corresponding store entries)
     /// Returns the number of lock files removed
     pub fn cleanup_stale_locks(&self) -> io::Result<usize> {
@@ -176,31 +315,15 @@ impl Store {
         Ok(count)
     }
 
-    /// Clean up stale temp directories from interrupted extractions
+    /// Create a temp directory in the store that is removed on drop
+    pub fn temp_dir(&self, label: &str) -> Result<TempDirGuard, Error> {
+        TempDirGuard::new(&self.store_dir, label)
+    }
+
+    /// Clean up temp directories left behind by processes that are no longer running
     /// Returns the count of directories removed and total bytes freed
     pub fn cleanup_temp_dirs(&self) -> io::Result<(usize, u64)> {
-        let mut count = 0;
-        let mut bytes_freed = 0;
-
-        for entry in fs::read_dir(&self.store_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.is_dir()
-                && let Some(name) = path.file_name().and_then(|n| n.to_str())
-                && name.starts_with('.')
-                && name.contains(".tmp.")
-            {
-                // Temp directories start with "." and contain ".tmp."
-                let size = dir_size(&path).unwrap_or(0);
-                if fs::remove_dir_all(&path).is_ok() {
-                    count += 1;
-                    bytes_freed += size;
-                }
-            }
-        }
-
-        Ok((count, bytes_freed))
+        tempdir::reap_stale(&self.store_dir)
     }
 }
 
@@ -223,6 +346,32 @@ fn dir_size(path: &Path) -> io::Result<u64> {
     Ok(total)
 }
 
+/// A store key names one directory directly under the store
+fn is_valid_store_key(store_key: &str) -> bool {
+    !store_key.is_empty()
+        && store_key != "."
+        && store_key != ".."
+        && !store_key.starts_with(".")
+        && !store_key.contains(['/', '\\'])
+}
+
+/// Clear the write bits of the files `manifest` lists under `root`.
+/// Best effort: a file whose permissions can't be changed is left as is.
+fn make_read_only(root: &Path, manifest: &Manifest) {
+    use std::os::unix::fs::PermissionsExt;
+    for entry in &manifest.entries {
+        let path = root.join(&entry.path);
+        if let Ok(meta) = fs::symlink_metadata(&path)
+            && meta.file_type().is_file()
+        {
+            let mode = meta.permissions().mode();
+            if mode & 0o222 != 0 {
+                let _ = fs::set_permissions(&path, fs::Permissions::from_mode(mode & !0o222));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -252,6 +401,107 @@ mod tests {
         encoder.finish().unwrap()
     }
 
+    #[test]
+    fn ingest_reader_creates_entry_with_manifest() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let tarball = create_test_tarball(b"from a reader");
+        let path = store
+            .ingest_reader("reader123", tarball.as_slice())
+            .unwrap();
+
+        assert_eq!(path, store.entry_path("reader123"));
+        assert_eq!(fs::read(path.join("test.txt")).unwrap(), b"from a reader");
+        assert!(store.manifest("reader123").is_ok());
+
+        // An existing entry is returned without reading anything
+        let again = store.ingest_reader("reader123", std::io::empty()).unwrap();
+        assert_eq!(again, path);
+    }
+
+    #[test]
+    fn ingest_keg_lays_out_entry_like_a_bottle() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(&tmp.path().join("root")).unwrap();
+        let keg = tmp.path().join("cellar/foo/1.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/foo"), b"#!/bin/sh\n").unwrap();
+
+        let path = store
+            .ingest_keg("adopted-foo-1.0", "foo", "1.0", &keg)
+            .unwrap();
+
+        assert_eq!(path, store.entry_path("adopted-foo-1.0"));
+        assert_eq!(
+            fs::read(path.join("foo/1.0/bin/foo")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+        assert!(store.manifest_path("adopted-foo-1.0").exists());
+        assert!(store.ingest_keg("../foo", "foo", "1.0", &keg).is_err());
+    }
+
+    #[test]
+    fn ingested_files_are_read_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let path = store
+            .ingest_reader("ro123", create_test_tarball(b"shared").as_slice())
+            .unwrap();
+
+        let mode = fs::metadata(path.join("test.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o444);
+
+        // Entries can still be removed
+        store.remove_entry("ro123").unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn identical_files_across_entries_are_stored_once() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+        let tarball = create_test_tarball(b"shared between versions");
+        let old = store.ingest_reader("old123", tarball.as_slice()).unwrap();
+        let new = store.ingest_reader("new456", tarball.as_slice()).unwrap();
+
+        let ino = |dir: &Path| fs::metadata(dir.join("test.txt")).unwrap().ino();
+        assert_eq!(ino(&old), ino(&new));
+        let size = store.entry_size("old123").unwrap();
+        assert_eq!(
+            store.entries_disk_usage(["old123", "new456"]).unwrap(),
+            size
+        );
+
+        // The pooled copy outlives one entry but not both
+        store.remove_entry("old123").unwrap();
+        assert_eq!(store.prune_files().unwrap().0, 0);
+        store.remove_entry("new456").unwrap();
+        assert_eq!(store.prune_files().unwrap(), (1, size));
+    }
+
+    #[test]
+    fn ingest_reader_rejects_keys_outside_store() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        for key in ["", "..", "../escape", "a/b", ".hidden"] {
+            let tarball = create_test_tarball(b"x");
+            assert!(
+                store.ingest_reader(key, tarball.as_slice()).is_err(),
+                "{key}"
+            );
+        }
+        assert!(!tmp.path().join("escape").exists());
+    }
+
     #[test]
     fn second_call_is_noop() {
         let tmp = TempDir::new().unwrap();
@@ -348,6 +598,26 @@ mod tests {
         assert!(store.has_entry(store_key));
     }
 
+    #[test]
+    fn formula_versions_finds_extracted_bottles() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        for (key, version) in [("aaa", "1.0.0"), ("bbb", "1.1.0")] {
+            fs::create_dir_all(store.entry_path(key).join("jq").join(version).join("bin")).unwrap();
+        }
+        fs::create_dir_all(store.entry_path("ccc").join("wget/1.24")).unwrap();
+
+        assert_eq!(
+            store.formula_versions("jq").unwrap(),
+            vec![
+                ("1.0.0".to_string(), "aaa".to_string()),
+                ("1.1.0".to_string(), "bbb".to_string()),
+            ]
+        );
+        assert!(store.formula_versions("curl").unwrap().is_empty());
+    }
+
     #[test]
     fn list_entries_returns_all_store_entries() {
         let tmp = TempDir::new().unwrap();
@@ -394,6 +664,40 @@ mod tests {
         assert!(!locks_dir.join("orphan2.lock").exists());
     }
 
+    #[test]
+    fn cleanup_stale_locks_keeps_formula_locks() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let guard = store.lock_formula("jq").unwrap();
+        let lock_path = guard.path().to_path_buf();
+        drop(guard);
+
+        store.cleanup_stale_locks().unwrap();
+        assert!(lock_path.exists());
+    }
+
+    #[test]
+    fn formula_locks_serialize_same_formula_only() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let _jq = store.lock_formula("jq").unwrap();
+        assert!(store.try_lock_formula("jq").unwrap().is_none());
+        assert!(store.try_lock_formula("wget").unwrap().is_some());
+    }
+
+    #[test]
+    fn lock_formulas_dedupes_names() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        // A duplicate name would deadlock on its own lock
+        let guards = store.lock_formulas(["wget", "jq", "wget"]).unwrap();
+        assert_eq!(guards.len(), 2);
+        assert!(store.try_lock_formula("openssl@3").unwrap().is_some());
+    }
+
     #[test]
     fn cleanup_temp_dirs_removes_stale_temp_directories() {
         let tmp = TempDir::new().unwrap();
@@ -432,4 +736,38 @@ mod tests {
         // Size should be > 0 (includes the extracted content)
         assert!(size > 0);
     }
+
+    #[test]
+    fn ensure_entry_writes_manifest() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let tarball = create_test_tarball(b"hello world");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+        store.ensure_entry("abc123", &blob_path).unwrap();
+
+        assert!(store.manifest_path("abc123").exists());
+        let manifest = store.manifest("abc123").unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries[0].path, "test.txt");
+
+        store.remove_entry("abc123").unwrap();
+        assert!(!store.manifest_path("abc123").exists());
+    }
+
+    #[test]
+    fn manifest_is_built_for_entries_without_one() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let entry = store.entry_path("legacy");
+        fs::create_dir_all(&entry).unwrap();
+        fs::write(entry.join("file.txt"), b"legacy").unwrap();
+
+        let manifest = store.manifest("legacy").unwrap();
+        assert_eq!(manifest.entries.len(), 1);
+        assert!(store.manifest_path("legacy").exists());
+        assert!(store.manifest("missing").is_err());
+    }
 }
@@ -37,6 +37,19 @@ pub struct TapInfo {
     pub updated_at: Option<i64>,
 }
 
+/// What refreshing a tap's cached formulas changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TapRefresh {
+    /// Full tap name in "user/repo" format
+    pub tap: String,
+    /// Cached formulas fetched again
+    pub refreshed: usize,
+    /// `(formula, old version, new version)` for formulas whose version changed
+    pub updated: Vec<(String, String, String)>,
+    /// Formulas no longer found in the tap, whose cached copies were dropped
+    pub removed: Vec<String>,
+}
+
 /// Result of parsing a tap reference like "user/repo/formula"
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TapFormula {
@@ -68,6 +81,15 @@ impl TapFormula {
         }
     }
 
+    /// Name a formula is installed under: `formula` for `user/repo/formula`,
+    /// anything else unchanged
+    pub fn formula_name(name: &str) -> &str {
+        match name.split('/').collect::<Vec<_>>()[..] {
+            [_, _, formula] => formula,
+            _ => name,
+        }
+    }
+
     /// Get the full tap name in "user/repo" format
     pub fn tap_name(&self) -> String {
         format!("{}/{}", self.user, self.repo)
@@ -85,6 +107,8 @@ pub struct TapManager {
     taps_dir: PathBuf,
     /// HTTP client for fetching formulas
     client: reqwest::Client,
+    /// Where tap repositories' files are fetched from
+    raw_url: String,
 }
 
 impl TapManager {
@@ -98,9 +122,17 @@ impl TapManager {
         Self {
             taps_dir: taps_dir.to_path_buf(),
             client,
+            raw_url: "https://raw.githubusercontent.com".to_string(),
         }
     }
 
+    /// Fetch tap repositories' files from `raw_url` instead of
+    /// raw.githubusercontent.com, e.g. from a mirror
+    pub fn with_raw_url(mut self, raw_url: &str) -> Self {
+        self.raw_url = raw_url.trim_end_matches('/').to_string();
+        self
+    }
+
     /// Get the directory for a specific tap
     fn tap_dir(&self, user: &str, repo: &str) -> PathBuf {
         self.taps_dir.join(user).join(repo)
@@ -309,13 +341,15 @@ impl TapManager {
         let cache_path = self.formula_path(user, repo, name);
         if cache_path.exists()
             && let Ok(json) = fs::read_to_string(&cache_path)
-            && let Ok(formula) = serde_json::from_str::<Formula>(&json)
+            && let Ok(mut formula) = serde_json::from_str::<Formula>(&json)
         {
+            formula.tap.get_or_insert_with(|| format!("{user}/{repo}"));
             return Ok(formula);
         }
 
         // Fetch from GitHub - try the API first
-        let formula = self.fetch_formula_from_github(user, repo, name).await?;
+        let mut formula = self.fetch_formula_from_github(user, repo, name).await?;
+        formula.tap.get_or_insert_with(|| format!("{user}/{repo}"));
 
         // Cache the result
         if let Ok(json) = serde_json::to_string_pretty(&formula) {
@@ -327,6 +361,91 @@ impl TapManager {
         Ok(formula)
     }
 
+    /// Fetch a formula from the tap's current HEAD, replacing the cached
+    /// copy, and note the time in the tap's info as when it was last updated
+    pub async fn refresh_formula(
+        &self,
+        user: &str,
+        repo: &str,
+        name: &str,
+    ) -> Result<Formula, Error> {
+        let repo = repo.strip_prefix("homebrew-").unwrap_or(repo);
+        if !self.is_tapped(user, repo) {
+            return Err(Error::MissingFormula {
+                name: format!(
+                    "{}/{}/{} (tap not installed, run: zb tap {}/{})",
+                    user, repo, name, user, repo
+                ),
+            });
+        }
+
+        let mut formula = self.fetch_formula_from_github(user, repo, name).await?;
+        formula.tap.get_or_insert_with(|| format!("{user}/{repo}"));
+
+        if let Ok(json) = serde_json::to_string_pretty(&formula) {
+            let _ = fs::create_dir_all(self.formula_dir(user, repo));
+            let _ = fs::write(self.formula_path(user, repo, name), json);
+        }
+        if let Some(mut info) = self.tap_info(user, repo) {
+            info.updated_at = Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+            );
+            if let Ok(json) = serde_json::to_string_pretty(&info) {
+                let _ = fs::write(self.tap_info_path(user, repo), json);
+            }
+        }
+
+        Ok(formula)
+    }
+
+    /// Fetch every cached formula of a tap from its current HEAD, dropping
+    /// the ones the tap no longer has
+    pub async fn refresh_tap(&self, user: &str, repo: &str) -> Result<TapRefresh, Error> {
+        let repo = repo.strip_prefix("homebrew-").unwrap_or(repo);
+        let mut refresh = TapRefresh {
+            tap: format!("{user}/{repo}"),
+            ..TapRefresh::default()
+        };
+
+        for name in self.list_formulas(user, repo)? {
+            let path = self.formula_path(user, repo, &name);
+            let old_version = fs::read_to_string(&path)
+                .ok()
+                .and_then(|json| serde_json::from_str::<Formula>(&json).ok())
+                .map(|formula| formula.versions.stable);
+
+            match self.refresh_formula(user, repo, &name).await {
+                Ok(formula) => {
+                    refresh.refreshed += 1;
+                    if let Some(old_version) = old_version
+                        && old_version != formula.versions.stable
+                    {
+                        refresh
+                            .updated
+                            .push((name, old_version, formula.versions.stable));
+                    }
+                }
+                Err(Error::MissingFormula { .. }) => {
+                    let _ = fs::remove_file(&path);
+                    refresh.removed.push(name);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(refresh)
+    }
+
+    /// Metadata of an installed tap
+    pub fn tap_info(&self, user: &str, repo: &str) -> Option<TapInfo> {
+        let repo = repo.strip_prefix("homebrew-").unwrap_or(repo);
+        let json = fs::read_to_string(self.tap_info_path(user, repo)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
     /// Fetch a formula JSON from GitHub
     async fn fetch_formula_from_github(
         &self,
@@ -372,10 +491,7 @@ impl TapManager {
         ];
 
         for path in &paths_to_try {
-            let url = format!(
-                "https://raw.githubusercontent.com/{}/homebrew-{}/HEAD/{}",
-                user, repo, path
-            );
+            let url = format!("{}/{}/homebrew-{}/HEAD/{}", self.raw_url, user, repo, path);
 
             let response = self.client.get(&url).send().await;
 
@@ -416,8 +532,8 @@ impl TapManager {
             // Try both HEAD and main/master branches
             for branch in &["HEAD", "main", "master"] {
                 let url = format!(
-                    "https://raw.githubusercontent.com/{}/homebrew-{}/{}/{}",
-                    user, repo, branch, path
+                    "{}/{}/homebrew-{}/{}/{}",
+                    self.raw_url, user, repo, branch, path
                 );
 
                 let response = self.client.get(&url).send().await;
@@ -569,6 +685,14 @@ mod tests {
         assert_eq!(tf.formula, "formula789");
     }
 
+    #[test]
+    fn tap_formula_name_strips_tap() {
+        assert_eq!(TapFormula::formula_name("user/repo/formula"), "formula");
+        assert_eq!(TapFormula::formula_name("formula"), "formula");
+        assert_eq!(TapFormula::formula_name("user/formula"), "user/formula");
+        assert_eq!(TapFormula::formula_name("a/b/c/d"), "a/b/c/d");
+    }
+
     #[test]
     fn tap_formula_equality() {
         let tf1 = TapFormula::parse("user/repo/formula").unwrap();
@@ -836,6 +960,7 @@ mod tests {
         let manager = TapManager {
             taps_dir: tmp.path().to_path_buf(),
             client,
+            raw_url: mock_server.uri(),
         };
 
         // Manually construct the URL for the mock
@@ -1754,4 +1879,106 @@ end
         assert_eq!(taps[1].name, "mike/middle");
         assert_eq!(taps[2].name, "zebra/zoo");
     }
+
+    #[tokio::test]
+    async fn refresh_formula_fetches_head_and_records_update() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/homebrew-repo/HEAD/Formula/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name": "foo", "versions": {"stable": "2.0.0"}, "dependencies": []}"#,
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let manager = TapManager::new(tmp.path()).with_raw_url(&mock_server.uri());
+        fs::create_dir_all(manager.formula_dir("user", "repo")).unwrap();
+        let info = TapInfo {
+            name: "user/repo".to_string(),
+            url: "https://github.com/user/homebrew-repo".to_string(),
+            added_at: 12345,
+            updated_at: None,
+        };
+        fs::write(
+            manager.tap_info_path("user", "repo"),
+            serde_json::to_string(&info).unwrap(),
+        )
+        .unwrap();
+        fs::write(
+            manager.formula_path("user", "repo", "foo"),
+            r#"{"name": "foo", "versions": {"stable": "1.0.0"}, "dependencies": []}"#,
+        )
+        .unwrap();
+
+        // The cached copy is used until the formula is refreshed
+        let formula = manager.get_formula("user", "repo", "foo").await.unwrap();
+        assert_eq!(formula.versions.stable, "1.0.0");
+
+        let formula = manager
+            .refresh_formula("user", "repo", "foo")
+            .await
+            .unwrap();
+        assert_eq!(formula.versions.stable, "2.0.0");
+        assert_eq!(formula.tap.as_deref(), Some("user/repo"));
+        let formula = manager.get_formula("user", "repo", "foo").await.unwrap();
+        assert_eq!(formula.versions.stable, "2.0.0");
+        assert!(
+            manager
+                .tap_info("user", "repo")
+                .unwrap()
+                .updated_at
+                .is_some()
+        );
+
+        assert!(matches!(
+            manager.refresh_formula("user", "other", "foo").await,
+            Err(Error::MissingFormula { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_tap_reports_updated_and_removed_formulas() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/user/homebrew-repo/HEAD/Formula/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name": "foo", "versions": {"stable": "2.0.0"}, "dependencies": []}"#,
+            ))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/user/homebrew-repo/HEAD/Formula/same.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"{"name": "same", "versions": {"stable": "1.0.0"}, "dependencies": []}"#,
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let manager = TapManager::new(tmp.path()).with_raw_url(&mock_server.uri());
+        fs::create_dir_all(manager.formula_dir("user", "repo")).unwrap();
+        for name in ["foo", "gone", "same"] {
+            fs::write(
+                manager.formula_path("user", "repo", name),
+                format!(
+                    r#"{{"name": "{name}", "versions": {{"stable": "1.0.0"}}, "dependencies": []}}"#
+                ),
+            )
+            .unwrap();
+        }
+
+        let refresh = manager.refresh_tap("user", "homebrew-repo").await.unwrap();
+        assert_eq!(refresh.tap, "user/repo");
+        assert_eq!(refresh.refreshed, 2);
+        assert_eq!(
+            refresh.updated,
+            [("foo".to_string(), "1.0.0".to_string(), "2.0.0".to_string())]
+        );
+        assert_eq!(refresh.removed, ["gone"]);
+        assert_eq!(
+            manager.list_formulas("user", "repo").unwrap(),
+            ["foo", "same"]
+        );
+    }
 }
@@ -0,0 +1,222 @@
+//! Crash-safe temporary directories.
+//!
+//! Temp directories are named `.{label}.tmp.{pid}` and live next to their final
+//! destination so they can be renamed into place atomically. The guard removes the
+//! directory on drop unless it was persisted. If the process dies before that, the
+//! PID in the name lets a later run tell the directory is abandoned and reap it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use zb_core::Error;
+
+/// Marker separating the label from the owner PID in temp directory names
+const TMP_MARKER: &str = ".tmp.";
+
+/// A temp directory that is removed when dropped
+#[derive(Debug)]
+pub struct TempDirGuard {
+    path: PathBuf,
+    armed: bool,
+}
+
+impl TempDirGuard {
+    /// Create `parent/.{label}.tmp.{pid}`, replacing any leftover from a previous
+    /// run that had the same PID.
+    pub fn new(parent: &Path, label: &str) -> Result<Self, Error> {
+        let path = parent.join(temp_dir_name(label, std::process::id()));
+
+        if path.exists() {
+            let _ = fs::remove_dir_all(&path);
+        }
+
+        fs::create_dir_all(&path).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to create temp directory: {e}"),
+        })?;
+
+        Ok(Self { path, armed: true })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically rename the directory to `dest` and stop tracking it
+    pub fn persist(mut self, dest: &Path) -> Result<(), Error> {
+        fs::rename(&self.path, dest).map_err(|e| Error::StoreCorruption {
+            message: format!("failed to move {} into place: {e}", dest.display()),
+        })?;
+        self.armed = false;
+        Ok(())
+    }
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        if self.armed {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Name of a temp directory owned by `pid`
+pub fn temp_dir_name(label: &str, pid: u32) -> String {
+    format!(".{label}{TMP_MARKER}{pid}")
+}
+
+/// Owner PID of a temp directory name, if it is one
+pub fn owner_pid(name: &str) -> Option<u32> {
+    if !name.starts_with('.') {
+        return None;
+    }
+    let (_, pid) = name.rsplit_once(TMP_MARKER)?;
+    pid.parse().ok()
+}
+
+/// Check whether a process with the given PID is still running
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 performs error checking only; EPERM means the process exists
+    // but belongs to someone else.
+    let alive = unsafe { libc::kill(pid, 0) == 0 };
+    alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Remove temp directories in `parent` whose owning process is no longer running.
+/// Returns the count of directories removed and total bytes freed.
+pub fn reap_stale(parent: &Path) -> io::Result<(usize, u64)> {
+    let mut count = 0;
+    let mut bytes_freed = 0;
+
+    let entries = match fs::read_dir(parent) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+
+        let Some(pid) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(owner_pid)
+        else {
+            continue;
+        };
+
+        if !path.is_dir() || pid == std::process::id() || process_alive(pid) {
+            continue;
+        }
+
+        let size = dir_size(&path);
+        if fs::remove_dir_all(&path).is_ok() {
+            count += 1;
+            bytes_freed += size;
+        }
+    }
+
+    Ok((count, bytes_freed))
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A PID that is effectively guaranteed not to be running
+    const DEAD_PID: u32 = 999_999_999;
+
+    #[test]
+    fn guard_removes_directory_on_drop() {
+        let tmp = TempDir::new().unwrap();
+        let path = {
+            let guard = TempDirGuard::new(tmp.path(), "abc").unwrap();
+            fs::write(guard.path().join("file"), b"x").unwrap();
+            guard.path().to_path_buf()
+        };
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn persist_moves_directory_and_disarms() {
+        let tmp = TempDir::new().unwrap();
+        let dest = tmp.path().join("final");
+
+        let guard = TempDirGuard::new(tmp.path(), "abc").unwrap();
+        let temp_path = guard.path().to_path_buf();
+        fs::write(guard.path().join("file"), b"x").unwrap();
+        guard.persist(&dest).unwrap();
+
+        assert!(!temp_path.exists());
+        assert!(dest.join("file").exists());
+    }
+
+    #[test]
+    fn name_is_tagged_with_pid() {
+        let tmp = TempDir::new().unwrap();
+        let guard = TempDirGuard::new(tmp.path(), "abc").unwrap();
+        let name = guard.path().file_name().unwrap().to_str().unwrap();
+        assert_eq!(owner_pid(name), Some(std::process::id()));
+    }
+
+    #[test]
+    fn owner_pid_parses_names() {
+        assert_eq!(owner_pid(".abc123.tmp.1234"), Some(1234));
+        assert_eq!(owner_pid(".jq-1.7.tmp.42"), Some(42));
+        assert_eq!(owner_pid("abc123.tmp.1234"), None);
+        assert_eq!(owner_pid(".abc123"), None);
+        assert_eq!(owner_pid(".abc.tmp.notapid"), None);
+    }
+
+    #[test]
+    fn current_process_is_alive() {
+        assert!(process_alive(std::process::id()));
+    }
+
+    #[test]
+    fn reap_stale_removes_only_dead_owners() {
+        let tmp = TempDir::new().unwrap();
+        let dead = tmp.path().join(temp_dir_name("dead", DEAD_PID));
+        let live = tmp.path().join(temp_dir_name("live", std::process::id()));
+        let entry = tmp.path().join("abc123");
+        for dir in [&dead, &live, &entry] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(dead.join("file"), b"12345").unwrap();
+
+        let (count, bytes) = reap_stale(tmp.path()).unwrap();
+
+        assert_eq!(count, 1);
+        assert_eq!(bytes, 5);
+        assert!(!dead.exists());
+        assert!(live.exists());
+        assert!(entry.exists());
+    }
+
+    #[test]
+    fn reap_stale_missing_parent_is_noop() {
+        let tmp = TempDir::new().unwrap();
+        assert_eq!(reap_stale(&tmp.path().join("missing")).unwrap(), (0, 0));
+    }
+}
@@ -247,6 +247,17 @@ pub fn sha256_hex(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Make a patch from `old` to `new` the way `zstd --patch-from` does
+/// (see [`crate::delta`]).
+pub fn make_delta_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), 19, old).unwrap();
+    encoder.long_distance_matching(true).unwrap();
+    encoder.window_log(27).unwrap();
+    encoder.write_all(new).unwrap();
+    encoder.finish().unwrap()
+}
+
 // ============================================================================
 // Network failure helpers
 // ============================================================================
@@ -581,9 +592,7 @@ pub fn create_test_installer(mock_server: &MockServer, tmp: &TempDir) -> Install
         linker,
         db,
         tap_manager,
-        prefix.clone(),
-        prefix.join("Cellar"),
-        4,
+        zb_core::Context::new(&root, &prefix),
     )
 }
 
@@ -649,6 +658,7 @@ mod tests {
     }
 
     #[tokio::test]
+    #[allow(clippy::overly_complex_bool_expr)]
     async fn test_context_creation() {
         let ctx = TestContext::new().await;
         assert!(ctx.root().exists() || true); // May not exist until first use
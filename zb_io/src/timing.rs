@@ -0,0 +1,247 @@
+//! Install timing measurements.
+//!
+//! The executor records how long each phase took for every package it installs.
+//! These measurements are persisted in the database and summarized by `zb stats`.
+//! Each phase is also kept as a span so `zb install --profile` can show how much
+//! of the work overlapped.
+
+use std::time::{Duration, Instant};
+
+/// Install phase measured by a span
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Download,
+    Extract,
+    Link,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Download => "download",
+            Phase::Extract => "extract",
+            Phase::Link => "link",
+        }
+    }
+}
+
+/// A timed interval for one package, relative to the start of plan execution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub phase: Phase,
+    pub package: String,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Span {
+    pub fn duration(&self) -> Duration {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Collects spans against a fixed origin
+pub(crate) struct SpanRecorder {
+    origin: Instant,
+    spans: Vec<Span>,
+}
+
+impl SpanRecorder {
+    pub(crate) fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Offset from the origin, used as the start of a span
+    pub(crate) fn now(&self) -> Duration {
+        self.origin.elapsed()
+    }
+
+    /// Close a span that started at `start` and return its duration
+    pub(crate) fn record(&mut self, phase: Phase, package: &str, start: Duration) -> Duration {
+        let span = Span {
+            phase,
+            package: package.to_string(),
+            start,
+            end: self.now(),
+        };
+        let duration = span.duration();
+        self.spans.push(span);
+        duration
+    }
+
+    pub(crate) fn into_spans(self) -> Vec<Span> {
+        self.spans
+    }
+}
+
+/// Timings for a single package within an install run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageTiming {
+    pub name: String,
+    pub version: String,
+    /// Time from the start of downloads until this package's bottle was available
+    pub download: Duration,
+    /// Time spent extracting into the store and materializing into the cellar
+    pub extract: Duration,
+    /// Time spent linking into the prefix
+    pub link: Duration,
+    /// Size of the bottle in bytes
+    pub bytes: u64,
+    /// True if the bottle was already in the blob cache (no network transfer)
+    pub blob_cached: bool,
+    /// True if the store entry already existed (no extraction needed)
+    pub store_reused: bool,
+    /// True if this replaced a different installed version of the package
+    pub upgraded: bool,
+}
+
+/// Timings for a whole install run (one plan execution)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstallTimings {
+    /// Time spent resolving dependencies and selecting bottles
+    pub resolve: Duration,
+    /// Wall clock time for executing the plan
+    pub execute: Duration,
+    /// Per-package timings in install order
+    pub packages: Vec<PackageTiming>,
+    /// Raw phase spans in the order they finished
+    pub spans: Vec<Span>,
+}
+
+impl InstallTimings {
+    /// Total wall clock time including resolution
+    pub fn total(&self) -> Duration {
+        self.resolve + self.execute
+    }
+
+    /// Bytes actually transferred over the network
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.packages
+            .iter()
+            .filter(|p| !p.blob_cached)
+            .map(|p| p.bytes)
+            .sum()
+    }
+
+    /// Total time spent in a phase across all packages
+    pub fn phase_total(&self, phase: Phase) -> Duration {
+        self.spans
+            .iter()
+            .filter(|s| s.phase == phase)
+            .map(Span::duration)
+            .sum()
+    }
+
+    /// Average number of phases running at once during execution.
+    ///
+    /// Downloads are streamed, so a download span covers the time from the start of
+    /// execution until the bottle was available.
+    pub fn parallelism(&self) -> f64 {
+        if self.execute.is_zero() {
+            return 0.0;
+        }
+        let busy: Duration = self.spans.iter().map(Span::duration).sum();
+        busy.as_secs_f64() / self.execute.as_secs_f64()
+    }
+}
+
+/// Convert a duration to whole milliseconds for storage
+pub(crate) fn duration_to_ms(duration: Duration) -> i64 {
+    duration.as_millis().min(i64::MAX as u128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_includes_resolve_and_execute() {
+        let timings = InstallTimings {
+            resolve: Duration::from_millis(200),
+            execute: Duration::from_millis(800),
+            ..Default::default()
+        };
+        assert_eq!(timings.total(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bytes_downloaded_skips_cached_blobs() {
+        let timings = InstallTimings {
+            packages: vec![
+                PackageTiming {
+                    name: "a".to_string(),
+                    bytes: 100,
+                    ..Default::default()
+                },
+                PackageTiming {
+                    name: "b".to_string(),
+                    bytes: 50,
+                    blob_cached: true,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(timings.bytes_downloaded(), 100);
+    }
+
+    fn span(phase: Phase, start_ms: u64, end_ms: u64) -> Span {
+        Span {
+            phase,
+            package: "a".to_string(),
+            start: Duration::from_millis(start_ms),
+            end: Duration::from_millis(end_ms),
+        }
+    }
+
+    #[test]
+    fn phase_total_sums_matching_spans() {
+        let timings = InstallTimings {
+            spans: vec![
+                span(Phase::Download, 0, 100),
+                span(Phase::Download, 0, 300),
+                span(Phase::Extract, 100, 150),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            timings.phase_total(Phase::Download),
+            Duration::from_millis(400)
+        );
+        assert_eq!(timings.phase_total(Phase::Link), Duration::ZERO);
+    }
+
+    #[test]
+    fn parallelism_is_busy_time_over_wall_clock() {
+        let timings = InstallTimings {
+            execute: Duration::from_millis(200),
+            spans: vec![span(Phase::Download, 0, 200), span(Phase::Download, 0, 200)],
+            ..Default::default()
+        };
+        assert!((timings.parallelism() - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn parallelism_is_zero_without_execution() {
+        assert_eq!(InstallTimings::default().parallelism(), 0.0);
+    }
+
+    #[test]
+    fn recorder_returns_span_duration() {
+        let mut recorder = SpanRecorder::new();
+        let start = recorder.now();
+        let duration = recorder.record(Phase::Link, "a", start);
+        let spans = recorder.into_spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].duration(), duration);
+        assert_eq!(spans[0].phase, Phase::Link);
+    }
+
+    #[test]
+    fn duration_to_ms_rounds_down() {
+        assert_eq!(duration_to_ms(Duration::from_micros(1999)), 1);
+    }
+}
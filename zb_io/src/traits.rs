@@ -12,6 +12,8 @@ use mockall::automock;
 
 use zb_core::Error;
 
+use crate::credentials::Credentials;
+
 /// HTTP client trait for abstracting network operations.
 ///
 /// This trait allows tests to inject mock HTTP clients that can simulate
@@ -46,8 +48,13 @@ pub trait FileSystem: Send + Sync {
 }
 
 /// Real HTTP client implementation using reqwest.
+///
+/// Requests to hosts with a login in its credentials carry it as basic auth
+/// (see [`crate::credentials`]); there are none unless set with
+/// `with_credentials`.
 pub struct ReqwestHttpClient {
     client: reqwest::Client,
+    credentials: Credentials,
 }
 
 impl ReqwestHttpClient {
@@ -58,11 +65,20 @@ impl ReqwestHttpClient {
                 .pool_max_idle_per_host(10)
                 .build()
                 .unwrap_or_else(|_| reqwest::Client::new()),
+            credentials: Credentials::none(),
         }
     }
 
     pub fn with_client(client: reqwest::Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            credentials: Credentials::none(),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
     }
 }
 
@@ -76,8 +92,8 @@ impl Default for ReqwestHttpClient {
 impl HttpClient for ReqwestHttpClient {
     async fn get(&self, url: &str) -> Result<Vec<u8>, Error> {
         let response = self
-            .client
-            .get(url)
+            .credentials
+            .authorize(url, self.client.get(url))
             .send()
             .await
             .map_err(|e| Error::NetworkFailure {
@@ -101,8 +117,8 @@ impl HttpClient for ReqwestHttpClient {
 
     async fn get_with_timeout(&self, url: &str, timeout: Duration) -> Result<Vec<u8>, Error> {
         let response = self
-            .client
-            .get(url)
+            .credentials
+            .authorize(url, self.client.get(url))
             .timeout(timeout)
             .send()
             .await
@@ -391,6 +407,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::default_constructed_unit_structs)]
     fn test_std_filesystem_default() {
         let fs = StdFileSystem::default();
         assert!(std::mem::size_of_val(&fs) == 0);